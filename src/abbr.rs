@@ -0,0 +1,160 @@
+//! Abbreviations, as in fish: typing `gco` then a space turns it into
+//! `git checkout ` on the command line, so what runs and goes into history
+//! is there to see. A Backspace straight after takes the expansion back.
+
+use std::{collections::BTreeMap, fmt::Write, fs};
+
+use crate::{Cursor, Model, config};
+
+/// Abbreviations live next to the config, one `name = expansion` per line.
+fn path() -> Option<std::path::PathBuf> {
+    Some(config::dir()?.join("abbreviations"))
+}
+
+pub fn load() -> BTreeMap<String, String> {
+    let Some(contents) = path().and_then(|p| fs::read_to_string(p).ok()) else {
+        return BTreeMap::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, expansion)| (name.trim().to_string(), expansion.trim().to_string()))
+        .filter(|(name, _)| !name.is_empty() && !name.starts_with('#'))
+        .collect()
+}
+
+fn save(abbreviations: &BTreeMap<String, String>) -> Result<(), String> {
+    let path = path().ok_or("abbr: no config directory")?;
+    let mut contents = String::new();
+    for (name, expansion) in abbreviations {
+        let _ = writeln!(contents, "{name} = {expansion}");
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("abbr: {e}"))?;
+    }
+    fs::write(&path, contents).map_err(|e| format!("abbr: {}: {e}", path.display()))
+}
+
+/// An expansion just made, so Backspace can take it back.
+#[derive(Debug)]
+pub struct Expanded {
+    /// Where the abbreviation started in the command.
+    offset: usize,
+    name: String,
+    /// Length of the expansion and the space after it.
+    len: usize,
+}
+
+/// The word ending at the end of `before`, which is the command up to the
+/// cursor, and where it starts. Unless `anywhere`, only the command's first
+/// word counts.
+fn word_before(before: &str, anywhere: bool) -> Option<(usize, &str)> {
+    let start = before.rfind(char::is_whitespace).map_or(0, |i| {
+        i + before[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let word = &before[start..];
+    let first = before[..start].trim().is_empty();
+    (!word.is_empty() && (first || anywhere)).then_some((start, word))
+}
+
+impl Model {
+    /// Space after an abbreviation: put its expansion in its place, and the
+    /// space after it. Returns whether it did.
+    pub fn expand_abbreviation(&mut self) -> bool {
+        let Cursor::CommandLine(x, y) = self.cursor else {
+            return false;
+        };
+        let offset = self.command_offset(x, y);
+        let before = &self.current_command[..offset];
+        let Some((start, name)) = word_before(before, self.config.abbr_anywhere) else {
+            return false;
+        };
+        let Some(expansion) = self.abbreviations.get(name) else {
+            return false;
+        };
+        let name = name.to_string();
+        let text = format!("{expansion} ");
+        self.current_command.replace_range(start..offset, &text);
+        self.expanded = Some(Expanded {
+            offset: start,
+            name,
+            len: text.len(),
+        });
+        self.put_cursor_at(start + text.len());
+        true
+    }
+
+    /// Backspace just after an expansion: back to the abbreviation as it
+    /// was typed, space and all, so it's left alone.
+    pub fn unexpand_abbreviation(&mut self) -> bool {
+        let Some(expanded) = self.expanded.take() else {
+            return false;
+        };
+        let typed = format!("{} ", expanded.name);
+        let end = expanded.offset + expanded.len;
+        if self.current_command.get(expanded.offset..end).is_none() {
+            return false;
+        }
+        self.current_command
+            .replace_range(expanded.offset..end, &typed);
+        self.put_cursor_at(expanded.offset + typed.len());
+        true
+    }
+
+    /// Move the cursor to byte `offset` of the command.
+    pub fn put_cursor_at(&mut self, offset: usize) {
+        let before = &self.current_command[..offset];
+        let row = before.matches('\n').count();
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1);
+        self.cursor = Cursor::CommandLine(column as u16, row as u16);
+    }
+}
+
+/// `:abbr add NAME EXPANSION`, `:abbr rm NAME` and `:abbr` to list them.
+pub fn command(model: &mut Model, line: &str) -> String {
+    let line = line.trim_start().trim_start_matches(':').trim_start();
+    let rest = line.strip_prefix("abbr").unwrap_or(line).trim();
+    let (action, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let (name, expansion) = rest
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((rest.trim(), ""));
+    let result = match action {
+        "" | "list" => return list(model),
+        "add" if !name.is_empty() && !expansion.trim().is_empty() => {
+            model
+                .abbreviations
+                .insert(name.to_string(), expansion.trim().to_string());
+            save(&model.abbreviations).map(|()| format!("added abbreviation {name}"))
+        }
+        "rm" if model.abbreviations.remove(name).is_some() => {
+            save(&model.abbreviations).map(|()| format!("removed abbreviation {name}"))
+        }
+        "rm" => Err(format!("no abbreviation named {name}")),
+        _ => Err("usage: :abbr [add NAME EXPANSION | rm NAME]".into()),
+    };
+    result.unwrap_or_else(|e| e)
+}
+
+fn list(model: &Model) -> String {
+    let mut listing = String::new();
+    for (name, expansion) in &model.abbreviations {
+        let _ = writeln!(listing, "{name}  {expansion}");
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_first_word_by_default() {
+        assert_eq!(word_before("gco", false), Some((0, "gco")));
+        assert_eq!(word_before("  gco", false), Some((2, "gco")));
+        assert_eq!(word_before("git gco", false), None);
+        assert_eq!(word_before("git gco", true), Some((4, "gco")));
+        assert_eq!(word_before("git ", true), None);
+        assert_eq!(word_before("", false), None);
+    }
+}
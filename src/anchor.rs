@@ -0,0 +1,131 @@
+//! Keeping a view on the text being read as its buffer's output changes.
+//! The view and the cursor in it count lines from the top, so output
+//! added at the end leaves a buffer that isn't following as it was. With
+//! `max_lines`, a buffer's oldest lines are dropped as more come in, and
+//! everything counted from the top (the view, the cursor, bookmarks,
+//! marks, checkpoints, images) moves up by as many, so the same text
+//! stays on screen; a view on lines that have gone stops at the new first
+//! line. Its stdout and stderr apart are kept to as many lines, each half
+//! of a split staying on its text the same way. Nothing is dropped while
+//! a buffer is shown piped or frozen, as the lines on screen aren't its
+//! own; it catches up once it's back.
+
+use crate::{Cursor, Model, Output};
+
+/// Lines allowed beyond `max_lines`, as a fraction of it, before the
+/// oldest are dropped, so a long run isn't copied for every line.
+const SLACK: usize = 10;
+
+/// Where the text after the first `lines` lines of `text` starts.
+pub fn after_lines(text: &str, lines: usize) -> usize {
+    match lines.checked_sub(1) {
+        None => 0,
+        Some(last) => text
+            .match_indices('\n')
+            .nth(last)
+            .map_or(text.len(), |(i, _)| i + 1),
+    }
+}
+
+/// Move a view `scroll` lines down its text, and `cursor` in it, up past
+/// `lines` lines dropped from the top, keeping both on the same text
+/// where it's still there.
+pub fn shift(scroll: &mut usize, cursor: &mut Cursor, lines: usize) {
+    let gone = lines.saturating_sub(*scroll);
+    *scroll = scroll.saturating_sub(lines);
+    if let Cursor::OutputBuffer(_, y) = cursor {
+        *y = (*y as usize).saturating_sub(gone) as u16;
+    }
+}
+
+impl Output {
+    /// Drop the first `lines` lines and what's kept about them.
+    fn drop_lines(&mut self, lines: usize) {
+        let cut = after_lines(&self.stdout, lines);
+        self.stdout.drain(..cut);
+        let raw_cut = self.raw.as_mut().map(|raw| {
+            let cut = after_lines(raw, lines);
+            raw.drain(..cut);
+            cut
+        });
+        self.line_index.changed();
+        self.dropped += lines;
+        self.bookmarks = self
+            .bookmarks
+            .iter()
+            .filter_map(|line| line.checked_sub(lines))
+            .collect();
+        self.drop_checkpoints(cut);
+        self.drop_section(cut, raw_cut);
+        self.images.drop_lines(lines);
+        if let Some(streams) = self.streams.as_mut() {
+            streams.keep_last(self.stdout.lines().count());
+        }
+        // found again once the job ends
+        self.table = None;
+    }
+}
+
+impl Model {
+    /// Keep output `index` to `max_lines`, after output was added to it;
+    /// unless it's `following` its end, its view stays on the same text.
+    pub fn cap_lines(&mut self, index: usize, following: bool) {
+        let max = self.config.max_lines;
+        let output = &mut self.outputs[index];
+        if max == 0 || output.pipe.is_some() || output.frozen.is_some() {
+            return;
+        }
+        let lines = output.line_count();
+        if lines <= max + max / SLACK {
+            return;
+        }
+        if output.dropped == 0 {
+            output.warn(format!("only the last {max} lines are kept (max_lines)"));
+        }
+        let dropped = lines - max;
+        output.drop_lines(dropped);
+        let id = output.id;
+        for mark in self.marks.values_mut().chain(self.last_jump.as_mut()) {
+            mark.lines_dropped(id, dropped);
+        }
+        if following {
+            return;
+        }
+        let output = &mut self.outputs[index];
+        if let Some(target) = &mut output.scroll_target {
+            target.lines_dropped(dropped);
+        }
+        // the scrollback view has a cursor of its own
+        let viewed = index == self.viewing_output && self.scrollback.is_none();
+        let mut unmoved = Cursor::CommandLine(0, 0);
+        let cursor = match (viewed, &mut output.cursor) {
+            (true, _) => &mut self.cursor,
+            (false, Some(cursor)) => cursor,
+            (false, None) => &mut unmoved,
+        };
+        shift(&mut output.scroll.0, cursor, dropped);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_are_counted_off_the_top() {
+        assert_eq!(after_lines("a\nb\nc", 0), 0);
+        assert_eq!(after_lines("a\nb\nc", 2), 4);
+        assert_eq!(after_lines("a\nb\nc", 5), 5);
+    }
+
+    #[test]
+    fn a_view_on_dropped_lines_stops_at_the_top() {
+        let (mut scroll, mut cursor) = (10, Cursor::OutputBuffer(3, 4));
+        shift(&mut scroll, &mut cursor, 6);
+        assert_eq!((scroll, cursor), (4, Cursor::OutputBuffer(3, 4)));
+        shift(&mut scroll, &mut cursor, 6);
+        assert_eq!((scroll, cursor), (0, Cursor::OutputBuffer(3, 2)));
+        shift(&mut scroll, &mut cursor, 6);
+        assert_eq!((scroll, cursor), (0, Cursor::OutputBuffer(3, 0)));
+    }
+}
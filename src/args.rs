@@ -0,0 +1,196 @@
+//! Arguments of past commands, whatever they were given to. Alt-. on the
+//! command line puts in the last argument of the command before, and
+//! pressed again swaps it for the last argument of the one before that,
+//! as readline does; after a count (Alt-1 Alt-.) it's that word instead,
+//! the command's name being 0. Alt-a picks from every argument ever given,
+//! best first by how often and how lately, to put in at the cursor. Words
+//! are split as they are when a command runs, so a quoted argument comes
+//! back whole, quoted again if it needs to be; one with a variable, a `~`
+//! or a glob in it comes back as typed, to mean the same where it goes.
+//! The index is brought up to date as commands are submitted.
+
+use std::{collections::HashMap, ops::Range};
+
+use crate::{
+    Cursor, Model,
+    picker::{Picker, PickerKind},
+    shell,
+};
+
+/// The words of `command`, ready to be put in another.
+pub fn arguments(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    for line in command.lines() {
+        for span in shell::token_spans(line) {
+            let raw = &line[span];
+            if raw.starts_with('#') {
+                break;
+            }
+            if raw.chars().all(|c| "|&;<>".contains(c)) {
+                continue;
+            }
+            let word = match &shell::words(raw, |_| None)[..] {
+                [Some(word)] if !raw.contains('$') && !raw.starts_with('~') => shell::quote(word),
+                _ => raw.to_string(),
+            };
+            words.push(word);
+        }
+    }
+    words
+}
+
+#[derive(Debug, Default)]
+struct Use {
+    uses: u32,
+    /// The command it was last in, counting from 1.
+    last: usize,
+}
+
+#[derive(Debug, Default)]
+pub struct Index {
+    entries: HashMap<String, Use>,
+    /// Commands of the history indexed so far.
+    indexed: usize,
+}
+
+impl Index {
+    fn add(&mut self, command: &str) {
+        self.indexed += 1;
+        for word in arguments(command).into_iter().skip(1) {
+            let entry = self.entries.entry(word).or_default();
+            entry.uses += 1;
+            entry.last = self.indexed;
+        }
+    }
+
+    /// Uses weighted by how many commands ago the last one was.
+    fn score(&self, entry: &Use) -> f64 {
+        let recency = match self.indexed - entry.last {
+            0..20 => 4.0,
+            20..200 => 2.0,
+            200..2000 => 0.5,
+            _ => 0.25,
+        };
+        entry.uses as f64 * recency
+    }
+
+    /// Every argument, best first.
+    fn ranked(&self) -> Vec<&str> {
+        let mut ranked: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(word, entry)| (self.score(entry), word))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        ranked.into_iter().map(|(_, word)| word.as_str()).collect()
+    }
+}
+
+/// What Alt-. put in, for the next Alt-. to swap.
+#[derive(Debug)]
+pub struct LastArgument {
+    range: Range<usize>,
+    /// How many commands back it came from.
+    back: usize,
+    /// The word asked for, if not the last.
+    word: Option<usize>,
+}
+
+impl Model {
+    /// Bring the index up to date with the history.
+    pub fn index_arguments(&mut self) {
+        if self.arguments.indexed > self.previous_commands.len() {
+            self.arguments = Index::default();
+        }
+        for entry in &self.previous_commands[self.arguments.indexed..] {
+            self.arguments.add(&entry.command);
+        }
+    }
+
+    /// Alt-. on the command line: the last argument of the command before,
+    /// or word `n` of it, or the same of the command before the one the
+    /// last Alt-. took it from. In the output it's the word under the
+    /// cursor; see `grab`.
+    pub fn insert_last_argument(&mut self, n: Option<usize>) {
+        if let Cursor::OutputBuffer(_, _) = self.cursor {
+            return self.insert_from_output(n);
+        }
+        let again = self.last_argument.take();
+        let (back, word) = match &again {
+            Some(last) => (last.back + 1, last.word),
+            None => (0, n),
+        };
+        let Some(command) = self
+            .previous_commands
+            .iter()
+            .rev()
+            .filter(|e| !e.command.trim().is_empty())
+            .nth(back)
+            .map(|e| e.command.clone())
+        else {
+            self.status = Some("no older command".into());
+            self.last_argument = again;
+            return;
+        };
+        let words = arguments(&command);
+        let Some(text) = word.map_or(words.last(), |n| words.get(n)).cloned() else {
+            self.status = Some(format!("no word {} in {command}", word.unwrap_or(0)));
+            self.last_argument = again.map(|last| LastArgument { back, ..last });
+            return;
+        };
+        if let Some(last) = &again {
+            self.current_command.replace_range(last.range.clone(), "");
+            self.put_cursor_at(last.range.start);
+        }
+        let range = self.insert_text(&text);
+        self.last_argument = Some(LastArgument { range, back, word });
+    }
+
+    /// Alt-a: pick from every argument in the history.
+    pub fn open_argument_picker(&mut self) {
+        self.index_arguments();
+        let items: Vec<String> = self
+            .arguments
+            .ranked()
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if items.is_empty() {
+            self.status = Some("no arguments in the history".into());
+            return;
+        }
+        self.picker = Some(Picker::new(PickerKind::Arguments, items));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arguments_come_back_whole_and_quoted() {
+        assert_eq!(
+            arguments(r#"scp "my notes.txt" host:/tmp # copy"#),
+            ["scp", "'my notes.txt'", "host:/tmp"]
+        );
+        assert_eq!(
+            arguments(r"ls a\ b $HOME/src ~/x *.rs | wc -l"),
+            ["ls", "'a b'", "$HOME/src", "~/x", "*.rs", "wc", "-l"]
+        );
+        assert_eq!(arguments("make && ./run"), ["make", "./run"]);
+    }
+
+    #[test]
+    fn arguments_are_ranked_by_uses_and_recency() {
+        let mut index = Index::default();
+        for command in ["ssh web1", "ssh web1", "ssh db"] {
+            index.add(command);
+        }
+        assert_eq!(index.ranked(), ["web1", "db"]);
+        for _ in 0..30 {
+            index.add("ls");
+        }
+        index.add("ssh db");
+        assert_eq!(index.ranked(), ["db", "web1"]);
+    }
+}
@@ -0,0 +1,266 @@
+//! History kept by atuin (`history = atuin`), read with `atuin history
+//! list` and recorded with `atuin history start` and `end`, as its shell
+//! hooks do. Those run on threads of their own, as atuin can be slow to
+//! answer; one that fails is told of when the next command is recorded.
+
+use std::{
+    collections::HashMap,
+    env, io,
+    path::PathBuf,
+    process::{Command, Output, Stdio},
+    sync::{Arc, Mutex},
+    thread::{self, JoinHandle},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::history::{Backend, Entry};
+
+#[derive(Debug)]
+pub struct Atuin {
+    /// Identifies this shim to atuin, like a shell's session.
+    session: String,
+    /// `atuin`, or a stand-in for it in tests.
+    program: PathBuf,
+    /// `history start`s, by the handle given for each, giving atuin's id.
+    starting: HashMap<String, JoinHandle<Result<String, String>>>,
+    /// Handles given out so far.
+    started: u64,
+    /// Why recording a command last failed, not yet told.
+    failed: Arc<Mutex<Option<String>>>,
+}
+
+impl Atuin {
+    pub fn new() -> Self {
+        // atuin's hooks export this; shim makes its own if run outside them
+        let session = env::var("ATUIN_SESSION").unwrap_or_else(|_| {
+            let nanos = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |d| d.as_nanos());
+            format!("{:032x}", nanos ^ ((std::process::id() as u128) << 96))
+        });
+        Atuin {
+            session,
+            program: PathBuf::from("atuin"),
+            starting: HashMap::new(),
+            started: 0,
+            failed: Arc::default(),
+        }
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.program);
+        command
+            .env("ATUIN_SESSION", &self.session)
+            .stdin(Stdio::null());
+        command
+    }
+}
+
+fn run_error(e: io::Error) -> String {
+    match e.kind() {
+        io::ErrorKind::NotFound => "atuin isn't installed".into(),
+        _ => format!("can't run atuin: {e}"),
+    }
+}
+
+/// stdout of a finished atuin command, or what went wrong.
+fn stdout(output: io::Result<Output>) -> Result<String, String> {
+    let output = output.map_err(run_error)?;
+    if !output.status.success() {
+        let error = String::from_utf8_lossy(&output.stderr);
+        let error = error.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        return Err(format!("atuin failed: {}", error.trim()));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl Backend for Atuin {
+    fn name(&self) -> &'static str {
+        "atuin"
+    }
+
+    fn load(&self) -> Result<Vec<Entry>, String> {
+        let output = self
+            .command()
+            .args([
+                "history",
+                "list",
+                "--print0",
+                "--format",
+                "{directory}\t{command}",
+            ])
+            .output();
+        // NUL separated, as commands may span lines
+        let entries = stdout(output)?
+            .split('\0')
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                let (cwd, command) = entry.split_once('\t').unwrap_or(("", entry));
+                Entry {
+                    command: command.to_string(),
+                    cwd: Some(PathBuf::from(cwd)).filter(|c| !c.as_os_str().is_empty()),
+                    context: None,
+                    time: None,
+                }
+            })
+            .collect();
+        Ok(entries)
+    }
+
+    fn start(&mut self, entry: &Entry) -> Result<Option<String>, String> {
+        if let Some(e) = self.failed.lock().ok().and_then(|mut f| f.take()) {
+            return Err(e);
+        }
+        let mut command = self.command();
+        command.args(["history", "start", "--", &entry.command]);
+        // one whose directory has gone is recorded as run from here
+        if let Some(cwd) = entry.cwd.as_ref().filter(|cwd| cwd.is_dir()) {
+            command.current_dir(cwd);
+        }
+        let failed = Arc::clone(&self.failed);
+        let starting = thread::spawn(move || {
+            let id = stdout(command.output()).map(|id| id.trim().to_string());
+            if let (Err(e), Ok(mut failed)) = (&id, failed.lock()) {
+                *failed = Some(e.clone());
+            }
+            id
+        });
+        self.started += 1;
+        let handle = self.started.to_string();
+        self.starting.insert(handle.clone(), starting);
+        Ok(Some(handle))
+    }
+
+    fn finish(
+        &mut self,
+        handle: String,
+        status: Option<i32>,
+        duration: Duration,
+    ) -> Result<(), String> {
+        let Some(starting) = self.starting.remove(&handle) else {
+            return Ok(());
+        };
+        let mut command = self.command();
+        command
+            .args(["history", "end", "--exit"])
+            // killed by a signal counts as failing
+            .arg(status.unwrap_or(1).to_string())
+            .arg("--duration")
+            .arg(duration.as_nanos().to_string())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        let failed = Arc::clone(&self.failed);
+        // it syncs afterwards, which shouldn't hold anything up either
+        thread::spawn(move || {
+            // one that didn't start has said why already
+            let Ok(Ok(id)) = starting.join() else {
+                return;
+            };
+            if id.is_empty() {
+                return;
+            }
+            match command.arg(id).spawn() {
+                Ok(mut child) => drop(child.wait()),
+                Err(e) => {
+                    if let Ok(mut failed) = failed.lock() {
+                        *failed = Some(run_error(e));
+                    }
+                }
+            }
+        });
+        Ok(())
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{fs, os::unix::fs::PermissionsExt, time::Instant};
+
+    use super::*;
+
+    /// An atuin that runs `script`, noting its arguments and directory in
+    /// a log next to it.
+    fn stand_in(name: &str, script: &str) -> (Atuin, PathBuf) {
+        let dir = env::temp_dir().join(format!("shim-atuin-{name}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (program, log) = (dir.join("atuin"), dir.join("log"));
+        let script = format!("#!/bin/sh\necho \"$PWD $*\" >> {}\n{script}", log.display());
+        fs::write(&program, script).unwrap();
+        fs::set_permissions(&program, fs::Permissions::from_mode(0o755)).unwrap();
+        let atuin = Atuin {
+            program,
+            ..Atuin::new()
+        };
+        (atuin, log)
+    }
+
+    /// The log once it has `lines` lines.
+    fn logged(log: &PathBuf, lines: usize) -> Vec<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let text = fs::read_to_string(log).unwrap_or_default();
+            if text.lines().count() >= lines || Instant::now() > deadline {
+                return text.lines().map(String::from).collect();
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn recording_doesnt_wait_for_atuin() {
+        let (mut atuin, log) = stand_in("wait", "[ \"$2\" = start ] && sleep 0.5 && echo id-1");
+        let entry = Entry {
+            cwd: Some(PathBuf::from("/no/such/dir/for/shim")),
+            ..Entry::new("make".into())
+        };
+        let asked = Instant::now();
+        let handle = atuin.start(&entry).unwrap().unwrap();
+        atuin
+            .finish(handle, Some(2), Duration::from_secs(1))
+            .unwrap();
+        assert!(asked.elapsed() < Duration::from_millis(300));
+        let lines = logged(&log, 2);
+        // a directory that's gone isn't atuin not being there
+        let here = env::current_dir().unwrap();
+        assert_eq!(
+            lines[0],
+            format!("{} history start -- make", here.display())
+        );
+        assert!(lines[1].ends_with("history end --exit 2 --duration 1000000000 id-1"));
+        assert!(atuin.start(&entry).is_ok());
+        let _ = fs::remove_dir_all(log.parent().unwrap());
+    }
+
+    /// What the next `start` says once a failure has come back.
+    fn failure(atuin: &mut Atuin, entry: &Entry) -> Result<Option<String>, String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            match atuin.start(entry) {
+                Ok(_) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+                result => return result,
+            }
+        }
+    }
+
+    #[test]
+    fn a_failure_is_told_at_the_next_command() {
+        let (mut atuin, log) = stand_in("fail", "echo 'no database' >&2; exit 1");
+        let entry = Entry::new("ls".into());
+        let handle = atuin.start(&entry).unwrap().unwrap();
+        atuin.finish(handle, Some(0), Duration::ZERO).unwrap();
+        assert_eq!(
+            failure(&mut atuin, &entry),
+            Err("atuin failed: no database".into())
+        );
+        let _ = fs::remove_dir_all(log.parent().unwrap());
+
+        let mut missing = Atuin {
+            program: PathBuf::from("no-such-atuin-for-shim"),
+            ..Atuin::new()
+        };
+        assert_eq!(
+            failure(&mut missing, &entry),
+            Err("atuin isn't installed".into())
+        );
+    }
+}
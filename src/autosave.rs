@@ -0,0 +1,379 @@
+//! Saving the buffers every so often, so a crash loses little. Every
+//! `autosave_minutes`, and after every `autosave_commands` commands, they
+//! are written to `autosave` in the config directory: to a file beside it
+//! first, then renamed over it, so a crash while writing leaves the last
+//! one whole. Writing is done on a thread of its own so drawing never
+//! waits for the disk, and only buffers that changed since the last save
+//! are serialized again, each keeping its last `autosave_lines` lines.
+//! Each shim has a file of its own, named for its process, and holds a
+//! lock on another beside it for as long as it runs. Quitting cleanly
+//! removes both; at startup, the autosave of a shim whose lock is free
+//! was left by one that didn't quit cleanly, and it's moved aside for
+//! `:recover` to bring its buffers back, finished. If it can't be
+//! written, a toast says so once and shim carries on, trying again at
+//! the next save.
+
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+    thread::{self, JoinHandle},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Model, Output, clock,
+    toast::{Level, TTL},
+};
+
+/// How often to look for a save in progress having finished.
+const POLL: Duration = Duration::from_secs(1);
+
+/// Shim `instance`'s autosave in `dir`, and the lock it holds.
+fn paths(dir: &Path, instance: u32) -> (PathBuf, PathBuf) {
+    (
+        dir.join(format!("autosave-{instance}")),
+        dir.join(format!("autosave-{instance}.lock")),
+    )
+}
+
+/// Where the autosave of a shim that's gone is moved to, for `:recover`.
+fn crashed(path: &Path) -> PathBuf {
+    path.with_extension("crashed")
+}
+
+/// Move aside the autosaves of shims in `dir` that are gone.
+fn reap(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        let instance = name
+            .strip_prefix("autosave-")
+            .and_then(|n| n.strip_suffix(".lock"))
+            .and_then(|n| n.parse().ok());
+        let Some(instance) = instance else {
+            continue;
+        };
+        let Ok(lock) = File::open(entry.path()) else {
+            continue;
+        };
+        // still running
+        if lock.try_lock().is_err() {
+            continue;
+        }
+        let (path, lock_path) = paths(dir, instance);
+        let _ = fs::rename(&path, crashed(&path));
+        let _ = fs::remove_file(path.with_extension("tmp"));
+        drop(lock);
+        let _ = fs::remove_file(lock_path);
+    }
+}
+
+/// What shims that are gone left in `dir`, the latest last.
+fn crashed_saves(dir: &Path) -> Vec<(PathBuf, Saved)> {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    let mut saves: Vec<_> = entries
+        .flatten()
+        .map(|e| e.path())
+        .filter(|p| {
+            let name = p.file_name().unwrap_or_default().to_string_lossy();
+            name.starts_with("autosave-") && name.ends_with(".crashed")
+        })
+        .filter_map(|p| read(&p).map(|saved| (p, saved)))
+        .collect();
+    saves.sort_by_key(|(_, saved)| saved.saved_at);
+    saves
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Buffer {
+    command: String,
+    #[serde(default)]
+    status: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    title: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct Saved {
+    /// Seconds since the epoch.
+    saved_at: u64,
+    buffers: Vec<Buffer>,
+}
+
+/// What a buffer's saved form depends on.
+#[derive(Debug, PartialEq)]
+struct Key {
+    len: usize,
+    dropped: usize,
+    status: Option<i32>,
+    running: bool,
+    title: Option<String>,
+    tags: Vec<String>,
+}
+
+impl Key {
+    fn of(output: &Output) -> Self {
+        Key {
+            len: output.stdout.len(),
+            dropped: output.dropped,
+            status: output.status,
+            running: output.running,
+            title: output.title.clone(),
+            tags: output.tags.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Autosave {
+    /// Where autosaves are kept; `None` to not save.
+    dir: Option<PathBuf>,
+    /// This shim's.
+    path: Option<PathBuf>,
+    /// Locked while this shim runs.
+    held: Option<(File, PathBuf)>,
+    /// When the last save started.
+    last: Option<Instant>,
+    /// Commands run since then.
+    commands: usize,
+    /// Each buffer's saved form, by id, with what it was made from.
+    cache: HashMap<usize, (Key, String)>,
+    /// The buffers in the last save, to see if any went.
+    ids: Vec<usize>,
+    writing: Option<JoinHandle<io::Result<()>>>,
+    /// The last save failed.
+    failed: bool,
+    warned: bool,
+}
+
+/// The last `lines` lines of `text`.
+fn tail(text: &str, lines: usize) -> &str {
+    let body = text.strip_suffix('\n').unwrap_or(text);
+    match body.rmatch_indices('\n').nth(lines.saturating_sub(1)) {
+        Some((at, _)) if lines > 0 => &text[at + 1..],
+        _ if lines > 0 => text,
+        _ => "",
+    }
+}
+
+fn write(path: &Path, json: &str) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let temporary = path.with_extension("tmp");
+    fs::write(&temporary, json)?;
+    fs::rename(&temporary, path)
+}
+
+impl Model {
+    /// When the next save is due, or a save going on is to be looked in on.
+    pub fn autosave_due(&self) -> Option<Instant> {
+        let autosave = &self.autosave;
+        autosave.path.as_ref()?;
+        if autosave.writing.is_some() {
+            return Some(Instant::now() + POLL);
+        }
+        let minutes = self.config.autosave_minutes;
+        let last = autosave.last.filter(|_| minutes > 0)?;
+        Some(last + Duration::from_secs(minutes * 60))
+    }
+
+    /// Save if it's been `autosave_minutes` since the last time.
+    pub fn autosave_if_due(&mut self, now: Instant) {
+        self.reap_autosave(false);
+        let minutes = self.config.autosave_minutes;
+        if minutes == 0 {
+            return;
+        }
+        match self.autosave.last {
+            Some(last) if now < last + Duration::from_secs(minutes * 60) => {}
+            Some(_) => self.autosave(now),
+            // counted from startup
+            None => self.autosave.last = Some(now),
+        }
+    }
+
+    /// A command was run; save after every `autosave_commands` of them.
+    pub fn autosave_ran(&mut self) {
+        self.autosave.commands += 1;
+        let every = self.config.autosave_commands;
+        if every > 0 && self.autosave.commands >= every {
+            self.autosave(Instant::now());
+        }
+    }
+
+    /// Start writing the buffers out, unless a save is still going or
+    /// nothing changed since the last.
+    pub fn autosave(&mut self, now: Instant) {
+        self.reap_autosave(false);
+        let Some(path) = self.autosave.path.clone() else {
+            return;
+        };
+        if self.autosave.writing.is_some() {
+            return;
+        }
+        self.autosave.last = Some(now);
+        self.autosave.commands = 0;
+        let lines = self.config.autosave_lines;
+        let autosave = &mut self.autosave;
+        let ids: Vec<usize> = self.outputs.iter().map(|o| o.id).collect();
+        let mut changed = autosave.failed || ids != autosave.ids;
+        autosave.cache.retain(|id, _| ids.contains(id));
+        let mut buffers = Vec::new();
+        for output in &self.outputs {
+            let key = Key::of(output);
+            let saved = autosave.cache.get(&output.id).filter(|(k, _)| *k == key);
+            let json = match saved {
+                Some((_, json)) => json.clone(),
+                None => {
+                    changed = true;
+                    let buffer = Buffer {
+                        command: output.command.clone(),
+                        status: output.status,
+                        title: output.title.clone(),
+                        tags: output.tags.clone(),
+                        text: tail(&output.stdout, lines).to_string(),
+                    };
+                    let json = serde_json::to_string(&buffer).unwrap_or_default();
+                    autosave.cache.insert(output.id, (key, json.clone()));
+                    json
+                }
+            };
+            buffers.push(json);
+        }
+        autosave.ids = ids;
+        if !changed {
+            return;
+        }
+        let saved_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let json = format!(
+            "{{\"saved_at\":{saved_at},\"buffers\":[{}]}}",
+            buffers.join(",")
+        );
+        autosave.writing = Some(thread::spawn(move || write(&path, &json)));
+    }
+
+    /// See how a save going on went, waiting for it with `wait`.
+    pub fn reap_autosave(&mut self, wait: bool) {
+        let autosave = &mut self.autosave;
+        if !wait && !autosave.writing.as_ref().is_some_and(|w| w.is_finished()) {
+            return;
+        }
+        let Some(writing) = autosave.writing.take() else {
+            return;
+        };
+        let result = writing
+            .join()
+            .unwrap_or_else(|_| Err(io::Error::other("panicked")));
+        autosave.failed = result.is_err();
+        if let Err(e) = result
+            && !autosave.warned
+        {
+            autosave.warned = true;
+            let path = autosave.path.as_deref().unwrap_or(Path::new("autosave"));
+            let text = format!("can't autosave to {}: {e}", path.display());
+            self.push_toast(Level::Warn, text, TTL * 2);
+        }
+    }
+
+    /// On quitting cleanly: the autosave isn't needed.
+    pub fn end_autosave(&mut self) {
+        self.reap_autosave(true);
+        if let Some(path) = &self.autosave.path {
+            let _ = fs::remove_file(path);
+        }
+        if let Some((lock, path)) = self.autosave.held.take() {
+            drop(lock);
+            let _ = fs::remove_file(path);
+        }
+    }
+
+    /// At startup, as shim `instance`, autosave to `dir`; move aside what
+    /// shims that didn't quit cleanly left there, and offer to bring it
+    /// back.
+    pub fn start_autosave(&mut self, dir: PathBuf, instance: u32) {
+        self.autosave.last = Some(Instant::now());
+        reap(&dir);
+        let (path, lock_path) = paths(&dir, instance);
+        let lock = fs::create_dir_all(&dir).and_then(|()| File::create(&lock_path));
+        self.autosave.held = lock
+            .ok()
+            .filter(|lock| lock.try_lock().is_ok())
+            .map(|lock| (lock, lock_path));
+        self.autosave.path = Some(path);
+        let saves = crashed_saves(&dir);
+        self.autosave.dir = Some(dir);
+        let Some((_, saved)) = saves.last() else {
+            return;
+        };
+        let at = clock::format(UNIX_EPOCH + Duration::from_secs(saved.saved_at));
+        let mut offer = format!(
+            "shim didn't quit cleanly; :recover brings back the {} buffers saved at {at}",
+            saved.buffers.len()
+        );
+        if saves.len() > 1 {
+            offer.push_str(&format!(", then {} older autosaves", saves.len() - 1));
+        }
+        match self.status {
+            None => self.status = Some(offer),
+            Some(_) => self.push_toast(Level::Info, offer, TTL * 3),
+        }
+    }
+
+    /// `:recover`: bring back the buffers of the latest shim that didn't
+    /// quit cleanly.
+    pub fn recover(&mut self) -> String {
+        let Some(dir) = &self.autosave.dir else {
+            return "autosave is off".into();
+        };
+        let mut saves = crashed_saves(dir);
+        let Some((crashed, saved)) = saves.pop() else {
+            return "nothing to recover".into();
+        };
+        let count = saved.buffers.len();
+        for buffer in saved.buffers {
+            self.push_output(buffer.command, buffer.text);
+            let output = &mut self.outputs[self.viewing_output];
+            output.status = buffer.status;
+            output.title = buffer.title;
+            output.tags = buffer.tags;
+        }
+        let _ = fs::remove_file(&crashed);
+        let at = clock::format(UNIX_EPOCH + Duration::from_secs(saved.saved_at));
+        match saves.len() {
+            0 => format!("brought back {count} buffers saved at {at}"),
+            more => format!("brought back {count} buffers saved at {at}; {more} more to :recover"),
+        }
+    }
+}
+
+fn read(path: &Path) -> Option<Saved> {
+    let json = fs::read_to_string(path).ok()?;
+    serde_json::from_str(&json).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffers_keep_their_last_lines() {
+        assert_eq!(tail("a\nb\nc\n", 2), "b\nc\n");
+        assert_eq!(tail("a\nb\nc", 2), "b\nc");
+        assert_eq!(tail("a\nb\n", 5), "a\nb\n");
+        assert_eq!(tail("a\nb\n", 0), "");
+    }
+}
@@ -0,0 +1,125 @@
+//! Bookmarks on lines of an output buffer, for a few places to come back
+//! to in a long log: `M` bookmarks the line under the cursor or takes the
+//! bookmark off, `]b` and `[b` go to the next and previous one, and
+//! `:bookmarks` lists them with their lines. Output only grows at the
+//! end, so a bookmarked line keeps its number as more comes in. A
+//! bookmarked line's first character is drawn reversed.
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    style::{Style, Stylize},
+};
+
+use crate::{
+    Model, display,
+    picker::{Picker, PickerKind},
+};
+
+/// How much of a line the `:bookmarks` list shows.
+const PREVIEW: usize = 80;
+
+impl Model {
+    /// `M`
+    pub fn toggle_bookmark(&mut self) {
+        let Some((index, line)) = self.line_under_cursor() else {
+            self.status = Some("M bookmarks a line of output".into());
+            return;
+        };
+        let bookmarks = &mut self.outputs[index].bookmarks;
+        let status = match bookmarks.remove(&line) {
+            true => format!("removed the bookmark on line {}", line + 1),
+            false => {
+                bookmarks.insert(line);
+                format!("bookmarked line {}", line + 1)
+            }
+        };
+        self.status = Some(status);
+    }
+
+    /// `]b` and `[b`: the `count`th bookmark below or above the cursor in
+    /// the viewed buffer, or the last one there is that way.
+    pub fn jump_to_bookmark(&mut self, forward: bool, count: usize) {
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return;
+        };
+        let here = match self.line_under_cursor() {
+            Some((index, line)) if index == self.viewing_output => line,
+            _ => output.scroll.0,
+        };
+        let found = match forward {
+            true => output.bookmarks.range(here + 1..).take(count).last(),
+            false => output.bookmarks.range(..here).rev().take(count).last(),
+        };
+        match (found, forward) {
+            (Some(&line), _) => self.jump_to_output_line(line),
+            (None, true) => self.status = Some("no bookmark below".into()),
+            (None, false) => self.status = Some("no bookmark above".into()),
+        }
+    }
+
+    /// `:bookmarks`
+    pub fn open_bookmarks(&mut self) -> String {
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return "no buffer".into();
+        };
+        if output.bookmarks.is_empty() {
+            return "no bookmarks in this buffer".into();
+        }
+        let items = output
+            .bookmarks
+            .iter()
+            .map(|&n| {
+                let line = output.line(n).unwrap_or("");
+                let line: String = line.chars().take(PREVIEW).collect();
+                format!("{:>6}  {line}", n + 1)
+            })
+            .collect();
+        self.picker = Some(Picker::new(PickerKind::Bookmarks, items));
+        String::new()
+    }
+
+    /// Go to the bookmark picked from the `:bookmarks` list.
+    pub fn jump_to_picked_bookmark(&mut self, row: &str) {
+        let line = row.split_whitespace().next().and_then(|n| n.parse().ok());
+        if let Some(line) = line.and_then(|n: usize| n.checked_sub(1)) {
+            self.jump_to_output_line(line);
+        }
+    }
+}
+
+/// Reverse the first character of each bookmarked line in view.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    if model.scrollback.is_some() {
+        return;
+    }
+    let Some(output) = model.outputs.get(model.viewing_output) else {
+        return;
+    };
+    if output.bookmarks.is_empty() {
+        return;
+    }
+    let (width, settings) = (model.wrap_width(), model.output_settings());
+    let top = output.scroll.0;
+    let inner = pane.inner(Margin::new(1, 1));
+    let mut screen_row = 0;
+    for (i, line) in model.shown_lines().enumerate() {
+        if screen_row >= inner.height as usize {
+            break;
+        }
+        if output.bookmarks.contains(&(top + i)) {
+            let area = Rect {
+                x: inner.x,
+                y: inner.y + screen_row as u16,
+                width: 1,
+                height: 1,
+            }
+            .intersection(inner);
+            frame.buffer_mut().set_style(area, Style::new().reversed());
+        }
+        screen_row += match model.wrapping() {
+            true => display::rows(line, width, settings).len(),
+            false => 1,
+        };
+    }
+}
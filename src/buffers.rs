@@ -0,0 +1,336 @@
+//! The buffer picker, `B`. Buffers that ran the same command are grouped
+//! into one row, newest first, saying how many runs there were and how
+//! the latest went and how long it took; choosing the row views the latest
+//! run. Tab or → opens a group up to list its runs by when they started,
+//! to choose one of those, and Tab or ← closes it again. Commands are the
+//! same if they differ only in blanks outside quotes, and with
+//! `group_words` set, in what comes after that many words. Ctrl-f switches
+//! to a flat list of every buffer and back. A buffer's title and note
+//! (`:title`, `:note`) come after its command, and after a group's for
+//! each of its runs. Typing narrows the list to the rows that match, group
+//! rows and the runs of open groups alike, keeping their order. Buffers
+//! closed lately come last, marked ✕, to be brought back with Enter, or
+//! the latest with Ctrl-u; see `closed`.
+
+use std::collections::HashSet;
+
+use crate::{
+    Model, Output, clock,
+    closed::Closed,
+    memory::format_size,
+    picker::{Picker, PickerKind},
+    stats,
+};
+
+/// A row of the picker.
+#[derive(Debug, Clone, PartialEq)]
+enum Row {
+    /// Buffers by id, newest first, and what they're grouped by.
+    Group { key: String, ids: Vec<usize> },
+    /// A buffer by id.
+    Run(usize),
+    /// A closed buffer, by where it is on the stack.
+    Closed(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct BufferPicker {
+    /// Every buffer in a row of its own.
+    flat: bool,
+    /// Groups opened up, by key.
+    expanded: HashSet<String>,
+    /// The rows of the open picker.
+    rows: Vec<Row>,
+}
+
+/// The words of `command`, split at blanks outside quotes.
+fn words(command: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in command.chars() {
+        match (quote, c) {
+            _ if escaped => escaped = false,
+            (Some('\''), '\'') => quote = None,
+            (Some('\''), _) => {}
+            (_, '\\') => escaped = true,
+            (Some('"'), '"') => quote = None,
+            (None, '\'' | '"') => quote = Some(c),
+            (None, c) if c.is_whitespace() => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+                continue;
+            }
+            _ => {}
+        }
+        word.push(c);
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// What buffers running `command` are grouped by: its first `keep` words,
+/// or all of them for 0, with single blanks between.
+pub fn group_key(command: &str, keep: usize) -> String {
+    let words = words(command);
+    let keep = match keep {
+        0 => words.len(),
+        n => n.min(words.len()),
+    };
+    words[..keep].join(" ")
+}
+
+impl Output {
+    /// How the command went, or that it's still running.
+    fn outcome(&self) -> String {
+        let took = self
+            .duration
+            .map(|d| format!("  {}", stats::format_ms(d.as_millis() as u64)))
+            .unwrap_or_default();
+        format!("{}{took}", self.state())
+    }
+
+    /// Its title and note, to go after its command.
+    fn labels(&self) -> String {
+        [&self.title, &self.note]
+            .into_iter()
+            .flatten()
+            .map(|label| format!("  {}", label.replace('\n', " ")))
+            .collect()
+    }
+}
+
+impl Model {
+    /// `B`: pick a buffer to view.
+    pub fn open_buffer_picker(&mut self) {
+        if self.outputs.is_empty() && self.closed.is_empty() {
+            self.status = Some("no buffers".into());
+            return;
+        }
+        self.buffer_picker.rows = self.buffer_rows();
+        let mut picker = Picker::new(PickerKind::Buffers, Vec::new());
+        picker.ordered = true;
+        picker.set_items(self.buffer_row_texts());
+        self.picker = Some(picker);
+    }
+
+    /// The rows, newest first: groups, with the runs of open ones after
+    /// them, or every buffer when flat, and then the closed.
+    fn buffer_rows(&self) -> Vec<Row> {
+        let mut rows = self.open_buffer_rows();
+        rows.extend((0..self.closed.len()).rev().map(Row::Closed));
+        rows
+    }
+
+    fn open_buffer_rows(&self) -> Vec<Row> {
+        let newest = self.outputs.iter().rev();
+        if self.buffer_picker.flat {
+            return newest.map(|o| Row::Run(o.id)).collect();
+        }
+        let mut groups: Vec<(String, Vec<usize>)> = Vec::new();
+        for output in newest {
+            let key = group_key(&output.command, self.config.group_words);
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, ids)) => ids.push(output.id),
+                None => groups.push((key, vec![output.id])),
+            }
+        }
+        let mut rows = Vec::new();
+        for (key, ids) in groups {
+            let runs = match self.buffer_picker.expanded.contains(&key) && ids.len() > 1 {
+                true => ids.clone(),
+                false => Vec::new(),
+            };
+            rows.push(Row::Group { key, ids });
+            rows.extend(runs.into_iter().map(Row::Run));
+        }
+        rows
+    }
+
+    fn buffer_row_texts(&self) -> Vec<String> {
+        self.buffer_picker
+            .rows
+            .iter()
+            .map(|row| self.buffer_row_text(row))
+            .collect()
+    }
+
+    fn buffer_row_text(&self, row: &Row) -> String {
+        let output = |id| self.job_index(id).map(|i| (i, &self.outputs[i]));
+        match row {
+            Row::Group { key, ids } => {
+                let Some((_, latest)) = output(ids[0]) else {
+                    return key.clone();
+                };
+                let open = match (ids.len(), self.buffer_picker.expanded.contains(key)) {
+                    (1, _) => ' ',
+                    (_, true) => '▾',
+                    (_, false) => '▸',
+                };
+                let labels: String = ids
+                    .iter()
+                    .filter_map(|&id| output(id))
+                    .map(|(_, run)| run.labels())
+                    .collect();
+                format!("{open} {key}  ×{}  {}{labels}", ids.len(), latest.outcome())
+            }
+            Row::Run(id) => {
+                let Some((index, run)) = output(*id) else {
+                    return String::new();
+                };
+                let started = run.started_at.map_or_else(
+                    || "-".into(),
+                    |t| clock::strftime(t, &self.config.time_format, self.config.utc),
+                );
+                let indent = if self.buffer_picker.flat { "" } else { "    " };
+                format!(
+                    "{indent}{:>3}  {started}  {}  {}{}",
+                    index + 1,
+                    run.outcome(),
+                    run.command.replace('\n', " "),
+                    run.labels()
+                )
+            }
+            Row::Closed(at) => match &self.closed[*at] {
+                Closed::Buffer { output, .. } => format!(
+                    "✕ {}  {}  closed",
+                    output.command.replace('\n', " "),
+                    output.outcome()
+                ),
+                Closed::Contents { id, contents } => {
+                    let command = output(*id).map_or("", |(_, o)| &o.command);
+                    format!(
+                        "✕ {}  {} evicted",
+                        command.replace('\n', " "),
+                        format_size(contents.size())
+                    )
+                }
+            },
+        }
+    }
+
+    /// Make the rows again, keeping the same one selected, or else the
+    /// group a run went into or a group's latest run.
+    fn refresh_buffer_picker(&mut self) {
+        let Some(picker) = self
+            .picker
+            .as_ref()
+            .filter(|p| p.kind == PickerKind::Buffers)
+        else {
+            return;
+        };
+        let selected = picker
+            .selected_index()
+            .and_then(|i| self.buffer_picker.rows.get(i).cloned());
+        self.buffer_picker.rows = self.buffer_rows();
+        let texts = self.buffer_row_texts();
+        let rows = &self.buffer_picker.rows;
+        let index = selected.and_then(|was| {
+            let same = |row: &Row| match (row, &was) {
+                (Row::Group { key, .. }, Row::Group { key: was, .. }) => key == was,
+                (row, was) => row == was,
+            };
+            let near = |row: &Row| match (row, &was) {
+                (Row::Group { ids, .. }, Row::Run(id)) => ids.contains(id),
+                (Row::Run(id), Row::Group { ids, .. }) => ids[0] == *id,
+                _ => false,
+            };
+            rows.iter()
+                .position(same)
+                .or_else(|| rows.iter().position(near))
+        });
+        let Some(picker) = self.picker.as_mut() else {
+            return;
+        };
+        picker.set_items(texts);
+        if let Some(index) = index {
+            picker.select_index(index);
+        }
+    }
+
+    /// Tab, or → and ← for `open`: open the selected group up or close it.
+    /// On a run, its group is closed.
+    pub fn expand_group(&mut self, open: Option<bool>) {
+        let Some(picker) = self
+            .picker
+            .as_ref()
+            .filter(|p| p.kind == PickerKind::Buffers)
+        else {
+            return;
+        };
+        let row = picker
+            .selected_index()
+            .and_then(|i| self.buffer_picker.rows.get(i));
+        let key = match row {
+            Some(Row::Group { key, ids }) if ids.len() > 1 => key.clone(),
+            Some(Row::Run(id)) if open != Some(true) && !self.buffer_picker.flat => {
+                let Some(output) = self.outputs.iter().find(|o| o.id == *id) else {
+                    return;
+                };
+                group_key(&output.command, self.config.group_words)
+            }
+            _ => return,
+        };
+        let open = match open {
+            Some(open) => open,
+            None => {
+                matches!(row, Some(Row::Group { .. }))
+                    && !self.buffer_picker.expanded.contains(&key)
+            }
+        };
+        match open {
+            true => self.buffer_picker.expanded.insert(key),
+            false => self.buffer_picker.expanded.remove(&key),
+        };
+        self.refresh_buffer_picker();
+    }
+
+    /// Ctrl-f: switch between groups and every buffer.
+    pub fn toggle_flat_buffers(&mut self) {
+        self.buffer_picker.flat = !self.buffer_picker.flat;
+        self.refresh_buffer_picker();
+    }
+
+    /// Ctrl-u: bring back the latest closed, keeping the picker open.
+    pub fn undo_close_in_picker(&mut self) {
+        self.status = Some(self.undo_close(None));
+        self.refresh_buffer_picker();
+    }
+
+    /// View the buffer of the chosen row, the latest run for a group, or
+    /// bring a closed one back.
+    pub fn view_picked_buffer(&mut self, index: Option<usize>) {
+        let id = match index.and_then(|i| self.buffer_picker.rows.get(i)) {
+            Some(Row::Group { ids, .. }) => ids[0],
+            Some(Row::Run(id)) => *id,
+            Some(Row::Closed(at)) => {
+                self.status = Some(self.undo_close(Some(*at)));
+                return;
+            }
+            None => return,
+        };
+        if let Some(index) = self.job_index(id) {
+            self.view_output(index);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_are_grouped_by_their_words() {
+        assert_eq!(group_key("  cargo   test\t--lib ", 0), "cargo test --lib");
+        assert_eq!(group_key("echo 'a  b'", 0), "echo 'a  b'");
+        assert_eq!(group_key(r#"echo "a  \" b" c"#, 0), r#"echo "a  \" b" c"#);
+        assert_eq!(group_key("echo a\\ b", 0), "echo a\\ b");
+        assert_eq!(group_key("cargo test --lib", 2), "cargo test");
+        assert_eq!(group_key("ls", 2), "ls");
+        assert_eq!(group_key("", 0), "");
+    }
+}
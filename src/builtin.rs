@@ -0,0 +1,368 @@
+use std::fmt::Write;
+
+use crate::{
+    Model, abbr, dirs, dotenv,
+    memory::format_size,
+    shell::{self, Expansion},
+    snippet, vars,
+};
+
+pub const NAMES: [&str; 46] = [
+    "abbr",
+    "at",
+    "bookmarks",
+    "buffers",
+    "capture",
+    "checkhealth",
+    "ctx",
+    "drop-old",
+    "each",
+    "encoding",
+    "env",
+    "follow",
+    "import-history",
+    "invisibles",
+    "messages",
+    "note",
+    "parse",
+    "pin",
+    "pipe",
+    "pipe!",
+    "preview",
+    "queue",
+    "raw",
+    "record",
+    "recover",
+    "registers",
+    "replay",
+    "rerun",
+    "retag-run",
+    "retry",
+    "scrollback",
+    "session",
+    "snippet",
+    "sort",
+    "sort!",
+    "stats",
+    "suspend",
+    "sync-history",
+    "table",
+    "tabnew",
+    "tag",
+    "title",
+    "top",
+    "trace",
+    "undo-close",
+    "uniq",
+];
+
+/// Builtins that act on the viewed buffer or only set something, so they
+/// report on the status line instead of opening a buffer of their own.
+pub const REPORTS_ON_STATUS: [&str; 40] = [
+    "at",
+    "bookmarks",
+    "capture",
+    "ctx",
+    "each",
+    "encoding",
+    "export",
+    "fg",
+    "follow",
+    "import-history",
+    "invisibles",
+    "jobs",
+    "kill",
+    "note",
+    "pin",
+    "pipe",
+    "pipe!",
+    "preview",
+    "queue",
+    "raw",
+    "record",
+    "recover",
+    "registers",
+    "replay",
+    "rerun",
+    "retag-run",
+    "retry",
+    "scrollback",
+    "session",
+    "sort",
+    "sort!",
+    "suspend",
+    "sync-history",
+    "table",
+    "tabnew",
+    "tag",
+    "title",
+    "top",
+    "undo-close",
+    "uniq",
+];
+
+/// How many commands `:top` lists.
+const TOP_COMMANDS: usize = 20;
+
+/// Builtins run like ordinary commands, without a `:`.
+pub const SHELL_NAMES: [&str; 8] = [
+    "cd", "dirs", "export", "fg", "jobs", "popd", "pushd", "vars",
+];
+
+/// The builtin `command` invokes, if any.
+pub fn lookup(command: &str) -> Option<&'static str> {
+    let command = command.trim_start();
+    match command.strip_prefix(':') {
+        Some(rest) => match rest.split_whitespace().next() {
+            Some(name) => NAMES.iter().find(|n| **n == name).copied(),
+            None => Some(""),
+        },
+        None => {
+            let mut words = command.split_whitespace();
+            let name = words.next()?;
+            // other kills go to the kill program
+            if name == "kill" && words.any(|w| w.starts_with('%')) {
+                return Some("kill");
+            }
+            SHELL_NAMES.iter().find(|n| **n == name).copied()
+        }
+    }
+}
+
+/// Run a builtin against the model, returning the text to show for it.
+pub fn run(model: &mut Model, line: &str) -> String {
+    let mut args = line.trim_start().trim_start_matches(':').split_whitespace();
+    match args.next().unwrap_or("") {
+        name @ ("cd" | "dirs" | "export" | "fg" | "jobs" | "kill" | "popd" | "pushd" | "vars") => {
+            let args = model
+                .parse(line)
+                .map(|parsed| parsed.args)
+                .unwrap_or_default();
+            match name {
+                "cd" => dirs::cd(model, &args),
+                "pushd" => dirs::pushd(model, &args),
+                "popd" => dirs::popd(model),
+                "export" => vars::export(model, &args),
+                "vars" => vars::vars(model),
+                "jobs" => model.jobs(&args),
+                "fg" => model.fg(&args),
+                "kill" => model.kill_job(&args),
+                _ => dirs::dirs(model),
+            }
+        }
+        "snippet" => snippet::command(model, line),
+        "abbr" => abbr::command(model, line),
+        "env" => dotenv::command(model, &args.collect::<Vec<_>>()),
+        "capture" => vars::capture(model, &args.collect::<Vec<_>>()),
+        "buffers" => buffers(model),
+        "checkhealth" => model.check_health(),
+        "ctx" => model.switch_context(args.next()),
+        "messages" => model.messages(),
+        "follow" => model.toggle_follow(&args.map(String::from).collect::<Vec<_>>()),
+        "drop-old" => {
+            let evicted = model.evict_to(model.config.memory_cap);
+            format!(
+                "evicted {evicted} buffers, {} in use",
+                format_size(model.memory_usage())
+            )
+        }
+        "parse" => {
+            let rest = line.trim_start_matches(':').trim_start();
+            parse(model, rest.strip_prefix("parse").unwrap_or(rest))
+        }
+        "queue" => {
+            let rest = line.trim_start_matches(':').trim_start();
+            match rest.strip_prefix("queue").unwrap_or("").trim() {
+                "" if model.queue.pending.is_empty() => "nothing queued".into(),
+                "" => {
+                    model.open_queue_picker();
+                    model.queue_summary().unwrap_or_default()
+                }
+                command => model.enqueue(command.to_string()),
+            }
+        }
+        "each" => {
+            let rest = line.trim_start_matches(':').trim_start();
+            let template = rest.strip_prefix("each").unwrap_or("").trim();
+            model.start_each(line, template)
+        }
+        name @ ("pipe" | "pipe!") => {
+            let rest = line.trim_start_matches(':').trim_start();
+            let command = rest.strip_prefix(name).unwrap_or("").trim();
+            model.pipe(command, name == "pipe!")
+        }
+        "preview" => {
+            let rest = line.trim_start_matches(':').trim_start();
+            model.preview(rest.strip_prefix("preview").unwrap_or("").trim())
+        }
+        "retry" => {
+            let rest = line.trim_start_matches(':').trim_start();
+            let rest = rest.strip_prefix("retry").unwrap_or("").trim_start();
+            let (attempts, command) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+            match (attempts.parse::<u32>(), command.trim()) {
+                (Ok(attempts @ 1..), command) if !command.is_empty() => {
+                    model.start_retrying(attempts, command.to_string());
+                    format!("trying {command} up to {attempts} times")
+                }
+                _ => "usage: :retry N CMD".into(),
+            }
+        }
+        "stats" => {
+            let rest = line.trim_start_matches(':').trim_start();
+            model.stats(rest.strip_prefix("stats").unwrap_or(""))
+        }
+        "at" => model.view_at(args.next().unwrap_or("")),
+        "suspend" => model.suspend(),
+        "sync-history" => model.sync_history(),
+        "trace" => model.dump_trace(),
+        "encoding" => model.set_next_encoding(args.next()),
+        "tag" => model.tag(args.next().unwrap_or("")),
+        "table" => model.toggle_table(),
+        "tabnew" => model.new_workspace(args.next()),
+        "scrollback" => model.toggle_scrollback(),
+        "import-history" => model.import_history(&args.collect::<Vec<_>>()),
+        name @ ("sort" | "sort!" | "uniq") => model.derive(name, &args.collect::<Vec<_>>()),
+        name @ ("title" | "note") => {
+            let rest = line.trim_start_matches(':').trim_start();
+            let text = rest.strip_prefix(name).unwrap_or("");
+            match name {
+                "title" => model.set_title(text),
+                _ => model.set_note(text),
+            }
+        }
+        "retag-run" => model.retag_run(args.next().unwrap_or("")),
+        "rerun" => model.rerun(&args.collect::<Vec<_>>()),
+        "record" => model.record(&args.collect::<Vec<_>>()),
+        "recover" => model.recover(),
+        "session" => match args.next() {
+            Some(path) => model.save_session(path),
+            None => "usage: :session FILE".into(),
+        },
+        "replay" => match args.next() {
+            Some(path) => model.start_replay(path).err().unwrap_or_default(),
+            None => "usage: :replay FILE".into(),
+        },
+        "bookmarks" => model.open_bookmarks(),
+        "registers" if model.registers.all().is_empty() => "registers are empty".into(),
+        "registers" => {
+            model.open_registers_picker();
+            String::new()
+        }
+        "top" => {
+            model.open_history_picker(Some(TOP_COMMANDS));
+            String::new()
+        }
+        "undo-close" => model.undo_close(None),
+        "pin" => match model.outputs.get_mut(model.viewing_output) {
+            Some(output) => {
+                output.pinned = !output.pinned;
+                let state = if output.pinned { "pinned" } else { "unpinned" };
+                format!("{state} {}", output.command)
+            }
+            None => "no buffer to pin".into(),
+        },
+        "raw" => match model.outputs.get_mut(model.viewing_output) {
+            Some(output) if output.raw.is_none() => "nothing was redacted".into(),
+            Some(output) => {
+                output.show_raw = !output.show_raw;
+                output.line_index.changed();
+                let state = if output.show_raw { "showing" } else { "hiding" };
+                format!("{state} secrets in {}", output.command)
+            }
+            None => "no buffer".into(),
+        },
+        "invisibles" => match model.outputs.get_mut(model.viewing_output) {
+            Some(output) => {
+                output.show_invisibles = !output.show_invisibles;
+                let state = if output.show_invisibles {
+                    "showing"
+                } else {
+                    "hiding"
+                };
+                format!("{state} invisibles in {}", output.command)
+            }
+            None => "no buffer".into(),
+        },
+        "" => String::new(),
+        other => format!("unknown command: {other}"),
+    }
+}
+
+fn buffers(model: &Model) -> String {
+    let mut listing = String::new();
+    for (i, output) in model.outputs.iter().enumerate() {
+        let viewing = if i == model.viewing_output { '*' } else { ' ' };
+        let pinned = if output.pinned { '+' } else { ' ' };
+        let size = match output.evicted {
+            Some(freed) => format!("evicted {}", format_size(freed)),
+            None => format_size(output.size()),
+        };
+        let peak = output
+            .peak_note()
+            .map(|p| format!("  ({p})"))
+            .unwrap_or_default();
+        let tags = output
+            .tag_note()
+            .map(|t| format!("  {t}"))
+            .unwrap_or_default();
+        let encoding = output
+            .decoder
+            .note()
+            .map(|e| format!("  [{e}]"))
+            .unwrap_or_default();
+        let warned = match output.warnings.is_empty() {
+            true => "",
+            false => "  ⚠",
+        };
+        let command = match &output.title {
+            Some(title) => format!("{title} — {}", output.command),
+            None => output.command.clone(),
+        };
+        let _ = writeln!(
+            listing,
+            "{viewing}{pinned}{:>3}  {size:>14}  {command}{tags}{encoding}{peak}{warned}",
+            i + 1,
+        );
+        if let Some(note) = &output.note {
+            let _ = writeln!(listing, "{:>23}{note}", "");
+        }
+    }
+    let _ = write!(
+        listing,
+        "total {} / cap {}",
+        format_size(model.memory_usage()),
+        format_size(model.config.memory_cap)
+    );
+    listing
+}
+
+/// Show how `command` would be tokenized and expanded, without running it.
+pub fn parse(model: &Model, command: &str) -> String {
+    let Some(parsed) = model.parse(command) else {
+        return "nothing to run".into();
+    };
+    let mut preview = match shell::resolve(&parsed.program, |name| model.var(name)) {
+        Some(path) => format!("program: {} ({path})\n", parsed.program),
+        None => format!("program: {} (not found)\n", parsed.program),
+    };
+    for (i, arg) in parsed.args.iter().enumerate() {
+        let _ = writeln!(preview, "arg {}: {arg}", i + 1);
+    }
+    for expansion in &parsed.expansions {
+        let _ = match expansion {
+            Expansion::Variable {
+                name,
+                value: Some(value),
+            } => writeln!(preview, "${name} → {value}"),
+            Expansion::Variable { name, value: None } => writeln!(preview, "${name} → (unset)"),
+            Expansion::Tilde { home } => writeln!(preview, "~ → {home}"),
+            Expansion::Glob { pattern, matches } if matches.is_empty() => {
+                writeln!(preview, "{pattern} → (no matches, passed as is)")
+            }
+            Expansion::Glob { pattern, matches } => {
+                writeln!(preview, "{pattern} → {}", matches.join(" "))
+            }
+        };
+    }
+    preview
+}
@@ -0,0 +1,238 @@
+//! Local wall clock times as `HH:MM:SS`, without pulling in a date crate.
+//! The offset from UTC is asked of `date` once; where that can't be done,
+//! times are in UTC. Also the clock on the top line, and when each
+//! command started and ended, shown in its title; both are written out
+//! with a pattern of `%` fields from the config, in local time or UTC.
+
+use std::{
+    fmt::Write,
+    process::Command,
+    sync::OnceLock,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Model, Output};
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Seconds east of UTC.
+fn offset() -> i64 {
+    static OFFSET: OnceLock<i64> = OnceLock::new();
+    *OFFSET.get_or_init(|| {
+        let output = Command::new("date").arg("+%z").output().ok();
+        output
+            .and_then(|o| parse_offset(String::from_utf8_lossy(&o.stdout).trim()))
+            .unwrap_or(0)
+    })
+}
+
+/// `+0130` or `-0800` as seconds.
+fn parse_offset(text: &str) -> Option<i64> {
+    let (sign, digits) = match text.split_at_checked(1)? {
+        ("+", digits) => (1, digits),
+        ("-", digits) => (-1, digits),
+        _ => return None,
+    };
+    if digits.len() != 4 || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    let hours: i64 = digits[..2].parse().ok()?;
+    let minutes: i64 = digits[2..].parse().ok()?;
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Seconds since local midnight at `time`.
+fn seconds_of_day(time: SystemTime) -> u64 {
+    let since = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    (since + offset()).rem_euclid(DAY as i64) as u64
+}
+
+fn format_seconds(seconds: u64) -> String {
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+pub fn format(time: SystemTime) -> String {
+    format_seconds(seconds_of_day(time))
+}
+
+/// Year, month and day of the day `days` after 1970-01-01.
+fn civil(days: i64) -> (i64, i64, i64) {
+    // from Howard Hinnant's date algorithms, counting from 0000-03-01
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    (yoe + era * 400 + (month <= 2) as i64, month, day)
+}
+
+/// `time` written out as `pattern`: %Y, %m, %d, %H, %M and %S, %F for
+/// %Y-%m-%d, %T for %H:%M:%S and %% for a %; anything else as it is.
+pub fn strftime(time: SystemTime, pattern: &str, utc: bool) -> String {
+    let since = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs() as i64;
+    let local = since + if utc { 0 } else { offset() };
+    let (days, seconds) = (local.div_euclid(DAY as i64), local.rem_euclid(DAY as i64));
+    let (year, month, day) = civil(days);
+    let (hour, minute, second) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    let mut text = String::new();
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        if c != '%' {
+            text.push(c);
+            continue;
+        }
+        let _ = match chars.next() {
+            Some('Y') => write!(text, "{year:04}"),
+            Some('m') => write!(text, "{month:02}"),
+            Some('d') => write!(text, "{day:02}"),
+            Some('H') => write!(text, "{hour:02}"),
+            Some('M') => write!(text, "{minute:02}"),
+            Some('S') => write!(text, "{second:02}"),
+            Some('F') => write!(text, "{year:04}-{month:02}-{day:02}"),
+            Some('T') => write!(text, "{hour:02}:{minute:02}:{second:02}"),
+            Some('%') => write!(text, "%"),
+            Some(other) => write!(text, "%{other}"),
+            None => write!(text, "%"),
+        };
+    }
+    text
+}
+
+/// How often what `pattern` shows changes, in seconds.
+fn period(pattern: &str) -> u64 {
+    match pattern.contains("%S") || pattern.contains("%T") {
+        true => 1,
+        false => 60,
+    }
+}
+
+/// How long from `time` until the clock next turns over a whole `period`
+/// of seconds. Offsets from UTC are whole minutes, so that's the same in
+/// local time.
+fn until_turn(time: SystemTime, period: u64) -> Duration {
+    let since = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let into = Duration::from_secs(since.as_secs() % period)
+        + Duration::from_nanos(since.subsec_nanos() as u64);
+    Duration::from_secs(period) - into
+}
+
+impl Output {
+    /// Note that the command starts now.
+    pub fn mark_started(&mut self) {
+        self.started = Some(Instant::now());
+        self.started_at = Some(SystemTime::now());
+        self.ended_at = None;
+    }
+
+    /// When the command started and, once it's over, ended, for the
+    /// title.
+    pub fn run_times(&self, pattern: &str, utc: bool) -> Option<String> {
+        let started = strftime(self.started_at?, pattern, utc);
+        Some(match self.ended_at.filter(|_| !self.running) {
+            Some(ended) => format!("{started} → {}", strftime(ended, pattern, utc)),
+            None => started,
+        })
+    }
+}
+
+impl Model {
+    /// The clock for the top line, if it's shown.
+    pub fn clock_note(&self) -> Option<String> {
+        let pattern = self.config.clock.as_deref()?;
+        Some(strftime(SystemTime::now(), pattern, self.config.utc))
+    }
+
+    /// When the clock next changes, from `now`, if it's shown.
+    pub fn clock_due(&self, now: Instant) -> Option<Instant> {
+        let pattern = self.config.clock.as_deref()?;
+        Some(now + until_turn(SystemTime::now(), period(pattern)))
+    }
+}
+
+/// `HH:MM` or `HH:MM:SS` as seconds since midnight.
+fn parse_seconds(text: &str) -> Option<u64> {
+    let parts: Vec<_> = text.split(':').collect();
+    if !(2..=3).contains(&parts.len()) {
+        return None;
+    }
+    let mut seconds = 0;
+    for (i, part) in parts.iter().enumerate() {
+        let max = if i == 0 { 24 } else { 60 };
+        // minutes and seconds take two digits, hours one or two
+        if !(1..=2).contains(&part.len()) || (i > 0 && part.len() != 2) {
+            return None;
+        }
+        let n: u64 = part.parse().ok().filter(|n| *n < max)?;
+        seconds += n * [3600, 60, 1][i];
+    }
+    Some(seconds)
+}
+
+/// The last time up to now that the clock read `text` (`HH:MM[:SS]`).
+pub fn parse(text: &str) -> Option<SystemTime> {
+    let seconds = parse_seconds(text)?;
+    let now = SystemTime::now();
+    let today = seconds_of_day(now);
+    let back = (today + DAY - seconds) % DAY;
+    Some(now - Duration::from_secs(back))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offsets() {
+        assert_eq!(parse_offset("+0000"), Some(0));
+        assert_eq!(parse_offset("+0130"), Some(5400));
+        assert_eq!(parse_offset("-0800"), Some(-28800));
+        assert_eq!(parse_offset("0800"), None);
+        assert_eq!(parse_offset(""), None);
+    }
+
+    #[test]
+    fn times_of_day() {
+        assert_eq!(parse_seconds("14:32"), Some(14 * 3600 + 32 * 60));
+        assert_eq!(parse_seconds("9:05:07"), Some(9 * 3600 + 5 * 60 + 7));
+        assert_eq!(parse_seconds("24:00"), None);
+        assert_eq!(parse_seconds("14:3"), None);
+        assert_eq!(parse_seconds("14:32:05:01"), None);
+        assert_eq!(parse_seconds("noon"), None);
+        assert_eq!(format_seconds(14 * 3600 + 32 * 60 + 5), "14:32:05");
+    }
+
+    #[test]
+    fn patterns() {
+        assert_eq!(civil(0), (1970, 1, 1));
+        assert_eq!(civil(11_016), (2000, 2, 29));
+        assert_eq!(civil(-1), (1969, 12, 31));
+        // 2024-01-01 14:02:11 UTC
+        let time = UNIX_EPOCH + Duration::from_secs(19_723 * DAY + 14 * 3600 + 2 * 60 + 11);
+        assert_eq!(strftime(time, "%T", true), "14:02:11");
+        assert_eq!(strftime(time, "%F %H:%M", true), "2024-01-01 14:02");
+        assert_eq!(
+            strftime(time, "%d/%m/%Y 100%% %q%", true),
+            "01/01/2024 100% %q%"
+        );
+        assert_eq!(until_turn(time, 60), Duration::from_secs(49));
+        assert_eq!(
+            until_turn(time + Duration::from_millis(300), 1),
+            Duration::from_millis(700)
+        );
+        assert_eq!((period("%H:%M"), period("%T")), (60, 1));
+    }
+}
@@ -0,0 +1,111 @@
+//! Closed buffers, kept a while to be brought back. A buffer closed to
+//! stay under `max_buffers` goes on a stack of recently closed ones, as do
+//! contents `memory_cap` evicts that fit in `closed_size`. `:undo-close`
+//! brings back the latest: a buffer to where it was in the list, scrolled
+//! and with the cursor where it was left, or evicted contents into their
+//! buffer. The stack keeps the last `closed_buffers`, dropping the oldest
+//! while they come to more than `closed_size`. A buffer comes back as it
+//! was closed, finished: its command is long gone and nothing more is
+//! read for it. The buffer picker lists them after the others, where
+//! Enter brings one back and Ctrl-u the latest.
+
+use std::collections::BTreeSet;
+
+use crate::{Model, Output, stderr};
+
+/// What `evict` took out of a buffer, to be put back.
+#[derive(Debug)]
+pub struct Contents {
+    pub stdout: String,
+    pub raw: Option<String>,
+    pub streams: Option<stderr::Streams>,
+    pub bookmarks: BTreeSet<usize>,
+    pub scroll: (usize, u16),
+}
+
+impl Contents {
+    pub fn size(&self) -> usize {
+        self.stdout.len()
+            + self.raw.as_ref().map_or(0, String::len)
+            + self.streams.as_ref().map_or(0, stderr::Streams::size)
+    }
+}
+
+#[derive(Debug)]
+pub enum Closed {
+    /// A buffer, closed at `index` in the list.
+    Buffer { output: Box<Output>, index: usize },
+    /// What was evicted from buffer `id`.
+    Contents { id: usize, contents: Box<Contents> },
+}
+
+impl Closed {
+    fn size(&self) -> usize {
+        match self {
+            Closed::Buffer { output, .. } => output.size(),
+            Closed::Contents { contents, .. } => contents.size(),
+        }
+    }
+}
+
+impl Model {
+    /// Keep `closed`, dropping the oldest kept to stay within bounds.
+    pub fn keep_closed(&mut self, closed: Closed) {
+        self.closed.push(closed);
+        let mut size: usize = self.closed.iter().map(Closed::size).sum();
+        while self.closed.len() > self.config.closed_buffers || size > self.config.closed_size {
+            size -= self.closed.remove(0).size();
+        }
+    }
+
+    /// `:undo-close`: bring back the latest closed, or the one at `which`
+    /// on the stack.
+    pub fn undo_close(&mut self, which: Option<usize>) -> String {
+        let which = which.or(self.closed.len().checked_sub(1));
+        let Some(which) = which.filter(|&i| i < self.closed.len()) else {
+            return "no closed buffers".into();
+        };
+        match self.closed.remove(which) {
+            Closed::Buffer { mut output, index } => {
+                // finished, whatever it was doing when closed
+                output.running = false;
+                output.process = None;
+                output.held = false;
+                if let Some(retry) = output.retry.as_mut() {
+                    retry.next = None;
+                }
+                let command = output.command.clone();
+                let index = index.min(self.outputs.len());
+                self.save_cursor();
+                if !self.outputs.is_empty() && self.viewing_output >= index {
+                    self.viewing_output += 1;
+                }
+                self.outputs.insert(index, *output);
+                self.view_output(index);
+                format!("brought back {command}")
+            }
+            Closed::Contents { id, contents } => {
+                let Some(index) = self.job_index(id) else {
+                    // closed since, so they go back in with it
+                    let closed = self.closed.iter_mut().find_map(|c| match c {
+                        Closed::Buffer { output, .. } if output.id == id => Some(output),
+                        _ => None,
+                    });
+                    return match closed {
+                        Some(output) => {
+                            output.restore(*contents);
+                            format!("put back the contents of {}, still closed", output.command)
+                        }
+                        None => "the buffer those contents were evicted from is gone".into(),
+                    };
+                };
+                self.outputs[index].restore(*contents);
+                self.view_output(index);
+                format!(
+                    "brought back the contents of {}",
+                    self.outputs[index].command
+                )
+            }
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Cursor columns on a row of the command line. They count bytes. In
+//! Normal mode the cursor rests on a character; in Insert mode it may also
+//! sit after the last one, like vim.
+
+use std::cmp::min;
+
+use crate::Mode;
+
+/// Column `x`, moved back to the start of the character it falls in and
+/// kept within the row.
+pub fn floor(row: &str, x: usize) -> usize {
+    let mut x = min(x, row.len());
+    while !row.is_char_boundary(x) {
+        x -= 1;
+    }
+    x
+}
+
+/// The column after the character at `x`, or the end of the row.
+pub fn next(row: &str, x: usize) -> usize {
+    let x = floor(row, x);
+    x + row[x..].chars().next().map_or(0, char::len_utf8)
+}
+
+/// The column of the character before `x`, or the start of the row.
+pub fn previous(row: &str, x: usize) -> usize {
+    let x = floor(row, x);
+    x - row[..x].chars().next_back().map_or(0, char::len_utf8)
+}
+
+/// The furthest the cursor may go: after the last character in Insert
+/// and Replace mode, on it in Normal mode.
+pub fn last(row: &str, mode: &Mode) -> usize {
+    match mode {
+        Mode::Insert | Mode::Replace => row.len(),
+        Mode::Normal => previous(row, row.len()),
+    }
+}
+
+/// Where the cursor rests for column `x` in `mode`.
+pub fn rest(row: &str, x: usize, mode: &Mode) -> usize {
+    min(floor(row, x), last(row, mode))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_mode_rests_on_a_character() {
+        assert_eq!(last("ls", &Mode::Normal), 1);
+        assert_eq!(last("", &Mode::Normal), 0);
+        assert_eq!(rest("ls", 2, &Mode::Normal), 1);
+        assert_eq!(rest("ls", 7, &Mode::Normal), 1);
+        assert_eq!(rest("ls", 0, &Mode::Normal), 0);
+        assert_eq!(rest("", 3, &Mode::Normal), 0);
+        // on the start of the last character, not its last byte
+        assert_eq!(last("caf\u{e9}", &Mode::Normal), 3);
+        assert_eq!(rest("caf\u{e9}", 4, &Mode::Normal), 3);
+    }
+
+    #[test]
+    fn insert_mode_may_sit_after_the_end() {
+        assert_eq!(last("ls", &Mode::Insert), 2);
+        assert_eq!(last("", &Mode::Insert), 0);
+        assert_eq!(rest("ls", 2, &Mode::Insert), 2);
+        assert_eq!(rest("ls", 7, &Mode::Insert), 2);
+        assert_eq!(rest("caf\u{e9}", 4, &Mode::Insert), 3);
+        assert_eq!(rest("caf\u{e9}", 5, &Mode::Insert), 5);
+    }
+
+    #[test]
+    fn moving_stays_within_the_row() {
+        assert_eq!(next("ls", 1), 2);
+        assert_eq!(next("ls", 2), 2);
+        assert_eq!(next("", 0), 0);
+        assert_eq!(previous("ls", 0), 0);
+        assert_eq!(previous("caf\u{e9}", 5), 3);
+        assert_eq!(next("caf\u{e9}", 3), 5);
+        // a column left behind by a longer command
+        assert_eq!(next("ls", 9), 2);
+        assert_eq!(previous("ls", 9), 1);
+        assert_eq!(previous("caf\u{e9}", 4), 2);
+    }
+
+    #[test]
+    fn appending_on_an_empty_row_stays_put() {
+        // `a` moves to the column after the one the cursor rests on
+        let append = |row: &str, x| next(row, rest(row, x, &Mode::Normal));
+        assert_eq!(append("", 0), 0);
+        assert_eq!(append("ls", 1), 2);
+        assert_eq!(append("ls", 0), 1);
+    }
+
+    #[test]
+    fn leaving_insert_mode_pulls_back_from_the_end() {
+        // Esc after typing `ls` at the end of the row
+        assert_eq!(rest("ls", 2, &Mode::Normal), 1);
+        // but not from the middle
+        assert_eq!(rest("ls", 1, &Mode::Normal), 1);
+    }
+}
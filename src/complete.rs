@@ -0,0 +1,717 @@
+//! Completing the word before the cursor from commands on `$PATH`, files,
+//! variables and past commands, chosen from a popup above the command line.
+//! A word ending in `$` or `${` and a name completes variables, shown with
+//! their values, masked if they look like secrets; not in single quotes,
+//! where nothing is expanded.
+
+use std::{collections::BTreeSet, env, fs, ops::Range, path::Path};
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListState},
+};
+use regex::Regex;
+
+use crate::{
+    Cursor, Model, builtin,
+    completer::{self, Key},
+    history, path_index, redact, shell,
+};
+
+/// Rows of candidates shown at once; the rest scroll.
+const MAX_SHOWN: u16 = 10;
+
+/// End one command and start another, so a command name comes next.
+const SEPARATORS: [char; 4] = [';', '|', '&', '('];
+
+/// Shown while only the outside completer may have something to offer.
+const WAITING: &str = "completing…";
+
+/// Past commands offered besides the word's own candidates.
+const MAX_HISTORY: usize = 5;
+
+/// Characters of a variable's value shown beside it.
+const MAX_VALUE: usize = 40;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    File,
+    Dir,
+    Binary,
+    Builtin,
+    History,
+    Variable,
+    /// From the outside completer
+    External,
+}
+
+impl Kind {
+    fn label(self) -> &'static str {
+        match self {
+            Kind::File => "file",
+            Kind::Dir => "dir",
+            Kind::Binary => "binary",
+            Kind::Builtin => "builtin",
+            Kind::History => "history",
+            Kind::Variable => "variable",
+            Kind::External => "external",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Candidate {
+    pub text: String,
+    pub kind: Kind,
+    /// Shown after the kind: where a binary lives, looked up once it's
+    /// shown, or what the outside completer says about it.
+    pub detail: String,
+    /// Byte offset in the command where the text replaces what's typed,
+    /// up to the cursor.
+    pub start: usize,
+}
+
+/// The open completion popup.
+#[derive(Debug)]
+pub struct Completion {
+    pub candidates: Vec<Candidate>,
+    pub selected: usize,
+    /// The first candidate shown.
+    offset: usize,
+}
+
+impl Completion {
+    pub fn next(&mut self) {
+        self.selected = (self.selected + 1) % self.candidates.len().max(1);
+    }
+
+    pub fn previous(&mut self) {
+        self.selected = self
+            .selected
+            .checked_sub(1)
+            .unwrap_or(self.candidates.len().saturating_sub(1));
+    }
+
+    /// The candidates in a popup `rows` high, scrolled no more than needed
+    /// to show the selected one. Where those that are binaries live is
+    /// looked up now, rather than for every match as it's typed.
+    fn shown(&mut self, rows: usize) -> Range<usize> {
+        self.offset = self
+            .offset
+            .min(self.selected)
+            .max((self.selected + 1).saturating_sub(rows.max(1)));
+        let shown =
+            self.offset.min(self.candidates.len())..(self.offset + rows).min(self.candidates.len());
+        for candidate in &mut self.candidates[shown.clone()] {
+            if candidate.kind == Kind::Binary && candidate.detail.is_empty() {
+                candidate.detail = path_index::resolve(&candidate.text)
+                    .map(|p| p.display().to_string())
+                    .unwrap_or_default();
+            }
+        }
+        shown
+    }
+}
+
+/// Where the word ending at the end of `before` starts. Escaped spaces
+/// are part of the word.
+fn word_start(before: &str) -> usize {
+    let mut start = 0;
+    let mut escaped = false;
+    for (i, c) in before.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if !cfg!(windows) => escaped = true,
+            c if c.is_whitespace() || SEPARATORS.contains(&c) => start = i + c.len_utf8(),
+            _ => {}
+        }
+    }
+    start
+}
+
+fn unescape(word: &str) -> String {
+    if cfg!(windows) {
+        return word.to_string();
+    }
+    let mut unescaped = String::new();
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// Protect what the shell would otherwise split or expand.
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        if c.is_whitespace() || (!cfg!(windows) && "\\'\"$*?[;|&()".contains(c)) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// What the word being completed is.
+#[derive(Debug, PartialEq)]
+enum Context {
+    Command,
+    Dir,
+    Path,
+    /// A variable's name, after the `$` at this offset in the word, and
+    /// a `{` if it's braced.
+    Variable(usize, bool),
+}
+
+/// Whether the end of `line` is inside single quotes.
+fn in_single_quotes(line: &str) -> bool {
+    let mut quote = None;
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (None, '\'') | (None, '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None | Some('"'), '\\') if !cfg!(windows) => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    quote == Some('\'')
+}
+
+/// The variable `word` ends in the name of, if any: where its `$` is and
+/// whether it's braced.
+fn variable_reference(word: &str) -> Option<(usize, bool)> {
+    let name = word
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .count();
+    let before = &word[..word.len() - name];
+    let (dollar, braced) = match before.strip_suffix("${") {
+        Some(rest) => (rest.len(), true),
+        None => (before.strip_suffix('$')?.len(), false),
+    };
+    let backslashes = word[..dollar]
+        .chars()
+        .rev()
+        .take_while(|&c| c == '\\')
+        .count();
+    let escaped = !cfg!(windows) && backslashes % 2 == 1;
+    let numbered = word[word.len() - name..].starts_with(|c: char| c.is_ascii_digit());
+    (!escaped && !numbered).then_some((dollar, braced))
+}
+
+/// A variable's value as shown beside it: on one line, cut short, and
+/// masked if `patterns` take it for a secret.
+fn shown_value(name: &str, value: &str, patterns: &[Regex]) -> String {
+    let assignment = format!("{name}={value}");
+    if redact::line(patterns, &assignment) != assignment {
+        return redact::MASK.into();
+    }
+    let value = history::escape(value);
+    match value.char_indices().nth(MAX_VALUE) {
+        Some((end, _)) => format!("{}…", &value[..end]),
+        None => value,
+    }
+}
+
+/// What's being completed at the end of `line`, the word starting at
+/// `start`.
+fn context(line: &str, start: usize) -> Context {
+    let word = &line[start..];
+    if let Some((dollar, braced)) = variable_reference(word)
+        && !in_single_quotes(line)
+    {
+        return Context::Variable(dollar, braced);
+    }
+    let before = &line[..start];
+    let first_word = before.trim_end().ends_with(SEPARATORS) || before.trim().is_empty();
+    match (first_word, word.contains('/')) {
+        (true, false) => Context::Command,
+        (false, _) if before.trim() == "cd" => Context::Dir,
+        _ => Context::Path,
+    }
+}
+
+/// Commands and builtins starting with `prefix`.
+fn commands(prefix: &str, start: usize) -> Vec<Candidate> {
+    if let Some(name) = prefix.strip_prefix(':') {
+        return builtin::NAMES
+            .iter()
+            .filter(|n| n.starts_with(name))
+            .map(|n| Candidate {
+                text: format!(":{n}"),
+                kind: Kind::Builtin,
+                detail: String::new(),
+                start,
+            })
+            .collect();
+    }
+    let builtins = builtin::SHELL_NAMES
+        .iter()
+        .filter(|n| n.starts_with(prefix))
+        .map(|n| Candidate {
+            text: n.to_string(),
+            kind: Kind::Builtin,
+            detail: String::new(),
+            start,
+        });
+    let binaries = path_index::executables()
+        .iter()
+        .filter(|n| n.starts_with(prefix) && !builtin::SHELL_NAMES.contains(&n.as_str()))
+        .map(|n| Candidate {
+            text: n.clone(),
+            kind: Kind::Binary,
+            detail: String::new(),
+            start,
+        });
+    builtins.chain(binaries).collect()
+}
+
+/// Files and directories starting with `word`, which may name a directory
+/// to look in first.
+fn paths(word: &str, start: usize, only_dirs: bool) -> Vec<Candidate> {
+    let (dir, prefix) = match word.rfind('/') {
+        Some(i) => (&word[..=i], &word[i + 1..]),
+        None => ("", word),
+    };
+    let look_in = match dir.strip_prefix('~') {
+        Some(rest) if dir == "~/" || rest.starts_with('/') => {
+            let home = shell::home(|name| env::var(name).ok()).unwrap_or_default();
+            format!("{home}{rest}")
+        }
+        _ if dir.is_empty() => ".".into(),
+        _ => dir.into(),
+    };
+    let Ok(entries) = fs::read_dir(Path::new(&look_in)) else {
+        return Vec::new();
+    };
+    let mut candidates: Vec<_> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            // like ls, dotfiles only when asked for
+            if !name.starts_with(prefix) || (name.starts_with('.') && !prefix.starts_with('.')) {
+                return None;
+            }
+            // follows symlinks, so a link to a directory completes as one
+            let is_dir = fs::metadata(entry.path()).is_ok_and(|m| m.is_dir());
+            if only_dirs && !is_dir {
+                return None;
+            }
+            Some(Candidate {
+                text: match is_dir {
+                    true => format!("{dir}{name}/"),
+                    false => format!("{dir}{name}"),
+                },
+                kind: if is_dir { Kind::Dir } else { Kind::File },
+                detail: String::new(),
+                start,
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| a.text.cmp(&b.text));
+    candidates
+}
+
+impl Model {
+    /// Variables whose names start with `prefix`, as `$NAME`, or `${NAME}`
+    /// if `braced` (`${NAME` if the `}` is there already).
+    fn variables(&self, prefix: &str, start: usize, braced: bool, closed: bool) -> Vec<Candidate> {
+        let overlay = self.env_overlay.iter().flat_map(|o| o.vars.keys().cloned());
+        let names: BTreeSet<String> = env::vars_os()
+            .filter_map(|(name, _)| name.into_string().ok())
+            .chain(overlay)
+            .chain(self.vars.keys().cloned())
+            .filter(|name| name.starts_with(prefix))
+            .collect();
+        names
+            .into_iter()
+            .map(|name| Candidate {
+                detail: shown_value(
+                    &name,
+                    &self.var(&name).unwrap_or_default(),
+                    &self.config.redact,
+                ),
+                text: match (braced, closed) {
+                    (false, _) => format!("${name}"),
+                    (true, false) => format!("${{{name}}}"),
+                    (true, true) => format!("${{{name}"),
+                },
+                kind: Kind::Variable,
+                start,
+            })
+            .collect()
+    }
+
+    /// Byte offset of the cursor in the command being typed.
+    fn completion_cursor(&self) -> Option<usize> {
+        match self.cursor {
+            Cursor::CommandLine(x, y) => Some(self.command_offset(x, y)),
+            Cursor::OutputBuffer(_, _) => None,
+        }
+    }
+
+    /// Byte offset of the word being completed.
+    pub fn completion_anchor(&self) -> usize {
+        let cursor = self.completion_cursor().unwrap_or(0);
+        word_start(&self.command()[..cursor])
+    }
+
+    /// What the outside completer is asked about for the word before the
+    /// cursor: not command names or builtins, which shim knows.
+    fn completer_key(&self) -> Option<Key> {
+        self.config.completer.as_ref()?;
+        let before = &self.command()[..self.completion_cursor()?];
+        let line = &before[..word_start(before)];
+        let line = &line[line.rfind('\n').map_or(0, |i| i + 1)..];
+        let first_word = line.trim_end().ends_with(SEPARATORS) || line.trim().is_empty();
+        if first_word || line.trim_start().starts_with(':') {
+            return None;
+        }
+        Some((line.to_string(), env::current_dir().ok()?))
+    }
+
+    /// Ask the outside completer about the word before the cursor unless
+    /// it's been asked already. Returns whether an answer is awaited.
+    fn request_completions(&mut self) -> bool {
+        let Some(key) = self.completer_key() else {
+            return false;
+        };
+        if self.completer.cache.contains_key(&key) {
+            return false;
+        }
+        if self.completer.pending.contains(&key) {
+            return true;
+        }
+        let (Some(completer), Some(events)) = (&self.config.completer, &self.events) else {
+            return false;
+        };
+        let requested = completer::request(
+            completer,
+            key.clone(),
+            self.config.completer_timeout,
+            events.clone(),
+        );
+        if requested {
+            self.completer.pending.insert(key);
+        }
+        requested
+    }
+
+    /// The outside completer answered; show what it found if the popup is
+    /// still open.
+    pub fn receive_completions(&mut self, key: Key, values: Option<completer::Values>) {
+        self.completer.pending.remove(&key);
+        if let Some(values) = values {
+            self.completer.cache.insert(key, values);
+        }
+        self.refresh_completion();
+    }
+
+    /// Everything the text before the cursor could be completed to.
+    pub fn candidates(&self) -> Vec<Candidate> {
+        let Some(cursor) = self.completion_cursor() else {
+            return Vec::new();
+        };
+        let command = self.command();
+        let before = &command[..cursor];
+        let start = word_start(before);
+        let word = unescape(&before[start..]);
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let mut candidates = match context(&before[line_start..], start - line_start) {
+            Context::Command => commands(&word, start),
+            Context::Dir => paths(&word, start, true),
+            Context::Path => paths(&word, start, false),
+            Context::Variable(dollar, braced) => {
+                let name = &before[start + dollar + 1 + braced as usize..];
+                let closed = command[cursor..].starts_with('}');
+                self.variables(name, start + dollar, braced, closed)
+            }
+        };
+        // the outside completer knows better, so its candidates come first
+        let cached = self
+            .completer_key()
+            .and_then(|key| self.completer.cache.get(&key));
+        if let Some(values) = cached {
+            let external: Vec<_> = values
+                .iter()
+                .filter(|(value, _)| value.starts_with(&word))
+                .map(|(value, description)| Candidate {
+                    text: value.clone(),
+                    kind: Kind::External,
+                    detail: description.clone(),
+                    start,
+                })
+                .collect();
+            candidates.retain(|c| !external.iter().any(|e| e.text == c.text));
+            candidates.splice(0..0, external);
+        }
+        let line = &before[line_start..];
+        if !line.trim().is_empty() {
+            let mut seen = Vec::new();
+            for entry in self.previous_commands.iter().rev() {
+                let past = entry.command.as_str();
+                if past.len() > line.len()
+                    && past.starts_with(line)
+                    && !past.contains('\n')
+                    && !seen.contains(&past)
+                {
+                    seen.push(past);
+                }
+                if seen.len() == MAX_HISTORY {
+                    break;
+                }
+            }
+            candidates.extend(seen.into_iter().map(|past| Candidate {
+                text: past.to_string(),
+                kind: Kind::History,
+                detail: String::new(),
+                start: line_start,
+            }));
+        }
+        candidates
+    }
+
+    /// Tab on the command line: fill in the only candidate, or open the
+    /// popup to choose between them.
+    pub fn open_completion(&mut self) {
+        let waiting = self.request_completions();
+        let mut candidates = self.candidates();
+        match candidates.len() {
+            // wait for the outside completer in an empty popup
+            0 if !waiting => self.status = Some("no completions".into()),
+            1 if !waiting => self.accept_candidate(candidates.remove(0)),
+            _ => {
+                self.completion = Some(Completion {
+                    candidates,
+                    selected: 0,
+                    offset: 0,
+                })
+            }
+        }
+    }
+
+    /// Narrow the popup down to what's typed now, keeping the selection
+    /// where it can.
+    pub fn refresh_completion(&mut self) {
+        let Some(completion) = self.completion.as_ref() else {
+            return;
+        };
+        let selected = completion
+            .candidates
+            .get(completion.selected)
+            .map(|c| c.text.clone());
+        let waiting = self
+            .completer_key()
+            .is_some_and(|key| self.completer.pending.contains(&key));
+        let candidates = self.candidates();
+        if candidates.is_empty() && !waiting {
+            self.completion = None;
+            return;
+        }
+        let selected = candidates
+            .iter()
+            .position(|c| Some(&c.text) == selected.as_ref())
+            .unwrap_or(0);
+        self.completion = Some(Completion {
+            candidates,
+            selected,
+            offset: 0,
+        });
+    }
+
+    pub fn accept_completion(&mut self) {
+        if let Some(mut completion) = self.completion.take()
+            && !completion.candidates.is_empty()
+        {
+            let candidate = completion.candidates.swap_remove(completion.selected);
+            self.accept_candidate(candidate);
+        }
+    }
+
+    /// Replace what's typed of the word with `candidate`.
+    fn accept_candidate(&mut self, candidate: Candidate) {
+        let Some(cursor) = self.completion_cursor() else {
+            return;
+        };
+        let mut text = match candidate.kind {
+            // already as it would be typed
+            Kind::History | Kind::Variable => candidate.text,
+            _ => escape(&candidate.text),
+        };
+        // a directory may be completed further, and a variable may be
+        // followed by more of the word; anything else is done
+        let more = matches!(candidate.kind, Kind::Dir | Kind::Variable);
+        if !more && !text.ends_with('/') {
+            text.push(' ');
+        }
+        self.edit_recalled();
+        self.current_command
+            .replace_range(candidate.start..cursor, &text);
+        let end = candidate.start + text.len();
+        let line_start = self.current_command[..end].rfind('\n').map_or(0, |i| i + 1);
+        let row = self.current_command[..end].matches('\n').count();
+        self.cursor = Cursor::CommandLine((end - line_start) as u16, row as u16);
+    }
+}
+
+/// Draw the popup above the command line, starting under the word being
+/// completed and kept on screen.
+pub fn render(completion: &mut Completion, frame: &mut Frame, command_line: Rect, x: u16) {
+    let screen = frame.area();
+    let rows = (completion.candidates.len() as u16)
+        .clamp(1, MAX_SHOWN)
+        .min(command_line.y.saturating_sub(screen.y + 2));
+    let shown = completion.shown(rows as usize);
+    let candidates = &completion.candidates;
+    let text_width = candidates
+        .iter()
+        .map(|c| c.text.chars().count())
+        .max()
+        .unwrap_or(0);
+    let kind_width = candidates
+        .iter()
+        .map(|c| c.kind.label().len())
+        .max()
+        .unwrap_or(0);
+    let labels: Vec<_> = candidates[shown.clone()]
+        .iter()
+        .map(|c| (c.text.as_str(), c.kind.label(), c.detail.as_str()))
+        .collect();
+    let detail_width = labels
+        .iter()
+        .map(|(_, _, d)| d.chars().count())
+        .max()
+        .unwrap_or(0);
+    let width = (text_width + kind_width + detail_width + 6).min(screen.width as usize) as u16;
+    let width = width.max(WAITING.len() as u16 + 2);
+    let height = rows + 2;
+    let area = Rect {
+        x: x.min(screen.right().saturating_sub(width)),
+        y: command_line.y.saturating_sub(height),
+        width,
+        height,
+    };
+    let mut items: Vec<_> = labels
+        .iter()
+        .map(|(text, kind, detail)| {
+            Line::from(vec![
+                Span::raw(format!("{text:text_width$}  ")),
+                Span::raw(format!("{kind:kind_width$}  ")).dark_gray(),
+                Span::raw(detail.to_string()).dark_gray(),
+            ])
+        })
+        .collect();
+    if items.is_empty() {
+        items.push(Line::from(WAITING).dark_gray());
+    }
+    let title = format!(
+        "{}/{}",
+        completion.selected + 1,
+        completion.candidates.len()
+    );
+    let list = List::new(items)
+        .block(Block::bordered().title(title))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let selected = completion.selected.checked_sub(shown.start);
+    let mut state = ListState::default().with_selected(selected);
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn context_tells_variables_from_commands_and_paths() {
+        assert_eq!(context("gi", 0), Context::Command);
+        assert_eq!(context("ls | gr", 5), Context::Command);
+        assert_eq!(context("cd sr", 3), Context::Dir);
+        assert_eq!(context("ls sr", 3), Context::Path);
+        assert_eq!(context("echo $CA", 5), Context::Variable(0, false));
+        assert_eq!(context("echo ${CA", 5), Context::Variable(0, true));
+        assert_eq!(context("ls --dir=$", 3), Context::Variable(6, false));
+        assert_eq!(context("echo \"$HO", 5), Context::Variable(1, false));
+        assert_eq!(context("echo '$HO", 5), Context::Path);
+        assert_eq!(context("echo 'a' $HO", 9), Context::Variable(0, false));
+        assert_eq!(context("echo $1", 5), Context::Path);
+        if !cfg!(windows) {
+            assert_eq!(context("echo \\$HO", 5), Context::Path);
+        }
+    }
+
+    #[test]
+    fn variables_complete_with_their_values() {
+        let mut model = Model::default();
+        for (name, value) in [("SHIM_TEST_DIR", "/tmp/x"), ("SHIM_TEST_TOKEN", "hunter2")] {
+            let value = crate::vars::Variable {
+                value: value.into(),
+                exported: false,
+            };
+            model.vars.insert(name.into(), value);
+        }
+        model.current_command = "ls ${SHIM_TEST_".into();
+        model.cursor = model.command_end();
+        let candidates = model.candidates();
+        let shown: Vec<_> = candidates
+            .iter()
+            .map(|c| (c.text.as_str(), c.detail.as_str()))
+            .collect();
+        assert_eq!(
+            shown,
+            [
+                ("${SHIM_TEST_DIR}", "/tmp/x"),
+                ("${SHIM_TEST_TOKEN}", redact::MASK)
+            ]
+        );
+        model.accept_candidate(candidates[0].clone());
+        assert_eq!(model.current_command, "ls ${SHIM_TEST_DIR}");
+        assert_eq!(model.cursor, model.command_end());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn only_binaries_shown_are_looked_up() {
+        let binary = Candidate {
+            text: "sh".into(),
+            kind: Kind::Binary,
+            detail: String::new(),
+            start: 0,
+        };
+        let mut completion = Completion {
+            candidates: vec![binary; 30],
+            selected: 0,
+            offset: 0,
+        };
+        let looked_up = |c: &Completion| -> Vec<usize> {
+            let found = c.candidates.iter().enumerate();
+            found
+                .filter(|(_, c)| !c.detail.is_empty())
+                .map(|(i, _)| i)
+                .collect()
+        };
+        assert_eq!(completion.shown(10), 0..10);
+        assert_eq!(looked_up(&completion), Vec::from_iter(0..10));
+        assert!(completion.candidates[0].detail.ends_with("/sh"));
+        // scrolled only as far as the selection needs
+        completion.selected = 25;
+        assert_eq!(completion.shown(10), 16..26);
+        completion.selected = 20;
+        assert_eq!(completion.shown(10), 16..26);
+        assert_eq!(
+            looked_up(&completion),
+            Vec::from_iter((0..10).chain(16..26))
+        );
+    }
+}
@@ -0,0 +1,227 @@
+//! Completions from an outside program, such as carapace, which knows the
+//! subcommands and arguments of other tools. It runs on its own thread
+//! with a timeout, and what it gives is cached, so a slow or missing
+//! completer only means fewer candidates.
+
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsStr,
+    io::Read,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{event::AppEvent, path_index};
+
+/// The line up to the word being completed, and where it was typed.
+pub type Key = (String, PathBuf);
+
+/// Candidates with their descriptions, which may be empty.
+pub type Values = Vec<(String, String)>;
+
+#[derive(Debug, Default)]
+pub struct Completer {
+    pub cache: HashMap<Key, Values>,
+    /// Asked for and not answered yet.
+    pub pending: HashSet<Key>,
+}
+
+/// Ask `completer` for the candidates after `key.0` in the background. The
+/// answer comes back as `AppEvent::Completions`, with `None` if the
+/// completer failed or took longer than `timeout`. Returns false, asking
+/// nothing, if the completer isn't installed.
+pub fn request(completer: &str, key: Key, timeout: Duration, events: Sender<AppEvent>) -> bool {
+    let command = match completer {
+        "carapace" => carapace(&key.0),
+        _ => custom(completer, &key.0),
+    };
+    let Some(command) = command.filter(|c| installed(c.get_program())) else {
+        return false;
+    };
+    thread::spawn(move || {
+        let values = run(command, timeout);
+        let _ = events.send(AppEvent::Completions { key, values });
+    });
+    true
+}
+
+fn installed(program: &OsStr) -> bool {
+    Path::new(program).is_file()
+        || program
+            .to_str()
+            .is_some_and(|name| path_index::resolve(name).is_some())
+}
+
+/// `carapace CMD export CMD ARGS... ""` for the last command on the line,
+/// which prints JSON.
+fn carapace(line: &str) -> Option<Command> {
+    let last = line.rsplit([';', '|', '&', '(']).next().unwrap_or(line);
+    let words: Vec<_> = last.split_whitespace().collect();
+    let (program, args) = words.split_first()?;
+    let mut command = Command::new("carapace");
+    command
+        .args([program, &"export", program])
+        .args(args)
+        .arg("");
+    Some(command)
+}
+
+/// Any other completer gets the line and the cursor position as its last
+/// two arguments and prints a candidate per line, with an optional
+/// description after a tab.
+fn custom(completer: &str, line: &str) -> Option<Command> {
+    let mut words = completer.split_whitespace();
+    let mut command = Command::new(words.next()?);
+    command.args(words).arg(line).arg(line.len().to_string());
+    Some(command)
+}
+
+fn run(mut command: Command, timeout: Duration) -> Option<Values> {
+    let carapace = command.get_program() == "carapace";
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    // read alongside, so a completer with a lot to say can't fill the pipe
+    let reader = thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).ok().map(|_| text)
+    });
+    let deadline = Instant::now() + timeout;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    let text = reader.join().ok()??;
+    if !status.success() {
+        return None;
+    }
+    match carapace {
+        true => parse_carapace(&text),
+        false => Some(parse_lines(&text)),
+    }
+}
+
+fn parse_lines(text: &str) -> Values {
+    text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| match line.split_once('\t') {
+            Some((value, description)) => (value.to_string(), description.to_string()),
+            None => (line.to_string(), String::new()),
+        })
+        .collect()
+}
+
+fn parse_carapace(text: &str) -> Option<Values> {
+    let json: serde_json::Value = serde_json::from_str(text).ok()?;
+    let values = json.get("values")?.as_array()?;
+    Some(
+        values
+            .iter()
+            .filter_map(|v| {
+                let value = v.get("value")?.as_str()?;
+                let description = v.get("description").and_then(|d| d.as_str());
+                Some((value.to_string(), description.unwrap_or("").to_string()))
+            })
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pair(value: &str, description: &str) -> (String, String) {
+        (value.into(), description.into())
+    }
+
+    #[test]
+    fn carapace_values_and_what_it_says_of_them() {
+        let output = r#"{
+            "version": "v1.0.0",
+            "nospace": "",
+            "values": [
+                {"value": "checkout", "display": "checkout", "description": "Switch branches"},
+                {"value": "clone", "display": "clone"},
+                {"display": "no value"},
+                {"value": 42, "description": "not a string"},
+                {"value": "commit", "description": null},
+                "not an object"
+            ]
+        }"#;
+        assert_eq!(
+            parse_carapace(output),
+            Some(vec![
+                pair("checkout", "Switch branches"),
+                pair("clone", ""),
+                pair("commit", "")
+            ])
+        );
+        assert_eq!(parse_carapace(r#"{"values": []}"#), Some(vec![]));
+        for broken in [
+            "",
+            "not json",
+            r#"{"values": "checkout"}"#,
+            r#"{"messages": []}"#,
+        ] {
+            assert_eq!(parse_carapace(broken), None, "{broken}");
+        }
+    }
+
+    #[test]
+    fn other_completers_give_a_line_each() {
+        assert_eq!(
+            parse_lines("main\tdefault branch\n\n  \nfeature/x\nodd\ttab\there\n"),
+            [
+                pair("main", "default branch"),
+                pair("feature/x", ""),
+                pair("odd", "tab\there")
+            ]
+        );
+    }
+
+    #[test]
+    fn carapace_is_asked_about_the_last_command() {
+        let command = carapace("cd src && git check").unwrap();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["git", "export", "git", "check", ""]);
+        assert!(carapace("ls | ").is_none());
+        let command = custom("my-completer --json", "git ch").unwrap();
+        let args: Vec<_> = command.get_args().collect();
+        assert_eq!(args, ["--json", "git ch", "6"]);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn a_slow_or_failing_completer_gives_nothing() {
+        let sh = |script: &str| {
+            let mut command = Command::new("sh");
+            command.args(["-c", script]);
+            command
+        };
+        let timeout = Duration::from_millis(200);
+        let values = run(sh("printf 'a\\tfirst\\nb\\n'"), timeout);
+        assert_eq!(values, Some(vec![pair("a", "first"), pair("b", "")]));
+        let started = Instant::now();
+        assert_eq!(run(sh("sleep 5"), timeout), None);
+        assert!(started.elapsed() < Duration::from_secs(2));
+        assert_eq!(run(sh("echo a; exit 1"), timeout), None);
+        assert_eq!(
+            run(Command::new("no-such-completer-for-shim"), timeout),
+            None
+        );
+    }
+}
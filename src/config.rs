@@ -0,0 +1,548 @@
+use std::{env, fs, path::PathBuf, time::Duration};
+
+use encoding_rs::Encoding;
+use regex::Regex;
+
+use crate::{
+    encoding, guard, history, passthrough, redact,
+    segment::{self, Segment},
+    theme::Theme,
+    typeahead::Keep,
+};
+
+/// User settings, read from `$XDG_CONFIG_HOME/shim/config` (`%APPDATA%` on
+/// Windows).
+///
+/// The file holds `key = value` lines; anything after a `#` is ignored.
+#[derive(Debug)]
+pub struct Config {
+    /// Total bytes of output kept in memory before old buffers are evicted.
+    pub memory_cap: usize,
+    /// Largest value a session variable may hold.
+    pub var_cap: usize,
+    /// Refuse to run a command using an unset variable instead of
+    /// expanding it to nothing.
+    pub unset_var_error: bool,
+    /// Rank commands that often fail lower in history suggestions.
+    pub penalize_failures: bool,
+    /// Hold the rest of the queue when a queued command fails.
+    pub queue_stop_on_failure: bool,
+    /// Attempts made when retrying a failed buffer with `r`.
+    pub retries: u32,
+    /// Start with the view locked, so new jobs don't take it over.
+    pub lock_view: bool,
+    /// Columns between tab stops in output.
+    pub tab_stop: usize,
+    /// Wrap output lines wider than the pane instead of cutting them off.
+    pub wrap: bool,
+    /// Positions of an output line drawn before the rest is cut off.
+    pub long_line: usize,
+    /// Run a command's output through the filter its last run was `:pipe`d
+    /// through.
+    pub repipe: bool,
+    /// Slide the view to where a big scroll or jump takes it.
+    pub smooth_scroll: bool,
+    /// Seconds between checkpoints of a job's output; see `scrub`.
+    pub checkpoint_interval: u64,
+    /// Checkpoints kept per buffer, the oldest dropped first.
+    pub checkpoint_limit: usize,
+    /// Expand abbreviations wherever they're typed, not only as the
+    /// command's first word.
+    pub abbr_anywhere: bool,
+    /// Warn about a command's first word that isn't a program or builtin,
+    /// before it's run; see `lint`.
+    pub warn_unknown_commands: bool,
+    /// Warn about quotes and brackets left open or closed too often.
+    pub warn_unbalanced: bool,
+    /// Warn about paths given to a command that don't exist, which may be
+    /// ones it's about to create.
+    pub warn_missing_paths: bool,
+    /// Turn on table mode for output whose columns line up under a
+    /// header; see `table`.
+    pub detect_tables: bool,
+    /// Put a failed command's exit code in front of the prompt, not only
+    /// turn it red.
+    pub show_exit_code: bool,
+    /// Record a pipeline's exit code as its first failing stage's, not
+    /// its last stage's.
+    pub pipefail: bool,
+    /// Draw images in output, where the terminal can; see `image`.
+    pub inline_images: bool,
+    /// Keep the `#` of a command stashed in history without running it.
+    pub stash_keep_hash: bool,
+    /// Run the command `gx` takes from the output instead of leaving it
+    /// on the command line.
+    pub run_from_output: bool,
+    /// How the clock on the top line is written out, if it's shown; see
+    /// `clock::strftime`.
+    pub clock: Option<String>,
+    /// How the times a command started and ended are written out in its
+    /// title.
+    pub time_format: String,
+    /// Show the clock and those times in UTC rather than local time.
+    pub utc: bool,
+    /// Colours; see `theme`.
+    pub theme: Theme,
+    /// Copy plain yanks to the system clipboard, not just `"+` ones.
+    pub clipboard: bool,
+    /// Take the mouse from the terminal to select output and scroll; see
+    /// `mouse`.
+    pub mouse: bool,
+    /// Yank a selection made with the mouse when the button is let go.
+    pub copy_on_select: bool,
+    /// Which keys typed while shim was busy are kept; see `typeahead`.
+    pub stale_keys: Keep,
+    /// Save registers with `:session`; off as they often hold secrets.
+    pub session_registers: bool,
+    /// Where history is read from and recorded to.
+    pub history: history::Source,
+    /// Program asked for completions shim can't work out itself; see
+    /// `completer`.
+    pub completer: Option<String>,
+    /// How long the completer may take before it's given up on.
+    pub completer_timeout: Duration,
+    /// Output matching any of these is masked; see `redact`.
+    pub redact: Vec<Regex>,
+    redact_defaults: bool,
+    redact_extra: Vec<Regex>,
+    /// Buffers kept before the oldest are closed; 0 for no limit.
+    pub max_buffers: usize,
+    /// Lines kept of each buffer, the oldest dropped; 0 for no limit. See
+    /// `anchor`.
+    pub max_lines: usize,
+    /// Only close buffers whose command succeeded.
+    pub keep_failed_buffers: bool,
+    /// Closed buffers kept to be brought back, and bytes of them; see
+    /// `closed`.
+    pub closed_buffers: usize,
+    pub closed_size: usize,
+    /// What commands' output is read as without a byte order mark, if
+    /// not UTF-8; see `encoding`.
+    pub encoding: Option<&'static Encoding>,
+    /// Keep a trace of the messages handled; see `trace`.
+    pub debug: bool,
+    /// Keep the characters typed in the trace.
+    pub trace_keys: bool,
+    /// Where the trace is written if shim panics.
+    pub trace_file: Option<PathBuf>,
+    /// Load history in the background, after the first frame.
+    pub defer_history: bool,
+    /// Words of a command the buffer picker groups by; 0 for all of them.
+    pub group_words: usize,
+    /// Minutes and commands between saves of the buffers, 0 for never,
+    /// and lines kept of each; see `autosave`.
+    pub autosave_minutes: u64,
+    pub autosave_commands: usize,
+    pub autosave_lines: usize,
+    /// Preview pipelines built on the viewed buffer as they're typed; see
+    /// `preview`.
+    pub pipe_preview: bool,
+    /// Programs given the terminal rather than run into a buffer; see
+    /// `passthrough`.
+    pub fullscreen: Vec<String>,
+    /// What opens links, the URL given after its words; see `link`.
+    pub opener: Option<String>,
+    /// Ask before running a command matching `dangerous`.
+    pub confirm_dangerous: bool,
+    /// Commands to confirm first; see `guard`.
+    pub dangerous: Vec<Regex>,
+    dangerous_defaults: bool,
+    dangerous_extra: Vec<Regex>,
+    /// Run contexts by name, each a template with `{cmd}` in it; see
+    /// `context`.
+    pub contexts: Vec<(String, String)>,
+    /// Commands whose output is shown by the prompt; see `segment`.
+    pub segments: Vec<Segment>,
+    /// Run after every command, and after one that succeeded or failed;
+    /// see `hook`.
+    pub on_command_end: Option<String>,
+    pub on_command_success: Option<String>,
+    pub on_command_failure: Option<String>,
+    /// How long a command must run for the hooks to run after it.
+    pub hook_min_duration: Duration,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            memory_cap: 64 * 1024 * 1024,
+            var_cap: 1024 * 1024,
+            unset_var_error: false,
+            penalize_failures: false,
+            queue_stop_on_failure: true,
+            retries: 5,
+            lock_view: false,
+            tab_stop: 8,
+            wrap: false,
+            long_line: 4096,
+            repipe: false,
+            smooth_scroll: false,
+            checkpoint_interval: 10,
+            checkpoint_limit: 360,
+            abbr_anywhere: false,
+            warn_unknown_commands: true,
+            warn_unbalanced: true,
+            warn_missing_paths: true,
+            detect_tables: true,
+            show_exit_code: true,
+            pipefail: false,
+            inline_images: true,
+            stash_keep_hash: true,
+            run_from_output: false,
+            clock: Some("%H:%M".into()),
+            time_format: "%H:%M:%S".into(),
+            utc: false,
+            theme: Theme::default(),
+            clipboard: false,
+            mouse: false,
+            copy_on_select: false,
+            stale_keys: Keep::Last,
+            session_registers: false,
+            history: history::Source::Shim,
+            completer: Some("carapace".into()),
+            completer_timeout: Duration::from_millis(500),
+            redact: redact::defaults(),
+            redact_defaults: true,
+            redact_extra: Vec::new(),
+            max_buffers: 50,
+            max_lines: 0,
+            closed_buffers: 10,
+            closed_size: 16 * 1024 * 1024,
+            keep_failed_buffers: false,
+            encoding: None,
+            debug: false,
+            trace_keys: false,
+            trace_file: None,
+            group_words: 0,
+            defer_history: false,
+            autosave_minutes: 5,
+            autosave_commands: 20,
+            autosave_lines: 1000,
+            pipe_preview: false,
+            fullscreen: passthrough::FULLSCREEN.map(String::from).to_vec(),
+            opener: None,
+            confirm_dangerous: true,
+            dangerous: guard::defaults(),
+            dangerous_defaults: true,
+            dangerous_extra: Vec::new(),
+            contexts: Vec::new(),
+            segments: segment::defaults(),
+            on_command_end: None,
+            on_command_success: None,
+            on_command_failure: None,
+            hook_min_duration: Duration::ZERO,
+        }
+    }
+}
+
+impl Config {
+    pub fn load() -> Self {
+        let Some(contents) = path().and_then(|p| fs::read_to_string(p).ok()) else {
+            return Config::default();
+        };
+        // a bad line shouldn't stop shim from starting
+        Config::parse(&contents).0
+    }
+
+    /// The settings in `contents`, with what was wrong with each line that
+    /// couldn't be used, by line number.
+    pub fn parse(contents: &str) -> (Self, Vec<(usize, String)>) {
+        let mut config = Config::default();
+        let mut problems = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let result = match line.split_once('=') {
+                Some((key, value)) => config.set(key.trim(), value.trim()),
+                None if line.trim().is_empty() => Ok(()),
+                None => Err(format!("not a `key = value` line: {}", line.trim())),
+            };
+            if let Err(e) = result {
+                problems.push((i + 1, e));
+            }
+        }
+        (config, problems)
+    }
+
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        match key {
+            "memory_cap" => self.memory_cap = parse_size(value)?,
+            "var_cap" => self.var_cap = parse_size(value)?,
+            "penalize_failures" => self.penalize_failures = parse_bool(value)?,
+            "lock_view" => self.lock_view = parse_bool(value)?,
+            "queue_stop_on_failure" => self.queue_stop_on_failure = parse_bool(value)?,
+            "retries" => {
+                self.retries = match value.parse() {
+                    Ok(n @ 2..) => n,
+                    _ => return Err(format!("retries must be a number above 1: {value}")),
+                }
+            }
+            "tab_stop" => {
+                self.tab_stop = match value.parse() {
+                    Ok(n @ 1..) => n,
+                    _ => return Err(format!("tab_stop must be a positive number: {value}")),
+                }
+            }
+            "wrap" => self.wrap = parse_bool(value)?,
+            "long_line" => {
+                self.long_line = match value.parse() {
+                    Ok(n @ 1..) => n,
+                    _ => return Err(format!("long_line must be a positive number: {value}")),
+                }
+            }
+            "repipe" => self.repipe = parse_bool(value)?,
+            "smooth_scroll" => self.smooth_scroll = parse_bool(value)?,
+            "warn_unknown_commands" => self.warn_unknown_commands = parse_bool(value)?,
+            "warn_unbalanced" => self.warn_unbalanced = parse_bool(value)?,
+            "warn_missing_paths" => self.warn_missing_paths = parse_bool(value)?,
+            "show_exit_code" => self.show_exit_code = parse_bool(value)?,
+            "pipefail" => self.pipefail = parse_bool(value)?,
+            "inline_images" => self.inline_images = parse_bool(value)?,
+            "stash_keep_hash" => self.stash_keep_hash = parse_bool(value)?,
+            "run_from_output" => self.run_from_output = parse_bool(value)?,
+            "clock" => {
+                self.clock = match value {
+                    "" | "off" => None,
+                    _ => Some(value.into()),
+                }
+            }
+            "time_format" => self.time_format = value.into(),
+            "utc" => self.utc = parse_bool(value)?,
+            "detect_tables" => self.detect_tables = parse_bool(value)?,
+            "abbr_anywhere" => self.abbr_anywhere = parse_bool(value)?,
+            "clipboard" => self.clipboard = parse_bool(value)?,
+            "mouse" => self.mouse = parse_bool(value)?,
+            "copy_on_select" => self.copy_on_select = parse_bool(value)?,
+            "stale_keys" => {
+                self.stale_keys = match value {
+                    "all" => Keep::All,
+                    "last" => Keep::Last,
+                    "none" => Keep::None,
+                    _ => return Err(format!("stale_keys must be all, last or none: {value}")),
+                }
+            }
+            "session_registers" => self.session_registers = parse_bool(value)?,
+            "history" => {
+                self.history = match value {
+                    "shim" => history::Source::Shim,
+                    "atuin" => history::Source::Atuin,
+                    "both" => history::Source::Both,
+                    _ => return Err(format!("history must be shim, atuin or both: {value}")),
+                }
+            }
+            "completer" => {
+                self.completer = match value {
+                    "" | "none" => None,
+                    _ => Some(value.into()),
+                }
+            }
+            "checkpoint_interval" => {
+                self.checkpoint_interval = value
+                    .parse()
+                    .map_err(|_| format!("checkpoint_interval must be seconds: {value}"))?
+            }
+            "checkpoint_limit" => {
+                self.checkpoint_limit = value
+                    .parse()
+                    .map_err(|_| format!("checkpoint_limit must be a number: {value}"))?
+            }
+            "completer_timeout" => {
+                let ms = value
+                    .parse()
+                    .map_err(|_| format!("completer_timeout must be milliseconds: {value}"))?;
+                self.completer_timeout = Duration::from_millis(ms);
+            }
+            "redact" => {
+                let pattern = Regex::new(value).map_err(|e| format!("redact: {e}"))?;
+                self.redact_extra.push(pattern);
+                self.update_redact();
+            }
+            "redact_defaults" => {
+                self.redact_defaults = parse_bool(value)?;
+                self.update_redact();
+            }
+            "confirm_dangerous" => self.confirm_dangerous = parse_bool(value)?,
+            "dangerous" => {
+                let pattern = Regex::new(value).map_err(|e| format!("dangerous: {e}"))?;
+                self.dangerous_extra.push(pattern);
+                self.update_dangerous();
+            }
+            "dangerous_defaults" => {
+                self.dangerous_defaults = parse_bool(value)?;
+                self.update_dangerous();
+            }
+            "max_buffers" => {
+                self.max_buffers = value
+                    .parse()
+                    .map_err(|_| format!("max_buffers must be a number: {value}"))?
+            }
+            "max_lines" => {
+                self.max_lines = value
+                    .parse()
+                    .map_err(|_| format!("max_lines must be a number: {value}"))?
+            }
+            "closed_buffers" => {
+                self.closed_buffers = value
+                    .parse()
+                    .map_err(|_| format!("closed_buffers must be a number: {value}"))?
+            }
+            "closed_size" => self.closed_size = parse_size(value)?,
+            "autosave_minutes" => {
+                self.autosave_minutes = value
+                    .parse()
+                    .map_err(|_| format!("autosave_minutes must be a number: {value}"))?
+            }
+            "autosave_commands" => {
+                self.autosave_commands = value
+                    .parse()
+                    .map_err(|_| format!("autosave_commands must be a number: {value}"))?
+            }
+            "autosave_lines" => {
+                self.autosave_lines = value
+                    .parse()
+                    .map_err(|_| format!("autosave_lines must be a number: {value}"))?
+            }
+            "close_buffers" => {
+                self.keep_failed_buffers = match value {
+                    "oldest" => false,
+                    "successful" => true,
+                    _ => {
+                        return Err(format!(
+                            "close_buffers must be oldest or successful: {value}"
+                        ));
+                    }
+                }
+            }
+            "debug" => self.debug = parse_bool(value)?,
+            "defer_history" => self.defer_history = parse_bool(value)?,
+            "pipe_preview" => self.pipe_preview = parse_bool(value)?,
+            "fullscreen" => self.fullscreen = value.split_whitespace().map(String::from).collect(),
+            "trace_keys" => self.trace_keys = parse_bool(value)?,
+            "trace_file" => {
+                self.trace_file = match value {
+                    "" | "none" => None,
+                    _ => Some(PathBuf::from(value)),
+                }
+            }
+            "encoding" => self.encoding = encoding::parse(value)?,
+            "group_words" => {
+                self.group_words = value
+                    .parse()
+                    .map_err(|_| format!("group_words must be a number: {value}"))?
+            }
+            "opener" => self.opener = parse_hook(value),
+            "on_command_end" => self.on_command_end = parse_hook(value),
+            "on_command_success" => self.on_command_success = parse_hook(value),
+            "on_command_failure" => self.on_command_failure = parse_hook(value),
+            "hook_min_duration" => {
+                let seconds = parse_seconds(value)
+                    .ok_or_else(|| format!("hook_min_duration must be like 10s or 2m: {value}"))?;
+                self.hook_min_duration = Duration::from_secs(seconds);
+            }
+            "unset_vars" => {
+                self.unset_var_error = match value {
+                    "empty" => false,
+                    "error" => true,
+                    _ => return Err(format!("unset_vars must be empty or error: {value}")),
+                }
+            }
+            _ if key.starts_with("theme_") => self.theme.set(&key["theme_".len()..], value)?,
+            _ if key.starts_with("context_") => {
+                let name = &key["context_".len()..];
+                if name.is_empty() || !value.contains("{cmd}") {
+                    return Err(format!(
+                        "{key} must be a command with {{cmd}} in it: {value}"
+                    ));
+                }
+                self.contexts.retain(|(n, _)| n != name);
+                self.contexts.push((name.to_string(), value.to_string()));
+            }
+            _ if key.starts_with("segment_") => {
+                let name = &key["segment_".len()..];
+                let segment = segment::parse(name, value)?;
+                self.segments.retain(|s| s.name != name);
+                self.segments.extend(segment);
+            }
+            _ => return Err(format!("unknown setting: {key}")),
+        }
+        Ok(())
+    }
+}
+
+/// Where shim keeps its config and other files the user may edit.
+pub fn dir() -> Option<PathBuf> {
+    // tests mustn't read or write the user's files
+    if cfg!(test) {
+        return None;
+    }
+    let dir = env::var_os("XDG_CONFIG_HOME")
+        .or_else(|| env::var_os("APPDATA").filter(|_| cfg!(windows)))
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".config")))?;
+    Some(dir.join("shim"))
+}
+
+impl Config {
+    fn update_redact(&mut self) {
+        self.redact = match self.redact_defaults {
+            true => redact::defaults(),
+            false => Vec::new(),
+        };
+        self.redact.extend(self.redact_extra.iter().cloned());
+    }
+
+    fn update_dangerous(&mut self) {
+        self.dangerous = match self.dangerous_defaults {
+            true => guard::defaults(),
+            false => Vec::new(),
+        };
+        self.dangerous.extend(self.dangerous_extra.iter().cloned());
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    Some(dir()?.join("config"))
+}
+
+/// A hook's command, without double quotes around the whole of it, or
+/// `None` for none.
+fn parse_hook(value: &str) -> Option<String> {
+    let quoted = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+    let value = quoted.filter(|v| !v.contains('"')).unwrap_or(value);
+    match value {
+        "" | "none" => None,
+        _ => Some(value.into()),
+    }
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "on" | "yes" => Ok(true),
+        "false" | "off" | "no" => Ok(false),
+        _ => Err(format!("expected true or false: {value}")),
+    }
+}
+
+/// Parse a number of seconds, or of minutes with an `m` after it, as in
+/// `30s`, `2m` or `5`.
+pub fn parse_seconds(value: &str) -> Option<u64> {
+    match value.char_indices().last() {
+        Some((i, 's')) => value[..i].parse().ok(),
+        Some((i, 'm')) => value[..i].parse().ok().map(|m: u64| m * 60),
+        _ => value.parse().ok(),
+    }
+}
+
+/// Parse a byte count with an optional `K`, `M` or `G` suffix.
+fn parse_size(value: &str) -> Result<usize, String> {
+    let (digits, unit) = match value.char_indices().last() {
+        Some((i, 'K' | 'k')) => (&value[..i], 1024),
+        Some((i, 'M' | 'm')) => (&value[..i], 1024 * 1024),
+        Some((i, 'G' | 'g')) => (&value[..i], 1024 * 1024 * 1024),
+        _ => (value, 1),
+    };
+    digits
+        .trim()
+        .parse::<usize>()
+        .map(|n| n * unit)
+        .map_err(|_| format!("invalid size: {value}"))
+}
@@ -0,0 +1,121 @@
+//! Run contexts: commands run inside something else, like a dev
+//! container, by way of a template from the config such as
+//! `context_app = docker compose exec app {cmd}`. `:ctx app` makes one
+//! active and `:ctx none` goes back to running commands directly. A
+//! command starting with `\` runs directly whatever the context. History
+//! keeps the command as typed, with the context it ran in.
+
+use std::borrow::Cow;
+
+use crate::{Model, shell};
+
+/// What a command is run as in context `template`: `{cmd}` replaced by
+/// the command, or by a `sh -c` of it if it takes more than one line.
+fn wrap(template: &str, command: &str) -> String {
+    let command = match command.contains('\n') {
+        true => Cow::Owned(format!("sh -c {}", shell::quote(command))),
+        false => Cow::Borrowed(command),
+    };
+    template.replace("{cmd}", &command)
+}
+
+impl Model {
+    /// The template of the active context, if there is one.
+    fn context_template(&self) -> Option<&str> {
+        let active = self.context.as_deref()?;
+        let (_, template) = self.config.contexts.iter().find(|(n, _)| n == active)?;
+        Some(template)
+    }
+
+    /// The context `command` runs in, once submitted.
+    pub fn context_for(&self, command: &str) -> Option<String> {
+        match command.trim_start().starts_with('\\') {
+            true => None,
+            false => self.context.clone(),
+        }
+    }
+
+    /// `line` as it's to be run: in the active context, or with the `\`
+    /// that keeps it out of one taken off.
+    pub fn in_context<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let trimmed = line.trim_start();
+        if let Some(direct) = trimmed.strip_prefix('\\') {
+            return Cow::Borrowed(direct);
+        }
+        match self.context_template() {
+            Some(template) => Cow::Owned(wrap(template, trimmed)),
+            None => Cow::Borrowed(line),
+        }
+    }
+
+    /// `:ctx [NAME|none]`: switch context, or say which is active.
+    pub fn switch_context(&mut self, name: Option<&str>) -> String {
+        let names = || {
+            let names: Vec<_> = self
+                .config
+                .contexts
+                .iter()
+                .map(|(n, _)| n.as_str())
+                .collect();
+            match names.is_empty() {
+                true => "none are set; add context_NAME = ... {cmd} to the config".to_string(),
+                false => format!("contexts: {}", names.join(", ")),
+            }
+        };
+        match name {
+            None => match self.context_template() {
+                Some(template) => format!("running in {template}; {}", names()),
+                None => format!("running commands directly; {}", names()),
+            },
+            Some("none") => {
+                self.context = None;
+                "running commands directly".into()
+            }
+            Some(name) => {
+                if !self.config.contexts.iter().any(|(n, _)| n == name) {
+                    return format!("no context {name}; {}", names());
+                }
+                self.context = Some(name.to_string());
+                format!(
+                    "running commands in {}",
+                    self.context_template().unwrap_or("")
+                )
+            }
+        }
+    }
+
+    /// For the status line while a context is active.
+    pub fn context_note(&self) -> Option<String> {
+        self.context.as_ref().map(|name| format!("ctx {name}"))
+    }
+
+    /// When a recalled command ran in a context other than the active
+    /// one, which.
+    pub fn recalled_context_note(&self) -> Option<String> {
+        let entry = self.previous_commands.get(self.viewing_command?)?;
+        let then = entry
+            .context
+            .as_ref()
+            .filter(|c| Some(*c) != self.context.as_ref())?;
+        match &self.context {
+            Some(now) => Some(format!("ran in {then}; now in {now}")),
+            None => Some(format!("ran in {then}; now running directly")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn commands_go_into_the_template() {
+        let docker = "docker compose exec app {cmd}";
+        assert_eq!(wrap(docker, "ls -la"), "docker compose exec app ls -la");
+        assert_eq!(
+            wrap(docker, "for f in *\ndo echo $f\ndone"),
+            "docker compose exec app sh -c 'for f in *\ndo echo $f\ndone'"
+        );
+        assert_eq!(wrap("ssh box -- {cmd} {cmd}", "id"), "ssh box -- id id");
+    }
+}
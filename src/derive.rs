@@ -0,0 +1,256 @@
+//! `:sort` and `:uniq`: a new buffer made from the viewed one's lines, as
+//! it's shown, without running it through a pipeline. The work is done on
+//! a thread of its own, as a buffer can be hundreds of megabytes; the new
+//! buffer says what it's doing in its title until it's done.
+
+use std::{cmp::Ordering, collections::HashMap, thread};
+
+use crate::{Model, Output, event::AppEvent};
+
+/// What to make of the lines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operation {
+    Sort {
+        /// By the number each line starts with, like `sort -n`.
+        numeric: bool,
+        reverse: bool,
+    },
+    Uniq {
+        /// Put how many times each line was there in front of it.
+        count: bool,
+        /// Sort the lines first; otherwise each line stays where it was
+        /// first seen.
+        sorted: bool,
+    },
+}
+
+impl Operation {
+    /// `:sort[!] [-n] [-r]` or `:uniq [-c] [-s]`.
+    pub fn parse(name: &str, args: &[&str]) -> Result<Operation, String> {
+        let usage = match name {
+            "uniq" => "usage: :uniq [-c] [-s]",
+            _ => "usage: :sort[!] [-n] [-r]",
+        };
+        let mut flags = Vec::new();
+        for arg in args {
+            match arg.strip_prefix('-') {
+                Some(letters) if !letters.is_empty() => flags.extend(letters.chars()),
+                _ => return Err(usage.into()),
+            }
+        }
+        let allowed = match name {
+            "uniq" => "cs",
+            _ => "nr",
+        };
+        if flags.iter().any(|f| !allowed.contains(*f)) {
+            return Err(usage.into());
+        }
+        Ok(match name {
+            "uniq" => Operation::Uniq {
+                count: flags.contains(&'c'),
+                sorted: flags.contains(&'s'),
+            },
+            _ => Operation::Sort {
+                numeric: flags.contains(&'n'),
+                reverse: name == "sort!" || flags.contains(&'r'),
+            },
+        })
+    }
+
+    /// The operation as a command, for the new buffer's title.
+    fn describe(self) -> String {
+        let flags: String = match self {
+            Operation::Sort { numeric, reverse } => [(numeric, 'n'), (reverse, 'r')]
+                .into_iter()
+                .filter_map(|(on, flag)| on.then_some(flag))
+                .collect(),
+            Operation::Uniq { count, sorted } => [(count, 'c'), (sorted, 's')]
+                .into_iter()
+                .filter_map(|(on, flag)| on.then_some(flag))
+                .collect(),
+        };
+        let name = match self {
+            Operation::Sort { .. } => "sort",
+            Operation::Uniq { .. } => "uniq",
+        };
+        match flags.is_empty() {
+            true => name.into(),
+            false => format!("{name} -{flags}"),
+        }
+    }
+
+    fn verb(self) -> &'static str {
+        match self {
+            Operation::Sort { .. } => "sorting",
+            Operation::Uniq { .. } => "merging",
+        }
+    }
+}
+
+/// The number `line` starts with, after any blanks; lines without one
+/// count as 0, as with `sort -n`.
+fn leading_number(line: &str) -> f64 {
+    let line = line.trim_start();
+    let end = line
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || c == '.' || (i == 0 && c == '-')))
+        .map_or(line.len(), |(i, _)| i);
+    line[..end].parse().unwrap_or(0.0)
+}
+
+fn sort_lines(lines: &mut [&str], numeric: bool) {
+    match numeric {
+        true => lines.sort_by(|a, b| {
+            leading_number(a)
+                .partial_cmp(&leading_number(b))
+                .unwrap_or(Ordering::Equal)
+                .then_with(|| a.cmp(b))
+        }),
+        false => lines.sort(),
+    }
+}
+
+/// `text` with `operation` done to its lines.
+pub fn apply(operation: Operation, text: &str) -> String {
+    let mut lines: Vec<&str> = text.lines().collect();
+    let lines: Vec<String> = match operation {
+        Operation::Sort { numeric, reverse } => {
+            sort_lines(&mut lines, numeric);
+            if reverse {
+                lines.reverse();
+            }
+            lines.into_iter().map(String::from).collect()
+        }
+        Operation::Uniq { count, sorted } => {
+            if sorted {
+                sort_lines(&mut lines, false);
+            }
+            let mut counts: HashMap<&str, usize> = HashMap::new();
+            let mut order = Vec::new();
+            for line in lines {
+                let seen = counts.entry(line).or_default();
+                if *seen == 0 {
+                    order.push(line);
+                }
+                *seen += 1;
+            }
+            order
+                .into_iter()
+                .map(|line| match count {
+                    true => format!("{:>7} {line}", counts[line]),
+                    false => line.to_string(),
+                })
+                .collect()
+        }
+    };
+    let mut out = lines.join("\n");
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+impl Output {
+    /// What's being worked out for this buffer, for its title.
+    pub fn deriving_note(&self) -> Option<String> {
+        self.deriving.clone()
+    }
+}
+
+impl Model {
+    /// `:sort` and `:uniq`: open a buffer with the viewed one's lines
+    /// sorted or merged. Returns what to show on the status line.
+    pub fn derive(&mut self, name: &str, args: &[&str]) -> String {
+        let operation = match Operation::parse(name, args) {
+            Ok(operation) => operation,
+            Err(usage) => return usage,
+        };
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return "no buffer".into();
+        };
+        let text = output.shown_stdout().to_string();
+        let command = format!("{} ⟨ {}", operation.describe(), output.command);
+        let lines = output.line_count();
+        self.push_output(command, String::new());
+        let Some(derived) = self.outputs.last_mut() else {
+            return String::new();
+        };
+        let id = derived.id;
+        let Some(events) = self.events.clone() else {
+            self.finish_derive(id, apply(operation, &text));
+            return String::new();
+        };
+        derived.deriving = Some(format!("{} {lines} lines", operation.verb()));
+        thread::spawn(move || {
+            let text = apply(operation, &text);
+            let _ = events.send(AppEvent::Derived { output: id, text });
+        });
+        String::new()
+    }
+
+    /// Fill in derived buffer `id` once its lines are worked out.
+    pub fn finish_derive(&mut self, id: usize, text: String) {
+        let Some(index) = self.outputs.iter().position(|o| o.id == id) else {
+            return;
+        };
+        let output = &mut self.outputs[index];
+        output.deriving = None;
+        output.ingest(&text, &self.config.redact);
+        output.flush(&self.config.redact);
+        self.record_output(&text);
+        self.evict_to(self.config.memory_cap);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn op(name: &str, args: &[&str]) -> Operation {
+        Operation::parse(name, args).unwrap()
+    }
+
+    #[test]
+    fn sorting_lexically_and_numerically() {
+        let text = "10 b\n9 a\n  100 c\nx\n-1 d\n";
+        assert_eq!(
+            apply(op("sort", &[]), text),
+            "  100 c\n-1 d\n10 b\n9 a\nx\n"
+        );
+        assert_eq!(
+            apply(op("sort", &["-n"]), text),
+            "-1 d\nx\n9 a\n10 b\n  100 c\n"
+        );
+        assert_eq!(
+            apply(op("sort!", &["-n"]), text),
+            "  100 c\n10 b\n9 a\nx\n-1 d\n"
+        );
+        assert_eq!(op("sort", &["-nr"]), op("sort!", &["-n"]));
+        assert_eq!(apply(op("sort", &[]), ""), "");
+    }
+
+    #[test]
+    fn uniq_keeps_first_places_or_sorts() {
+        let text = "b\na\nb\nc\na\nb";
+        assert_eq!(apply(op("uniq", &[]), text), "b\na\nc\n");
+        assert_eq!(apply(op("uniq", &["-s"]), text), "a\nb\nc\n");
+        assert_eq!(
+            apply(op("uniq", &["-c", "-s"]), text),
+            "      2 a\n      3 b\n      1 c\n"
+        );
+        assert_eq!(op("uniq", &["-sc"]).describe(), "uniq -cs");
+        assert!(Operation::parse("uniq", &["-n"]).is_err());
+        assert!(Operation::parse("sort", &["x"]).is_err());
+    }
+
+    #[test]
+    fn the_derived_buffer_names_its_source() {
+        let mut model = Model::default();
+        model.push_output("du -s *".into(), "12 src\n3 docs\n40 target\n".into());
+        assert_eq!(model.derive("sort!", &["-n"]), "");
+        let derived = &model.outputs[1];
+        assert_eq!(derived.command, "sort -nr ⟨ du -s *");
+        assert_eq!(derived.stdout, "40 target\n12 src\n3 docs\n");
+        assert_eq!(model.viewing_output, 1);
+    }
+}
@@ -0,0 +1,124 @@
+use std::{
+    env,
+    fmt::Write,
+    path::{Path, PathBuf},
+};
+
+use crate::{Model, shell};
+
+impl Model {
+    /// Change the working directory, remembering the old and new ones for
+    /// `cd -` and the directory picker.
+    pub fn change_dir(&mut self, dir: &Path) -> Result<(), String> {
+        let previous = env::current_dir().ok();
+        env::set_current_dir(dir).map_err(|e| format!("cd: {}: {e}", dir.display()))?;
+        // most recent last, once each
+        for dir in [previous, env::current_dir().ok()].into_iter().flatten() {
+            self.recent_dirs.retain(|d| *d != dir);
+            self.recent_dirs.push(dir);
+        }
+        self.check_env_file();
+        self.refresh_segments();
+        Ok(())
+    }
+
+    /// Directories cd'd to this session, most recent first, excluding the
+    /// current one.
+    pub fn recent_dirs(&self) -> Vec<PathBuf> {
+        let cwd = env::current_dir().ok();
+        self.recent_dirs
+            .iter()
+            .rev()
+            .filter(|d| Some(*d) != cwd.as_ref())
+            .cloned()
+            .collect()
+    }
+}
+
+pub fn cd(model: &mut Model, args: &[String]) -> String {
+    let target = match args.first().map(String::as_str) {
+        None => match shell::home(|name| env::var(name).ok()) {
+            Some(home) => PathBuf::from(home),
+            None => return "cd: HOME not set".into(),
+        },
+        Some("-") => match model.recent_dirs().first() {
+            Some(dir) => dir.clone(),
+            None => return "cd: no previous directory".into(),
+        },
+        Some(arg) if arg.starts_with('-') && arg.len() > 1 => {
+            match arg[1..]
+                .parse::<usize>()
+                .ok()
+                .and_then(|n| stack_entry(model, n))
+            {
+                Some(dir) => dir,
+                None => return format!("cd: no such entry in directory stack: {arg}"),
+            }
+        }
+        Some(arg) => PathBuf::from(arg),
+    };
+    match model.change_dir(&target) {
+        Ok(()) => String::new(),
+        Err(e) => e,
+    }
+}
+
+/// Entry `n` as numbered by `dirs`, where 0 is the working directory.
+fn stack_entry(model: &Model, n: usize) -> Option<PathBuf> {
+    match n {
+        0 => env::current_dir().ok(),
+        n => model.dir_stack.iter().rev().nth(n - 1).cloned(),
+    }
+}
+
+pub fn pushd(model: &mut Model, args: &[String]) -> String {
+    let Ok(cwd) = env::current_dir() else {
+        return "pushd: working directory is gone".into();
+    };
+    let target = match args.first() {
+        Some(dir) => PathBuf::from(dir),
+        // like bash, no argument swaps the top two
+        None => match model.dir_stack.pop() {
+            Some(dir) => dir,
+            None => return "pushd: no other directory".into(),
+        },
+    };
+    if let Err(e) = model.change_dir(&target) {
+        if args.is_empty() {
+            model.dir_stack.push(target);
+        }
+        return e;
+    }
+    model.dir_stack.push(cwd);
+    dirs(model)
+}
+
+pub fn popd(model: &mut Model) -> String {
+    let mut notes = String::new();
+    while let Some(dir) = model.dir_stack.pop() {
+        if !dir.is_dir() {
+            let _ = writeln!(notes, "popd: skipping missing {}", dir.display());
+            continue;
+        }
+        if let Err(e) = model.change_dir(&dir) {
+            let _ = writeln!(notes, "{e}");
+            continue;
+        }
+        return notes + &dirs(model);
+    }
+    notes + "popd: directory stack empty"
+}
+
+pub fn dirs(model: &Model) -> String {
+    let cwd = env::current_dir().unwrap_or_default();
+    let mut listing = String::new();
+    for (i, dir) in Some(&cwd)
+        .into_iter()
+        .chain(model.dir_stack.iter().rev())
+        .enumerate()
+    {
+        let missing = if dir.is_dir() { "" } else { "  (missing)" };
+        let _ = writeln!(listing, "{i:>2}  {}{missing}", dir.display());
+    }
+    listing
+}
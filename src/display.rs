@@ -0,0 +1,453 @@
+//! How output is drawn, and the mapping between positions in a line and
+//! where they land on screen. The cursor moves over grapheme clusters, which
+//! can be zero (a combining accent on its own), one or two columns wide;
+//! tabs reach to the next tab stop and control characters are shown in
+//! caret notation (`^G`). A line longer than `long_line` positions is
+//! drawn and moved over only that far, with how much more there is after
+//! it, so one huge line doesn't have to be walked at every step.
+
+use std::{
+    borrow::Cow,
+    cell::{Cell, RefCell},
+};
+
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+use crate::{Output, config::Config, memory::format_size};
+
+#[derive(Debug, Clone, Copy)]
+pub struct Settings {
+    pub tab_stop: usize,
+    /// Draw tabs as `^I` and mark line ends with `$`, like `cat -A`.
+    pub invisibles: bool,
+    /// Positions drawn of a line before it's cut off.
+    pub long_line: usize,
+}
+
+impl Output {
+    pub fn display_settings(&self, config: &Config) -> Settings {
+        Settings {
+            tab_stop: config.tab_stop,
+            invisibles: self.show_invisibles,
+            long_line: config.long_line,
+        }
+    }
+
+    /// The shown text from the start of line `line`, or nothing past the
+    /// end.
+    pub fn text_from(&self, line: usize) -> &str {
+        let text = self.shown_text();
+        self.line_index
+            .start(text, line)
+            .map_or("", |start| &text[start..])
+    }
+
+    /// Line `line` of the shown text.
+    pub fn line(&self, line: usize) -> Option<&str> {
+        self.text_from(line).lines().next()
+    }
+
+    /// What to show in place of the output of a job that ended without
+    /// writing anything, so it doesn't look like it never ran.
+    pub fn empty_note(&self) -> Option<String> {
+        if self.running
+            || self.started.is_none()
+            || self.evicted.is_some()
+            || !self.raw_text().is_empty()
+        {
+            return None;
+        }
+        let exit = match self.status {
+            Some(status) => format!("exit {status}"),
+            None => "no exit code".into(),
+        };
+        let took = self
+            .duration
+            .map(|d| format!(" in {:.2}s", d.as_secs_f64()))
+            .unwrap_or_default();
+        match self.in_terminal {
+            true => Some(format!("(ran in the terminal — {exit}{took})")),
+            false => Some(format!("(no output — {exit}{took})")),
+        }
+    }
+
+    /// How many lines the shown text has.
+    pub fn line_count(&self) -> usize {
+        let text = self.shown_text();
+        let unended = !text.is_empty() && !text.ends_with('\n');
+        self.line_index.newlines(text) + unended as usize
+    }
+}
+
+/// Where the lines of a buffer's shown text start, so the view can go
+/// straight to the ones on screen however long the output is. Text that
+/// has grown since it was last looked at is indexed from where it left
+/// off; once it's been changed otherwise, or other text is shown, the
+/// index starts over.
+#[derive(Debug, Default)]
+pub struct LineIndex {
+    indexed: RefCell<Indexed>,
+    /// Bumped whenever the shown text changes other than by growing.
+    generation: Cell<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Indexed {
+    /// The generation of the text indexed, and how much of it.
+    generation: u64,
+    len: usize,
+    /// Where each line after the first starts.
+    starts: Vec<usize>,
+}
+
+impl LineIndex {
+    fn update(&self, text: &str) -> std::cell::RefMut<'_, Indexed> {
+        let mut indexed = self.indexed.borrow_mut();
+        let generation = self.generation.get();
+        // shorter is a frozen view further back
+        if indexed.generation != generation || text.len() < indexed.len {
+            *indexed = Indexed {
+                generation,
+                ..Default::default()
+            };
+        }
+        let len = indexed.len;
+        let added = text[len..].match_indices('\n').map(|(i, _)| len + i + 1);
+        indexed.starts.extend(added);
+        indexed.len = text.len();
+        indexed
+    }
+
+    /// Where line `line` of `text` starts, if it has that many.
+    fn start(&self, text: &str, line: usize) -> Option<usize> {
+        match line.checked_sub(1) {
+            None => Some(0),
+            Some(i) => self.update(text).starts.get(i).copied(),
+        }
+    }
+
+    fn newlines(&self, text: &str) -> usize {
+        self.update(text).starts.len()
+    }
+
+    /// The shown text has been changed other than by adding to it, or is
+    /// other text: index it again.
+    pub fn changed(&self) {
+        self.generation.set(self.generation.get() + 1);
+        self.indexed.borrow_mut().starts = Vec::new();
+    }
+
+    pub fn size(&self) -> usize {
+        self.indexed.borrow().starts.len() * size_of::<usize>()
+    }
+}
+
+/// What to draw for `grapheme` when it starts at column `col`.
+fn shown(grapheme: &str, col: usize, settings: Settings) -> Cow<'_, str> {
+    let mut chars = grapheme.chars();
+    match (chars.next(), chars.next()) {
+        (Some('\t'), None) if !settings.invisibles => {
+            Cow::Owned(" ".repeat(settings.tab_stop - col % settings.tab_stop))
+        }
+        (Some(c), None) if c.is_ascii_control() => {
+            Cow::Owned(format!("^{}", char::from(c as u8 ^ 0x40)))
+        }
+        _ => Cow::Borrowed(grapheme),
+    }
+}
+
+/// The first `limit` positions of `line`.
+pub fn cap(line: &str, limit: usize) -> &str {
+    // no position is shorter than a byte
+    if line.len() <= limit {
+        return line;
+    }
+    line.grapheme_indices(true)
+        .nth(limit)
+        .map_or(line, |(i, _)| &line[..i])
+}
+
+/// What's drawn after `shown`, the start of `line`, when that's all of it
+/// that's shown.
+fn cut_note(line: &str, shown: &str) -> Option<String> {
+    let rest = line.len() - shown.len();
+    (rest > 0).then(|| format!(" … +{}", format_size(rest)))
+}
+
+/// `line` as it appears on screen.
+pub fn render_line(line: &str, settings: Settings) -> String {
+    let shown_part = cap(line, settings.long_line);
+    let mut out = String::with_capacity(shown_part.len());
+    for grapheme in shown_part.graphemes(true) {
+        out.push_str(&shown(grapheme, out.width(), settings));
+    }
+    out.extend(cut_note(line, shown_part));
+    if settings.invisibles {
+        out.push('$');
+    }
+    out
+}
+
+/// The first `height` lines of `text` as they appear on screen.
+pub fn render(text: &str, height: usize, settings: Settings) -> String {
+    let lines: Vec<_> = text
+        .lines()
+        .take(height)
+        .map(|l| render_line(l, settings))
+        .collect();
+    lines.join("\n")
+}
+
+/// Number of cursor positions in `line`.
+pub fn len(line: &str) -> usize {
+    line.graphemes(true).count()
+}
+
+/// The screen column at which cursor position `index` of `line` starts.
+pub fn column(line: &str, index: usize, settings: Settings) -> usize {
+    line.graphemes(true)
+        .take(index)
+        .fold(0, |col, g| col + shown(g, col, settings).width())
+}
+
+/// A screen row of a wrapped line.
+#[derive(Debug, PartialEq)]
+pub struct Row {
+    /// The cursor position the row starts at.
+    pub start: usize,
+    /// What it shows.
+    pub text: String,
+}
+
+/// `line` as rows of at most `width` columns. A grapheme that doesn't fit
+/// starts the next row, so tabs and wide characters aren't split. Both the
+/// view and cursor movement use this, so they agree on where rows break.
+pub fn rows(line: &str, width: usize, settings: Settings) -> Vec<Row> {
+    let full = line;
+    let line = cap(full, settings.long_line);
+    let mut rows = vec![Row {
+        start: 0,
+        text: String::new(),
+    }];
+    let mut col = 0;
+    let mut row_width = 0;
+    for (i, grapheme) in line.graphemes(true).enumerate() {
+        // tab stops count from the start of the line, not of the row
+        let shown = shown(grapheme, col, settings);
+        let width_here = shown.width();
+        if row_width > 0 && row_width + width_here > width {
+            rows.push(Row {
+                start: i,
+                text: String::new(),
+            });
+            row_width = 0;
+        }
+        if let Some(row) = rows.last_mut() {
+            row.text.push_str(&shown);
+        }
+        row_width += width_here;
+        col += width_here;
+    }
+    if let Some(note) = cut_note(full, line)
+        && let Some(row) = rows.last_mut()
+    {
+        row.text.push_str(&note);
+    }
+    if settings.invisibles
+        && let Some(row) = rows.last_mut()
+    {
+        row.text.push('$');
+    }
+    rows
+}
+
+/// The screen row of `line` that starts at byte `start`, in `width`
+/// columns: where the next row starts, and what this one shows. Unlike
+/// `rows` it goes no further than the one row, for a line too long to
+/// break up all at once, and it doesn't cut the line off.
+pub fn row_at(line: &str, start: usize, width: usize, settings: Settings) -> (usize, String) {
+    let mut text = String::new();
+    let mut col = 0;
+    for (i, grapheme) in line[start..].grapheme_indices(true) {
+        let shown = shown(grapheme, col, settings);
+        let width_here = shown.width();
+        if col > 0 && col + width_here > width {
+            return (start + i, text);
+        }
+        text.push_str(&shown);
+        col += width_here;
+    }
+    (line.len(), text)
+}
+
+/// Which of `rows` cursor position `index` is on.
+pub fn row_of(rows: &[Row], index: usize) -> usize {
+    rows.iter().rposition(|r| r.start <= index).unwrap_or(0)
+}
+
+/// The cursor position on row `row` of `line` that is drawn `col` columns
+/// into the row, or the row's last one if it's shorter.
+pub fn index_at(line: &str, rows: &[Row], row: usize, col: usize, settings: Settings) -> usize {
+    let start = rows[row].start;
+    let end = rows
+        .get(row + 1)
+        .map_or_else(|| len(cap(line, settings.long_line)), |r| r.start);
+    let mut at = 0;
+    let mut row_col = None;
+    for (i, grapheme) in line.graphemes(true).enumerate().take(end) {
+        let width = shown(grapheme, at, settings).width();
+        if i >= start {
+            let row_col = *row_col.get_or_insert(at);
+            if at + width - row_col > col {
+                return i;
+            }
+        }
+        at += width;
+    }
+    end.saturating_sub(1).max(start)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tabs(tab_stop: usize) -> Settings {
+        Settings {
+            tab_stop,
+            invisibles: false,
+            long_line: usize::MAX,
+        }
+    }
+
+    #[test]
+    fn tabs_reach_the_next_stop() {
+        let line = "a\tbc\td";
+        assert_eq!(len(line), 6);
+        assert_eq!(column(line, 1, tabs(8)), 1);
+        assert_eq!(column(line, 2, tabs(8)), 8);
+        assert_eq!(column(line, 4, tabs(8)), 10);
+        assert_eq!(column(line, 5, tabs(8)), 16);
+        assert_eq!(column(line, 5, tabs(4)), 8);
+    }
+
+    #[test]
+    fn emoji_are_two_columns() {
+        let line = "🦀 ok";
+        assert_eq!(len(line), 4);
+        assert_eq!(column(line, 1, tabs(8)), 2);
+        assert_eq!(column(line, 2, tabs(8)), 3);
+        assert_eq!(column("日本語", 2, tabs(8)), 4);
+    }
+
+    #[test]
+    fn combining_accents_stay_with_their_letter() {
+        let line = "e\u{301}te\u{301}";
+        assert_eq!(len(line), 3);
+        assert_eq!(column(line, 1, tabs(8)), 1);
+        assert_eq!(column(line, 3, tabs(8)), 3);
+    }
+
+    #[test]
+    fn lines_are_found_as_the_text_grows() {
+        let mut output = Output {
+            stdout: (0..1000).map(|n| format!("{n}\n")).collect(),
+            ..Default::default()
+        };
+        assert_eq!(output.line(500), Some("500"));
+        assert_eq!(render(output.text_from(998), 5, tabs(8)), "998\n999");
+        assert_eq!(output.line(1000), None);
+        assert_eq!(output.line_count(), 1000);
+        output.stdout.push_str("1000\n10");
+        assert_eq!(output.line(1001), Some("10"));
+        assert_eq!(output.line_count(), 1002);
+        // cleared and filled again in place, as when run again
+        output.stdout.clear();
+        output.line_index.changed();
+        output.stdout.push_str("a\nb\n");
+        assert_eq!(output.line(1), Some("b"));
+        assert_eq!(output.line_count(), 2);
+        // changed in place, the length and all else the same
+        output.stdout.replace_range(.., "ab\n\n");
+        output.line_index.changed();
+        assert_eq!(output.line(1), Some(""));
+        assert_eq!(output.text_from(1), "\n");
+    }
+
+    #[test]
+    fn tab_after_wide_text() {
+        assert_eq!(column("日本\tx", 3, tabs(8)), 8);
+    }
+
+    #[test]
+    fn control_characters_are_escaped() {
+        assert_eq!(render_line("a\x07b\x08", tabs(8)), "a^Gb^H");
+        assert_eq!(column("\x07x", 1, tabs(8)), 2);
+        assert_eq!(render("a\tb\nc", 2, tabs(4)), "a   b\nc");
+    }
+
+    fn starts(line: &str, width: usize) -> Vec<usize> {
+        rows(line, width, tabs(8)).iter().map(|r| r.start).collect()
+    }
+
+    #[test]
+    fn rows_break_at_the_width() {
+        assert_eq!(starts("abcdefg", 3), [0, 3, 6]);
+        assert_eq!(starts("abc", 3), [0]);
+        assert_eq!(starts("", 3), [0]);
+        let texts: Vec<_> = rows("ab\tc", 3, tabs(4))
+            .into_iter()
+            .map(|r| r.text)
+            .collect();
+        assert_eq!(texts, ["ab", "  c"]);
+    }
+
+    #[test]
+    fn wide_characters_move_to_the_next_row() {
+        assert_eq!(starts("a日本", 4), [0, 2]);
+        assert_eq!(starts("日本語", 1), [0, 1, 2]);
+    }
+
+    #[test]
+    fn index_at_a_column_of_a_row() {
+        let line = "abcdefg";
+        let rows = rows(line, 3, tabs(8));
+        assert_eq!(row_of(&rows, 4), 1);
+        assert_eq!(index_at(line, &rows, 1, 1, tabs(8)), 4);
+        // past the end of a short last row
+        assert_eq!(index_at(line, &rows, 2, 2, tabs(8)), 6);
+        let line = "a日本";
+        let rows = super::rows(line, 3, tabs(8));
+        assert_eq!(index_at(line, &rows, 0, 2, tabs(8)), 1);
+    }
+
+    #[test]
+    fn invisibles_show_tabs_and_line_ends() {
+        let settings = Settings {
+            tab_stop: 8,
+            invisibles: true,
+            long_line: usize::MAX,
+        };
+        assert_eq!(render("a\tb\nc", 2, settings), "a^Ib$\nc$");
+        assert_eq!(column("\tx", 1, settings), 2);
+    }
+
+    #[test]
+    fn long_lines_are_cut_off_where_drawn() {
+        let settings = Settings {
+            long_line: 4,
+            ..tabs(8)
+        };
+        let line = format!("日本語e\u{301}{}", "x".repeat(2046));
+        assert_eq!(cap(&line, 4), "日本語e\u{301}");
+        assert_eq!(cap("abc", 4), "abc");
+        assert_eq!(render_line(&line, settings), "日本語e\u{301} … +2.0 KB");
+        let rows = rows(&line, 4, settings);
+        assert_eq!(
+            rows.iter().map(|r| r.text.as_str()).collect::<Vec<_>>(),
+            ["日本", "語e\u{301} … +2.0 KB"]
+        );
+        assert_eq!(index_at(&line, &rows, 1, 9, settings), 3);
+        assert_eq!(render_line("abcd", settings), "abcd");
+    }
+}
@@ -0,0 +1,286 @@
+//! Loading a directory's `.env` or `.envrc` on cd, once the user has
+//! allowed that directory. The variables are an overlay passed to commands
+//! shim starts while the current directory is in the one the file is in,
+//! or under it, unless one there has an env file of its own; shim's own
+//! environment is left alone.
+
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::Write as _,
+    fs,
+    io::Write as _,
+    path::{Path, PathBuf},
+};
+
+use crate::{Model, config};
+
+/// Checked for in this order; the first found is used.
+const FILES: [&str; 2] = [".env", ".envrc"];
+
+/// Variables from an env file, given to every command started.
+#[derive(Debug)]
+pub struct Overlay {
+    pub file: PathBuf,
+    pub vars: BTreeMap<String, String>,
+}
+
+/// Where the answers to "load this directory's env file?" are kept, one
+/// `allow` or `deny`, a tab and the directory per line.
+fn decisions_path() -> Option<PathBuf> {
+    Some(config::dir()?.join("env-allow"))
+}
+
+/// The remembered answer for `dir`, if any.
+fn decision(dir: &Path) -> Option<bool> {
+    let contents = fs::read_to_string(decisions_path()?).ok()?;
+    // the last answer counts
+    contents.lines().rev().find_map(|line| {
+        let (answer, path) = line.split_once('\t')?;
+        (Path::new(path) == dir).then_some(answer == "allow")
+    })
+}
+
+fn remember(dir: &Path, allow: bool) {
+    let Some(path) = decisions_path() else {
+        return;
+    };
+    if let Some(parent) = path.parent() {
+        let _ = fs::create_dir_all(parent);
+    }
+    let answer = if allow { "allow" } else { "deny" };
+    if let Ok(mut file) = fs::OpenOptions::new().create(true).append(true).open(path) {
+        let _ = writeln!(file, "{answer}\t{}", dir.display());
+    }
+}
+
+/// Parse `KEY=value` lines. Blank lines, `#` comments and an `export`
+/// prefix are allowed; values may be single quoted (taken as is) or
+/// double quoted (with `\n`, `\"` and `\\` escapes). Other lines are
+/// skipped.
+pub fn parse(text: &str) -> BTreeMap<String, String> {
+    let mut vars = BTreeMap::new();
+    for line in text.lines() {
+        let line = line.trim();
+        let line = line.strip_prefix("export ").unwrap_or(line).trim_start();
+        if line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            continue;
+        };
+        let key = key.trim();
+        let is_name = key.chars().all(|c| c.is_alphanumeric() || c == '_')
+            && key.chars().next().is_some_and(|c| !c.is_ascii_digit());
+        if !is_name {
+            continue;
+        }
+        if let Some(value) = parse_value(value.trim_start()) {
+            vars.insert(key.to_string(), value);
+        }
+    }
+    vars
+}
+
+fn parse_value(value: &str) -> Option<String> {
+    let mut chars = value.chars();
+    match chars.next() {
+        Some('\'') => Some(chars.take_while(|c| *c != '\'').collect()),
+        Some('"') => {
+            let mut parsed = String::new();
+            while let Some(c) = chars.next() {
+                match c {
+                    '"' => return Some(parsed),
+                    '\\' => match chars.next()? {
+                        'n' => parsed.push('\n'),
+                        't' => parsed.push('\t'),
+                        c => parsed.push(c),
+                    },
+                    c => parsed.push(c),
+                }
+            }
+            // no closing quote
+            None
+        }
+        // a comment needs a space before it, as in `a#b` it's part of the value
+        _ => Some(
+            value
+                .split(" #")
+                .next()
+                .unwrap_or(value)
+                .trim_end()
+                .to_string(),
+        ),
+    }
+}
+
+impl Model {
+    /// After cd: load the new directory's env file if it's allowed, or ask.
+    pub fn check_env_file(&mut self) {
+        if let Ok(dir) = env::current_dir() {
+            self.check_env_file_in(&dir);
+        }
+    }
+
+    /// Load `dir`'s env file, or ask, dropping the last one's variables
+    /// unless `dir` is still in its tree and has none of its own.
+    fn check_env_file_in(&mut self, dir: &Path) {
+        self.env_prompt = None;
+        let file = FILES.iter().map(|f| dir.join(f)).find(|f| f.is_file());
+        let within = self
+            .env_overlay
+            .as_ref()
+            .and_then(|o| o.file.parent())
+            .is_some_and(|tree| dir.starts_with(tree));
+        if within && file.is_none() {
+            return;
+        }
+        if let Some(overlay) = self.env_overlay.take() {
+            self.status = Some(format!("unloaded {}", overlay.file.display()));
+        }
+        let Some(file) = file else {
+            return;
+        };
+        match decision(dir) {
+            Some(true) => self.load_env_file(file),
+            Some(false) => {}
+            None => self.env_prompt = Some(file),
+        }
+    }
+
+    /// The answer to whether to load the env file asked about.
+    pub fn allow_env_file(&mut self, allow: bool) {
+        let Some(file) = self.env_prompt.take() else {
+            return;
+        };
+        if let Some(dir) = file.parent() {
+            remember(dir, allow);
+        }
+        if allow {
+            self.load_env_file(file);
+        } else {
+            self.status = Some(format!("not loading {}", file.display()));
+        }
+    }
+
+    fn load_env_file(&mut self, file: PathBuf) {
+        match fs::read_to_string(&file) {
+            Ok(text) => {
+                let vars = parse(&text);
+                self.status = Some(format!(
+                    "loaded {} vars from {}",
+                    vars.len(),
+                    file.display()
+                ));
+                self.env_overlay = Some(Overlay { file, vars });
+            }
+            Err(e) => self.status = Some(format!("{}: {e}", file.display())),
+        }
+    }
+
+    /// Variables from the loaded env file.
+    pub fn overlay_vars(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.env_overlay
+            .iter()
+            .flat_map(|o| o.vars.iter().map(|(k, v)| (k.as_str(), v.as_str())))
+    }
+}
+
+/// `:env` to list the overlay, `:env clear` to drop it.
+pub fn command(model: &mut Model, args: &[&str]) -> String {
+    match args {
+        [] => match &model.env_overlay {
+            Some(overlay) => {
+                let mut out = format!("from {}:\n", overlay.file.display());
+                for (key, value) in &overlay.vars {
+                    let _ = writeln!(out, "{key}={value}");
+                }
+                out
+            }
+            None => "no env file loaded".into(),
+        },
+        ["clear"] => match model.env_overlay.take() {
+            Some(overlay) => format!(
+                "dropped {} vars from {}",
+                overlay.vars.len(),
+                overlay.file.display()
+            ),
+            None => "no env file loaded".into(),
+        },
+        _ => "usage: :env or :env clear".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(text: &str) -> Vec<(String, String)> {
+        parse(text).into_iter().collect()
+    }
+
+    fn pair(key: &str, value: &str) -> (String, String) {
+        (key.into(), value.into())
+    }
+
+    #[test]
+    fn plain_and_exported() {
+        assert_eq!(
+            vars("A=1\nexport B=two\n  C = 3 "),
+            [pair("A", "1"), pair("B", "two"), pair("C", "3")]
+        );
+    }
+
+    #[test]
+    fn comments_and_junk() {
+        assert_eq!(
+            vars("# a comment\n\nA=1 # note\nB=x#y\nnot a var\n1X=2"),
+            [pair("A", "1"), pair("B", "x#y")]
+        );
+    }
+
+    #[test]
+    fn quotes() {
+        assert_eq!(
+            vars(
+                r#"A='$HOME \n'
+B="say \"hi\"\nbye" # after
+C="unterminated"#
+            ),
+            [pair("A", r"$HOME \n"), pair("B", "say \"hi\"\nbye")]
+        );
+    }
+
+    #[test]
+    fn the_variables_stay_while_in_the_files_tree() {
+        let project = env::temp_dir().join(format!("shim-dotenv-{}", std::process::id()));
+        let (src, other) = (project.join("src"), env::temp_dir());
+        fs::create_dir_all(&src).unwrap();
+        fs::write(project.join(".env"), "A=1\n").unwrap();
+        let mut model = Model {
+            env_overlay: Some(Overlay {
+                file: project.join(".env"),
+                vars: parse("A=1"),
+            }),
+            ..Default::default()
+        };
+        model.check_env_file_in(&src);
+        assert_eq!(model.var("A").as_deref(), Some("1"));
+        assert!(model.env_prompt.is_none());
+
+        // one of its own takes over, asked about first
+        fs::write(src.join(".env"), "B=2\n").unwrap();
+        model.check_env_file_in(&src);
+        assert!(model.env_overlay.is_none());
+        assert_eq!(model.env_prompt, Some(src.join(".env")));
+
+        model.env_overlay = Some(Overlay {
+            file: project.join(".env"),
+            vars: parse("A=1"),
+        });
+        model.check_env_file_in(&other);
+        assert!(model.env_overlay.is_none());
+        assert_eq!(model.var("A"), None);
+        let _ = fs::remove_dir_all(&project);
+    }
+}
@@ -0,0 +1,221 @@
+//! `:each [-k] TEMPLATE`: run TEMPLATE once per line of the viewed buffer,
+//! like xargs, one after another into a buffer of its own.
+
+use std::ops::ControlFlow;
+
+use crate::{Model, Output, shell};
+
+/// Stands for the line in the template; it's quoted when filled in.
+const PLACEHOLDER: &str = "{}";
+
+/// Running a template over lines, into one buffer.
+#[derive(Debug)]
+pub struct Each {
+    template: String,
+    items: Vec<String>,
+    /// Items started so far.
+    started: usize,
+    /// Carry on after an item fails instead of stopping.
+    keep_going: bool,
+    failed: usize,
+    /// The exit status for the whole run: that of the last failure, if any.
+    status: Option<i32>,
+    /// No more items will be started.
+    done: bool,
+}
+
+impl Each {
+    /// The next item's command, counting it as started.
+    fn next_command(&mut self) -> Option<String> {
+        let item = self.items.get(self.started)?;
+        self.started += 1;
+        Some(fill(&self.template, item))
+    }
+
+    /// Count the item that just ended. Returns whether to start the next.
+    fn finish(&mut self, status: Option<i32>) -> bool {
+        if self.done {
+            return false;
+        }
+        if status != Some(0) {
+            self.failed += 1;
+            self.status = status;
+        }
+        self.done = self.started == self.items.len() || (status != Some(0) && !self.keep_going);
+        !self.done
+    }
+
+    fn summary(&self) -> String {
+        let total = self.items.len();
+        let failed = match self.failed {
+            0 => String::new(),
+            n => format!(", {n} failed"),
+        };
+        match self.started {
+            started if started < total => format!("stopped after {started}/{total}{failed}"),
+            _ => format!("ran {total}{failed}"),
+        }
+    }
+}
+
+/// `template` with `{}` replaced by `item`, quoted, or with `item` added
+/// to the end if there's no `{}`.
+fn fill(template: &str, item: &str) -> String {
+    let quoted = shell::quote(item);
+    match template.contains(PLACEHOLDER) {
+        true => template.replace(PLACEHOLDER, &quoted),
+        false => format!("{template} {quoted}"),
+    }
+}
+
+impl Output {
+    /// How the run is going, for the title.
+    pub fn each_note(&self) -> Option<String> {
+        let each = self.each.as_ref()?;
+        Some(match self.running {
+            true => format!("item {}/{}", each.started, each.items.len()),
+            false => each.summary(),
+        })
+    }
+}
+
+impl Model {
+    /// `:each [-k] TEMPLATE`, returning what to show on the status line.
+    pub fn start_each(&mut self, command: &str, args: &str) -> String {
+        let (keep_going, template) = match args.strip_prefix("-k ") {
+            Some(template) => (true, template.trim()),
+            None => (false, args),
+        };
+        if template.is_empty() {
+            return "usage: :each [-k] CMD, with {} for the line".into();
+        }
+        let items: Vec<_> = self
+            .outputs
+            .get(self.viewing_output)
+            .map(|o| o.raw_stdout())
+            .unwrap_or("")
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+        if items.is_empty() {
+            return "no lines in the viewed buffer to run over".into();
+        }
+        let count = items.len();
+        let viewing = self.outputs[self.viewing_output].id;
+        self.push_output(command.to_string(), String::new());
+        // old buffers may have been closed to make room
+        if self.view_locked {
+            let viewing = self.job_index(viewing);
+            self.view_output(viewing.unwrap_or(self.outputs.len() - 1));
+        }
+        let Some(output) = self.outputs.last_mut() else {
+            return String::new();
+        };
+        let id = output.id;
+        output.mark_started();
+        output.each = Some(Each {
+            template: template.to_string(),
+            items,
+            started: 0,
+            keep_going,
+            failed: 0,
+            status: Some(0),
+            done: false,
+        });
+        self.run_item(id);
+        format!("running {template} over {count} lines")
+    }
+
+    /// Start the next item of the run in buffer `id`. One that can't be
+    /// started counts as failed, and so on until one is running or the run
+    /// is over.
+    pub fn run_item(&mut self, id: usize) {
+        loop {
+            let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) else {
+                return;
+            };
+            let Some(each) = output.each.as_mut() else {
+                return;
+            };
+            let Some(command) = each.next_command() else {
+                break;
+            };
+            let progress = format!("item {}/{}", each.started, each.items.len());
+            output.push_note(
+                &format!("--- {progress}: {command} ---"),
+                &self.config.redact,
+            );
+            self.status = Some(progress);
+            let spawned = self
+                .parse_pipeline(&command)
+                .map(|stages| self.spawn_stages(stages, id));
+            let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) else {
+                return;
+            };
+            let status = match spawned {
+                Some(Ok(process)) => {
+                    output.running = true;
+                    output.process = Some(process);
+                    output.status = None;
+                    return;
+                }
+                Some(Err(e)) => {
+                    output.warn(format!("couldn't start {command}: {e}"));
+                    Some(127)
+                }
+                None => Some(1),
+            };
+            if !output.each.as_mut().is_some_and(|e| e.finish(status)) {
+                break;
+            }
+        }
+        self.finish_job(id, None);
+    }
+
+    /// After the job in buffer `id` ends with `status`: if it's an item
+    /// of a run, note a failure and start the next item. Breaks with the
+    /// status the buffer ends with, which for a run is the run's.
+    pub fn next_item(&mut self, id: usize, status: Option<i32>) -> ControlFlow<Option<i32>> {
+        let redact = &self.config.redact;
+        let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) else {
+            return ControlFlow::Break(status);
+        };
+        let Some(each) = output.each.as_mut() else {
+            return ControlFlow::Break(status);
+        };
+        let failed = status != Some(0) && !each.done;
+        let more = each.finish(status);
+        let (overall, summary) = (each.status, each.summary());
+        if failed {
+            let exit = status.map_or("killed".into(), |s| format!("exit {s}"));
+            output.push_note(&format!("--- {exit} ---"), redact);
+        }
+        if more {
+            self.run_item(id);
+            return ControlFlow::Continue(());
+        }
+        output.push_note(&format!("--- {summary} ---"), redact);
+        self.status = Some(summary);
+        ControlFlow::Break(overall)
+    }
+
+    /// Ctrl-C on a run in the viewed buffer: stop the item running and
+    /// start no more. Returns false if there's no run going.
+    pub fn stop_each(&mut self) -> bool {
+        let Some(output) = self
+            .outputs
+            .get_mut(self.viewing_output)
+            .filter(|o| o.running)
+        else {
+            return false;
+        };
+        let Some(each) = output.each.as_mut().filter(|e| !e.done) else {
+            return false;
+        };
+        each.done = true;
+        each.status = None;
+        output.kill();
+        true
+    }
+}
@@ -0,0 +1,245 @@
+//! Normal mode commands that change the command in place: `~`, `guw`,
+//! `gUw`, Ctrl-a and Ctrl-x.
+
+use crate::{Cursor, Model};
+
+/// Changes to one character case. Some characters change length, like
+/// `ß` to `SS`; titlecase ones like `ǅ` go to lowercase, as in vim.
+fn toggled(c: char) -> String {
+    match c.is_lowercase() {
+        true => c.to_uppercase().collect(),
+        false => c.to_lowercase().collect(),
+    }
+}
+
+/// Which kind of run `c` belongs to in a word: blanks, word characters
+/// or other non-blanks.
+pub fn class(c: char) -> u8 {
+    match c {
+        c if c.is_whitespace() => 0,
+        c if c.is_alphanumeric() || c == '_' => 1,
+        _ => 2,
+    }
+}
+
+/// Byte offset where the word starting at `from` ends, as `w` would find
+/// the next one: a run of word characters or of other non-blanks, then
+/// any blanks.
+fn word_end(line: &str, from: usize) -> usize {
+    let rest = &line[from..];
+    let run = match rest.chars().next() {
+        Some(first) if !first.is_whitespace() => rest
+            .find(|c| class(c) != class(first))
+            .unwrap_or(rest.len()),
+        _ => 0,
+    };
+    let blanks = &rest[run..];
+    from + run + (blanks.len() - blanks.trim_start().len())
+}
+
+impl Model {
+    /// The cursor's byte offset in the command and the bounds of its line.
+    pub fn edit_position(&self) -> Option<(usize, usize, usize)> {
+        let Cursor::CommandLine(x, y) = self.cursor else {
+            return None;
+        };
+        let offset = self.command_offset(x, y);
+        let command = &self.current_command;
+        let start = command[..offset].rfind('\n').map_or(0, |i| i + 1);
+        let end = command[offset..]
+            .find('\n')
+            .map_or(command.len(), |i| offset + i);
+        Some((offset, start, end))
+    }
+
+    /// Put the cursor at byte `offset`, on the line starting at `start`.
+    pub fn edit_cursor(&mut self, offset: usize, start: usize) {
+        if let Cursor::CommandLine(_, y) = self.cursor {
+            self.cursor = Cursor::CommandLine((offset - start) as u16, y);
+        }
+    }
+
+    /// `~`: switch the case of the character under the cursor and move
+    /// right.
+    pub fn toggle_case(&mut self) {
+        let Some((offset, start, end)) = self.edit_position() else {
+            return;
+        };
+        let Some(c) = self.current_command[offset..end].chars().next() else {
+            return;
+        };
+        let replacement = toggled(c);
+        self.current_command
+            .replace_range(offset..offset + c.len_utf8(), &replacement);
+        let end = end - c.len_utf8() + replacement.len();
+        // like vim, stay on the last character
+        let next = offset + replacement.len();
+        let last = self.current_command[start..end]
+            .char_indices()
+            .last()
+            .map_or(start, |(i, _)| start + i);
+        self.edit_cursor(next.min(last), start);
+    }
+
+    /// `guw` and `gUw`: change the case of the rest of the word.
+    pub fn change_word_case(&mut self, upper: bool) {
+        let Some((offset, start, end)) = self.edit_position() else {
+            return;
+        };
+        let line = &self.current_command[start..end];
+        let word = &line[offset - start..word_end(line, offset - start)];
+        let changed = match upper {
+            true => word.to_uppercase(),
+            false => word.to_lowercase(),
+        };
+        let word_len = word.len();
+        self.current_command
+            .replace_range(offset..offset + word_len, &changed);
+    }
+
+    /// Ctrl-a and Ctrl-x: add `by` to the number under or after the cursor,
+    /// leaving the cursor on its last digit.
+    pub fn increment(&mut self, by: i64) {
+        let Some((offset, start, end)) = self.edit_position() else {
+            return;
+        };
+        let line = &self.current_command[start..end];
+        let at = offset - start;
+        // back to the start of a number the cursor is in
+        let digits_before = line[..at]
+            .chars()
+            .rev()
+            .take_while(char::is_ascii_digit)
+            .count();
+        let Some(first) = line[at - digits_before..]
+            .find(|c: char| c.is_ascii_digit())
+            .map(|i| i + at - digits_before)
+        else {
+            self.status = Some("no number under or after the cursor".into());
+            return;
+        };
+        let len = line[first..]
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(line.len() - first);
+        // a minus after a word is a dash, as in `file-2`
+        let negative = line[..first].ends_with('-')
+            && !line[..first - 1]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_');
+        let number_start = if negative { first - 1 } else { first };
+        let Ok(number) = line[number_start..first + len].parse::<i64>() else {
+            self.status = Some("number too large".into());
+            return;
+        };
+        let new = number.saturating_add(by).to_string();
+        let from = start + number_start;
+        self.current_command
+            .replace_range(from..start + first + len, &new);
+        self.edit_cursor(from + new.len() - 1, start);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, Mode, update};
+
+    /// A model in Normal mode with `command` on the command line and the
+    /// cursor at byte `at`.
+    fn editing(command: &str, at: u16) -> Model {
+        Model {
+            current_command: command.into(),
+            mode: Mode::Normal,
+            cursor: Cursor::CommandLine(at, 0),
+            ..Default::default()
+        }
+    }
+
+    fn send(model: &mut Model, msg: Message) {
+        let mut next = Some(msg);
+        while let Some(msg) = next {
+            next = update(model, msg);
+        }
+    }
+
+    #[test]
+    fn case_changes_that_change_length() {
+        let mut model = editing("straße", 4);
+        model.toggle_case();
+        assert_eq!(model.current_command, "straSSe");
+        assert_eq!(model.cursor, Cursor::CommandLine(6, 0));
+        model.toggle_case();
+        assert_eq!(model.current_command, "straSSE");
+        // still on the last character
+        assert_eq!(model.cursor, Cursor::CommandLine(6, 0));
+
+        let mut model = editing("ǅ", 0);
+        model.toggle_case();
+        assert_eq!(model.current_command, "ǆ");
+        model.toggle_case();
+        assert_eq!(model.current_command, "Ǆ");
+    }
+
+    #[test]
+    fn a_number_at_the_end_of_the_line() {
+        let mut model = editing("curl localhost:8080", 0);
+        model.increment(1);
+        assert_eq!(model.current_command, "curl localhost:8081");
+        assert_eq!(model.cursor, Cursor::CommandLine(18, 0));
+        model.increment(-82);
+        assert_eq!(model.current_command, "curl localhost:7999");
+        assert_eq!(model.cursor, Cursor::CommandLine(18, 0));
+        let mut model = editing("seq 99", 5);
+        model.increment(1);
+        assert_eq!(model.current_command, "seq 100");
+        assert_eq!(model.cursor, Cursor::CommandLine(6, 0));
+    }
+
+    #[test]
+    fn negative_numbers_and_dashes() {
+        let mut model = editing("tail -n -5", 8);
+        model.increment(1);
+        assert_eq!(model.current_command, "tail -n -4");
+        model.increment(10);
+        assert_eq!(model.current_command, "tail -n 6");
+        assert_eq!(model.cursor, Cursor::CommandLine(8, 0));
+        model.increment(-7);
+        assert_eq!(model.current_command, "tail -n -1");
+        // after a word it's a dash
+        let mut model = editing("cat part-2", 0);
+        model.increment(-1);
+        assert_eq!(model.current_command, "cat part-1");
+    }
+
+    #[test]
+    fn no_number_after_the_cursor() {
+        let mut model = editing("sleep 5 && ls", 8);
+        model.increment(1);
+        assert_eq!(model.current_command, "sleep 5 && ls");
+        assert_eq!(
+            model.status.as_deref(),
+            Some("no number under or after the cursor")
+        );
+    }
+
+    #[test]
+    fn dot_makes_these_changes_again() {
+        let mut model = editing("echo ab", 5);
+        send(&mut model, Message::ToggleCase);
+        send(&mut model, Message::RepeatChange);
+        assert_eq!(model.current_command, "echo AB");
+
+        let mut model = editing("kill 41 42", 5);
+        send(&mut model, Message::Increment(1));
+        model.cursor = Cursor::CommandLine(8, 0);
+        send(&mut model, Message::RepeatChange);
+        assert_eq!(model.current_command, "kill 42 43");
+
+        let mut model = editing("ls src test", 3);
+        send(&mut model, Message::ChangeWordCase { upper: true });
+        model.cursor = Cursor::CommandLine(7, 0);
+        send(&mut model, Message::RepeatChange);
+        assert_eq!(model.current_command, "ls SRC TEST");
+    }
+}
@@ -0,0 +1,249 @@
+//! Output in encodings other than UTF-8. A command's output starting with
+//! a byte order mark is read as the UTF-8 or UTF-16 it marks, and without
+//! one as UTF-8 unless an encoding is given: the `encoding` setting for
+//! every command, or `:encoding NAME` for the next one run, by any name
+//! the WHATWG encoding standard knows (`latin1` is windows-1252 there).
+//! Nothing else is guessed at, so output that is UTF-8 is never read as
+//! anything else. Whatever it came in, it's kept as UTF-8, so what's
+//! yanked or saved from it is UTF-8 too, and the title says what it was
+//! read as when that wasn't UTF-8.
+
+use std::fmt;
+
+use encoding_rs::{Encoding, UTF_8};
+
+use crate::{Model, job};
+
+/// The byte order marks looked for at the start of output.
+const BOMS: [&[u8]; 3] = [b"\xef\xbb\xbf", b"\xff\xfe", b"\xfe\xff"];
+
+/// The encoding `label` names, or `None` for `auto`.
+pub fn parse(label: &str) -> Result<Option<&'static Encoding>, String> {
+    match label {
+        "auto" => Ok(None),
+        _ => Encoding::for_label(label.as_bytes())
+            .map(Some)
+            .ok_or_else(|| format!("unknown encoding: {label}")),
+    }
+}
+
+/// Turns a stream of output into UTF-8, a chunk at a time.
+#[derive(Default)]
+pub struct Decoder {
+    /// Given rather than found.
+    forced: Option<&'static Encoding>,
+    /// The start of the output has been looked at for a byte order mark.
+    sniffed: bool,
+    /// What the output is read as, when it isn't UTF-8.
+    decoder: Option<encoding_rs::Decoder>,
+    /// Bytes held back: a character split across chunks, or what may be
+    /// the start of a byte order mark.
+    pending: Vec<u8>,
+}
+
+impl fmt::Debug for Decoder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Decoder")
+            .field("encoding", &self.encoding().map(Encoding::name))
+            .field("pending", &self.pending)
+            .finish()
+    }
+}
+
+impl Decoder {
+    pub fn new(forced: Option<&'static Encoding>) -> Self {
+        Decoder {
+            forced,
+            ..Default::default()
+        }
+    }
+
+    /// Start again for another run, in the same encoding if it was given.
+    pub fn reset(&mut self) {
+        *self = Decoder::new(self.forced);
+    }
+
+    /// The encoding given, if any.
+    pub fn forced(&self) -> Option<&'static Encoding> {
+        self.forced
+    }
+
+    /// What the output is read as, if it's been found or given; `None`
+    /// is UTF-8.
+    pub fn encoding(&self) -> Option<&'static Encoding> {
+        match &self.decoder {
+            Some(decoder) => Some(decoder.encoding()),
+            None => self.forced,
+        }
+    }
+
+    /// Pick the encoding from what's come so far, unless it may yet be
+    /// a byte order mark and more is to come.
+    fn sniff(&mut self, last: bool) {
+        let partial = BOMS
+            .iter()
+            .any(|bom| bom.len() > self.pending.len() && bom.starts_with(&self.pending));
+        if self.sniffed || (partial && !last) {
+            return;
+        }
+        self.sniffed = true;
+        let encoding = match Encoding::for_bom(&self.pending) {
+            Some((encoding, len)) => {
+                self.pending.drain(..len);
+                Some(encoding)
+            }
+            None => self.forced,
+        };
+        self.decoder = encoding
+            .filter(|&e| e != UTF_8)
+            .map(Encoding::new_decoder_without_bom_handling);
+    }
+
+    /// The text of `data` after what was held back, and whether any of it
+    /// couldn't be read.
+    pub fn decode(&mut self, data: &[u8]) -> (String, bool) {
+        if !self.sniffed {
+            self.pending.extend_from_slice(data);
+            self.sniff(false);
+            if !self.sniffed {
+                return (String::new(), false);
+            }
+            let data = std::mem::take(&mut self.pending);
+            return self.transcode(&data, false);
+        }
+        self.transcode(data, false)
+    }
+
+    /// What was held back, now the output has ended, and whether any of it
+    /// couldn't be read.
+    pub fn finish(&mut self) -> (String, bool) {
+        self.sniff(true);
+        let rest = std::mem::take(&mut self.pending);
+        match &mut self.decoder {
+            Some(_) => self.transcode(&rest, true),
+            None => (
+                String::from_utf8_lossy(&rest).into_owned(),
+                !rest.is_empty(),
+            ),
+        }
+    }
+
+    fn transcode(&mut self, data: &[u8], last: bool) -> (String, bool) {
+        let Some(decoder) = &mut self.decoder else {
+            return job::decode(&mut self.pending, data);
+        };
+        let room = decoder.max_utf8_buffer_length(data.len()).unwrap_or(0);
+        let mut text = String::with_capacity(room);
+        let (_, _, replaced) = decoder.decode_to_string(data, &mut text, last);
+        (text, replaced)
+    }
+
+    /// Says that some of the output couldn't be read.
+    pub fn warning(&self) -> String {
+        match self.encoding() {
+            Some(encoding) if encoding != UTF_8 => {
+                format!("output that wasn't {} is shown as �", encoding.name())
+            }
+            _ => job::INVALID_UTF8.into(),
+        }
+    }
+
+    /// What the output was read as, when that wasn't UTF-8.
+    pub fn note(&self) -> Option<&'static str> {
+        self.encoding().filter(|&e| e != UTF_8).map(Encoding::name)
+    }
+}
+
+impl Model {
+    /// `:encoding NAME`: read the next command's output as NAME.
+    pub fn set_next_encoding(&mut self, label: Option<&str>) -> String {
+        let Some(label) = label else {
+            return match self.next_encoding.or(self.config.encoding) {
+                Some(encoding) => format!("the next command is read as {}", encoding.name()),
+                None => "the next command is read as UTF-8 unless it has a BOM".into(),
+            };
+        };
+        match parse(label) {
+            Ok(encoding) => {
+                self.next_encoding = encoding;
+                match encoding {
+                    Some(encoding) => format!("the next command is read as {}", encoding.name()),
+                    None => "the next command is read as the encoding setting says".into(),
+                }
+            }
+            Err(e) => e,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `data` fed through a decoder a byte at a time.
+    fn bytewise(decoder: &mut Decoder, data: &[u8]) -> (String, bool) {
+        let mut text = String::new();
+        let mut invalid = false;
+        for byte in data {
+            let (chunk, bad) = decoder.decode(&[*byte]);
+            text.push_str(&chunk);
+            invalid |= bad;
+        }
+        let (rest, bad) = decoder.finish();
+        (text + &rest, invalid || bad)
+    }
+
+    #[test]
+    fn a_byte_order_mark_says_what_output_is() {
+        let mut decoder = Decoder::default();
+        let utf16: Vec<u8> = b"\xff\xfe"
+            .iter()
+            .copied()
+            .chain("héllo\n".encode_utf16().flat_map(u16::to_le_bytes))
+            .collect();
+        assert_eq!(bytewise(&mut decoder, &utf16), ("héllo\n".into(), false));
+        assert_eq!(decoder.note(), Some("UTF-16LE"));
+
+        let mut decoder = Decoder::default();
+        assert_eq!(decoder.decode(b"\xef\xbb\xbfok"), ("ok".into(), false));
+        assert_eq!(decoder.note(), None);
+        // output too short to tell is UTF-8
+        let mut decoder = Decoder::default();
+        assert_eq!(decoder.decode(b"\xef"), (String::new(), false));
+        assert_eq!(decoder.finish(), ("\u{fffd}".into(), true));
+    }
+
+    #[test]
+    fn utf8_is_never_read_as_anything_else() {
+        let mut decoder = Decoder::default();
+        assert_eq!(
+            bytewise(&mut decoder, "þæ €\n".as_bytes()),
+            ("þæ €\n".into(), false)
+        );
+        assert_eq!(decoder.note(), None);
+        // latin-1 isn't guessed at
+        let mut decoder = Decoder::default();
+        assert_eq!(
+            bytewise(&mut decoder, b"caf\xe9"),
+            ("caf\u{fffd}".into(), true)
+        );
+    }
+
+    #[test]
+    fn an_encoding_can_be_given() {
+        let latin1 = parse("latin1").unwrap();
+        let mut decoder = Decoder::new(latin1);
+        assert_eq!(
+            bytewise(&mut decoder, b"caf\xe9 \x80"),
+            ("café €".into(), false)
+        );
+        assert_eq!(decoder.note(), Some("windows-1252"));
+        // a byte order mark is still believed
+        let mut decoder = Decoder::new(latin1);
+        assert_eq!(decoder.decode(b"\xfe\xff\x00h"), ("h".into(), false));
+        decoder.reset();
+        assert_eq!(decoder.encoding(), latin1);
+        assert_eq!(parse("auto"), Ok(None));
+        assert!(parse("klingon").is_err());
+    }
+}
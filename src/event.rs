@@ -0,0 +1,181 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use ratatui::crossterm::event::{self, Event, KeyEvent, KeyEventKind, MouseEvent, MouseEventKind};
+
+use crate::{completer, history, import};
+
+/// Everything the main loop wakes up for. Each source runs on its own
+/// thread and sends into one channel.
+#[derive(Debug)]
+pub enum AppEvent {
+    /// A key press, mapped to a `Message` on the main thread since that
+    /// depends on the mode, with when it was read; see `typeahead`.
+    Key(KeyEvent, Instant),
+    OutputChunk {
+        job_id: usize,
+        data: Vec<u8>,
+    },
+    /// What a job wrote to stderr; see `stderr`.
+    ErrorChunk {
+        job_id: usize,
+        data: Vec<u8>,
+    },
+    JobExited {
+        job_id: usize,
+        status: Option<i32>,
+        /// Each stage's exit code, if it was a pipeline.
+        stages: Vec<i32>,
+    },
+    /// Reading a job's output failed, before it exited.
+    ReadFailed {
+        job_id: usize,
+        error: String,
+    },
+    Resize {
+        width: u16,
+        height: u16,
+    },
+    /// Only sent while shim has the mouse; see `mouse`.
+    Mouse(MouseEvent),
+    /// What the outside completer offered, or `None` if it failed.
+    Completions {
+        key: completer::Key,
+        values: Option<completer::Values>,
+    },
+    /// Paths found by a walk for the file picker; `done` on the last batch.
+    Files {
+        walk: usize,
+        paths: Vec<String>,
+        done: bool,
+    },
+    /// What a prompt segment's command gave, run in `dir`.
+    Segment {
+        name: String,
+        dir: PathBuf,
+        text: String,
+    },
+    /// Another shell's history, read by `:import-history`.
+    HistoryImported(import::Import),
+    /// The history, loaded in the background with `defer_history`.
+    HistoryLoaded(history::Loaded),
+    /// The lines worked out for a buffer made by `:sort` or `:uniq`.
+    Derived {
+        output: usize,
+        text: String,
+    },
+    /// What the stages being previewed made of command line `typed`; see
+    /// `preview`.
+    Previewed {
+        typed: String,
+        result: Result<String, String>,
+    },
+    /// What `:pipe`'s filter `command` made of buffer `output`, for
+    /// request `request`.
+    Piped {
+        output: usize,
+        request: u64,
+        command: String,
+        result: Result<String, String>,
+    },
+    /// SIGTSTP, to stop; see `suspend`.
+    #[cfg(unix)]
+    Suspend,
+    /// SIGCONT, after being stopped.
+    #[cfg(unix)]
+    Continued,
+}
+
+/// Forward SIGTSTP and SIGCONT until the channel closes. Ctrl-Z in the
+/// terminal comes as a key, as it's in raw mode, so SIGTSTP is from
+/// elsewhere, or for a program shim has given the terminal to, as are
+/// SIGINT and SIGQUIT; those are only for it then.
+#[cfg(unix)]
+pub fn spawn_signals(events: Sender<AppEvent>) -> std::io::Result<()> {
+    use signal_hook::{
+        consts::{SIGCONT, SIGINT, SIGQUIT, SIGTSTP},
+        flag,
+        iterator::Signals,
+    };
+    for signal in [SIGINT, SIGQUIT] {
+        flag::register_conditional_default(signal, crate::passthrough::ours())?;
+    }
+    let mut signals = Signals::new([SIGTSTP, SIGCONT])?;
+    thread::spawn(move || {
+        for signal in signals.forever() {
+            if crate::passthrough::handed_over() {
+                continue;
+            }
+            let event = match signal {
+                SIGTSTP => AppEvent::Suspend,
+                _ => AppEvent::Continued,
+            };
+            if events.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    Ok(())
+}
+
+/// Set for the input thread to leave the terminal's input alone.
+static PAUSED: AtomicBool = AtomicBool::new(false);
+/// Set by the input thread once it has.
+static IDLE: AtomicBool = AtomicBool::new(false);
+
+/// Stop reading the terminal's input, for a program given the terminal,
+/// or start again. Returns once nothing more will be read.
+pub fn pause_input(paused: bool) {
+    PAUSED.store(paused, Ordering::SeqCst);
+    let waiting = Instant::now();
+    // the input thread looks every poll, unless it's gone
+    while paused && !IDLE.load(Ordering::SeqCst) && waiting.elapsed() < POLL * 4 {
+        thread::sleep(POLL / 10);
+    }
+}
+
+/// How long the input thread waits for input before looking at `PAUSED`.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Forward terminal input until the channel closes, or reading it fails.
+pub fn spawn_input(events: Sender<AppEvent>) {
+    thread::spawn(move || {
+        loop {
+            if PAUSED.load(Ordering::SeqCst) {
+                IDLE.store(true, Ordering::SeqCst);
+                thread::sleep(POLL / 5);
+                continue;
+            }
+            IDLE.store(false, Ordering::SeqCst);
+            match event::poll(POLL) {
+                Ok(true) => {}
+                Ok(false) => continue,
+                Err(_) => break,
+            }
+            let Ok(event) = event::read() else {
+                break;
+            };
+            let app_event = match event {
+                Event::Key(key) if key.kind == KeyEventKind::Press => {
+                    AppEvent::Key(key, Instant::now())
+                }
+                Event::Resize(width, height) => AppEvent::Resize { width, height },
+                // nothing's done with the pointer going by
+                Event::Mouse(mouse) if mouse.kind != MouseEventKind::Moved => {
+                    AppEvent::Mouse(mouse)
+                }
+                _ => continue,
+            };
+            if events.send(app_event).is_err() {
+                break;
+            }
+        }
+    });
+}
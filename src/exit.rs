@@ -0,0 +1,76 @@
+//! How the last command ended: `$?` expands to its exit code, and after a
+//! failure the prompt turns red, with the code in front of it, until the
+//! next command is submitted. A command killed by a signal ends with 128
+//! plus the signal's number, as in sh.
+
+use ratatui::{
+    style::{Style, Stylize},
+    text::Span,
+};
+
+use crate::{Mode, Model, Output, jobs};
+
+/// `$?` for a command that ended with `status`, or was killed by `signal`
+/// when there's no status. Without either shim killed it itself, which
+/// it does with SIGKILL.
+pub fn code(status: Option<i32>, signal: Option<i32>) -> i32 {
+    status.unwrap_or_else(|| 128 + signal.unwrap_or(9))
+}
+
+impl Output {
+    /// The signal that ended the job: as seen when it was waited for, or
+    /// else the one `kill` sent it.
+    pub fn exit_signal(&self) -> Option<i32> {
+        #[cfg(unix)]
+        if let Some(signal) = self.waited_signal() {
+            return Some(signal);
+        }
+        self.signal.and_then(jobs::signal_number)
+    }
+
+    #[cfg(unix)]
+    fn waited_signal(&self) -> Option<i32> {
+        use std::os::unix::process::ExitStatusExt;
+        let mut child = self.process.as_ref()?.lock().ok()?;
+        // gives back what the job's thread saw, without waiting again
+        child.try_wait().ok().flatten()?.signal()
+    }
+}
+
+impl Model {
+    /// Note how a command ended, for `$?` and the prompt.
+    pub fn record_exit(&mut self, code: i32) {
+        self.last_exit = Some(code);
+        self.failed = (code != 0).then_some(code);
+    }
+
+    /// What the command line starts with: the mode's glyph, red after a
+    /// failure, with the exit code in front if `show_exit_code` is set.
+    pub fn prompt(&self) -> Span<'static> {
+        let glyph = match self.mode {
+            Mode::Insert | Mode::Replace => "❯",
+            Mode::Normal => "❮",
+        };
+        match self.failed {
+            Some(code) if self.config.show_exit_code => {
+                Span::styled(format!("✗ {code} {glyph}"), Style::new().red())
+            }
+            Some(_) => Span::styled(glyph, Style::new().red()),
+            None => Span::raw(glyph),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn exit_codes() {
+        assert_eq!(code(Some(0), None), 0);
+        assert_eq!(code(Some(101), Some(15)), 101);
+        assert_eq!(code(None, Some(15)), 143);
+        assert_eq!(code(None, Some(2)), 130);
+        assert_eq!(code(None, None), 137);
+    }
+}
@@ -0,0 +1,167 @@
+//! `gl`: the line under the cursor in full, wrapped in a popup over the
+//! output, for a line cut off at `long_line` or one wider than the pane.
+//! Rows are found only as far as they're scrolled to, so a line of
+//! megabytes opens at once. `y` in the popup yanks the whole line.
+
+use std::cell::RefCell;
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Clear, Paragraph},
+};
+
+use crate::{Cursor, Model, display, memory::format_size};
+
+#[derive(Debug)]
+pub struct LinePopup {
+    /// The buffer, by id.
+    output: usize,
+    line: usize,
+    /// The row at the top of the popup.
+    pub top: usize,
+    rows: RefCell<Rows>,
+}
+
+/// Where rows of the line start, as far as they've been found.
+#[derive(Debug, Default)]
+struct Rows {
+    /// The width they were found for.
+    width: usize,
+    starts: Vec<usize>,
+    /// The last row has been found.
+    ended: bool,
+}
+
+impl Rows {
+    /// Find rows of `line` until there are `count` of them, or no more.
+    fn find(&mut self, line: &str, width: usize, settings: display::Settings, count: usize) {
+        if width != self.width || self.starts.is_empty() {
+            *self = Rows {
+                width,
+                starts: vec![0],
+                ended: false,
+            };
+        }
+        while self.starts.len() < count && !self.ended {
+            let last = self.starts[self.starts.len() - 1];
+            match display::row_at(line, last, width, settings).0 {
+                next if next >= line.len() => self.ended = true,
+                next => self.starts.push(next),
+            }
+        }
+    }
+}
+
+impl Model {
+    /// The line shown in the popup, all of it.
+    pub fn popup_line(&self) -> Option<&str> {
+        let popup = self.line_popup.as_ref()?;
+        let output = self.outputs.iter().find(|o| o.id == popup.output)?;
+        output.line(popup.line)
+    }
+
+    /// The buffer and line the cursor is on in the output, if any.
+    pub fn line_under_cursor(&self) -> Option<(usize, usize)> {
+        let Cursor::OutputBuffer(_, y) = self.cursor else {
+            return None;
+        };
+        let place = match self.scrollback_top() {
+            Some(top) => self
+                .locate(top + y as usize)
+                .and_then(|p| Some((p.output, p.line?))),
+            None => self
+                .outputs
+                .get(self.viewing_output)
+                .map(|o| (self.viewing_output, o.scroll.0 + y as usize)),
+        };
+        place.filter(|&(i, n)| self.outputs[i].line(n).is_some())
+    }
+
+    /// `gl`: open the line under the cursor in the popup.
+    pub fn expand_line(&mut self) {
+        let Some((index, line)) = self.line_under_cursor() else {
+            return;
+        };
+        self.line_popup = Some(LinePopup {
+            output: self.outputs[index].id,
+            line,
+            top: 0,
+            rows: RefCell::default(),
+        });
+    }
+
+    /// Move the popup's view `by` rows.
+    pub fn scroll_line_popup(&mut self, by: isize) {
+        let (width, settings) = (self.width.max(1) as usize, self.output_settings());
+        let Some(popup) = self.line_popup.as_mut() else {
+            return;
+        };
+        let output = self.outputs.iter().find(|o| o.id == popup.output);
+        let Some(line) = output.and_then(|o| o.line(popup.line)) else {
+            return;
+        };
+        let to = popup.top.saturating_add_signed(by);
+        let rows = popup.rows.get_mut();
+        rows.find(line, width, settings, to + 1);
+        popup.top = to.min(rows.starts.len() - 1);
+    }
+}
+
+/// The popup, over the output pane `pane`.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    let (Some(popup), Some(line)) = (&model.line_popup, model.popup_line()) else {
+        return;
+    };
+    let inner = pane.inner(Margin::new(1, 1));
+    let (width, height) = (inner.width.max(1) as usize, inner.height as usize);
+    let settings = model.output_settings();
+    let mut rows = popup.rows.borrow_mut();
+    rows.find(line, width, settings, popup.top + height);
+    let shown: Vec<Line> = rows
+        .starts
+        .iter()
+        .skip(popup.top)
+        .take(height)
+        .map(|&start| Line::raw(display::row_at(line, start, width, settings).1))
+        .collect();
+    let title = format!(" line {}, {} ", popup.line + 1, format_size(line.len()));
+    let keys = " j/k Ctrl-d/Ctrl-u scroll, y yank, q close ";
+    frame.render_widget(Clear, pane);
+    frame.render_widget(
+        Paragraph::new(shown).block(
+            Block::bordered()
+                .title(title)
+                .title_bottom(Line::raw(keys).dim())
+                .border_style(model.config.theme.border(true)),
+        ),
+        pane,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rows_are_found_only_as_far_as_asked() {
+        let settings = display::Settings {
+            tab_stop: 8,
+            invisibles: false,
+            long_line: 4,
+        };
+        let line = "abcdefghij";
+        let mut rows = Rows::default();
+        rows.find(line, 4, settings, 2);
+        assert_eq!((rows.starts.as_slice(), rows.ended), (&[0, 4][..], false));
+        rows.find(line, 4, settings, 10);
+        assert_eq!((rows.starts.as_slice(), rows.ended), (&[0, 4, 8][..], true));
+        // the line isn't cut off in the popup
+        assert_eq!(display::row_at(line, 8, 4, settings), (10, "ij".into()));
+        // another width starts over
+        rows.find(line, 5, settings, 1);
+        assert_eq!(rows.starts, [0]);
+    }
+}
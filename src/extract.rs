@@ -0,0 +1,172 @@
+//! `gx`: take a command from the output line under the cursor and put it
+//! on the command line to look over, for the commands tools suggest
+//! ("run `cargo fix --lib -p shim` to apply"). A prompt in front (`$ `,
+//! `❯ `, `% `) is left out, and of a line of prose only what's in
+//! backticks is taken. On a line of a shell code fence the fence's
+//! commands are taken, joined with `&&`. With `run_from_output` set the
+//! command runs at once.
+
+use crate::{Message, Mode, Model, toast};
+
+/// Prompts put in front of commands in docs and transcripts.
+const PROMPTS: [&str; 3] = ["$ ", "❯ ", "% "];
+/// Code fences whose lines are taken as commands.
+const SHELLS: [&str; 7] = ["", "sh", "bash", "zsh", "fish", "shell", "console"];
+
+/// `line` without a prompt in front, if it had one.
+fn strip_prompt(line: &str) -> Option<&str> {
+    let line = line.trim_start();
+    PROMPTS
+        .iter()
+        .find_map(|p| line.strip_prefix(p))
+        .map(str::trim)
+}
+
+/// The command on `line`: after a prompt, in backticks, or else the line.
+fn strip(line: &str) -> Option<&str> {
+    let line = match strip_prompt(line) {
+        Some(command) => command,
+        None => {
+            let line = line.trim();
+            let quoted = line
+                .split_once('`')
+                .and_then(|(_, rest)| rest.split_once('`'))
+                .map(|(quoted, _)| quoted.trim())
+                .filter(|q| !q.is_empty());
+            match quoted {
+                Some(quoted) => strip_prompt(quoted).unwrap_or(quoted),
+                None => line,
+            }
+        }
+    };
+    (!line.is_empty()).then_some(line)
+}
+
+/// The lines opening and closing the code fence line `n` is in or on;
+/// no closing one if it isn't closed.
+fn fence(lines: &[&str], n: usize) -> Option<(usize, Option<usize>)> {
+    let mut open = None;
+    for (i, line) in lines.iter().enumerate() {
+        if !line.trim_start().starts_with("```") {
+            continue;
+        }
+        match open {
+            None if i > n => return None,
+            None => open = Some(i),
+            Some(start) if i >= n => return Some((start, Some(i))),
+            Some(_) => open = None,
+        }
+    }
+    open.map(|start| (start, None))
+}
+
+/// The command to take from line `n` of `lines`, or why there isn't one.
+fn command_at(lines: &[&str], n: usize) -> Result<String, String> {
+    let Some((start, end)) = fence(lines, n) else {
+        let line = lines.get(n).copied().unwrap_or("");
+        return strip(line)
+            .map(String::from)
+            .ok_or_else(|| "no command on this line".into());
+    };
+    let Some(end) = end else {
+        return Err("the code fence isn't closed".into());
+    };
+    let info = lines[start].trim().trim_start_matches('`').trim();
+    if !SHELLS.contains(&info) {
+        return Err(format!("not a shell code fence: {info}"));
+    }
+    let commands: Vec<&str> = lines[start + 1..end]
+        .iter()
+        .map(|l| strip_prompt(l).unwrap_or(l.trim()))
+        .filter(|l| !l.is_empty() && !l.starts_with('#'))
+        .collect();
+    if commands.iter().any(|c| c.ends_with('\\')) {
+        return Err("can't join a code fence with lines continued by \\".into());
+    }
+    match commands.is_empty() {
+        true => Err("the code fence is empty".into()),
+        false => Ok(commands.join(" && ")),
+    }
+}
+
+impl Model {
+    /// `gx`
+    pub fn take_command(&mut self) -> Option<Message> {
+        let Some((index, n)) = self.line_under_cursor() else {
+            self.status = Some("gx takes a command from a line of output".into());
+            return None;
+        };
+        let lines: Vec<&str> = self.outputs[index].shown_text().lines().collect();
+        match command_at(&lines, n) {
+            Ok(command) => {
+                self.current_command = command;
+                self.viewing_command = None;
+                self.mode = Mode::Insert;
+                self.cursor = self.command_end();
+                self.config.run_from_output.then_some(Message::Submit)
+            }
+            Err(e) => {
+                self.push_toast(toast::Level::Warn, e, toast::TTL);
+                None
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn prompts_and_backticks_are_left_out() {
+        assert_eq!(strip("$ cargo build"), Some("cargo build"));
+        assert_eq!(strip("  ❯  ls -la  "), Some("ls -la"));
+        assert_eq!(strip("% make"), Some("make"));
+        assert_eq!(
+            strip("run `cargo fix --lib -p shim` to apply"),
+            Some("cargo fix --lib -p shim")
+        );
+        assert_eq!(strip("`$ npm install`"), Some("npm install"));
+        // backticks in a command after a prompt are its own
+        assert_eq!(strip("$ echo `date`"), Some("echo `date`"));
+        assert_eq!(strip("    git status"), Some("git status"));
+        assert_eq!(strip("empty `` quotes"), Some("empty `` quotes"));
+        assert_eq!(strip("$ "), None);
+        assert_eq!(strip("   "), None);
+    }
+
+    #[test]
+    fn a_shell_fence_is_joined_with_and() {
+        let text = "To set up:\n```sh\n# fetch\n$ git pull\n\nmake install\n```\ndone";
+        let lines: Vec<_> = text.lines().collect();
+        for n in 1..=6 {
+            assert_eq!(
+                command_at(&lines, n).as_deref(),
+                Ok("git pull && make install")
+            );
+        }
+        assert_eq!(command_at(&lines, 0).as_deref(), Ok("To set up:"));
+        assert_eq!(command_at(&lines, 7).as_deref(), Ok("done"));
+    }
+
+    #[test]
+    fn fences_that_cant_be_run_are_refused() {
+        let cases = [
+            ("```rust\nfn main() {}\n```", "not a shell code fence: rust"),
+            ("```\n```", "the code fence is empty"),
+            ("```bash\nls", "the code fence isn't closed"),
+            (
+                "```\ndocker run \\\n  -it ubuntu\n```",
+                "can't join a code fence with lines continued by \\",
+            ),
+        ];
+        for (text, error) in cases {
+            let lines: Vec<_> = text.lines().collect();
+            assert_eq!(command_at(&lines, 1), Err(error.into()), "{text}");
+        }
+        // a line after a closed fence isn't in it
+        let lines = ["```", "ls", "```", "", "$ pwd"];
+        assert_eq!(command_at(&lines, 4).as_deref(), Ok("pwd"));
+        assert_eq!(command_at(&lines, 3), Err("no command on this line".into()));
+    }
+}
@@ -0,0 +1,198 @@
+//! Ctrl-T: pick files under the current directory to put on the command
+//! line, like fzf's. The directory is walked on its own thread, nearest
+//! files first, and the picker fills up as they're found.
+
+use std::{
+    collections::VecDeque,
+    env, fs,
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread,
+};
+
+use crate::{
+    Cursor, Model,
+    event::AppEvent,
+    glob,
+    picker::{Picker, PickerKind},
+    shell,
+};
+
+/// Directories deeper than this aren't looked in.
+const MAX_DEPTH: usize = 12;
+/// Stop after this many entries, so a huge tree can't fill memory.
+const MAX_ENTRIES: usize = 100_000;
+/// Entries sent to the picker at a time.
+const BATCH: usize = 500;
+
+/// A walk feeding the open file picker.
+#[derive(Debug)]
+pub struct Walk {
+    id: usize,
+    /// Set once the picker has gone, to stop the walk early.
+    cancel: Arc<AtomicBool>,
+}
+
+/// Names in the root `.gitignore`, as patterns matched against each
+/// directory and file name. Negations and patterns with a `/` in the
+/// middle aren't understood, and are left out.
+fn ignore_patterns(gitignore: &str) -> Vec<String> {
+    gitignore
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with(['#', '!']))
+        .map(|line| line.trim_start_matches('/').trim_end_matches('/'))
+        .filter(|pattern| !pattern.is_empty() && !pattern.contains('/'))
+        .map(String::from)
+        .collect()
+}
+
+fn ignored(patterns: &[String], name: &str) -> bool {
+    name == ".git" || patterns.iter().any(|p| glob::matches(p, name))
+}
+
+/// Paths under `root`, relative to it and breadth first, handed to `send`
+/// a batch at a time with whether it's the last. Stops when `send`
+/// returns false or `cancel` is set.
+fn walk(root: &Path, cancel: &AtomicBool, mut send: impl FnMut(Vec<String>, bool) -> bool) {
+    let patterns = fs::read_to_string(root.join(".gitignore"))
+        .map(|text| ignore_patterns(&text))
+        .unwrap_or_default();
+    let mut dirs = VecDeque::from([(PathBuf::new(), 0)]);
+    let mut batch = Vec::new();
+    let mut found = 0;
+    while let Some((dir, depth)) = dirs.pop_front() {
+        if cancel.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(entries) = fs::read_dir(root.join(&dir)) else {
+            continue;
+        };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|e| e.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if ignored(&patterns, &name) {
+                continue;
+            }
+            let path = dir.join(&name);
+            let mut shown = path.to_string_lossy().into_owned();
+            if entry.file_type().is_ok_and(|t| t.is_dir()) {
+                shown.push('/');
+                if depth < MAX_DEPTH {
+                    dirs.push_back((path, depth + 1));
+                }
+            }
+            batch.push(shown);
+            found += 1;
+            if found >= MAX_ENTRIES {
+                send(batch, true);
+                return;
+            }
+            if batch.len() >= BATCH && !send(std::mem::take(&mut batch), false) {
+                return;
+            }
+        }
+    }
+    send(batch, true);
+}
+
+impl Model {
+    /// Open the file picker and start walking the current directory.
+    pub fn open_file_picker(&mut self) {
+        let (Some(events), Ok(root)) = (self.events.clone(), env::current_dir()) else {
+            return;
+        };
+        if let Some(walk) = self.file_walk.take() {
+            walk.cancel.store(true, Ordering::Relaxed);
+        }
+        let id = self.next_walk;
+        self.next_walk += 1;
+        let cancel = Arc::new(AtomicBool::new(false));
+        self.file_walk = Some(Walk {
+            id,
+            cancel: cancel.clone(),
+        });
+        let mut picker = Picker::new(PickerKind::Files, Vec::new());
+        picker.source = Some("walking");
+        self.picker = Some(picker);
+        thread::spawn(move || {
+            walk(&root, &cancel, |paths, done| {
+                events
+                    .send(AppEvent::Files {
+                        walk: id,
+                        paths,
+                        done,
+                    })
+                    .is_ok()
+            });
+        });
+    }
+
+    /// Add paths found by walk `id` to the file picker, or stop the walk if
+    /// the picker has been closed since.
+    pub fn receive_files(&mut self, id: usize, paths: Vec<String>, done: bool) {
+        let Some(walk) = self.file_walk.as_ref().filter(|w| w.id == id) else {
+            return;
+        };
+        let picker = self.picker.as_mut().filter(|p| p.kind == PickerKind::Files);
+        let Some(picker) = picker else {
+            walk.cancel.store(true, Ordering::Relaxed);
+            self.file_walk = None;
+            return;
+        };
+        picker.extend(paths);
+        if done {
+            picker.source = None;
+            self.file_walk = None;
+        }
+    }
+
+    /// Put the paths chosen in the file picker at the cursor, quoted where
+    /// need be, with a space after.
+    pub fn insert_paths(&mut self, paths: &[&str]) {
+        if paths.is_empty() {
+            return;
+        }
+        let mut text = paths
+            .iter()
+            .map(|p| shell::quote(p))
+            .collect::<Vec<_>>()
+            .join(" ");
+        text.push(' ');
+        self.insert_text(&text);
+    }
+
+    /// Put `text` in the command at the cursor, or at the end from the
+    /// output, leaving the cursor after it. Returns where it went.
+    pub fn insert_text(&mut self, text: &str) -> Range<usize> {
+        self.edit_recalled();
+        let offset = match self.cursor {
+            Cursor::CommandLine(x, y) => self.command_offset(x, y),
+            Cursor::OutputBuffer(_, _) => self.current_command.len(),
+        };
+        self.current_command.insert_str(offset, text);
+        let end = offset + text.len();
+        self.put_cursor_at(end);
+        offset..end
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gitignore_names_are_left_out() {
+        let patterns = ignore_patterns("# build output\n/target/\n*.log\n!keep.log\nsrc/gen\n\n");
+        assert_eq!(patterns, ["target", "*.log"]);
+        assert!(ignored(&patterns, "target"));
+        assert!(ignored(&patterns, "debug.log"));
+        assert!(ignored(&patterns, ".git"));
+        assert!(!ignored(&patterns, "src"));
+    }
+}
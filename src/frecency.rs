@@ -0,0 +1,221 @@
+//! Ranking of past commands by how often and how recently they were run,
+//! kept up to date as commands are submitted and finish.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    fmt::Write,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    Model, config, history, import, lock,
+    picker::{Picker, PickerKind},
+};
+
+#[derive(Debug, Default, Clone, Copy)]
+struct Entry {
+    uses: u32,
+    /// Seconds since the epoch.
+    last_used: u64,
+    /// Runs that exited nonzero.
+    failures: u32,
+}
+
+#[derive(Debug, Default)]
+pub struct Frecency {
+    entries: HashMap<String, Entry>,
+}
+
+/// Kept next to the history as `uses, last used, failures, command` lines.
+fn path() -> Option<PathBuf> {
+    Some(config::dir()?.join("history.frecency"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// The entries of a frecency file.
+fn parse(contents: &str) -> HashMap<String, Entry> {
+    let mut entries = HashMap::new();
+    for line in contents.lines() {
+        let mut fields = line.splitn(4, '\t');
+        let mut number = || fields.next().and_then(|f| f.parse().ok());
+        let (Some(uses), Some(last_used), Some(failures)) = (number(), number(), number()) else {
+            continue;
+        };
+        if let Some(command) = fields.next() {
+            let entry = Entry {
+                uses: uses as u32,
+                last_used,
+                failures: failures as u32,
+            };
+            entries.insert(history::unescape(command), entry);
+        }
+    }
+    entries
+}
+
+fn to_file(entries: &HashMap<String, Entry>) -> String {
+    let mut contents = String::new();
+    for (command, entry) in entries {
+        let _ = writeln!(
+            contents,
+            "{}\t{}\t{}\t{}",
+            entry.uses,
+            entry.last_used,
+            entry.failures,
+            history::escape(command)
+        );
+    }
+    contents
+}
+
+impl Frecency {
+    pub fn load() -> Self {
+        let contents = path().and_then(|p| lock::read(&p).ok());
+        Frecency {
+            entries: contents.map_or_else(HashMap::new, |c| parse(&c)),
+        }
+    }
+
+    /// Make `change` to the entries as the file has them, as other shims
+    /// may have changed it since, and write them back.
+    fn update(&mut self, change: impl FnOnce(&mut HashMap<String, Entry>)) {
+        let mut change = Some(change);
+        if let Some(path) = path() {
+            let _ = lock::rewrite(&path, |contents| {
+                self.entries = parse(contents);
+                if let Some(change) = change.take() {
+                    change(&mut self.entries);
+                }
+                to_file(&self.entries)
+            });
+        }
+        // no file, or it couldn't be opened
+        if let Some(change) = change {
+            change(&mut self.entries);
+        }
+    }
+
+    pub fn used(&mut self, command: &str) {
+        self.update(|entries| {
+            let entry = entries.entry(command.to_string()).or_default();
+            entry.uses += 1;
+            entry.last_used = now();
+        });
+    }
+
+    /// Rank commands brought in from another shell's history by how often
+    /// and when they were run there. Those without a time aren't ranked,
+    /// and ones shim has run itself keep their own ranking.
+    pub fn import(&mut self, commands: &[import::Command]) {
+        self.update(|entries| {
+            for command in commands {
+                let Some(last_used) = command.time else {
+                    continue;
+                };
+                entries.entry(command.text.clone()).or_insert(Entry {
+                    uses: command.uses,
+                    last_used,
+                    failures: 0,
+                });
+            }
+        });
+    }
+
+    pub fn finished(&mut self, command: &str, status: Option<i32>) {
+        if status != Some(0) {
+            self.update(|entries| {
+                if let Some(entry) = entries.get_mut(command) {
+                    entry.failures = (entry.failures + 1).min(entry.uses);
+                }
+            });
+        }
+    }
+
+    /// Uses weighted by how long ago the last one was, and with
+    /// `penalize_failures` by the share of runs that succeeded.
+    fn score(entry: &Entry, now: u64, penalize_failures: bool) -> f64 {
+        let age = now.saturating_sub(entry.last_used);
+        let recency = match age {
+            0..3600 => 4.0,
+            3600..86400 => 2.0,
+            86400..604800 => 0.5,
+            _ => 0.25,
+        };
+        let mut score = entry.uses as f64 * recency;
+        if penalize_failures {
+            score *= (entry.uses - entry.failures + 1) as f64 / (entry.uses + 1) as f64;
+        }
+        score
+    }
+
+    /// Every command, best first.
+    pub fn ranked(&self, penalize_failures: bool) -> Vec<&str> {
+        let now = now();
+        let mut ranked: Vec<_> = self
+            .entries
+            .iter()
+            .map(|(command, entry)| (Self::score(entry, now, penalize_failures), command))
+            .collect();
+        ranked.sort_by(|a, b| b.0.total_cmp(&a.0).then_with(|| a.1.cmp(b.1)));
+        ranked.into_iter().map(|(_, c)| c.as_str()).collect()
+    }
+
+    /// The best command that starts with, and is longer than, `prefix`.
+    pub fn complete(&self, prefix: &str, penalize_failures: bool) -> Option<&str> {
+        let now = now();
+        self.entries
+            .iter()
+            .filter(|(command, _)| command.len() > prefix.len() && command.starts_with(prefix))
+            .max_by(|a, b| {
+                let score = |e| Self::score(e, now, penalize_failures);
+                score(a.1).total_cmp(&score(b.1)).then_with(|| b.0.cmp(a.0))
+            })
+            .map(|(command, _)| command.as_str())
+    }
+}
+
+impl Model {
+    /// Past commands best first, or only the best `limit` of them.
+    pub fn open_history_picker(&mut self, limit: Option<usize>) {
+        let ranked = self.frecency.ranked(self.config.penalize_failures);
+        let here = match (self.history_here, env::current_dir()) {
+            (true, Ok(dir)) => Some(
+                self.previous_commands
+                    .iter()
+                    .filter(|e| e.ran_under(&dir))
+                    .map(|e| e.command.as_str())
+                    .collect::<HashSet<_>>(),
+            ),
+            _ => None,
+        };
+        // then what the history backend has that shim hasn't run, newest
+        // first
+        let ranked_set: HashSet<_> = ranked.iter().copied().collect();
+        let mut seen = HashSet::new();
+        let rest = self
+            .previous_commands
+            .iter()
+            .rev()
+            .map(|e| e.command.as_str())
+            .filter(|c| !c.trim().is_empty() && !ranked_set.contains(c) && seen.insert(*c));
+        let items = ranked
+            .iter()
+            .copied()
+            .chain(rest)
+            .filter(|c| here.as_ref().is_none_or(|here| here.contains(c)))
+            .take(limit.unwrap_or(usize::MAX))
+            .map(String::from)
+            .collect();
+        let mut picker = Picker::new(PickerKind::History, items);
+        picker.source = Some(self.history.name());
+        picker.limit = limit;
+        self.picker = Some(picker);
+    }
+}
@@ -0,0 +1,290 @@
+//! Fuzzy matching for the pickers, scored the way fzf and skim do it: each
+//! matched character scores, more so at the start of a word or path part
+//! and in a run of consecutive matches, and gaps between matches cost a
+//! little. Case is ignored.
+//!
+//! `cargo test --release -- --ignored` times matching 100k paths.
+
+use std::cell::RefCell;
+
+const MATCH: i32 = 16;
+const GAP_START: i32 = -3;
+const GAP_EXTENSION: i32 = -1;
+/// A match at the start of a word.
+const BOUNDARY: i32 = MATCH / 2;
+/// After a blank, or at the very start.
+const BOUNDARY_WHITE: i32 = BOUNDARY + 2;
+/// After a `/`, `:`, `,`, `;` or `|`.
+const BOUNDARY_DELIMITER: i32 = BOUNDARY + 1;
+/// An upper-case letter after a lower-case one, or a digit after a
+/// non-digit.
+const CAMEL: i32 = BOUNDARY - 1;
+/// Each match in a run after the first, so a run is worth as much as not
+/// leaving a gap.
+const CONSECUTIVE: i32 = -(GAP_START + GAP_EXTENSION);
+/// The first character of the query counts double where it lands.
+const FIRST_CHAR: i32 = 2;
+
+const NONE: i32 = i32::MIN / 2;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    White,
+    Delimiter,
+    NonWord,
+    Lower,
+    Upper,
+    Letter,
+    Number,
+}
+
+fn class(c: char) -> Class {
+    match c {
+        _ if c.is_whitespace() => Class::White,
+        '/' | ':' | ',' | ';' | '|' => Class::Delimiter,
+        _ if c.is_lowercase() => Class::Lower,
+        _ if c.is_uppercase() => Class::Upper,
+        _ if c.is_numeric() => Class::Number,
+        _ if c.is_alphabetic() => Class::Letter,
+        _ => Class::NonWord,
+    }
+}
+
+/// What matching a character of class `current` after one of `previous`
+/// is worth.
+fn bonus(previous: Class, current: Class) -> i32 {
+    use Class::*;
+    match (previous, current) {
+        (_, White) => BOUNDARY_WHITE,
+        (_, Delimiter | NonWord) => BOUNDARY,
+        (White, _) => BOUNDARY_WHITE,
+        (Delimiter, _) => BOUNDARY_DELIMITER,
+        (NonWord, _) => BOUNDARY,
+        (Lower, Upper) => CAMEL,
+        (previous, Number) if previous != Number => CAMEL,
+        _ => 0,
+    }
+}
+
+fn fold(c: char) -> char {
+    match c.is_ascii() {
+        true => c.to_ascii_lowercase(),
+        false => c.to_lowercase().next().unwrap_or(c),
+    }
+}
+
+/// Tables for `score`, kept between calls so scoring a long list doesn't
+/// allocate for each item.
+#[derive(Default)]
+struct Scratch {
+    chars: Vec<char>,
+    bonuses: Vec<i32>,
+    scores: Vec<i32>,
+    runs: Vec<i32>,
+    from: Vec<usize>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+/// How well `needle` matches `haystack`, if its characters are all in
+/// there in order, with the positions (counting characters) of the best
+/// way they can be matched. Higher is better; an empty needle matches
+/// everything with a score of 0.
+pub fn score(needle: &str, haystack: &str) -> Option<(i32, Vec<usize>)> {
+    let needle: Vec<char> = needle.chars().map(fold).collect();
+    if needle.is_empty() {
+        return Some((0, Vec::new()));
+    }
+    // most candidates fail here, before anything is allocated for them
+    let mut rest = haystack.chars().map(fold);
+    if !needle.iter().all(|&n| rest.any(|c| c == n)) {
+        return None;
+    }
+    SCRATCH.with_borrow_mut(|scratch| best_match(&needle, haystack, scratch))
+}
+
+fn best_match(needle: &[char], haystack: &str, scratch: &mut Scratch) -> Option<(i32, Vec<usize>)> {
+    let Scratch {
+        chars,
+        bonuses,
+        scores,
+        runs,
+        from,
+    } = scratch;
+    chars.clear();
+    bonuses.clear();
+    let mut previous = Class::White;
+    for c in haystack.chars() {
+        let current = class(c);
+        bonuses.push(bonus(previous, current));
+        chars.push(fold(c));
+        previous = current;
+    }
+    let (n, m) = (needle.len(), chars.len());
+    // the best score with needle[..=i] matched and needle[i] at j, the
+    // bonus of the run it's in, and where needle[i - 1] went
+    scores.clear();
+    scores.resize(n * m, NONE);
+    runs.clear();
+    runs.resize(n * m, 0);
+    from.clear();
+    from.resize(n * m, 0);
+    // needle[i] can't go before needle[..i] has had room
+    let mut first = chars.iter().position(|&c| c == needle[0])?;
+    for j in first..m {
+        if chars[j] == needle[0] {
+            scores[j] = MATCH + bonuses[j] * FIRST_CHAR;
+            runs[j] = bonuses[j];
+        }
+    }
+    for i in 1..n {
+        let (above, row) = scores.split_at_mut(i * m);
+        let above = &above[(i - 1) * m..];
+        // the best match of needle[i - 1] left of j - 1, less the gap
+        let mut gap = (NONE, 0);
+        let start = first + 1;
+        first = m;
+        for j in start..m {
+            if j >= 2 && above[j - 2] > NONE {
+                let start = above[j - 2] + GAP_START;
+                if start >= gap.0 + GAP_EXTENSION {
+                    gap = (start, j - 2);
+                } else {
+                    gap.0 += GAP_EXTENSION;
+                }
+            } else if gap.0 > NONE {
+                gap.0 += GAP_EXTENSION;
+            }
+            if chars[j] != needle[i] {
+                continue;
+            }
+            let at = i * m + j;
+            let run = runs[at - m - 1].max(CONSECUTIVE).max(bonuses[j]);
+            let consecutive = match above[j - 1] > NONE {
+                true => above[j - 1] + MATCH + run,
+                false => NONE,
+            };
+            let gapped = match gap.0 > NONE {
+                true => gap.0 + MATCH + bonuses[j],
+                false => NONE,
+            };
+            if consecutive >= gapped && consecutive > NONE {
+                row[j] = consecutive;
+                runs[at] = run;
+                from[at] = j - 1;
+            } else if gapped > NONE {
+                row[j] = gapped;
+                runs[at] = bonuses[j];
+                from[at] = gap.1;
+            } else {
+                continue;
+            }
+            first = first.min(j);
+        }
+    }
+    let last = &scores[(n - 1) * m..];
+    let (mut j, &best) = last.iter().enumerate().rev().max_by_key(|(_, s)| **s)?;
+    if best <= NONE {
+        return None;
+    }
+    let mut positions = vec![0; n];
+    for i in (0..n).rev() {
+        positions[i] = j;
+        j = from[i * m + j];
+    }
+    Some((best, positions))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranked<'a>(needle: &str, haystacks: &[&'a str]) -> Vec<&'a str> {
+        let mut scored: Vec<_> = haystacks
+            .iter()
+            .filter_map(|h| Some((score(needle, h)?.0, *h)))
+            .collect();
+        scored.sort_by_key(|(score, _)| -score);
+        scored.into_iter().map(|(_, h)| h).collect()
+    }
+
+    #[test]
+    fn matches_in_order_ignoring_case() {
+        assert_eq!(score("fb", "foo_bar").unwrap().1, [0, 4]);
+        assert_eq!(score("FB", "foo_bar").unwrap().1, [0, 4]);
+        assert_eq!(score("ab", "ba"), None);
+        assert_eq!(score("", "anything"), Some((0, Vec::new())));
+        // the later, consecutive `main` beats the scattered one
+        assert_eq!(
+            score("main", "my animal/main.rs").unwrap().1,
+            [10, 11, 12, 13]
+        );
+    }
+
+    #[test]
+    fn paths_rank_by_word_starts_and_runs() {
+        let paths = [
+            "tests/pick_errors.rs",
+            "src/complete.rs",
+            "src/picker.rs",
+            "docs/topic/kernel.md",
+        ];
+        assert_eq!(
+            ranked("picker", &paths),
+            [
+                "src/picker.rs",
+                "tests/pick_errors.rs",
+                "docs/topic/kernel.md"
+            ]
+        );
+        assert_eq!(ranked("srccomp", &paths), ["src/complete.rs"]);
+        assert_eq!(
+            ranked("fb", &["xfoobar", "foo_bar", "FooBar"]),
+            ["foo_bar", "FooBar", "xfoobar"]
+        );
+    }
+
+    #[test]
+    fn commands_rank_by_word_starts_and_runs() {
+        let commands = [
+            "less /var/log/syslog",
+            "git log --oneline",
+            "git clone origin",
+            "echo hello",
+        ];
+        assert_eq!(
+            ranked("log", &commands),
+            [
+                "git log --oneline",
+                "less /var/log/syslog",
+                "git clone origin"
+            ]
+        );
+        assert_eq!(
+            ranked("gl", &commands),
+            [
+                "git log --oneline",
+                "git clone origin",
+                "less /var/log/syslog"
+            ]
+        );
+    }
+
+    #[test]
+    #[ignore = "a benchmark; run with --release"]
+    fn matching_100k_paths() {
+        let paths: Vec<String> = (0..100_000)
+            .map(|i| format!("src/module_{}/sub{}/file_{i}.rs", i % 97, i % 13))
+            .collect();
+        let start = std::time::Instant::now();
+        for needle in ["f", "fi", "fil", "file1", "mod5sub3"] {
+            let found = paths.iter().filter_map(|p| score(needle, p)).count();
+            assert!(found > 0);
+        }
+        let each = start.elapsed() / 5;
+        println!("{each:?} a keystroke over 100k paths");
+        assert!(each.as_millis() < 100, "{each:?}");
+    }
+}
@@ -0,0 +1,137 @@
+use std::{fs, path::Path};
+
+/// Whether `word` holds an unescaped `*`, `?` or `[`.
+pub fn is_pattern(word: &str) -> bool {
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// Paths under `cwd` matching `pattern`, sorted. Matches are relative unless
+/// the pattern is absolute.
+pub fn expand(pattern: &str, cwd: &Path) -> Vec<String> {
+    // Windows takes `/` too, and a bare `\\` there arrives escaped
+    let pattern = if cfg!(windows) {
+        pattern.replace("\\\\", "/")
+    } else {
+        pattern.to_string()
+    };
+    let pattern = pattern.as_str();
+    let (mut found, segments) = match pattern.strip_prefix('/') {
+        Some(rest) => (vec![String::from("/")], rest),
+        None => (vec![String::new()], pattern),
+    };
+    for segment in segments.split('/').filter(|s| !s.is_empty()) {
+        let mut next = Vec::new();
+        for prefix in &found {
+            if !is_pattern(segment) {
+                let path = format!("{prefix}{}", unescape(segment));
+                if cwd.join(&path).exists() {
+                    next.push(path);
+                }
+                continue;
+            }
+            let Ok(entries) = fs::read_dir(cwd.join(if prefix.is_empty() { "." } else { prefix }))
+            else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                let Some(name) = entry.file_name().to_str().map(String::from) else {
+                    continue;
+                };
+                // like sh, wildcards don't match hidden files
+                if name.starts_with('.') && !segment.starts_with('.') {
+                    continue;
+                }
+                if matches(segment, &name) {
+                    next.push(format!("{prefix}{name}"));
+                }
+            }
+        }
+        found = next.into_iter().map(|p| p + "/").collect();
+    }
+    let mut found: Vec<String> = found
+        .into_iter()
+        .map(|p| p.trim_end_matches('/').to_string())
+        .filter(|p| !p.is_empty())
+        .collect();
+    found.sort();
+    found
+}
+
+/// Match a single path component against a pattern of `*`, `?`, `[...]`
+/// and `\` escapes.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let name: Vec<char> = name.chars().collect();
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|i| match_from(&pattern[1..], &name[i..])),
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => {
+            let Some(end) = pattern
+                .iter()
+                .skip(2)
+                .position(|c| *c == ']')
+                .map(|p| p + 2)
+            else {
+                return name.first() == Some(&'[') && match_from(&pattern[1..], &name[1..]);
+            };
+            let Some(c) = name.first() else {
+                return false;
+            };
+            let (negate, class) = match pattern[1] {
+                '!' | '^' => (true, &pattern[2..end]),
+                _ => (false, &pattern[1..end]),
+            };
+            in_class(class, *c) != negate && match_from(&pattern[end + 1..], &name[1..])
+        }
+        Some('\\') if pattern.len() > 1 => {
+            name.first() == Some(&pattern[1]) && match_from(&pattern[2..], &name[1..])
+        }
+        Some(p) => name.first() == Some(p) && match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+fn in_class(class: &[char], c: char) -> bool {
+    let mut i = 0;
+    while i < class.len() {
+        if i + 2 < class.len() && class[i + 1] == '-' {
+            if (class[i]..=class[i + 2]).contains(&c) {
+                return true;
+            }
+            i += 3;
+        } else {
+            if class[i] == c {
+                return true;
+            }
+            i += 1;
+        }
+    }
+    false
+}
+
+/// Drop the backslashes protecting quoted wildcards.
+pub fn unescape(word: &str) -> String {
+    let mut out = String::with_capacity(word.len());
+    let mut chars = word.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,101 @@
+//! Alt-. takes text from output into the command line. On the command
+//! line it's a line of the latest buffer: the last one, or line N with a
+//! count (`3` then Alt-. in normal mode, Alt-3 Alt-. while typing), like
+//! bash's insert-last-argument but for lines of output. With the cursor
+//! in the output it's the word under the cursor, and typing goes on after
+//! it. Either is quoted if need be, and taken from the output as shown,
+//! through its `:pipe` if it has one.
+
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{Cursor, Mode, Model};
+
+/// Line `n`, counting from 1, or the last that isn't blank, trimmed.
+fn nth_line(text: &str, n: Option<usize>) -> Option<&str> {
+    let line = match n {
+        Some(n) => text.lines().nth(n.checked_sub(1)?),
+        None => text.lines().rev().find(|l| !l.trim().is_empty()),
+    };
+    line.map(str::trim).filter(|l| !l.is_empty())
+}
+
+/// The blank-separated word of `line` that cursor position `index` is in.
+fn word_at(line: &str, index: usize) -> Option<&str> {
+    let (at, grapheme) = line.grapheme_indices(true).nth(index)?;
+    if grapheme.trim().is_empty() {
+        return None;
+    }
+    let start = line[..at]
+        .char_indices()
+        .rfind(|(_, c)| c.is_whitespace())
+        .map_or(0, |(i, c)| i + c.len_utf8());
+    let end = line[at..]
+        .find(char::is_whitespace)
+        .map_or(line.len(), |i| at + i);
+    Some(&line[start..end])
+}
+
+impl Model {
+    /// Alt-., with the count given before it if there was one.
+    pub fn insert_from_output(&mut self, count: Option<usize>) {
+        match self.cursor {
+            Cursor::CommandLine(_, _) => self.insert_output_line(count),
+            Cursor::OutputBuffer(x, y) => self.insert_output_word(x, y),
+        }
+    }
+
+    fn insert_output_line(&mut self, n: Option<usize>) {
+        let latest = self.outputs.iter().rev().find(|o| !o.transient);
+        let Some(line) = latest.and_then(|o| nth_line(o.shown_text(), n)) else {
+            self.status = Some(match n {
+                Some(n) => format!("no line {n} in the last output"),
+                None => "no output to take a line from".into(),
+            });
+            return;
+        };
+        let line = line.to_string();
+        self.insert_paths(&[&line]);
+    }
+
+    fn insert_output_word(&mut self, x: u16, y: u16) {
+        let Some(word) = self.output_line(y).and_then(|l| word_at(l, x as usize)) else {
+            self.status = Some("no word under the cursor".into());
+            return;
+        };
+        let word = word.to_string();
+        self.edit_recalled();
+        if !self.current_command.is_empty() && !self.current_command.ends_with(char::is_whitespace)
+        {
+            self.current_command.push(' ');
+        }
+        self.insert_paths(&[&word]);
+        self.mode = Mode::Insert;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_count_from_one_and_default_to_the_last() {
+        let text = "src/main.rs\nmy notes.txt\n\n  \n";
+        assert_eq!(nth_line(text, None), Some("my notes.txt"));
+        assert_eq!(nth_line(text, Some(1)), Some("src/main.rs"));
+        assert_eq!(nth_line(text, Some(3)), None);
+        assert_eq!(nth_line(text, Some(9)), None);
+        assert_eq!(nth_line(text, Some(0)), None);
+        assert_eq!(nth_line("", None), None);
+    }
+
+    #[test]
+    fn the_word_under_the_cursor_goes_to_the_blanks() {
+        let line = "-rw-r--r-- 1 me 日本/語.txt  end";
+        assert_eq!(word_at(line, 0), Some("-rw-r--r--"));
+        assert_eq!(word_at(line, 17), Some("日本/語.txt"));
+        assert_eq!(word_at(line, 21), Some("日本/語.txt"));
+        assert_eq!(word_at(line, 24), None);
+        assert_eq!(word_at(line, 27), Some("end"));
+        assert_eq!(word_at(line, 40), None);
+    }
+}
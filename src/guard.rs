@@ -0,0 +1,114 @@
+//! Asking before running commands that are easy to regret.
+
+use std::env;
+
+use regex::Regex;
+
+use crate::{Model, builtin, shell, vars};
+
+/// Typed before a command to run it without asking.
+pub const UNCHECKED: &str = "!!";
+
+/// Patterns for commands that destroy a lot with one typo. They are
+/// matched against the expanded command, so `~` has become `$HOME`, but
+/// before globs are matched, so `~/*` is still that.
+pub fn defaults() -> Vec<Regex> {
+    let home = env::var("HOME")
+        .ok()
+        .filter(|h| !h.is_empty())
+        .map_or(String::new(), |h| {
+            format!("|{}", regex::escape(h.trim_end_matches('/')))
+        });
+    [
+        // recursive rm of / or the home directory, or all that's in them
+        format!(
+            r"(^|[\s;&|(])rm\s+(\S+\s+)*-[a-zA-Z]*[rR]\S*\s+(\S+\s+)*(/{home})/*(\.?\*)?(\s|$)"
+        ),
+        // force pushes to main or master, whichever order the arguments are in
+        r"(^|[\s;&|(])git\s+push(\s.*)?\s(-f|--force\S*)(\s.*)?\s\+?(\S+:)?(main|master)(\s|$)"
+            .into(),
+        r"(^|[\s;&|(])git\s+push\s(.*\s)?\+?(\S+:)?(main|master)\s(.*\s)?(-f|--force\S*)(\s|$)"
+            .into(),
+        r"(^|[\s;&|(])git\s+push\s(.*\s)?\+(\S+:)?(main|master)(\s|$)".into(),
+        // dd onto a whole disk
+        r"(^|[\s;&|(])dd\s(.*\s)?of=/dev/(sd|hd|vd|nvme|mmcblk|disk)".into(),
+    ]
+    .into_iter()
+    .map(|pattern| Regex::new(&pattern).expect("default confirmation patterns are valid"))
+    .collect()
+}
+
+impl Model {
+    /// Whether `command` should be confirmed before it runs: it matches
+    /// one of the `dangerous` patterns once expanded.
+    pub fn is_dangerous(&self, command: &str) -> bool {
+        if !self.config.confirm_dangerous
+            || command.trim_start().starts_with(UNCHECKED)
+            || builtin::lookup(command).is_some()
+        {
+            return false;
+        }
+        let line = vars::split_capture(command).map_or(command, |(_, line)| line);
+        let expanded = shell::unglobbed(line, |name| self.var(name)).join(" ");
+        self.config.dangerous.iter().any(|p| p.is_match(&expanded))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dangerous(command: &str) -> bool {
+        let mut model = Model::default();
+        model.config.confirm_dangerous = true;
+        model.config.dangerous = defaults();
+        model.is_dangerous(command)
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn removing_everything_is_asked_about() {
+        assert!(dangerous("rm -rf /"));
+        assert!(dangerous("rm -rf /*"));
+        assert!(dangerous("rm -fr ~"));
+        assert!(dangerous("rm -rf ~/"));
+        // the stray space
+        assert!(dangerous("rm -rf ~/ src"));
+        // globs aren't matched before looking
+        assert!(dangerous("rm -rf ~/*"));
+        assert!(dangerous("rm -rf ~/.*"));
+        assert!(dangerous("sudo rm -r --no-preserve-root /"));
+        assert!(!dangerous("rm -rf ~/src"));
+        assert!(!dangerous("rm -rf /tmp/build"));
+        assert!(!dangerous("rm ~/*"));
+    }
+
+    #[test]
+    fn force_pushes_to_main_are_asked_about() {
+        assert!(dangerous("git push -f origin main"));
+        assert!(dangerous("git push origin master --force"));
+        assert!(dangerous("git push --force-with-lease origin HEAD:main"));
+        assert!(dangerous("git push origin +main"));
+        assert!(!dangerous("git push -f origin feature"));
+        assert!(!dangerous("git push origin main"));
+        assert!(!dangerous("git push -f origin mainline"));
+    }
+
+    #[test]
+    fn dd_onto_a_disk_is_asked_about() {
+        assert!(dangerous("dd if=image.iso of=/dev/sda bs=4M"));
+        assert!(dangerous("dd of=/dev/nvme0n1 if=/dev/zero"));
+        assert!(!dangerous("dd if=/dev/sda of=disk.img"));
+        assert!(!dangerous("dd if=/dev/zero of=/tmp/blank"));
+    }
+
+    #[test]
+    fn some_lines_are_never_asked_about() {
+        assert!(!dangerous("!!rm -rf /"));
+        assert!(!dangerous(":title rm -rf /"));
+        let mut model = Model::default();
+        model.config.confirm_dangerous = false;
+        model.config.dangerous = defaults();
+        assert!(!model.is_dangerous("rm -rf /"));
+    }
+}
@@ -0,0 +1,383 @@
+//! `:checkhealth`: what shim found of the things it can do without, for
+//! when one of them doesn't work. Each check is OK, WARN or FAIL with what
+//! to do about it. Nothing here may fail itself: whatever can't be read
+//! is reported rather than unwrapped.
+
+use std::{env, fs, io, path::Path};
+
+use ratatui::style::Color;
+
+use crate::{Model, config, history, shell};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Level {
+    Ok,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, PartialEq)]
+struct Check {
+    level: Level,
+    item: &'static str,
+    detail: String,
+    /// What to do about it, unless it's OK.
+    remedy: Option<String>,
+}
+
+fn ok(item: &'static str, detail: impl Into<String>) -> Check {
+    Check {
+        level: Level::Ok,
+        item,
+        detail: detail.into(),
+        remedy: None,
+    }
+}
+
+fn warn(item: &'static str, detail: impl Into<String>, remedy: impl Into<String>) -> Check {
+    Check {
+        level: Level::Warn,
+        item,
+        detail: detail.into(),
+        remedy: Some(remedy.into()),
+    }
+}
+
+fn fail(item: &'static str, detail: impl Into<String>, remedy: impl Into<String>) -> Check {
+    Check {
+        level: Level::Fail,
+        ..warn(item, detail, remedy)
+    }
+}
+
+fn var(name: &str) -> Option<String> {
+    env::var(name).ok().filter(|v| !v.is_empty())
+}
+
+/// Whether `path`, or the directory it would be made in, can be written
+/// to, as far as its permissions say.
+fn writable(path: &Path) -> Result<(), String> {
+    match fs::metadata(path) {
+        Ok(meta) if meta.is_dir() && meta.permissions().readonly() => Err("read-only".into()),
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => match path.parent() {
+            Some(parent) if parent != Path::new("") => writable(parent),
+            _ => Err("doesn't exist".into()),
+        },
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// The config file at `path`, having been read as `read`.
+fn config_checks(path: Option<&Path>, read: io::Result<String>) -> Vec<Check> {
+    let Some(path) = path else {
+        return vec![warn(
+            "config",
+            "no config directory, as neither XDG_CONFIG_HOME nor HOME is set",
+            "set HOME, or XDG_CONFIG_HOME",
+        )];
+    };
+    let shown = path.display();
+    let contents = match read {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            return vec![ok("config", format!("no {shown}; using the defaults"))];
+        }
+        Err(e) => {
+            return vec![fail(
+                "config",
+                format!("can't read {shown}: {e}"),
+                "check the file's permissions",
+            )];
+        }
+    };
+    let (_, problems) = config::Config::parse(&contents);
+    if problems.is_empty() {
+        return vec![ok("config", format!("{shown} read"))];
+    }
+    problems
+        .into_iter()
+        .map(|(line, problem)| {
+            warn(
+                "config",
+                format!("{shown} line {line}: {problem}"),
+                "fix the line or remove it; it's ignored",
+            )
+        })
+        .collect()
+}
+
+/// Whether `program` can be found, by itself or on PATH.
+fn found(program: &str) -> Option<String> {
+    shell::resolve(program, var)
+}
+
+impl Model {
+    fn checks(&self) -> Vec<Check> {
+        let path = config::path();
+        let read = path
+            .as_ref()
+            .map_or_else(|| Err(io::ErrorKind::NotFound.into()), fs::read_to_string);
+        let mut checks = config_checks(path.as_deref(), read);
+        checks.push(match config::dir() {
+            Some(dir) => match writable(&dir) {
+                Ok(()) => ok("files", format!("{} can be written", dir.display())),
+                Err(e) => warn(
+                    "files",
+                    format!(
+                        "{}: {e}; snippets, abbreviations and env answers won't be kept",
+                        dir.display()
+                    ),
+                    "make the directory writable",
+                ),
+            },
+            None => warn(
+                "files",
+                "nowhere to keep snippets, abbreviations and env answers",
+                "set HOME",
+            ),
+        });
+        checks.extend(self.terminal_checks());
+        checks.extend(self.program_checks());
+        checks.push(self.history_check());
+        let cwd = env::current_dir().map_err(|e| e.to_string());
+        checks.push(match cwd.and_then(|dir| writable(&dir).map(|()| dir)) {
+            Ok(dir) => ok(
+                "cwd",
+                format!("{} can be written (:session, :record)", dir.display()),
+            ),
+            Err(e) => warn(
+                "cwd",
+                format!("current directory: {e}"),
+                "give :session and :record a path elsewhere",
+            ),
+        });
+        checks
+    }
+
+    fn terminal_checks(&self) -> Vec<Check> {
+        let term = var("TERM").unwrap_or_else(|| "(unset)".into());
+        let mut checks = vec![match self.terminal.alternate_screen {
+            true => ok("terminal", format!("TERM={term}, alternate screen")),
+            false => warn(
+                "terminal",
+                format!("TERM={term} has no alternate screen; the shell's screen is drawn over"),
+                "set TERM to what the terminal is, e.g. xterm-256color",
+            ),
+        }];
+        checks.push(match self.terminal.clipboard {
+            true => ok(
+                "clipboard",
+                "copying to the system clipboard through OSC 52",
+            ),
+            false => warn(
+                "clipboard",
+                format!("TERM={term} has no OSC 52; \"+ and clipboard = true do nothing"),
+                "use a terminal with OSC 52, or tmux with set-clipboard on",
+            ),
+        });
+        let images = self.terminal.images;
+        checks.push(match (images.kitty, images.sixel) {
+            (true, _) => ok("images", "kitty graphics"),
+            (false, true) => ok("images", "sixel"),
+            _ if !self.config.inline_images => ok("images", "inline_images is off"),
+            _ => warn(
+                "images",
+                "no image protocol found; images in output show as [image]",
+                "use a terminal with kitty graphics or sixel",
+            ),
+        });
+        let truecolor = var("COLORTERM").is_some_and(|c| c == "truecolor" || c == "24bit");
+        let theme = &self.config.theme;
+        let colours = [
+            theme.focused,
+            theme.unfocused,
+            theme.insert,
+            theme.normal,
+            theme.column,
+        ];
+        let rgb = colours.iter().any(|c| matches!(c, Color::Rgb(..)));
+        checks.push(match (rgb, truecolor) {
+            (true, false) => warn(
+                "theme",
+                "rrggbb colours, but COLORTERM doesn't say the terminal has 24-bit colour",
+                "set COLORTERM=truecolor if it does, or use colour names or 256-colour indexes",
+            ),
+            (_, true) => ok("theme", "colours resolve; 24-bit colour"),
+            (false, false) => ok("theme", "colours resolve"),
+        });
+        checks
+    }
+
+    fn program_checks(&self) -> Vec<Check> {
+        let (shell_var, fallback) = match cfg!(windows) {
+            true => ("COMSPEC", "cmd.exe"),
+            false => ("SHELL", "/bin/sh"),
+        };
+        let mut checks = vec![match var(shell_var) {
+            Some(shell) => match found(&shell) {
+                Some(path) => ok("shell", format!("${shell_var} is {path}")),
+                None => fail(
+                    "shell",
+                    format!("${shell_var} is {shell}, which isn't there; multi-line commands fail"),
+                    format!("set {shell_var} to an installed shell"),
+                ),
+            },
+            None => warn(
+                "shell",
+                format!("${shell_var} isn't set; multi-line commands run with {fallback}"),
+                format!("set {shell_var}"),
+            ),
+        }];
+        let editor = var("VISUAL").or_else(|| var("EDITOR"));
+        let first = |command: &str| command.split_whitespace().next().unwrap_or("").to_string();
+        checks.push(match editor {
+            Some(editor) => match found(&first(&editor)) {
+                Some(_) => ok("editor", format!("{editor}, for programs that open one")),
+                None => fail(
+                    "editor",
+                    format!("{editor} isn't installed; git commit and the like will fail"),
+                    "set EDITOR to an installed editor",
+                ),
+            },
+            None => warn(
+                "editor",
+                "neither $VISUAL nor $EDITOR is set; programs that open one fall back or fail",
+                "set EDITOR",
+            ),
+        });
+        checks.push(match &self.config.completer {
+            None => ok("completer", "off"),
+            Some(completer) => match found(&first(completer)) {
+                Some(_) => ok("completer", completer.clone()),
+                None => warn(
+                    "completer",
+                    format!(
+                        "{completer} isn't installed; Tab offers only paths, programs and builtins"
+                    ),
+                    "install it, or set completer = none",
+                ),
+            },
+        });
+        checks
+    }
+
+    fn history_check(&self) -> Check {
+        if self.config.history != history::Source::Shim {
+            return match found("atuin") {
+                Some(_) => ok(
+                    "history",
+                    format!("atuin, {} commands", self.previous_commands.len()),
+                ),
+                None => fail(
+                    "history",
+                    "history is atuin, which isn't installed; using shim's own",
+                    "install atuin, or set history = shim",
+                ),
+            };
+        }
+        let Some(path) = history::path() else {
+            return warn(
+                "history",
+                "nowhere to keep history; it's lost on exit",
+                "set HOME",
+            );
+        };
+        match writable(&path) {
+            Ok(()) => ok(
+                "history",
+                format!(
+                    "{}, {} commands",
+                    path.display(),
+                    self.previous_commands.len()
+                ),
+            ),
+            Err(e) => fail(
+                "history",
+                format!("{}: {e}; commands aren't kept", path.display()),
+                "make the file and its directory writable",
+            ),
+        }
+    }
+
+    /// `:checkhealth`: the report, a check per line.
+    pub fn check_health(&self) -> String {
+        let checks = self.checks();
+        let failed = checks.iter().filter(|c| c.level == Level::Fail).count();
+        let warned = checks.iter().filter(|c| c.level == Level::Warn).count();
+        let mut report: String = checks
+            .iter()
+            .map(|check| {
+                let level = match check.level {
+                    Level::Ok => "OK",
+                    Level::Warn => "WARN",
+                    Level::Fail => "FAIL",
+                };
+                let remedy = check
+                    .remedy
+                    .as_ref()
+                    .map(|r| format!(" — {r}"))
+                    .unwrap_or_default();
+                format!("{level:<5} {:<10} {}{remedy}\n", check.item, check.detail)
+            })
+            .collect();
+        report.push_str(&format!("{failed} failed, {warned} warnings"));
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    #[test]
+    fn config_lines_are_checked() {
+        let path = PathBuf::from("/home/me/.config/shim/config");
+        let read = Ok("wrap = true\nbogus = 1\n\n# note\ntab_stop = x\nnonsense\n".to_string());
+        let checks = config_checks(Some(&path), read);
+        let details: Vec<_> = checks
+            .iter()
+            .map(|c| (c.level, c.detail.as_str()))
+            .collect();
+        let line = |n, problem| format!("/home/me/.config/shim/config line {n}: {problem}");
+        assert_eq!(
+            details,
+            [
+                (Level::Warn, line(2, "unknown setting: bogus").as_str()),
+                (
+                    Level::Warn,
+                    line(5, "tab_stop must be a positive number: x").as_str()
+                ),
+                (
+                    Level::Warn,
+                    line(6, "not a `key = value` line: nonsense").as_str()
+                ),
+            ]
+        );
+
+        let missing = config_checks(Some(&path), Err(io::ErrorKind::NotFound.into()));
+        assert_eq!(missing[0].level, Level::Ok);
+        let denied = config_checks(Some(&path), Err(io::ErrorKind::PermissionDenied.into()));
+        assert_eq!(denied[0].level, Level::Fail);
+        assert_eq!(config_checks(None, Ok(String::new()))[0].level, Level::Warn);
+    }
+
+    #[test]
+    fn the_report_never_fails_to_be_made() {
+        let report = Model::default().check_health();
+        let last = report.lines().last().unwrap();
+        assert!(last.ends_with("warnings"), "{report}");
+        assert!(
+            report
+                .lines()
+                .all(|l| l == last || l.starts_with(['O', 'W', 'F']))
+        );
+    }
+}
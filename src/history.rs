@@ -0,0 +1,718 @@
+use std::{
+    collections::HashMap,
+    env, fmt,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{Model, atuin::Atuin, config, event::AppEvent, lock};
+
+/// First line of a history file that records directories. Older files are
+/// just commands, one per line.
+const HEADER: &str = "#shim-history v2";
+
+/// A submitted command and the directory it was run in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub command: String,
+    /// Unknown for entries from before directories were recorded.
+    pub cwd: Option<PathBuf>,
+    /// The run context (`:ctx`) it was run in, if any.
+    pub context: Option<String>,
+    /// When it was run, in seconds since the epoch; unknown for entries
+    /// from before times were recorded.
+    pub time: Option<u64>,
+}
+
+impl Entry {
+    /// Trailing whitespace isn't kept, unless it's escaped.
+    pub fn new(command: String) -> Self {
+        let trimmed = command.trim_end();
+        let command = match trimmed.ends_with('\\') {
+            true => command,
+            false => trimmed.to_string(),
+        };
+        Entry {
+            command,
+            cwd: env::current_dir().ok(),
+            context: None,
+            time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .map(|d| d.as_secs()),
+        }
+    }
+
+    /// Whether this was run in or under `dir`. Entries without a directory
+    /// match everywhere.
+    pub fn ran_under(&self, dir: &Path) -> bool {
+        self.cwd.as_ref().is_none_or(|cwd| cwd.starts_with(dir))
+    }
+
+    fn to_line(&self) -> String {
+        let cwd = self.cwd.as_ref().and_then(|c| c.to_str()).unwrap_or("");
+        let mut line = format!("{}\t{}", escape(cwd), escape(&self.command));
+        if self.context.is_some() || self.time.is_some() {
+            line.push('\t');
+            line.push_str(&escape(self.context.as_deref().unwrap_or("")));
+        }
+        if let Some(time) = self.time {
+            line.push_str(&format!("\t{time}"));
+        }
+        line
+    }
+
+    /// A line of the file: the directory, the command and, if it ran in
+    /// one, the context, then when it ran, the context left empty if
+    /// there was none.
+    fn from_line(line: &str) -> Self {
+        let mut fields = line.splitn(4, '\t');
+        let (cwd, command) = match (fields.next(), fields.next()) {
+            (Some(cwd), Some(command)) => (cwd, command),
+            _ => ("", line),
+        };
+        Entry {
+            command: unescape(command),
+            cwd: Some(PathBuf::from(unescape(cwd))).filter(|c| !c.as_os_str().is_empty()),
+            context: fields.next().filter(|c| !c.is_empty()).map(unescape),
+            time: fields.next().and_then(|t| t.parse().ok()),
+        }
+    }
+}
+
+/// Which history commands are read from and recorded to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Source {
+    /// shim's own file.
+    Shim,
+    Atuin,
+    /// Read from atuin, record to both.
+    Both,
+}
+
+/// Where past commands are read from and recorded to.
+pub trait Backend: fmt::Debug {
+    /// Shown in the Ctrl-r picker, so it's clear where results came from.
+    fn name(&self) -> &'static str;
+    /// Every entry, oldest first.
+    fn load(&self) -> Result<Vec<Entry>, String>;
+    /// Record `entry` as started. Backends that keep exit statuses return
+    /// a handle to pass to `finish`.
+    fn start(&mut self, entry: &Entry) -> Result<Option<String>, String>;
+    /// Record how the command given `handle` ended.
+    fn finish(
+        &mut self,
+        handle: String,
+        status: Option<i32>,
+        duration: Duration,
+    ) -> Result<(), String>;
+}
+
+/// shim's own history file.
+#[derive(Debug)]
+pub struct File;
+
+impl Backend for File {
+    fn name(&self) -> &'static str {
+        "shim"
+    }
+
+    fn load(&self) -> Result<Vec<Entry>, String> {
+        Ok(load())
+    }
+
+    fn start(&mut self, entry: &Entry) -> Result<Option<String>, String> {
+        append(entry);
+        Ok(None)
+    }
+
+    fn finish(&mut self, _: String, _: Option<i32>, _: Duration) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// Read from one backend and record to another as well: atuin and shim's
+/// own file, with `history = both`.
+#[derive(Debug)]
+pub struct Both {
+    main: Box<dyn Backend>,
+    copy: Box<dyn Backend>,
+}
+
+impl Backend for Both {
+    fn name(&self) -> &'static str {
+        self.main.name()
+    }
+
+    fn load(&self) -> Result<Vec<Entry>, String> {
+        self.main.load()
+    }
+
+    fn start(&mut self, entry: &Entry) -> Result<Option<String>, String> {
+        // if the main one fails, shim's own records it instead
+        let handle = self.main.start(entry)?;
+        let _ = self.copy.start(entry);
+        Ok(handle)
+    }
+
+    fn finish(
+        &mut self,
+        handle: String,
+        status: Option<i32>,
+        duration: Duration,
+    ) -> Result<(), String> {
+        self.main.finish(handle, status, duration)
+    }
+}
+
+impl Default for Box<dyn Backend> {
+    fn default() -> Self {
+        Box::new(File)
+    }
+}
+
+/// The backend `source` asks for and its entries, or shim's own with a
+/// warning if that fails.
+pub fn open(source: Source) -> (Box<dyn Backend>, Vec<Entry>, Option<String>) {
+    let backend = backend(source);
+    if source == Source::Shim {
+        return (backend, load(), None);
+    }
+    open_backend(backend)
+}
+
+/// `backend` and its entries, or shim's own with a warning if it fails.
+fn open_backend(backend: Box<dyn Backend>) -> (Box<dyn Backend>, Vec<Entry>, Option<String>) {
+    match backend.load() {
+        Ok(entries) => (backend, entries, None),
+        Err(e) => (
+            Box::new(File),
+            load(),
+            Some(fallback_warning(&*backend, &e)),
+        ),
+    }
+}
+
+fn backend(source: Source) -> Box<dyn Backend> {
+    match source {
+        Source::Shim => Box::new(File),
+        Source::Atuin => Box::new(Atuin::new()),
+        Source::Both => Box::new(Both {
+            main: Box::new(Atuin::new()),
+            copy: Box::new(File),
+        }),
+    }
+}
+
+/// What `open` found, on a thread of its own.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Loaded {
+    entries: Vec<Entry>,
+    warning: Option<String>,
+    /// The backend asked for failed, and shim's own is used.
+    fell_back: bool,
+    took: Duration,
+}
+
+impl Loaded {
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
+/// The backend `source` asks for, its entries loaded on a thread of their
+/// own and sent as `HistoryLoaded`.
+pub fn open_later(source: Source, events: Sender<AppEvent>) -> Box<dyn Backend> {
+    thread::spawn(move || {
+        let started = Instant::now();
+        let (backend, entries, warning) = open(source);
+        let loaded = Loaded {
+            entries,
+            warning,
+            fell_back: source != Source::Shim && backend.name() == File.name(),
+            took: started.elapsed(),
+        };
+        let _ = events.send(AppEvent::HistoryLoaded(loaded));
+    });
+    backend(source)
+}
+
+fn fallback_warning(backend: &dyn Backend, error: &str) -> String {
+    format!("{} history: {error}; using shim's own", backend.name())
+}
+
+/// A command whose history entry waits for its exit status.
+#[derive(Debug)]
+pub struct Running {
+    /// The buffer it runs in.
+    output: usize,
+    handle: String,
+    started: Instant,
+}
+
+impl Model {
+    /// History loaded in the background, with `defer_history`. Commands
+    /// run meanwhile come after it.
+    pub fn history_loaded(&mut self, loaded: Loaded) {
+        if loaded.fell_back {
+            self.history = Box::new(File);
+        }
+        // what ran meanwhile may have been written in time to be loaded
+        let run = std::mem::take(&mut self.previous_commands);
+        let viewing = self.viewing_command.and_then(|i| run.get(i).cloned());
+        self.arguments = Default::default();
+        self.previous_commands = merge(run, loaded.entries).0;
+        // the command being looked at has moved along
+        self.viewing_command =
+            viewing.and_then(|entry| self.previous_commands.iter().rposition(|e| *e == entry));
+        if let Some(warning) = loaded.warning {
+            self.status = Some(warning);
+        } else if self.startup.report {
+            let took = loaded.took.as_secs_f64() * 1000.0;
+            self.status = Some(format!("history loaded in the background in {took:.1}ms"));
+        }
+    }
+
+    /// Add `entry` to the history backend. If the backend keeps exit
+    /// statuses, buffer `output` gives it, once finished; a queued command
+    /// has no buffer yet, so it's recorded without.
+    pub fn record_history(&mut self, entry: &Entry, output: Option<usize>) {
+        let started = Instant::now();
+        let handle = match self.history.start(entry) {
+            Ok(handle) => handle,
+            Err(e) => {
+                self.history_failed(&e);
+                // still keep it, in shim's own
+                let _ = self.history.start(entry);
+                return;
+            }
+        };
+        let (Some(handle), Some(output)) = (handle, output) else {
+            return;
+        };
+        match self.outputs.iter().find(|o| o.id == output) {
+            Some(o) if o.running => self.history_running.push(Running {
+                output,
+                handle,
+                started,
+            }),
+            // a builtin, or a command that couldn't start
+            Some(o) => {
+                let status = o.status.or(Some(0));
+                self.finish_history_handle(handle, status, started.elapsed());
+            }
+            None => self.finish_history_handle(handle, Some(0), started.elapsed()),
+        }
+    }
+
+    /// Keep `command`, which starts with `#`, in the history without
+    /// running it. The `#` is kept too unless `stash_keep_hash` is off.
+    pub fn stash(&mut self, command: &str) {
+        let command = command.trim_start();
+        let rest = command[1..].trim_start();
+        if rest.trim_end().is_empty() {
+            return;
+        }
+        let entry = match self.config.stash_keep_hash {
+            true => Entry::new(command.to_string()),
+            false => Entry::new(rest.to_string()),
+        };
+        self.status = Some("stashed in history".into());
+        self.previous_commands.push(entry.clone());
+        self.record_history(&entry, None);
+    }
+
+    /// Buffer `output` finished; record its status in the history.
+    pub fn finish_history(&mut self, output: usize, status: Option<i32>) {
+        let Some(i) = self.history_running.iter().position(|r| r.output == output) else {
+            return;
+        };
+        let running = self.history_running.remove(i);
+        self.finish_history_handle(running.handle, status, running.started.elapsed());
+    }
+
+    fn finish_history_handle(&mut self, handle: String, status: Option<i32>, took: Duration) {
+        if let Err(e) = self.history.finish(handle, status, took) {
+            self.history_failed(&e);
+        }
+    }
+
+    /// `:sync-history`: take in commands other shims have written to the
+    /// history file since this one read it.
+    pub fn sync_history(&mut self) -> String {
+        if self.history.name() != File.name() {
+            return format!("{} history is shared already", self.history.name());
+        }
+        let Some(path) = path() else {
+            return "no history file".into();
+        };
+        let ours = std::mem::take(&mut self.previous_commands);
+        let (merged, added) = merge(ours, read(&path));
+        self.previous_commands = merged;
+        self.arguments = Default::default();
+        format!("took in {added} commands from other shims")
+    }
+
+    /// Fall back to shim's own history for the rest of the session.
+    fn history_failed(&mut self, error: &str) {
+        self.status = Some(fallback_warning(&*self.history, error));
+        self.history = Box::new(File);
+        self.history_running.clear();
+    }
+}
+
+/// Submitted commands, oldest first.
+pub fn path() -> Option<PathBuf> {
+    Some(config::dir()?.join("history"))
+}
+
+/// The entries of a history file, and whether it's in the current format.
+fn parse(contents: &str) -> (Vec<Entry>, bool) {
+    let lines = contents.lines();
+    if lines.clone().next() == Some(HEADER) {
+        return (lines.skip(1).map(Entry::from_line).collect(), true);
+    }
+    let entries = lines
+        .map(|line| Entry {
+            command: unescape(line),
+            cwd: None,
+            context: None,
+            time: None,
+        })
+        .collect();
+    (entries, contents.is_empty())
+}
+
+fn to_file(entries: &[Entry]) -> String {
+    let mut contents = format!("{HEADER}\n");
+    for entry in entries {
+        contents.push_str(&entry.to_line());
+        contents.push('\n');
+    }
+    contents
+}
+
+fn read(path: &Path) -> Vec<Entry> {
+    lock::read(path).map_or(Vec::new(), |contents| parse(&contents).0)
+}
+
+/// Read the history, upgrading an old file to the current format.
+pub fn load() -> Vec<Entry> {
+    let Some(path) = path() else {
+        return Vec::new();
+    };
+    let Ok(contents) = lock::read(&path) else {
+        return Vec::new();
+    };
+    let (entries, current) = parse(&contents);
+    if !current {
+        // unless another shim got there first
+        let _ = lock::rewrite(&path, |contents| match parse(contents) {
+            (entries, false) => to_file(&entries),
+            (_, true) => contents.to_string(),
+        });
+    }
+    entries
+}
+
+fn append_to(path: &Path, entry: &Entry) {
+    // losing a history entry isn't worth interrupting the user for
+    let _ = lock::append(path, Some(HEADER), &format!("{}\n", entry.to_line()));
+}
+
+/// Add `entry` to the end of the history file.
+pub fn append(entry: &Entry) {
+    if let Some(path) = path() {
+        append_to(&path, entry);
+    }
+}
+
+/// Put `entries` before the rest of the history file, as they were run
+/// before anything in it.
+pub fn prepend(entries: &[Entry]) -> Result<(), String> {
+    let Some(path) = path() else {
+        return Ok(());
+    };
+    lock::rewrite(&path, |contents| {
+        let mut all = entries.to_vec();
+        all.extend(parse(contents).0);
+        to_file(&all)
+    })
+    .map_err(|e| e.to_string())
+}
+
+/// `ours` with the entries of `theirs` it doesn't have, a command run at
+/// the same second being the same entry, all in the order they ran in
+/// with those of unknown time first; and how many were added.
+pub fn merge(ours: Vec<Entry>, theirs: Vec<Entry>) -> (Vec<Entry>, usize) {
+    let mut known: HashMap<_, usize> = HashMap::new();
+    for entry in &ours {
+        *known
+            .entry((entry.time, entry.command.clone()))
+            .or_default() += 1;
+    }
+    let mut merged: Vec<Entry> = theirs
+        .into_iter()
+        .filter(|e| match known.get_mut(&(e.time, e.command.clone())) {
+            Some(count @ 1..) => {
+                *count -= 1;
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    let added = merged.len();
+    merged.extend(ours);
+    merged.sort_by_key(|e| e.time);
+    (merged, added)
+}
+
+/// Keep a command on one line and free of tabs: `\` becomes `\\`, a
+/// newline `\n` and a tab `\t`.
+pub fn escape(command: &str) -> String {
+    command
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+pub fn unescape(line: &str) -> String {
+    let mut command = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        let escaped = match (c, chars.clone().next()) {
+            ('\\', Some('n')) => '\n',
+            ('\\', Some('t')) => '\t',
+            ('\\', Some('\\')) => '\\',
+            _ => {
+                command.push(c);
+                continue;
+            }
+        };
+        command.push(escaped);
+        chars.next();
+    }
+    command
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lines_keep_the_context_if_there_is_one() {
+        let entry = Entry {
+            command: "make\ttest".into(),
+            cwd: Some(PathBuf::from("/src")),
+            context: Some("app".into()),
+            time: None,
+        };
+        assert_eq!(entry.to_line(), "/src\tmake\\ttest\tapp");
+        let read = Entry::from_line(&entry.to_line());
+        assert_eq!(
+            (read.command, read.context),
+            (entry.command.clone(), entry.context.clone())
+        );
+        let older = Entry::from_line("/src\tls");
+        assert_eq!((older.command.as_str(), older.context), ("ls", None));
+        assert_eq!(Entry::from_line("ls").cwd, None);
+        let timed = Entry {
+            time: Some(1700000000),
+            context: None,
+            ..entry
+        };
+        assert_eq!(timed.to_line(), "/src\tmake\\ttest\t\t1700000000");
+        assert_eq!(Entry::from_line(&timed.to_line()), timed);
+    }
+
+    fn at(command: &str, time: Option<u64>) -> Entry {
+        Entry {
+            command: command.into(),
+            cwd: None,
+            context: None,
+            time,
+        }
+    }
+
+    #[test]
+    fn merging_keeps_each_run_once_in_order() {
+        let ours = vec![at("ls", Some(1)), at("ls", Some(1)), at("make", Some(5))];
+        let theirs = vec![
+            at("old", None),
+            at("ls", Some(1)),
+            at("ls", Some(1)),
+            at("ls", Some(1)),
+            at("cd", Some(3)),
+        ];
+        let (merged, added) = merge(ours, theirs);
+        let commands: Vec<_> = merged.iter().map(|e| e.command.as_str()).collect();
+        assert_eq!(commands, ["old", "ls", "ls", "ls", "cd", "make"]);
+        assert_eq!(added, 3);
+    }
+
+    #[test]
+    fn shims_appending_at_once_keep_each_others_entries() {
+        let dir = env::temp_dir().join(format!("shim-history-{}", std::process::id()));
+        let path = dir.join("history");
+        let shims: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    let ran: Vec<_> = (0..100)
+                        .map(|i| at(&format!("{name} {i}"), Some(i)))
+                        .collect();
+                    for entry in &ran {
+                        append_to(&path, entry);
+                    }
+                    ran
+                })
+            })
+            .collect();
+        let ran: Vec<_> = shims.into_iter().map(|s| s.join().unwrap()).collect();
+        let (merged, added) = merge(ran[0].clone(), read(&path));
+        assert_eq!(added, 100);
+        assert_eq!(merged.len(), 200);
+        assert!(merged.is_sorted_by_key(|e| e.time));
+        for name in ["a", "b"] {
+            let mine = merged.iter().filter(|e| e.command.starts_with(name));
+            assert!(mine.map(|e| e.time).eq((0..100).map(Some)));
+        }
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn history_loaded_later_goes_before_what_ran_meanwhile() {
+        let mut model = Model {
+            history: Box::new(Atuin::new()),
+            previous_commands: vec![Entry::new("ls".into())],
+            ..Default::default()
+        };
+        model.viewing_command = Some(0);
+        model.history_loaded(Loaded {
+            entries: vec![Entry::new("make".into())],
+            warning: Some("atuin history: not found; using shim's own".into()),
+            fell_back: true,
+            took: Duration::from_millis(80),
+        });
+        let commands: Vec<_> = model.previous_commands.iter().map(|e| &e.command).collect();
+        assert_eq!(commands, ["make", "ls"]);
+        assert_eq!(model.viewing_command, Some(1));
+        assert_eq!(model.history.name(), "shim");
+        assert!(model.status.is_some());
+    }
+
+    /// A backend that notes what it's asked to record, or fails.
+    #[derive(Debug, Default)]
+    struct Fake {
+        fails: bool,
+        log: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    }
+
+    impl Backend for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+
+        fn load(&self) -> Result<Vec<Entry>, String> {
+            match self.fails {
+                true => Err("down".into()),
+                false => Ok(vec![Entry::new("make".into())]),
+            }
+        }
+
+        fn start(&mut self, entry: &Entry) -> Result<Option<String>, String> {
+            if self.fails {
+                return Err("down".into());
+            }
+            self.log
+                .lock()
+                .unwrap()
+                .push(format!("start {}", entry.command));
+            Ok(Some(entry.command.clone()))
+        }
+
+        fn finish(
+            &mut self,
+            handle: String,
+            status: Option<i32>,
+            _: Duration,
+        ) -> Result<(), String> {
+            let finished = format!("finish {handle} {status:?}");
+            self.log.lock().unwrap().push(finished);
+            Ok(())
+        }
+    }
+
+    fn failing() -> Box<Fake> {
+        Box::new(Fake {
+            fails: true,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn a_backend_that_fails_gives_way_to_shims_own() {
+        let (backend, _, warning) = open_backend(failing());
+        assert_eq!(backend.name(), "shim");
+        assert_eq!(
+            warning.as_deref(),
+            Some("fake history: down; using shim's own")
+        );
+        let (backend, entries, warning) = open_backend(Box::<Fake>::default());
+        assert_eq!((backend.name(), entries.len(), warning), ("fake", 1, None));
+
+        let mut model = Model {
+            history: failing(),
+            ..Default::default()
+        };
+        model.record_history(&Entry::new("ls".into()), None);
+        assert_eq!(model.history.name(), "shim");
+        assert_eq!(
+            model.status.as_deref(),
+            Some("fake history: down; using shim's own")
+        );
+    }
+
+    #[test]
+    fn both_records_to_each_and_finishes_with_the_main_one() {
+        let (main, copy) = (Fake::default(), Fake::default());
+        let (main_log, copy_log) = (main.log.clone(), copy.log.clone());
+        let mut model = Model {
+            history: Box::new(Both {
+                main: Box::new(main),
+                copy: Box::new(copy),
+            }),
+            ..Default::default()
+        };
+        model.push_output("make".into(), String::new());
+        model.outputs[0].running = true;
+        model.record_history(&Entry::new("make".into()), Some(0));
+        // a builtin, making no buffer, is finished at once
+        model.record_history(&Entry::new(":pin".into()), Some(1));
+        model.finish_history(0, Some(2));
+        assert_eq!(
+            *main_log.lock().unwrap(),
+            [
+                "start make",
+                "start :pin",
+                "finish :pin Some(0)",
+                "finish make Some(2)"
+            ]
+        );
+        assert_eq!(*copy_log.lock().unwrap(), ["start make", "start :pin"]);
+
+        // the copy isn't written to as well as shim's own on a failure
+        let copy = Fake::default();
+        let copy_log = copy.log.clone();
+        model.history = Box::new(Both {
+            main: failing(),
+            copy: Box::new(copy),
+        });
+        model.record_history(&Entry::new("ls".into()), None);
+        assert_eq!(model.history.name(), "shim");
+        assert!(copy_log.lock().unwrap().is_empty());
+    }
+}
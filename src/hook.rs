@@ -0,0 +1,208 @@
+//! Hooks: commands run when a command finishes, to send a notification
+//! or play a sound. `on_command_end` runs after every command, and
+//! `on_command_success` or `on_command_failure` after one that succeeded
+//! or failed, each only if the command ran for `hook_min_duration` or
+//! longer. In a hook `{cmd}`, `{code}`, `{duration}` and `{dir}` stand for
+//! the command, its exit code, how long it ran and where. The hook is
+//! split into words before they're put in, so what they hold stays in the
+//! word they're in; in the script given to a shell with `-c`, `-lc` and
+//! the like, run itself or through `env`, they're quoted for that shell.
+//! Hooks are started and left to run with their output dropped, so a slow
+//! one never holds shim up, and one that can't be started says so in a
+//! toast the first time only.
+
+use std::{collections::HashSet, env, path::Path, process::Stdio, thread, time::Duration};
+
+use crate::{Model, redact, shell, stats, toast};
+
+/// Shells whose `-c` script has what's put in quoted.
+const SHELLS: &[&str] = &["sh", "bash", "zsh", "dash", "ksh", "fish"];
+
+#[derive(Debug, Default)]
+pub struct Hooks {
+    /// Hooks that couldn't be started, said so once.
+    warned: HashSet<&'static str>,
+}
+
+/// `word` with each placeholder in `values` put in, quoted for a shell if
+/// `quoted`. What's put in isn't looked at again.
+fn fill(word: &str, values: &[(&str, String)], quoted: bool) -> String {
+    let mut filled = String::new();
+    let mut rest = word;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match values.iter().find(|(name, _)| rest.starts_with(name)) {
+            Some((name, value)) => {
+                match quoted {
+                    true => filled.push_str(&shell::quote(value)),
+                    false => filled.push_str(value),
+                }
+                rest = &rest[name.len()..];
+            }
+            None => {
+                filled.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+    filled.push_str(rest);
+    filled
+}
+
+/// Whether `program` is one of `names`, wherever it is.
+fn is_one_of(program: &str, names: &[&str]) -> bool {
+    let stem = Path::new(program).file_stem();
+    stem.is_some_and(|s| names.iter().any(|&name| s == name))
+}
+
+/// Whether `arg` is a cluster of a shell's short options with `c` among
+/// them, so the next word is its script: `-c`, `-lc`, `-ec`.
+fn script_flag(arg: &str) -> bool {
+    arg.strip_prefix('-')
+        .is_some_and(|flags| flags.contains('c') && flags.chars().all(|c| c.is_ascii_alphabetic()))
+}
+
+/// The program and arguments of `hook` with `values` put in.
+fn words(hook: &str, values: &[(&str, String)], dir: &Path) -> Option<Vec<String>> {
+    let parsed = shell::parse(hook, |name| env::var(name).ok(), dir)?;
+    let mut shell = is_one_of(&parsed.program, SHELLS);
+    // the program env runs is the first word that's not an option or a
+    // variable for it
+    let mut through_env = is_one_of(&parsed.program, &["env"]);
+    let mut words = vec![fill(&parsed.program, values, false)];
+    let mut script = false;
+    for arg in &parsed.args {
+        words.push(fill(arg, values, shell && script));
+        if through_env && !arg.starts_with('-') && !arg.contains('=') {
+            through_env = false;
+            shell = is_one_of(arg, SHELLS);
+            script = false;
+        } else {
+            script = script_flag(arg);
+        }
+    }
+    Some(words)
+}
+
+impl Model {
+    /// Run the hooks for `command` having ended with `code` after `took`.
+    pub fn run_hooks(&mut self, command: &str, code: i32, took: Duration) {
+        if took < self.config.hook_min_duration {
+            return;
+        }
+        let which = match code {
+            0 => ("on_command_success", &self.config.on_command_success),
+            _ => ("on_command_failure", &self.config.on_command_failure),
+        };
+        let hooks: Vec<_> = [("on_command_end", &self.config.on_command_end), which]
+            .into_iter()
+            .filter_map(|(name, hook)| Some((name, hook.clone()?)))
+            .collect();
+        if hooks.is_empty() {
+            return;
+        }
+        let Ok(dir) = env::current_dir() else {
+            return;
+        };
+        let command = redact::line(&self.config.redact, command).into_owned();
+        let values = [
+            ("{cmd}", command),
+            ("{code}", code.to_string()),
+            ("{duration}", stats::format_ms(took.as_millis() as u64)),
+            ("{dir}", dir.display().to_string()),
+        ];
+        for (name, hook) in hooks {
+            if let Err(e) = self.start_hook(&hook, &values, &dir)
+                && self.hooks.warned.insert(name)
+            {
+                let warning = format!("{name} couldn't be run: {e}");
+                self.push_toast(toast::Level::Warn, warning, toast::TTL);
+            }
+        }
+    }
+
+    /// Start `hook` and leave it to run.
+    fn start_hook(&self, hook: &str, values: &[(&str, String)], dir: &Path) -> Result<(), String> {
+        let mut words = words(hook, values, dir)
+            .ok_or("nothing to run")?
+            .into_iter();
+        let parsed = shell::ParsedCommand {
+            program: words.next().ok_or("nothing to run")?,
+            args: words.collect(),
+            ..Default::default()
+        };
+        let mut child = self
+            .process(parsed)
+            .current_dir(dir)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| e.to_string())?;
+        // reaped once it's done
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values() -> Vec<(&'static str, String)> {
+        vec![
+            ("{cmd}", "rm -rf '$HOME'; echo {code}".into()),
+            ("{code}", "1".into()),
+        ]
+    }
+
+    #[test]
+    fn placeholders_stay_in_their_word() {
+        let dir = env::temp_dir();
+        let hook = "notify-send 'shim' '{cmd} exited {code}' {nope}";
+        assert_eq!(
+            words(hook, &values(), &dir).unwrap(),
+            [
+                "notify-send",
+                "shim",
+                "rm -rf '$HOME'; echo {code} exited 1",
+                "{nope}"
+            ]
+        );
+        // a shell's script has them quoted
+        let hook = "sh -c 'echo {cmd} >> log; exit {code}'";
+        assert_eq!(
+            words(hook, &values(), &dir).unwrap(),
+            [
+                "sh",
+                "-c",
+                r#"echo 'rm -rf '\''$HOME'\''; echo {code}' >> log; exit 1"#
+            ]
+        );
+        assert_eq!(words("", &values(), &dir), None);
+    }
+
+    #[test]
+    fn a_script_is_quoted_after_any_c_flag_and_through_env() {
+        let dir = env::temp_dir();
+        let quoted = r#"echo 'rm -rf '\''$HOME'\''; echo {code}'"#;
+        assert_eq!(
+            words("bash -lc 'echo {cmd}'", &values(), &dir).unwrap(),
+            ["bash", "-lc", quoted]
+        );
+        assert_eq!(
+            words("env LANG=C sh -c 'echo {cmd}'", &values(), &dir).unwrap(),
+            ["env", "LANG=C", "sh", "-c", quoted]
+        );
+        assert_eq!(
+            words("/usr/bin/env -i zsh -ec 'echo {cmd}'", &values(), &dir).unwrap(),
+            ["/usr/bin/env", "-i", "zsh", "-ec", quoted]
+        );
+        // only a shell's
+        assert_eq!(
+            words("notify-send -c '{cmd}'", &values(), &dir).unwrap()[2],
+            "rm -rf '$HOME'; echo {code}"
+        );
+    }
+}
@@ -0,0 +1,535 @@
+//! Pictures drawn by programs like `chafa`, `viu` and `timg` with the
+//! sixel, kitty or iTerm2 image escapes. They're taken out of the output as
+//! it comes in and a placeholder like `[image 640x480]` left in their place.
+//! If the terminal said at startup that it draws that kind itself, the
+//! image is kept and sent to it whenever its line is in view.
+
+use std::io::{self, Write};
+
+use ratatui::{
+    crossterm::{
+        QueueableCommand,
+        cursor::{MoveTo, RestorePosition, SavePosition},
+    },
+    layout::Rect,
+};
+use regex::bytes::Regex;
+
+use crate::{Model, Output, sanitize};
+
+/// Ends every sequence but iTerm2's, which may end with a bell instead.
+const ST: &str = "\x1b\\";
+
+/// Image protocols the terminal draws, from its answers at startup.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Support {
+    pub kitty: bool,
+    pub sixel: bool,
+    /// Pixels in a cell's height, to tell how many rows a sixel image
+    /// takes.
+    pub cell_height: Option<u16>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Protocol {
+    Sixel,
+    Kitty,
+    Iterm,
+}
+
+/// An image taken out of the output, kept to draw.
+#[derive(Debug)]
+struct Image {
+    /// The line of the output its placeholder is on.
+    line: usize,
+    protocol: Protocol,
+    sequence: String,
+    /// Height in pixels, if it says.
+    height: Option<usize>,
+}
+
+/// What's being taken out of a buffer's output.
+#[derive(Debug, Default)]
+pub struct Images {
+    /// The start of a sequence whose end hasn't arrived yet.
+    partial: String,
+    /// A kitty image is being sent in chunks, with more to come; whether
+    /// it's being kept.
+    kitty_more: Option<bool>,
+    kept: Vec<Image>,
+}
+
+/// An image on screen: which buffer and image it is, and the row of the
+/// output pane it's at.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Shown {
+    output: usize,
+    image: usize,
+    row: u16,
+}
+
+/// What kind of sequence `text`, starting with ESC, is; `None` if it may
+/// be an image but is cut short.
+fn classify(text: &str) -> Option<Option<Protocol>> {
+    let starts = [
+        ("\x1b_G", Protocol::Kitty),
+        ("\x1b]1337;File=", Protocol::Iterm),
+    ];
+    for (start, protocol) in starts {
+        if text.starts_with(start) {
+            return Some(Some(protocol));
+        }
+        if start.starts_with(text) {
+            return None;
+        }
+    }
+    // sixel is a DCS with only numbers before its `q`
+    let Some(rest) = text.strip_prefix("\x1bP") else {
+        return (text != "\x1b").then_some(None);
+    };
+    match rest
+        .trim_start_matches(|c: char| c.is_ascii_digit() || c == ';')
+        .chars()
+        .next()
+    {
+        Some('q') => Some(Some(Protocol::Sixel)),
+        Some(_) => Some(None),
+        None => None,
+    }
+}
+
+/// Where the sequence at the start of `text` ends, after its terminator.
+fn end_of(text: &str, protocol: Protocol) -> Option<usize> {
+    let st = text.find(ST).map(|i| i + ST.len());
+    match protocol {
+        Protocol::Iterm => {
+            let bell = text.find('\x07').map(|i| i + 1);
+            st.into_iter().chain(bell).min()
+        }
+        _ => st,
+    }
+}
+
+/// The `key=value` pairs of a kitty sequence's control data.
+fn kitty_keys(sequence: &str) -> impl Iterator<Item = (&str, &str)> {
+    let body = sequence.trim_start_matches("\x1b_G").trim_end_matches(ST);
+    let control = body.split(';').next().unwrap_or("");
+    control.split(',').filter_map(|pair| pair.split_once('='))
+}
+
+/// Width and height the sequence gives, in pixels for sixel and kitty, or
+/// as iTerm2 was asked to size it.
+fn size(sequence: &str, protocol: Protocol) -> Option<(String, String)> {
+    match protocol {
+        Protocol::Sixel => {
+            let raster = sequence.split_once('q')?.1.strip_prefix('"')?;
+            let mut numbers = raster.split(|c: char| !c.is_ascii_digit()).take(4).skip(2);
+            Some((numbers.next()?.into(), numbers.next()?.into()))
+        }
+        Protocol::Kitty => {
+            let keys: Vec<_> = kitty_keys(sequence).collect();
+            let get = |k| {
+                keys.iter()
+                    .find(|(key, _)| *key == k)
+                    .map(|(_, v)| v.to_string())
+            };
+            Some((get("s")?, get("v")?))
+        }
+        Protocol::Iterm => {
+            let args = sequence.split_once('=')?.1.split(':').next()?;
+            let get = |k| {
+                args.split(';')
+                    .find_map(|a| a.strip_prefix(k)?.strip_prefix('='))
+                    .filter(|v| *v != "auto")
+                    .map(String::from)
+            };
+            Some((get("width")?, get("height")?))
+        }
+    }
+}
+
+fn placeholder(sequence: &str, protocol: Protocol) -> String {
+    match size(sequence, protocol) {
+        Some((width, height)) => format!("[image {width}x{height}]"),
+        None => "[image]".into(),
+    }
+}
+
+impl Images {
+    /// The first `lines` lines of the output are gone, with their images.
+    pub fn drop_lines(&mut self, lines: usize) {
+        self.kept
+            .retain_mut(|image| match image.line.checked_sub(lines) {
+                Some(line) => {
+                    image.line = line;
+                    true
+                }
+                None => false,
+            });
+    }
+
+    /// `text` with the image sequences in it taken out, and what's left of
+    /// one cut short held back for the next chunk. Images the terminal can
+    /// draw, by `support`, are kept with the line they're on, counting from
+    /// the start of `text`.
+    fn strip(&mut self, text: &str, support: Support) -> String {
+        let text = std::mem::take(&mut self.partial) + text;
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text.as_str();
+        while let Some(i) = rest.find('\x1b') {
+            out.push_str(&rest[..i]);
+            let sequence = &rest[i..];
+            let protocol = match classify(sequence) {
+                Some(Some(protocol)) => protocol,
+                Some(None) => {
+                    out.push('\x1b');
+                    rest = &sequence[1..];
+                    continue;
+                }
+                None => {
+                    self.partial = sequence.to_string();
+                    return out;
+                }
+            };
+            let Some(end) = end_of(sequence, protocol) else {
+                self.partial = sequence.to_string();
+                return out;
+            };
+            self.take(
+                &sequence[..end],
+                protocol,
+                out.matches('\n').count(),
+                &mut out,
+                support,
+            );
+            rest = &sequence[end..];
+        }
+        out.push_str(rest);
+        out
+    }
+
+    /// Put a placeholder in `out` for an image `sequence` on `line`, and
+    /// keep it if the terminal draws it.
+    fn take(
+        &mut self,
+        sequence: &str,
+        protocol: Protocol,
+        line: usize,
+        out: &mut String,
+        support: Support,
+    ) {
+        let keep = match protocol {
+            Protocol::Sixel => support.sixel,
+            Protocol::Kitty => support.kitty,
+            Protocol::Iterm => false,
+        };
+        // it goes back to the terminal as it is, so nothing may hide in it
+        let keep = keep && sanitize::sealed(sequence);
+        if protocol == Protocol::Kitty {
+            let more = kitty_keys(sequence).any(|pair| pair == ("m", "1"));
+            if let Some(kept) = self.kitty_more.take() {
+                // one bad chunk and none of the image is drawn
+                if kept && !keep {
+                    self.kept.pop();
+                }
+                let kept = kept && keep;
+                if more {
+                    self.kitty_more = Some(kept);
+                }
+                if let Some(image) = self.kept.last_mut().filter(|_| kept) {
+                    image.sequence.push_str(sequence);
+                }
+                return;
+            }
+            // only transmitting, querying or deleting shows nothing
+            let shown = kitty_keys(sequence).any(|pair| matches!(pair, ("a", "T" | "p")));
+            if more {
+                self.kitty_more = Some(shown && keep);
+            }
+            if !shown {
+                return;
+            }
+        }
+        out.push_str(&placeholder(sequence, protocol));
+        if keep {
+            let height = size(sequence, protocol).and_then(|(_, h)| h.parse().ok());
+            // kitty mustn't move the cursor, or the screen could scroll
+            let sequence = match protocol {
+                Protocol::Kitty => sequence.replacen("\x1b_G", "\x1b_GC=1,", 1),
+                _ => sequence.to_string(),
+            };
+            self.kept.push(Image {
+                line,
+                protocol,
+                sequence,
+                height,
+            });
+        }
+    }
+
+    /// What's held back once the output has ended: an image cut short is
+    /// left out.
+    fn finish(&mut self) -> String {
+        let partial = std::mem::take(&mut self.partial);
+        match classify(&partial) {
+            Some(None) => partial,
+            _ => "[image]".into(),
+        }
+    }
+
+    /// Bytes kept.
+    pub fn size(&self) -> usize {
+        self.partial.len() + self.kept.iter().map(|i| i.sequence.len()).sum::<usize>()
+    }
+
+    pub fn clear(&mut self) {
+        *self = Images::default();
+    }
+}
+
+impl Output {
+    /// Take the images out of a chunk of output before it's added.
+    pub fn strip_images(&mut self, text: &str, support: Support) -> String {
+        let before = self.images.kept.len();
+        let text = self.images.strip(text, support);
+        if self.images.kept.len() > before {
+            let line = self.stdout.matches('\n').count();
+            for image in &mut self.images.kept[before..] {
+                image.line += line;
+            }
+        }
+        text
+    }
+
+    /// What was held back of an image once the job has ended.
+    pub fn finish_images(&mut self) -> String {
+        match self.images.partial.is_empty() {
+            true => String::new(),
+            false => self.images.finish(),
+        }
+    }
+}
+
+impl Model {
+    /// The images to keep from output coming in.
+    pub fn image_support(&self) -> Support {
+        match self.config.inline_images {
+            true => self.terminal.images,
+            false => Support::default(),
+        }
+    }
+
+    /// The images that belong on screen now: those in view in the viewed
+    /// buffer, shown plainly, with nothing open over it.
+    pub fn visible_images(&self) -> Vec<Shown> {
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return Vec::new();
+        };
+        let plain = self.config.inline_images
+            && !self.wrapping()
+            && self.scrollback.is_none()
+            && self.picker.is_none()
+            && self.completion.is_none()
+            && output.scroll.1 == 0
+            && output.table.is_none()
+            && output.piped_text().is_none();
+        if !plain {
+            return Vec::new();
+        }
+        let top = output.scroll.0;
+        let height = self.height as usize;
+        let cell = self.terminal.images.cell_height;
+        output
+            .images
+            .kept
+            .iter()
+            .enumerate()
+            .filter(|(_, image)| (top..top + height).contains(&image.line))
+            .filter(|(_, image)| {
+                // a sixel running off the bottom would scroll the screen
+                let row = image.line - top;
+                image.protocol != Protocol::Sixel
+                    || matches!((image.height, cell), (Some(h), Some(c)) if c > 0
+                        && row + h.div_ceil(c as usize) < height)
+            })
+            .map(|(image, kept)| Shown {
+                output: output.id,
+                image,
+                row: (kept.line - top) as u16,
+            })
+            .collect()
+    }
+
+    /// Send `shown` images to the terminal, at their rows of output pane
+    /// `pane`, putting the cursor back after.
+    pub fn draw_images(&self, out: &mut impl Write, shown: &[Shown], pane: Rect) -> io::Result<()> {
+        if self.terminal.images.kitty {
+            // take down kitty's placements; they stay over redrawn text
+            out.write_all(format!("\x1b_Ga=d{ST}").as_bytes())?;
+        }
+        out.queue(SavePosition)?;
+        for image in shown {
+            let Some(output) = self.outputs.iter().find(|o| o.id == image.output) else {
+                continue;
+            };
+            let Some(kept) = output.images.kept.get(image.image) else {
+                continue;
+            };
+            out.queue(MoveTo(pane.x + 1, pane.y + 1 + image.row))?;
+            out.write_all(kept.sequence.as_bytes())?;
+        }
+        out.queue(RestorePosition)?;
+        out.flush()
+    }
+}
+
+/// Read what the terminal answered to the queries `query` sent: kitty's
+/// graphics reply, the cell size and the device attributes, whose `4` is
+/// sixel.
+#[cfg_attr(not(unix), allow(dead_code))]
+fn parse_reply(reply: &[u8]) -> Support {
+    let attributes = Regex::new(r"\x1b\[\?([0-9;]*)c").expect("valid pattern");
+    let cell = Regex::new(r"\x1b\[6;([0-9]+);([0-9]+)t").expect("valid pattern");
+    let sixel = attributes
+        .captures(reply)
+        .is_some_and(|c| c[1].split(|&b| b == b';').any(|p| p == b"4"));
+    let cell_height = cell
+        .captures(reply)
+        .and_then(|c| std::str::from_utf8(&c[1]).ok()?.parse().ok());
+    Support {
+        kitty: reply.windows(10).any(|w| w == b"\x1b_Gi=31;OK"),
+        sixel,
+        cell_height,
+    }
+}
+
+/// Ask the terminal what images it draws. Every terminal answers the
+/// device attributes query, sent last, so its answer ends the wait.
+#[cfg(unix)]
+pub fn query() -> Support {
+    use std::time::{Duration, Instant};
+
+    let mut out = io::stdout();
+    let asked = out
+        .write_all(b"\x1b_Gi=31,s=1,v=1,a=q,t=d,f=24;AAAA\x1b\\\x1b[16t\x1b[c")
+        .and_then(|_| out.flush());
+    if asked.is_err() {
+        return Support::default();
+    }
+    let ended = Regex::new(r"\x1b\[\?[0-9;]*c").expect("valid pattern");
+    let deadline = Instant::now() + Duration::from_millis(300);
+    let mut reply = Vec::new();
+    while !ended.is_match(&reply) {
+        let left = deadline.saturating_duration_since(Instant::now());
+        let mut fd = libc::pollfd {
+            fd: libc::STDIN_FILENO,
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // SAFETY: one pollfd, valid for the call
+        let ready = unsafe { libc::poll(&mut fd, 1, left.as_millis() as libc::c_int) };
+        if ready <= 0 {
+            break;
+        }
+        let mut buf = [0u8; 256];
+        // SAFETY: reads at most buf.len() bytes into buf
+        let read = unsafe { libc::read(libc::STDIN_FILENO, buf.as_mut_ptr().cast(), buf.len()) };
+        if read <= 0 {
+            break;
+        }
+        reply.extend_from_slice(&buf[..read as usize]);
+    }
+    parse_reply(&reply)
+}
+
+/// Without a way to wait on the console for a while, assume none.
+#[cfg(not(unix))]
+pub fn query() -> Support {
+    Support::default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SIXEL: &str = "\x1bPq\"1;1;640;480#0;2;0;0;0#0~~@@vv\x1b\\";
+
+    #[test]
+    fn images_become_placeholders() {
+        let mut images = Images::default();
+        let text = format!("before\n{SIXEL}\nafter \x1b[1mbold\n");
+        let out = images.strip(&text, Support::default());
+        assert_eq!(out, "before\n[image 640x480]\nafter \x1b[1mbold\n");
+        assert!(images.kept.is_empty());
+
+        let iterm = "\x1b]1337;File=name=eA==;width=10;height=5;inline=1:AAAA\x07 done";
+        assert_eq!(images.strip(iterm, Support::default()), "[image 10x5] done");
+        let iterm = "\x1b]1337;File=inline=1:AAAA\x1b\\";
+        assert_eq!(images.strip(iterm, Support::default()), "[image]");
+    }
+
+    #[test]
+    fn an_image_split_across_chunks_waits_for_its_end() {
+        let mut images = Images::default();
+        let support = Support {
+            sixel: true,
+            ..Default::default()
+        };
+        let (first, second) = SIXEL.split_at(12);
+        assert_eq!(images.strip(&format!("a\nb{first}"), support), "a\nb");
+        assert_eq!(images.strip(second, support), "[image 640x480]");
+        assert_eq!(images.kept[0].line, 0);
+        assert_eq!(images.kept[0].sequence, SIXEL);
+        // even the escape alone is held back
+        assert_eq!(images.strip("c\x1b", support), "c");
+        assert_eq!(images.strip("[0m", support), "\x1b[0m");
+        assert_eq!(images.strip("\x1bPq#0", support), "");
+        assert_eq!(images.finish(), "[image]");
+    }
+
+    #[test]
+    fn kitty_chunks_make_one_image() {
+        let mut images = Images::default();
+        let support = Support {
+            kitty: true,
+            ..Default::default()
+        };
+        let chunks = "\x1b_Ga=T,f=32,s=2,v=3,m=1;AAAA\x1b\\\x1b_Gm=1;BBBB\x1b\\\x1b_Gm=0;CC\x1b\\";
+        assert_eq!(images.strip(chunks, support), "[image 2x3]");
+        assert_eq!(images.kept.len(), 1);
+        assert_eq!(
+            images.kept[0].sequence,
+            "\x1b_GC=1,a=T,f=32,s=2,v=3,m=1;AAAA\x1b\\\x1b_Gm=1;BBBB\x1b\\\x1b_Gm=0;CC\x1b\\"
+        );
+        // a query shows nothing
+        assert_eq!(images.strip("\x1b_Ga=q,i=1;AAAA\x1b\\x", support), "x");
+    }
+
+    #[test]
+    fn images_hiding_escapes_arent_drawn() {
+        let mut images = Images::default();
+        let support = Support {
+            sixel: true,
+            kitty: true,
+            ..Default::default()
+        };
+        let sixel = "\x1bPq\"1;1;2;2#0~~\x1b]0;pwned\x07\x1b[2J\x1b\\";
+        assert_eq!(images.strip(sixel, support), "[image 2x2]");
+        assert!(images.kept.is_empty());
+        let chunks =
+            "\x1b_Ga=T,s=2,v=3,m=1;AAAA\x1b\\\x1b_Gm=1;B\x18\x1b[2J\x1b\\\x1b_Gm=0;CC\x1b\\";
+        assert_eq!(images.strip(chunks, support), "[image 2x3]");
+        assert!(images.kept.is_empty());
+        assert_eq!(images.strip(SIXEL, support), "[image 640x480]");
+        assert_eq!(images.kept.len(), 1);
+    }
+
+    #[test]
+    fn terminal_replies() {
+        let reply = b"\x1b_Gi=31;OK\x1b\\\x1b[6;20;10t\x1b[?62;4;22c";
+        let support = parse_reply(reply);
+        assert!(support.kitty && support.sixel);
+        assert_eq!(support.cell_height, Some(20));
+        assert_eq!(parse_reply(b"\x1b[?1;2c"), Support::default());
+    }
+}
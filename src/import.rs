@@ -0,0 +1,362 @@
+//! Bringing in another shell's history: `:import-history zsh|bash [FILE]`,
+//! and on first run whichever of `~/.zsh_history` or `~/.bash_history`
+//! goes with `$SHELL`. The file is read on its own thread and the commands
+//! added to shim's history once it's done.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env, fs,
+    path::{Path, PathBuf},
+    thread,
+};
+
+use crate::{Model, event::AppEvent, history};
+
+/// zsh marks a byte that would clash with its own tokens with this, and
+/// flips bit 5 of the byte after.
+const META: u8 = 0x83;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Shell {
+    Zsh,
+    Bash,
+}
+
+impl Shell {
+    fn name(self) -> &'static str {
+        match self {
+            Shell::Zsh => "zsh",
+            Shell::Bash => "bash",
+        }
+    }
+
+    /// Where the shell keeps its history unless told otherwise.
+    fn default_file(self) -> Option<PathBuf> {
+        let home = env::var_os("HOME").map(PathBuf::from)?;
+        Some(match self {
+            Shell::Zsh => env::var_os("ZDOTDIR")
+                .map_or(home, PathBuf::from)
+                .join(".zsh_history"),
+            Shell::Bash => home.join(".bash_history"),
+        })
+    }
+}
+
+/// A command from the file, with how often it was there and, if the file
+/// keeps them, when it was last run.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Command {
+    pub text: String,
+    pub uses: u32,
+    /// Seconds since the epoch.
+    pub time: Option<u64>,
+}
+
+/// What a history file held, oldest command first.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Parsed {
+    pub commands: Vec<Command>,
+    /// Later runs of a command already seen.
+    pub repeats: usize,
+    /// Lines that couldn't be read, left out.
+    pub unreadable: usize,
+}
+
+/// Undo zsh's metafying of `line`.
+fn unmetafy(line: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::with_capacity(line.len());
+    let mut iter = line.iter();
+    while let Some(&b) = iter.next() {
+        match b {
+            META => bytes.extend(iter.next().map(|b| b ^ 0x20)),
+            _ => bytes.push(b),
+        }
+    }
+    bytes
+}
+
+/// The time and command of an extended history line, `: START:TOOK;CMD`.
+fn extended(line: &str) -> Option<(u64, &str)> {
+    let rest = line.strip_prefix(": ")?;
+    let (stamp, command) = rest.split_once(';')?;
+    let (start, took) = stamp.split_once(':')?;
+    took.trim().parse::<u64>().ok()?;
+    Some((start.trim().parse().ok()?, command))
+}
+
+/// The commands in a zsh history file, plain or extended. A line ending
+/// in `\` goes on to the next, as zsh writes a command spanning lines.
+fn zsh(contents: &[u8]) -> (Vec<(String, Option<u64>)>, usize) {
+    let mut lines = contents.split(|&b| b == b'\n').map(unmetafy);
+    let (mut commands, mut unreadable) = (Vec::new(), 0);
+    while let Some(mut entry) = lines.next() {
+        while entry.ends_with(b"\\") {
+            let Some(next) = lines.next() else {
+                break;
+            };
+            entry.pop();
+            entry.push(b'\n');
+            entry.extend(next);
+        }
+        let Ok(entry) = String::from_utf8(entry) else {
+            unreadable += 1;
+            continue;
+        };
+        if entry.trim().is_empty() {
+            continue;
+        }
+        match extended(&entry) {
+            Some((_, command)) if command.trim().is_empty() => {}
+            Some((time, command)) => commands.push((command.to_string(), Some(time))),
+            None if entry.starts_with(": ") => unreadable += 1,
+            None => commands.push((entry, None)),
+        }
+    }
+    (commands, unreadable)
+}
+
+/// The commands in a bash history file, with the times `HISTTIMEFORMAT`
+/// has it write as `#SECONDS` lines before them.
+fn bash(contents: &[u8]) -> (Vec<(String, Option<u64>)>, usize) {
+    let (mut commands, mut unreadable) = (Vec::new(), 0);
+    let mut time = None;
+    for line in contents.split(|&b| b == b'\n') {
+        let Ok(line) = std::str::from_utf8(line) else {
+            unreadable += 1;
+            time = None;
+            continue;
+        };
+        if let Some(stamp) = line.strip_prefix('#')
+            && let Ok(seconds) = stamp.trim().parse()
+        {
+            time = Some(seconds);
+        } else if !line.trim().is_empty() {
+            commands.push((line.to_string(), time.take()));
+        }
+    }
+    (commands, unreadable)
+}
+
+/// Read a history file written by `shell`, merging repeats of a command
+/// into its last run.
+pub fn parse(shell: Shell, contents: &[u8]) -> Parsed {
+    let (runs, unreadable) = match shell {
+        Shell::Zsh => zsh(contents),
+        Shell::Bash => bash(contents),
+    };
+    let total = runs.len();
+    let mut last: HashMap<&str, (usize, Option<u64>)> = HashMap::new();
+    for (i, (command, time)) in runs.iter().enumerate() {
+        let seen = last.entry(command).or_insert((i, *time));
+        *seen = (i, time.or(seen.1));
+    }
+    let mut uses: HashMap<&str, u32> = HashMap::new();
+    for (command, _) in &runs {
+        *uses.entry(command).or_default() += 1;
+    }
+    let mut commands: Vec<_> = last
+        .iter()
+        .map(|(&text, &(i, time))| {
+            let command = Command {
+                text: text.to_string(),
+                uses: uses[text],
+                time,
+            };
+            (i, command)
+        })
+        .collect();
+    commands.sort_by_key(|(i, _)| *i);
+    Parsed {
+        repeats: total - commands.len(),
+        commands: commands.into_iter().map(|(_, c)| c).collect(),
+        unreadable,
+    }
+}
+
+/// A finished import, or why the file couldn't be read.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    shell: Shell,
+    file: PathBuf,
+    parsed: Result<Parsed, String>,
+}
+
+/// The shell whose history to bring in on first run, going by `$SHELL`
+/// and else whichever file there is.
+fn first_run_shell() -> Option<Shell> {
+    let path = history::path()?;
+    if path.exists() {
+        return None;
+    }
+    let shell = env::var("SHELL").unwrap_or_default();
+    let named = match Path::new(&shell).file_name().and_then(|n| n.to_str()) {
+        Some("zsh") => Some(Shell::Zsh),
+        Some("bash") => Some(Shell::Bash),
+        _ => None,
+    };
+    named
+        .into_iter()
+        .chain([Shell::Zsh, Shell::Bash])
+        .find(|s| s.default_file().is_some_and(|f| f.is_file()))
+}
+
+impl Model {
+    /// `:import-history zsh|bash [FILE]`: start reading another shell's
+    /// history. A summary opens in a buffer once it's done.
+    pub fn import_history(&mut self, args: &[&str]) -> String {
+        let shell = match args.first() {
+            Some(&"zsh") => Shell::Zsh,
+            Some(&"bash") => Shell::Bash,
+            _ => return "usage: :import-history zsh|bash [FILE]".into(),
+        };
+        let file = match args.get(1) {
+            Some(file) => Some(PathBuf::from(file)),
+            None => shell.default_file(),
+        };
+        let (Some(file), Some(events)) = (file, self.events.clone()) else {
+            return format!("no {} history to import", shell.name());
+        };
+        let status = format!("importing {}", file.display());
+        thread::spawn(move || {
+            let parsed = fs::read(&file)
+                .map(|contents| parse(shell, &contents))
+                .map_err(|e| format!("can't read {}: {e}", file.display()));
+            let import = Import {
+                shell,
+                file,
+                parsed,
+            };
+            let _ = events.send(AppEvent::HistoryImported(import));
+        });
+        status
+    }
+
+    /// On the first run, with no history of shim's own yet, bring in the
+    /// shell's.
+    pub fn import_on_first_run(&mut self) {
+        if self.config.history == history::Source::Atuin {
+            return;
+        }
+        if let Some(shell) = first_run_shell() {
+            self.import_history(&[shell.name()]);
+        }
+    }
+
+    /// Add what an import found to the history, before what shim has run
+    /// itself, leaving out commands it already has.
+    pub fn finish_import(&mut self, import: Import) {
+        let command = format!(
+            ":import-history {} {}",
+            import.shell.name(),
+            import.file.display()
+        );
+        let parsed = match import.parsed {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                self.push_output(command, e);
+                return;
+            }
+        };
+        let known: HashSet<_> = self
+            .previous_commands
+            .iter()
+            .map(|e| e.command.as_str())
+            .collect();
+        let (new, old): (Vec<_>, Vec<_>) = parsed
+            .commands
+            .into_iter()
+            .partition(|c| !known.contains(c.text.as_str()));
+        let entries: Vec<_> = new
+            .iter()
+            .map(|c| history::Entry {
+                command: c.text.clone(),
+                cwd: None,
+                context: None,
+                time: c.time,
+            })
+            .collect();
+        if let Err(e) = history::prepend(&entries) {
+            self.push_output(command, format!("can't write the history: {e}"));
+            return;
+        }
+        self.frecency.import(&new);
+        let summary = format!(
+            "imported {} commands from {}\n\
+             skipped {} already in history\n\
+             skipped {} unreadable lines\n\
+             merged {} repeated runs\n",
+            entries.len(),
+            import.file.display(),
+            old.len(),
+            parsed.unreadable,
+            parsed.repeats,
+        );
+        self.previous_commands.splice(0..0, entries);
+        self.push_output(command, summary);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(parsed: &Parsed) -> Vec<&str> {
+        parsed.commands.iter().map(|c| c.text.as_str()).collect()
+    }
+
+    #[test]
+    fn zsh_extended_history_with_multiline_and_metafied_commands() {
+        let mut file = b": 1700000000:0;ls -la\n".to_vec();
+        file.extend(b": 1700000005:2;for f in *; do\\\necho $f\\\ndone\n");
+        // `é` is 0xc3 0xa9; zsh metafies 0xa9 as META 0x89
+        file.extend(b": 1700000009:0;echo caf\xc3\x83\x89\n");
+        file.extend(b": garbage;\n");
+        file.extend(b": 1700000010:0;\xff\xfe\n");
+        file.extend(b": 1700000020:0;ls -la\n");
+        let parsed = parse(Shell::Zsh, &file);
+        assert_eq!(
+            texts(&parsed),
+            ["for f in *; do\necho $f\ndone", "echo café", "ls -la"]
+        );
+        assert_eq!(parsed.commands[2].uses, 2);
+        assert_eq!(parsed.commands[2].time, Some(1700000020));
+        assert_eq!(parsed.repeats, 1);
+        assert_eq!(parsed.unreadable, 2);
+    }
+
+    #[test]
+    fn plain_zsh_and_bash_history() {
+        let parsed = parse(Shell::Zsh, b"make\n\ncargo test\nmake\n");
+        assert_eq!(texts(&parsed), ["cargo test", "make"]);
+        assert_eq!(parsed.commands[0].time, None);
+
+        let parsed = parse(Shell::Bash, b"#1700000000\ngit status\nls\n\xc3\n#oops\n");
+        assert_eq!(texts(&parsed), ["git status", "ls", "#oops"]);
+        assert_eq!(parsed.commands[0].time, Some(1700000000));
+        assert_eq!(parsed.commands[1].time, None);
+        assert_eq!(parsed.unreadable, 1);
+    }
+
+    #[test]
+    fn imported_commands_go_before_shims_own() {
+        let mut model = Model::default();
+        model
+            .previous_commands
+            .push(history::Entry::new("make".into()));
+        let import = Import {
+            shell: Shell::Bash,
+            file: "bash_history".into(),
+            parsed: Ok(parse(Shell::Bash, b"make\ngit pull\nmake\n")),
+        };
+        model.finish_import(import);
+        let commands: Vec<_> = model.previous_commands.iter().map(|e| &e.command).collect();
+        assert_eq!(commands, ["git pull", "make"]);
+        assert_eq!(
+            model.outputs[0].stdout,
+            "imported 1 commands from bash_history\n\
+             skipped 1 already in history\n\
+             skipped 0 unreadable lines\n\
+             merged 1 repeated runs\n"
+        );
+    }
+}
@@ -0,0 +1,566 @@
+//! Random runs of messages against a model with random commands and
+//! output, checking after every step that `update` didn't panic and the
+//! cursor and scroll stayed where they may be.
+
+use std::path::PathBuf;
+
+use proptest::prelude::*;
+use ratatui::crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+
+use crate::{
+    Cursor, Focus, Message, Mode, Model, Overlay, display, history,
+    killring::Direction,
+    picker::{Picker, PickerKind},
+    update,
+};
+
+/// Text with the characters that make columns tricky: wide ones,
+/// combining accents, tabs and blanks.
+fn text(lines: usize) -> impl Strategy<Value = String> {
+    proptest::collection::vec("[a-c 1.\t'\"é日\u{301}-]{0,12}", 0..=lines)
+        .prop_map(|lines| lines.join("\n"))
+}
+
+/// Messages a user can send that neither run anything nor touch files.
+fn message() -> impl Strategy<Value = Message> {
+    use Message::*;
+    let plain: Vec<fn() -> Message> = vec![
+        || Down,
+        || Up,
+        || Left,
+        || Right,
+        || LineStart,
+        || LineEnd,
+        || WordForward,
+        || WordBack,
+        || Top,
+        || Bottom,
+        || RowDown,
+        || RowUp,
+        || ScrollDown,
+        || ScrollUp,
+        || ScrollFrame,
+        || OutCommand,
+        || InCommand,
+        || NextOutput,
+        || PreviousOutput,
+        || ToggleViewLock,
+        || JumpBack,
+        || Newline,
+        || Backspace,
+        || Kill(Direction::Back),
+        || Kill(Direction::Forward),
+        || KillLine(Direction::Back),
+        || KillLine(Direction::Forward),
+        || YankKill,
+        || YankPop,
+        || DeleteLine,
+        || ToggleCase,
+        || ChangeWordCase { upper: true },
+        || Paste,
+        || RepeatChange,
+        || Normal,
+        || InsertBefore,
+        || InsertAfter,
+        || InsertBeforeLine,
+        || InsertAfterLine,
+        || Yank,
+        || Visual,
+        || EndVisual,
+        || YankSelection,
+        || ExpandLine,
+        || TakeCommand,
+        || InsertFromOutput,
+        || InsertLastArgument,
+        || CloseLinePopup,
+        || NextWorkspace,
+        || PreviousWorkspace,
+        || CancelPending,
+    ];
+    let operator = prop_oneof![Just('c'), Just('d'), Just('y')];
+    prop_oneof![
+        4 => proptest::sample::select(plain).prop_map(|message| message()),
+        2 => "[a 'é日\u{301}]".prop_map(|c| WriteCommandChar(c.chars().next().unwrap())),
+        1 => "[ab]".prop_map(|c| SetMark(c.chars().next().unwrap())),
+        1 => "[ab]".prop_map(|c| JumpToMark(c.chars().next().unwrap())),
+        1 => (-3i64..=3).prop_map(Increment),
+        1 => (1u8..=3).prop_map(Count),
+        1 => (-9isize..=9).prop_map(ScrollLinePopup),
+        1 => "[a0\"]".prop_map(|c| SelectRegister(c.chars().next().unwrap())),
+        1 => "[gmyd]".prop_map(|c| PendingKey(c.chars().next().unwrap())),
+        1 => (operator, any::<bool>(), any::<bool>()).prop_map(|(operator, around, argument)| {
+            TextObject {
+                operator,
+                around,
+                argument,
+            }
+        }),
+    ]
+}
+
+/// A model with some history, some buffers of output and a command line,
+/// sized as if drawn.
+fn model() -> impl Strategy<Value = Model> {
+    (
+        proptest::collection::vec(text(2), 0..4),
+        proptest::collection::vec(text(30), 0..4),
+        text(3),
+        (1u16..30, 1u16..12, any::<bool>(), 1usize..20),
+    )
+        .prop_map(
+            |(commands, outputs, command, (width, height, wrap, long_line))| {
+                let mut model = Model {
+                    previous_commands: commands
+                        .into_iter()
+                        .map(|command| history::Entry {
+                            command,
+                            cwd: None,
+                            context: None,
+                            time: None,
+                        })
+                        .collect(),
+                    width,
+                    height,
+                    ..Default::default()
+                };
+                model.config.wrap = wrap;
+                model.config.long_line = long_line;
+                for (i, stdout) in outputs.into_iter().enumerate() {
+                    model.push_output(format!("seed {i}"), stdout);
+                }
+                model.current_command = command;
+                model
+            },
+        )
+}
+
+/// What must hold between messages.
+fn check(model: &Model) -> Result<(), TestCaseError> {
+    prop_assert!(model.outputs.is_empty() || model.viewing_output < model.outputs.len());
+    match model.cursor {
+        Cursor::CommandLine(x, y) => {
+            prop_assert!(
+                y < model.get_command_rows(),
+                "row {y} of {:?}",
+                model.command()
+            );
+            let row = model.command_row(y);
+            let x = x as usize;
+            match model.mode {
+                Mode::Insert | Mode::Replace => {
+                    prop_assert!(x <= row.len(), "column {x} of {row:?}")
+                }
+                // on a character, unless there are none
+                Mode::Normal => prop_assert!(
+                    x < row.len() || x == 0,
+                    "column {x} of {row:?} in normal mode"
+                ),
+            }
+        }
+        Cursor::OutputBuffer(x, y) => {
+            let output = model.outputs.get(model.viewing_output);
+            let lines = output.map_or(0, |o| o.line_count() - o.scroll.0);
+            prop_assert!(
+                (y as usize) < lines.max(1),
+                "line {y} of {lines} below the top"
+            );
+            prop_assert!(
+                y < model.height.max(1),
+                "line {y} of a view {} high",
+                model.height
+            );
+            let line = model.output_line(y).unwrap_or("");
+            let len = display::len(line);
+            prop_assert!((x as usize) < len.max(1), "position {x} of {line:?}");
+        }
+    }
+    for (i, output) in model.outputs.iter().enumerate() {
+        let lines = output.line_count();
+        prop_assert!(
+            output.scroll.0 <= lines,
+            "buffer {i} scrolled to {} of {lines} lines",
+            output.scroll.0
+        );
+    }
+    Ok(())
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(256))]
+
+    #[test]
+    fn the_cursor_stays_where_it_may(
+        mut model in model(),
+        messages in proptest::collection::vec(message(), 1..60),
+    ) {
+        model.clamp_command_cursor();
+        check(&model)?;
+        for msg in messages {
+            let mut next = Some(msg);
+            while let Some(msg) = next {
+                next = update(&mut model, msg);
+            }
+            check(&model)?;
+        }
+    }
+}
+
+/// One of every message, with made-up contents where it has any, but for
+/// `HistoryImported` and `HistoryLoaded`, as those only come from reading
+/// a history file.
+fn every_message() -> Vec<Message> {
+    use Message::*;
+    let messages = vec![
+        Down,
+        Up,
+        Submit,
+        Quit,
+        NextOutput,
+        PreviousOutput,
+        WriteCommandChar('x'),
+        Newline,
+        Normal,
+        InsertBefore,
+        InsertAfter,
+        Backspace,
+        EnterReplace,
+        ReplaceChar('x'),
+        ReplaceBackspace,
+        Kill(Direction::Back),
+        KillLine(Direction::Forward),
+        YankKill,
+        YankPop,
+        OutCommand,
+        InCommand,
+        ScrollDown,
+        ScrollUp,
+        Left,
+        Right,
+        LineStart,
+        LineEnd,
+        ScrubBack,
+        ScrubForward,
+        ToggleBookmark,
+        NextBookmark,
+        PreviousBookmark,
+        RerunAppending,
+        InsertBeforeLine,
+        InsertAfterLine,
+        AcceptSuggestion,
+        Preview,
+        PendingKey('g'),
+        PendingObject {
+            operator: 'd',
+            around: true,
+        },
+        TextObject {
+            operator: 'c',
+            around: false,
+            argument: true,
+        },
+        DeleteLine,
+        RepeatChange,
+        RunLast,
+        Count(3),
+        CancelPending,
+        SetMark('a'),
+        JumpToMark('a'),
+        JumpBack,
+        Top,
+        Bottom,
+        OutputChunk(0, b"more\n".to_vec()),
+        JobExited(0, Some(1), vec![0, 1]),
+        ReadFailed(0, "broken pipe".into()),
+        OpenDirectoryPicker,
+        OpenSnippetPicker,
+        ExpandSnippet,
+        SnippetChar('x'),
+        SnippetBackspace,
+        SnippetSubmit,
+        SnippetCancel,
+        PickerChar('e'),
+        PickerBackspace,
+        PickerUp,
+        PickerDown,
+        PickerAccept,
+        PickerCancel,
+        PickerMove(1),
+        PickerRemove,
+        PickerMark,
+        PickerExpand(true),
+        OpenFilePicker,
+        OpenBufferPicker,
+        UndoClose,
+        OpenLink,
+        OpenLinkPicker,
+        PickLink(0),
+        Files(0, vec!["src".into()], true),
+        Derived(0, "sorted".into()),
+        Previewed("ls | head".into(), Err("head: exit 1".into())),
+        Piped(0, 0, "jq .".into(), Err("pipe: jq: exit 5".into())),
+        Enqueue,
+        InsertComment,
+        Interrupt,
+        AbandonQueue(true),
+        Confirm(true),
+        AllowEnv(false),
+        ConfirmQuit(false),
+        Retry,
+        Tick,
+        OpenHistoryPicker,
+        AcceptGhost,
+        ToggleHistoryHere,
+        ReplaySkip,
+        ReplayStop,
+        Resize(30, 8),
+        Mouse(MouseEvent {
+            kind: MouseEventKind::Down(MouseButton::Left),
+            column: 3,
+            row: 2,
+            modifiers: KeyModifiers::NONE,
+        }),
+        ToggleViewLock,
+        ToggleCase,
+        ChangeWordCase { upper: false },
+        Increment(2),
+        SelectRegister('a'),
+        Complete,
+        CompletionNext,
+        CompletionPrevious,
+        CompletionAccept,
+        CompletionCancel,
+        Completions(("git".into(), PathBuf::from(".")), Some(Vec::new())),
+        Segment("git".into(), PathBuf::from("."), "main".into()),
+        Yank,
+        Visual,
+        EndVisual,
+        ExpandLine,
+        TakeCommand,
+        InsertFromOutput,
+        InsertLastArgument,
+        OpenArgumentPicker,
+        ScrollLinePopup(2),
+        CloseLinePopup,
+        NextWorkspace,
+        PreviousWorkspace,
+        YankSelection,
+        YankColumn,
+        WordForward,
+        WordBack,
+        Paste,
+        RowDown,
+        RowUp,
+        ScrollFrame,
+        ErrorChunk(0, b"warning\n".to_vec()),
+        ToggleSplit,
+        SwitchHalf,
+        Suspend,
+        Continued,
+    ];
+    // fails to build when a message is added, until it's added above too
+    for message in &messages {
+        match message {
+            Down
+            | Up
+            | Submit
+            | Quit
+            | NextOutput
+            | PreviousOutput
+            | WriteCommandChar(_)
+            | Newline
+            | Normal
+            | InsertBefore
+            | InsertAfter
+            | EnterReplace
+            | ReplaceChar(_)
+            | ReplaceBackspace
+            | Backspace
+            | Kill(_)
+            | KillLine(_)
+            | YankKill
+            | YankPop
+            | OutCommand
+            | InCommand
+            | ScrollDown
+            | ScrollUp
+            | Left
+            | Right
+            | LineStart
+            | LineEnd
+            | ScrubBack
+            | ToggleBookmark
+            | NextBookmark
+            | PreviousBookmark
+            | RerunAppending
+            | ScrubForward
+            | InsertBeforeLine
+            | InsertAfterLine
+            | AcceptSuggestion
+            | Preview
+            | PendingKey(_)
+            | PendingObject { .. }
+            | TextObject { .. }
+            | DeleteLine
+            | RepeatChange
+            | RunLast
+            | Count(_)
+            | CancelPending
+            | SetMark(_)
+            | JumpToMark(_)
+            | JumpBack
+            | Top
+            | Bottom
+            | OutputChunk(..)
+            | JobExited(..)
+            | ReadFailed(..)
+            | OpenDirectoryPicker
+            | OpenSnippetPicker
+            | ExpandSnippet
+            | SnippetChar(_)
+            | SnippetBackspace
+            | SnippetSubmit
+            | SnippetCancel
+            | PickerChar(_)
+            | PickerBackspace
+            | PickerUp
+            | PickerDown
+            | PickerAccept
+            | PickerCancel
+            | PickerMove(_)
+            | PickerRemove
+            | PickerMark
+            | PickerExpand(_)
+            | OpenFilePicker
+            | OpenBufferPicker
+            | UndoClose
+            | OpenLink
+            | OpenLinkPicker
+            | PickLink(_)
+            | Files(..)
+            | HistoryImported(_)
+            | HistoryLoaded(_)
+            | Derived(..)
+            | Previewed(..)
+            | Piped(..)
+            | Enqueue
+            | InsertComment
+            | Interrupt
+            | AbandonQueue(_)
+            | Confirm(_)
+            | AllowEnv(_)
+            | ConfirmQuit(_)
+            | Retry
+            | Tick
+            | OpenHistoryPicker
+            | AcceptGhost
+            | ToggleHistoryHere
+            | ReplaySkip
+            | ReplayStop
+            | Resize(..)
+            | Mouse(_)
+            | ToggleViewLock
+            | ToggleCase
+            | ChangeWordCase { .. }
+            | Increment(_)
+            | SelectRegister(_)
+            | Complete
+            | CompletionNext
+            | CompletionPrevious
+            | CompletionAccept
+            | CompletionCancel
+            | Completions(..)
+            | Segment(..)
+            | Yank
+            | Visual
+            | EndVisual
+            | ExpandLine
+            | TakeCommand
+            | InsertFromOutput
+            | InsertLastArgument
+            | OpenArgumentPicker
+            | ScrollLinePopup(_)
+            | CloseLinePopup
+            | NextWorkspace
+            | PreviousWorkspace
+            | YankSelection
+            | YankColumn
+            | WordForward
+            | WordBack
+            | Paste
+            | RowDown
+            | RowUp
+            | ScrollFrame
+            | ErrorChunk(..)
+            | ToggleSplit
+            | SwitchHalf
+            | Suspend
+            | Continued => {}
+        }
+    }
+    messages
+}
+
+/// Puts focus somewhere on a model.
+type Setup = fn(&mut Model);
+
+/// Ways to put focus somewhere, with where it should then be.
+fn focus_states() -> Vec<(Focus, Setup)> {
+    vec![
+        (Focus::CommandLine, |_| {}),
+        (Focus::CommandLine, |m| m.mode = Mode::Normal),
+        (Focus::Output, |m| {
+            m.mode = Mode::Normal;
+            m.cursor = Cursor::OutputBuffer(1, 1);
+        }),
+        (Focus::Overlay(Overlay::AbandonQueue), |m| {
+            m.queue.asking = true
+        }),
+        (Focus::Overlay(Overlay::Quit), |m| m.quitting = true),
+        (Focus::Overlay(Overlay::Dangerous), |m| {
+            m.confirming = Some(false)
+        }),
+        (Focus::Overlay(Overlay::EnvFile), |m| {
+            m.env_prompt = Some(PathBuf::from("/nonexistent/.env"))
+        }),
+        (Focus::Overlay(Overlay::Snippet), |m| {
+            m.snippets.insert("greet".into(), "echo {name}".into());
+            m.start_snippet("greet");
+        }),
+        (Focus::Overlay(Overlay::Picker), |m| {
+            m.picker = Some(Picker::new(PickerKind::History, vec!["echo hi".into()]))
+        }),
+        (Focus::Overlay(Overlay::LinePopup), |m| {
+            m.cursor = Cursor::OutputBuffer(0, 0);
+            m.expand_line();
+        }),
+    ]
+}
+
+#[test]
+fn every_message_is_safe_wherever_focus_is() {
+    for (focus, setup) in focus_states() {
+        for message in every_message() {
+            let mut model = Model {
+                width: 20,
+                height: 5,
+                current_command: "echo hi".into(),
+                ..Default::default()
+            };
+            model.push_output("seq 3".into(), "1\n22\n333\n".into());
+            model.clamp_command_cursor();
+            setup(&mut model);
+            assert_eq!(model.focus(), focus);
+            let name = format!("{message:?} with focus on {focus:?}");
+            let mut next = Some(message);
+            for _ in 0..10 {
+                let Some(message) = next else {
+                    break;
+                };
+                next = update(&mut model, message);
+            }
+            if let Err(e) = check(&model) {
+                panic!("{name}: {e}");
+            }
+        }
+    }
+}
@@ -0,0 +1,472 @@
+use std::{
+    io::{self, Read},
+    ops::ControlFlow,
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex, atomic::AtomicBool, mpsc::Sender},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use regex::Regex;
+
+use crate::{
+    Model, Output, encoding,
+    event::AppEvent,
+    exit, image, jobs, path_index, reap,
+    shell::{self, ParsedCommand},
+    stats, vars,
+};
+
+pub const INVALID_UTF8: &str = "output that wasn't UTF-8 is shown as �";
+
+/// A running job's process, shared with the thread reading from it so that
+/// it can be killed.
+pub type Process = Arc<Mutex<Child>>;
+
+/// Start `commands` in the background, each one's output going into the
+/// next's input, streaming the last one's stdout back as `OutputChunk`s
+/// and all of their stderr as `ErrorChunk`s, followed by a `JobExited`.
+/// The process returned is the first, along with what the job holds until
+/// it's reaped.
+pub fn spawn(
+    job_id: usize,
+    commands: Vec<Command>,
+    events: Sender<AppEvent>,
+) -> io::Result<(Process, reap::Handles)> {
+    let mut children: Vec<Process> = Vec::new();
+    let mut stdout = None;
+    // one pipe for every stage's stderr
+    let (mut stderr, error_writer) = io::pipe()?;
+    for mut command in commands {
+        // in a group of its own, led by the first, so `kill %N` reaches
+        // everything it started
+        #[cfg(unix)]
+        {
+            let leader = children.first().and_then(|c| c.lock().ok().map(|c| c.id()));
+            std::os::unix::process::CommandExt::process_group(
+                &mut command,
+                leader.unwrap_or(0) as i32,
+            );
+        }
+        let stdin = stdout.take().map_or_else(Stdio::null, Stdio::from);
+        let spawned = command
+            .stdin(stdin)
+            .stdout(Stdio::piped())
+            .stderr(error_writer.try_clone()?)
+            .spawn();
+        let mut child = match spawned {
+            Ok(child) => child,
+            Err(e) => {
+                for child in &children {
+                    let _ = child.lock().map(|mut c| c.kill().and_then(|_| c.wait()));
+                }
+                return Err(e);
+            }
+        };
+        stdout = child.stdout.take();
+        children.push(Arc::new(Mutex::new(child)));
+    }
+    // or the pipe would never end
+    drop(error_writer);
+    let process = children
+        .first()
+        .cloned()
+        .ok_or(io::ErrorKind::InvalidInput)?;
+    #[cfg(unix)]
+    let fd = stdout.as_ref().map(std::os::fd::AsRawFd::as_raw_fd);
+    #[cfg(not(unix))]
+    let fd = None;
+    let close = Arc::new(AtomicBool::new(false));
+    let closing = close.clone();
+    let waited = children.clone();
+    let error_events = events.clone();
+    let error_closing = close.clone();
+    let reader = thread::spawn(move || {
+        let children = waited;
+        let errors = thread::spawn(move || {
+            let mut buf = [0; 8192];
+            loop {
+                let data = match read_unless_closed(&mut stderr, &mut buf, &error_closing) {
+                    Ok(0) => break,
+                    Ok(n) => buf[..n].to_vec(),
+                    Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                    Err(_) => break,
+                };
+                if error_events
+                    .send(AppEvent::ErrorChunk { job_id, data })
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+        let mut buf = [0; 8192];
+        while let Some(read) = stdout
+            .as_mut()
+            .map(|s| read_unless_closed(s, &mut buf, &closing))
+        {
+            let event = match read {
+                Ok(0) => break,
+                Ok(n) => AppEvent::OutputChunk {
+                    job_id,
+                    data: buf[..n].to_vec(),
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let error = e.to_string();
+                    let _ = events.send(AppEvent::ReadFailed { job_id, error });
+                    break;
+                }
+            };
+            if events.send(event).is_err() {
+                return;
+            }
+        }
+        let statuses: Vec<_> = children.iter().map(wait).collect();
+        // all of stderr comes before the end
+        let _ = errors.join();
+        let status = statuses.last().copied().flatten().and_then(|s| s.code());
+        let stages = match statuses.len() {
+            1 => Vec::new(),
+            _ => statuses.into_iter().map(stage_code).collect(),
+        };
+        let _ = events.send(AppEvent::JobExited {
+            job_id,
+            status,
+            stages,
+        });
+    });
+    let handles = reap::Handles::new(job_id, children, reader, close, fd);
+    Ok((process, handles))
+}
+
+/// Read from `pipe`, or find nothing more once `close` is set.
+#[cfg(unix)]
+fn read_unless_closed(
+    pipe: &mut (impl Read + std::os::fd::AsRawFd),
+    buf: &mut [u8],
+    close: &AtomicBool,
+) -> io::Result<usize> {
+    use std::sync::atomic::Ordering;
+    loop {
+        if close.load(Ordering::Relaxed) {
+            return Ok(0);
+        }
+        let mut fd = libc::pollfd {
+            fd: pipe.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        // wake now and then to see whether to give up
+        match unsafe { libc::poll(&mut fd, 1, 100) } {
+            0 => continue,
+            -1 => return Err(io::Error::last_os_error()),
+            _ => return pipe.read(buf),
+        }
+    }
+}
+
+/// Windows can't wait on a pipe with a timeout, so a pipe held open there
+/// is read until it's closed.
+#[cfg(not(unix))]
+fn read_unless_closed(
+    pipe: &mut impl Read,
+    buf: &mut [u8],
+    _close: &AtomicBool,
+) -> io::Result<usize> {
+    pipe.read(buf)
+}
+
+/// How `child` ended, once it has.
+fn wait(child: &Process) -> Option<ExitStatus> {
+    // poll rather than wait, which would hold the lock and block a kill
+    loop {
+        match child.lock().map(|mut c| c.try_wait()) {
+            Ok(Ok(Some(status))) => return Some(status),
+            Ok(Ok(None)) => thread::sleep(Duration::from_millis(10)),
+            _ => return None,
+        }
+    }
+}
+
+/// A pipeline stage's exit code, as `$?` would give it.
+fn stage_code(status: Option<ExitStatus>) -> i32 {
+    #[cfg(unix)]
+    let signal = status.and_then(|s| std::os::unix::process::ExitStatusExt::signal(&s));
+    #[cfg(not(unix))]
+    let signal = None;
+    exit::code(status.and_then(|s| s.code()), signal)
+}
+
+/// The text of `data` after the bytes held back in `pending`, holding back
+/// a character it ends partway through, and whether any of it wasn't UTF-8.
+pub fn decode(pending: &mut Vec<u8>, data: &[u8]) -> (String, bool) {
+    pending.extend_from_slice(data);
+    let (valid, invalid) = match std::str::from_utf8(pending) {
+        Ok(s) => (s.len(), false),
+        Err(e) if e.error_len().is_none() => (e.valid_up_to(), false),
+        Err(_) => (pending.len(), true),
+    };
+    let rest = pending.split_off(valid);
+    let text = String::from_utf8_lossy(pending).into_owned();
+    *pending = rest;
+    (text, invalid)
+}
+
+impl Output {
+    /// Add a chunk of output. A multi-byte character split across chunks is
+    /// held back until the rest of it arrives, as is an image.
+    pub fn append(&mut self, data: &[u8], redact: &[Regex], images: image::Support) {
+        let (text, invalid) = self.decoder.decode(data);
+        if invalid {
+            self.warn(self.decoder.warning());
+        }
+        let text = self.strip_images(&text, images);
+        self.copy_stdout(&text, redact);
+        self.ingest(&text, redact);
+    }
+
+    /// Stop the job writing to this buffer, if there is one. All of a
+    /// pipeline is stopped, not just its first stage.
+    pub fn kill(&mut self) -> bool {
+        let Some(mut child) = self.process.as_ref().and_then(|p| p.lock().ok()) else {
+            return false;
+        };
+        let line = vars::split_capture(&self.command).map_or(self.command.as_str(), |(_, l)| l);
+        if cfg!(unix) && shell::pipeline(line).len() > 1 {
+            return jobs::send(child.id(), "KILL").is_ok();
+        }
+        child.kill().is_ok()
+    }
+}
+
+impl Model {
+    /// Run `command` in the background into a new buffer. With `capture`,
+    /// its output is also stored in that session variable when it ends.
+    pub fn start_job(&mut self, command: String, capture: Option<String>) {
+        let line = match vars::split_capture(&command) {
+            Some((_, line)) => line,
+            None => &command,
+        };
+        let Some(stages) = self.parse_pipeline(line) else {
+            return;
+        };
+        if self.config.unset_var_error
+            && let Some(name) = stages.iter().find_map(vars::unbound)
+        {
+            self.push_output(command.clone(), format!("shim: unbound variable: {name}"));
+            self.set_last_status(1);
+            return;
+        }
+        let programs: Vec<_> = stages.iter().map(|s| s.program.clone()).collect();
+        let encoding = self.next_encoding.take().or(self.config.encoding);
+        match self.spawn_stages(stages, self.next_output_id) {
+            Ok(process) => {
+                let viewing = self.outputs.get(self.viewing_output).map(|o| o.id);
+                self.push_output(command, String::new());
+                // old buffers may have been closed to make room
+                let viewing = self.outputs.iter().position(|o| Some(o.id) == viewing);
+                if self.view_locked {
+                    self.view_output(viewing.unwrap_or(self.outputs.len() - 1));
+                }
+                if let Some(output) = self.outputs.last_mut() {
+                    output.running = true;
+                    output.mark_started();
+                    output.decoder = encoding::Decoder::new(encoding);
+                    output.capture = capture;
+                    output.process = Some(process);
+                }
+            }
+            // exit statuses as sh would give them
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                let program = self.failed_program(programs);
+                let executables = path_index::executables().iter().map(String::as_str);
+                self.push_not_found(command, &program, executables);
+                self.set_last_status(127);
+            }
+            Err(e) => {
+                let program = self.failed_program(programs);
+                self.push_output(command, String::new());
+                if let Some(output) = self.outputs.last_mut() {
+                    output.warn(format!("couldn't start {program}: {e}"));
+                }
+                self.set_last_status(126);
+            }
+        }
+    }
+
+    /// Which of a pipeline's `programs` couldn't be started: the first
+    /// that isn't found, or else the first.
+    fn failed_program(&self, mut programs: Vec<String>) -> String {
+        let missing = programs
+            .iter()
+            .position(|p| shell::resolve(p, |name| self.var(name)).is_none());
+        programs.swap_remove(missing.unwrap_or(0))
+    }
+
+    /// The process for `parsed`, with the session's variables set.
+    pub fn process(&self, parsed: ParsedCommand) -> Command {
+        let mut process = shell::command(parsed);
+        process.envs(self.overlay_vars());
+        process.envs(self.exported_vars());
+        process
+    }
+
+    /// Each stage of the pipeline `line` in the run context, parsed, or
+    /// `None` if one has nothing to run. Most commands are a single stage.
+    pub fn parse_pipeline(&self, line: &str) -> Option<Vec<ParsedCommand>> {
+        let line = self.in_context(line);
+        shell::pipeline(&line)
+            .into_iter()
+            .map(|stage| self.parse(stage))
+            .collect()
+    }
+
+    /// Start `stages` with the env file's variables and the exported
+    /// session variables, their output going to buffer `job_id`.
+    pub fn spawn_stages(
+        &mut self,
+        stages: Vec<ParsedCommand>,
+        job_id: usize,
+    ) -> io::Result<Process> {
+        let commands = stages.into_iter().map(|s| self.process(s)).collect();
+        let events = self.events.clone().ok_or(io::ErrorKind::NotConnected)?;
+        let (process, handles) = spawn(job_id, commands, events)?;
+        self.reaper.track(handles);
+        Ok(process)
+    }
+
+    pub fn set_last_status(&mut self, status: i32) {
+        if let Some(output) = self.outputs.last_mut() {
+            output.status = Some(status);
+        }
+        self.record_exit(status);
+    }
+
+    /// Whether output `index` is scrolled to its end, to stay there as
+    /// more comes in.
+    pub fn following(&self, index: usize) -> bool {
+        !self.outputs[index].held && self.outputs[index].scroll_goal() >= self.bottom(index)
+    }
+
+    pub fn append_output(&mut self, job_id: usize, data: &[u8]) {
+        let images = self.image_support();
+        if let Some(index) = self.outputs.iter().position(|o| o.id == job_id) {
+            let following = self.following(index);
+            let halves = self.halves_following(index);
+            let output = &mut self.outputs[index];
+            let before = output.stdout.len();
+            output.append(data, &self.config.redact, images);
+            output.checkpoint(
+                self.config.checkpoint_interval,
+                self.config.checkpoint_limit,
+            );
+            // before any of it is dropped
+            if let Some(recorder) = &self.recorder {
+                recorder.output(&output.stdout[before..]);
+            }
+            self.cap_lines(index, following);
+            if following {
+                let bottom = self.bottom(index);
+                self.outputs[index].set_scroll(bottom);
+            }
+            self.keep_halves_following(index, halves);
+        }
+    }
+
+    pub fn finish_job(&mut self, job_id: usize, status: Option<i32>) {
+        if self.outputs.iter().any(|o| o.id == job_id && o.restart) {
+            self.finish_history(job_id, status);
+            self.run_again(job_id, false);
+            return;
+        }
+        let mut signal = None;
+        let images = self.image_support();
+        if let Some(index) = self.outputs.iter().position(|o| o.id == job_id) {
+            let following = self.following(index);
+            let halves = self.halves_following(index);
+            let output = &mut self.outputs[index];
+            signal = output.exit_signal();
+            let (rest, invalid) = output.decoder.finish();
+            if invalid {
+                output.warn(output.decoder.warning());
+            }
+            let before = output.stdout.len();
+            let rest = output.strip_images(&rest, images);
+            let rest = rest + &output.finish_images();
+            output.copy_stdout(&rest, &self.config.redact);
+            output.ingest(&rest, &self.config.redact);
+            output.finish_error(&self.config.redact);
+            output.flush(&self.config.redact);
+            if let Some(recorder) = &self.recorder {
+                recorder.output(&output.stdout[before..]);
+            }
+            // the last line may only now be shown, if it had no newline
+            if following {
+                let bottom = self.bottom(index);
+                self.outputs[index].set_scroll(bottom);
+            }
+            self.keep_halves_following(index, halves);
+            let output = &mut self.outputs[index];
+            output.running = false;
+            output.ended_at = Some(SystemTime::now());
+            output.process = None;
+            output.status = status;
+            output.close_section(status);
+            if output.schedule_retry(status, &self.config.redact) {
+                return;
+            }
+        }
+        let ControlFlow::Break(status) = self.next_item(job_id, status) else {
+            return;
+        };
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.id == job_id) {
+            output.status = status;
+            output.duration = output.started.map(|s| s.elapsed());
+            self.frecency.finished(&output.command, status);
+            stats::log(&output.command, status, output.duration.unwrap_or_default());
+            if let Some(name) = output.capture.take() {
+                let value = output.raw_stdout().to_string();
+                self.status = Some(self.set_var(&name, value));
+            }
+        }
+        let code = exit::code(status, signal);
+        self.record_exit(code);
+        if let Some(output) = self.outputs.iter().find(|o| o.id == job_id) {
+            let (command, took) = (output.command.clone(), output.duration.unwrap_or_default());
+            self.run_hooks(&command, code, took);
+        }
+        self.refresh_segments();
+        self.toast_unseen_exit(job_id, code);
+        self.repipe(job_id);
+        if let Some(index) = self.outputs.iter().position(|o| o.id == job_id) {
+            self.detect_table(index);
+        }
+        self.evict_to(self.config.memory_cap);
+        self.finish_history(job_id, status);
+        self.finish_step(job_id, status);
+        self.rerun_finished(job_id, status);
+        self.finish_queued(job_id, status);
+    }
+
+    /// Ctrl-C: cancel the viewed buffer's next retry, or stop the running
+    /// queued command, or the viewed buffer's `:each` run, or else the job
+    /// in the viewed buffer.
+    pub fn interrupt(&mut self) {
+        if self.cancel_retry() || self.interrupt_queue() || self.stop_each() {
+            return;
+        }
+        let killed = self
+            .outputs
+            .get_mut(self.viewing_output)
+            .filter(|o| o.running)
+            .is_some_and(|o| {
+                o.retry = None;
+                o.restart = false;
+                o.kill()
+            });
+        if !killed {
+            self.status = Some("nothing to interrupt".into());
+        }
+    }
+}
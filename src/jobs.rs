@@ -0,0 +1,382 @@
+//! Job control, as in a shell. `jobs` opens a table of the running
+//! commands that keeps up as they finish, `kill [-SIGNAL] %N` signals one
+//! and `fg %N` views its buffer. `%N` names a job by its buffer's id, which
+//! doesn't change as other buffers come and go.
+
+use std::process::{Command, Stdio};
+
+use crate::{
+    Model, Output,
+    picker::{Picker, PickerKind},
+    usage,
+};
+
+/// Signals `kill` takes by name, with the numbers that are the same on
+/// every system.
+const SIGNALS: [(&str, Option<u8>); 9] = [
+    ("HUP", Some(1)),
+    ("INT", Some(2)),
+    ("QUIT", Some(3)),
+    ("KILL", Some(9)),
+    ("TERM", Some(15)),
+    ("USR1", None),
+    ("USR2", None),
+    ("STOP", None),
+    ("CONT", None),
+];
+
+/// `9`, `KILL`, `kill` or `SIGKILL` as the signal's name.
+fn parse_signal(text: &str) -> Option<&'static str> {
+    let upper = text.to_ascii_uppercase();
+    let name = upper.strip_prefix("SIG").unwrap_or(&upper);
+    SIGNALS
+        .iter()
+        .find(|(n, number)| *n == name || number.is_some_and(|k| k.to_string() == name))
+        .map(|(n, _)| *n)
+}
+
+/// The number of signal `name`, where it's the same on every system.
+pub fn signal_number(name: &str) -> Option<i32> {
+    let (_, number) = SIGNALS.iter().find(|(n, _)| *n == name)?;
+    number.map(i32::from)
+}
+
+/// `%N` as the id of the buffer it names.
+fn parse_job(text: &str) -> Option<usize> {
+    let n: usize = text.strip_prefix('%')?.parse().ok()?;
+    n.checked_sub(1)
+}
+
+/// A buffer's job as `%N`.
+fn job_name(id: usize) -> String {
+    format!("%{}", id + 1)
+}
+
+impl Output {
+    /// Send `signal` to the job's process group.
+    fn signal(&mut self, signal: &'static str) -> Result<(), String> {
+        let process = self.process.as_ref().filter(|_| self.running);
+        let pid = process
+            .and_then(|p| p.lock().ok().map(|child| child.id()))
+            .ok_or("not running")?;
+        send(pid, signal)?;
+        self.signal = Some(signal);
+        Ok(())
+    }
+
+    /// How a job killed by a signal from `kill` ended, for the title.
+    pub fn signal_note(&self) -> Option<String> {
+        let signal = self
+            .signal
+            .filter(|_| !self.running && self.status.is_none())?;
+        Some(format!("terminated, SIG{signal}"))
+    }
+
+    /// Running, or how it ended.
+    pub fn state(&self) -> String {
+        match (self.running, self.status) {
+            (true, _) if self.held => "running, held".to_string(),
+            (true, _) => "running".into(),
+            (false, Some(0)) => "done".into(),
+            (false, Some(code)) => format!("exit {code}"),
+            (false, None) => match self.signal {
+                Some(signal) => format!("SIG{signal}"),
+                None => "killed".into(),
+            },
+        }
+    }
+
+    /// One row of the `jobs` table.
+    fn job_row(&self, index: usize) -> String {
+        let pid = self
+            .process
+            .as_ref()
+            .and_then(|p| p.lock().ok().map(|child| child.id().to_string()))
+            .unwrap_or_else(|| "-".into());
+        let state = self.state();
+        let elapsed = match (self.running, self.started, self.duration) {
+            (false, _, Some(duration)) => duration.as_secs(),
+            (_, Some(started), _) => started.elapsed().as_secs(),
+            _ => 0,
+        };
+        let usage = match usage::SUPPORTED {
+            true => format!("{:<26}  ", self.usage_columns()),
+            false => String::new(),
+        };
+        format!(
+            "{:<4} buf {:<3} pid {pid:<6} {state:<13} {elapsed:>4}s  {usage}{}",
+            job_name(self.id),
+            index + 1,
+            self.command.replace('\n', " ")
+        )
+    }
+}
+
+#[cfg(unix)]
+pub fn send(pid: u32, signal: &str) -> Result<(), String> {
+    // jobs start in a process group of their own, led by the job
+    let output = Command::new("kill")
+        .args(["-s", signal, "--", &format!("-{pid}")])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .map_err(|e| format!("kill: {e}"))?;
+    if output.status.success() {
+        return Ok(());
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    Err(stderr.lines().next().unwrap_or("kill failed").to_string())
+}
+
+#[cfg(not(unix))]
+pub fn send(pid: u32, signal: &str) -> Result<(), String> {
+    if !matches!(signal, "KILL" | "TERM" | "INT") {
+        return Err(format!("SIG{signal} can't be sent here"));
+    }
+    let pid = pid.to_string();
+    let status = Command::new("taskkill")
+        .args(["/F", "/T", "/PID", &pid])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_err(|e| format!("taskkill: {e}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(format!("taskkill failed for {pid}")),
+    }
+}
+
+impl Model {
+    pub fn job_index(&self, id: usize) -> Option<usize> {
+        self.outputs.iter().position(|o| o.id == id)
+    }
+
+    /// The job `%N` in `args` names, or the latest running one.
+    fn job_arg(&self, args: &[String]) -> Result<usize, String> {
+        match args.iter().find(|a| a.starts_with('%')) {
+            Some(arg) => parse_job(arg)
+                .filter(|id| self.job_index(*id).is_some())
+                .ok_or_else(|| format!("no such job: {arg}")),
+            None => self
+                .outputs
+                .iter()
+                .rev()
+                .find(|o| o.running)
+                .map(|o| o.id)
+                .ok_or_else(|| "no jobs running".into()),
+        }
+    }
+
+    /// `jobs`: open the table of running jobs.
+    pub fn jobs(&mut self, args: &[String]) -> String {
+        let all = args.iter().any(|a| a == "--all");
+        self.reaper.all = all;
+        let listed: Vec<_> = match all {
+            true => self
+                .outputs
+                .iter()
+                .filter(|o| o.started.is_some())
+                .map(|o| o.id)
+                .collect(),
+            false => Vec::new(),
+        };
+        let rows = self.job_rows(&listed);
+        if rows.is_empty() {
+            return "no jobs running".into();
+        }
+        let count = match rows.len() {
+            1 => "1 job".to_string(),
+            n => format!("{n} jobs"),
+        };
+        self.picker = Some(Picker::new(PickerKind::Jobs, rows));
+        match all {
+            true => format!("{count}; {}", self.reaper.summary()),
+            false => format!("{count} running"),
+        }
+    }
+
+    /// Rows for the running jobs, and for `listed` ones even once they've
+    /// ended, in buffer order. After `jobs --all`, each says what the job
+    /// still holds.
+    fn job_rows(&self, listed: &[usize]) -> Vec<String> {
+        self.outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.running || listed.contains(&o.id))
+            .map(|(i, o)| match self.reaper.all {
+                true => format!("{}  [{}]", o.job_row(i), self.reaper.note(o.id)),
+                false => o.job_row(i),
+            })
+            .collect()
+    }
+
+    /// Bring the open `jobs` table up to date, keeping the same job
+    /// selected.
+    pub fn refresh_jobs(&mut self) {
+        let Some(picker) = self.picker.as_ref().filter(|p| p.kind == PickerKind::Jobs) else {
+            return;
+        };
+        let listed: Vec<_> = picker.items.iter().filter_map(|row| row_job(row)).collect();
+        let selected = picker.selection().and_then(row_job);
+        let rows = self.job_rows(&listed);
+        let Some(picker) = self.picker.as_mut() else {
+            return;
+        };
+        picker.set_items(rows);
+        let index =
+            selected.and_then(|id| picker.items.iter().position(|r| row_job(r) == Some(id)));
+        if let Some(index) = index {
+            picker.select_index(index);
+        }
+    }
+
+    /// The job selected in the `jobs` table.
+    fn selected_job(&self) -> Option<usize> {
+        let picker = self
+            .picker
+            .as_ref()
+            .filter(|p| p.kind == PickerKind::Jobs)?;
+        picker.selection().and_then(row_job)
+    }
+
+    /// `kill [-SIGNAL] %N`: signal a job's process group, TERM by default.
+    pub fn kill_job(&mut self, args: &[String]) -> String {
+        let given = match args.iter().position(|a| a == "-s") {
+            Some(i) => args.get(i + 1).map(|name| format!("-{name}")),
+            None => args.iter().find(|a| a.starts_with('-')).cloned(),
+        };
+        let signal = match given {
+            Some(arg) => match parse_signal(&arg[1..]) {
+                Some(signal) => signal,
+                None => return format!("kill: unknown signal {arg}"),
+            },
+            None => "TERM",
+        };
+        match self.job_arg(args) {
+            Ok(id) => self.signal_job(id, signal),
+            Err(e) => format!("kill: {e}"),
+        }
+    }
+
+    fn signal_job(&mut self, id: usize, signal: &'static str) -> String {
+        let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) else {
+            return format!("no such job: {}", job_name(id));
+        };
+        // a killed job isn't tried again or started over
+        if matches!(signal, "KILL" | "TERM" | "INT" | "HUP" | "QUIT") {
+            output.retry = None;
+            output.restart = false;
+        }
+        let result = output.signal(signal);
+        self.refresh_jobs();
+        match result {
+            Ok(()) => format!("sent SIG{signal} to {}", job_name(id)),
+            Err(e) => format!("kill {}: {e}", job_name(id)),
+        }
+    }
+
+    /// `^x` in the `jobs` table.
+    pub fn terminate_selected_job(&mut self) {
+        if let Some(id) = self.selected_job() {
+            self.status = Some(self.signal_job(id, "TERM"));
+        }
+    }
+
+    /// `fg %N`: view a job's buffer.
+    pub fn fg(&mut self, args: &[String]) -> String {
+        match self.job_arg(args) {
+            Ok(id) => self.view_job(id),
+            Err(e) => format!("fg: {e}"),
+        }
+    }
+
+    /// Enter in the `jobs` table.
+    pub fn view_selected_job(&mut self, row: &str) {
+        if let Some(id) = row_job(row) {
+            self.status = Some(self.view_job(id));
+        }
+    }
+
+    fn view_job(&mut self, id: usize) -> String {
+        let Some(index) = self.job_index(id) else {
+            return format!("no such job: {}", job_name(id));
+        };
+        self.view_output(index);
+        format!("viewing {} {}", job_name(id), self.outputs[index].command)
+    }
+
+    /// `:follow [%N]`: stop the view of a buffer moving down with new
+    /// output, or have it follow again from the end.
+    pub fn toggle_follow(&mut self, args: &[String]) -> String {
+        let id = match args.first() {
+            Some(_) => self.job_arg(args),
+            None => self
+                .outputs
+                .get(self.viewing_output)
+                .map(|o| o.id)
+                .ok_or_else(|| "no buffer".into()),
+        };
+        match id {
+            Ok(id) => self.follow(id),
+            Err(e) => format!("follow: {e}"),
+        }
+    }
+
+    /// `^f` in the `jobs` table.
+    pub fn toggle_follow_selected(&mut self) {
+        if let Some(id) = self.selected_job() {
+            self.status = Some(self.follow(id));
+        }
+    }
+
+    fn follow(&mut self, id: usize) -> String {
+        let Some(index) = self.job_index(id) else {
+            return format!("no such job: {}", job_name(id));
+        };
+        let output = &mut self.outputs[index];
+        output.held = !output.held;
+        let status = match output.held {
+            true => format!("holding the view of {}", job_name(id)),
+            false => {
+                let bottom = self.bottom(index);
+                self.scroll_to(index, bottom);
+                format!("following {}", job_name(id))
+            }
+        };
+        self.refresh_jobs();
+        status
+    }
+}
+
+/// The job a `jobs` table row is for.
+fn row_job(row: &str) -> Option<usize> {
+    parse_job(row.split_whitespace().next()?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signals() {
+        assert_eq!(parse_signal("9"), Some("KILL"));
+        assert_eq!(parse_signal("KILL"), Some("KILL"));
+        assert_eq!(parse_signal("sigterm"), Some("TERM"));
+        assert_eq!(parse_signal("int"), Some("INT"));
+        assert_eq!(parse_signal("STOP"), Some("STOP"));
+        // numbered differently from system to system
+        assert_eq!(parse_signal("19"), None);
+        assert_eq!(parse_signal("BOGUS"), None);
+    }
+
+    #[test]
+    fn job_specs() {
+        assert_eq!(parse_job("%1"), Some(0));
+        assert_eq!(parse_job("%12"), Some(11));
+        assert_eq!(parse_job("%0"), None);
+        assert_eq!(parse_job("2"), None);
+        assert_eq!(row_job("%3   buf 2   pid 4242"), Some(2));
+    }
+}
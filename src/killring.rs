@@ -0,0 +1,217 @@
+//! Readline's kill ring for Insert mode. Ctrl-W, Ctrl-U and Ctrl-K cut
+//! text into it, Ctrl-Y puts the latest back and Alt-Y swaps what was just
+//! put back for older kills. It's apart from the vim registers, and
+//! emptied when a command is run.
+
+use std::collections::VecDeque;
+
+use crate::Model;
+
+/// Kills kept.
+const KILLS: usize = 16;
+
+/// Which way from the cursor a kill went.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Back,
+    Forward,
+}
+
+/// What the ring last did, so the next key can carry on from it.
+#[derive(Debug, Clone, Copy)]
+enum Last {
+    Kill,
+    /// Text put back at `offset`, from kill `index`.
+    Yank {
+        offset: usize,
+        len: usize,
+        index: usize,
+    },
+}
+
+#[derive(Debug, Default)]
+pub struct KillRing {
+    /// Latest first.
+    kills: VecDeque<String>,
+    last: Option<Last>,
+}
+
+impl KillRing {
+    /// Add killed text. Kills one after another make up a single entry,
+    /// as in readline: text killed backwards goes in front of it, text
+    /// killed forwards after it.
+    fn kill(&mut self, text: &str, direction: Direction) {
+        match (self.last, self.kills.front_mut()) {
+            (Some(Last::Kill), Some(latest)) => match direction {
+                Direction::Back => latest.insert_str(0, text),
+                Direction::Forward => latest.push_str(text),
+            },
+            // nothing to start a run with
+            _ if text.is_empty() => return,
+            _ => {
+                self.kills.push_front(text.to_string());
+                self.kills.truncate(KILLS);
+            }
+        }
+        self.last = Some(Last::Kill);
+    }
+
+    fn latest(&self) -> Option<&str> {
+        self.kills.front().map(String::as_str)
+    }
+
+    /// Any key other than the ones for the ring ends a run of kills or
+    /// yanks.
+    pub fn interrupt(&mut self) {
+        self.last = None;
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Where Ctrl-W kills back to from `at`: over blanks, then to the start of
+/// the word before them.
+fn word_start(line: &str, at: usize) -> usize {
+    let before = line[..at].trim_end_matches(char::is_whitespace);
+    before.rfind(char::is_whitespace).map_or(0, |i| {
+        i + before[i..].chars().next().map_or(1, char::len_utf8)
+    })
+}
+
+impl Model {
+    /// Ctrl-W, Ctrl-U and Ctrl-K: cut from the cursor back to the start of
+    /// the word, back to the start of the line, or on to its end.
+    pub fn kill(&mut self, direction: Direction, whole_line: bool) {
+        let Some((offset, start, end)) = self.edit_position() else {
+            return;
+        };
+        let range = match (direction, whole_line) {
+            (Direction::Back, false) => {
+                start + word_start(&self.current_command[start..end], offset - start)..offset
+            }
+            (Direction::Back, true) => start..offset,
+            (Direction::Forward, _) => offset..end,
+        };
+        let text: String = self.current_command.drain(range.clone()).collect();
+        self.kills.kill(&text, direction);
+        self.edit_cursor(range.start, start);
+    }
+
+    /// Ctrl-Y: put the latest kill in at the cursor.
+    pub fn yank_kill(&mut self) {
+        let Some(text) = self.kills.latest().map(String::from) else {
+            return;
+        };
+        self.put_kill(&text, 0);
+    }
+
+    /// Alt-Y, just after Ctrl-Y or Alt-Y: swap the text put back for the
+    /// kill before it, going round to the latest after the oldest.
+    pub fn yank_pop(&mut self) {
+        let Some(Last::Yank { offset, len, index }) = self.kills.last else {
+            return;
+        };
+        let Some((_, start, _)) = self.edit_position() else {
+            return;
+        };
+        self.current_command.replace_range(offset..offset + len, "");
+        self.edit_cursor(offset, start);
+        let index = (index + 1) % self.kills.kills.len();
+        let text = self.kills.kills[index].clone();
+        self.put_kill(&text, index);
+    }
+
+    fn put_kill(&mut self, text: &str, index: usize) {
+        let Some((offset, start, _)) = self.edit_position() else {
+            return;
+        };
+        self.current_command.insert_str(offset, text);
+        self.edit_cursor(offset + text.len(), start);
+        self.kills.last = Some(Last::Yank {
+            offset,
+            len: text.len(),
+            index,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn killed(kills: &[(&str, Direction)]) -> KillRing {
+        let mut ring = KillRing::default();
+        for (text, direction) in kills {
+            ring.kill(text, *direction);
+        }
+        ring
+    }
+
+    #[test]
+    fn consecutive_kills_make_one_entry() {
+        // Ctrl-W twice on `echo one two`
+        let ring = killed(&[("two", Direction::Back), ("one ", Direction::Back)]);
+        assert_eq!(ring.latest(), Some("one two"));
+        assert_eq!(ring.kills.len(), 1);
+        // Ctrl-K then more killed forwards, as after moving back
+        let ring = killed(&[("two", Direction::Forward), (" three", Direction::Forward)]);
+        assert_eq!(ring.latest(), Some("two three"));
+    }
+
+    #[test]
+    fn kills_in_both_directions_join_around_the_cursor() {
+        // `echo one| two`: Ctrl-K then Ctrl-U
+        let ring = killed(&[(" two", Direction::Forward), ("echo one", Direction::Back)]);
+        assert_eq!(ring.latest(), Some("echo one two"));
+    }
+
+    #[test]
+    fn anything_in_between_starts_a_new_entry() {
+        let mut ring = killed(&[("one ", Direction::Back)]);
+        ring.interrupt();
+        ring.kill("two ", Direction::Back);
+        assert_eq!(ring.latest(), Some("two "));
+        assert_eq!(ring.kills.len(), 2);
+    }
+
+    #[test]
+    fn killing_nothing() {
+        // Ctrl-W at the start of the line, then Ctrl-K, goes apart from
+        // the kill before
+        let mut ring = killed(&[("old", Direction::Back)]);
+        ring.interrupt();
+        ring.kill("", Direction::Back);
+        ring.kill("ls", Direction::Forward);
+        assert_eq!(ring.latest(), Some("ls"));
+        assert_eq!(ring.kills.len(), 2);
+        let ring = killed(&[
+            ("b", Direction::Back),
+            ("", Direction::Back),
+            ("a ", Direction::Back),
+        ]);
+        assert_eq!(ring.latest(), Some("a b"));
+    }
+
+    #[test]
+    fn the_ring_is_capped() {
+        let mut ring = KillRing::default();
+        for i in 0..KILLS + 5 {
+            ring.interrupt();
+            ring.kill(&i.to_string(), Direction::Back);
+        }
+        assert_eq!(ring.kills.len(), KILLS);
+        assert_eq!(ring.latest(), Some((KILLS + 4).to_string().as_str()));
+    }
+
+    #[test]
+    fn words_killed_back_to() {
+        assert_eq!(word_start("echo one two", 12), 9);
+        assert_eq!(word_start("echo one two  ", 14), 9);
+        assert_eq!(word_start("echo one two", 10), 9);
+        assert_eq!(word_start("two", 3), 0);
+        assert_eq!(word_start("   ", 3), 0);
+        assert_eq!(word_start("a caf\u{e9}", 7), 2);
+    }
+}
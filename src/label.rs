@@ -0,0 +1,44 @@
+//! Naming a buffer (`:title TEXT`) and writing a note on it (`:note TEXT`),
+//! for telling runs of the same command apart afterwards.
+
+use crate::Model;
+
+impl Model {
+    /// `:title [TEXT]`: name the viewed buffer, or with no TEXT go back to
+    /// its command. Returns what to show on the status line.
+    pub fn set_title(&mut self, text: &str) -> String {
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return "no buffer to title".into();
+        };
+        let text = text.trim();
+        match text.is_empty() {
+            true if output.title.take().is_some() => {
+                format!("cleared the title of {}", output.command)
+            }
+            true => "usage: :title TEXT".into(),
+            false => {
+                output.title = Some(text.to_string());
+                format!("titled {}", output.command)
+            }
+        }
+    }
+
+    /// `:note [TEXT]`: write a note on the viewed buffer, shown in
+    /// `:buffers`, or with no TEXT rub it out.
+    pub fn set_note(&mut self, text: &str) -> String {
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return "no buffer to note".into();
+        };
+        let text = text.trim();
+        match text.is_empty() {
+            true if output.note.take().is_some() => {
+                format!("cleared the note on {}", output.command)
+            }
+            true => "usage: :note TEXT".into(),
+            false => {
+                output.note = Some(text.to_string());
+                format!("noted {}", output.command)
+            }
+        }
+    }
+}
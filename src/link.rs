@@ -0,0 +1,258 @@
+//! Links in output. URLs in the lines on screen are underlined; `go` opens
+//! the one under the cursor, or the first on its line, and `gL` lists
+//! every URL in the buffer to open one by the letter in front of it. A URL
+//! runs from its scheme to a blank, quote or angle bracket, less the
+//! punctuation it ends in and any closing bracket it didn't open, so the
+//! `)` ending a Markdown link is left out but the one in
+//! `wiki/Rust_(language)` isn't. Links are opened with `opener`, or
+//! `xdg-open` (`open` on macOS, `explorer` on Windows), the URL given as an
+//! argument of its own so nothing in it ever reaches a shell, and left to
+//! run with no terminal and their output dropped.
+
+use std::{env, ops::Range, process::Stdio, thread};
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    style::{Style, Stylize},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    Cursor, Model, display,
+    picker::{Picker, PickerKind},
+    select, shell,
+};
+
+const SCHEMES: [&str; 4] = ["https://", "http://", "file://", "ftp://"];
+
+/// Picks a link in the `gL` list; j and k are left for moving.
+pub const LABELS: &str = "asdfghlqwertyuiopzxcvbnm";
+
+#[cfg(target_os = "macos")]
+const OPENER: &str = "open";
+#[cfg(windows)]
+const OPENER: &str = "explorer";
+#[cfg(not(any(target_os = "macos", windows)))]
+const OPENER: &str = "xdg-open";
+
+/// Where `url` ends once the punctuation and unopened closing brackets
+/// it ends in are left out.
+fn trim(url: &str) -> usize {
+    let mut end = url.len();
+    while let Some(last) = url[..end].chars().next_back() {
+        let open = match last {
+            '.' | ',' | ';' | ':' | '!' | '?' | '*' => {
+                end -= 1;
+                continue;
+            }
+            ')' => '(',
+            ']' => '[',
+            '}' => '{',
+            _ => break,
+        };
+        let url = &url[..end];
+        if url.matches(last).count() <= url.matches(open).count() {
+            break;
+        }
+        end -= 1;
+    }
+    end
+}
+
+/// Where the URLs in `line` are, in bytes.
+pub fn find(line: &str) -> Vec<Range<usize>> {
+    let mut found = Vec::new();
+    let mut at = 0;
+    let mut after_word = false;
+    while let Some(c) = line[at..].chars().next() {
+        let scheme = SCHEMES.iter().find(|s| {
+            line.get(at..at + s.len())
+                .is_some_and(|l| l.eq_ignore_ascii_case(s))
+        });
+        if let Some(scheme) = scheme.filter(|_| !after_word) {
+            let rest = &line[at..];
+            let len = rest
+                .find(|c: char| c.is_whitespace() || c.is_control() || "<>\"'`".contains(c))
+                .unwrap_or(rest.len());
+            let len = trim(&rest[..len]);
+            if len > scheme.len() {
+                found.push(at..at + len);
+                at += len;
+                after_word = true;
+                continue;
+            }
+        }
+        after_word = c.is_alphanumeric();
+        at += c.len_utf8();
+    }
+    found
+}
+
+impl Model {
+    /// `go`: open the URL under the cursor, or the first on its line.
+    pub fn open_link_under_cursor(&mut self) {
+        let (Cursor::OutputBuffer(x, _), Some((index, n))) =
+            (self.cursor, self.line_under_cursor())
+        else {
+            return;
+        };
+        let Some(line) = self.outputs[index].line(n) else {
+            return;
+        };
+        let line = display::cap(line, self.config.long_line);
+        let at = line
+            .grapheme_indices(true)
+            .nth(x as usize)
+            .map_or(line.len(), |(i, _)| i);
+        let urls = find(line);
+        let url = urls.iter().find(|r| r.contains(&at)).or(urls.first());
+        match url.map(|r| line[r.clone()].to_string()) {
+            Some(url) => self.open_link(&url),
+            None => self.status = Some("no link on this line".into()),
+        }
+    }
+
+    /// `gL`: list the URLs in the viewed buffer, each once, to pick one.
+    pub fn open_link_picker(&mut self) {
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return;
+        };
+        let mut urls: Vec<&str> = Vec::new();
+        for line in (0..output.line_count()).filter_map(|n| output.line(n)) {
+            let line = display::cap(line, self.config.long_line);
+            for url in find(line).into_iter().map(|r| &line[r]) {
+                if !urls.contains(&url) {
+                    urls.push(url);
+                }
+            }
+        }
+        if urls.is_empty() {
+            self.status = Some("no links in this buffer".into());
+            return;
+        }
+        let mut labels = LABELS.chars();
+        let items = urls
+            .iter()
+            .map(|url| format!("{}  {url}", labels.next().unwrap_or(' ')))
+            .collect();
+        let mut picker = Picker::new(PickerKind::Links, items);
+        picker.ordered = true;
+        self.picker = Some(picker);
+    }
+
+    /// Open the URL of the row picked from the `gL` list.
+    pub fn open_picked_link(&mut self, row: &str) {
+        if let Some(url) = row.split_whitespace().last() {
+            self.open_link(url);
+        }
+    }
+
+    fn open_link(&mut self, url: &str) {
+        self.status = Some(match self.start_opener(url) {
+            Ok(()) => format!("opened {url}"),
+            Err(e) => format!("couldn't open {url}: {e}"),
+        });
+    }
+
+    /// Start the opener on `url` and leave it to run.
+    fn start_opener(&self, url: &str) -> Result<(), String> {
+        let dir = env::current_dir().map_err(|e| e.to_string())?;
+        let opener = self.config.opener.as_deref().unwrap_or(OPENER);
+        let mut parsed =
+            shell::parse(opener, |name| env::var(name).ok(), &dir).ok_or("no opener")?;
+        parsed.args.push(url.into());
+        let mut process = self.process(parsed);
+        process
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null());
+        // out of the terminal's process group, so Ctrl-C in shim misses it
+        #[cfg(unix)]
+        std::os::unix::process::CommandExt::process_group(&mut process, 0);
+        let mut child = process.spawn().map_err(|e| e.to_string())?;
+        // reaped once it's done
+        thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// Underline the URLs in view in the output pane `pane`.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    if model.scrollback.is_some() {
+        return;
+    }
+    let Some(output) = model.outputs.get(model.viewing_output) else {
+        return;
+    };
+    let (width, settings) = (model.wrap_width(), model.output_settings());
+    let across = match model.wrapping() {
+        true => 0,
+        false => output.scroll.1 as usize,
+    };
+    let inner = pane.inner(Margin::new(1, 1));
+    let mut screen_row = 0;
+    for line in model.shown_lines() {
+        if screen_row >= inner.height as usize {
+            break;
+        }
+        for url in find(line) {
+            let from = line[..url.start].graphemes(true).count();
+            let to = from + line[url].graphemes(true).count() - 1;
+            for (row, cols) in select::to_display(line, from, to, width, settings) {
+                let y = screen_row + row;
+                let cols = cols.start.saturating_sub(across)..cols.end.saturating_sub(across);
+                if y >= inner.height as usize || cols.is_empty() {
+                    continue;
+                }
+                let area = Rect {
+                    x: inner.x + cols.start as u16,
+                    y: inner.y + y as u16,
+                    width: cols.len() as u16,
+                    height: 1,
+                }
+                .intersection(inner);
+                frame
+                    .buffer_mut()
+                    .set_style(area, Style::new().underlined());
+            }
+        }
+        screen_row += match model.wrapping() {
+            true => display::rows(line, width, settings).len(),
+            false => 1,
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn urls(line: &str) -> Vec<&str> {
+        find(line).into_iter().map(|r| &line[r]).collect()
+    }
+
+    #[test]
+    fn urls_leave_out_what_they_end_in() {
+        assert_eq!(
+            urls("see https://ci.example.com/run/12. Then http://localhost:3000/?a=1&b=2, ok"),
+            [
+                "https://ci.example.com/run/12",
+                "http://localhost:3000/?a=1&b=2"
+            ]
+        );
+        assert_eq!(
+            urls("[docs](https://docs.rs/ratatui) and <https://x.org/a>"),
+            ["https://docs.rs/ratatui", "https://x.org/a"]
+        );
+        assert_eq!(
+            urls("(https://en.wikipedia.org/wiki/Rust_(language))"),
+            ["https://en.wikipedia.org/wiki/Rust_(language)"]
+        );
+        assert_eq!(
+            urls("\"file:///tmp/report.html\""),
+            ["file:///tmp/report.html"]
+        );
+        assert!(urls("https:// nothing, xhttps://no, http").is_empty());
+    }
+}
@@ -0,0 +1,215 @@
+//! Warnings about the command being typed, shown before it's run: a
+//! program that isn't on `$PATH`, a quote or bracket left open, or a file
+//! that isn't there. Checks wait for typing to pause, but a warning goes
+//! as soon as an edit fixes what it's about.
+
+use std::{
+    env,
+    path::{MAIN_SEPARATOR, Path},
+    time::{Duration, Instant},
+};
+
+use crate::{Model, builtin, guard, path_index, shell, vars};
+
+/// How long typing has to pause before the command is checked.
+const PAUSE: Duration = Duration::from_millis(300);
+
+/// Paths looked up per check at most.
+const MAX_PATHS: usize = 8;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Warning {
+    /// The first word isn't a program, builtin or abbreviation.
+    Unknown(String),
+    Unbalanced(&'static str),
+    /// A path given to the command that doesn't exist.
+    Missing(String),
+}
+
+#[derive(Debug, Default)]
+pub struct Lint {
+    /// The command the warnings are for.
+    checked: String,
+    /// When it last changed, if it hasn't been checked since.
+    edited: Option<Instant>,
+    warnings: Vec<Warning>,
+}
+
+/// Whether `word` looks like a path on this machine rather than, say, a
+/// branch like `origin/main`: it starts at the root, home or the current
+/// directory, or its first part is a directory here.
+fn is_local_path(word: &str, is_dir: impl Fn(&str) -> bool) -> bool {
+    if word.starts_with('-') || word.contains("://") {
+        return false;
+    }
+    let explicit = ["/", "./", "../"].iter().any(|p| word.starts_with(p))
+        || (cfg!(windows) && Path::new(word).is_absolute());
+    let first = word.split(['/', MAIN_SEPARATOR]).next().unwrap_or("");
+    explicit || (word.contains(['/', MAIN_SEPARATOR]) && !first.is_empty() && is_dir(first))
+}
+
+impl Warning {
+    fn text(&self) -> String {
+        match self {
+            Warning::Unknown(program) => format!("{program}: not found"),
+            Warning::Unbalanced(what) => what.to_string(),
+            Warning::Missing(path) => format!("{path}: no such file"),
+        }
+    }
+}
+
+impl Model {
+    /// The command's words, after any capture or `!!` prefix.
+    fn lint_words(&self, command: &str) -> Vec<Option<String>> {
+        let line = vars::split_capture(command).map_or(command, |(_, line)| line);
+        let line = line.trim_start();
+        let line = line.strip_prefix(guard::UNCHECKED).unwrap_or(line);
+        shell::words(line, |name| self.var(name))
+    }
+
+    /// Whether an edit to the command has left warning `warning` standing.
+    fn still_warns(&self, warning: &Warning, command: &str) -> bool {
+        match warning {
+            Warning::Unknown(program) => {
+                self.lint_words(command).first() == Some(&Some(program.clone()))
+            }
+            Warning::Unbalanced(what) => shell::unbalanced(command) == Some(what),
+            Warning::Missing(path) => self
+                .lint_words(command)
+                .iter()
+                .skip(1)
+                .any(|w| w.as_ref() == Some(path)),
+        }
+    }
+
+    /// Before each draw: note an edit, and drop warnings it fixed.
+    pub fn recheck_command(&mut self) {
+        if self.command() == self.lint.checked {
+            return;
+        }
+        let command = self.command().to_string();
+        let warnings = std::mem::take(&mut self.lint.warnings);
+        self.lint.warnings = warnings
+            .into_iter()
+            .filter(|w| self.still_warns(w, &command))
+            .collect();
+        self.lint.checked = command;
+        self.lint.edited = Some(Instant::now());
+    }
+
+    /// When the command is to be checked, if it has changed.
+    pub fn check_due(&self) -> Option<Instant> {
+        self.lint.edited.map(|edited| edited + PAUSE)
+    }
+
+    /// Once typing has paused: check the command afresh.
+    pub fn check_command(&mut self) {
+        if self.lint.edited.is_none_or(|e| e.elapsed() < PAUSE) {
+            return;
+        }
+        self.lint.edited = None;
+        let command = self.lint.checked.clone();
+        self.lint.warnings = self.warnings(&command);
+    }
+
+    fn warnings(&self, command: &str) -> Vec<Warning> {
+        let mut warnings = Vec::new();
+        if command.trim().is_empty() {
+            return warnings;
+        }
+        let config = &self.config;
+        if config.warn_unbalanced
+            && let Some(what) = shell::unbalanced(command)
+        {
+            warnings.push(Warning::Unbalanced(what));
+        }
+        // a script for the shell, whose words shim doesn't know
+        if command.replace("\\\n", " ").contains('\n') {
+            return warnings;
+        }
+        let words = self.lint_words(command);
+        if config.warn_unknown_commands
+            && let Some(Some(program)) = words.first()
+            && !self.is_known_program(command, program)
+        {
+            warnings.push(Warning::Unknown(program.clone()));
+        }
+        if config.warn_missing_paths {
+            let cwd = env::current_dir().unwrap_or_default();
+            let is_dir = |first: &str| cwd.join(first).is_dir();
+            let missing = words
+                .iter()
+                .skip(1)
+                .flatten()
+                .filter(|w| is_local_path(w, is_dir))
+                .take(MAX_PATHS)
+                .find(|w| !cwd.join(w).exists());
+            warnings.extend(missing.map(|w| Warning::Missing(w.clone())));
+        }
+        warnings
+    }
+
+    fn is_known_program(&self, command: &str, program: &str) -> bool {
+        if builtin::lookup(command).is_some_and(|name| !name.is_empty())
+            || self.abbreviations.contains_key(program)
+            || self.snippets.contains_key(program)
+        {
+            return true;
+        }
+        if program.contains(['/', MAIN_SEPARATOR]) {
+            return Path::new(program).exists();
+        }
+        // not known yet; better no warning than a wait
+        let Some(executables) = path_index::built() else {
+            return true;
+        };
+        executables
+            .binary_search_by(|e| e.as_str().cmp(program))
+            .is_ok()
+    }
+
+    /// The warnings for the status line, if any.
+    pub fn lint_summary(&self) -> Option<String> {
+        if self.lint.warnings.is_empty() {
+            return None;
+        }
+        let texts: Vec<_> = self.lint.warnings.iter().map(Warning::text).collect();
+        Some(format!("⚠ {}", texts.join("; ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_worth_checking() {
+        let is_dir = |first: &str| first == "src";
+        assert!(is_local_path("./build.sh", is_dir));
+        assert!(is_local_path("/etc/hosts", is_dir));
+        assert!(is_local_path("src/mian.rs", is_dir));
+        assert!(!is_local_path("origin/main", is_dir));
+        assert!(!is_local_path("README.md", is_dir));
+        assert!(!is_local_path("--out=./x", is_dir));
+        assert!(!is_local_path("https://example.com/a", is_dir));
+    }
+
+    #[test]
+    fn a_fixed_warning_goes_straight_away() {
+        let mut model = Model {
+            current_command: "gti status 'a".into(),
+            ..Default::default()
+        };
+        model.recheck_command();
+        model.lint.warnings = vec![
+            Warning::Unknown("gti".into()),
+            Warning::Unbalanced("unclosed quote"),
+        ];
+        model.current_command = "gti status 'a'".into();
+        model.recheck_command();
+        assert_eq!(model.lint.warnings, [Warning::Unknown("gti".into())]);
+        model.current_command = "git status 'a'".into();
+        model.recheck_command();
+        assert_eq!(model.lint.warnings, []);
+    }
+}
@@ -0,0 +1,109 @@
+//! Files every running shim writes to, like the history. Each write holds
+//! an advisory lock on the file, so one shim's line can't land in the
+//! middle of another's, nor an append be lost to another shim rewriting
+//! the file. Appends are a single write to a file opened for appending;
+//! a rewrite reads and writes the file in place, under the one lock, as
+//! renaming a new file over it would leave others locking the old one.
+
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, Write},
+    path::Path,
+};
+
+fn create_dir(path: &Path) -> io::Result<()> {
+    match path.parent() {
+        Some(dir) => fs::create_dir_all(dir),
+        None => Ok(()),
+    }
+}
+
+/// Add `text` to the end of `path`, after `header` if it's empty.
+pub fn append(path: &Path, header: Option<&str>, text: &str) -> io::Result<()> {
+    create_dir(path)?;
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    file.lock()?;
+    let text = match header {
+        Some(header) if file.metadata()?.len() == 0 => format!("{header}\n{text}"),
+        _ => text.to_string(),
+    };
+    file.write_all(text.as_bytes())
+}
+
+/// What `path` holds, read while no one's writing it; empty if there's no
+/// such file.
+pub fn read(path: &Path) -> io::Result<String> {
+    let mut file = match File::open(path) {
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(String::new()),
+        file => file?,
+    };
+    file.lock_shared()?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    Ok(contents)
+}
+
+/// Put what `change` makes of its contents in `path`.
+pub fn rewrite(path: &Path, change: impl FnOnce(&str) -> String) -> io::Result<()> {
+    create_dir(path)?;
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(path)?;
+    file.lock()?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+    let contents = change(&contents);
+    file.set_len(0)?;
+    file.rewind()?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process, thread};
+
+    use super::*;
+
+    #[test]
+    fn writers_at_once_lose_nothing() {
+        let dir = env::temp_dir().join(format!("shim-lock-{}", process::id()));
+        let path = dir.join("log");
+        let writers: Vec<_> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for i in 0..200 {
+                        let line = format!("{name}{i} {}\n", "x".repeat(i * 40));
+                        append(&path, Some("#header"), &line).unwrap();
+                    }
+                })
+            })
+            .collect();
+        // and a third puts lines in front meanwhile
+        for i in 0..20 {
+            rewrite(&path, |contents| {
+                let rest = contents.strip_prefix("#header\n").unwrap_or(contents);
+                format!("#header\nc{i}\n{rest}")
+            })
+            .unwrap();
+        }
+        for writer in writers {
+            writer.join().unwrap();
+        }
+        let contents = read(&path).unwrap();
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines[0], "#header");
+        assert_eq!(lines.len(), 1 + 200 + 200 + 20);
+        for name in ["a", "b"] {
+            let mine: Vec<_> = lines.iter().filter(|l| l.starts_with(name)).collect();
+            assert!(mine.iter().enumerate().all(|(i, l)| {
+                l.split_once(' ') == Some((&format!("{name}{i}"), &"x".repeat(i * 40)))
+            }));
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
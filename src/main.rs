@@ -1,41 +1,176 @@
+mod abbr;
+mod anchor;
+mod args;
+mod atuin;
+mod autosave;
+mod bookmark;
+mod buffers;
+mod builtin;
+mod clock;
+mod closed;
+mod column;
+mod complete;
+mod completer;
+mod config;
+mod context;
+mod derive;
+mod dirs;
+mod display;
+mod dotenv;
+mod each;
+mod edit;
+mod encoding;
+mod event;
+mod exit;
+mod expand;
+mod extract;
+mod files;
+mod frecency;
+mod fuzzy;
+mod glob;
+mod grab;
+mod guard;
+mod health;
+mod history;
+mod hook;
+mod image;
+mod import;
+#[cfg(test)]
+mod invariants;
+mod job;
+mod jobs;
+mod killring;
+mod label;
+mod link;
+mod lint;
+mod lock;
+mod marks;
+mod memory;
+mod mouse;
+mod passthrough;
+mod path_index;
+mod peek;
+mod picker;
+mod pipe;
+mod pipeline;
+mod preview;
+mod queue;
+mod reap;
+mod record;
+mod redact;
+mod registers;
+mod repeat;
+mod replace;
+mod rerun;
+mod retry;
+mod sanitize;
+mod schedule;
+mod scrollback;
+mod scrub;
+mod segment;
+mod select;
+mod session;
 mod shell;
+mod smooth;
+mod snippet;
+mod startup;
+mod stats;
+mod stderr;
+mod suggest;
+mod suspend;
+mod table;
+mod tag;
+mod textobj;
+mod theme;
+mod toast;
+mod trace;
+mod typeahead;
+mod usage;
+mod vars;
+mod warning;
+mod workspace;
+mod wrap;
 
-use std::usize;
-use std::{cmp::min, env, time::Duration};
+use std::{
+    cmp::min,
+    collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+    env, io,
+    path::PathBuf,
+    process,
+    sync::mpsc::{self, RecvTimeoutError, Sender},
+    time::{Duration, Instant, SystemTime},
+};
 
+use config::Config;
+use event::AppEvent;
+use marks::Mark;
+use picker::{Picker, PickerKind};
 use ratatui::crossterm::event::KeyModifiers;
 use ratatui::layout::Position;
 use ratatui::{
     Frame,
-    crossterm::event::{self, Event, KeyCode},
-    layout::{Constraint, Direction, Layout},
+    crossterm::event::{KeyCode, KeyEvent, MouseEvent},
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Style, Stylize},
+    text::{Line, Span, Text},
     widgets::{Block, Paragraph},
 };
-use shell::run;
+use unicode_width::UnicodeWidthStr;
+use vars::Variable;
 
-#[derive(Debug, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
 enum Cursor {
-    CommandLine(u16),
+    /// Column and row within the (possibly multi-line) command
+    CommandLine(u16, u16),
+    /// Grapheme within the line and row on screen; see `display` for the
+    /// column it is drawn at
     OutputBuffer(u16, u16),
 }
 
+/// What keys go to: one of the panes, or an overlay over them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Focus {
+    Output,
+    CommandLine,
+    Overlay(Overlay),
+}
+
+/// Something open over the panes that takes every key until it's done.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Overlay {
+    /// Whether to drop the rest of the queue.
+    AbandonQueue,
+    /// Whether to quit with jobs running.
+    Quit,
+    /// Whether to run a dangerous command.
+    Dangerous,
+    /// Whether to load a directory's env file.
+    EnvFile,
+    /// A snippet's placeholders being filled in.
+    Snippet,
+    Picker,
+    LinePopup,
+}
+
 impl Cursor {
-    fn left(&mut self) {
+    /// The pane the cursor is in. Moving with j/k goes on from one pane
+    /// into the other at the same column, so the two share one cursor.
+    fn pane(&self) -> Focus {
         match self {
-            Cursor::CommandLine(x) => *x = x.saturating_sub(1),
-            Cursor::OutputBuffer(x, _) => *x = x.saturating_sub(1),
+            Cursor::CommandLine(_, _) => Focus::CommandLine,
+            Cursor::OutputBuffer(_, _) => Focus::Output,
         }
     }
-    fn right(&mut self) {
+
+    fn left(&mut self) {
         match self {
-            Cursor::CommandLine(x) => *x = x.saturating_add(1),
-            Cursor::OutputBuffer(x, _) => *x = x.saturating_add(1),
+            Cursor::CommandLine(x, _) => *x = x.saturating_sub(1),
+            Cursor::OutputBuffer(x, _) => *x = x.saturating_sub(1),
         }
     }
-
     fn right_capped(&mut self, max: u16) {
         match self {
-            Cursor::CommandLine(x) => *x = min(x.saturating_add(1), max),
+            Cursor::CommandLine(x, _) => *x = min(x.saturating_add(1), max),
             Cursor::OutputBuffer(x, _) => *x = min(x.saturating_add(1), max),
         }
     }
@@ -43,15 +178,17 @@ impl Cursor {
 
 impl Default for Cursor {
     fn default() -> Self {
-        Cursor::CommandLine(0)
+        Cursor::CommandLine(0, 0)
     }
 }
 
-#[derive(Debug, Default, PartialEq)]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
 enum Mode {
     #[default]
     Insert,
     Normal,
+    /// Typing over the command line; see `replace`.
+    Replace,
 }
 
 #[derive(Debug, Default)]
@@ -60,30 +197,575 @@ struct Model {
     mode: Mode,
     running_state: RunningState,
     outputs: Vec<Output>,
-    previous_commands: Vec<String>,
+    previous_commands: Vec<history::Entry>,
+    /// Where `previous_commands` came from and new ones are recorded.
+    history: Box<dyn history::Backend>,
+    /// Commands waiting to give the history their exit statuses.
+    history_running: Vec<history::Running>,
+    /// A `:retag-run` under way.
+    rerun: Option<tag::Rerun>,
+    /// Only recall commands run in or under the current directory.
+    history_here: bool,
     viewing_output: usize,
+    /// Keep viewing the same buffer when a job starts instead of its new
+    /// one.
+    view_locked: bool,
     current_command: String,
     viewing_command: Option<usize>,
     height: u16,
+    /// Columns in the output pane, where lines wrap with `wrap` set.
+    width: u16,
+    /// Column within a screen row that `gj`/`gk` keep to.
+    goal_column: Option<usize>,
+    config: Config,
+    /// Directories set aside by `pushd`, top of the stack last.
+    dir_stack: Vec<PathBuf>,
+    /// Directories visited this session, most recent last.
+    recent_dirs: Vec<PathBuf>,
+    picker: Option<Picker>,
+    /// The walk filling the file picker, while it's under way.
+    file_walk: Option<files::Walk>,
+    next_walk: usize,
+    /// Where background jobs send their output.
+    events: Option<Sender<AppEvent>>,
+    next_output_id: usize,
+    marks: HashMap<char, Mark>,
+    /// Where the last jump came from, for `''`.
+    last_jump: Option<Mark>,
+    /// First key of a two-key normal mode command such as `ma`; `u` and
+    /// `U` stand for `gu` and `gU`, waiting for their motion.
+    pending_key: Option<char>,
+    /// The operator and whether it's `a` rather than `i`, after `ci`,
+    /// `da` and the like, waiting for the kind of text object.
+    pending_object: Option<(char, bool)>,
+    /// Set while every buffer is shown in one view; see `:scrollback`.
+    scrollback: Option<scrollback::View>,
+    /// A note for the user, shown until the next key.
+    status: Option<String>,
+    vars: HashMap<String, Variable>,
+    snippets: BTreeMap<String, String>,
+    abbreviations: BTreeMap<String, String>,
+    /// The abbreviation expanded by the last key, if it was a space.
+    expanded: Option<abbr::Expanded>,
+    snippet_prompt: Option<snippet::Prompt>,
+    queue: queue::Queue,
+    frecency: frecency::Frecency,
+    /// Commands run this session, for `:session`.
+    session: Vec<session::Step>,
+    replay: Option<session::Replay>,
+    recorder: Option<record::Recorder>,
+    registers: registers::Registers,
+    /// The last change to the command line, for `.`.
+    last_change: Option<repeat::Change>,
+    /// The change going on in insert mode, until Esc.
+    recording: Option<repeat::Change>,
+    /// Typed before a normal mode command, as in `3.`.
+    count: Option<usize>,
+    /// When running jobs' CPU and memory were last sampled.
+    sampled: Option<Instant>,
+    /// Jobs' reader threads, pipes and processes, until they're let go of.
+    reaper: reap::Reaper,
+    /// Text cut with Ctrl-W, Ctrl-U and Ctrl-K in Insert mode.
+    kills: killring::KillRing,
+    /// Warnings about the command being typed.
+    lint: lint::Lint,
+    /// What's where `cd` is going, while the command line is one.
+    peek: peek::Peek,
+    /// Notes in the corner that go by themselves.
+    toasts: toast::Toasts,
+    /// Commands run as others finish; see `hook`.
+    hooks: hook::Hooks,
+    /// What each character typed in Replace mode went over, to put back.
+    replaced: Vec<Option<char>>,
+    /// The messages handled lately, with `--debug`; see `trace`.
+    trace: Option<trace::Trace>,
+    /// What `:encoding` gave for the next command to be read as.
+    next_encoding: Option<&'static encoding_rs::Encoding>,
+    /// How the buffer picker groups buffers, and its rows.
+    buffer_picker: buffers::BufferPicker,
+    /// The workspaces out of view; see `:tabnew`.
+    workspaces: workspace::Workspaces,
+    /// The run context commands go through (`:ctx`), by name.
+    context: Option<String>,
+    /// Where `v` started selecting, and in which buffer.
+    visual: Option<(usize, select::Point)>,
+    /// A line shown in full with `gl`.
+    line_popup: Option<expand::LinePopup>,
+    /// Register chosen with `"x` for the next yank or paste.
+    register: Option<char>,
+    /// A dangerous command waits for `y`; whether it is to be queued.
+    confirming: Option<bool>,
+    /// Quitting waits for `y`, as jobs are still running.
+    quitting: bool,
+    completion: Option<complete::Completion>,
+    completer: completer::Completer,
+    /// Variables from an allowed `.env`, added to commands' environments.
+    env_overlay: Option<dotenv::Overlay>,
+    /// An env file waiting for `y` to be loaded.
+    env_prompt: Option<PathBuf>,
+    /// Exit code of the last command to end, for `$?`.
+    last_exit: Option<i32>,
+    /// A failed exit code the prompt shows until the next submission.
+    failed: Option<i32>,
+    /// What the terminal was found to support.
+    terminal: tui::Capabilities,
+    /// Where the output pane was last drawn, borders and all.
+    pane: Rect,
+    /// Images last sent to the terminal.
+    images_shown: Vec<image::Shown>,
+    /// Stopping or coming back, for the main loop; see `suspend`.
+    suspending: suspend::Pending,
+    /// A program to give the terminal to; see `passthrough`.
+    handover: Option<passthrough::Handover>,
+    /// When the main loop has to wake next.
+    schedule: schedule::Schedule,
+    /// Clicks and drags under way, with `mouse` set.
+    mouse: mouse::State,
+    /// What prompt segments last gave.
+    segments: segment::Segments,
+    /// When shim was last busy, for the keys typed meanwhile.
+    typeahead: typeahead::Typeahead,
+    /// Arguments of past commands; see `args`.
+    arguments: args::Index,
+    /// What the last Alt-. put in, while it can be swapped.
+    last_argument: Option<args::LastArgument>,
+    /// Buffers closed, latest last; see `closed`.
+    closed: Vec<closed::Closed>,
+    /// How long startup took; see `startup`.
+    startup: startup::Profile,
+    /// Saving the buffers every so often; see `autosave`.
+    autosave: autosave::Autosave,
+    /// Pipeline stages run on the viewed buffer as they're typed; see
+    /// `preview`.
+    preview: preview::Preview,
 }
 
 impl Model {
-    fn get_command_len(&self) -> u16 {
-        match self
-            .viewing_command
+    /// Where keys go. An overlay is open exactly while its state is set,
+    /// so focus is read off that state rather than kept in step with it;
+    /// the first one open here is the one answered first.
+    fn focus(&self) -> Focus {
+        let overlay = if self.queue.asking {
+            Overlay::AbandonQueue
+        } else if self.quitting {
+            Overlay::Quit
+        } else if self.confirming.is_some() {
+            Overlay::Dangerous
+        } else if self.env_prompt.is_some() {
+            Overlay::EnvFile
+        } else if self.snippet_prompt.is_some() {
+            Overlay::Snippet
+        } else if self.picker.is_some() {
+            Overlay::Picker
+        } else if self.line_popup.is_some() {
+            Overlay::LinePopup
+        } else {
+            return self.cursor.pane();
+        };
+        Focus::Overlay(overlay)
+    }
+
+    /// The command on the command line, either recalled or being typed.
+    fn command(&self) -> &str {
+        self.viewing_command
             .and_then(|i| self.previous_commands.get(i))
+            .map_or(&self.current_command, |e| &e.command)
+    }
+
+    fn get_command_rows(&self) -> u16 {
+        self.command().split('\n').count() as u16
+    }
+
+    fn get_row_len(&self, row: u16) -> u16 {
+        self.command_row(row).len() as u16
+    }
+
+    fn command_row(&self, row: u16) -> &str {
+        self.command().split('\n').nth(row as usize).unwrap_or("")
+    }
+
+    /// Column `x` on row `y`, moved back to the start of the character it
+    /// falls in, as columns count bytes.
+    fn char_column(&self, x: u16, y: u16) -> u16 {
+        column::floor(self.command_row(y), x as usize) as u16
+    }
+
+    /// The column after the character at `x` on row `y`.
+    pub fn next_column(&self, x: u16, y: u16) -> u16 {
+        column::next(self.command_row(y), x as usize) as u16
+    }
+
+    /// The column of the character before `x` on row `y`.
+    fn previous_column(&self, x: u16, y: u16) -> u16 {
+        column::previous(self.command_row(y), x as usize) as u16
+    }
+
+    /// Where the cursor rests for column `x` on row `y` in the current mode.
+    fn rest_column(&self, x: u16, y: u16) -> u16 {
+        column::rest(self.command_row(y), x as usize, &self.mode) as u16
+    }
+
+    fn command_end(&self) -> Cursor {
+        let last = self.get_command_rows() - 1;
+        Cursor::CommandLine(self.get_row_len(last), last)
+    }
+
+    /// Keep the cursor on the command after it changes to a shorter one,
+    /// or where it may rest after a change of mode.
+    fn clamp_command_cursor(&mut self) {
+        let Cursor::CommandLine(x, y) = self.cursor else {
+            return;
+        };
+        let last = self.get_command_rows() - 1;
+        let (x, y) = match y > last {
+            true => (u16::MAX, last),
+            false => (x, y),
+        };
+        self.cursor = Cursor::CommandLine(self.rest_column(x, y), y);
+    }
+
+    /// Byte offset into `current_command` of column `x` on row `y`, kept
+    /// on a character of that row.
+    fn command_offset(&self, x: u16, y: u16) -> usize {
+        let mut rows = self.current_command.split('\n');
+        let before: usize = rows.by_ref().take(y as usize).map(|l| l.len() + 1).sum();
+        let row = rows.next().unwrap_or("");
+        min(
+            before + column::floor(row, x as usize),
+            self.current_command.len(),
+        )
+    }
+
+    /// Before the command line is changed: if a past command is being
+    /// viewed, make a copy of it to edit, and keep the cursor within it.
+    fn edit_recalled(&mut self) {
+        if let Some(i) = self.viewing_command.take() {
+            self.current_command = self
+                .previous_commands
+                .get(i)
+                .map(|e| e.command.clone())
+                .unwrap_or_default();
+        }
+        self.clamp_command_cursor();
+    }
+
+    /// The line of the viewed output on screen row `y`, as far as it's
+    /// drawn.
+    fn output_line(&self, y: u16) -> Option<&str> {
+        let line = self.full_output_line(y)?;
+        Some(display::cap(line, self.config.long_line))
+    }
+
+    /// All of the line on screen row `y`, however long.
+    fn full_output_line(&self, y: u16) -> Option<&str> {
+        if self.scrollback.is_some() {
+            return self.scrollback_line(y);
+        }
+        let output = self.outputs.get(self.viewing_output)?;
+        output.line(y as usize + output.scroll.0)
+    }
+
+    /// Show output `index`, with the cursor back where it was when that
+    /// buffer was last viewed.
+    fn view_output(&mut self, index: usize) {
+        self.save_cursor();
+        self.viewing_output = index;
+        self.restore_cursor();
+    }
+
+    /// Remember where the cursor is in the viewed buffer, for coming back.
+    fn save_cursor(&mut self) {
+        if let Some(output) = self.outputs.get_mut(self.viewing_output) {
+            output.cursor = Some(self.cursor);
+        }
+    }
+
+    /// Put the cursor back in the pane it was in when the viewed buffer was
+    /// last left, kept within what the buffer holds now. The command line
+    /// is the same for every buffer, so only returning to it is restored.
+    fn restore_cursor(&mut self) {
+        let saved = self.outputs.get(self.viewing_output).and_then(|o| o.cursor);
+        match (saved, self.cursor) {
+            (Some(cursor @ Cursor::OutputBuffer(_, _)), _) => self.cursor = cursor,
+            (Some(Cursor::CommandLine(_, _)), Cursor::OutputBuffer(_, _)) => {
+                self.cursor = self.command_end()
+            }
+            _ => {}
+        }
+        self.clamp_command_cursor();
+        self.clamp_output_cursor();
+    }
+
+    /// Keep the cursor on a line and character of the viewed buffer.
+    fn clamp_output_cursor(&mut self) {
+        let Cursor::OutputBuffer(x, y) = self.cursor else {
+            return;
+        };
+        let lines = match self.scrollback_top() {
+            Some(top) => self.scrollback_len() - top,
+            None => self
+                .outputs
+                .get(self.viewing_output)
+                .map_or(0, |o| o.line_count().saturating_sub(o.scroll.0)),
+        };
+        let y = min(
+            y,
+            min(self.last_shown_line(), lines.saturating_sub(1) as u16),
+        );
+        let last = self
+            .output_line(y)
+            .map_or(0, |line| display::len(line).saturating_sub(1) as u16);
+        self.cursor = Cursor::OutputBuffer(min(x, last), y);
+    }
+
+    fn push_output(&mut self, command: String, stdout: String) {
+        self.save_cursor();
+        if self.outputs.last().is_some_and(|o| o.transient) {
+            self.outputs.pop();
+        }
+        let closed = self.close_to_fit(1);
+        if closed > 0 {
+            self.status = Some(format!("closed {closed} old buffers"));
+        }
+        let mut output = Output {
+            id: self.next_output_id,
+            command,
+            ..Default::default()
+        };
+        output.ingest(&stdout, &self.config.redact);
+        output.flush(&self.config.redact);
+        self.record_output(&output.stdout);
+        self.outputs.push(output);
+        self.next_output_id += 1;
+        self.viewing_output = self.outputs.len() - 1;
+        let bottom = self.bottom(self.viewing_output);
+        self.outputs[self.viewing_output].set_scroll(bottom);
+        self.restore_cursor();
+        self.evict_to(self.config.memory_cap);
+    }
+
+    /// Run a builtin or start a job for `command`.
+    fn execute(&mut self, command: String) {
+        if let Some(name) = builtin::lookup(&command) {
+            let text = builtin::run(self, &command);
+            self.record_exit(0);
+            if name == "parse" {
+                self.push_transient(command, text);
+            } else if builtin::REPORTS_ON_STATUS.contains(&name) {
+                self.status = Some(text);
+            } else {
+                self.push_output(command, text);
+            }
+        } else if let Some(line) = command.trim_start().strip_prefix(':') {
+            let name = line.split_whitespace().next().unwrap_or("").to_string();
+            self.push_not_found(command, &name, builtin::NAMES);
+            self.record_exit(127);
+        } else if let Some(line) = self.passthrough_line(&command) {
+            let line = line.to_string();
+            self.hand_over(command, &line);
+        } else {
+            let capture = vars::split_capture(&command).map(|(name, _)| name.to_string());
+            self.start_job(command, capture);
+        }
+    }
+
+    /// Run or queue the command line and add it to history.
+    fn submit(&mut self, enqueue: bool) {
+        // cleared first, as running it may fill the command line again
+        let mut command = std::mem::take(&mut self.current_command);
+        self.clear_peek();
+        if let Some(rest) = command.trim_start().strip_prefix(guard::UNCHECKED) {
+            command = rest.to_string();
+        }
+        self.viewing_command = None;
+        self.cursor = Cursor::CommandLine(0, 0);
+        // nothing to run: show the end of the output instead, like `G`
+        if command.trim().is_empty() && self.scrollback.is_some() {
+            self.scroll_scrollback(usize::MAX);
+            return;
+        }
+        if command.trim().is_empty() {
+            self.jump_to_line(self.bottom(self.viewing_output));
+            return;
+        }
+        if command.trim_start().starts_with('#') {
+            self.stash(&command);
+            return;
+        }
+        self.kills.clear();
+        self.recording = None;
+        self.failed = None;
+        let mut entry = history::Entry::new(command.clone());
+        if builtin::lookup(&command).is_none() {
+            entry.context = self.context_for(&command);
+        }
+        if let Some(recorder) = &self.recorder {
+            recorder.command(&command);
+        }
+        self.frecency.used(&entry.command);
+        self.previous_commands.push(entry.clone());
+        self.index_arguments();
+        if enqueue {
+            self.record_history(&entry, None);
+            self.status = Some(self.enqueue(command));
+        } else {
+            let id = self.next_output_id;
+            let replaying = self.replay_waiting();
+            if !self.materialize_preview(&command) {
+                self.execute(command.clone());
+            }
+            self.record_history(&entry, Some(id));
+            self.record_step(command, entry.cwd, id);
+            if replaying {
+                self.replay_ran(id);
+            }
+            self.autosave_ran();
+        }
+    }
+
+    /// Whether history entry `i` passes the directory filter.
+    fn recallable(&self, i: usize) -> bool {
+        match (self.history_here, env::current_dir()) {
+            (true, Ok(dir)) => self.previous_commands[i].ran_under(&dir),
+            _ => true,
+        }
+    }
+
+    /// The rest of the best past command starting with what's been typed,
+    /// offered in grey after the cursor.
+    fn ghost(&self) -> Option<&str> {
+        let command = self.command();
+        let at_end = self.cursor == self.command_end();
+        if self.mode != Mode::Insert
+            || !at_end
+            || command.is_empty()
+            || command.contains('\n')
+            || self.picker.is_some()
         {
-            Some(s) => s.len() as u16,
-            None => self.current_command.len() as u16,
+            return None;
+        }
+        let best = self
+            .frecency
+            .complete(command, self.config.penalize_failures)?;
+        Some(&best[command.len()..])
+    }
+
+    fn push_transient(&mut self, command: String, stdout: String) {
+        self.push_output(command, stdout);
+        if let Some(output) = self.outputs.last_mut() {
+            output.transient = true;
+        }
+    }
+
+    /// Report that `name` doesn't exist, offering the closest `candidates`.
+    fn push_not_found<'a>(
+        &mut self,
+        command: String,
+        name: &str,
+        candidates: impl IntoIterator<Item = &'a str>,
+    ) {
+        let matches = suggest::did_you_mean(name, candidates);
+        let mut text = format!("shim: command not found: {name}");
+        if !matches.is_empty() {
+            text.push('\n');
+            text.push_str(&suggest::message(name, &matches));
+        }
+        let suggestion = matches.first().map(|m| command.replacen(name, m, 1));
+        self.push_output(command, text);
+        if let Some(output) = self.outputs.last_mut() {
+            output.suggestion = suggestion;
         }
     }
 }
 
 #[derive(Debug, Default)]
 struct Output {
+    /// Stays the same as other buffers come and go; jobs are known by it.
+    id: usize,
     command: String,
     stdout: String,
-    scroll: (u16, u16),
+    scroll: (usize, u16),
+    /// Where the cursor was when the buffer was last viewed.
+    cursor: Option<Cursor>,
+    /// Lines bookmarked with `M`.
+    bookmarks: BTreeSet<usize>,
+    /// Where the view is sliding to, with `smooth_scroll`.
+    scroll_target: Option<smooth::ScrollTarget>,
+    pinned: bool,
+    /// Bytes dropped when the contents were evicted to save memory.
+    evicted: Option<usize>,
+    /// Lines dropped from the top under `max_lines`; see `anchor`.
+    dropped: usize,
+    /// Corrected command offered after a "command not found".
+    suggestion: Option<String>,
+    /// Replaced by the next buffer instead of being kept, e.g. a preview.
+    transient: bool,
+    /// Its command had the terminal, so there's no output; see
+    /// `passthrough`.
+    in_terminal: bool,
+    running: bool,
+    started: Option<Instant>,
+    /// When it started and ended by the wall clock, for the title.
+    started_at: Option<SystemTime>,
+    ended_at: Option<SystemTime>,
+    status: Option<i32>,
+    /// Turns the output into UTF-8, holding back a character split
+    /// across chunks; see `encoding`.
+    decoder: encoding::Decoder,
+    /// Session variable to store the output in once the job ends.
+    capture: Option<String>,
+    /// The last line, held back until it ends so it can be redacted whole.
+    partial: String,
+    /// The output before redaction, kept only if something was redacted.
+    raw: Option<String>,
+    show_raw: bool,
+    show_invisibles: bool,
+    process: Option<job::Process>,
+    retry: Option<retry::Retry>,
+    /// Set for the buffer of an `:each` run.
+    each: Option<each::Each>,
+    /// Names given with `:tag`.
+    tags: Vec<String>,
+    /// Each stage of a pipeline with its exit code, once it has ended.
+    stages: Vec<(String, i32)>,
+    /// Shown before the command in its title; see `:title`.
+    title: Option<String>,
+    /// Written with `:note`, shown in `:buffers`.
+    note: Option<String>,
+    /// Times the command was run again with `:retag-run` or `:rerun`.
+    reruns: u32,
+    /// The separator of the run going, with `:rerun --append`.
+    section: Option<rerun::Section>,
+    /// Run the command again once the killed run is gone.
+    restart: bool,
+    /// How much output there was every so often while the job ran.
+    checkpoints: Vec<scrub::Checkpoint>,
+    /// The checkpoint the view is frozen at, hiding later output.
+    frozen: Option<scrub::Checkpoint>,
+    /// A filter the output is shown through, from `:pipe`.
+    pipe: Option<pipe::Pipe>,
+    /// The `:pipe` filter running in the background, by request; a result
+    /// for any other isn't wanted any more.
+    piping: Option<u64>,
+    /// How long the command took, retries included.
+    duration: Option<Duration>,
+    /// The last signal sent to the job with `kill`.
+    signal: Option<&'static str>,
+    /// Keep the view where it is as output comes in; see `:follow`.
+    held: bool,
+    /// Columns found in the output, when in table mode.
+    table: Option<table::Table>,
+    /// What's being worked out for a buffer made by `:sort` or `:uniq`.
+    deriving: Option<String>,
+    /// What went wrong getting the output, shown under it.
+    warnings: Vec<String>,
+    /// CPU and memory the job used.
+    usage: usage::Usage,
+    /// Images taken out of the output.
+    images: image::Images,
+    /// Stdout and stderr apart, once there's been stderr.
+    streams: Option<stderr::Streams>,
+    line_index: display::LineIndex,
 }
 
 #[derive(Debug, Default, PartialEq, Eq)]
@@ -93,7 +775,7 @@ enum RunningState {
     Done,
 }
 
-#[derive(PartialEq)]
+#[derive(Debug, Clone, PartialEq)]
 enum Message {
     Down,
     Up,
@@ -102,379 +784,3525 @@ enum Message {
     NextOutput,
     PreviousOutput,
     WriteCommandChar(char),
+    Newline,
     Normal,
     InsertBefore,
     InsertAfter,
     Backspace,
+    EnterReplace,
+    ReplaceChar(char),
+    ReplaceBackspace,
+    Kill(killring::Direction),
+    KillLine(killring::Direction),
+    YankKill,
+    YankPop,
     OutCommand,
     InCommand,
     ScrollDown,
     ScrollUp,
     Left,
     Right,
+    LineStart,
+    LineEnd,
+    ScrubBack,
+    ScrubForward,
+    ToggleBookmark,
+    NextBookmark,
+    PreviousBookmark,
+    RerunAppending,
     InsertBeforeLine,
     InsertAfterLine,
+    AcceptSuggestion,
+    Preview,
+    PendingKey(char),
+    PendingObject {
+        operator: char,
+        around: bool,
+    },
+    TextObject {
+        operator: char,
+        around: bool,
+        argument: bool,
+    },
+    DeleteLine,
+    RepeatChange,
+    RunLast,
+    Count(u8),
+    CancelPending,
+    SetMark(char),
+    JumpToMark(char),
+    JumpBack,
+    Top,
+    Bottom,
+    OutputChunk(usize, Vec<u8>),
+    ErrorChunk(usize, Vec<u8>),
+    /// A job ended with `status`; for a pipeline, also each stage's exit
+    /// code.
+    JobExited(usize, Option<i32>, Vec<i32>),
+    ReadFailed(usize, String),
+    OpenDirectoryPicker,
+    OpenSnippetPicker,
+    ExpandSnippet,
+    SnippetChar(char),
+    SnippetBackspace,
+    SnippetSubmit,
+    SnippetCancel,
+    PickerChar(char),
+    PickerBackspace,
+    PickerUp,
+    PickerDown,
+    PickerAccept,
+    PickerCancel,
+    /// Move the selected picker entry by this many places.
+    PickerMove(isize),
+    PickerRemove,
+    PickerMark,
+    /// → or ← in the buffer picker: open a group up or close it.
+    PickerExpand(bool),
+    OpenFilePicker,
+    OpenBufferPicker,
+    /// Ctrl-u in the buffer picker: bring back the latest closed buffer.
+    UndoClose,
+    /// `go`: open the URL under the cursor.
+    OpenLink,
+    OpenLinkPicker,
+    /// A letter in the link picker: open the link it's in front of.
+    PickLink(usize),
+    Files(usize, Vec<String>, bool),
+    HistoryImported(import::Import),
+    HistoryLoaded(history::Loaded),
+    Derived(usize, String),
+    Previewed(String, Result<String, String>),
+    Piped(usize, u64, String, Result<String, String>),
+    Enqueue,
+    /// Put a `#` in front of the command line, or take it off, and submit.
+    InsertComment,
+    Interrupt,
+    /// Answer to whether to drop the rest of the queue.
+    AbandonQueue(bool),
+    /// Answer to whether to run a dangerous command.
+    Confirm(bool),
+    /// Answer to whether to load a directory's env file.
+    AllowEnv(bool),
+    /// Answer to whether to quit with jobs running.
+    ConfirmQuit(bool),
+    Retry,
+    Tick,
+    OpenHistoryPicker,
+    AcceptGhost,
+    ToggleHistoryHere,
+    ReplaySkip,
+    ReplayStop,
+    Resize(u16, u16),
+    /// With `mouse` set.
+    Mouse(MouseEvent),
+    ToggleViewLock,
+    ToggleCase,
+    ChangeWordCase {
+        upper: bool,
+    },
+    Increment(i64),
+    SelectRegister(char),
+    Complete,
+    CompletionNext,
+    CompletionPrevious,
+    CompletionAccept,
+    CompletionCancel,
+    Completions(completer::Key, Option<completer::Values>),
+    /// What a prompt segment gave: its name, where it ran and its text.
+    Segment(String, PathBuf, String),
+    Yank,
+    Visual,
+    EndVisual,
+    ExpandLine,
+    TakeCommand,
+    InsertFromOutput,
+    /// Alt-. on the command line; see `args`.
+    InsertLastArgument,
+    OpenArgumentPicker,
+    ScrollLinePopup(isize),
+    CloseLinePopup,
+    NextWorkspace,
+    PreviousWorkspace,
+    YankSelection,
+    YankColumn,
+    /// `w` and `b`.
+    WordForward,
+    WordBack,
+    Paste,
+    RowDown,
+    RowUp,
+    ScrollFrame,
+    ToggleSplit,
+    SwitchHalf,
+    Suspend,
+    /// Only sent where there's SIGCONT.
+    #[cfg_attr(not(unix), allow(dead_code))]
+    Continued,
 }
 
 impl Message {
+    /// Sent by jobs rather than the user.
+    fn is_background(&self) -> bool {
+        matches!(
+            self,
+            Self::OutputChunk(_, _)
+                | Self::ErrorChunk(..)
+                | Self::JobExited(..)
+                | Self::ReadFailed(..)
+                | Self::Tick
+                | Self::ScrollFrame
+                | Self::Continued
+                | Self::Resize(_, _)
+                | Self::Completions(_, _)
+                | Self::Segment(..)
+                | Self::HistoryImported(_)
+                | Self::HistoryLoaded(_)
+                | Self::Derived(..)
+                | Self::Previewed(..)
+                | Self::Piped(..)
+        )
+    }
+
+    fn ends_pending_key(&self) -> bool {
+        matches!(
+            self,
+            Self::CancelPending
+                | Self::SetMark(_)
+                | Self::JumpToMark(_)
+                | Self::JumpBack
+                | Self::Top
+                | Self::SelectRegister(_)
+                | Self::ChangeWordCase { .. }
+                | Self::RowDown
+                | Self::RowUp
+                | Self::ScrubBack
+                | Self::ScrubForward
+                | Self::NextBookmark
+                | Self::PreviousBookmark
+                | Self::ExpandLine
+                | Self::TakeCommand
+                | Self::OpenLink
+                | Self::OpenLinkPicker
+                | Self::NextWorkspace
+                | Self::PreviousWorkspace
+                | Self::Yank
+                | Self::YankColumn
+                | Self::PendingObject { .. }
+                | Self::TextObject { .. }
+                | Self::DeleteLine
+                | Self::RunLast
+                | Self::Mouse(_)
+        )
+    }
+
+    /// Moving on by screen row with `gj`/`gk` keeps to the same column.
+    fn keeps_goal_column(&self) -> bool {
+        self.is_background() || matches!(self, Self::PendingKey(_) | Self::RowDown | Self::RowUp)
+    }
+
+    /// Leaves the completion popup open; typing narrows it down.
+    fn keeps_completion(&self) -> bool {
+        self.is_background()
+            || matches!(
+                self,
+                Self::Complete
+                    | Self::CompletionNext
+                    | Self::CompletionPrevious
+                    | Self::CompletionAccept
+                    | Self::WriteCommandChar(_)
+                    | Self::Backspace
+            )
+    }
+
     fn is_editing_command(&self) -> bool {
         matches!(
             self,
-            Self::Submit | Self::WriteCommandChar(_) | Self::Backspace
+            Self::Submit
+                | Self::Enqueue
+                | Self::InsertComment
+                | Self::Complete
+                | Self::ToggleCase
+                | Self::ChangeWordCase { .. }
+                | Self::Increment(_)
+                | Self::TextObject { .. }
+                | Self::DeleteLine
+                | Self::WriteCommandChar(_)
+                | Self::Newline
+                | Self::Backspace
+                | Self::ReplaceChar(_)
+                | Self::ReplaceBackspace
+                | Self::Kill(_)
+                | Self::KillLine(_)
+                | Self::YankKill
+                | Self::YankPop
+                | Self::InsertLastArgument
+                | Self::AcceptGhost
+        )
+    }
+
+    /// Carries on a run of kills or yanks.
+    fn uses_kill_ring(&self) -> bool {
+        matches!(
+            self,
+            Self::Kill(_) | Self::KillLine(_) | Self::YankKill | Self::YankPop
         )
     }
 }
 
 fn main() -> color_eyre::Result<()> {
+    let mut profile = startup::Profile::new(env::args().any(|arg| arg == "--profile-startup"));
+    let (mut terminal, capabilities) = match profile.time("terminal", tui::init_terminal) {
+        Ok(terminal) => terminal,
+        Err(e) => {
+            eprintln!("shim: {e}");
+            process::exit(1);
+        }
+    };
     tui::install_panic_hook();
-    let mut terminal = tui::init_terminal()?;
-    let mut model = Model::default();
+    let (events, incoming) = mpsc::channel();
+    event::spawn_input(events.clone());
+    #[cfg(unix)]
+    let signals = event::spawn_signals(events.clone());
+    #[cfg(not(unix))]
+    let signals: io::Result<()> = Ok(());
+    let config = profile.time("config", Config::load);
+    if config.warn_unknown_commands {
+        profile.start("path index", path_index::build_soon);
+    }
+    let (history, previous_commands, history_warning) = match config.defer_history {
+        true => profile.start("history", || {
+            (
+                history::open_later(config.history, events.clone()),
+                Vec::new(),
+                None,
+            )
+        }),
+        false => profile.time("history", || history::open(config.history)),
+    };
+    let snippets = profile.time("snippets", snippet::load);
+    let abbreviations = profile.time("abbreviations", abbr::load);
+    let frecency = profile.time("frecency", frecency::Frecency::load);
+    let mut model = Model {
+        view_locked: config.lock_view,
+        config,
+        snippets,
+        abbreviations,
+        previous_commands,
+        history,
+        status: history_warning
+            .or_else(|| capabilities.missing())
+            .or_else(|| {
+                signals
+                    .err()
+                    .map(|e| format!("Ctrl-Z from outside won't suspend: {e}"))
+            }),
+        terminal: capabilities,
+        frecency,
+        events: Some(events),
+        ..Default::default()
+    };
+    if let Some(dir) = config::dir() {
+        profile.time("autosave", || model.start_autosave(dir, process::id()));
+    }
+    profile.time("env file", || model.check_env_file());
+    profile.start("history import", || model.import_on_first_run());
+    profile.start("prompt segments", || model.refresh_segments());
+    model.startup = profile;
+    model.preview.live = model.config.pipe_preview;
+    if model.config.debug {
+        model.start_trace();
+    }
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--debug" {
+            model.start_trace();
+            continue;
+        }
+        if arg == "--profile-startup" {
+            continue;
+        }
+        match (arg.as_str(), args.next()) {
+            ("--replay", Some(path)) => {
+                if let Err(e) = model.start_replay(&path) {
+                    model.status = Some(e);
+                }
+            }
+            ("--record", Some(path)) => model.status = Some(model.record(&["start", &path])),
+            _ => {}
+        }
+    }
 
+    let mut mouse = false;
+    let mut underline = false;
     while model.running_state != RunningState::Done {
+        let mut pending = std::mem::take(&mut model.suspending);
+        if let Some(handover) = model.handover.take() {
+            let ended = tui::hand_over(capabilities, handover.process);
+            model.handed_back(handover.output, ended);
+            pending = suspend::Pending::Redraw;
+        }
+        if pending == suspend::Pending::Stop {
+            let _ = tui::suspend(capabilities);
+        }
+        if pending != suspend::Pending::Nothing {
+            if let Err(e) = tui::resume(capabilities) {
+                model.status = Some(format!("couldn't take the terminal back: {e}"));
+            }
+            // given back along with the rest
+            mouse = false;
+            underline = false;
+            let _ = terminal.clear();
+            model.images_shown.clear();
+        }
+        if model.config.mouse != mouse {
+            mouse = model.config.mouse;
+            if let Err(e) = tui::capture_mouse(mouse) {
+                model.status = Some(format!("mouse: {e}"));
+            }
+        }
+        if (model.mode == Mode::Replace) != underline {
+            underline = model.mode == Mode::Replace;
+            let _ = tui::underline_cursor(underline);
+        }
         // Render the current view
-        terminal.draw(|f| view(&mut model, f))?;
+        let drawing = Instant::now();
+        model.recheck_command();
+        model.recheck_cd();
+        model.recheck_preview();
+        if let Err(e) = terminal.draw(|f| view(&mut model, f)) {
+            // most likely the terminal has gone; put back what can be
+            let _ = tui::restore_terminal(capabilities);
+            eprintln!("shim: lost the terminal: {e}");
+            process::exit(1);
+        }
+        if model.report_startup(drawing) {
+            continue;
+        }
+        let images = model.visible_images();
+        if images != model.images_shown {
+            // text drawn over an image leaves the rest of it on screen
+            if !model.images_shown.is_empty() {
+                let _ = terminal.clear();
+                let _ = terminal.draw(|f| view(&mut model, f));
+            }
+            let _ = model.draw_images(&mut io::stdout(), &images, model.pane);
+            model.images_shown = images;
+        }
+
+        model.typeahead.busy(drawing, Instant::now());
+        model.schedule.drawn(Instant::now());
 
-        // Handle events and map to a Message
-        let mut current_msg = handle_event(&model)?;
+        // Wait for something to happen or come due, then take everything
+        // else that has arrived so a burst of output costs one redraw.
+        // Output alone waits for the next frame.
+        loop {
+            model.plan(Instant::now());
+            let first = match incoming.recv_timeout(model.schedule.timeout(Instant::now())) {
+                Ok(app_event) => Some(app_event),
+                Err(RecvTimeoutError::Timeout) => None,
+                Err(e) => return Err(e.into()),
+            };
+            let mut urgent = first.is_none();
+            let arrived = first.into_iter().chain(incoming.try_iter()).collect();
+            let mut arrived = VecDeque::from(model.drop_stale_keys(arrived));
+            while let Some(app_event) = arrived.pop_front() {
+                match app_event {
+                    AppEvent::OutputChunk { .. } | AppEvent::ErrorChunk { .. } => {
+                        model.schedule.streamed()
+                    }
+                    _ => urgent = true,
+                }
+                let (handling, mode) = (Instant::now(), model.mode);
+                let read = match app_event {
+                    AppEvent::Key(_, at) => Some(at),
+                    _ => None,
+                };
+                // Handle events and map to a Message
+                let mut current_msg = handle_event(&model, app_event);
 
-        // Process updates as long as they return a non-None message
-        while current_msg.is_some() {
-            current_msg = update(&mut model, current_msg.unwrap());
+                // Process updates as long as they return a non-None message
+                while let Some(msg) = current_msg {
+                    current_msg = update(&mut model, msg);
+                }
+                if let Some(at) = read
+                    && model.mode != mode
+                {
+                    model.typeahead.switched_mode(at);
+                }
+                // keys typed meanwhile are on their way
+                if model.typeahead.busy(handling, Instant::now()) {
+                    arrived.extend(incoming.try_iter());
+                    arrived = VecDeque::from(model.drop_stale_keys(arrived.into()));
+                }
+            }
+            let due = model.schedule.due(Instant::now());
+            if due.contains(&schedule::Wake::Scroll) {
+                update(&mut model, Message::ScrollFrame);
+            }
+            if due
+                .iter()
+                .any(|w| !matches!(w, schedule::Wake::Scroll | schedule::Wake::Stream))
+            {
+                update(&mut model, Message::Tick);
+            }
+            if urgent || !due.is_empty() || !model.schedule.holding(Instant::now()) {
+                break;
+            }
         }
     }
 
-    tui::restore_terminal()?;
+    model.end_autosave();
+    tui::restore_terminal(capabilities)?;
     Ok(())
 }
 
+/// The command line grows with multi-line commands up to this many rows.
+const MAX_COMMAND_ROWS: u16 = 8;
+
 fn view(model: &mut Model, frame: &mut Frame) {
+    let command_rows = min(model.get_command_rows(), MAX_COMMAND_ROWS);
     let layout = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
                 Constraint::Length(1),
                 Constraint::Min(3),
-                Constraint::Length(3),
+                Constraint::Length(command_rows + 2),
             ]
             .as_ref(),
         )
         .split(frame.area());
 
+    model.pane = layout[1];
     model.height = layout[1].height.saturating_sub(2); // for the borders
+    model.width = layout[1].width.saturating_sub(2);
+    model.follow_scrollback_cursor();
 
     let path = env::current_dir()
         .ok()
         .and_then(|p| p.to_str().map(|p| p.to_string()))
         .unwrap_or("~".into());
 
-    frame.render_widget(
-        Paragraph::new(format!(
-            "{:?}  {}/{}",
-            model.mode,
+    let status = match (model.focus(), &model.env_prompt) {
+        (Focus::Overlay(Overlay::AbandonQueue), _) => {
+            Span::raw("command stopped; drop the rest of the queue? (y/n)")
+        }
+        (Focus::Overlay(Overlay::Quit), _) => Span::raw(format!(
+            "jobs still running: {}; quit anyway? (y/n)",
+            model.running_everywhere()
+        )),
+        (Focus::Overlay(Overlay::Dangerous), _) => Span::raw("really run? (y/n)").white().on_red(),
+        (Focus::Overlay(Overlay::EnvFile), Some(file)) => {
+            Span::raw(format!("load {}? (y/n)", file.display()))
+        }
+        _ => Span::raw(model.status.as_deref().unwrap_or("")),
+    };
+    let mut top: Vec<Span> = Vec::new();
+    for (tab, active) in model.tabs() {
+        let tab = Span::raw(format!(" {tab} "));
+        top.push(if active { tab.reversed() } else { tab });
+        top.push(Span::raw(" "));
+    }
+    top.push(status);
+    frame.render_widget(Paragraph::new(Line::from(top)), layout[0]);
+    let here = model.history_here.then(|| "history: here".to_string());
+    let locked = model.view_locked.then(|| "view locked".to_string());
+    let queue = model
+        .queue_summary()
+        .into_iter()
+        .chain(model.retry_summary())
+        .chain(model.replay_summary())
+        .chain(
+            model
+                .outputs
+                .get(model.viewing_output)
+                .and_then(Output::usage_note),
+        )
+        .chain(model.split_note())
+        .chain(model.context_note())
+        .chain(model.recorder.as_ref().map(|_| "rec".to_string()))
+        .chain(here)
+        .chain(locked)
+        .chain(model.clock_note())
+        .map(|q| format!("{q}  "))
+        .collect::<String>();
+    let theme = &model.config.theme;
+    let warning = model
+        .lint_summary()
+        .map(|w| format!("{w}  "))
+        .unwrap_or_default();
+    let viewed = model.outputs.get(model.viewing_output);
+    let troubled = match viewed.is_some_and(|o| !o.warnings.is_empty()) {
+        true => "⚠ ",
+        false => "",
+    };
+    let mode = match model.selection() {
+        Some(_) => "Visual".to_string(),
+        None => format!("{:?}", model.mode),
+    };
+    let mut indicator = vec![Span::raw(warning).yellow(), Span::raw(troubled).yellow()];
+    indicator.extend(model.segment_spans(segment::Place::Top));
+    indicator.extend([
+        Span::raw(queue),
+        Span::styled(format!(" {mode} "), theme.mode(&model.mode)),
+        Span::raw(format!(
+            " {}/{}",
             model.viewing_output + 1,
             model.outputs.len()
-        ))
-        .right_aligned(),
-        layout[0],
-    );
-
-    let (program, text, scroll) = model
-        .outputs
-        .get_mut(model.viewing_output)
-        .map(|o| (&o.stdout[..], &o.command[..], o.scroll))
-        .unwrap_or(("", "", (0, 0)));
+        )),
+    ]);
     frame.render_widget(
-        Paragraph::new(program)
-            .scroll(scroll)
-            .block(Block::bordered().title(text)),
-        layout[1],
+        Paragraph::new(Line::from(indicator)).right_aligned(),
+        layout[0],
     );
+    let focus = model.cursor.pane();
 
-    let start = match model.mode {
-        Mode::Insert => "❯",
-        Mode::Normal => "❮",
+    let footer = viewed
+        .filter(|_| model.scrollback.is_none())
+        .and_then(Output::warning_footer)
+        .unwrap_or_default();
+    let (program, text, scroll) = match model.scrollback {
+        Some(_) => (model.scrollback_text(), "scrollback".into(), (0, 0)),
+        None => model
+            .outputs
+            .get(model.viewing_output)
+            .map(|o| {
+                let mut title = title(o, &model.config.redact);
+                if let Some(times) = o.run_times(&model.config.time_format, model.config.utc) {
+                    title = format!("{title} {times}");
+                }
+                if let Some(note) = o.empty_note() {
+                    return (Line::raw(note).dim().into(), title, (0, 0));
+                }
+                if model.config.wrap {
+                    return (model.wrapped_view().into(), title, (0, 0));
+                }
+                let settings = o.display_settings(&model.config);
+                let top = o.text_from(o.scroll.0);
+                let text = display::render(top, model.height as usize, settings);
+                // it starts at the top of the view already
+                (text.into(), title, (0, o.scroll.1))
+            })
+            .unwrap_or_default(),
     };
-
-    if let Some(curr) = model.viewing_command {
-        let show = model
-            .previous_commands
-            .get(curr)
-            .cloned()
-            .unwrap_or("".into());
+    if model.splitting() {
+        stderr::render(model, frame, layout[1]);
+    } else {
         frame.render_widget(
-            Paragraph::new(format!("{} {}", start, show)).block(Block::bordered().title(path)),
-            layout[2],
+            Paragraph::new(program).scroll(scroll).block(
+                Block::bordered()
+                    .title(text)
+                    .title_bottom(Line::raw(footer).dim())
+                    .border_style(theme.border(focus == Focus::Output)),
+            ),
+            layout[1],
         );
-    } else {
+        table::render(model, frame, layout[1]);
+        select::render(model, frame, layout[1]);
+        bookmark::render(model, frame, layout[1]);
+        link::render(model, frame, layout[1]);
+        expand::render(model, frame, layout[1]);
+        preview::render(model, frame, layout[1]);
+    }
+    toast::render(model, frame, layout[1]);
+
+    let mut prompt = model.segment_spans(segment::Place::Prompt);
+    prompt.push(model.prompt());
+    let prompt_width: usize = prompt.iter().map(Span::width).sum();
+    // the border and the prompt with a space after it
+    let command_x = layout[2].x + 2 + prompt_width as u16;
+
+    // continuation lines are indented to line up under the first
+    let indent = " ".repeat(prompt_width + 1);
+    let mut show: Text = model
+        .command()
+        .split('\n')
+        .enumerate()
+        .map(|(i, line)| match i {
+            0 => Line::from_iter(
+                prompt
+                    .iter()
+                    .cloned()
+                    .chain([Span::raw(format!(" {line}"))]),
+            ),
+            _ => Line::raw(format!("{indent}{line}")),
+        })
+        .collect();
+    if let Some(ghost) = model.ghost() {
+        show.push_span(Span::styled(ghost.to_string(), Style::new().dark_gray()));
+    }
+    let cursor_row = match model.cursor {
+        Cursor::CommandLine(_, y) => y,
+        Cursor::OutputBuffer(_, _) => 0,
+    };
+    let command_scroll = (cursor_row + 1).saturating_sub(command_rows);
+    if let Some(prompt) = &model.snippet_prompt {
+        let ask = format!("{}: ", prompt.label());
         frame.render_widget(
-            Paragraph::new(format!("{} {}", start, model.current_command))
-                .block(Block::bordered().title(path)),
+            Paragraph::new(format!("{ask}{}", prompt.input)).block(
+                Block::bordered()
+                    .title(format!("snippet {}", prompt.snippet))
+                    .border_style(theme.border(true)),
+            ),
             layout[2],
         );
+        frame.set_cursor_position(Position::new(
+            layout[2].x + 1 + (ask.chars().count() + prompt.input.chars().count()) as u16,
+            layout[2].y + 1,
+        ));
+        return;
+    }
+    frame.render_widget(
+        Paragraph::new(show).scroll((command_scroll, 0)).block(
+            Block::bordered()
+                .title(path)
+                .border_style(theme.border(focus == Focus::CommandLine)),
+        ),
+        layout[2],
+    );
+
+    if let Some(picker) = &model.picker {
+        picker::render(picker, frame, layout[1]);
+    } else if model.completion.is_none() {
+        peek::render(model, frame, layout[2]);
+    }
+    if model.completion.is_some() {
+        let before = &model.command()[..model.completion_anchor()];
+        let column = before.len() - before.rfind('\n').map_or(0, |i| i + 1);
+        let x = command_x + column as u16;
+        if let Some(completion) = &mut model.completion {
+            complete::render(completion, frame, layout[2], x);
+        }
     }
 
     match model.cursor {
-        Cursor::CommandLine(x) => {
-            frame.set_cursor_position(Position::new(layout[2].x + 3 + x, layout[2].y + 1))
+        Cursor::CommandLine(x, y) => {
+            let before = &model.command_row(y)[..model.char_column(x, y) as usize];
+            frame.set_cursor_position(Position::new(
+                command_x + before.width() as u16,
+                layout[2].y + 1 + y - command_scroll,
+            ))
         }
+        // the halves of a split have no cursor
+        Cursor::OutputBuffer(..) if model.splitting() => {}
         Cursor::OutputBuffer(x, y) => {
-            frame.set_cursor_position(Position::new(layout[1].x + 1 + x, layout[1].y + 1 + y))
+            let rows = model.output_rows(y);
+            let row = display::row_of(&rows, x as usize);
+            let (column, scroll) = match model.outputs.get(model.viewing_output) {
+                Some(o) => {
+                    let line = model.output_line(y).unwrap_or("");
+                    let settings = o.display_settings(&model.config);
+                    let column = display::column(line, x as usize, settings)
+                        - display::column(line, rows[row].start, settings);
+                    (column as u16, scroll.1)
+                }
+                None => (0, 0),
+            };
+            let screen_row = min(model.screen_row(y) + row, model.height as usize);
+            frame.set_cursor_position(Position::new(
+                layout[1].x + 1 + column.saturating_sub(scroll),
+                layout[1].y + 1 + screen_row as u16,
+            ))
+        }
+    }
+}
+
+fn title(output: &Output, redact: &[regex::Regex]) -> String {
+    let command = output.command.replace('\n', " ");
+    let mut command = redact::line(redact, &command).into_owned();
+    if let Some(title) = &output.title {
+        command = format!("{title} — {command}");
+    }
+    if let Some(pipe) = output.pipe.as_ref().filter(|p| p.shown) {
+        command = format!("{command} | {}", pipe.command);
+    }
+    if output.reruns > 0 {
+        command = format!("{command} ×{}", output.reruns + 1);
+    }
+    if let Some(tags) = output.tag_note() {
+        command = format!("{command} {tags}");
+    }
+    if let Some(encoding) = output.decoder.note() {
+        command = format!("{command} [{encoding}]");
+    }
+    let retry = output
+        .frozen_note()
+        .or_else(|| output.retry_note())
+        .or_else(|| output.each_note())
+        .or_else(|| output.rerun_note())
+        .or_else(|| output.stage_note())
+        .or_else(|| output.signal_note())
+        .or_else(|| output.deriving_note());
+    match (output.started, retry) {
+        (Some(started), retry) if output.running => {
+            let retry = retry.map(|r| format!(", {r}")).unwrap_or_default();
+            format!(
+                "{command} (running {}s{retry})",
+                started.elapsed().as_secs()
+            )
         }
+        (_, Some(retry)) => format!("{command} ({retry})"),
+        _ => command,
+    }
+}
+
+/// Convert AppEvent to Message
+fn handle_event(model: &Model, app_event: AppEvent) -> Option<Message> {
+    match app_event {
+        AppEvent::Key(key, at) if model.typeahead.bounced(&key, at) => None,
+        AppEvent::Key(key, _) => handle_key(model, key),
+        AppEvent::ReadFailed { job_id, error } => Some(Message::ReadFailed(job_id, error)),
+        AppEvent::OutputChunk { job_id, data } => Some(Message::OutputChunk(job_id, data)),
+        AppEvent::ErrorChunk { job_id, data } => Some(Message::ErrorChunk(job_id, data)),
+        AppEvent::JobExited {
+            job_id,
+            status,
+            stages,
+        } => Some(Message::JobExited(job_id, status, stages)),
+        AppEvent::Resize { width, height } => Some(Message::Resize(width, height)),
+        AppEvent::Mouse(event) => Some(Message::Mouse(event)),
+        AppEvent::Completions { key, values } => Some(Message::Completions(key, values)),
+        AppEvent::Segment { name, dir, text } => Some(Message::Segment(name, dir, text)),
+        #[cfg(unix)]
+        AppEvent::Suspend => Some(Message::Suspend),
+        #[cfg(unix)]
+        AppEvent::Continued => Some(Message::Continued),
+        AppEvent::Files { walk, paths, done } => Some(Message::Files(walk, paths, done)),
+        AppEvent::HistoryImported(import) => Some(Message::HistoryImported(import)),
+        AppEvent::HistoryLoaded(loaded) => Some(Message::HistoryLoaded(loaded)),
+        AppEvent::Derived { output, text } => Some(Message::Derived(output, text)),
+        AppEvent::Previewed { typed, result } => Some(Message::Previewed(typed, result)),
+        AppEvent::Piped {
+            output,
+            request,
+            command,
+            result,
+        } => Some(Message::Piped(output, request, command, result)),
     }
 }
 
-/// Convert Event to Message
-///
-/// We don't need to pass in a `model` to this function in this example
-/// but you might need it as your project evolves
-fn handle_event(model: &Model) -> color_eyre::Result<Option<Message>> {
-    if event::poll(Duration::from_millis(250))? {
-        if let Event::Key(key) = event::read()? {
-            if key.kind == event::KeyEventKind::Press {
-                return Ok(handle_key(model, key));
+/// Keys while an overlay has focus; it takes all of them.
+fn overlay_key(model: &Model, overlay: Overlay, key: KeyEvent) -> Option<Message> {
+    let ctrl = |c| key.code == KeyCode::Char(c) && key.modifiers.contains(KeyModifiers::CONTROL);
+    let picking = |kind| model.picker.as_ref().map(|p| p.kind) == Some(kind);
+    let answer = |yes: Message, no: Message| match key.code {
+        KeyCode::Char('y') => Some(yes),
+        KeyCode::Char('n') | KeyCode::Esc => Some(no),
+        _ => None,
+    };
+    match overlay {
+        Overlay::AbandonQueue => answer(Message::AbandonQueue(true), Message::AbandonQueue(false)),
+        Overlay::Quit => answer(Message::ConfirmQuit(true), Message::ConfirmQuit(false)),
+        Overlay::Dangerous => answer(Message::Confirm(true), Message::Confirm(false)),
+        Overlay::EnvFile => answer(Message::AllowEnv(true), Message::AllowEnv(false)),
+        Overlay::Snippet => match key.code {
+            KeyCode::Char(c) => Some(Message::SnippetChar(c)),
+            KeyCode::Backspace => Some(Message::SnippetBackspace),
+            KeyCode::Enter => Some(Message::SnippetSubmit),
+            KeyCode::Esc => Some(Message::SnippetCancel),
+            _ => None,
+        },
+        Overlay::Picker if picking(PickerKind::Links) => match key.code {
+            KeyCode::Char('j') | KeyCode::Down => Some(Message::PickerDown),
+            KeyCode::Char('k') | KeyCode::Up => Some(Message::PickerUp),
+            KeyCode::Char(c) => link::LABELS.find(c).map(Message::PickLink),
+            KeyCode::Enter => Some(Message::PickerAccept),
+            KeyCode::Esc => Some(Message::PickerCancel),
+            _ => None,
+        },
+        Overlay::Picker => match key.code {
+            KeyCode::Char('n') if ctrl('n') => Some(Message::PickerDown),
+            KeyCode::Char('p') if ctrl('p') => Some(Message::PickerUp),
+            KeyCode::Char('j') if ctrl('j') => Some(Message::PickerMove(1)),
+            KeyCode::Char('k') if ctrl('k') => Some(Message::PickerMove(-1)),
+            KeyCode::Char('x') if ctrl('x') => Some(Message::PickerRemove),
+            KeyCode::Char('f') if ctrl('f') => Some(Message::ToggleHistoryHere),
+            KeyCode::Char('u') if ctrl('u') && picking(PickerKind::Buffers) => {
+                Some(Message::UndoClose)
+            }
+            KeyCode::Char(c) => Some(Message::PickerChar(c)),
+            KeyCode::Tab => Some(Message::PickerMark),
+            KeyCode::Right => Some(Message::PickerExpand(true)),
+            KeyCode::Left => Some(Message::PickerExpand(false)),
+            KeyCode::Backspace => Some(Message::PickerBackspace),
+            KeyCode::Down => Some(Message::PickerDown),
+            KeyCode::Up => Some(Message::PickerUp),
+            KeyCode::Enter => Some(Message::PickerAccept),
+            KeyCode::Esc => Some(Message::PickerCancel),
+            _ => None,
+        },
+        Overlay::LinePopup => {
+            let page = (model.height / 2).max(1) as isize;
+            match key.code {
+                KeyCode::Char('c') if ctrl('c') => Some(Message::Interrupt),
+                KeyCode::Char('d') if ctrl('d') => Some(Message::ScrollLinePopup(page)),
+                KeyCode::Char('u') if ctrl('u') => Some(Message::ScrollLinePopup(-page)),
+                KeyCode::Char('j') | KeyCode::Down => Some(Message::ScrollLinePopup(1)),
+                KeyCode::Char('k') | KeyCode::Up => Some(Message::ScrollLinePopup(-1)),
+                KeyCode::Char('y') => Some(Message::Yank),
+                KeyCode::Char('q') | KeyCode::Esc => Some(Message::CloseLinePopup),
+                _ => None,
             }
         }
     }
-    Ok(None)
 }
 
-fn handle_key(model: &Model, key: event::KeyEvent) -> Option<Message> {
+fn handle_key(model: &Model, key: KeyEvent) -> Option<Message> {
+    let ctrl = |c| key.code == KeyCode::Char(c) && key.modifiers.contains(KeyModifiers::CONTROL);
+    if let Focus::Overlay(overlay) = model.focus() {
+        return overlay_key(model, overlay, key);
+    }
+    if let Some(completion) = &model.completion {
+        match key.code {
+            KeyCode::Tab if completion.candidates.len() == 1 => {
+                return Some(Message::CompletionAccept);
+            }
+            KeyCode::Tab => return Some(Message::CompletionNext),
+            KeyCode::BackTab => return Some(Message::CompletionPrevious),
+            KeyCode::Char('n') if ctrl('n') => return Some(Message::CompletionNext),
+            KeyCode::Char('p') if ctrl('p') => return Some(Message::CompletionPrevious),
+            KeyCode::Enter => return Some(Message::CompletionAccept),
+            KeyCode::Esc => return Some(Message::CompletionCancel),
+            // anything else closes it, typing narrows it down
+            _ => {}
+        }
+    }
+    if ctrl('c') {
+        return Some(Message::Interrupt);
+    }
+    if ctrl('z') {
+        return Some(Message::Suspend);
+    }
     match model.mode {
         Mode::Insert => match key.code {
-            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::Quit)
+            // like bash, only on an empty line; jobs read stdin from
+            // /dev/null, so there's no job to send end of input to
+            KeyCode::Char('d') if ctrl('d') => model.command().is_empty().then_some(Message::Quit),
+            KeyCode::Char('r') if ctrl('r') => Some(Message::OpenHistoryPicker),
+            KeyCode::Char('t') if ctrl('t') => Some(Message::OpenFilePicker),
+            KeyCode::Char('f') if ctrl('f') => Some(Message::ToggleHistoryHere),
+            KeyCode::Char('w') if ctrl('w') => Some(Message::Kill(killring::Direction::Back)),
+            KeyCode::Char('u') if ctrl('u') => Some(Message::KillLine(killring::Direction::Back)),
+            KeyCode::Char('k') if ctrl('k') => {
+                Some(Message::KillLine(killring::Direction::Forward))
+            }
+            KeyCode::Char('y') if ctrl('y') => Some(Message::YankKill),
+            KeyCode::Char('y') if key.modifiers.contains(KeyModifiers::ALT) => {
+                Some(Message::YankPop)
+            }
+            KeyCode::Char('#') if key.modifiers.contains(KeyModifiers::ALT) => {
+                Some(Message::InsertComment)
+            }
+            KeyCode::Char('.') if key.modifiers.contains(KeyModifiers::ALT) => {
+                Some(Message::InsertLastArgument)
+            }
+            KeyCode::Char(',') if key.modifiers.contains(KeyModifiers::ALT) => {
+                Some(Message::InsertFromOutput)
+            }
+            KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::ALT) => {
+                Some(Message::OpenArgumentPicker)
+            }
+            // a count for Alt-. or Alt-,, as in readline
+            KeyCode::Char(c @ '0'..='9')
+                if key.modifiers.contains(KeyModifiers::ALT)
+                    && (c != '0' || model.count.is_some()) =>
+            {
+                c.to_digit(10).map(|d| Message::Count(d as u8))
             }
             KeyCode::Char(c) => Some(Message::WriteCommandChar(c)),
+            KeyCode::Right if model.ghost().is_some() => Some(Message::AcceptGhost),
             KeyCode::Esc => Some(Message::Normal),
             KeyCode::Backspace => Some(Message::Backspace),
+            KeyCode::Tab if model.is_snippet_name() => Some(Message::ExpandSnippet),
+            KeyCode::Tab => Some(Message::Complete),
+            KeyCode::Enter if key.modifiers.contains(KeyModifiers::ALT) => Some(Message::Enqueue),
+            KeyCode::Enter if shell::needs_continuation(model.command()) => Some(Message::Newline),
+            KeyCode::Enter => Some(Message::Submit),
+            KeyCode::Up => match model.cursor {
+                Cursor::CommandLine(_, y) if y > 0 => Some(Message::Up),
+                _ => Some(Message::OutCommand),
+            },
+            KeyCode::Down => match model.cursor {
+                Cursor::CommandLine(_, y) if y + 1 < model.get_command_rows() => {
+                    Some(Message::Down)
+                }
+                _ => Some(Message::InCommand),
+            },
+            _ => None,
+        },
+        Mode::Replace => match key.code {
+            KeyCode::Char(c) => Some(Message::ReplaceChar(c)),
+            KeyCode::Backspace => Some(Message::ReplaceBackspace),
+            KeyCode::Esc => Some(Message::Normal),
             KeyCode::Enter => Some(Message::Submit),
             _ => None,
         },
-        Mode::Normal => match key.code {
-            KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::NextOutput)
-            }
-            KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::PreviousOutput)
+        Mode::Normal if model.pending_key.is_some() => match (model.pending_key, key.code) {
+            (Some('m'), KeyCode::Char(c)) if c.is_ascii_lowercase() => Some(Message::SetMark(c)),
+            (Some('\''), KeyCode::Char('\'')) => Some(Message::JumpBack),
+            (Some('\''), KeyCode::Char(c)) if c.is_ascii_lowercase() => {
+                Some(Message::JumpToMark(c))
             }
-            KeyCode::Char('i') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::InCommand)
+            (Some('['), KeyCode::Char('[')) => Some(Message::ScrubBack),
+            (Some(']'), KeyCode::Char(']')) => Some(Message::ScrubForward),
+            (Some('['), KeyCode::Char('b')) => Some(Message::PreviousBookmark),
+            (Some(']'), KeyCode::Char('b')) => Some(Message::NextBookmark),
+            (Some('g'), KeyCode::Char('g')) => Some(Message::Top),
+            (Some('g'), KeyCode::Char('j')) => Some(Message::RowDown),
+            (Some('g'), KeyCode::Char('k')) => Some(Message::RowUp),
+            (Some('g'), KeyCode::Char('l')) => Some(Message::ExpandLine),
+            (Some('g'), KeyCode::Char('x')) => Some(Message::TakeCommand),
+            (Some('g'), KeyCode::Char('o')) => Some(Message::OpenLink),
+            (Some('g'), KeyCode::Char('L')) => Some(Message::OpenLinkPicker),
+            (Some('g'), KeyCode::Char('t')) => Some(Message::NextWorkspace),
+            (Some('g'), KeyCode::Char('T')) => Some(Message::PreviousWorkspace),
+            (Some('g'), KeyCode::Char(c @ ('u' | 'U'))) => Some(Message::PendingKey(c)),
+            (Some('u'), KeyCode::Char('w')) => Some(Message::ChangeWordCase { upper: false }),
+            (Some('U'), KeyCode::Char('w')) => Some(Message::ChangeWordCase { upper: true }),
+            (Some('y'), KeyCode::Char('y')) => Some(Message::Yank),
+            (Some('y'), KeyCode::Char('c')) => Some(Message::YankColumn),
+            (Some('d'), KeyCode::Char('d')) => Some(Message::DeleteLine),
+            (Some('!'), KeyCode::Char('!')) => Some(Message::RunLast),
+            (Some(operator @ ('c' | 'd' | 'y')), KeyCode::Char(c @ ('i' | 'a'))) => {
+                Some(Message::PendingObject {
+                    operator,
+                    around: c == 'a',
+                })
             }
-            KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::OutCommand)
+            (Some('"'), KeyCode::Char(c)) if registers::Registers::is_name(c) => {
+                Some(Message::SelectRegister(c))
             }
-            KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::ScrollUp)
+            _ => Some(Message::CancelPending),
+        },
+        Mode::Normal if model.pending_object.is_some() => match (model.pending_object, key.code) {
+            (Some((operator, around)), KeyCode::Char(c @ ('w' | 'a'))) => {
+                Some(Message::TextObject {
+                    operator,
+                    around,
+                    argument: c == 'a',
+                })
             }
-            KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
-                Some(Message::ScrollDown)
+            _ => Some(Message::CancelPending),
+        },
+        Mode::Normal if model.replay_waiting() && key.modifiers.is_empty() => match key.code {
+            KeyCode::Enter => Some(Message::Submit),
+            KeyCode::Char('s') => Some(Message::ReplaySkip),
+            KeyCode::Char('q') => Some(Message::ReplayStop),
+            _ => handle_normal_key(key),
+        },
+        // `3.`; a 0 that doesn't follow another digit is `0`
+        Mode::Normal
+            if key.modifiers.is_empty()
+                && match key.code {
+                    KeyCode::Char('1'..='9') => true,
+                    KeyCode::Char('0') => model.count.is_some(),
+                    _ => false,
+                } =>
+        {
+            match key.code {
+                KeyCode::Char(c) => c.to_digit(10).map(|d| Message::Count(d as u8)),
+                _ => None,
             }
-            KeyCode::Char('i') => Some(Message::InsertBefore),
-            KeyCode::Char('a') => Some(Message::InsertAfter),
-            KeyCode::Char('I') => Some(Message::InsertBeforeLine),
-            KeyCode::Char('A') => Some(Message::InsertAfterLine),
-            KeyCode::Char('h') => Some(Message::Left),
-            KeyCode::Char('j') => Some(Message::Down),
-            KeyCode::Char('k') => Some(Message::Up),
-            KeyCode::Char('l') => Some(Message::Right),
-            _ => None,
+        }
+        // `v` then motions, and `y` to yank what they cover
+        Mode::Normal if model.selection().is_some() && key.modifiers.is_empty() => match key.code {
+            KeyCode::Char('y') => Some(Message::YankSelection),
+            KeyCode::Char('v') | KeyCode::Esc => Some(Message::EndVisual),
+            _ => handle_normal_key(key),
         },
+        // `ciw`, `da`, `yy` and the like on the command line
+        Mode::Normal if model.operator(key).is_some() => {
+            model.operator(key).map(Message::PendingKey)
+        }
+        // `"ay` yanks rather than taking the suggestion
+        Mode::Normal if model.register.is_some() && key.code == KeyCode::Char('y') => {
+            Some(Message::Yank)
+        }
+        // `yy` and `yc` in a table
+        Mode::Normal if model.in_table() && key.code == KeyCode::Char('y') => {
+            Some(Message::PendingKey('y'))
+        }
+        Mode::Normal if model.splitting() && key.code == KeyCode::Tab => Some(Message::SwitchHalf),
+        // `R` in the output runs the command again
+        Mode::Normal
+            if key.code == KeyCode::Char('R')
+                && matches!(model.cursor, Cursor::CommandLine(..))
+                && !model.command().is_empty() =>
+        {
+            Some(Message::EnterReplace)
+        }
+        Mode::Normal => handle_normal_key(key),
     }
 }
 
-fn update(model: &mut Model, msg: Message) -> Option<Message> {
-    if msg.is_editing_command() {
-        if let Some(curr) = model.viewing_command {
-            model.current_command = model
-                .previous_commands
-                .get(curr)
-                .cloned()
-                .unwrap_or("".into());
+fn handle_normal_key(key: KeyEvent) -> Option<Message> {
+    let ctrl = |c| key.code == KeyCode::Char(c) && key.modifiers.contains(KeyModifiers::CONTROL);
+    match key.code {
+        KeyCode::Char('n') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::NextOutput)
         }
-        model.viewing_command = None;
-    }
-    match msg {
-        Message::Down => match model.cursor {
-            Cursor::CommandLine(_) => {}
-            Cursor::OutputBuffer(x, y) => {
-                if y + 1 >= model.height {
-                    model.cursor = Cursor::CommandLine(x);
-                } else {
-                    model.cursor = Cursor::OutputBuffer(x, y + 1)
-                }
-            }
-        },
-        Message::Up => match model.cursor {
-            Cursor::CommandLine(x) => {
-                model.cursor = Cursor::OutputBuffer(x, model.height.saturating_sub(1))
-            }
-            Cursor::OutputBuffer(x, y) => {
-                model.cursor = Cursor::OutputBuffer(x, y.saturating_sub(1))
-            }
-        },
-        Message::Left => {
-            model.cursor.left();
-        }
-        Message::Right => {
-            let max = match model.cursor {
-                Cursor::CommandLine(_) => match model.viewing_command {
-                    Some(i) => model.previous_commands.get(i).map(|s| s.len()).unwrap_or(0),
-                    None => model.current_command.len(),
-                },
-                Cursor::OutputBuffer(_, y) => model
-                    .outputs
-                    .get(model.viewing_output)
-                    .and_then(|o| o.stdout.lines().nth((y + o.scroll.0) as usize))
-                    .map(|s| s.len().saturating_sub(1))
-                    .unwrap_or(0),
-            };
-            model.cursor.right_capped(max as u16);
-        }
-        Message::Submit => {
-            if let Some(output) = run(model.current_command.clone()) {
-                if let Ok(s) = String::from_utf8(output.stdout) {
-                    model.outputs.push(Output {
-                        command: model.current_command.clone(),
-                        stdout: s.clone(),
-                        scroll: ((s.lines().count() as u16).saturating_sub(model.height), 0),
-                    });
-                    model.viewing_output = model.outputs.len() - 1;
-                }
-            }
-            model.previous_commands.push(model.current_command.clone());
-            model.viewing_command = None;
-            model.current_command.clear();
-            model.cursor = Cursor::CommandLine(0);
+        KeyCode::Char('#') if key.modifiers.contains(KeyModifiers::ALT) => {
+            Some(Message::InsertComment)
         }
-        Message::Quit => {
-            // You can handle cleanup and exit here
-            model.running_state = RunningState::Done;
+        KeyCode::Char('.') if key.modifiers.contains(KeyModifiers::ALT) => {
+            Some(Message::InsertLastArgument)
         }
-        Message::NextOutput => {
-            if model.outputs.is_empty() {
-                model.viewing_output = 0;
-            } else {
-                model.viewing_output = min(
-                    model.outputs.len() - 1,
-                    model.viewing_output.saturating_add(1),
-                );
-            }
+        KeyCode::Char(',') if key.modifiers.contains(KeyModifiers::ALT) => {
+            Some(Message::InsertFromOutput)
         }
-        Message::PreviousOutput => {
+        KeyCode::Char('a') if key.modifiers.contains(KeyModifiers::ALT) => {
+            Some(Message::OpenArgumentPicker)
+        }
+        KeyCode::Char('p') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::PreviousOutput)
+        }
+        KeyCode::Char('i') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::InCommand)
+        }
+        KeyCode::Char('o') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::OutCommand)
+        }
+        KeyCode::Char('u') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::ScrollUp)
+        }
+        KeyCode::Char('d') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::ScrollDown)
+        }
+        KeyCode::Char('g') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::OpenDirectoryPicker)
+        }
+        KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(Message::OpenSnippetPicker)
+        }
+        KeyCode::Char('r') if ctrl('r') => Some(Message::OpenHistoryPicker),
+        KeyCode::Char('f') if ctrl('f') => Some(Message::ToggleHistoryHere),
+        KeyCode::Char('l') if ctrl('l') => Some(Message::ToggleViewLock),
+        KeyCode::Char('a') if ctrl('a') => Some(Message::Increment(1)),
+        KeyCode::Char('x') if ctrl('x') => Some(Message::Increment(-1)),
+        KeyCode::Char('~') => Some(Message::ToggleCase),
+        KeyCode::Char('i') => Some(Message::InsertBefore),
+        KeyCode::Char('a') => Some(Message::InsertAfter),
+        KeyCode::Char('I') => Some(Message::InsertBeforeLine),
+        KeyCode::Char('A') => Some(Message::InsertAfterLine),
+        KeyCode::Char('h') => Some(Message::Left),
+        KeyCode::Char('j') => Some(Message::Down),
+        KeyCode::Char('k') => Some(Message::Up),
+        KeyCode::Char('l') => Some(Message::Right),
+        KeyCode::Char('0') => Some(Message::LineStart),
+        KeyCode::Char('$') => Some(Message::LineEnd),
+        KeyCode::Char('w') => Some(Message::WordForward),
+        KeyCode::Char('b') => Some(Message::WordBack),
+        KeyCode::Char('y') => Some(Message::AcceptSuggestion),
+        KeyCode::Char('r') => Some(Message::Retry),
+        KeyCode::Char('?') => Some(Message::Preview),
+        KeyCode::Char('Y') => Some(Message::Yank),
+        KeyCode::Char('v') => Some(Message::Visual),
+        KeyCode::Char('p') => Some(Message::Paste),
+        KeyCode::Char('.') => Some(Message::RepeatChange),
+        KeyCode::Char('M') => Some(Message::ToggleBookmark),
+        KeyCode::Char('R') => Some(Message::RerunAppending),
+        KeyCode::Char('S') => Some(Message::ToggleSplit),
+        KeyCode::Char('B') => Some(Message::OpenBufferPicker),
+        KeyCode::Char(c @ ('m' | '\'' | 'g' | '"' | '!' | '[' | ']')) => {
+            Some(Message::PendingKey(c))
+        }
+        KeyCode::Char('G') => Some(Message::Bottom),
+        _ => None,
+    }
+}
+
+fn update(model: &mut Model, msg: Message) -> Option<Message> {
+    let traced = model.trace_start(&msg);
+    let next = apply(model, msg);
+    // whatever the message, the cursor ends up somewhere it may rest
+    model.clamp_command_cursor();
+    model.clamp_output_cursor();
+    if let Some(traced) = traced {
+        model.trace_end(traced);
+    }
+    next
+}
+
+fn apply(model: &mut Model, msg: Message) -> Option<Message> {
+    if !msg.is_background() {
+        model.status = None;
+    }
+    model.follow_scrollback_cursor();
+    if model.selection().is_none() {
+        model.visual = None;
+    }
+    if msg.ends_pending_key() {
+        model.pending_key = None;
+        model.pending_object = None;
+    }
+    if !msg.keeps_completion() {
+        model.completion = None;
+    }
+    if !msg.keeps_goal_column() {
+        model.goal_column = None;
+    }
+    if !msg.is_background() && msg != Message::Backspace {
+        model.expanded = None;
+    }
+    if !msg.is_background() && !msg.uses_kill_ring() {
+        model.kills.interrupt();
+    }
+    if !msg.is_background() && msg != Message::InsertLastArgument {
+        model.last_argument = None;
+    }
+    if msg.is_editing_command() {
+        model.edit_recalled();
+    }
+    let counted = match msg {
+        // kept for the key after, as in 3]b
+        Message::Count(_) | Message::PendingKey(_) => None,
+        _ if msg.is_background() => None,
+        _ => model.count.take(),
+    };
+    let count = counted.unwrap_or(1);
+    model.record_typing(&msg);
+    if model.scroll_half(&msg) {
+        return None;
+    }
+    match msg {
+        Message::Down => match model.cursor {
+            Cursor::CommandLine(x, y) => {
+                if y + 1 < model.get_command_rows() {
+                    model.cursor = Cursor::CommandLine(model.rest_column(x, y + 1), y + 1)
+                }
+            }
+            Cursor::OutputBuffer(x, y) => {
+                if model.screen_row(y + 1) >= model.height as usize {
+                    model.cursor = Cursor::CommandLine(model.rest_column(x, 0), 0);
+                } else {
+                    model.cursor = Cursor::OutputBuffer(x, y + 1)
+                }
+            }
+        },
+        Message::Up => match model.cursor {
+            Cursor::CommandLine(x, y) if y > 0 => {
+                model.cursor = Cursor::CommandLine(model.rest_column(x, y - 1), y - 1)
+            }
+            Cursor::CommandLine(x, _) => {
+                model.cursor = Cursor::OutputBuffer(x, model.last_shown_line())
+            }
+            Cursor::OutputBuffer(x, y) => {
+                model.cursor = Cursor::OutputBuffer(x, y.saturating_sub(1))
+            }
+        },
+        Message::RowDown => return model.row_down(),
+        Message::RowUp => return model.row_up(),
+        Message::Left => match model.cursor {
+            Cursor::CommandLine(x, y) => {
+                model.cursor = Cursor::CommandLine(model.previous_column(x, y), y)
+            }
+            Cursor::OutputBuffer(_, _) => model.cursor.left(),
+        },
+        Message::Right => match model.cursor {
+            Cursor::CommandLine(x, y) => {
+                let x = model.next_column(model.char_column(x, y), y);
+                model.cursor = Cursor::CommandLine(model.rest_column(x, y), y)
+            }
+            Cursor::OutputBuffer(_, y) => {
+                let max = model
+                    .output_line(y)
+                    .map(|s| display::len(s).saturating_sub(1) as u16)
+                    .unwrap_or(0);
+                model.cursor.right_capped(max);
+            }
+        },
+        Message::Submit | Message::Enqueue => {
+            let enqueue = msg == Message::Enqueue;
+            if model.is_dangerous(model.command()) {
+                model.confirming = Some(enqueue);
+            } else {
+                model.submit(enqueue);
+            }
+        }
+        Message::InsertComment => {
+            let command = &mut model.current_command;
+            match command.trim_start().strip_prefix('#') {
+                Some(rest) => *command = rest.to_string(),
+                None => command.insert(0, '#'),
+            }
+            return Some(Message::Submit);
+        }
+        Message::AllowEnv(allow) => model.allow_env_file(allow),
+        Message::Confirm(run) => match model.confirming.take() {
+            Some(enqueue) if run => model.submit(enqueue),
+            _ => model.status = Some("not run".into()),
+        },
+        Message::Quit if model.running_everywhere() > 0 => model.quitting = true,
+        Message::Quit => model.running_state = RunningState::Done,
+        Message::ConfirmQuit(quit) => {
+            model.quitting = false;
+            if quit {
+                model.running_state = RunningState::Done;
+            }
+        }
+        Message::NextWorkspace => match counted {
+            Some(n) => model.switch_workspace(n.saturating_sub(1)),
+            None => model.cycle_workspace(true),
+        },
+        Message::PreviousWorkspace => {
+            for _ in 0..count {
+                model.cycle_workspace(false);
+            }
+        }
+        Message::NextOutput if model.scrollback.is_some() => model.scrollback_to_output(true),
+        Message::PreviousOutput if model.scrollback.is_some() => model.scrollback_to_output(false),
+        Message::NextOutput => {
             if model.outputs.is_empty() {
                 model.viewing_output = 0;
             } else {
-                model.viewing_output = model.viewing_output.saturating_sub(1);
+                model.view_output(min(
+                    model.outputs.len() - 1,
+                    model.viewing_output.saturating_add(1),
+                ));
             }
         }
+        Message::PreviousOutput => {
+            if model.outputs.is_empty() {
+                model.viewing_output = 0;
+            } else {
+                model.view_output(model.viewing_output.saturating_sub(1));
+            }
+        }
+        Message::WriteCommandChar(' ') if model.expand_abbreviation() => {
+            model.refresh_completion();
+        }
         Message::WriteCommandChar(c) => {
-            match model.cursor {
-                Cursor::CommandLine(x) => model.current_command.insert(x as usize, c),
-                Cursor::OutputBuffer(_, _) => panic!(
-                    "not supposed to write character to command when cursor is in output buffer"
-                ),
+            // typing with the cursor in the output does nothing
+            if let Cursor::CommandLine(x, y) = model.cursor {
+                let offset = model.command_offset(x, y);
+                model.current_command.insert(offset, c);
+                model.cursor = Cursor::CommandLine(x + c.len_utf8() as u16, y);
+                model.refresh_completion();
             }
-            model.cursor.right();
         }
-        Message::Normal => model.mode = Mode::Normal,
+        Message::Newline => {
+            if let Cursor::CommandLine(x, y) = model.cursor {
+                let offset = model.command_offset(x, y);
+                model.current_command.insert(offset, '\n');
+                model.cursor = Cursor::CommandLine(0, y + 1);
+            }
+        }
+        Message::Normal => {
+            model.mode = Mode::Normal;
+            model.clamp_command_cursor();
+        }
+        Message::EnterReplace => model.enter_replace(),
+        Message::ReplaceChar(c) => model.replace_char(c),
+        Message::ReplaceBackspace => model.replace_backspace(),
+        Message::LineStart => match model.cursor {
+            Cursor::CommandLine(_, y) => model.cursor = Cursor::CommandLine(0, y),
+            Cursor::OutputBuffer(_, y) => model.cursor = Cursor::OutputBuffer(0, y),
+        },
+        Message::LineEnd => match model.cursor {
+            Cursor::CommandLine(_, y) => {
+                model.cursor = Cursor::CommandLine(model.rest_column(u16::MAX, y), y)
+            }
+            Cursor::OutputBuffer(_, y) => {
+                let last = model
+                    .output_line(y)
+                    .map_or(0, |s| display::len(s).saturating_sub(1) as u16);
+                model.cursor = Cursor::OutputBuffer(last, y);
+            }
+        },
         Message::InsertBefore => {
             model.mode = Mode::Insert;
-            let x = match model.cursor {
-                Cursor::CommandLine(x) => x,
-                Cursor::OutputBuffer(x, _) => x,
+            model.cursor = match model.cursor {
+                Cursor::CommandLine(x, y) => Cursor::CommandLine(model.char_column(x, y), y),
+                Cursor::OutputBuffer(x, _) => Cursor::CommandLine(model.char_column(x, 0), 0),
             };
-            model.cursor = Cursor::CommandLine(min(model.get_command_len(), x))
+            model.begin_change(repeat::Edit::Insert('i'));
         }
         Message::InsertAfter => {
             model.mode = Mode::Insert;
-            let x = match model.cursor {
-                Cursor::CommandLine(x) => x,
-                Cursor::OutputBuffer(x, _) => x,
+            let (x, y) = match model.cursor {
+                Cursor::CommandLine(x, y) => (x, y),
+                Cursor::OutputBuffer(x, _) => (x, 0),
             };
-            model.cursor = Cursor::CommandLine(min(model.get_command_len(), x + 1))
-        }
-        Message::Backspace => {
-            model.cursor.left();
-            let _ = model.current_command.pop();
+            let x = model.char_column(x, y);
+            model.cursor = Cursor::CommandLine(model.next_column(x, y), y);
+            model.begin_change(repeat::Edit::Insert('a'));
         }
+        Message::Backspace if model.unexpand_abbreviation() => model.refresh_completion(),
+        Message::Backspace => match model.cursor {
+            Cursor::CommandLine(0, 0) => {}
+            Cursor::CommandLine(0, y) => {
+                let x = model.get_row_len(y - 1);
+                let offset = model.command_offset(0, y);
+                model.current_command.remove(offset - 1);
+                model.cursor = Cursor::CommandLine(x, y - 1);
+            }
+            Cursor::CommandLine(x, y) => {
+                let previous = model.previous_column(x, y);
+                let offset = model.command_offset(previous, y);
+                model.current_command.remove(offset);
+                model.cursor = Cursor::CommandLine(previous, y);
+                model.refresh_completion();
+            }
+            Cursor::OutputBuffer(_, _) => {}
+        },
+        Message::Kill(direction) => model.kill(direction, false),
+        Message::KillLine(direction) => model.kill(direction, true),
+        Message::YankKill => model.yank_kill(),
+        Message::YankPop => model.yank_pop(),
         Message::OutCommand => {
-            if let Some(curr) = model.viewing_command {
-                model.viewing_command = Some(curr.saturating_sub(1));
-            } else if !model.previous_commands.is_empty() {
-                model.viewing_command = Some(model.previous_commands.len() - 1);
+            let before = model
+                .viewing_command
+                .unwrap_or(model.previous_commands.len());
+            if let Some(i) = (0..before).rev().find(|i| model.recallable(*i)) {
+                model.viewing_command = Some(i);
             }
+            model.status = model.recalled_context_note();
+            model.clamp_command_cursor();
         }
         Message::InCommand => {
             if let Some(curr) = model.viewing_command {
-                if curr >= model.previous_commands.len() - 1 {
-                    model.viewing_command = None;
-                } else {
-                    model.viewing_command = Some(curr + 1);
-                }
+                let after = curr + 1..model.previous_commands.len();
+                model.viewing_command = after.into_iter().find(|i| model.recallable(*i));
             }
+            model.status = model.recalled_context_note();
+            model.clamp_command_cursor();
         }
+        Message::ScrollDown if model.scrollback.is_some() => model.scroll_scrollback_by(10),
+        Message::ScrollUp if model.scrollback.is_some() => model.scroll_scrollback_by(-10),
         Message::ScrollDown => {
-            if let Some(output) = model.outputs.get_mut(model.viewing_output) {
-                let (vert, horiz) = output.scroll;
-                output.scroll = (
-                    min(
-                        vert.saturating_add(10),
-                        (output.stdout.lines().count() as u16).saturating_sub(model.height),
-                    ),
-                    horiz,
-                );
+            let index = model.viewing_output;
+            if let Some(output) = model.outputs.get(index) {
+                let line = min(output.scroll_goal().saturating_add(10), model.bottom(index));
+                model.scroll_to(index, line);
             }
         }
         Message::ScrollUp => {
-            if let Some(output) = model.outputs.get_mut(model.viewing_output) {
-                let (vert, horiz) = output.scroll;
-                output.scroll = (vert.saturating_sub(10), horiz);
+            let index = model.viewing_output;
+            if let Some(output) = model.outputs.get(index) {
+                model.scroll_to(index, output.scroll_goal().saturating_sub(10));
             }
         }
         Message::InsertBeforeLine => {
             model.mode = Mode::Insert;
-            model.cursor = Cursor::CommandLine(0);
+            model.cursor = Cursor::CommandLine(0, 0);
+            model.begin_change(repeat::Edit::Insert('I'));
         }
         Message::InsertAfterLine => {
             model.mode = Mode::Insert;
-            model.cursor = Cursor::CommandLine(model.get_command_len())
+            model.cursor = model.command_end();
+            model.begin_change(repeat::Edit::Insert('A'));
+        }
+        Message::Preview => {
+            let preview = builtin::parse(model, model.command());
+            model.push_transient(format!(":parse {}", model.command()), preview);
+        }
+        Message::OpenDirectoryPicker => {
+            let dirs = model
+                .recent_dirs()
+                .into_iter()
+                .filter(|d| d.is_dir())
+                .filter_map(|d| d.to_str().map(String::from))
+                .collect();
+            model.picker = Some(Picker::new(PickerKind::Directory, dirs));
+        }
+        Message::OpenSnippetPicker => {
+            let snippets = model
+                .snippets
+                .iter()
+                .map(|(name, template)| format!("{name}  {template}"))
+                .collect();
+            model.picker = Some(Picker::new(PickerKind::Snippet, snippets));
+        }
+        Message::ExpandSnippet => {
+            let name = model.command().trim().to_string();
+            model.start_snippet(&name);
+        }
+        Message::SnippetChar(c) => model
+            .snippet_prompt
+            .iter_mut()
+            .for_each(|p| p.input.push(c)),
+        Message::SnippetBackspace => model.snippet_prompt.iter_mut().for_each(|p| {
+            p.input.pop();
+        }),
+        Message::SnippetSubmit => model.submit_snippet_value(),
+        Message::SnippetCancel => model.snippet_prompt = None,
+        Message::PickerChar(c) => model.picker.iter_mut().for_each(|p| p.push(c)),
+        Message::PickerBackspace => model.picker.iter_mut().for_each(Picker::pop),
+        Message::PickerUp => model.picker.iter_mut().for_each(Picker::up),
+        Message::PickerDown => model.picker.iter_mut().for_each(Picker::down),
+        Message::PickerCancel => model.picker = None,
+        Message::PickerMove(by) => model.edit_queue(Some(by)),
+        Message::PickerMark => match model.picker.as_mut() {
+            Some(picker) if picker.kind == PickerKind::Files => picker.toggle_mark(),
+            Some(picker) if picker.kind == PickerKind::Buffers => model.expand_group(None),
+            _ => {}
+        },
+        Message::PickerExpand(open) => model.expand_group(Some(open)),
+        Message::OpenFilePicker => model.open_file_picker(),
+        Message::OpenBufferPicker => model.open_buffer_picker(),
+        Message::UndoClose => model.undo_close_in_picker(),
+        Message::OpenLink => model.open_link_under_cursor(),
+        Message::OpenLinkPicker => model.open_link_picker(),
+        Message::PickLink(index) => {
+            if let Some(picker) = model.picker.as_mut()
+                && index < picker.items.len()
+            {
+                picker.select_index(index);
+                return Some(Message::PickerAccept);
+            }
+        }
+        Message::Files(walk, paths, done) => model.receive_files(walk, paths, done),
+        Message::HistoryImported(import) => model.finish_import(import),
+        Message::HistoryLoaded(loaded) => model.history_loaded(loaded),
+        Message::Derived(id, text) => model.in_home_of(id, |m| m.finish_derive(id, text)),
+        Message::Previewed(typed, result) => model.show_preview(typed, result),
+        Message::Piped(id, request, command, result) => {
+            if let Some(status) = model.in_home_of(id, |m| m.piped(id, request, command, result)) {
+                model.status = Some(status);
+            }
+        }
+        Message::PickerRemove => match model.picker.as_ref().map(|p| p.kind) {
+            Some(PickerKind::Jobs) => model.terminate_selected_job(),
+            _ => model.edit_queue(None),
+        },
+        Message::Interrupt => model.interrupt(),
+        Message::AbandonQueue(abandon) => model.abandon_queue(abandon),
+        Message::Retry => model.retry_viewed(),
+        Message::Tick => {
+            model.sample_usage(Instant::now());
+            model.sweep_jobs(Instant::now());
+            model.run_due_retries();
+            model.check_command();
+            model.peek_cd();
+            model.run_preview();
+            model.expire_toasts(Instant::now());
+            model.refresh_jobs();
+            model.autosave_if_due(Instant::now());
+        }
+        Message::ScrollFrame => model.step_scroll(),
+        Message::ScrubBack => model.scrub_back(),
+        Message::ScrubForward => model.scrub_forward(),
+        Message::ToggleBookmark => model.toggle_bookmark(),
+        Message::NextBookmark => model.jump_to_bookmark(true, count),
+        Message::PreviousBookmark => model.jump_to_bookmark(false, count),
+        Message::RerunAppending => model.rerun_appending(),
+        Message::OpenHistoryPicker => model.open_history_picker(None),
+        Message::ReplaySkip => model.skip_replayed(),
+        Message::ReplayStop => model.stop_replay(),
+        Message::Mouse(event) => model.mouse(event),
+        Message::Resize(width, height) => {
+            if let Some(recorder) = &model.recorder {
+                recorder.resize(width, height);
+            }
+        }
+        Message::ToggleHistoryHere
+            if model.picker.as_ref().map(|p| p.kind) == Some(PickerKind::Jobs) =>
+        {
+            model.toggle_follow_selected()
+        }
+        Message::ToggleHistoryHere
+            if model.picker.as_ref().map(|p| p.kind) == Some(PickerKind::Buffers) =>
+        {
+            model.toggle_flat_buffers()
+        }
+        Message::ToggleHistoryHere => {
+            model.history_here = !model.history_here;
+            let history = model
+                .picker
+                .as_ref()
+                .filter(|p| p.kind == PickerKind::History);
+            if let Some((query, limit)) = history.map(|p| (p.query.clone(), p.limit)) {
+                model.open_history_picker(limit);
+                if let Some(picker) = model.picker.as_mut() {
+                    picker.set_query(query);
+                }
+            }
+        }
+        Message::AcceptGhost => {
+            if let Some(ghost) = model.ghost().map(String::from) {
+                model.current_command.push_str(&ghost);
+                model.cursor = model.command_end();
+            }
+        }
+        Message::PickerAccept => {
+            if let Some(picker) = model.picker.take() {
+                match (picker.kind, picker.selection()) {
+                    (PickerKind::Directory, Some(dir)) => {
+                        if let Err(e) = model.change_dir(dir.as_ref()) {
+                            model.push_output(format!("cd {dir}"), e);
+                        }
+                    }
+                    (PickerKind::Snippet, Some(entry)) => {
+                        let name = entry.split_whitespace().next().unwrap_or("").to_string();
+                        model.start_snippet(&name);
+                    }
+                    (PickerKind::Queue, _) => model.resume_queue(),
+                    (PickerKind::History, Some(command)) => {
+                        model.current_command = command.to_string();
+                        model.viewing_command = None;
+                        model.mode = Mode::Insert;
+                        model.cursor = model.command_end();
+                    }
+                    (PickerKind::Register, Some(entry)) => {
+                        let entry = entry.to_string();
+                        model.paste_picked(&entry);
+                    }
+                    (PickerKind::Jobs, Some(row)) => {
+                        let row = row.to_string();
+                        model.view_selected_job(&row);
+                    }
+                    (PickerKind::Files, _) => model.insert_paths(&picker.chosen()),
+                    (PickerKind::Bookmarks, Some(row)) => {
+                        let row = row.to_string();
+                        model.jump_to_picked_bookmark(&row);
+                    }
+                    (PickerKind::Buffers, _) => model.view_picked_buffer(picker.selected_index()),
+                    (PickerKind::Arguments, Some(row)) => {
+                        let text = format!("{row} ");
+                        model.insert_text(&text);
+                    }
+                    (PickerKind::Links, Some(row)) => {
+                        let row = row.to_string();
+                        model.open_picked_link(&row);
+                    }
+                    (_, None) => {}
+                }
+            }
+        }
+        Message::PendingKey(c) => model.pending_key = Some(c),
+        Message::PendingObject { operator, around } => {
+            model.pending_object = Some((operator, around))
+        }
+        Message::TextObject {
+            operator,
+            around,
+            argument,
+        } => {
+            model.text_object(operator, around, argument);
+            let edit = repeat::Edit::Object {
+                operator,
+                around,
+                argument,
+            };
+            match operator {
+                'c' => model.begin_change(edit),
+                'd' => model.made_change(edit),
+                _ => {}
+            }
+        }
+        Message::DeleteLine => {
+            model.delete_line();
+            model.made_change(repeat::Edit::DeleteLine);
+        }
+        Message::RepeatChange => model.repeat_change(count),
+        Message::RunLast => model.run_last(),
+        Message::Count(digit) => {
+            model.count = Some(model.count.unwrap_or(0).saturating_mul(10) + digit as usize)
+        }
+        Message::CancelPending => {}
+        Message::ToggleCase => {
+            model.toggle_case();
+            model.made_change(repeat::Edit::ToggleCase);
+        }
+        Message::ChangeWordCase { upper } => {
+            model.change_word_case(upper);
+            model.made_change(repeat::Edit::WordCase { upper });
+        }
+        Message::Increment(by) => {
+            model.increment(by);
+            model.made_change(repeat::Edit::Increment(by));
+        }
+        Message::ToggleViewLock => {
+            model.view_locked = !model.view_locked;
+            model.status = Some(match model.view_locked {
+                true => "view locked: new jobs run without taking over the view".into(),
+                false => "view follows new jobs".into(),
+            });
+        }
+        Message::Complete => model.open_completion(),
+        Message::CompletionNext => {
+            if let Some(completion) = model.completion.as_mut() {
+                completion.next();
+            }
+        }
+        Message::CompletionPrevious => {
+            if let Some(completion) = model.completion.as_mut() {
+                completion.previous();
+            }
+        }
+        Message::CompletionAccept => model.accept_completion(),
+        Message::CompletionCancel => model.completion = None,
+        Message::Completions(key, values) => model.receive_completions(key, values),
+        Message::Segment(name, dir, text) => model.segment_done(name, dir, text),
+        Message::SelectRegister(c) => model.register = Some(c),
+        Message::Yank => model.yank(),
+        Message::Visual => model.toggle_visual(),
+        Message::EndVisual => model.visual = None,
+        Message::TakeCommand => return model.take_command(),
+        Message::ExpandLine => model.expand_line(),
+        Message::InsertFromOutput => model.insert_from_output(counted),
+        Message::InsertLastArgument => model.insert_last_argument(counted),
+        Message::OpenArgumentPicker => model.open_argument_picker(),
+        Message::ScrollLinePopup(by) => model.scroll_line_popup(by),
+        Message::CloseLinePopup => model.line_popup = None,
+        Message::YankSelection => model.yank_selection(),
+        Message::YankColumn => model.yank_column(),
+        Message::WordForward => model.column_motion(true),
+        Message::WordBack => model.column_motion(false),
+        Message::Paste => model.paste(),
+        Message::SetMark(c) => model.set_mark(c),
+        Message::JumpToMark(c) => model.jump_to_mark(c),
+        Message::JumpBack => model.jump_back(),
+        Message::Top if model.scrollback.is_some() => model.scroll_scrollback(0),
+        Message::Bottom if model.scrollback.is_some() => model.scroll_scrollback(usize::MAX),
+        Message::Top => model.jump_to_line(0),
+        Message::Bottom => {
+            model.jump_to_line(model.bottom(model.viewing_output));
+        }
+        Message::Suspend => {
+            let note = model.suspend();
+            model.status = Some(note).filter(|n| !n.is_empty());
+        }
+        Message::Continued => model.continued(),
+        Message::ToggleSplit => model.toggle_split(),
+        Message::SwitchHalf => model.switch_half(),
+        Message::OutputChunk(job_id, data) => {
+            model.in_home_of(job_id, |m| m.append_output(job_id, &data))
+        }
+        Message::ErrorChunk(job_id, data) => {
+            model.in_home_of(job_id, |m| m.append_error(job_id, &data))
+        }
+        Message::ReadFailed(job_id, error) => {
+            model.in_home_of(job_id, |m| m.read_failed(job_id, error))
+        }
+        Message::JobExited(job_id, status, stages) => {
+            model.reaper.ended(job_id);
+            model.in_home_of(job_id, |m| {
+                let status = m.finish_stages(job_id, stages, status);
+                m.finish_job(job_id, status);
+            });
+            model.refresh_jobs();
+        }
+        Message::AcceptSuggestion => {
+            if let Some(suggestion) = model
+                .outputs
+                .get(model.viewing_output)
+                .and_then(|o| o.suggestion.clone())
+            {
+                model.current_command = suggestion;
+                model.viewing_command = None;
+                model.mode = Mode::Insert;
+                model.cursor = model.command_end();
+            }
         }
     };
     None
 }
 
 mod tui {
+    use crate::{event, image, passthrough};
     use ratatui::{
         Terminal,
         backend::{Backend, CrosstermBackend},
         crossterm::{
             ExecutableCommand,
+            cursor::SetCursorStyle,
+            event::{DisableMouseCapture, EnableMouseCapture},
             terminal::{
                 EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode,
             },
         },
     };
-    use std::{io::stdout, panic};
+    use std::{
+        env,
+        io::{IsTerminal, stdin, stdout},
+        panic,
+        process::Command,
+        sync::atomic::{AtomicBool, Ordering},
+    };
 
-    pub fn init_terminal() -> color_eyre::Result<Terminal<impl Backend>> {
-        enable_raw_mode()?;
-        stdout().execute(EnterAlternateScreen)?;
-        let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
-        Ok(terminal)
+    /// Whether shim has the mouse, to give it back on the way out.
+    static MOUSE: AtomicBool = AtomicBool::new(false);
+    /// Whether shim changed the cursor's shape, to put it back.
+    static SHAPED: AtomicBool = AtomicBool::new(false);
+
+    /// What the terminal can do, as far as shim can tell. What it can't is
+    /// left out rather than sent anyway.
+    #[derive(Debug, Clone, Copy)]
+    pub struct Capabilities {
+        /// Drawing on a screen of its own, so the shell's is back as it
+        /// was once shim exits.
+        pub alternate_screen: bool,
+        /// OSC 52, for copying to the system clipboard.
+        pub clipboard: bool,
+        /// Image protocols it draws itself.
+        pub images: image::Support,
+    }
+
+    impl Default for Capabilities {
+        fn default() -> Self {
+            Capabilities {
+                alternate_screen: true,
+                clipboard: true,
+                images: image::Support::default(),
+            }
+        }
+    }
+
+    impl Capabilities {
+        /// A note of what's been turned off, for the status line.
+        pub fn missing(&self) -> Option<String> {
+            let missing: Vec<_> = [
+                (!self.alternate_screen).then_some("alternate screen"),
+                (!self.clipboard).then_some("clipboard"),
+            ]
+            .into_iter()
+            .flatten()
+            .collect();
+            (!missing.is_empty()).then(|| format!("terminal has no {}", missing.join(" or ")))
+        }
+    }
+
+    /// Set the terminal up for drawing, or say why it can't be. A dumb
+    /// terminal, or one refusing the alternate screen, is drawn on as it
+    /// is.
+    pub fn init_terminal() -> Result<(Terminal<impl Backend>, Capabilities), String> {
+        if !stdin().is_terminal() || !stdout().is_terminal() {
+            return Err(
+                "needs a terminal: run it from one, without redirecting its input or output".into(),
+            );
+        }
+        let term = env::var("TERM").unwrap_or_default();
+        let dumb = term == "dumb";
+        enable_raw_mode().map_err(|e| format!("can't set up the terminal ({term}): {e}"))?;
+        let alternate_screen = !dumb && stdout().execute(EnterAlternateScreen).is_ok();
+        let capabilities = Capabilities {
+            alternate_screen,
+            // the Linux console ignores OSC 52
+            clipboard: !dumb && term != "linux",
+            // the Linux console would print the kitty query
+            images: match dumb || term == "linux" {
+                true => image::Support::default(),
+                false => image::query(),
+            },
+        };
+        let mut terminal = Terminal::new(CrosstermBackend::new(stdout())).map_err(|e| {
+            let _ = restore_terminal(capabilities);
+            format!("can't set up the terminal ({term}): {e}")
+        })?;
+        if !alternate_screen {
+            let _ = terminal.clear();
+        }
+        Ok((terminal, capabilities))
     }
 
-    pub fn restore_terminal() -> color_eyre::Result<()> {
-        stdout().execute(LeaveAlternateScreen)?;
-        disable_raw_mode()?;
+    /// Take the mouse from the terminal, or give it back.
+    pub fn capture_mouse(on: bool) -> std::io::Result<()> {
+        match on {
+            true => stdout().execute(EnableMouseCapture)?,
+            false => stdout().execute(DisableMouseCapture)?,
+        };
+        MOUSE.store(on, Ordering::Relaxed);
         Ok(())
     }
 
+    /// Show the cursor as an underline, or as the user has it.
+    pub fn underline_cursor(on: bool) -> std::io::Result<()> {
+        match on {
+            true => stdout().execute(SetCursorStyle::SteadyUnderScore)?,
+            false => stdout().execute(SetCursorStyle::DefaultUserShape)?,
+        };
+        SHAPED.store(on, Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Give the terminal back and stop, until continued.
+    #[cfg(unix)]
+    pub fn suspend(capabilities: Capabilities) -> std::io::Result<()> {
+        restore_terminal(capabilities)?;
+        // SIGTSTP is caught, so this stops as it would have
+        signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)
+    }
+
+    #[cfg(not(unix))]
+    pub fn suspend(_capabilities: Capabilities) -> std::io::Result<()> {
+        Ok(())
+    }
+
+    /// Give the terminal to `process` until it ends, and its exit code.
+    /// Keys typed meanwhile are its own.
+    pub fn hand_over(capabilities: Capabilities, process: Command) -> std::io::Result<i32> {
+        event::pause_input(true);
+        let ended = restore_terminal(capabilities).and_then(|()| passthrough::run(process));
+        event::pause_input(false);
+        ended
+    }
+
+    /// Take the terminal again after being stopped.
+    pub fn resume(capabilities: Capabilities) -> std::io::Result<()> {
+        enable_raw_mode()?;
+        if capabilities.alternate_screen {
+            stdout().execute(EnterAlternateScreen)?;
+        }
+        Ok(())
+    }
+
+    pub fn restore_terminal(capabilities: Capabilities) -> std::io::Result<()> {
+        if MOUSE.load(Ordering::Relaxed) {
+            capture_mouse(false)?;
+        }
+        if SHAPED.load(Ordering::Relaxed) {
+            underline_cursor(false)?;
+        }
+        if capabilities.alternate_screen {
+            stdout().execute(LeaveAlternateScreen)?;
+        }
+        disable_raw_mode()
+    }
+
     pub fn install_panic_hook() {
         let original_hook = panic::take_hook();
         panic::set_hook(Box::new(move |panic_info| {
-            stdout().execute(LeaveAlternateScreen).unwrap();
-            disable_raw_mode().unwrap();
+            if passthrough::handed_over() {
+                crate::trace::write_on_panic();
+                return original_hook(panic_info);
+            }
+            // the terminal may be what failed, so these can too
+            if MOUSE.load(Ordering::Relaxed) {
+                let _ = stdout().execute(DisableMouseCapture);
+            }
+            if SHAPED.load(Ordering::Relaxed) {
+                let _ = stdout().execute(SetCursorStyle::DefaultUserShape);
+            }
+            let _ = stdout().execute(LeaveAlternateScreen);
+            let _ = disable_raw_mode();
+            crate::trace::write_on_panic();
             original_hook(panic_info);
         }));
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_history(commands: &[&str]) -> Model {
+        Model {
+            previous_commands: commands
+                .iter()
+                .map(|c| history::Entry {
+                    command: c.to_string(),
+                    cwd: None,
+                    context: None,
+                    time: None,
+                })
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn send(model: &mut Model, messages: impl IntoIterator<Item = Message>) {
+        for msg in messages {
+            let mut next = Some(msg);
+            while let Some(msg) = next {
+                next = update(model, msg);
+            }
+        }
+    }
+
+    fn type_text(model: &mut Model, text: &str) {
+        send(model, text.chars().map(Message::WriteCommandChar));
+    }
+
+    #[test]
+    fn accepting_a_suggestion_for_a_recalled_command() {
+        let mut model = with_history(&["git st"]);
+        model.frecency.used("git status");
+        send(&mut model, [Message::OutCommand]);
+        model.cursor = model.command_end();
+        assert_eq!(model.ghost(), Some("atus"));
+        send(&mut model, [Message::AcceptGhost]);
+        assert_eq!(model.current_command, "git status");
+        assert_eq!(model.viewing_command, None);
+        assert_eq!(model.previous_commands[0].command, "git st");
+    }
+
+    #[test]
+    fn typing_after_moving_along_a_longer_recalled_command() {
+        let mut model = with_history(&["git status --short"]);
+        type_text(&mut model, "ls");
+        send(&mut model, [Message::Normal, Message::OutCommand]);
+        send(&mut model, (0..5).map(|_| Message::Right));
+        send(&mut model, [Message::InsertBefore]);
+        type_text(&mut model, "X");
+        assert_eq!(model.current_command, "git stXatus --short");
+        assert_eq!(model.viewing_command, None);
+    }
+
+    #[test]
+    fn typing_into_a_shorter_recalled_command() {
+        let mut model = with_history(&["ls"]);
+        type_text(&mut model, "a much longer command");
+        send(&mut model, [Message::OutCommand]);
+        type_text(&mut model, "X");
+        assert_eq!(model.current_command, "lsX");
+        assert_eq!(model.cursor, Cursor::CommandLine(3, 0));
+    }
+
+    #[test]
+    fn editing_around_multibyte_characters_in_a_recalled_command() {
+        let mut model = with_history(&["echo caf\u{e9}"]);
+        send(
+            &mut model,
+            [Message::Normal, Message::OutCommand, Message::LineEnd],
+        );
+        send(&mut model, [Message::InsertAfter]);
+        type_text(&mut model, "!");
+        assert_eq!(model.current_command, "echo caf\u{e9}!");
+        send(
+            &mut model,
+            [Message::Normal, Message::Left, Message::InsertBefore],
+        );
+        type_text(&mut model, "?");
+        assert_eq!(model.current_command, "echo caf?\u{e9}!");
+    }
+
+    #[test]
+    fn deleting_from_a_recalled_command() {
+        let mut model = with_history(&["ls -la"]);
+        send(
+            &mut model,
+            [
+                Message::Normal,
+                Message::OutCommand,
+                Message::InsertAfterLine,
+                Message::Backspace,
+            ],
+        );
+        assert_eq!(model.current_command, "ls -l");
+        assert_eq!(model.previous_commands[0].command, "ls -la");
+    }
+
+    #[test]
+    fn a_cursor_left_past_the_end_is_pulled_back() {
+        let mut model = with_history(&[]);
+        type_text(&mut model, "l\u{e9}");
+        for x in [2, 9] {
+            model.cursor = Cursor::CommandLine(x, 0);
+            type_text(&mut model, "s");
+        }
+        assert_eq!(model.current_command, "ls\u{e9}s");
+    }
+
+    fn with_outputs(lengths: &[usize]) -> Model {
+        let mut model = Model {
+            height: 10,
+            ..Default::default()
+        };
+        for (i, &lines) in lengths.iter().enumerate() {
+            let text: String = (0..lines).map(|n| format!("line {n}\n")).collect();
+            model.push_output(format!("cmd {i}"), text);
+        }
+        model
+    }
+
+    #[test]
+    fn switching_buffers_brings_the_cursor_back() {
+        let mut model = with_outputs(&[100, 3]);
+        model.viewing_output = 0;
+        model.outputs[0].set_scroll(0);
+        model.cursor = Cursor::OutputBuffer(4, 8);
+        send(&mut model, [Message::NextOutput]);
+        // the short buffer has nothing below its third line
+        assert!(matches!(model.cursor, Cursor::OutputBuffer(_, y) if y < 3));
+        send(&mut model, [Message::Down, Message::PreviousOutput]);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(4, 8));
+    }
+
+    #[test]
+    fn a_saved_cursor_is_kept_within_a_buffer_that_shrank() {
+        let mut model = with_outputs(&[100, 100]);
+        model.outputs[0].set_scroll(0);
+        model.cursor = Cursor::OutputBuffer(5, 9);
+        send(&mut model, [Message::PreviousOutput]);
+        model.cursor = Cursor::OutputBuffer(6, 9);
+        send(&mut model, [Message::NextOutput]);
+        model.outputs[0].stdout = "short\n".into();
+        send(&mut model, [Message::PreviousOutput]);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(4, 0));
+        send(&mut model, [Message::Right]);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(4, 0));
+    }
+
+    #[test]
+    fn switching_keeps_which_pane_had_the_cursor() {
+        let mut model = with_outputs(&[5, 5]);
+        type_text(&mut model, "ls");
+        send(&mut model, [Message::PreviousOutput]);
+        model.cursor = Cursor::OutputBuffer(0, 2);
+        send(&mut model, [Message::NextOutput]);
+        // left from the command line, so back to it
+        assert_eq!(model.cursor, Cursor::CommandLine(2, 0));
+        send(&mut model, [Message::PreviousOutput]);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(0, 2));
+    }
+
+    #[test]
+    fn words_killed_one_after_another_are_yanked_together() {
+        use killring::Direction::Back;
+        let mut model = Model::default();
+        type_text(&mut model, "echo one two");
+        send(&mut model, [Message::Kill(Back), Message::Kill(Back)]);
+        assert_eq!(model.current_command, "echo ");
+        send(&mut model, [Message::LineEnd, Message::YankKill]);
+        assert_eq!(model.current_command, "echo one two");
+        assert_eq!(model.cursor, Cursor::CommandLine(12, 0));
+        // moving in between starts another kill
+        send(&mut model, [Message::Kill(Back), Message::Left]);
+        send(&mut model, [Message::KillLine(Back), Message::YankKill]);
+        assert_eq!(model.current_command, "echo one ");
+        send(&mut model, [Message::YankPop]);
+        assert_eq!(model.current_command, "two ");
+        send(&mut model, [Message::YankPop]);
+        assert_eq!(model.current_command, "one two ");
+        send(&mut model, [Message::YankPop]);
+        assert_eq!(model.current_command, "echo one ");
+    }
+
+    #[test]
+    fn the_kill_ring_survives_a_change_of_mode() {
+        use killring::Direction::Forward;
+        let mut model = Model::default();
+        type_text(&mut model, "ls -la");
+        send(&mut model, [Message::Normal, Message::LineStart]);
+        send(&mut model, [Message::InsertBefore]);
+        send(&mut model, [Message::KillLine(Forward), Message::Normal]);
+        send(&mut model, [Message::InsertBefore, Message::YankKill]);
+        assert_eq!(model.current_command, "ls -la");
+    }
+
+    #[test]
+    fn an_abbreviation_expands_on_space_and_backspace_takes_it_back() {
+        let mut model = Model::default();
+        model
+            .abbreviations
+            .insert("gco".into(), "git checkout".into());
+        type_text(&mut model, "gco main");
+        assert_eq!(model.current_command, "git checkout main");
+        send(&mut model, [Message::Kill(killring::Direction::Back)]);
+        send(&mut model, [Message::KillLine(killring::Direction::Back)]);
+        type_text(&mut model, "gco ");
+        send(&mut model, [Message::Backspace]);
+        assert_eq!(model.current_command, "gco ");
+        assert_eq!(model.cursor, Cursor::CommandLine(4, 0));
+        // only straight after
+        send(&mut model, [Message::Backspace, Message::Backspace]);
+        assert_eq!(model.current_command, "gc");
+        // not past the first word
+        type_text(&mut model, " gco ");
+        assert_eq!(model.current_command, "gc gco ");
+    }
+
+    #[test]
+    fn moving_and_yanking_by_column_in_a_table() {
+        let mut model = with_outputs(&[]);
+        let ps = "  PID TTY      CMD\n    1 ?        init\n  512 pts/0    bash\n";
+        model.push_output("ps".into(), ps.into());
+        model.detect_table(0);
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::OutputBuffer(0, 2);
+        send(&mut model, [Message::WordForward, Message::WordForward]);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(15, 2));
+        send(&mut model, [Message::WordBack]);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(6, 2));
+        let y = handle_key(&model, KeyEvent::from(KeyCode::Char('y')));
+        assert!(y == Some(Message::PendingKey('y')));
+        send(&mut model, [Message::PendingKey('y'), Message::YankColumn]);
+        assert_eq!(model.registers.get(None), Some("?\npts/0"));
+        assert_eq!(model.pending_key, None);
+        // outside a table `y` still takes the suggestion
+        model.outputs[0].table = None;
+        let y = handle_key(&model, KeyEvent::from(KeyCode::Char('y')));
+        assert!(y == Some(Message::AcceptSuggestion));
+    }
+
+    #[test]
+    fn a_failure_shows_in_the_prompt_until_the_next_command() {
+        let mut model = with_outputs(&[1]);
+        model.outputs[0].running = true;
+        model.finish_job(0, Some(101));
+        assert_eq!(model.prompt().content, "✗ 101 ❯");
+        assert_eq!(model.var("?").as_deref(), Some("101"));
+        let parsed = model.parse("echo $?").unwrap();
+        assert_eq!(parsed.args, ["101"]);
+        type_text(&mut model, ":pin");
+        send(&mut model, [Message::Submit]);
+        assert_eq!(model.prompt().content, "❯");
+        assert_eq!(model.var("?").as_deref(), Some("0"));
+    }
+
+    /// Draw `model` on a screen `width` by `height`, returning the text.
+    fn drawn(model: &mut Model, width: u16, height: u16) -> String {
+        let backend = ratatui::backend::TestBackend::new(width, height);
+        let mut terminal = ratatui::Terminal::new(backend).unwrap();
+        terminal.draw(|frame| view(model, frame)).unwrap();
+        let buffer = terminal.backend().buffer();
+        buffer.content.iter().map(|cell| cell.symbol()).collect()
+    }
+
+    #[test]
+    fn a_long_buffer_scrolls_past_line_65535() {
+        let mut model = with_outputs(&[70_000]);
+        assert_eq!(model.outputs[0].scroll.0, 69_990);
+        send(&mut model, [Message::Top, Message::Bottom]);
+        assert_eq!(model.outputs[0].scroll.0, 69_990);
+        // a screen 16 high leaves 10 lines for the output
+        let screen = drawn(&mut model, 40, 16);
+        assert!(screen.contains("line 69990"));
+        assert!(screen.contains("line 69999"));
+    }
+
+    #[test]
+    #[ignore = "a benchmark; run with --release"]
+    fn drawing_a_million_lines_is_as_quick_as_a_hundred() {
+        let time = |lines: usize| {
+            let mut model = with_outputs(&[lines]);
+            drawn(&mut model, 120, 40);
+            let start = Instant::now();
+            for _ in 0..100 {
+                drawn(&mut model, 120, 40);
+            }
+            start.elapsed() / 100
+        };
+        let (few, many) = (time(100), time(1_000_000));
+        println!("{few:?} a frame for 100 lines, {many:?} for 1M");
+        assert!(many < few * 3, "{few:?} against {many:?}");
+    }
+
+    /// A job started in a view 10 lines high that writes `data` and ends.
+    fn finished_job(data: &[u8]) -> Model {
+        let mut model = with_outputs(&[]);
+        model.push_output("seq 12".into(), String::new());
+        model.outputs[0].running = true;
+        model.outputs[0].started = Some(Instant::now());
+        model.append_output(0, data);
+        model.finish_job(0, Some(0));
+        model
+    }
+
+    #[test]
+    fn the_view_stays_on_its_text_as_lines_come_and_go() {
+        let mut model = with_outputs(&[]);
+        model.push_output("seq".into(), String::new());
+        model.outputs[0].running = true;
+        let lines = |from: usize, to: usize| -> Vec<u8> {
+            let text: String = (from..to).map(|n| format!("line {n}\n")).collect();
+            text.into_bytes()
+        };
+        model.append_output(0, &lines(0, 300));
+        model.outputs[0].set_scroll(200);
+        model.cursor = Cursor::OutputBuffer(2, 3);
+        model.set_mark('a');
+        model.outputs[0].bookmarks.insert(210);
+        model.append_output(0, &lines(300, 400));
+        assert_eq!(model.outputs[0].scroll.0, 200);
+        assert_eq!(model.output_line(3), Some("line 203"));
+
+        // lines dropped from the top take the view up with its text
+        model.config.max_lines = 250;
+        model.append_output(0, &lines(400, 420));
+        let output = &model.outputs[0];
+        assert_eq!((output.dropped, output.scroll.0), (170, 30));
+        assert_eq!(model.output_line(3), Some("line 203"));
+        assert_eq!(model.cursor, Cursor::OutputBuffer(2, 3));
+        assert_eq!(output.bookmarks, BTreeSet::from([40]));
+        assert_eq!(output.warnings.len(), 1);
+
+        // a view on lines that went stops at the new first line
+        model.append_output(0, &lines(420, 500));
+        assert_eq!(model.outputs[0].scroll.0, 0);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(2, 0));
+        assert_eq!(model.output_line(0), Some("line 250"));
+
+        // following, it stays at the end
+        let bottom = model.bottom(0);
+        model.outputs[0].set_scroll(bottom);
+        model.append_output(0, &lines(500, 600));
+        assert_eq!(model.outputs[0].scroll.0, model.bottom(0));
+        assert_eq!(model.output_line(9), Some("line 599"));
+        model.jump_to_mark('a');
+        assert_eq!(model.outputs[0].scroll.0, 0);
+        assert_eq!(model.cursor, Cursor::OutputBuffer(2, 0));
+        assert_eq!(model.output_line(0), Some("line 350"));
+    }
+
+    #[test]
+    fn output_with_a_byte_order_mark_is_transcoded() {
+        let utf16: Vec<u8> = "\u{feff}naïve\r\n"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect();
+        let model = finished_job(&utf16);
+        let output = &model.outputs[0];
+        assert_eq!(output.line(0), Some("naïve"));
+        assert!(output.warnings.is_empty());
+        assert_eq!(title(output, &[]), "seq 12 [UTF-16LE]");
+
+        let mut model = with_outputs(&[]);
+        assert_eq!(
+            builtin::run(&mut model, ":encoding latin1"),
+            "the next command is read as windows-1252"
+        );
+        assert_eq!(
+            builtin::run(&mut model, ":encoding ebcdic"),
+            "unknown encoding: ebcdic"
+        );
+        // it's for the next command only
+        model.start_job("true".into(), None);
+        assert_eq!(model.next_encoding, None);
+    }
+
+    #[test]
+    fn the_trace_keeps_messages_but_not_what_was_typed() {
+        let mut model = with_outputs(&[3]);
+        assert!(model.dump_trace().starts_with("no trace is kept"));
+        model.start_trace();
+        press(&mut model, "hunter2\x1b");
+        send(&mut model, [Message::OutputChunk(0, b"abc".to_vec())]);
+        let dump = model.dump_trace();
+        let lines: Vec<_> = dump.lines().collect();
+        assert!(lines[0].contains("Insert  CommandLine(1, 0)") && lines[0].ends_with("Typed"));
+        assert!(
+            lines[7].contains("Normal  CommandLine(6, 0)"),
+            "{}",
+            lines[7]
+        );
+        assert!(lines[8].ends_with("OutputChunk (3)"));
+        assert!(!dump.contains("hunter2") && !dump.contains("'h'"));
+
+        model.config.trace_keys = true;
+        press(&mut model, "ix");
+        assert!(model.dump_trace().ends_with("WriteCommandChar('x')\n"));
+        // only the latest are kept
+        for _ in 0..trace::LEN {
+            send(&mut model, [Message::Tick]);
+        }
+        assert_eq!(model.dump_trace().lines().count(), trace::LEN);
+    }
+
+    #[test]
+    fn replace_mode_types_over_and_backspace_puts_it_back() {
+        let mut model = with_history(&["date -d 2024-01-15"]);
+        send(&mut model, [Message::OutCommand]);
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::CommandLine(8, 0);
+        press(&mut model, "R");
+        assert_eq!(model.mode, Mode::Replace);
+        press(&mut model, "2025-0");
+        // the past command was copied to be typed over
+        assert_eq!(model.current_command, "date -d 2025-01-15");
+        assert_eq!(model.viewing_command, None);
+        assert_eq!(model.previous_commands[0].command, "date -d 2024-01-15");
+        send(
+            &mut model,
+            [Message::ReplaceBackspace, Message::ReplaceBackspace],
+        );
+        assert_eq!(model.current_command, "date -d 2025-01-15");
+        send(
+            &mut model,
+            [Message::ReplaceBackspace, Message::ReplaceBackspace],
+        );
+        assert_eq!(model.current_command, "date -d 2024-01-15");
+        assert_eq!(model.cursor, Cursor::CommandLine(10, 0));
+        // past the end it's added
+        press(&mut model, "24-02-01Z");
+        assert_eq!(model.current_command, "date -d 2024-02-01Z");
+        send(&mut model, [Message::ReplaceBackspace]);
+        assert_eq!(model.current_command, "date -d 2024-02-01");
+        for _ in 0..20 {
+            send(&mut model, [Message::ReplaceBackspace]);
+        }
+        // before where it started only moves left
+        assert_eq!(model.current_command, "date -d 2024-01-15");
+        assert_eq!(model.cursor, Cursor::CommandLine(0, 0));
+        press(&mut model, "\x1b");
+        assert_eq!(model.mode, Mode::Normal);
+    }
+
+    #[test]
+    fn appended_runs_go_under_a_separator() {
+        let lines: Vec<_> = (1..=12).map(|n| format!("{n}\n")).collect();
+        let mut model = finished_job(lines.concat().as_bytes());
+        model.outputs[0].bookmarks.insert(2);
+        model.outputs[0].set_scroll(0);
+        model.mode = Mode::Normal;
+        // no jobs can be started here, so the run fails to start at once
+        press(&mut model, "R");
+        let output = &model.outputs[0];
+        assert_eq!(output.line(2), Some("3"));
+        assert_eq!(output.bookmarks, BTreeSet::from([2]));
+        let separator = output.line(12).unwrap();
+        assert!(separator.starts_with("--- run 2 at "), "{separator}");
+        assert!(separator.ends_with(": exit 126"), "{separator}");
+        // scrolled down from the top to the new section, as far as it goes
+        assert_eq!(output.scroll.0, 3);
+        assert!(title(output, &[]).starts_with("seq 12 ×2"));
+        model.outputs[0].running = true;
+        assert_eq!(
+            builtin::run(&mut model, ":rerun --append"),
+            "seq 12 is still running"
+        );
+        model.outputs[0].running = false;
+        // run again in place, the old output goes
+        assert_eq!(builtin::run(&mut model, ":rerun"), "");
+        assert_eq!(model.outputs[0].line(0), None);
+        assert!(model.outputs[0].bookmarks.is_empty());
+        assert!(title(&model.outputs[0], &[]).starts_with("seq 12 ×3"));
+    }
+
+    #[test]
+    fn the_last_line_is_in_view_with_or_without_a_newline() {
+        let lines: Vec<_> = (1..=12).map(|n| n.to_string()).collect();
+        let ended = lines.join("\n") + "\n";
+        let unended = lines.join("\n");
+        for data in [ended, unended] {
+            let model = finished_job(data.as_bytes());
+            assert_eq!(model.outputs[0].line_count(), 12);
+            assert_eq!(model.outputs[0].scroll.0, 2);
+            assert_eq!(model.output_line(9), Some("12"));
+            assert_eq!(model.output_line(10), None);
+            assert_eq!(model.outputs[0].empty_note(), None);
+        }
+    }
+
+    #[test]
+    fn a_job_with_no_output_says_so() {
+        let model = finished_job(b"");
+        assert_eq!(model.outputs[0].scroll.0, 0);
+        let note = model.outputs[0].empty_note().unwrap();
+        assert!(note.starts_with("(no output — exit 0 in 0.0"), "{note}");
+        // a builtin with nothing to say is left blank
+        let mut model = with_outputs(&[]);
+        model.push_output(":pin".into(), String::new());
+        assert_eq!(model.outputs[0].empty_note(), None);
+    }
+
+    #[test]
+    fn what_went_wrong_is_kept_apart_from_the_output() {
+        // a bad byte, then a character cut off at the end
+        let mut model = finished_job(b"caf\xe9\nok\n\xe2\x82");
+        let output = &model.outputs[0];
+        assert_eq!(output.stdout, "caf\u{fffd}\nok\n\u{fffd}");
+        assert_eq!(
+            output.warning_footer().as_deref(),
+            Some(" ⚠ output that wasn't UTF-8 is shown as � ")
+        );
+        update(&mut model, Message::ReadFailed(0, "broken pipe".into()));
+        assert_eq!(model.outputs[0].warnings.len(), 2);
+
+        model.push_output("cat big.log".into(), "x\n".repeat(100));
+        model.outputs[1].started = Some(Instant::now());
+        model.viewing_output = 0;
+        model.evict_to(0);
+        let evicted = &model.outputs[1];
+        assert_eq!(evicted.stdout, "");
+        assert_eq!(evicted.empty_note(), None);
+        assert!(evicted.warnings[0].starts_with("contents evicted"));
+    }
+
+    #[test]
+    fn enter_on_a_blank_line_shows_the_end_of_the_output() {
+        let mut model = with_outputs(&[100]);
+        model.outputs[0].set_scroll(0);
+        for blank in ["", "   ", " \t "] {
+            type_text(&mut model, blank);
+            send(&mut model, [Message::Submit]);
+            assert_eq!(model.outputs[0].scroll.0, model.bottom(0));
+            assert_eq!(model.current_command, "");
+            model.outputs[0].set_scroll(0);
+        }
+        assert!(model.previous_commands.is_empty());
+        assert_eq!(model.outputs.len(), 1);
+    }
+
+    #[test]
+    fn trailing_whitespace_is_not_kept_in_history() {
+        let mut model = Model::default();
+        type_text(&mut model, ":pin  ");
+        send(&mut model, [Message::Submit]);
+        assert_eq!(model.previous_commands[0].command, ":pin");
+        assert_eq!(history::Entry::new("echo a\\ ".into()).command, "echo a\\ ");
+    }
+
+    #[test]
+    fn toggling_history_here_keeps_the_top_limit() {
+        let commands: Vec<_> = (0..30).map(|i| format!("echo {i}")).collect();
+        let commands: Vec<_> = commands.iter().map(String::as_str).collect();
+        let mut model = with_history(&commands);
+        let shown = |model: &Model| model.picker.as_ref().unwrap().items.len();
+        builtin::run(&mut model, ":top");
+        assert_eq!(shown(&model), 20);
+        send(&mut model, [Message::ToggleHistoryHere]);
+        assert_eq!(shown(&model), 20);
+        send(&mut model, [Message::ToggleHistoryHere]);
+        assert_eq!(shown(&model), 20);
+        model.picker = None;
+        send(&mut model, [Message::OpenHistoryPicker]);
+        send(&mut model, [Message::ToggleHistoryHere]);
+        assert_eq!(shown(&model), 30);
+    }
+
+    #[test]
+    fn buffers_can_be_titled_and_noted() {
+        let mut model = with_outputs(&[3]);
+        builtin::run(&mut model, ":title the weird timeout");
+        builtin::run(&mut model, ":note  retried twice, then it passed");
+        assert_eq!(title(&model.outputs[0], &[]), "the weird timeout — cmd 0");
+        let listing = builtin::run(&mut model, ":buffers");
+        assert!(listing.contains("the weird timeout — cmd 0\n"));
+        assert!(listing.contains("\n                       retried twice, then it passed\n"));
+        assert_eq!(
+            builtin::run(&mut model, ":title"),
+            "cleared the title of cmd 0"
+        );
+        assert_eq!(title(&model.outputs[0], &[]), "cmd 0");
+        builtin::run(&mut model, ":note");
+        assert_eq!(model.outputs[0].note, None);
+    }
+
+    #[test]
+    fn the_oldest_buffers_are_closed_past_the_limit() {
+        let mut model = with_outputs(&[1; 8]);
+        model.config.max_buffers = 8;
+        model.outputs[1].pinned = true;
+        model.outputs[3].running = true;
+        model.viewing_output = 4;
+        model.push_output("cmd 8".into(), String::new());
+        model.push_output("cmd 9".into(), String::new());
+        model.push_output("cmd 10".into(), String::new());
+        let commands: Vec<_> = model.outputs.iter().map(|o| o.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            [
+                "cmd 1", "cmd 3", "cmd 5", "cmd 6", "cmd 7", "cmd 8", "cmd 9", "cmd 10"
+            ]
+        );
+        assert_eq!(model.status.as_deref(), Some("closed 1 old buffers"));
+
+        // a viewed buffer in the middle keeps being viewed
+        model.viewing_output = 2;
+        assert_eq!(model.close_to_fit(1), 1);
+        assert_eq!(model.outputs[model.viewing_output].command, "cmd 5");
+    }
+
+    #[test]
+    fn failed_buffers_can_be_kept_past_the_limit() {
+        let mut model = with_outputs(&[1; 6]);
+        model.config.max_buffers = 6;
+        model.config.keep_failed_buffers = true;
+        for (i, status) in [1, 0, 2, 0, 0, 0].into_iter().enumerate() {
+            model.outputs[i].started = Some(Instant::now());
+            model.outputs[i].status = Some(status);
+        }
+        model.outputs[4].started = Some(Instant::now());
+        model.outputs[4].status = None;
+        model.push_output("cmd 6".into(), String::new());
+        model.push_output("cmd 7".into(), String::new());
+        model.push_output("cmd 8".into(), String::new());
+        let commands: Vec<_> = model.outputs.iter().map(|o| o.command.as_str()).collect();
+        assert_eq!(
+            commands,
+            ["cmd 0", "cmd 2", "cmd 4", "cmd 6", "cmd 7", "cmd 8"]
+        );
+    }
+
+    #[test]
+    fn autosaved_buffers_are_recovered_after_a_crash() {
+        let dir = env::temp_dir().join(format!("shim-autosave-{}", process::id()));
+        let path = dir.join("autosave-1");
+        let mut model = with_outputs(&[]);
+        model.start_autosave(dir.clone(), 1);
+        model.config.autosave_lines = 2;
+        model.config.autosave_commands = 2;
+        model.push_output("seq 3".into(), "1\n2\n3\n".into());
+        model.outputs[0].status = Some(0);
+        type_text(&mut model, ":tag x");
+        send(&mut model, [Message::Submit]);
+        assert!(!path.exists());
+        model.push_output("ls".into(), "a\n".into());
+        type_text(&mut model, ":tag y");
+        send(&mut model, [Message::Submit]);
+        model.reap_autosave(true);
+        assert!(path.exists());
+
+        // another shim running meanwhile leaves it be
+        let mut other = with_outputs(&[]);
+        other.start_autosave(dir.clone(), 2);
+        assert_eq!(other.status, None);
+        assert!(path.exists());
+
+        // not cleanly quit: the next one offers them back
+        drop(model);
+        let mut next = with_outputs(&[]);
+        next.start_autosave(dir.clone(), 3);
+        assert!(
+            next.status
+                .as_deref()
+                .unwrap()
+                .contains(":recover brings back the 2 buffers")
+        );
+        assert!(!path.exists());
+        assert!(builtin::run(&mut next, ":recover").starts_with("brought back 2 buffers"));
+        assert_eq!(next.outputs[0].command, "seq 3");
+        assert_eq!(next.outputs[0].stdout, "2\n3\n");
+        assert_eq!(next.outputs[0].status, Some(0));
+        assert_eq!(builtin::run(&mut next, ":recover"), "nothing to recover");
+
+        // quitting cleanly leaves nothing to offer
+        for shim in [&mut other, &mut next] {
+            shim.autosave(Instant::now());
+            shim.end_autosave();
+        }
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 0);
+
+        // somewhere it can't write says so once
+        let _ = std::fs::write(dir.join("file"), "");
+        next.start_autosave(dir.join("file"), 3);
+        for _ in 0..2 {
+            next.outputs[0].stdout.push('x');
+            next.autosave(Instant::now());
+            next.reap_autosave(true);
+        }
+        assert_eq!(next.messages().matches("can't autosave to").count(), 1);
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn a_locked_view_stays_on_its_buffer_as_each_makes_room() {
+        let mut model = with_outputs(&[1, 1, 3]);
+        model.config.max_buffers = 3;
+        model.view_locked = true;
+        model.view_output(2);
+        builtin::run(&mut model, ":each true");
+        assert_eq!(model.outputs.len(), 3);
+        assert_eq!(model.outputs[model.viewing_output].command, "cmd 2");
+    }
+
+    #[test]
+    fn closed_buffers_can_be_brought_back() {
+        let mut model = with_outputs(&[1, 1, 30]);
+        model.config.max_buffers = 3;
+        model.config.closed_buffers = 2;
+        model.push_output("cmd 3".into(), String::new());
+        model.push_output("cmd 4".into(), String::new());
+        model.outputs[0].scroll = (12, 0);
+        model.outputs[0].cursor = Some(Cursor::OutputBuffer(2, 3));
+        model.push_output("cmd 5".into(), String::new());
+        // only the last two closed are kept
+        let commands = |model: &Model| -> Vec<String> {
+            model.outputs.iter().map(|o| o.command.clone()).collect()
+        };
+        assert_eq!(model.closed.len(), 2);
+        assert_eq!(
+            builtin::run(&mut model, ":undo-close"),
+            "brought back cmd 2"
+        );
+        assert_eq!(commands(&model), ["cmd 2", "cmd 3", "cmd 4", "cmd 5"]);
+        assert_eq!(model.viewing_output, 0);
+        assert_eq!(model.outputs[0].scroll, (12, 0));
+        assert_eq!(model.cursor, Cursor::OutputBuffer(2, 3));
+
+        // in the picker, Enter brings back the one chosen, Ctrl-u the latest
+        model.config.closed_buffers = 10;
+        model.config.max_buffers = 0;
+        model.outputs.retain(|o| o.command != "cmd 5");
+        model.viewing_output = 0;
+        send(&mut model, [Message::OpenBufferPicker]);
+        let items = &model.picker.as_ref().unwrap().items;
+        let last = items.last().unwrap();
+        assert!(last.starts_with("✕ cmd 1  ") && last.ends_with("  closed"));
+        send(&mut model, [Message::UndoClose]);
+        assert_eq!(model.status.as_deref(), Some("brought back cmd 1"));
+        assert_eq!(commands(&model), ["cmd 1", "cmd 2", "cmd 3", "cmd 4"]);
+        assert_eq!(builtin::run(&mut model, ":undo-close"), "no closed buffers");
+
+        // evicted contents go back into their buffer as they were
+        model.outputs[0].stdout = "kept\n".repeat(20);
+        model.outputs[0].bookmarks.insert(3);
+        model.outputs[0].scroll = (5, 0);
+        model.viewing_output = 3;
+        model.evict_to(0);
+        assert!(model.outputs[0].evicted.is_some());
+        model.outputs.iter_mut().for_each(|o| o.warnings.clear());
+        model.viewing_output = 3;
+        send(&mut model, [Message::OpenBufferPicker]);
+        let items = model.picker.as_ref().unwrap().items.clone();
+        let last = items.iter().rposition(|i| i.starts_with("✕ cmd 1"));
+        model.picker.as_mut().unwrap().select_index(last.unwrap());
+        send(&mut model, [Message::PickerAccept]);
+        assert_eq!(
+            model.status.as_deref(),
+            Some("brought back the contents of cmd 1")
+        );
+        let output = &model.outputs[0];
+        assert_eq!(output.stdout, "kept\n".repeat(20));
+        assert_eq!((output.scroll, output.evicted), ((5, 0), None));
+        assert!(output.bookmarks.contains(&3));
+        assert_eq!(model.viewing_output, 0);
+    }
+
+    #[test]
+    fn picked_files_go_in_at_the_cursor() {
+        let mut model = Model::default();
+        type_text(&mut model, "cp  dest/");
+        model.cursor = Cursor::CommandLine(3, 0);
+        let items = ["src/", "src/main.rs", "my notes.txt"].map(String::from);
+        model.picker = Some(Picker::new(PickerKind::Files, items.to_vec()));
+        send(&mut model, [Message::PickerDown, Message::PickerMark]);
+        send(&mut model, [Message::PickerMark, Message::PickerAccept]);
+        assert_eq!(
+            model.current_command,
+            "cp src/main.rs 'my notes.txt'  dest/"
+        );
+        assert_eq!(model.cursor, Cursor::CommandLine(30, 0));
+        assert!(model.picker.is_none());
+    }
+
+    #[test]
+    fn the_clipboard_is_left_alone_when_the_terminal_has_none() {
+        let mut model = Model {
+            terminal: tui::Capabilities {
+                alternate_screen: false,
+                clipboard: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        assert_eq!(
+            model.terminal.missing().as_deref(),
+            Some("terminal has no alternate screen or clipboard")
+        );
+        model.register = Some('+');
+        model.yank_text("secret".into());
+        assert_eq!(
+            model.messages(),
+            "warn  clipboard: not supported by this terminal"
+        );
+        assert_eq!(model.registers.get(Some('+')), Some("secret"));
+    }
+
+    #[test]
+    fn jobs_ending_out_of_view_leave_a_toast() {
+        let mut model = with_outputs(&[1, 1, 1]);
+        model.viewing_output = 2;
+        for id in [0, 1, 2] {
+            model.outputs[id].running = true;
+        }
+        model.finish_job(0, Some(0));
+        model.finish_job(1, Some(2));
+        model.finish_job(2, Some(1));
+        assert_eq!(
+            builtin::run(&mut model, ":messages"),
+            "info  done: cmd 0\nerror exit 2: cmd 1"
+        );
+        model.expire_toasts(Instant::now() + toast::TTL);
+        assert_eq!(model.toast_due(), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn ctrl_z_leaves_stopping_to_the_main_loop() {
+        let mut model = Model::default();
+        let ctrl_z = KeyEvent::new(KeyCode::Char('z'), KeyModifiers::CONTROL);
+        assert_eq!(handle_key(&model, ctrl_z), Some(Message::Suspend));
+        send(&mut model, [Message::Suspend]);
+        assert_eq!(model.suspending, suspend::Pending::Stop);
+        // the SIGCONT after its own stop changes nothing
+        send(&mut model, [Message::Continued]);
+        assert_eq!(model.suspending, suspend::Pending::Stop);
+        model.suspending = suspend::Pending::Nothing;
+        send(&mut model, [Message::Continued]);
+        assert_eq!(model.suspending, suspend::Pending::Redraw);
+        model.suspending = suspend::Pending::Nothing;
+        assert_eq!(builtin::run(&mut model, ":suspend"), "");
+        assert_eq!(model.suspending, suspend::Pending::Stop);
+    }
+
+    #[test]
+    fn a_hook_that_cant_run_warns_once() {
+        let mut model = with_outputs(&[1, 1, 1]);
+        model
+            .config
+            .set("on_command_failure", "\"no-such-hook-for-shim '{cmd}'\"")
+            .unwrap();
+        model.config.set("hook_min_duration", "1m").unwrap();
+        for id in [0, 1, 2] {
+            model.outputs[id].running = true;
+            model.outputs[id].started = Some(Instant::now() - Duration::from_secs(90));
+        }
+        model.finish_job(2, Some(1));
+        // the second failure isn't warned about again
+        model.finish_job(1, Some(1));
+        // nor is one run after a quick command
+        model.outputs[0].started = Some(Instant::now());
+        model.finish_job(0, Some(1));
+        let messages = builtin::run(&mut model, ":messages");
+        let warnings: Vec<_> = messages.lines().filter(|l| l.starts_with("warn")).collect();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].starts_with("warn  on_command_failure couldn't be run: "));
+    }
+
+    #[test]
+    fn workspaces_keep_their_own_buffers_while_jobs_write_to_theirs() {
+        let mut model = with_outputs(&[3, 2]);
+        model.mode = Mode::Normal;
+        model.outputs[1].running = true;
+        assert_eq!(builtin::run(&mut model, ":tabnew"), "workspace 2");
+        assert!(model.outputs.is_empty());
+        assert_eq!(model.tabs().len(), 2);
+        model.push_output("ls".into(), "a\nb\n".into());
+        // the job started in the first goes on writing there
+        send(
+            &mut model,
+            [
+                Message::OutputChunk(1, b"more\n".to_vec()),
+                Message::JobExited(1, Some(0), Vec::new()),
+            ],
+        );
+        assert_eq!(model.outputs.len(), 1);
+        assert_eq!(model.messages(), "info  done: cmd 1");
+        press(&mut model, "gt");
+        assert_eq!(model.outputs.len(), 2);
+        assert_eq!(model.viewing_output, 1);
+        assert_eq!(model.outputs[1].stdout, "line 0\nline 1\nmore\n");
+        assert!(!model.outputs[1].running);
+        // buffers go round within the workspace
+        press(&mut model, "gT");
+        assert_eq!(model.outputs[0].command, "ls");
+        send(&mut model, [Message::NextOutput]);
+        assert_eq!(model.viewing_output, 0);
+        press(&mut model, "2gt");
+        assert_eq!(model.outputs[0].command, "ls");
+        press(&mut model, "1gt");
+        assert_eq!(model.outputs[0].command, "cmd 0");
+    }
+
+    #[test]
+    fn quitting_asks_while_jobs_run_in_any_workspace() {
+        let mut model = with_outputs(&[1]);
+        model.outputs[0].running = true;
+        model.new_workspace(None);
+        send(&mut model, [Message::Quit]);
+        assert!(model.quitting);
+        assert!(
+            handle_key(&model, KeyEvent::from(KeyCode::Char('n')))
+                == Some(Message::ConfirmQuit(false))
+        );
+        send(&mut model, [Message::ConfirmQuit(false)]);
+        assert_eq!(model.running_state, RunningState::Running);
+        send(&mut model, [Message::Quit, Message::ConfirmQuit(true)]);
+        assert_eq!(model.running_state, RunningState::Done);
+        let mut idle = with_outputs(&[1]);
+        send(&mut idle, [Message::Quit]);
+        assert_eq!(idle.running_state, RunningState::Done);
+    }
+
+    #[test]
+    fn runs_of_a_command_are_grouped_in_the_buffer_picker() {
+        let mut model = Model::default();
+        for (command, status) in [
+            ("cargo test", 101),
+            ("ls", 0),
+            ("cargo  test", 0),
+            ("cargo test --lib", 0),
+        ] {
+            model.push_output(command.into(), String::new());
+            model.outputs.last_mut().unwrap().status = Some(status);
+        }
+        let rows = |model: &Model| model.picker.as_ref().unwrap().items.clone();
+        send(&mut model, [Message::OpenBufferPicker]);
+        assert_eq!(
+            rows(&model),
+            [
+                "  cargo test --lib  ×1  done",
+                "▸ cargo test  ×2  done",
+                "  ls  ×1  done"
+            ]
+        );
+        // the group's row views its latest run
+        send(&mut model, [Message::PickerDown, Message::PickerAccept]);
+        assert_eq!(model.viewing_output, 2);
+
+        send(&mut model, [Message::OpenBufferPicker, Message::PickerDown]);
+        send(&mut model, [Message::PickerMark]);
+        assert_eq!(rows(&model).len(), 5);
+        assert!(rows(&model)[3].contains("exit 101  cargo test"));
+        send(&mut model, [Message::PickerDown, Message::PickerDown]);
+        // typing keeps the group together
+        for c in "101".chars() {
+            send(&mut model, [Message::PickerChar(c)]);
+        }
+        let picker = model.picker.as_ref().unwrap();
+        assert!(
+            picker
+                .selection()
+                .unwrap()
+                .ends_with("exit 101  cargo test")
+        );
+        send(&mut model, [Message::PickerAccept]);
+        assert_eq!(model.viewing_output, 0);
+
+        model.config.group_words = 2;
+        send(
+            &mut model,
+            [Message::OpenBufferPicker, Message::ToggleHistoryHere],
+        );
+        assert_eq!(rows(&model).len(), 4);
+        send(&mut model, [Message::ToggleHistoryHere]);
+        assert_eq!(rows(&model)[0], "▾ cargo test  ×3  done");
+        send(&mut model, [Message::PickerExpand(false)]);
+        assert_eq!(rows(&model).len(), 2);
+
+        // titles and notes are shown, and matched, with the runs they're on
+        model.picker = None;
+        model.config.group_words = 0;
+        model.outputs[0].title = Some("before the fix".into());
+        model.outputs[0].note = Some("flaky".into());
+        send(&mut model, [Message::OpenBufferPicker]);
+        assert_eq!(
+            rows(&model)[1],
+            "▸ cargo test  ×2  done  before the fix  flaky"
+        );
+        for c in "flaky".chars() {
+            send(&mut model, [Message::PickerChar(c)]);
+        }
+        let picker = model.picker.as_ref().unwrap();
+        assert!(picker.selection().unwrap().starts_with("▸ cargo test"));
+        send(&mut model, [Message::PickerExpand(true)]);
+        assert!(rows(&model)[3].ends_with("cargo test  before the fix  flaky"));
+    }
+
+    #[test]
+    fn stderr_splits_off_into_a_half_of_its_own() {
+        let mut model = with_outputs(&[0, 1]);
+        model.pane = Rect::new(0, 1, 20, 12);
+        model.mode = Mode::Normal;
+        press(&mut model, "S");
+        assert_eq!(
+            model.status.as_deref(),
+            Some("nothing came on stderr to split off")
+        );
+        send(&mut model, [Message::PreviousOutput]);
+        model.outputs[0].running = true;
+        press(&mut model, "S");
+        assert!(model.splitting());
+        for n in 0..10 {
+            send(
+                &mut model,
+                [
+                    Message::OutputChunk(0, format!("step {n}\n").into_bytes()),
+                    Message::ErrorChunk(0, format!("warning {n}\n").into_bytes()),
+                ],
+            );
+        }
+        assert_eq!(model.split_note().as_deref(), Some("scrolling stdout"));
+        let scrolls = |model: &Model| {
+            let streams = model.outputs[0].streams.as_ref().unwrap();
+            (streams.stdout.scroll, streams.stderr.scroll)
+        };
+        // each half is 4 lines high and follows its own end
+        assert_eq!(scrolls(&model), (6, 6));
+        model.cursor = Cursor::OutputBuffer(0, 0);
+        press(&mut model, "\tkk");
+        assert_eq!(model.split_note().as_deref(), Some("scrolling stderr"));
+        assert_eq!(scrolls(&model), (6, 4));
+        send(&mut model, [Message::ScrollUp]);
+        assert_eq!(scrolls(&model), (6, 2));
+        // more stderr leaves the scrolled half be
+        send(
+            &mut model,
+            [Message::ErrorChunk(0, b"warning 10\n".to_vec())],
+        );
+        assert_eq!(scrolls(&model), (6, 2));
+        assert!(
+            model.outputs[0]
+                .stdout
+                .starts_with("step 0\nwarning 0\nstep 1\n")
+        );
+        press(&mut model, "S");
+        assert!(!model.splitting());
+        assert_eq!(model.split_note(), None);
+    }
+
+    #[test]
+    fn segments_show_what_they_last_gave() {
+        let mut model = Model::default();
+        let here = env::current_dir().unwrap();
+        let git = |model: &Model| {
+            let spans = model.segment_spans(segment::Place::Prompt);
+            spans
+                .iter()
+                .map(|s| (s.content.to_string(), s.style))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(git(&model), []);
+        let done = Message::Segment("git".into(), here.clone(), "main".into());
+        send(&mut model, [done]);
+        assert_eq!(git(&model), [("main ".to_string(), Style::new())]);
+        assert_eq!(model.segment_spans(segment::Place::Top), []);
+        // from before a cd, it's dimmed until it's run again
+        let elsewhere = here.join("elsewhere");
+        send(
+            &mut model,
+            [Message::Segment("git".into(), elsewhere, "dev".into())],
+        );
+        assert_eq!(git(&model), [("dev ".to_string(), Style::new().dim())]);
+        // failing, or outside a repository, shows nothing
+        send(
+            &mut model,
+            [Message::Segment("git".into(), here, String::new())],
+        );
+        assert_eq!(git(&model), []);
+    }
+
+    #[test]
+    fn ctrl_d_quits_only_from_an_empty_command_line() {
+        let ctrl_d = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        let mut model = with_outputs(&[1]);
+        model.current_command = "cat > notes".into();
+        assert_eq!(handle_key(&model, ctrl_d), None);
+        // a recalled command counts as on the line
+        model.current_command.clear();
+        model.previous_commands = vec![history::Entry::new("python3".into())];
+        model.viewing_command = Some(0);
+        assert_eq!(handle_key(&model, ctrl_d), None);
+        model.viewing_command = None;
+        assert_eq!(handle_key(&model, ctrl_d), Some(Message::Quit));
+        // in Normal mode it scrolls, whatever's on the line
+        model.mode = Mode::Normal;
+        assert_eq!(handle_key(&model, ctrl_d), Some(Message::ScrollDown));
+        model.mode = Mode::Insert;
+        model.outputs[0].running = true;
+        let msg = handle_key(&model, ctrl_d);
+        send(&mut model, msg);
+        assert!(model.quitting);
+        assert_eq!(model.running_state, RunningState::Running);
+        press(&mut model, "y");
+        assert_eq!(model.running_state, RunningState::Done);
+    }
+
+    #[test]
+    fn a_failing_stage_of_a_pipeline_is_shown() {
+        let mut model = with_outputs(&[1]);
+        model.outputs[0].command = "ps aux | grep foo | sort".into();
+        model.outputs[0].running = true;
+        let status = model.finish_stages(0, vec![0, 1, 0], Some(0));
+        model.finish_job(0, status);
+        assert_eq!(model.last_exit, Some(0));
+        assert_eq!(
+            title(&model.outputs[0], &[]),
+            "ps aux | grep foo | sort (stage 2 exited 1)"
+        );
+        assert_eq!(
+            model.status.as_deref(),
+            Some("ps aux → 0 | grep foo → 1 | sort → 0")
+        );
+
+        model.config.pipefail = true;
+        model.outputs[0].running = true;
+        let status = model.finish_stages(0, vec![0, 1, 0], Some(0));
+        model.finish_job(0, status);
+        assert_eq!(model.last_exit, Some(1));
+    }
+
+    #[test]
+    fn cia_changes_a_whole_quoted_argument() {
+        let mut model = Model::default();
+        type_text(&mut model, "git commit -m \"some long message\" --amend");
+        send(&mut model, [Message::Normal]);
+        model.cursor = Cursor::CommandLine(20, 0);
+        for c in "cia".chars() {
+            let msg = handle_key(&model, KeyEvent::from(KeyCode::Char(c)));
+            send(&mut model, msg);
+        }
+        assert_eq!(model.current_command, "git commit -m  --amend");
+        assert_eq!(model.cursor, Cursor::CommandLine(14, 0));
+        assert_eq!(model.mode, Mode::Insert);
+        assert_eq!(model.registers.get(None), Some("\"some long message\""));
+        type_text(&mut model, "'fix'");
+        assert_eq!(model.current_command, "git commit -m 'fix' --amend");
+
+        // `daw` takes a blank with it, and anything else after `d` gives up
+        send(&mut model, [Message::Normal]);
+        model.cursor = Cursor::CommandLine(5, 0);
+        for c in "dxdaw".chars() {
+            let msg = handle_key(&model, KeyEvent::from(KeyCode::Char(c)));
+            send(&mut model, msg);
+        }
+        assert_eq!(model.current_command, "git -m 'fix' --amend");
+        assert_eq!(model.pending_key, None);
+        assert_eq!(model.pending_object, None);
+
+        // `"ay` yanks the command rather than waiting for a text object
+        press(&mut model, "\"ay");
+        assert_eq!(model.registers.get(Some('a')), Some("git -m 'fix' --amend"));
+        assert_eq!(model.pending_key, None);
+    }
+
+    fn press(model: &mut Model, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\x1b' => KeyCode::Esc,
+                '\t' => KeyCode::Tab,
+                c => KeyCode::Char(c),
+            };
+            let msg = handle_key(model, KeyEvent::from(code));
+            send(model, msg);
+        }
+    }
+
+    #[test]
+    fn commands_run_in_the_active_context() {
+        let mut model = Model::default();
+        model.config.set("context_low", "nice -n 5 {cmd}").unwrap();
+        let program = |model: &Model, line| {
+            let stages = model.parse_pipeline(line).unwrap();
+            let stage = &stages[0];
+            format!("{} {}", stage.program, stage.args.join(" "))
+        };
+        assert_eq!(program(&model, "ls -la"), "ls -la");
+        assert_eq!(
+            model.switch_context(Some("high")),
+            "no context high; contexts: low"
+        );
+        model.switch_context(Some("low"));
+        assert_eq!(program(&model, "ls -la"), "nice -n 5 ls -la");
+        assert_eq!(program(&model, "\\ls -la"), "ls -la");
+
+        // history has what was typed, and where it ran
+        type_text(&mut model, "make");
+        send(&mut model, [Message::Submit]);
+        let entry = model.previous_commands.last().unwrap();
+        assert_eq!(
+            (entry.command.as_str(), entry.context.as_deref()),
+            ("make", Some("low"))
+        );
+        model.switch_context(Some("none"));
+        send(&mut model, [Message::OutCommand]);
+        assert_eq!(model.command(), "make");
+        assert_eq!(
+            model.status.as_deref(),
+            Some("ran in low; now running directly")
+        );
+    }
+
+    #[test]
+    fn a_wrapped_selection_yanks_as_the_line_it_is() {
+        let mut model = with_outputs(&[]);
+        model.push_output("seq".into(), "abcdefghij\nxy\n".into());
+        (model.config.wrap, model.width, model.height) = (true, 4, 10);
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::OutputBuffer(2, 0);
+        // from mid-row, a screen row down and across the wrap
+        press(&mut model, "vgjy");
+        assert_eq!(model.registers.get(None), Some("cdefg"));
+        assert_eq!(model.visual, None);
+        // on down to a shorter line, which is taken to its end
+        model.cursor = Cursor::OutputBuffer(9, 0);
+        press(&mut model, "vjy");
+        assert_eq!(model.registers.get(None), Some("j\nxy"));
+        press(&mut model, "v\x1b");
+        assert_eq!(model.selection(), None);
+    }
+
+    #[test]
+    fn the_mouse_selects_through_wrapping_and_scrolling() {
+        use ratatui::crossterm::event::{MouseButton, MouseEventKind};
+        let at = |kind, column, row| {
+            Message::Mouse(MouseEvent {
+                kind,
+                column,
+                row,
+                modifiers: KeyModifiers::NONE,
+            })
+        };
+        let (down, drag, up) = (
+            MouseEventKind::Down(MouseButton::Left),
+            MouseEventKind::Drag(MouseButton::Left),
+            MouseEventKind::Up(MouseButton::Left),
+        );
+        let mut model = with_outputs(&[]);
+        model.push_output("seq".into(), "abcdefghij\nxy\nsome words here\n".into());
+        (model.config.wrap, model.width, model.height) = (true, 4, 10);
+        // the pane's inside starts at column 1, row 2
+        model.pane = Rect::new(0, 1, 6, 12);
+        // "abcd" "efgh" "ij": from c across the wrap to e
+        send(&mut model, [at(down, 3, 2), at(drag, 1, 3), at(up, 1, 3)]);
+        assert_eq!(model.mode, Mode::Normal);
+        press(&mut model, "y");
+        assert_eq!(model.registers.get(None), Some("cde"));
+
+        // a triple click on the line after, copied as the button goes up
+        model.config.copy_on_select = true;
+        for _ in 0..3 {
+            send(&mut model, [at(down, 2, 5), at(up, 2, 5)]);
+        }
+        assert_eq!(model.registers.get(None), Some("xy"));
+        assert_eq!(model.selection(), None);
+
+        // unwrapped and scrolled across, a double click takes "words"
+        model.config.wrap = false;
+        model.outputs[0].scroll.1 = 5;
+        send(&mut model, [at(down, 2, 4), at(up, 2, 4)]);
+        assert_eq!(model.registers.get(None), Some("xy"));
+        send(&mut model, [at(down, 2, 4), at(up, 2, 4)]);
+        assert_eq!(model.registers.get(None), Some("words"));
+
+        // the wheel scrolls, and dragging onto the border scrolls on
+        model.push_output("seq".into(), (1..=30).map(|n| format!("{n}\n")).collect());
+        model.outputs[1].set_scroll(0);
+        send(&mut model, [at(MouseEventKind::ScrollDown, 2, 4)]);
+        assert_eq!(model.outputs[1].scroll.0, 3);
+        send(&mut model, [at(down, 1, 2), at(drag, 1, 1), at(drag, 1, 0)]);
+        assert_eq!(model.outputs[1].scroll.0, 1);
+        send(&mut model, [at(up, 1, 0)]);
+        assert_eq!(model.registers.get(None), Some("2\n3\n4"));
+    }
+
+    #[test]
+    fn a_long_line_is_cut_off_but_yanked_whole() {
+        let mut model = with_outputs(&[]);
+        let long = "x".repeat(5000);
+        model.push_output("cat min.js".into(), format!("{long}\nshort\n"));
+        (model.width, model.height) = (20, 10);
+        model.config.long_line = 100;
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::OutputBuffer(0, 0);
+        press(&mut model, "$");
+        assert_eq!(model.cursor, Cursor::OutputBuffer(99, 0));
+        press(&mut model, "Y");
+        assert_eq!(model.registers.get(None), Some(long.as_str()));
+        // a selection to where it's cut off takes the rest
+        model.cursor = Cursor::OutputBuffer(90, 0);
+        press(&mut model, "v$y");
+        assert_eq!(model.registers.get(None).map(str::len), Some(4910));
+
+        // the popup shows it all, a pane-wide row at a time
+        press(&mut model, "gl");
+        assert_eq!(model.popup_line(), Some(long.as_str()));
+        press(&mut model, "jjjj");
+        assert_eq!(model.line_popup.as_ref().map(|p| p.top), Some(4));
+        let key = KeyEvent::new(KeyCode::Char('d'), KeyModifiers::CONTROL);
+        for _ in 0..100 {
+            let msg = handle_key(&model, key);
+            send(&mut model, msg);
+        }
+        // 5000 columns are 250 rows of 20
+        assert_eq!(model.line_popup.as_ref().map(|p| p.top), Some(249));
+        press(&mut model, "yq");
+        assert_eq!(model.registers.get(None), Some(long.as_str()));
+        assert!(model.line_popup.is_none());
+    }
+
+    #[test]
+    fn alt_dot_takes_lines_and_words_from_output() {
+        let mut model = with_outputs(&[]);
+        model.push_output(
+            "fd".into(),
+            "src/main.rs\nmy notes.txt\nCargo.toml\n".into(),
+        );
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        type_text(&mut model, "vim ");
+        let msg = handle_key(&model, alt(','));
+        send(&mut model, msg);
+        assert_eq!(model.current_command, "vim Cargo.toml ");
+        for c in ['2', ','] {
+            let msg = handle_key(&model, alt(c));
+            send(&mut model, msg);
+        }
+        assert_eq!(model.current_command, "vim Cargo.toml 'my notes.txt' ");
+        assert_eq!(model.cursor, model.command_end());
+
+        // in the output, the word under the cursor
+        model.current_command = "cat".into();
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::OutputBuffer(5, 0);
+        let msg = handle_key(&model, alt('.'));
+        send(&mut model, msg);
+        assert_eq!(model.current_command, "cat src/main.rs ");
+        assert_eq!(model.mode, Mode::Insert);
+        // a count in normal mode
+        send(&mut model, [Message::Normal]);
+        press(&mut model, "9");
+        let msg = handle_key(&model, alt(','));
+        send(&mut model, msg);
+        assert_eq!(
+            model.status.as_deref(),
+            Some("no line 9 in the last output")
+        );
+    }
+
+    #[test]
+    fn alt_dot_takes_arguments_of_commands_before() {
+        let mut model = with_history(&["scp \"my notes.txt\" host:/tmp", "ssh web1", ""]);
+        let alt = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::ALT);
+        type_text(&mut model, "ping ");
+        let msg = handle_key(&model, alt('.'));
+        send(&mut model, msg);
+        assert_eq!(model.current_command, "ping web1");
+        // again, the one before that in its place
+        let msg = handle_key(&model, alt('.'));
+        send(&mut model, msg);
+        assert_eq!(model.current_command, "ping host:/tmp");
+        assert_eq!(model.cursor, model.command_end());
+        let msg = handle_key(&model, alt('.'));
+        send(&mut model, msg);
+        assert_eq!(model.status.as_deref(), Some("no older command"));
+        assert_eq!(model.current_command, "ping host:/tmp");
+
+        // a count picks the word, and anything else starts over
+        type_text(&mut model, " ");
+        for c in ['1', '.'] {
+            let msg = handle_key(&model, alt(c));
+            send(&mut model, msg);
+        }
+        assert_eq!(model.current_command, "ping host:/tmp web1");
+
+        // Alt-a picks from them all
+        model.current_command = "cat ".into();
+        model.cursor = model.command_end();
+        let msg = handle_key(&model, alt('a'));
+        send(&mut model, msg);
+        let picker = model.picker.as_ref().unwrap();
+        assert_eq!(picker.kind, PickerKind::Arguments);
+        send(&mut model, "notes".chars().map(Message::PickerChar));
+        send(&mut model, [Message::PickerAccept]);
+        assert_eq!(model.current_command, "cat 'my notes.txt' ");
+    }
+
+    #[test]
+    fn links_are_opened_from_under_the_cursor_or_by_letter() {
+        let mut model = with_outputs(&[]);
+        model.push_output(
+            "gh run view".into(),
+            "see [run](https://ci.example.com/run/12) or http://localhost:3000.\nno link\n".into(),
+        );
+        model.config.opener = Some("true".into());
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::OutputBuffer(50, 0);
+        press(&mut model, "go");
+        assert_eq!(
+            model.status.as_deref(),
+            Some("opened http://localhost:3000")
+        );
+        // off any link, the first on the line
+        model.cursor = Cursor::OutputBuffer(0, 0);
+        press(&mut model, "go");
+        assert_eq!(
+            model.status.as_deref(),
+            Some("opened https://ci.example.com/run/12")
+        );
+        model.cursor = Cursor::OutputBuffer(0, 1);
+        press(&mut model, "go");
+        assert_eq!(model.status.as_deref(), Some("no link on this line"));
+
+        press(&mut model, "gL");
+        let items = &model.picker.as_ref().unwrap().items;
+        assert_eq!(
+            items,
+            &[
+                "a  https://ci.example.com/run/12",
+                "s  http://localhost:3000"
+            ]
+        );
+        // a letter with no link is nothing
+        press(&mut model, "d");
+        assert!(model.picker.is_some());
+        model.config.opener = Some("no-such-opener-here".into());
+        press(&mut model, "s");
+        assert!(model.picker.is_none());
+        let status = model.status.as_deref().unwrap();
+        assert!(status.starts_with("couldn't open http://localhost:3000: "));
+    }
+
+    #[test]
+    fn gx_takes_a_suggested_command_to_look_over() {
+        let mut model = with_outputs(&[]);
+        model.push_output(
+            "cargo clippy".into(),
+            "warning: unused import\nrun `cargo fix --lib -p shim` to apply\n```rust\nuse std::fs;\n```\n".into(),
+        );
+        model.mode = Mode::Normal;
+        model.cursor = Cursor::OutputBuffer(3, 1);
+        press(&mut model, "gx");
+        assert_eq!(model.current_command, "cargo fix --lib -p shim");
+        assert_eq!(model.mode, Mode::Insert);
+        assert_eq!(model.cursor, model.command_end());
+        assert_eq!(model.outputs.len(), 1);
+        // a fence that isn't shell is refused with a toast
+        send(&mut model, [Message::Normal]);
+        model.cursor = Cursor::OutputBuffer(0, 3);
+        press(&mut model, "gx");
+        assert_eq!(model.current_command, "cargo fix --lib -p shim");
+        assert_eq!(model.messages(), "warn  not a shell code fence: rust");
+        // or run at once
+        model.config.run_from_output = true;
+        model.cursor = Cursor::OutputBuffer(0, 1);
+        let g = handle_key(&model, KeyEvent::from(KeyCode::Char('g')));
+        send(&mut model, g);
+        let x = handle_key(&model, KeyEvent::from(KeyCode::Char('x')));
+        assert!(x == Some(Message::TakeCommand));
+        assert!(update(&mut model, Message::TakeCommand) == Some(Message::Submit));
+    }
+
+    #[test]
+    fn bookmarked_lines_are_gone_back_to() {
+        let mut model = with_outputs(&[100]);
+        model.mode = Mode::Normal;
+        model.outputs[0].set_scroll(0);
+        model.cursor = Cursor::OutputBuffer(0, 2);
+        press(&mut model, "M");
+        model.cursor = Cursor::OutputBuffer(0, 5);
+        press(&mut model, "M");
+        assert_eq!(model.status.as_deref(), Some("bookmarked line 6"));
+        // more output doesn't move them
+        let id = model.outputs[0].id;
+        send(
+            &mut model,
+            [Message::OutputChunk(id, b"line 100\n".to_vec())],
+        );
+        press(&mut model, "G[b");
+        assert_eq!(model.line_under_cursor(), Some((0, 5)));
+        press(&mut model, "[b");
+        assert_eq!(model.line_under_cursor(), Some((0, 2)));
+        press(&mut model, "[b");
+        assert_eq!(model.status.as_deref(), Some("no bookmark above"));
+        // a count past the last one stops there
+        press(&mut model, "3]b");
+        assert_eq!(model.line_under_cursor(), Some((0, 5)));
+        press(&mut model, "M");
+        assert_eq!(model.outputs[0].bookmarks, BTreeSet::from([2]));
+        assert_eq!(builtin::run(&mut model, ":bookmarks"), "");
+        let picker = model.picker.as_ref().unwrap();
+        assert_eq!(picker.items, ["     3  line 2"]);
+        model.cursor = Cursor::CommandLine(0, 0);
+        send(&mut model, [Message::PickerAccept]);
+        assert_eq!(model.line_under_cursor(), Some((0, 2)));
+        // checkpoints are stepped through with [[ and ]] now
+        press(&mut model, "[[");
+        assert!(model.outputs[0].frozen.is_some());
+    }
+
+    #[test]
+    fn dot_repeats_the_last_change() {
+        let mut model = Model::default();
+        type_text(&mut model, "cp old.txt old.bak");
+        send(&mut model, [Message::Normal]);
+        model.cursor = Cursor::CommandLine(3, 0);
+        press(&mut model, "ciwnew\x1b");
+        assert_eq!(model.current_command, "cp new.txt old.bak");
+        model.cursor = Cursor::CommandLine(11, 0);
+        press(&mut model, ".");
+        assert_eq!(model.current_command, "cp new.txt new.bak");
+
+        // a count makes it that many times over
+        press(&mut model, "0ix\x1b3.");
+        assert_eq!(model.current_command, "xxxxcp new.txt new.bak");
+        assert_eq!(model.count, None);
+    }
+
+    #[test]
+    fn dot_repeats_dd() {
+        let mut model = Model::default();
+        type_text(&mut model, "echo a\\");
+        send(&mut model, [Message::Newline]);
+        type_text(&mut model, "echo b\\");
+        send(&mut model, [Message::Newline]);
+        type_text(&mut model, "echo c");
+        send(&mut model, [Message::Normal]);
+        model.cursor = Cursor::CommandLine(0, 1);
+        press(&mut model, "dd");
+        assert_eq!(model.current_command, "echo a\\\necho c");
+        assert_eq!(model.registers.get(None), Some("echo b\\"));
+        press(&mut model, ".");
+        assert_eq!(model.current_command, "echo a\\");
+        assert_eq!(model.cursor, Cursor::CommandLine(0, 0));
+    }
+
+    #[test]
+    fn bang_bang_runs_the_last_command_again() {
+        let mut model = with_history(&["ls", ":pin"]);
+        model.push_output("make".into(), String::new());
+        type_text(&mut model, "git st");
+        send(&mut model, [Message::Normal]);
+        press(&mut model, "!!");
+        assert!(model.outputs[0].pinned);
+        assert_eq!(model.previous_commands.len(), 3);
+        assert_eq!(model.current_command, "git st");
+        press(&mut model, "!!");
+        assert!(!model.outputs[0].pinned);
+    }
+
+    #[test]
+    fn the_scrollback_shows_every_buffer_under_its_command() {
+        let mut model = with_outputs(&[20, 3]);
+        model.viewing_output = 0;
+        model.outputs[0].set_scroll(5);
+        model.cursor = Cursor::OutputBuffer(0, 2);
+        model.toggle_scrollback();
+        // the same line stays under the cursor
+        assert_eq!(model.output_line(2), Some("line 7"));
+        send(&mut model, [Message::ScrollDown]);
+        assert_eq!(model.output_line(5), Some("line 19"));
+        // a prompt line, then the next buffer
+        assert_eq!(model.output_line(6), None);
+        assert_eq!(model.output_line(7), Some("line 0"));
+        model.cursor = Cursor::OutputBuffer(0, 7);
+        send(&mut model, [Message::LineStart]);
+        assert_eq!(model.viewing_output, 1);
+
+        // at the end it keeps up with new buffers
+        model.push_output("cmd 2".into(), "a\nb\n".into());
+        assert_eq!(model.output_line(9), Some("b"));
+        send(
+            &mut model,
+            [Message::PreviousOutput, Message::PreviousOutput],
+        );
+        assert_eq!(model.scrollback_top(), Some(0));
+        assert_eq!(model.output_line(7), Some("line 6"));
+        model.toggle_scrollback();
+        assert_eq!(model.viewing_output, 0);
+        assert_eq!(model.output_line(6), Some("line 6"));
+        assert_eq!(model.cursor, Cursor::OutputBuffer(0, 6));
+    }
+
+    #[test]
+    fn a_commented_command_is_stashed_without_running() {
+        let mut model = Model::default();
+        type_text(&mut model, "# rm -rf build");
+        send(&mut model, [Message::Submit]);
+        assert!(model.outputs.is_empty());
+        assert_eq!(model.current_command, "");
+        assert_eq!(model.previous_commands[0].command, "# rm -rf build");
+        assert_eq!(model.status.as_deref(), Some("stashed in history"));
+
+        model.config.stash_keep_hash = false;
+        type_text(&mut model, "#make release");
+        send(&mut model, [Message::Submit]);
+        assert_eq!(model.previous_commands[1].command, "make release");
+        type_text(&mut model, "#  ");
+        send(&mut model, [Message::Submit]);
+        assert_eq!(model.previous_commands.len(), 2);
+    }
+
+    #[test]
+    fn insert_comment_stashes_the_line() {
+        let mut model = Model::default();
+        type_text(&mut model, "make release");
+        send(&mut model, [Message::InsertComment]);
+        assert!(model.outputs.is_empty());
+        assert_eq!(model.previous_commands[0].command, "#make release");
+    }
+}
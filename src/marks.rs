@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{Cursor, Model, anchor};
+
+/// A place in an output buffer to come back to.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct Mark {
+    /// Not saved: a session file says which of its steps' buffers.
+    #[serde(skip)]
+    pub output_id: usize,
+    scroll: (usize, u16),
+    cursor: Cursor,
+}
+
+impl Mark {
+    /// The first `lines` lines of buffer `output_id` are gone; see
+    /// `anchor`.
+    pub fn lines_dropped(&mut self, output_id: usize, lines: usize) {
+        if self.output_id == output_id {
+            anchor::shift(&mut self.scroll.0, &mut self.cursor, lines);
+        }
+    }
+}
+
+impl Model {
+    fn position(&self) -> Option<Mark> {
+        let output = self.outputs.get(self.viewing_output)?;
+        Some(Mark {
+            output_id: output.id,
+            scroll: (output.scroll_goal(), output.scroll.1),
+            cursor: self.cursor,
+        })
+    }
+
+    /// Go to `position`, remembering where we were for `''`.
+    fn jump(&mut self, position: Mark) -> Result<(), String> {
+        let index = self
+            .outputs
+            .iter()
+            .position(|o| o.id == position.output_id)
+            .ok_or("mark invalid: its buffer is gone")?;
+        self.last_jump = self.position();
+        let (line, column) = position.scroll;
+        match index == self.viewing_output {
+            true => self.scroll_to(index, line),
+            false => self.outputs[index].set_scroll(line),
+        }
+        self.view_output(index);
+        self.outputs[index].scroll.1 = column;
+        self.cursor = position.cursor;
+        self.clamp_output_cursor();
+        Ok(())
+    }
+
+    pub fn set_mark(&mut self, name: char) {
+        match self.position() {
+            Some(position) => {
+                self.marks.insert(name, position);
+            }
+            None => self.status = Some("no buffer to mark".into()),
+        }
+    }
+
+    pub fn jump_to_mark(&mut self, name: char) {
+        let result = match self.marks.get(&name) {
+            Some(position) => self.jump(*position),
+            None => Err(format!("mark not set: {name}")),
+        };
+        if let Err(e) = result {
+            self.status = Some(e);
+        }
+    }
+
+    pub fn jump_back(&mut self) {
+        let result = match self.last_jump {
+            Some(position) => self.jump(position),
+            None => Err("no previous position".into()),
+        };
+        if let Err(e) = result {
+            self.status = Some(e);
+        }
+    }
+
+    /// Scroll the viewed buffer to `line`, as a jump.
+    pub fn jump_to_line(&mut self, line: usize) {
+        if let Some(position) = self.position() {
+            let _ = self.jump(Mark {
+                scroll: (line, position.scroll.1),
+                ..position
+            });
+        }
+    }
+
+    /// Put the cursor on `line` of the viewed buffer, as a jump, scrolling
+    /// only if it's out of view.
+    pub fn jump_to_output_line(&mut self, line: usize) {
+        let Some(position) = self.position() else {
+            return;
+        };
+        let (top, column) = position.scroll;
+        let height = self.height as usize;
+        let top = match (top..top + height).contains(&line) {
+            true => top,
+            false => line.saturating_sub(height / 2),
+        };
+        let x = match position.cursor {
+            Cursor::OutputBuffer(x, _) => x,
+            Cursor::CommandLine(_, _) => 0,
+        };
+        let _ = self.jump(Mark {
+            scroll: (top, column),
+            cursor: Cursor::OutputBuffer(x, (line - top) as u16),
+            ..position
+        });
+    }
+}
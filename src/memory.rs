@@ -0,0 +1,162 @@
+use std::mem;
+
+use crate::{
+    Model, Output,
+    closed::{Closed, Contents},
+    stderr,
+};
+
+/// How an evicted buffer's warning starts.
+const EVICTED: &str = "contents evicted";
+
+impl Output {
+    /// Approximate number of bytes this buffer holds on to.
+    pub fn size(&self) -> usize {
+        self.command.len()
+            + self.stdout.len()
+            + self.raw.as_ref().map_or(0, String::len)
+            + self.pipe_size()
+            + self.line_index.size()
+            + self.images.size()
+            + self.streams.as_ref().map_or(0, stderr::Streams::size)
+    }
+
+    /// Drop the contents, leaving a placeholder. Returns what can be put
+    /// back with `restore`.
+    pub fn evict(&mut self) -> Contents {
+        let contents = Contents {
+            stdout: mem::take(&mut self.stdout),
+            raw: self.raw.take(),
+            streams: self.streams.take(),
+            bookmarks: mem::take(&mut self.bookmarks),
+            scroll: self.scroll,
+        };
+        let freed = contents.size() + self.pipe_size() + self.images.size();
+        self.pipe = None;
+        self.images.clear();
+        self.line_index.changed();
+        self.clear_checkpoints();
+        self.warn(format!(
+            "{EVICTED} to stay under memory_cap, {} KB",
+            freed.div_ceil(1024)
+        ));
+        self.evicted = Some(freed);
+        self.scroll = (0, 0);
+        self.scroll_target = None;
+        contents
+    }
+
+    /// Put back what `evict` took out.
+    pub fn restore(&mut self, contents: Contents) {
+        self.stdout = contents.stdout;
+        self.raw = contents.raw;
+        self.streams = contents.streams;
+        self.bookmarks = contents.bookmarks;
+        self.scroll = contents.scroll;
+        self.line_index.changed();
+        self.evicted = None;
+        self.warnings.retain(|w| !w.starts_with(EVICTED));
+    }
+
+    /// Whether the command failed or was killed. Builtins' buffers count as
+    /// successful.
+    pub fn failed(&self) -> bool {
+        match self.status {
+            Some(status) => status != 0,
+            None => self.started.is_some() && !self.running,
+        }
+    }
+}
+
+impl Model {
+    pub fn memory_usage(&self) -> usize {
+        self.outputs.iter().map(Output::size).sum()
+    }
+
+    /// Evict the oldest buffers until usage is under `cap`, returning how
+    /// many were evicted. Pinned buffers, the one on screen and ones still
+    /// being written to are kept. What's evicted is kept among the closed
+    /// if it fits.
+    pub fn evict_to(&mut self, cap: usize) -> usize {
+        let mut usage = self.memory_usage();
+        let mut evicted = 0;
+        let mut kept = Vec::new();
+        for (i, output) in self.outputs.iter_mut().enumerate() {
+            if usage <= cap {
+                break;
+            }
+            if i == self.viewing_output
+                || output.running
+                || output.pinned
+                || output.evicted.is_some()
+            {
+                continue;
+            }
+            let before = output.size();
+            let contents = output.evict();
+            usage = usage + output.size() - before;
+            evicted += 1;
+            if contents.size() <= self.config.closed_size {
+                kept.push(Closed::Contents {
+                    id: output.id,
+                    contents: Box::new(contents),
+                });
+            }
+        }
+        for closed in kept {
+            self.keep_closed(closed);
+        }
+        evicted
+    }
+
+    /// Close the oldest buffers until `room` more fit under `max_buffers`,
+    /// returning how many were closed. Pinned buffers, the one on screen
+    /// and ones still running or waiting to retry are kept, and with
+    /// `close_buffers = successful` so are failures. The closed are kept
+    /// to be brought back; see `closed`.
+    pub fn close_to_fit(&mut self, room: usize) -> usize {
+        let max = self.config.max_buffers;
+        if max == 0 {
+            return 0;
+        }
+        let mut excess = (self.outputs.len() + room).saturating_sub(max);
+        let mut closed = 0;
+        let mut i = 0;
+        while excess > 0 && i < self.outputs.len() {
+            let output = &self.outputs[i];
+            let waiting = output.retry.as_ref().is_some_and(|r| r.next.is_some());
+            if i == self.viewing_output
+                || output.running
+                || output.pinned
+                || waiting
+                || (self.config.keep_failed_buffers && output.failed())
+            {
+                i += 1;
+                continue;
+            }
+            let output = Box::new(self.outputs.remove(i));
+            self.keep_closed(Closed::Buffer { output, index: i });
+            if self.viewing_output > i {
+                self.viewing_output -= 1;
+            }
+            excess -= 1;
+            closed += 1;
+        }
+        closed
+    }
+}
+
+pub fn format_size(bytes: usize) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
@@ -0,0 +1,207 @@
+//! The mouse, with `mouse` set. shim takes it from the terminal, which
+//! then can't select text itself, so shim selects instead: dragging in
+//! the output pane selects as `v` does and the selection stays once the
+//! button is let go, for `y`, or with `copy_on_select` it's yanked
+//! straight away. A double click takes the word under the pointer and a
+//! triple click the line. Dragging onto the pane's top or bottom border
+//! scrolls a line each time the pointer moves there, and the wheel
+//! scrolls. Screen positions go through the pane's borders, the view's
+//! scroll either way and wrapping to land on a line of the buffer.
+
+use std::time::{Duration, Instant};
+
+use ratatui::{
+    crossterm::event::{MouseButton, MouseEvent, MouseEventKind},
+    layout::{Margin, Position, Rect},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{Cursor, Focus, Mode, Model, display, select, select::Point, textobj};
+
+/// Clicks closer together than this in the same place make a double or
+/// triple click.
+const MULTI_CLICK: Duration = Duration::from_millis(400);
+/// Lines a turn of the wheel scrolls.
+const WHEEL_LINES: u16 = 3;
+
+#[derive(Debug, Default)]
+pub struct State {
+    /// When and where the button last went down, and how many clicks in a
+    /// row that made.
+    last: Option<(Instant, u16, u16)>,
+    clicks: u8,
+    /// Where a drag selects from, while the button is down in the pane.
+    anchor: Option<Point>,
+}
+
+/// The first and last positions of the word at position `index` of
+/// `line`, or of the run of blanks there.
+fn word_at(line: &str, index: usize) -> Option<(usize, usize)> {
+    let starts: Vec<usize> = line.grapheme_indices(true).map(|(i, _)| i).collect();
+    let range = textobj::word(line, *starts.get(index)?, false)?;
+    let first = starts.iter().position(|&i| i >= range.start)?;
+    let last = starts.iter().rposition(|&i| i < range.end)?;
+    Some((first, last))
+}
+
+impl Model {
+    /// What the mouse did, in the terminal's columns and rows.
+    pub fn mouse(&mut self, event: MouseEvent) {
+        if matches!(self.focus(), Focus::Overlay(_)) {
+            return;
+        }
+        let (col, row) = (event.column, event.row);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => self.press(col, row),
+            MouseEventKind::Drag(MouseButton::Left) => self.drag(col, row),
+            MouseEventKind::Up(MouseButton::Left) => self.release(),
+            MouseEventKind::ScrollDown => self.wheel(WHEEL_LINES as i16),
+            MouseEventKind::ScrollUp => self.wheel(-(WHEEL_LINES as i16)),
+            _ => {}
+        }
+    }
+
+    /// The output pane without its borders.
+    fn inner_pane(&self) -> Rect {
+        self.pane.inner(Margin::new(1, 1))
+    }
+
+    /// The line and position of the viewed buffer drawn at `col`, `row`,
+    /// if that's in the output pane. Below the last line is the end of it.
+    fn point_at(&self, col: u16, row: u16) -> Option<Point> {
+        let inner = self.inner_pane();
+        if self.scrollback.is_some() || self.splitting() || !inner.contains(Position::new(col, row))
+        {
+            return None;
+        }
+        let output = self.outputs.get(self.viewing_output)?;
+        let across = match self.wrapping() {
+            true => 0,
+            false => output.scroll.1 as usize,
+        };
+        let top = output.scroll.0;
+        let (row, col) = ((row - inner.y) as usize, (col - inner.x) as usize);
+        let (width, settings) = (self.wrap_width(), self.output_settings());
+        match select::to_logical(self.shown_lines(), width, settings, row, col + across) {
+            Some(point) => Some(Point {
+                line: top + point.line,
+                ..point
+            }),
+            None => {
+                let line = output.line_count().checked_sub(1).filter(|&n| n >= top)?;
+                let index = display::len(output.line(line).unwrap_or(""));
+                Some(Point { line, index })
+            }
+        }
+    }
+
+    /// Put the cursor on `point` of the viewed buffer, which is in view.
+    fn put_cursor(&mut self, point: Point) {
+        let top = self.outputs[self.viewing_output].scroll.0;
+        let (x, y) = (point.index, point.line.saturating_sub(top));
+        self.cursor = Cursor::OutputBuffer(x.min(u16::MAX as usize) as u16, y as u16);
+        self.mode = Mode::Normal;
+    }
+
+    fn press(&mut self, col: u16, row: u16) {
+        self.mouse.anchor = None;
+        let Some(point) = self.point_at(col, row) else {
+            return;
+        };
+        let now = Instant::now();
+        self.mouse.clicks = match self.mouse.last {
+            Some((at, c, r)) if (c, r) == (col, row) && now - at < MULTI_CLICK => {
+                self.mouse.clicks % 3 + 1
+            }
+            _ => 1,
+        };
+        self.mouse.last = Some((now, col, row));
+        let line = self.outputs[self.viewing_output]
+            .line(point.line)
+            .unwrap_or("");
+        let (first, last) = match self.mouse.clicks {
+            2 => word_at(line, point.index).unwrap_or((point.index, point.index)),
+            3 => (0, display::len(line).saturating_sub(1)),
+            _ => (point.index, point.index),
+        };
+        let first = Point {
+            index: first,
+            ..point
+        };
+        self.visual = (self.mouse.clicks > 1).then_some((self.viewing_output, first));
+        self.mouse.anchor = Some(first);
+        self.put_cursor(Point {
+            index: last,
+            ..point
+        });
+    }
+
+    fn drag(&mut self, col: u16, row: u16) {
+        let Some(anchor) = self.mouse.anchor else {
+            return;
+        };
+        let (inner, index) = (self.inner_pane(), self.viewing_output);
+        if inner.is_empty() || index >= self.outputs.len() {
+            return;
+        }
+        let top = self.outputs[index].scroll.0;
+        let row = if row < inner.y {
+            self.outputs[index].set_scroll(top.saturating_sub(1));
+            inner.y
+        } else if row >= inner.bottom() {
+            let bottom = self.bottom(index);
+            self.outputs[index].set_scroll(top.saturating_add(1).min(bottom.max(top)));
+            inner.bottom() - 1
+        } else {
+            row
+        };
+        let col = col.clamp(inner.x, inner.right() - 1);
+        let Some(point) = self.point_at(col, row) else {
+            return;
+        };
+        self.visual.get_or_insert((index, anchor));
+        self.put_cursor(point);
+    }
+
+    fn release(&mut self) {
+        if self.mouse.anchor.take().is_some()
+            && self.config.copy_on_select
+            && self.selection().is_some()
+        {
+            self.yank_selection();
+        }
+    }
+
+    fn wheel(&mut self, lines: i16) {
+        if self.scrollback.is_some() {
+            self.scroll_scrollback_by(lines as isize);
+            return;
+        }
+        if self.splitting() {
+            self.wheel_half(lines);
+            return;
+        }
+        let index = self.viewing_output;
+        if let Some(output) = self.outputs.get(index) {
+            let line = output.scroll_goal().saturating_add_signed(lines as isize);
+            self.scroll_to(index, line.min(self.bottom(index)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_double_click_takes_the_word() {
+        let line = "error: café-au-lait at src/main.rs";
+        assert_eq!(word_at(line, 0), Some((0, 4)));
+        assert_eq!(word_at(line, 3), Some((0, 4)));
+        // the blanks between words
+        assert_eq!(word_at(line, 6), Some((6, 6)));
+        // é is one position though two bytes
+        assert_eq!(word_at(line, 9), Some((7, 10)));
+        assert_eq!(word_at(line, 40), None);
+    }
+}
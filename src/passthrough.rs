@@ -0,0 +1,218 @@
+//! Running a full-screen program, like htop or vim, in the terminal itself
+//! rather than into a buffer: `@cmd`, or any command whose program is in
+//! `fullscreen`. Before it next draws, the main loop gives the terminal
+//! back as it does to suspend, stops reading keys and waits for the
+//! program, then takes the terminal again and draws it all. The buffer
+//! left is a stub, with when the program ran, how long for and how it
+//! ended, but none of its output.
+//!
+//! The program is in shim's process group, so Ctrl-C and Ctrl-\ reach it
+//! from the terminal as they would from a shell; shim only doesn't die of
+//! them meanwhile, and the panic hook leaves the terminal alone. Ctrl-Z
+//! would stop it with no one to continue it, so it's continued at once.
+//! Unix only: elsewhere shim can't keep Ctrl-C from ending itself.
+
+use std::{
+    io,
+    process::Command,
+    sync::{
+        Arc, LazyLock,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crate::{Model, shell};
+
+/// Programs given the terminal without `@`.
+pub const FULLSCREEN: [&str; 12] = [
+    "btop", "emacs", "htop", "less", "man", "nano", "nvim", "screen", "tmux", "top", "vi", "vim",
+];
+
+/// Whether shim has the terminal, rather than a program it's handed it
+/// to; signals from the terminal are for shim only while it has.
+static OURS: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(true)));
+
+/// Shared with the signal handlers, to do as they would without shim's
+/// while it has the terminal.
+#[cfg(unix)]
+pub fn ours() -> Arc<AtomicBool> {
+    OURS.clone()
+}
+
+/// Whether a program has the terminal.
+pub fn handed_over() -> bool {
+    !OURS.load(Ordering::SeqCst)
+}
+
+/// A program waiting for the terminal.
+#[derive(Debug)]
+pub struct Handover {
+    /// The buffer it ran from.
+    pub output: usize,
+    pub process: Command,
+}
+
+/// Run `process` with the terminal, as the main loop has left it, and
+/// give back its exit code.
+#[cfg(unix)]
+pub fn run(mut process: Command) -> io::Result<i32> {
+    use std::os::unix::process::ExitStatusExt;
+
+    OURS.store(false, Ordering::SeqCst);
+    let child = process.spawn();
+    let ended = child.and_then(|child| {
+        let pid = child.id() as libc::pid_t;
+        loop {
+            let mut status = 0;
+            if unsafe { libc::waitpid(pid, &mut status, libc::WUNTRACED) } == -1 {
+                match io::Error::last_os_error() {
+                    e if e.kind() == io::ErrorKind::Interrupted => continue,
+                    e => break Err(e),
+                }
+            }
+            // Ctrl-Z: nothing would bring it back
+            if libc::WIFSTOPPED(status) {
+                unsafe { libc::kill(pid, libc::SIGCONT) };
+                continue;
+            }
+            let status = std::process::ExitStatus::from_raw(status);
+            break Ok(crate::exit::code(status.code(), status.signal()));
+        }
+    });
+    OURS.store(true, Ordering::SeqCst);
+    ended
+}
+
+#[cfg(not(unix))]
+pub fn run(_process: Command) -> io::Result<i32> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+impl Model {
+    /// What to give the terminal to for `command`: all of it after `@`,
+    /// or the command if it's one program in `fullscreen`.
+    pub fn passthrough_line<'a>(&self, command: &'a str) -> Option<&'a str> {
+        if let Some(rest) = command.trim_start().strip_prefix('@') {
+            return Some(rest);
+        }
+        if !cfg!(unix) || shell::pipeline(command).len() > 1 {
+            return None;
+        }
+        let program = shell::words(command, |name| self.var(name))
+            .into_iter()
+            .next()??;
+        let name = program.rsplit('/').next().unwrap_or(&program);
+        self.config
+            .fullscreen
+            .iter()
+            .any(|p| p == name)
+            .then_some(command)
+    }
+
+    /// Start `line` from `command` with the terminal: a stub buffer now,
+    /// and the program once the main loop gets to it.
+    pub fn hand_over(&mut self, command: String, line: &str) {
+        if !cfg!(unix) {
+            self.push_output(command, String::new());
+            self.set_last_status(126);
+            if let Some(output) = self.outputs.last_mut() {
+                output.warn("programs can only be given the terminal on Unix".into());
+            }
+            return;
+        }
+        let Some(mut stages) = self.parse_pipeline(line) else {
+            return;
+        };
+        if stages.len() != 1 {
+            self.push_output(command, String::new());
+            self.set_last_status(2);
+            if let Some(output) = self.outputs.last_mut() {
+                output.warn("only a single program can be given the terminal".into());
+            }
+            return;
+        }
+        let process = self.process(stages.remove(0));
+        self.push_output(command, String::new());
+        if let Some(output) = self.outputs.last_mut() {
+            output.running = true;
+            output.in_terminal = true;
+            output.mark_started();
+            self.handover = Some(Handover {
+                output: output.id,
+                process,
+            });
+        }
+    }
+
+    /// The program given the terminal for buffer `output` has ended, or
+    /// couldn't start.
+    pub fn handed_back(&mut self, output: usize, ended: io::Result<i32>) {
+        let code = match ended {
+            Ok(code) => code,
+            Err(e) => {
+                let code = match e.kind() {
+                    io::ErrorKind::NotFound => 127,
+                    _ => 126,
+                };
+                if let Some(output) = self.outputs.iter_mut().find(|o| o.id == output) {
+                    output.warn(format!("couldn't start it: {e}"));
+                }
+                code
+            }
+        };
+        self.finish_job(output, Some(code));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn full_screen_programs_get_the_terminal() {
+        let mut model = Model::default();
+        assert_eq!(model.passthrough_line("@ssh -t web1"), Some("ssh -t web1"));
+        assert_eq!(model.passthrough_line("ls"), None);
+        if cfg!(unix) {
+            assert_eq!(
+                model.passthrough_line("/usr/bin/htop -d 5"),
+                Some("/usr/bin/htop -d 5")
+            );
+            assert_eq!(model.passthrough_line("ps aux | less"), None);
+            model.config.fullscreen = vec!["lazygit".into()];
+            assert_eq!(model.passthrough_line("htop"), None);
+            assert_eq!(model.passthrough_line("lazygit"), Some("lazygit"));
+        }
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn a_stub_buffer_says_how_the_program_ended() {
+        let mut model = Model {
+            current_command: "@sh -c 'kill -STOP $$; exit 3'".into(),
+            ..Default::default()
+        };
+        crate::update(&mut model, crate::Message::Submit);
+        let handover = model.handover.take().unwrap();
+        assert!(model.outputs[0].running);
+        // stopped, it's continued rather than left waiting
+        let ended = run(handover.process);
+        assert!(!handed_over());
+        model.handed_back(handover.output, ended);
+        let output = &model.outputs[0];
+        assert_eq!(output.status, Some(3));
+        assert!(!output.running);
+        let note = output.empty_note().unwrap();
+        assert!(
+            note.starts_with("(ran in the terminal — exit 3 in"),
+            "{note}"
+        );
+        assert_eq!(model.last_exit, Some(3));
+
+        model.current_command = "@no-such-program-for-shim".into();
+        crate::update(&mut model, crate::Message::Submit);
+        let handover = model.handover.take().unwrap();
+        model.handed_back(handover.output, run(handover.process));
+        assert_eq!(model.outputs[1].status, Some(127));
+    }
+}
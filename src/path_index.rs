@@ -0,0 +1,91 @@
+use std::{env, fs, path::PathBuf, sync::OnceLock, thread};
+
+use crate::shell;
+
+static INDEX: OnceLock<Vec<String>> = OnceLock::new();
+
+/// Names of every executable on `$PATH`, sorted and deduplicated.
+///
+/// Built once on first use; anything that needs to know which commands exist
+/// (suggestions, completion) should go through here rather than walking
+/// `$PATH` itself.
+pub fn executables() -> &'static [String] {
+    INDEX.get_or_init(|| {
+        let mut names = Vec::new();
+        let Some(path) = env::var_os("PATH") else {
+            return names;
+        };
+        let extensions = shell::executable_extensions(|name| env::var(name).ok());
+        for dir in env::split_paths(&path) {
+            let Ok(entries) = fs::read_dir(dir) else {
+                continue;
+            };
+            for entry in entries.flatten() {
+                if is_executable(&entry, &extensions) {
+                    names.extend(
+                        entry
+                            .file_name()
+                            .to_str()
+                            .map(|name| strip_extension(name, &extensions)),
+                    );
+                }
+            }
+        }
+        names.sort();
+        names.dedup();
+        names
+    })
+}
+
+/// The index if it's been built, for when waiting on it would hold up
+/// typing.
+pub fn built() -> Option<&'static [String]> {
+    INDEX.get().map(Vec::as_slice)
+}
+
+/// Build the index in the background.
+pub fn build_soon() {
+    thread::spawn(executables);
+}
+
+/// `git.exe` is run as `git`.
+fn strip_extension(name: &str, extensions: &[String]) -> String {
+    let lower = name.to_lowercase();
+    extensions
+        .iter()
+        .filter(|ext| !ext.is_empty() && lower.ends_with(ext.as_str()))
+        .map(|ext| name[..name.len() - ext.len()].to_string())
+        .next()
+        .unwrap_or(name.to_string())
+}
+
+#[cfg(unix)]
+fn is_executable(entry: &fs::DirEntry, _extensions: &[String]) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    entry
+        .metadata()
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(entry: &fs::DirEntry, extensions: &[String]) -> bool {
+    let name = entry.file_name().to_string_lossy().to_lowercase();
+    entry.metadata().map(|m| m.is_file()).unwrap_or(false)
+        && extensions
+            .iter()
+            .any(|ext| !ext.is_empty() && name.ends_with(ext.as_str()))
+}
+
+/// Where on `$PATH` the executable `name` would be found.
+pub fn resolve(name: &str) -> Option<PathBuf> {
+    let path = env::var_os("PATH")?;
+    let extensions = shell::executable_extensions(|name| env::var(name).ok());
+    env::split_paths(&path)
+        .flat_map(|dir| {
+            extensions
+                .iter()
+                .map(move |ext| dir.join(format!("{name}{ext}")))
+        })
+        .find(|candidate| candidate.is_file())
+}
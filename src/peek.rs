@@ -0,0 +1,259 @@
+//! A look at where `cd` is going: while the command line is `cd` and a
+//! path, what's in the directory it names, or the entries its last part
+//! begins, shown once typing pauses. Nothing is made of it but the popup,
+//! and it goes as soon as the command isn't a `cd`.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    Frame,
+    layout::Rect,
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Clear, Paragraph},
+};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{Model, shell};
+
+/// How long typing has to pause before the directory is read.
+const PAUSE: Duration = Duration::from_millis(150);
+/// Entries shown at most.
+const ENTRIES: usize = 10;
+
+#[derive(Debug, Default)]
+pub struct Peek {
+    /// The command the listing is for.
+    typed: String,
+    /// When it last changed, if the directory hasn't been read since.
+    edited: Option<Instant>,
+    shown: Option<Listing>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Listing {
+    dir: String,
+    lines: Vec<String>,
+}
+
+/// The directory `cd` would list for `arg`, and the start of the names
+/// to show from it: all of a directory, or what a partial name begins.
+fn target(arg: &str) -> (PathBuf, String) {
+    let path = Path::new(arg);
+    if arg.ends_with(['/', std::path::MAIN_SEPARATOR]) || path.is_dir() {
+        return (path.to_path_buf(), String::new());
+    }
+    let parent = match path.parent() {
+        Some(parent) if parent != Path::new("") => parent.to_path_buf(),
+        _ => PathBuf::from("."),
+    };
+    let prefix = path.file_name().unwrap_or_default().to_string_lossy();
+    (parent, prefix.into_owned())
+}
+
+/// Names and whether each is a directory, as lines: directories first
+/// with a `/`, hidden ones only if `prefix` asks for them, and a count of
+/// the rest past `ENTRIES`.
+fn lines(mut entries: Vec<(String, bool)>, prefix: &str) -> Vec<String> {
+    entries.retain(|(name, _)| {
+        name.starts_with(prefix) && (prefix.starts_with('.') || !name.starts_with('.'))
+    });
+    entries.sort_by_cached_key(|(name, dir)| (!dir, name.to_lowercase()));
+    let mut lines: Vec<String> = entries
+        .iter()
+        .take(ENTRIES)
+        .map(|(name, dir)| match dir {
+            true => format!("{name}/"),
+            false => name.clone(),
+        })
+        .collect();
+    match entries.len().checked_sub(ENTRIES) {
+        Some(more @ 1..) => lines.push(format!("… {more} more")),
+        _ if lines.is_empty() && prefix.is_empty() => lines.push("(empty)".into()),
+        _ if lines.is_empty() => lines.push(format!("(nothing starting {prefix})")),
+        _ => {}
+    }
+    lines
+}
+
+fn read(dir: &Path, prefix: &str) -> Listing {
+    let shown = match dir == Path::new(".") {
+        true => "./".to_string(),
+        false => dir.display().to_string(),
+    };
+    let lines = match fs::read_dir(dir) {
+        Ok(entries) => {
+            let entries = entries
+                .flatten()
+                .map(|e| {
+                    let dir = e.path().is_dir();
+                    (e.file_name().to_string_lossy().into_owned(), dir)
+                })
+                .collect();
+            self::lines(entries, prefix)
+        }
+        Err(e) => vec![e.to_string()],
+    };
+    Listing { dir: shown, lines }
+}
+
+impl Model {
+    /// The directory a one-line `cd` command would list, if the command
+    /// line is one.
+    fn cd_target(&self, command: &str) -> Option<(PathBuf, String)> {
+        if command.contains('\n') {
+            return None;
+        }
+        let words = shell::words(command, |name| self.var(name));
+        match words.as_slice() {
+            [Some(cd)] if cd == "cd" => {
+                let home = shell::home(|name| self.var(name))?;
+                Some((PathBuf::from(home), String::new()))
+            }
+            [Some(cd), Some(arg)] if cd == "cd" && !arg.starts_with('-') => Some(target(arg)),
+            _ => None,
+        }
+    }
+
+    /// Before each draw: note an edit, and drop the listing once the
+    /// command isn't a `cd`.
+    pub fn recheck_cd(&mut self) {
+        if self.command() == self.peek.typed {
+            return;
+        }
+        self.peek.typed = self.command().to_string();
+        match self.cd_target(&self.peek.typed) {
+            Some(_) => self.peek.edited = Some(Instant::now()),
+            None => {
+                self.peek = Peek {
+                    typed: std::mem::take(&mut self.peek.typed),
+                    ..Default::default()
+                }
+            }
+        }
+    }
+
+    /// When the directory is to be read, if the command has changed.
+    pub fn cd_due(&self) -> Option<Instant> {
+        self.peek.edited.map(|edited| edited + PAUSE)
+    }
+
+    /// Once typing has paused: list where `cd` is going.
+    pub fn peek_cd(&mut self) {
+        if self.peek.edited.is_none_or(|e| e.elapsed() < PAUSE) {
+            return;
+        }
+        self.peek.edited = None;
+        self.peek.shown = self
+            .cd_target(&self.peek.typed)
+            .map(|(dir, prefix)| read(&dir, &prefix));
+    }
+
+    /// Drop the listing, as the command has gone.
+    pub fn clear_peek(&mut self) {
+        self.peek = Peek::default();
+    }
+}
+
+/// The listing in a box just above the command line.
+pub fn render(model: &Model, frame: &mut Frame, command_line: Rect) {
+    let Some(listing) = &model.peek.shown else {
+        return;
+    };
+    let title = format!(" {} ", listing.dir);
+    let widest = listing.lines.iter().map(|l| l.width()).max().unwrap_or(0);
+    let screen = frame.area();
+    let width = (widest.max(title.width()) as u16 + 2).min(screen.width);
+    let room = command_line.y.saturating_sub(screen.y + 2);
+    let height = (listing.lines.len() as u16).min(room) + 2;
+    let area = Rect {
+        x: command_line.x,
+        y: command_line.y.saturating_sub(height),
+        width,
+        height,
+    };
+    let lines: Vec<Line> = listing
+        .lines
+        .iter()
+        .map(|l| match l.ends_with('/') {
+            true => Line::raw(l.as_str()).bold(),
+            false => Line::raw(l.as_str()),
+        })
+        .collect();
+    frame.render_widget(Clear, area);
+    frame.render_widget(
+        Paragraph::new(lines).block(Block::bordered().title(title).dim()),
+        area,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entries(names: &[&str]) -> Vec<(String, bool)> {
+        names
+            .iter()
+            .map(|n| match n.strip_suffix('/') {
+                Some(dir) => (dir.to_string(), true),
+                None => (n.to_string(), false),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn directories_come_first_and_hidden_ones_only_when_asked() {
+        let found = entries(&["b.txt", "src/", ".git/", "A.md", "docs/", ".env"]);
+        assert_eq!(lines(found.clone(), ""), ["docs/", "src/", "A.md", "b.txt"]);
+        assert_eq!(lines(found.clone(), "."), [".git/", ".env"]);
+        assert_eq!(lines(found.clone(), "s"), ["src/"]);
+        assert_eq!(lines(found, "x"), ["(nothing starting x)"]);
+        assert_eq!(lines(Vec::new(), ""), ["(empty)"]);
+        let many: Vec<String> = (0..13).map(|i| format!("d{i:02}/")).collect();
+        let many = lines(
+            entries(&many.iter().map(String::as_str).collect::<Vec<_>>()),
+            "",
+        );
+        assert_eq!(many.len(), ENTRIES + 1);
+        assert_eq!(many.last().unwrap(), "… 3 more");
+    }
+
+    #[test]
+    fn a_partial_name_lists_its_parent() {
+        assert_eq!(target("src/ma"), (PathBuf::from("src"), "ma".into()));
+        assert_eq!(target("sr"), (PathBuf::from("."), "sr".into()));
+        assert_eq!(
+            target("nowhere/"),
+            (PathBuf::from("nowhere/"), String::new())
+        );
+    }
+
+    #[test]
+    fn the_listing_waits_for_a_pause_and_goes_with_the_cd() {
+        let mut model = Model {
+            current_command: "cd sr".into(),
+            ..Default::default()
+        };
+        model.recheck_cd();
+        model.peek_cd();
+        assert_eq!(model.peek.shown, None);
+        model.peek.edited = Some(Instant::now() - PAUSE);
+        model.peek_cd();
+        let listing = model.peek.shown.as_ref().unwrap();
+        assert_eq!(
+            (listing.dir.as_str(), &listing.lines[..]),
+            ("./", &["src/".to_string()][..])
+        );
+        model.current_command = "cd src ".into();
+        model.recheck_cd();
+        assert!(model.peek.shown.is_some());
+        model.current_command = "cp src ".into();
+        model.recheck_cd();
+        assert_eq!(model.peek.shown, None);
+        assert_eq!(model.cd_due(), None);
+    }
+}
@@ -0,0 +1,323 @@
+use std::cmp::Reverse;
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Flex, Layout, Rect},
+    style::{Modifier, Style, Stylize},
+    text::{Line, Span},
+    widgets::{Block, Clear, List, ListState},
+};
+
+use crate::fuzzy;
+
+/// What choosing an entry in a picker does.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PickerKind {
+    /// cd to the chosen directory
+    Directory,
+    /// fill in the chosen snippet
+    Snippet,
+    /// rearrange queued commands; choosing resumes the queue
+    Queue,
+    /// put the chosen past command on the command line
+    History,
+    /// paste the chosen register into the command
+    Register,
+    /// signal running jobs; choosing views the job's buffer
+    Jobs,
+    /// put the chosen paths on the command line
+    Files,
+    /// go to the chosen bookmarked line
+    Bookmarks,
+    /// view the chosen buffer; see `buffers`
+    Buffers,
+    /// open the chosen URL; see `link`
+    Links,
+    /// put the chosen argument in at the cursor; see `args`
+    Arguments,
+}
+
+/// An item matching the query.
+#[derive(Debug)]
+struct Match {
+    /// Position in `items`.
+    index: usize,
+    score: i32,
+    /// Characters of the item the query's characters matched.
+    positions: Vec<usize>,
+}
+
+/// A popup list narrowed down by typing, best matches first. Items that
+/// match equally well keep their order.
+#[derive(Debug)]
+pub struct Picker {
+    pub kind: PickerKind,
+    /// Changed with `extend` and `set_items`, to keep the matches right.
+    pub items: Vec<String>,
+    /// Changed with `push`, `pop` and `set_query`.
+    pub query: String,
+    pub selected: usize,
+    /// Where the items came from, shown after the title.
+    pub source: Option<&'static str>,
+    /// Items marked with Tab to choose together, by position in `items`.
+    pub marked: Vec<usize>,
+    /// Matches keep the items' order rather than going best first.
+    pub ordered: bool,
+    /// How many items the list was cut to, so rebuilding it cuts it again.
+    pub limit: Option<usize>,
+    matched: Vec<Match>,
+}
+
+impl Picker {
+    pub fn new(kind: PickerKind, items: Vec<String>) -> Self {
+        let mut picker = Picker {
+            kind,
+            items: Vec::new(),
+            query: String::new(),
+            selected: 0,
+            source: None,
+            marked: Vec::new(),
+            ordered: false,
+            limit: None,
+            matched: Vec::new(),
+        };
+        picker.set_items(items);
+        picker
+    }
+
+    /// Score `items[indices]` against the query, keeping those that match.
+    fn rank(&self, indices: impl Iterator<Item = usize>) -> Vec<Match> {
+        indices
+            .filter_map(|index| {
+                let (score, positions) = fuzzy::score(&self.query, &self.items[index])?;
+                Some(Match {
+                    index,
+                    score,
+                    positions,
+                })
+            })
+            .collect()
+    }
+
+    fn sort(&mut self) {
+        match self.ordered {
+            true => self.matched.sort_unstable_by_key(|m| m.index),
+            false => self
+                .matched
+                .sort_unstable_by_key(|m| (Reverse(m.score), m.index)),
+        }
+    }
+
+    /// Replace the items, matching them all again.
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.items = items;
+        self.matched = self.rank(0..self.items.len());
+        self.sort();
+        self.clamp_selected();
+    }
+
+    /// Add items, e.g. as they're found.
+    pub fn extend(&mut self, items: impl IntoIterator<Item = String>) {
+        let start = self.items.len();
+        self.items.extend(items);
+        let new = self.rank(start..self.items.len());
+        self.matched.extend(new);
+        self.sort();
+    }
+
+    pub fn set_query(&mut self, query: String) {
+        self.query = query;
+        self.matched = self.rank(0..self.items.len());
+        self.sort();
+        self.selected = 0;
+    }
+
+    fn clamp_selected(&mut self) {
+        self.selected = self.selected.min(self.matched.len().saturating_sub(1));
+    }
+
+    pub fn selection(&self) -> Option<&str> {
+        Some(&self.items[self.selected_index()?])
+    }
+
+    /// Position in `items` of the selected entry.
+    pub fn selected_index(&self) -> Option<usize> {
+        self.matched.get(self.selected).map(|m| m.index)
+    }
+
+    /// Select `items[index]`, if it matches the query.
+    pub fn select_index(&mut self, index: usize) {
+        if let Some(at) = self.matched.iter().position(|m| m.index == index) {
+            self.selected = at;
+        }
+    }
+
+    pub fn down(&mut self) {
+        self.selected = (self.selected + 1).min(self.matched.len().saturating_sub(1));
+    }
+
+    pub fn up(&mut self) {
+        self.selected = self.selected.saturating_sub(1);
+    }
+
+    /// Mark the selected entry, or unmark it, and move down.
+    pub fn toggle_mark(&mut self) {
+        let Some(index) = self.selected_index() else {
+            return;
+        };
+        match self.marked.iter().position(|&i| i == index) {
+            Some(at) => {
+                self.marked.remove(at);
+            }
+            None => self.marked.push(index),
+        }
+        self.down();
+    }
+
+    /// The marked entries in the order they were marked, or else the
+    /// selected one.
+    pub fn chosen(&self) -> Vec<&str> {
+        match self.marked.is_empty() {
+            true => self.selection().into_iter().collect(),
+            false => self
+                .marked
+                .iter()
+                .map(|&i| self.items[i].as_str())
+                .collect(),
+        }
+    }
+
+    /// Type `c` into the query. Only what matched before can match now.
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        let before = std::mem::take(&mut self.matched);
+        self.matched = self.rank(before.into_iter().map(|m| m.index));
+        self.sort();
+        self.selected = 0;
+    }
+
+    pub fn pop(&mut self) {
+        let mut query = std::mem::take(&mut self.query);
+        query.pop();
+        self.set_query(query);
+    }
+
+    fn title(&self) -> &str {
+        match self.kind {
+            PickerKind::Directory => "cd",
+            PickerKind::Snippet => "snippet",
+            PickerKind::History => "history",
+            PickerKind::Register => "registers",
+            PickerKind::Queue => "queue (enter resumes, ^j/^k move, ^x removes)",
+            PickerKind::Jobs => "jobs (enter views, ^x terminates, ^f toggles follow)",
+            PickerKind::Files => "files (tab marks)",
+            PickerKind::Bookmarks => "bookmarks",
+            PickerKind::Buffers => "buffers (tab opens a group, ^f flat)",
+            PickerKind::Links => "links (a letter opens one)",
+            PickerKind::Arguments => "arguments",
+        }
+    }
+}
+
+/// `item` with the characters at `positions` picked out.
+fn highlighted<'a>(prefix: &'a str, item: &'a str, positions: &[usize]) -> Line<'a> {
+    let mut spans = vec![Span::raw(prefix)];
+    let mut positions = positions.iter().peekable();
+    for (i, c) in item.chars().enumerate() {
+        let matched = positions.next_if_eq(&&i).is_some();
+        let span = Span::raw(c.to_string());
+        spans.push(match matched {
+            true => span.yellow().bold(),
+            false => span,
+        });
+    }
+    Line::from(spans)
+}
+
+pub fn render(picker: &Picker, frame: &mut Frame, area: Rect) {
+    let matched = &picker.matched;
+    // half the pane, but never less than a line between the borders
+    // unless the pane itself is smaller
+    let rows = matched.len().min(usize::from(area.height)) as u16 + 2;
+    let height = rows.min(area.height / 2).max(3).min(area.height);
+    let [area] = Layout::vertical([Constraint::Length(height)])
+        .flex(Flex::Center)
+        .areas(area);
+    let [area] = Layout::horizontal([Constraint::Percentage(60)])
+        .flex(Flex::Center)
+        .areas(area);
+    let title = match picker.source {
+        Some(source) => format!("{} ({source})", picker.title()),
+        None => picker.title().to_string(),
+    };
+    // only what can be on screen is drawn, however many match
+    let shown = picker.selected + usize::from(area.height);
+    let items: Vec<Line> = matched
+        .iter()
+        .take(shown)
+        .map(|m| {
+            let prefix = match (picker.marked.is_empty(), picker.marked.contains(&m.index)) {
+                (true, _) => "",
+                (false, true) => "+ ",
+                (false, false) => "  ",
+            };
+            highlighted(prefix, &picker.items[m.index], &m.positions)
+        })
+        .collect();
+    let list = List::new(items)
+        .block(Block::bordered().title(format!("{title}: {}", picker.query)))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let mut state = ListState::default().with_selected(Some(picker.selected));
+    frame.render_widget(Clear, area);
+    frame.render_stateful_widget(list, area, &mut state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn shown(picker: &Picker) -> Vec<&str> {
+        picker
+            .matched
+            .iter()
+            .map(|m| picker.items[m.index].as_str())
+            .collect()
+    }
+
+    #[test]
+    fn best_matches_come_first_as_the_query_is_typed() {
+        let items = ["cargo clippy", "git log", "git clone", "gcc -o lol"];
+        let mut picker = Picker::new(PickerKind::History, items.map(String::from).to_vec());
+        assert_eq!(shown(&picker), items);
+        picker.push('g');
+        picker.push('l');
+        // a word start beats the middle of a word
+        assert_eq!(
+            shown(&picker),
+            ["git log", "gcc -o lol", "git clone", "cargo clippy"]
+        );
+        assert_eq!(picker.selection(), Some("git log"));
+        picker.push('o');
+        picker.extend(["glow".to_string()]);
+        assert_eq!(
+            shown(&picker),
+            ["glow", "git log", "gcc -o lol", "git clone"]
+        );
+        picker.pop();
+        picker.pop();
+        assert_eq!(shown(&picker)[0], "git log");
+        assert_eq!(shown(&picker).len(), 5);
+    }
+
+    #[test]
+    fn a_short_pane_still_draws() {
+        let picker = Picker::new(PickerKind::History, vec!["ls".into(), "pwd".into()]);
+        for rows in 0..8 {
+            let backend = ratatui::backend::TestBackend::new(40, rows);
+            let mut terminal = ratatui::Terminal::new(backend).unwrap();
+            terminal
+                .draw(|frame| render(&picker, frame, frame.area()))
+                .unwrap();
+        }
+    }
+}
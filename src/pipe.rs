@@ -0,0 +1,302 @@
+//! `:pipe CMD`: show the viewed buffer run through a filter such as `jq .`
+//! or `column -t`, keeping the output as it was to go back to. The filter
+//! runs in the background, and the buffer is shown as it was until it's
+//! done; if the filter is dropped, another asked for or the command run
+//! again meanwhile, what it made is thrown away.
+
+use std::{
+    io::{Read, Write},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicU64, Ordering},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::{Model, Output, event::AppEvent, redact};
+
+/// How long a filter may take before it's killed.
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+/// The last filter started, to tell their results apart.
+static REQUESTS: AtomicU64 = AtomicU64::new(0);
+
+/// A filter applied to a buffer.
+#[derive(Debug, Clone)]
+pub struct Pipe {
+    pub command: String,
+    /// The filtered output.
+    text: String,
+    /// Showing the filtered output rather than the original.
+    pub shown: bool,
+}
+
+impl Output {
+    /// The filtered output, if a filter is on and shown.
+    pub fn piped_text(&self) -> Option<&str> {
+        self.pipe
+            .as_ref()
+            .filter(|p| p.shown)
+            .map(|p| p.text.as_str())
+    }
+
+    pub fn pipe_size(&self) -> usize {
+        self.pipe.as_ref().map_or(0, |p| p.text.len())
+    }
+}
+
+/// Run `command` with `input` on stdin, returning its stdout, or what went
+/// wrong: its first line of stderr when it fails.
+pub fn filter(mut command: Command, input: String) -> Result<String, String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|e| e.to_string())?;
+    let (Some(mut stdin), Some(mut stdout), Some(mut stderr)) =
+        (child.stdin.take(), child.stdout.take(), child.stderr.take())
+    else {
+        return Err("no pipes to the filter".into());
+    };
+    // all alongside, so neither side can fill a pipe and stall the other
+    thread::spawn(move || stdin.write_all(input.as_bytes()));
+    let reader = thread::spawn(move || {
+        let mut text = Vec::new();
+        stdout.read_to_end(&mut text).map(|_| text)
+    });
+    let errors = thread::spawn(move || {
+        let mut text = Vec::new();
+        let _ = stderr.read_to_end(&mut text);
+        text
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(format!("took over {}s", TIMEOUT.as_secs()));
+            }
+        }
+    };
+    let text = reader.join().ok().and_then(Result::ok).unwrap_or_default();
+    let errors = errors.join().unwrap_or_default();
+    if !status.success() {
+        let errors = String::from_utf8_lossy(&errors);
+        let error = errors.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+        let exit = status
+            .code()
+            .map_or("killed".into(), |s| format!("exit {s}"));
+        return Err(match error.trim() {
+            "" => exit,
+            error => format!("{exit}: {error}"),
+        });
+    }
+    Ok(String::from_utf8_lossy(&text).into_owned())
+}
+
+impl Model {
+    /// `:pipe [CMD]` and `:pipe!`, returning what to show on the status line.
+    pub fn pipe(&mut self, args: &str, remove: bool) -> String {
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return "no buffer".into();
+        };
+        if args.is_empty() || remove {
+            output.line_index.changed();
+            output.scroll = (0, 0);
+            output.scroll_target = None;
+        }
+        if remove {
+            let running = output.piping.take().is_some();
+            return match output.pipe.take() {
+                Some(pipe) => format!("dropped {}", pipe.command),
+                None if running => "dropped the filter still running".into(),
+                None => "no filter to drop".into(),
+            };
+        }
+        if args.is_empty() {
+            return match output.pipe.as_mut() {
+                Some(pipe) => {
+                    pipe.shown = !pipe.shown;
+                    let state = if pipe.shown { "showing" } else { "hiding" };
+                    format!("{state} {}", pipe.command)
+                }
+                None => "usage: :pipe CMD".into(),
+            };
+        }
+        let index = self.viewing_output;
+        match self.apply_pipe(index, args) {
+            Ok(Some(status)) => status,
+            Ok(None) => format!("piping through {args}"),
+            Err(e) => e,
+        }
+    }
+
+    /// Run buffer `index` through `command`, in the background, to be
+    /// shown once it's done. Without a way to hear back it's run now, and
+    /// what to show on the status line is given.
+    fn apply_pipe(&mut self, index: usize, command: &str) -> Result<Option<String>, String> {
+        let Some(output) = self.outputs.get(index) else {
+            return Ok(None);
+        };
+        let (id, input) = (output.id, output.stdout_text().to_string());
+        let parsed = self.parse(command).ok_or("nothing to pipe through")?;
+        let program = parsed.program.clone();
+        let process = self.process(parsed);
+        let command = command.to_string();
+        let request = REQUESTS.fetch_add(1, Ordering::Relaxed) + 1;
+        self.outputs[index].piping = Some(request);
+        let Some(events) = self.events.clone() else {
+            let result = filter(process, input).map_err(|e| format!("pipe: {program}: {e}"));
+            return Ok(self.piped(id, request, command, result));
+        };
+        thread::spawn(move || {
+            let result = filter(process, input).map_err(|e| format!("pipe: {program}: {e}"));
+            let _ = events.send(AppEvent::Piped {
+                output: id,
+                request,
+                command,
+                result,
+            });
+        });
+        Ok(None)
+    }
+
+    /// Show what filter `command` made of buffer `id` for `request`, if
+    /// it's still wanted, returning what to show on the status line. If it
+    /// failed the buffer is left as it was.
+    pub fn piped(
+        &mut self,
+        id: usize,
+        request: u64,
+        command: String,
+        result: Result<String, String>,
+    ) -> Option<String> {
+        let index = self.outputs.iter().position(|o| o.id == id)?;
+        let output = &mut self.outputs[index];
+        if output.piping != Some(request) {
+            return None;
+        }
+        output.piping = None;
+        let text = match result {
+            Ok(text) => text,
+            Err(e) => return Some(e),
+        };
+        // its input was masked, but it may have decoded something
+        let text: String = text
+            .split_inclusive('\n')
+            .map(|line| redact::line(&self.config.redact, line))
+            .collect();
+        let status = format!("piped through {command}");
+        let output = &mut self.outputs[index];
+        output.pipe = Some(Pipe {
+            command,
+            text,
+            shown: true,
+        });
+        output.line_index.changed();
+        output.scroll = (0, 0);
+        output.scroll_target = None;
+        self.detect_table(index);
+        Some(status)
+    }
+
+    /// Once buffer `id`'s command ends, filter it again: through its own
+    /// filter, or with `repipe` set, that of the last run of the command.
+    pub fn repipe(&mut self, id: usize) {
+        let Some(index) = self.outputs.iter().position(|o| o.id == id) else {
+            return;
+        };
+        let output = &self.outputs[index];
+        let command = match &output.pipe {
+            Some(pipe) => pipe.command.clone(),
+            None if self.config.repipe => {
+                let earlier = self.outputs[..index]
+                    .iter()
+                    .rev()
+                    .find(|o| o.command == output.command)
+                    .and_then(|o| o.pipe.as_ref());
+                match earlier {
+                    Some(pipe) => pipe.command.clone(),
+                    None => return,
+                }
+            }
+            None => return,
+        };
+        match self.apply_pipe(index, &command) {
+            Ok(Some(status)) | Err(status) => self.status = Some(status),
+            Ok(None) => {}
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::mpsc::Receiver;
+
+    use super::*;
+    use crate::Message;
+
+    fn wait(received: &Receiver<AppEvent>) -> Message {
+        match received.recv_timeout(TIMEOUT) {
+            Ok(AppEvent::Piped {
+                output,
+                request,
+                command,
+                result,
+            }) => Message::Piped(output, request, command, result),
+            _ => panic!("no result from the filter"),
+        }
+    }
+
+    #[test]
+    fn a_filter_runs_in_the_background() {
+        let (events, received) = std::sync::mpsc::channel();
+        let mut model = Model {
+            events: Some(events),
+            ..Default::default()
+        };
+        model.push_output("ls".into(), "b\na\n".into());
+        assert_eq!(model.pipe("sort", false), "piping through sort");
+        assert_eq!(model.outputs[0].shown_text(), "b\na\n");
+        crate::update(&mut model, wait(&received));
+        assert_eq!(model.status.as_deref(), Some("piped through sort"));
+        assert_eq!(model.outputs[0].shown_text(), "a\nb\n");
+
+        // one that fails leaves it as it was
+        model.pipe("", true);
+        model.pipe("false", false);
+        crate::update(&mut model, wait(&received));
+        assert_eq!(model.status.as_deref(), Some("pipe: false: exit 1"));
+        assert_eq!(model.outputs[0].shown_text(), "b\na\n");
+    }
+
+    #[test]
+    fn a_filter_no_longer_wanted_is_thrown_away() {
+        let (events, received) = std::sync::mpsc::channel();
+        let mut model = Model {
+            events: Some(events),
+            ..Default::default()
+        };
+        model.push_output("ls".into(), "b\na\n".into());
+        model.status = None;
+        // dropped while it runs
+        model.pipe("sort", false);
+        assert_eq!(model.pipe("", true), "dropped the filter still running");
+        crate::update(&mut model, wait(&received));
+        assert!(model.outputs[0].pipe.is_none());
+        assert_eq!(model.status, None);
+
+        // another asked for: only the last counts, whichever ends first
+        model.pipe("sort", false);
+        let sorted = wait(&received);
+        model.pipe("tac", false);
+        let reversed = wait(&received);
+        crate::update(&mut model, reversed);
+        crate::update(&mut model, sorted);
+        assert_eq!(model.outputs[0].shown_text(), "a\nb\n");
+        assert_eq!(model.outputs[0].pipe.as_ref().unwrap().command, "tac");
+    }
+}
@@ -0,0 +1,58 @@
+//! Pipelines (`a | b | c`) are run by shim itself, each stage's output
+//! going into the next's input. How every stage ended is kept, so a
+//! failure partway along isn't hidden by the last stage succeeding.
+
+use crate::{Model, Output, shell, vars};
+
+impl Output {
+    /// The first stage to fail, for the title.
+    pub fn stage_note(&self) -> Option<String> {
+        let (i, (_, code)) = self
+            .stages
+            .iter()
+            .enumerate()
+            .find(|(_, (_, code))| *code != 0)?;
+        Some(format!("stage {} exited {code}", i + 1))
+    }
+}
+
+impl Model {
+    /// Note how each stage of buffer `job_id`'s pipeline ended, with every
+    /// stage's exit code on the status line if one failed. Returns the
+    /// status to record: the last stage's, or with `pipefail` the first
+    /// failure's.
+    pub fn finish_stages(
+        &mut self,
+        job_id: usize,
+        codes: Vec<i32>,
+        status: Option<i32>,
+    ) -> Option<i32> {
+        let Some(output) = self.outputs.iter_mut().find(|o| o.id == job_id) else {
+            return status;
+        };
+        let line =
+            vars::split_capture(&output.command).map_or(output.command.as_str(), |(_, line)| line);
+        let stages = shell::pipeline(line).into_iter().map(String::from);
+        output.stages = match codes.is_empty() {
+            true => Vec::new(),
+            false => stages.zip(codes).collect(),
+        };
+        let failure = output
+            .stages
+            .iter()
+            .find(|(_, code)| *code != 0)
+            .map(|(_, code)| *code);
+        if failure.is_some() {
+            let breakdown: Vec<_> = output
+                .stages
+                .iter()
+                .map(|(stage, code)| format!("{stage} → {code}"))
+                .collect();
+            self.status = Some(breakdown.join(" | "));
+        }
+        match failure {
+            Some(code) if self.config.pipefail => Some(code),
+            _ => status,
+        }
+    }
+}
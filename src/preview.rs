@@ -0,0 +1,350 @@
+//! A live look at a pipeline being built on the viewed buffer. With the
+//! preview on (`:preview`, or `pipe_preview`), a command line that is the
+//! viewed buffer's command followed by `|` and more stages has just those
+//! stages run on the buffer's stored output once typing pauses, never the
+//! command itself, and what they make is shown in the lower half of the
+//! output pane, tabs and all as the buffer would show it. A stage that
+//! fails shows its error there instead, and the buffer is left alone. A
+//! command line that would be confirmed before it ran (`dangerous`) isn't
+//! previewed, nor made a buffer of: Enter runs it, asking first.
+//! Enter makes the preview a buffer of its own, as `:sort` does, rather
+//! than running the whole pipeline again. `:preview STAGES` puts the
+//! viewed buffer's command and `| STAGES` on the command line to preview
+//! it and what it's edited to, on or off.
+
+use std::{
+    process::Command,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    style::Stylize,
+    widgets::{Block, Clear, Paragraph},
+};
+
+use crate::{Model, display, event::AppEvent, pipe, redact, shell};
+
+/// How long typing has to pause before the stages are run.
+const PAUSE: Duration = Duration::from_millis(300);
+
+/// Shown instead of a preview of a command line that's to be confirmed.
+const NEEDS_CONFIRMATION: &str = "not previewed: needs confirmation";
+
+#[derive(Debug, Default)]
+pub struct Preview {
+    /// Previews follow the command line as it's edited.
+    pub live: bool,
+    /// `:preview STAGES` asked for one, until the command line isn't one
+    /// to preview.
+    asked: bool,
+    /// The command line as last seen.
+    typed: String,
+    /// When it last changed, if the stages haven't run since.
+    edited: Option<Instant>,
+    shown: Option<Shown>,
+}
+
+#[derive(Debug)]
+struct Shown {
+    /// The command line it's for.
+    typed: String,
+    stages: String,
+    /// `None` while the stages run.
+    result: Option<Result<String, String>>,
+}
+
+/// The stages `typed` adds to `command`, if it's `command | ...`.
+fn added_stages<'a>(typed: &'a str, command: &str) -> Option<&'a str> {
+    let command = command.trim();
+    let rest = typed.trim().strip_prefix(command)?.trim_start();
+    let stages = rest.strip_prefix('|')?;
+    if command.is_empty() || stages.starts_with('|') || stages.trim().is_empty() {
+        return None;
+    }
+    Some(stages.trim())
+}
+
+/// Run `input` through each stage in turn.
+fn run(stages: Vec<(String, Command)>, input: String) -> Result<String, String> {
+    stages
+        .into_iter()
+        .try_fold(input, |text, (stage, command)| {
+            pipe::filter(command, text).map_err(|e| format!("{stage}: {e}"))
+        })
+}
+
+impl Model {
+    /// The buffer `typed` adds stages to, the stages, and whether the line
+    /// needs confirming so isn't to be run, if it's to be previewed.
+    fn preview_target(&self, typed: &str) -> Option<(usize, String, bool)> {
+        let preview = &self.preview;
+        if !preview.live && !preview.asked {
+            return None;
+        }
+        let output = self.outputs.get(self.viewing_output)?;
+        let stages = added_stages(typed, &output.command)?;
+        let dangerous = self.is_dangerous(typed)
+            || shell::pipeline(stages)
+                .into_iter()
+                .any(|stage| self.is_dangerous(stage));
+        Some((self.viewing_output, stages.to_string(), dangerous))
+    }
+
+    /// The stages of `stages` ready to run, or what's wrong with one.
+    fn preview_commands(&self, stages: &str) -> Result<Vec<(String, Command)>, String> {
+        shell::pipeline(stages)
+            .into_iter()
+            .map(|stage| match self.parse(stage) {
+                Some(parsed) => Ok((stage.to_string(), self.process(parsed))),
+                None => Err(format!("{stage}: nothing to run")),
+            })
+            .collect()
+    }
+
+    /// Before each draw: note an edit, and drop the preview once the
+    /// command line isn't one to preview.
+    pub fn recheck_preview(&mut self) {
+        if self.command() == self.preview.typed {
+            return;
+        }
+        self.preview.typed = self.command().to_string();
+        match self.preview_target(&self.preview.typed) {
+            Some(_) => self.preview.edited = Some(Instant::now()),
+            None => {
+                let preview = &mut self.preview;
+                preview.edited = None;
+                preview.shown = None;
+                preview.asked = false;
+            }
+        }
+    }
+
+    /// When the stages are to be run, if the command has changed.
+    pub fn preview_due(&self) -> Option<Instant> {
+        self.preview.edited.map(|edited| edited + PAUSE)
+    }
+
+    /// Once typing has paused: run the stages on the buffer, in the
+    /// background.
+    pub fn run_preview(&mut self) {
+        if self.preview.edited.is_none_or(|e| e.elapsed() < PAUSE) {
+            return;
+        }
+        self.preview.edited = None;
+        let typed = self.preview.typed.clone();
+        let Some((index, stages, dangerous)) = self.preview_target(&typed) else {
+            return;
+        };
+        if dangerous {
+            self.preview.shown = Some(Shown {
+                typed,
+                stages,
+                result: Some(Err(NEEDS_CONFIRMATION.into())),
+            });
+            return;
+        }
+        let commands = self.preview_commands(&stages);
+        let input = self.outputs[index].stdout_text().to_string();
+        self.preview.shown = Some(Shown {
+            typed: typed.clone(),
+            stages,
+            result: None,
+        });
+        let Some(events) = self.events.clone() else {
+            self.show_preview(typed, commands.and_then(|c| run(c, input)));
+            return;
+        };
+        thread::spawn(move || {
+            let result = commands.and_then(|c| run(c, input));
+            let _ = events.send(AppEvent::Previewed { typed, result });
+        });
+    }
+
+    /// What the stages made of command line `typed`, if it's still there.
+    pub fn show_preview(&mut self, typed: String, result: Result<String, String>) {
+        let Some(shown) = self.preview.shown.as_mut().filter(|s| s.typed == typed) else {
+            return;
+        };
+        // as with :pipe, its input was masked but it may have decoded something
+        let redacted = result.map(|text| {
+            text.split_inclusive('\n')
+                .map(|line| redact::line(&self.config.redact, line))
+                .collect()
+        });
+        shown.result = Some(redacted);
+    }
+
+    /// `:preview` turns previews on or off; `:preview STAGES` previews the
+    /// viewed buffer through STAGES.
+    pub fn preview(&mut self, stages: &str) -> String {
+        if stages.is_empty() {
+            self.preview.live = !self.preview.live;
+            return match self.preview.live {
+                true => "pipe preview on".into(),
+                false => "pipe preview off".into(),
+            };
+        }
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return "no buffer to preview".into();
+        };
+        let typed = format!("{} | {stages}", output.command.trim());
+        self.current_command = typed;
+        self.cursor = self.command_end();
+        self.preview.asked = true;
+        String::new()
+    }
+
+    /// Enter on a previewed command line: make a buffer of the preview,
+    /// working it out now if it's not been yet. Returns whether it was
+    /// one; a line that needs confirming isn't, to be run once confirmed.
+    /// If the stages failed, the command is put back to be fixed.
+    pub fn materialize_preview(&mut self, typed: &str) -> bool {
+        let Some((index, stages, false)) = self.preview_target(typed) else {
+            return false;
+        };
+        let shown = self.preview.shown.take().filter(|s| s.typed == typed);
+        let result = match shown.and_then(|s| s.result) {
+            Some(result) => result,
+            None => {
+                let input = self.outputs[index].stdout_text().to_string();
+                self.preview_commands(&stages)
+                    .and_then(|commands| run(commands, input))
+            }
+        };
+        self.preview = Preview {
+            live: self.preview.live,
+            ..Default::default()
+        };
+        match result {
+            Ok(text) => {
+                let command = format!("{stages} ⟨ {}", self.outputs[index].command);
+                self.push_output(command, text);
+            }
+            Err(e) => {
+                self.status = Some(format!("preview: {e}"));
+                self.current_command = typed.to_string();
+                self.cursor = self.command_end();
+            }
+        }
+        true
+    }
+}
+
+/// The preview over the lower half of the output pane `pane`.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    let Some(shown) = &model.preview.shown else {
+        return;
+    };
+    let inner = pane.inner(Margin::new(1, 1));
+    let height = inner.height / 2;
+    if shown.typed != model.command() || height < 3 {
+        return;
+    }
+    let area = Rect {
+        y: inner.y + inner.height - height,
+        height,
+        ..inner
+    };
+    let title = format!(" preview: {} ", shown.stages);
+    let block = Block::bordered().title(title).dim();
+    let rows = height.saturating_sub(2) as usize;
+    let paragraph = match &shown.result {
+        None => Paragraph::new("running…").dim(),
+        Some(Ok(text)) if text.is_empty() => Paragraph::new("(no output)").dim(),
+        Some(Ok(text)) => Paragraph::new(display::render(text, rows, model.output_settings())),
+        Some(Err(e)) => Paragraph::new(e.as_str()).red(),
+    };
+    frame.render_widget(Clear, area);
+    frame.render_widget(paragraph.block(block), area);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_stages_added_to_the_command_are_previewed() {
+        assert_eq!(
+            added_stages("ps aux | awk '{print $2}'", "ps aux"),
+            Some("awk '{print $2}'")
+        );
+        assert_eq!(
+            added_stages("  ps aux|sort | head ", "ps aux"),
+            Some("sort | head")
+        );
+        assert_eq!(added_stages("ps aux |", "ps aux"), None);
+        assert_eq!(added_stages("ps aux || echo no", "ps aux"), None);
+        assert_eq!(added_stages("ps auxf | head", "ps aux"), None);
+        assert_eq!(added_stages("ls | head", "ps aux"), None);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn stages_run_on_the_buffer_and_enter_keeps_them() {
+        let mut model = Model::default();
+        model.push_output("ps".into(), "b\t1\na\t2\n".into());
+        assert_eq!(
+            crate::builtin::run(&mut model, ":preview"),
+            "pipe preview on"
+        );
+        let preview = |model: &mut Model, typed: &str| {
+            model.current_command = typed.into();
+            model.recheck_preview();
+            model.preview.edited = Some(Instant::now() - PAUSE);
+            model.run_preview();
+        };
+        preview(&mut model, "ps | sort");
+        let shown = model.preview.shown.as_ref().unwrap();
+        assert_eq!(shown.result, Some(Ok("a\t2\nb\t1\n".into())));
+
+        // a stage that fails leaves the buffer be
+        preview(&mut model, "ps | sort | no-such-program-for-shim");
+        let shown = model.preview.shown.as_ref().unwrap();
+        assert!(shown.result.as_ref().unwrap().is_err());
+        assert_eq!(model.outputs[0].stdout, "b\t1\na\t2\n");
+        crate::update(&mut model, crate::Message::Submit);
+        assert!(
+            model
+                .status
+                .as_deref()
+                .unwrap()
+                .starts_with("preview: no-such")
+        );
+        assert_eq!(
+            model.current_command,
+            "ps | sort | no-such-program-for-shim"
+        );
+        assert_eq!(model.outputs.len(), 1);
+
+        // Enter makes a buffer of it, worked out then if need be
+        model.current_command = "ps | sort -r".into();
+        crate::update(&mut model, crate::Message::Submit);
+        assert_eq!(model.outputs.len(), 2);
+        assert_eq!(model.outputs[1].command, "sort -r ⟨ ps");
+        assert_eq!(model.outputs[1].stdout, "b\t1\na\t2\n");
+
+        // not once it's off
+        crate::builtin::run(&mut model, ":preview");
+        preview(&mut model, "sort -r ⟨ ps | head");
+        assert!(model.preview.shown.is_none());
+    }
+
+    #[test]
+    fn a_line_to_confirm_is_not_previewed() {
+        let mut model = Model::default();
+        model.push_output("ls".into(), "a\n".into());
+        crate::builtin::run(&mut model, ":preview");
+        model.current_command = "ls | xargs rm -rf /".into();
+        model.recheck_preview();
+        model.preview.edited = Some(Instant::now() - PAUSE);
+        model.run_preview();
+        let shown = model.preview.shown.as_ref().unwrap();
+        assert_eq!(shown.result, Some(Err(NEEDS_CONFIRMATION.into())));
+        // Enter asks, as for any other line
+        assert!(!model.materialize_preview("ls | xargs rm -rf /"));
+        assert_eq!(model.outputs.len(), 1);
+    }
+}
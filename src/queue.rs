@@ -0,0 +1,191 @@
+use std::collections::VecDeque;
+
+use crate::{
+    Model,
+    picker::{Picker, PickerKind},
+};
+
+/// Commands lined up to run one after another.
+#[derive(Debug, Default)]
+pub struct Queue {
+    /// Waiting to run, next first.
+    pub pending: VecDeque<String>,
+    /// Buffer of the queued command now running.
+    pub current: Option<usize>,
+    /// A command failed, so the rest wait until the queue is resumed.
+    pub stopped: bool,
+    /// Ctrl-C stopped the current command; asking whether to drop the rest.
+    pub asking: bool,
+}
+
+impl Model {
+    pub fn enqueue(&mut self, command: String) -> String {
+        self.queue.pending.push_back(command.clone());
+        self.run_queue();
+        match (self.queue.stopped, self.queue.pending.len()) {
+            (true, waiting) => format!("{waiting} queued, queue stopped (:queue to resume)"),
+            (false, 0) => format!("running {command}"),
+            (false, waiting) => format!("{waiting} queued"),
+        }
+    }
+
+    /// Start queued commands until one is left running.
+    pub fn run_queue(&mut self) {
+        while self.queue.current.is_none() && !self.queue.stopped && !self.queue.asking {
+            let Some(command) = self.queue.pending.pop_front() else {
+                return;
+            };
+            self.refresh_queue_picker();
+            let id = self.next_output_id;
+            self.execute(command);
+            let Some(output) = self.outputs.iter().find(|o| o.id == id) else {
+                continue;
+            };
+            if output.running {
+                self.queue.current = Some(id);
+            } else if output.status.is_some_and(|s| s != 0) {
+                self.stop_queue(id);
+            }
+        }
+    }
+
+    /// Move on once the running queued command `job_id` has exited.
+    pub fn finish_queued(&mut self, job_id: usize, status: Option<i32>) {
+        if self.queue.current != Some(job_id) {
+            return;
+        }
+        self.queue.current = None;
+        if status != Some(0) {
+            self.stop_queue(job_id);
+        }
+        self.run_queue();
+    }
+
+    fn stop_queue(&mut self, failed: usize) {
+        if !self.config.queue_stop_on_failure || self.queue.pending.is_empty() {
+            return;
+        }
+        self.queue.stopped = true;
+        let command = self
+            .outputs
+            .iter()
+            .find(|o| o.id == failed)
+            .map_or("", |o| o.command.as_str());
+        self.status = Some(format!(
+            "queue stopped: {command} failed, {} left (:queue to resume)",
+            self.queue.pending.len()
+        ));
+    }
+
+    /// Kill the running queued command and ask about the rest. Returns
+    /// false if no queued command is running.
+    pub fn interrupt_queue(&mut self) -> bool {
+        // taken now so that its exit doesn't count as a failure
+        let Some(id) = self.queue.current.take() else {
+            return false;
+        };
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) {
+            output.retry = None;
+            output.kill();
+        }
+        self.queue.asking = !self.queue.pending.is_empty();
+        true
+    }
+
+    /// Answer whether to drop the queue after Ctrl-C.
+    pub fn abandon_queue(&mut self, abandon: bool) {
+        self.queue.asking = false;
+        if abandon {
+            let dropped = self.queue.pending.len();
+            self.queue.pending.clear();
+            self.refresh_queue_picker();
+            self.status = Some(format!("dropped {dropped} queued commands"));
+        }
+        self.run_queue();
+    }
+
+    /// Shown at the top of the screen while anything is queued.
+    pub fn queue_summary(&self) -> Option<String> {
+        let waiting = self.queue.pending.len();
+        let running = self
+            .queue
+            .current
+            .and_then(|id| self.outputs.iter().find(|o| o.id == id));
+        match running {
+            Some(output) if waiting > 0 => Some(format!("queue: {} (+{waiting})", output.command)),
+            Some(output) => Some(format!("queue: {}", output.command)),
+            None if waiting == 0 => None,
+            None if self.queue.stopped => Some(format!("queue stopped ({waiting})")),
+            None => Some(format!("queue ({waiting})")),
+        }
+    }
+
+    /// Carry on after the queue stopped on a failure.
+    pub fn resume_queue(&mut self) {
+        self.queue.stopped = false;
+        self.run_queue();
+    }
+
+    pub fn open_queue_picker(&mut self) {
+        let items = self.queue.pending.iter().cloned().collect();
+        self.picker = Some(Picker::new(PickerKind::Queue, items));
+    }
+
+    /// Show the queue as it is now in its picker, if that's open, once
+    /// commands have been started or dropped from it.
+    fn refresh_queue_picker(&mut self) {
+        if let Some(picker) = self.picker.as_mut().filter(|p| p.kind == PickerKind::Queue) {
+            picker.set_items(self.queue.pending.iter().cloned().collect());
+        }
+    }
+
+    /// Move the selected queued command `by` places, or remove it if `by`
+    /// is `None`, keeping the picker open.
+    pub fn edit_queue(&mut self, by: Option<isize>) {
+        let Some(picker) = self.picker.as_mut().filter(|p| p.kind == PickerKind::Queue) else {
+            return;
+        };
+        let pending = &mut self.queue.pending;
+        let Some(from) = picker.selected_index().filter(|&from| from < pending.len()) else {
+            return;
+        };
+        match by {
+            Some(by) => {
+                let to = from.saturating_add_signed(by).min(pending.len() - 1);
+                pending.swap(from, to);
+                picker.set_items(pending.iter().cloned().collect());
+                picker.select_index(to);
+            }
+            None => {
+                pending.remove(from);
+                picker.set_items(pending.iter().cloned().collect());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_picker_keeps_up_with_the_queue() {
+        let mut model = Model::default();
+        model.queue.pending = ["sleep 1", "make"].map(String::from).into();
+        model.queue.stopped = true;
+        model.open_queue_picker();
+        model.picker.as_mut().unwrap().down();
+        model.queue.asking = true;
+        model.abandon_queue(true);
+        assert_eq!(model.picker.as_ref().unwrap().selected_index(), None);
+        model.edit_queue(Some(1));
+        model.edit_queue(None);
+        // gone from under it without the picker being told
+        model.queue.pending = ["make"].map(String::from).into();
+        model.open_queue_picker();
+        model.queue.pending.clear();
+        model.edit_queue(Some(-1));
+        model.edit_queue(None);
+        assert!(model.queue.pending.is_empty());
+    }
+}
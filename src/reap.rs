@@ -0,0 +1,268 @@
+//! What a job holds once started, and letting go of it. Each job has a
+//! thread reading its output pipe, the pipe itself and its processes.
+//! When the job ends its thread is joined and the rest dropped, leaving
+//! only the buffer. A sweep every second joins any thread that ended
+//! after that, and closes the pipe of a job whose processes have all
+//! exited while something they left running in the background still
+//! holds it open, so the job ends rather than waiting on that forever.
+//! `jobs --all` lists what every job still holds.
+
+use std::{
+    fs,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+use crate::{Model, job::Process};
+
+/// Time between sweeps while any job holds anything.
+pub const SWEEP: Duration = Duration::from_secs(1);
+/// How long a job's pipe may stay open after its processes have exited.
+pub const LINGER: Duration = Duration::from_secs(2);
+
+/// What a started job holds until it's reaped.
+#[derive(Debug)]
+pub struct Handles {
+    job_id: usize,
+    children: Vec<Process>,
+    reader: Option<JoinHandle<()>>,
+    /// Tells the reader to close the pipe and stop.
+    close: Arc<AtomicBool>,
+    /// The pipe's file descriptor, where there is one.
+    fd: Option<i32>,
+    /// When all its processes were first seen to have exited.
+    exited: Option<Instant>,
+}
+
+impl Handles {
+    pub fn new(
+        job_id: usize,
+        children: Vec<Process>,
+        reader: JoinHandle<()>,
+        close: Arc<AtomicBool>,
+        fd: Option<i32>,
+    ) -> Self {
+        Handles {
+            job_id,
+            children,
+            reader: Some(reader),
+            close,
+            fd,
+            exited: None,
+        }
+    }
+
+    /// Whether every process has exited. A lock held elsewhere counts as
+    /// running, to be looked at again next sweep.
+    fn all_exited(&self) -> bool {
+        self.children.iter().all(|child| {
+            child
+                .try_lock()
+                .is_ok_and(|mut c| matches!(c.try_wait(), Ok(Some(_)) | Err(_)))
+        })
+    }
+
+    /// Join the reader if it's done.
+    fn join_finished(&mut self) {
+        if self.reader.as_ref().is_some_and(JoinHandle::is_finished)
+            && let Some(reader) = self.reader.take()
+        {
+            let _ = reader.join();
+        }
+    }
+
+    /// What it holds, for `jobs --all`.
+    fn describe(&self) -> String {
+        let pipe = match (self.fd, self.close.load(Ordering::Relaxed)) {
+            (_, true) => "pipe closing".to_string(),
+            (Some(fd), false) => format!("pipe fd {fd}"),
+            (None, false) => "pipe".into(),
+        };
+        let processes = match self.exited {
+            Some(_) => "exited".to_string(),
+            None => format!("{} processes", self.children.len()),
+        };
+        format!("reader thread, {pipe}, {processes}")
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Reaper {
+    live: Vec<Handles>,
+    /// Jobs let go of this session.
+    reaped: usize,
+    swept: Option<Instant>,
+    /// The `jobs` table shows what each job holds.
+    pub all: bool,
+}
+
+impl Reaper {
+    pub fn track(&mut self, handles: Handles) {
+        self.live.push(handles);
+    }
+
+    /// Let go of what's done with.
+    fn collect(&mut self) {
+        for handles in &mut self.live {
+            handles.join_finished();
+        }
+        let before = self.live.len();
+        self.live.retain(|h| h.reader.is_some());
+        self.reaped += before - self.live.len();
+    }
+
+    /// Job `job_id` has ended. Its reader, on its way out, is joined now
+    /// or by the next sweep.
+    pub fn ended(&mut self, job_id: usize) {
+        for handles in self.live.iter_mut().filter(|h| h.job_id == job_id) {
+            handles.exited.get_or_insert_with(Instant::now);
+            handles.close.store(true, Ordering::Relaxed);
+        }
+        self.collect();
+    }
+
+    /// Join finished readers, and close the pipes of jobs that have been
+    /// exited for `LINGER`, returning their ids.
+    pub fn sweep(&mut self, now: Instant) -> Vec<usize> {
+        self.swept = Some(now);
+        self.collect();
+        let mut closed = Vec::new();
+        for handles in &mut self.live {
+            if handles.exited.is_none() && handles.all_exited() {
+                handles.exited = Some(now);
+            }
+            let lingering = handles.exited.is_some_and(|at| now >= at + LINGER);
+            if lingering && !handles.close.swap(true, Ordering::Relaxed) {
+                closed.push(handles.job_id);
+            }
+        }
+        closed
+    }
+
+    /// When the next sweep is due, while anything is held.
+    pub fn due(&self, now: Instant) -> Option<Instant> {
+        (!self.live.is_empty()).then(|| self.swept.map_or(now, |at| at + SWEEP))
+    }
+
+    /// What job `job_id` holds, for its row in `jobs --all`.
+    pub fn note(&self, job_id: usize) -> String {
+        let held: Vec<_> = self
+            .live
+            .iter()
+            .filter(|h| h.job_id == job_id)
+            .map(Handles::describe)
+            .collect();
+        match held.is_empty() {
+            true => "reaped".into(),
+            false => held.join("; "),
+        }
+    }
+
+    /// What all jobs hold, and what the process has open, where that can
+    /// be read.
+    pub fn summary(&self) -> String {
+        let pipes = self.live.iter().filter(|h| h.fd.is_some()).count();
+        let mut summary = format!(
+            "{} reader threads, {pipes} pipes held; {} jobs reaped",
+            self.live.len(),
+            self.reaped
+        );
+        if let Some((fds, threads)) = process_counts() {
+            summary += &format!("; shim has {fds} fds open and {threads} threads");
+        }
+        summary
+    }
+}
+
+/// File descriptors open and threads running in this process, on Linux.
+fn process_counts() -> Option<(usize, usize)> {
+    let fds = fs::read_dir("/proc/self/fd").ok()?.count();
+    let status = fs::read_to_string("/proc/self/status").ok()?;
+    let threads = status
+        .lines()
+        .find_map(|l| l.strip_prefix("Threads:"))?
+        .trim()
+        .parse()
+        .ok()?;
+    Some((fds, threads))
+}
+
+impl Model {
+    /// Sweep the jobs' handles if it's time, noting on a job's buffer when
+    /// its pipe is closed on it.
+    pub fn sweep_jobs(&mut self, now: Instant) {
+        if self.reaper.due(now).is_none_or(|at| now < at) {
+            return;
+        }
+        for job_id in self.reaper.sweep(now) {
+            self.in_home_of(job_id, |m| {
+                if let Some(output) = m.outputs.iter_mut().find(|o| o.id == job_id) {
+                    output.warn(format!(
+                        "output closed: held open {}s after exit by a background process",
+                        LINGER.as_secs()
+                    ));
+                }
+            });
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{process::Command, sync::mpsc, thread};
+
+    use super::*;
+    use crate::{event::AppEvent, job, jobs};
+
+    /// Call `f` until it's true, for a few seconds at most.
+    fn eventually(mut f: impl FnMut() -> bool) {
+        let start = Instant::now();
+        while !f() {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn a_pipe_held_open_in_the_background_is_closed_and_reaped() {
+        let (events, incoming) = mpsc::channel();
+        let mut command = Command::new("sh");
+        command.args(["-c", "sleep 30 & echo hi"]);
+        let (process, handles) = job::spawn(7, vec![command], events).unwrap();
+        let pid = process.lock().unwrap().id();
+        let mut reaper = Reaper::default();
+        reaper.track(handles);
+        let chunk = incoming.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(chunk, AppEvent::OutputChunk { job_id: 7, .. }));
+        // sh has exited, but sleep still holds the pipe
+        let start = Instant::now();
+        eventually(|| reaper.sweep(start).is_empty() && reaper.live[0].exited.is_some());
+        assert!(reaper.note(7).starts_with("reader thread, pipe fd"));
+        assert_eq!(reaper.sweep(start + LINGER), [7]);
+        let exited = incoming.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert!(matches!(
+            exited,
+            AppEvent::JobExited {
+                job_id: 7,
+                status: Some(0),
+                ..
+            }
+        ));
+        reaper.ended(7);
+        eventually(|| {
+            reaper.sweep(start + LINGER * 2);
+            reaper.live.is_empty()
+        });
+        assert_eq!(reaper.note(7), "reaped");
+        assert!(
+            reaper
+                .summary()
+                .starts_with("0 reader threads, 0 pipes held; 1 jobs reaped")
+        );
+        let _ = jobs::send(pid, "KILL");
+    }
+}
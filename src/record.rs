@@ -0,0 +1,121 @@
+//! Recording a session as an asciinema v2 cast: what the commands print and
+//! the commands typed, rather than the screen shim draws.
+
+use std::{
+    fs::File,
+    io::{self, BufWriter, Write},
+    sync::mpsc::{self, Sender},
+    thread::{self, JoinHandle},
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use serde_json::json;
+
+use crate::Model;
+
+/// An event for the cast: seconds since the start, `"o"` or `"r"`, data.
+type Event = (f64, &'static str, String);
+
+/// Writes to the cast on its own thread so the screen never waits on the
+/// disk. Dropping it finishes the file.
+#[derive(Debug)]
+pub struct Recorder {
+    pub path: String,
+    events: Option<Sender<Event>>,
+    writer: Option<JoinHandle<()>>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn start(path: &str, width: u16, height: u16) -> io::Result<Self> {
+        let mut file = BufWriter::new(File::create(path)?);
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        let header = json!({
+            "version": 2,
+            "width": width,
+            "height": height,
+            "timestamp": timestamp,
+        });
+        writeln!(file, "{header}")?;
+        let (events, incoming) = mpsc::channel::<Event>();
+        let writer = thread::spawn(move || {
+            // flush whenever caught up, so little is lost if shim dies
+            while let Ok(first) = incoming.recv() {
+                for event in std::iter::once(first).chain(incoming.try_iter()) {
+                    let _ = writeln!(file, "{}", json!([event.0, event.1, event.2]));
+                }
+                let _ = file.flush();
+            }
+        });
+        Ok(Recorder {
+            path: path.to_string(),
+            events: Some(events),
+            writer: Some(writer),
+            start: Instant::now(),
+        })
+    }
+
+    fn send(&self, kind: &'static str, data: String) {
+        if let Some(events) = &self.events {
+            let _ = events.send((self.start.elapsed().as_secs_f64(), kind, data));
+        }
+    }
+
+    /// Text printed to the terminal; newlines get the carriage return a
+    /// player needs.
+    pub fn output(&self, text: &str) {
+        if !text.is_empty() {
+            self.send("o", text.replace("\r\n", "\n").replace('\n', "\r\n"));
+        }
+    }
+
+    pub fn command(&self, command: &str) {
+        self.output(&format!("❯ {command}\n"));
+    }
+
+    pub fn resize(&self, width: u16, height: u16) {
+        self.send("r", format!("{width}x{height}"));
+    }
+}
+
+impl Model {
+    pub fn record_output(&self, text: &str) {
+        if let Some(recorder) = &self.recorder {
+            recorder.output(text);
+        }
+    }
+
+    /// `:record start [FILE]` and `:record stop`.
+    pub fn record(&mut self, args: &[&str]) -> String {
+        match args {
+            ["start", rest @ ..] if rest.len() <= 1 => {
+                let path = rest.first().copied().unwrap_or("shim.cast");
+                let (width, height) = ratatui::crossterm::terminal::size().unwrap_or((80, 24));
+                match Recorder::start(path, width, height) {
+                    Ok(recorder) => {
+                        self.recorder = Some(recorder);
+                        format!("recording to {path}")
+                    }
+                    Err(e) => format!("record: {path}: {e}"),
+                }
+            }
+            ["stop"] => match self.recorder.take() {
+                Some(recorder) => format!("saved recording to {}", recorder.path),
+                None => "not recording".into(),
+            },
+            _ => "usage: :record start [FILE] or :record stop".into(),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        // closing the channel lets the writer finish what's queued and exit
+        self.events.take();
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
@@ -0,0 +1,98 @@
+use std::borrow::Cow;
+
+use regex::{Captures, Regex};
+
+use crate::Output;
+
+pub const MASK: &str = "•••REDACTED•••";
+
+/// Patterns for secrets that commonly end up in output. Where there is a
+/// `secret` group only it is masked, so `PASSWORD=` stays readable.
+pub fn defaults() -> Vec<Regex> {
+    [
+        // AWS access key ids
+        r"\b(?:AKIA|ASIA)[0-9A-Z]{16}\b",
+        r"(?i)aws_secret_access_key\s*[=:]\s*(?P<secret>\S+)",
+        r"(?i)\bbearer\s+(?P<secret>[A-Za-z0-9\-._~+/]+=*)",
+        r"(?i)\b\w*(?:password|passwd|secret|token|api_key)\w*\s*[=:]\s*(?P<secret>\S+)",
+    ]
+    .into_iter()
+    .map(|pattern| Regex::new(pattern).expect("default redaction patterns are valid"))
+    .collect()
+}
+
+/// Mask whatever in `line` matches `patterns`.
+pub fn line<'a>(patterns: &[Regex], line: &'a str) -> Cow<'a, str> {
+    let mut line = Cow::Borrowed(line);
+    for pattern in patterns {
+        if let Cow::Owned(masked) = pattern.replace_all(&line, mask) {
+            line = Cow::Owned(masked);
+        }
+    }
+    line
+}
+
+fn mask(caps: &Captures) -> String {
+    let whole = caps.get(0).expect("group 0 always matches");
+    match caps.name("secret") {
+        Some(secret) => format!(
+            "{}{MASK}{}",
+            &whole.as_str()[..secret.start() - whole.start()],
+            &whole.as_str()[secret.end() - whole.start()..]
+        ),
+        None => MASK.into(),
+    }
+}
+
+impl Output {
+    /// Add text to the buffer. Only whole lines are redacted and shown, so
+    /// a secret split across chunks is still caught; the rest waits in
+    /// `partial` for its newline or for `flush`.
+    pub fn ingest(&mut self, text: &str, patterns: &[Regex]) {
+        self.partial.push_str(text);
+        let Some(end) = self.partial.rfind('\n') else {
+            return;
+        };
+        let rest = self.partial.split_off(end + 1);
+        let lines = std::mem::replace(&mut self.partial, rest);
+        self.push_redacted(&lines, patterns);
+    }
+
+    /// Redact and show the last, unterminated line.
+    pub fn flush(&mut self, patterns: &[Regex]) {
+        let rest = std::mem::take(&mut self.partial);
+        self.push_redacted(&rest, patterns);
+    }
+
+    fn push_redacted(&mut self, text: &str, patterns: &[Regex]) {
+        for raw in text.split_inclusive('\n') {
+            let masked = line(patterns, raw);
+            // the unredacted text is only kept once there is a secret in it
+            if matches!(masked, Cow::Owned(_)) && self.raw.is_none() {
+                self.raw = Some(self.stdout.clone());
+            }
+            self.stdout.push_str(&masked);
+            if let Some(all) = self.raw.as_mut() {
+                all.push_str(raw);
+            }
+        }
+    }
+
+    /// The output as the command wrote it, secrets and all.
+    pub fn raw_text(&self) -> &str {
+        self.raw.as_deref().unwrap_or(&self.stdout)
+    }
+
+    /// What to draw: the output up to a checkpoint if the view is frozen,
+    /// the filtered output if there's a filter on, otherwise redacted
+    /// unless the user asked to see the raw text.
+    pub fn shown_text(&self) -> &str {
+        if let Some(text) = self.frozen_text().or_else(|| self.piped_text()) {
+            return text;
+        }
+        match self.show_raw {
+            true => self.raw_text(),
+            false => &self.stdout,
+        }
+    }
+}
@@ -0,0 +1,265 @@
+//! Vim-style registers for yanked text. Plain yanks go to `"0`, pushing
+//! older ones down to `"9`; `"a`–`"z` are set by name and `"+` is the
+//! system clipboard, reached through OSC 52.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    io::{self, Write},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    Cursor, Model,
+    picker::{Picker, PickerKind},
+    sanitize,
+    toast::{Level, TTL},
+};
+
+const NUMBERED: usize = 10;
+
+/// Longest register shown in full in the `:registers` list.
+const PREVIEW: usize = 60;
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct Registers {
+    /// `"0` first.
+    numbered: VecDeque<String>,
+    named: BTreeMap<char, String>,
+}
+
+impl Registers {
+    pub fn is_name(c: char) -> bool {
+        c.is_ascii_alphanumeric() || c == '+'
+    }
+
+    pub fn store(&mut self, register: Option<char>, text: String) {
+        match register {
+            None | Some('0') => {
+                self.numbered.push_front(text);
+                self.numbered.truncate(NUMBERED);
+            }
+            Some(c @ '1'..='9') => {
+                let i = c as usize - '0' as usize;
+                while self.numbered.len() <= i {
+                    self.numbered.push_back(String::new());
+                }
+                self.numbered[i] = text;
+            }
+            Some(c) => {
+                self.named.insert(c, text);
+            }
+        }
+    }
+
+    pub fn get(&self, register: Option<char>) -> Option<&str> {
+        match register {
+            None => self.numbered.front(),
+            Some(c @ '0'..='9') => self.numbered.get(c as usize - '0' as usize),
+            Some(c) => self.named.get(&c),
+        }
+        .map(String::as_str)
+    }
+
+    /// Every register that holds something, in name order.
+    pub fn all(&self) -> Vec<(char, &str)> {
+        let numbered = self
+            .numbered
+            .iter()
+            .enumerate()
+            .map(|(i, text)| ((b'0' + i as u8) as char, text.as_str()));
+        let named = self.named.iter().map(|(c, text)| (*c, text.as_str()));
+        numbered
+            .chain(named)
+            .filter(|(_, text)| !text.is_empty())
+            .collect()
+    }
+}
+
+impl Model {
+    /// Yank the line under the cursor: the output line in the output
+    /// buffer, or the whole command.
+    pub fn yank(&mut self) {
+        let text = match self.cursor {
+            _ if let Some(line) = self.popup_line() => line.to_string(),
+            Cursor::OutputBuffer(_, y) => self.full_output_line(y).unwrap_or("").to_string(),
+            Cursor::CommandLine(_, _) => self.command().to_string(),
+        };
+        self.yank_text(text);
+    }
+
+    /// Store yanked `text` in the selected register, or `"0`.
+    pub fn yank_text(&mut self, text: String) {
+        let register = self.register.take();
+        let to_clipboard = register == Some('+') || (register.is_none() && self.config.clipboard);
+        if to_clipboard && !self.terminal.clipboard {
+            let note = "clipboard: not supported by this terminal";
+            self.push_toast(Level::Warn, note, TTL);
+        } else if to_clipboard && let Err(e) = copy_to_clipboard(&text) {
+            self.push_toast(Level::Error, format!("clipboard: {e}"), TTL);
+        }
+        let lines = text.lines().count().max(1);
+        self.registers.store(register, text);
+        let name = register.unwrap_or('0');
+        self.status
+            .get_or_insert_with(|| format!("yanked {lines} line(s) into \"{name}"));
+    }
+
+    /// Put a register's text into the command after the cursor.
+    pub fn paste(&mut self) {
+        let register = self.register.take();
+        let Some(text) = self.registers.get(register).map(String::from) else {
+            let name = register.unwrap_or('0');
+            self.status = Some(format!("register \"{name} is empty"));
+            return;
+        };
+        self.insert_into_command(&text);
+    }
+
+    fn insert_into_command(&mut self, text: &str) {
+        self.edit_recalled();
+        let offset = match self.cursor {
+            Cursor::CommandLine(x, y) if !self.current_command.is_empty() => {
+                self.command_offset(self.next_column(x, y), y)
+            }
+            Cursor::CommandLine(_, _) => 0,
+            Cursor::OutputBuffer(_, _) => self.current_command.len(),
+        };
+        self.current_command.insert_str(offset, text);
+        let end = offset + text.len();
+        let row = self.current_command[..end].matches('\n').count();
+        let line_start = self.current_command[..end].rfind('\n').map_or(0, |i| i + 1);
+        // on the last character pasted
+        let col = self.current_command[line_start..end]
+            .char_indices()
+            .next_back()
+            .map_or(0, |(i, _)| i);
+        self.cursor = Cursor::CommandLine(col as u16, row as u16);
+    }
+
+    pub fn open_registers_picker(&mut self) {
+        let items = self
+            .registers
+            .all()
+            .into_iter()
+            .map(|(c, text)| format!("\"{c}  {}", preview(text)))
+            .collect();
+        self.picker = Some(Picker::new(PickerKind::Register, items));
+    }
+
+    /// Paste the register picked from the `:registers` list.
+    pub fn paste_picked(&mut self, entry: &str) {
+        self.register = entry.chars().nth(1);
+        self.paste();
+    }
+}
+
+/// One line, cut short, for the register list.
+fn preview(text: &str) -> String {
+    let line = text.replace('\n', "⏎");
+    match line.char_indices().nth(PREVIEW) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line,
+    }
+}
+
+/// Ask the terminal to put `text` on the system clipboard.
+fn copy_to_clipboard(text: &str) -> io::Result<()> {
+    let sequence = sanitize::clipboard(text).map_err(io::Error::other)?;
+    let mut stdout = io::stdout();
+    stdout.write_all(sequence.as_bytes())?;
+    stdout.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plain_yanks_push_older_ones_down() {
+        let mut registers = Registers::default();
+        for i in 0..12 {
+            registers.store(None, format!("yank {i}"));
+        }
+        assert_eq!(registers.get(None), Some("yank 11"));
+        assert_eq!(registers.get(Some('0')), Some("yank 11"));
+        assert_eq!(registers.get(Some('1')), Some("yank 10"));
+        assert_eq!(registers.get(Some('9')), Some("yank 2"));
+        // "0 named is a plain yank too
+        registers.store(Some('0'), "latest".into());
+        assert_eq!(registers.get(Some('1')), Some("yank 11"));
+        assert_eq!(registers.all().len(), NUMBERED);
+    }
+
+    #[test]
+    fn numbered_and_named_registers_are_set_in_place() {
+        let mut registers = Registers::default();
+        registers.store(Some('3'), "three".into());
+        assert_eq!(registers.get(Some('3')), Some("three"));
+        assert_eq!(registers.get(None), Some(""));
+        registers.store(Some('a'), "first".into());
+        registers.store(Some('a'), "second".into());
+        registers.store(Some('+'), "copied".into());
+        registers.store(None, "plain".into());
+        assert_eq!(registers.get(Some('a')), Some("second"));
+        assert_eq!(registers.get(Some('b')), None);
+        assert_eq!(registers.get(Some('4')), Some("three"));
+        // the gaps left before "3 aren't listed
+        assert_eq!(
+            registers.all(),
+            [
+                ('0', "plain"),
+                ('4', "three"),
+                ('+', "copied"),
+                ('a', "second")
+            ]
+        );
+    }
+
+    #[test]
+    fn a_register_is_pasted_after_the_cursor() {
+        let mut model = Model {
+            // kept from writing to the clipboard, here stdout
+            terminal: crate::tui::Capabilities {
+                clipboard: false,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        model.register = Some('+');
+        model.yank_text("token".into());
+        assert_eq!(model.registers.get(None), None);
+        assert_eq!(model.status.as_deref(), Some("yanked 1 line(s) into \"+"));
+        model.registers.store(Some('a'), "curl -H ".into());
+        model.register = Some('a');
+        model.paste();
+        model.register = Some('+');
+        model.paste();
+        assert_eq!(model.current_command, "curl -H token");
+        assert_eq!(model.cursor, Cursor::CommandLine(12, 0));
+        model.register = Some('q');
+        model.paste();
+        assert_eq!(model.status.as_deref(), Some("register \"q is empty"));
+    }
+
+    #[test]
+    fn the_clipboard_gets_base64() {
+        let encoded = |text: &str| {
+            let sequence = sanitize::clipboard(text).unwrap();
+            sequence["\x1b]52;c;".len()..sequence.len() - 1].to_string()
+        };
+        let vectors = [
+            ("", ""),
+            ("f", "Zg=="),
+            ("fo", "Zm8="),
+            ("foo", "Zm9v"),
+            ("foob", "Zm9vYg=="),
+            ("fooba", "Zm9vYmE="),
+            ("foobar", "Zm9vYmFy"),
+            ("café ✓", "Y2Fmw6kg4pyT"),
+        ];
+        for (text, base64) in vectors {
+            assert_eq!(encoded(text), base64, "{text}");
+        }
+    }
+}
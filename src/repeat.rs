@@ -0,0 +1,144 @@
+//! Repeating: `.` does the last change to the command line again, and `!!`
+//! runs the last command again without touching the command line.
+
+use crate::{Message, Mode, Model, update};
+
+/// How a change to the command line began.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Edit {
+    /// `i`, `a`, `I` or `A`, then typing.
+    Insert(char),
+    /// `ciw`, `daa` and the like; `c` is followed by typing.
+    Object {
+        operator: char,
+        around: bool,
+        argument: bool,
+    },
+    /// `dd`.
+    DeleteLine,
+    /// `~`.
+    ToggleCase,
+    /// `guw` and `gUw`.
+    WordCase { upper: bool },
+    /// Ctrl-a and Ctrl-x.
+    Increment(i64),
+}
+
+/// A change to the command line that `.` can make again.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    edit: Edit,
+    /// What was typed after it, backspaces taken into account.
+    typed: String,
+}
+
+impl Change {
+    /// The messages that make the change.
+    fn messages(&self) -> Vec<Message> {
+        let start = match self.edit {
+            Edit::Insert('a') => Message::InsertAfter,
+            Edit::Insert('I') => Message::InsertBeforeLine,
+            Edit::Insert('A') => Message::InsertAfterLine,
+            Edit::Insert(_) => Message::InsertBefore,
+            Edit::Object {
+                operator,
+                around,
+                argument,
+            } => Message::TextObject {
+                operator,
+                around,
+                argument,
+            },
+            Edit::DeleteLine => Message::DeleteLine,
+            Edit::ToggleCase => Message::ToggleCase,
+            Edit::WordCase { upper } => Message::ChangeWordCase { upper },
+            Edit::Increment(by) => Message::Increment(by),
+        };
+        let mut messages = vec![start];
+        // the rest only go on into insert mode
+        if !matches!(
+            self.edit,
+            Edit::Insert(_) | Edit::Object { operator: 'c', .. }
+        ) {
+            return messages;
+        }
+        messages.extend(self.typed.chars().map(|c| match c {
+            '\n' => Message::Newline,
+            c => Message::WriteCommandChar(c),
+        }));
+        messages.push(Message::Normal);
+        messages
+    }
+}
+
+impl Model {
+    /// Start recording a change that goes on into insert mode.
+    pub fn begin_change(&mut self, edit: Edit) {
+        self.recording = (self.mode == Mode::Insert).then(|| Change {
+            edit,
+            typed: String::new(),
+        });
+    }
+
+    /// Note a change that's over as soon as it's made.
+    pub fn made_change(&mut self, edit: Edit) {
+        self.recording = None;
+        self.last_change = Some(Change {
+            edit,
+            typed: String::new(),
+        });
+    }
+
+    /// Note what's typed in insert mode for the change being recorded.
+    pub fn record_typing(&mut self, msg: &Message) {
+        let Some(change) = self.recording.as_mut() else {
+            return;
+        };
+        match msg {
+            Message::WriteCommandChar(c) => change.typed.push(*c),
+            Message::Newline => change.typed.push('\n'),
+            Message::Backspace => {
+                change.typed.pop();
+            }
+            Message::Normal => self.last_change = self.recording.take(),
+            _ => {}
+        }
+    }
+
+    /// `.`: make the last change again, `count` times.
+    pub fn repeat_change(&mut self, count: usize) {
+        let Some(change) = self.last_change.clone() else {
+            self.status = Some("no change to repeat".into());
+            return;
+        };
+        for _ in 0..count {
+            for msg in change.messages() {
+                let mut next = Some(msg);
+                while let Some(msg) = next {
+                    next = update(self, msg);
+                }
+            }
+        }
+    }
+
+    /// `!!`: run the last command again, leaving the command line as it is.
+    pub fn run_last(&mut self) {
+        let Some(command) = self.previous_commands.last().map(|e| e.command.clone()) else {
+            self.status = Some("no command to run again".into());
+            return;
+        };
+        // asking first needs it on the command line, as for Enter
+        if self.is_dangerous(&command) {
+            self.current_command = command;
+            self.confirming = Some(false);
+            return;
+        }
+        let line = std::mem::replace(&mut self.current_command, command);
+        let cursor = self.cursor;
+        self.submit(false);
+        if self.current_command.is_empty() {
+            self.current_command = line;
+            self.cursor = cursor;
+        }
+    }
+}
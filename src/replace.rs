@@ -0,0 +1,49 @@
+//! Replace mode, `R` on the command line. What's typed goes over the
+//! character under the cursor and moves on, and past the end of the line
+//! it's added as in Insert mode. Backspace puts back what was typed over
+//! since `R`, a character at a time, and before where typing over started
+//! only moves left. Esc goes back to Normal mode. The cursor is an
+//! underline meanwhile, where the terminal can show one.
+
+use crate::{Cursor, Mode, Model};
+
+impl Model {
+    /// `R`: start typing over the command line.
+    pub fn enter_replace(&mut self) {
+        self.mode = Mode::Replace;
+        self.replaced.clear();
+    }
+
+    /// Type `c` over the character under the cursor.
+    pub fn replace_char(&mut self, c: char) {
+        let Cursor::CommandLine(x, y) = self.cursor else {
+            return;
+        };
+        let offset = self.command_offset(x, y);
+        let over = self.current_command[offset..]
+            .chars()
+            .next()
+            .filter(|&o| o != '\n');
+        let end = offset + over.map_or(0, char::len_utf8);
+        self.current_command
+            .replace_range(offset..end, c.encode_utf8(&mut [0; 4]));
+        self.replaced.push(over);
+        self.cursor = Cursor::CommandLine(x + c.len_utf8() as u16, y);
+    }
+
+    /// Backspace: put back the character last typed over.
+    pub fn replace_backspace(&mut self) {
+        let Cursor::CommandLine(x @ 1.., y) = self.cursor else {
+            return;
+        };
+        let previous = self.previous_column(x, y);
+        if let Some(original) = self.replaced.pop() {
+            let offset = self.command_offset(previous, y);
+            let typed = self.current_command[offset..].chars().next();
+            let end = offset + typed.map_or(0, char::len_utf8);
+            let original = original.map(String::from).unwrap_or_default();
+            self.current_command.replace_range(offset..end, &original);
+        }
+        self.cursor = Cursor::CommandLine(previous, y);
+    }
+}
@@ -0,0 +1,150 @@
+//! `:rerun [--append]`: run the viewed buffer's command again in the same
+//! buffer. With `--append`, or `R`, the new output goes after what's
+//! there, under a line saying when the run started and, once it's over,
+//! how it exited; nothing above moves, so bookmarks and marks stay put.
+
+use std::time::SystemTime;
+
+use regex::Regex;
+
+use crate::{Model, Output, clock};
+
+/// Stands for the exit status in a separator until the run ends; what
+/// replaces it is padded to the same length so nothing after it moves.
+const RUNNING: &str = "running ";
+
+/// Where the exit status goes in the separator of an appended run that's
+/// still going, in the output and in its unredacted copy.
+#[derive(Debug)]
+pub struct Section {
+    stdout: usize,
+    raw: Option<usize>,
+}
+
+impl Output {
+    /// Start a section for run `run`, the new output to come under it.
+    pub fn open_section(&mut self, run: u32, redact: &[Regex]) {
+        let time = clock::format(SystemTime::now());
+        self.push_note(&format!("--- run {run} at {time}: {RUNNING}"), redact);
+        // just before the newline
+        let at = |text: &str| text.len() - 1 - RUNNING.len();
+        self.section = Some(Section {
+            stdout: at(&self.stdout),
+            raw: self.raw.as_deref().map(at),
+        });
+    }
+
+    /// The first `cut` bytes of the output, and `raw_cut` of the raw
+    /// output, are gone; so is the separator if it was in them.
+    pub fn drop_section(&mut self, cut: usize, raw_cut: Option<usize>) {
+        let Some(section) = &mut self.section else {
+            return;
+        };
+        let raw = section
+            .raw
+            .zip(raw_cut)
+            .map(|(at, cut)| at.checked_sub(cut));
+        match (section.stdout.checked_sub(cut), raw) {
+            (Some(stdout), Some(Some(raw))) => {
+                *section = Section {
+                    stdout,
+                    raw: Some(raw),
+                }
+            }
+            (Some(stdout), None) => section.stdout = stdout,
+            _ => self.section = None,
+        }
+    }
+
+    /// Fill in how the appended run exited, if one is going.
+    pub fn close_section(&mut self, status: Option<i32>) {
+        let Some(section) = self.section.take() else {
+            return;
+        };
+        let exit = status.map_or("killed".into(), |s| format!("exit {s}"));
+        let exit = format!("{exit:<width$}", width = RUNNING.len());
+        // a longer status can't fit without moving what's after it
+        if exit.len() != RUNNING.len() {
+            return;
+        }
+        let texts = [
+            Some((&mut self.stdout, section.stdout)),
+            self.raw.as_mut().zip(section.raw),
+        ];
+        for (text, at) in texts.into_iter().flatten() {
+            if text.get(at..at + RUNNING.len()) == Some(RUNNING) {
+                text.replace_range(at..at + RUNNING.len(), &exit);
+            }
+        }
+    }
+}
+
+impl Model {
+    /// Start a section in output `index` for the run about to start, with
+    /// the view going to it if it's in view.
+    pub fn append_section(&mut self, index: usize) {
+        let output = &mut self.outputs[index];
+        output.open_section(output.reruns + 2, &self.config.redact);
+        if index == self.viewing_output {
+            let separator = output.line_count().saturating_sub(1);
+            let top = separator.min(self.bottom(index));
+            self.outputs[index].set_scroll(top);
+        }
+    }
+
+    /// `:rerun [--append]`, returning what to show on the status line.
+    pub fn rerun(&mut self, args: &[&str]) -> String {
+        let append = match args {
+            [] => false,
+            ["--append"] => true,
+            _ => return "usage: :rerun [--append]".into(),
+        };
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return "no buffer to run again".into();
+        };
+        if output.started.is_none() || output.each.is_some() {
+            return "only a command's buffer can be run again".into();
+        }
+        if output.running {
+            return format!("{} is still running", output.command);
+        }
+        let id = output.id;
+        self.run_again(id, append);
+        String::new()
+    }
+
+    /// `R`
+    pub fn rerun_appending(&mut self) {
+        let status = self.rerun(&["--append"]);
+        if !status.is_empty() {
+            self.status = Some(status);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_exit_status_is_filled_in_without_moving_anything() {
+        let mut output = Output {
+            stdout: "total 4\n".into(),
+            ..Default::default()
+        };
+        output.open_section(2, &[]);
+        let separator = output.stdout.lines().nth(1).unwrap().to_string();
+        assert!(separator.starts_with("--- run 2 at "));
+        assert!(separator.ends_with(": running "));
+        output.stdout.push_str("total 8\n");
+        let len = output.stdout.len();
+        output.close_section(Some(127));
+        assert_eq!(output.stdout.len(), len);
+        let separator = output.stdout.lines().nth(1).unwrap();
+        assert!(separator.ends_with(": exit 127"));
+        assert!(output.stdout.ends_with("exit 127\ntotal 8\n"));
+        // once filled in, it's left alone
+        output.close_section(Some(0));
+        assert!(output.stdout.contains("exit 127"));
+    }
+}
@@ -0,0 +1,291 @@
+use std::time::{Duration, Instant};
+
+use regex::Regex;
+
+use crate::{Model, Output, vars};
+
+/// Re-running a failed command into the same buffer.
+#[derive(Debug)]
+pub struct Retry {
+    /// The attempt running or last run, from 1.
+    pub attempt: u32,
+    pub attempts: u32,
+    /// When the next attempt starts, while waiting for it.
+    pub next: Option<Instant>,
+}
+
+impl Retry {
+    fn new(attempts: u32) -> Self {
+        Retry {
+            attempt: 1,
+            attempts,
+            next: None,
+        }
+    }
+}
+
+/// How long to wait after failed attempt `attempt`: 2s, 4s, 8s and so on,
+/// up to five minutes.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(2u64.saturating_pow(attempt).min(300))
+}
+
+/// Whole seconds left until `time`, rounded up so the countdown ends at 1.
+fn seconds_until(time: Instant) -> u64 {
+    time.saturating_duration_since(Instant::now())
+        .as_secs_f64()
+        .ceil() as u64
+}
+
+impl Output {
+    /// After an attempt fails, note it in the buffer and schedule the next
+    /// one. Returns whether there will be one.
+    pub fn schedule_retry(&mut self, status: Option<i32>, redact: &[Regex]) -> bool {
+        let Some(retry) = self.retry.as_mut() else {
+            return false;
+        };
+        if status == Some(0) || retry.attempt >= retry.attempts {
+            return false;
+        }
+        let delay = backoff(retry.attempt);
+        retry.next = Some(Instant::now() + delay);
+        let exit = status.map_or("killed".into(), |s| format!("exit {s}"));
+        let note = format!(
+            "--- attempt {}/{} ({exit}), retrying in {}s ---",
+            retry.attempt,
+            retry.attempts,
+            delay.as_secs()
+        );
+        self.push_note(&note, redact);
+        true
+    }
+
+    /// Add a line of shim's own to the buffer.
+    pub fn push_note(&mut self, note: &str, redact: &[Regex]) {
+        if !self.stdout.is_empty() && !self.stdout.ends_with('\n') {
+            self.ingest("\n", redact);
+        }
+        self.ingest(note, redact);
+        self.ingest("\n", redact);
+    }
+
+    /// How retrying is going, for the title.
+    pub fn retry_note(&self) -> Option<String> {
+        let retry = self.retry.as_ref()?;
+        let (attempt, attempts) = (retry.attempt, retry.attempts);
+        let took = self.duration.map_or(0, |d| d.as_secs());
+        Some(match (retry.next, self.status) {
+            _ if self.running => format!("attempt {attempt}/{attempts}"),
+            (Some(next), _) => format!(
+                "attempt {attempt}/{attempts} failed, retrying in {}s",
+                seconds_until(next)
+            ),
+            (None, Some(0)) => format!("succeeded on attempt {attempt}/{attempts}, took {took}s"),
+            (None, _) => format!("failed after {attempt} attempts, took {took}s"),
+        })
+    }
+}
+
+impl Model {
+    /// `:retry N CMD`: run CMD, trying again up to N times in all if it
+    /// fails.
+    pub fn start_retrying(&mut self, attempts: u32, command: String) {
+        let id = self.next_output_id;
+        self.start_job(command, None);
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.id == id && o.running) {
+            output.retry = Some(Retry::new(attempts));
+        }
+    }
+
+    /// Retry the viewed buffer's command, counting the failed run as the
+    /// first attempt.
+    pub fn retry_viewed(&mut self) {
+        let attempts = self.config.retries;
+        let redact = &self.config.redact;
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return;
+        };
+        let waiting = output.retry.as_ref().is_some_and(|r| r.next.is_some());
+        let failed = output.started.is_some() && output.status != Some(0);
+        if output.running || waiting || !failed || output.each.is_some() {
+            self.status = Some("only a command that failed can be retried".into());
+            return;
+        }
+        output.retry = Some(Retry::new(attempts));
+        output.schedule_retry(output.status, redact);
+    }
+
+    /// Start every attempt whose wait is over.
+    pub fn run_due_retries(&mut self) {
+        let now = Instant::now();
+        let due: Vec<_> = self
+            .outputs
+            .iter()
+            .filter(|o| {
+                o.retry
+                    .as_ref()
+                    .and_then(|r| r.next)
+                    .is_some_and(|t| t <= now)
+            })
+            .map(|o| o.id)
+            .collect();
+        for id in due {
+            self.run_attempt(id);
+        }
+    }
+
+    fn run_attempt(&mut self, id: usize) {
+        let Some(command) = self
+            .outputs
+            .iter()
+            .find(|o| o.id == id)
+            .map(|o| o.command.clone())
+        else {
+            return;
+        };
+        let line = vars::split_capture(&command).map_or(command.as_str(), |(_, line)| line);
+        let spawned = self
+            .parse_pipeline(line)
+            .map(|stages| self.spawn_stages(stages, id));
+        let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) else {
+            return;
+        };
+        if let Some(retry) = output.retry.as_mut() {
+            retry.attempt += 1;
+            retry.next = None;
+        }
+        match spawned {
+            Some(Ok(process)) => {
+                output.running = true;
+                output.process = Some(process);
+                output.status = None;
+            }
+            Some(Err(e)) => {
+                output.warn(format!("couldn't start: {e}"));
+                self.finish_job(id, Some(126));
+            }
+            None => self.finish_job(id, Some(1)),
+        }
+    }
+
+    /// Ctrl-C while the viewed buffer waits to retry: give up on it.
+    pub fn cancel_retry(&mut self) -> bool {
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return false;
+        };
+        let Some(retry) = output.retry.as_mut().filter(|r| r.next.is_some()) else {
+            return false;
+        };
+        retry.next = None;
+        retry.attempts = retry.attempt;
+        output.push_note("--- retry cancelled ---", &self.config.redact);
+        let (id, status) = (output.id, output.status);
+        self.finish_job(id, status);
+        true
+    }
+
+    /// Shown at the top of the screen while a retry is counting down.
+    pub fn retry_summary(&self) -> Option<String> {
+        self.outputs.iter().find_map(|o| {
+            let next = o.retry.as_ref()?.next?;
+            Some(format!(
+                "retrying {} in {}s",
+                o.command,
+                seconds_until(next)
+            ))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, update};
+
+    /// A model whose one buffer runs attempt 1 of `attempts`.
+    fn retrying(attempts: u32) -> Model {
+        let mut model = Model::default();
+        model.push_output("curl example.com".into(), String::new());
+        let output = &mut model.outputs[0];
+        output.running = true;
+        output.started = Some(Instant::now());
+        output.retry = Some(Retry::new(attempts));
+        model
+    }
+
+    #[test]
+    fn the_wait_doubles_up_to_five_minutes() {
+        let waits: Vec<_> = (1..=10).map(|a| backoff(a).as_secs()).collect();
+        assert_eq!(waits, [2, 4, 8, 16, 32, 64, 128, 256, 300, 300]);
+        assert_eq!(backoff(u32::MAX).as_secs(), 300);
+    }
+
+    #[test]
+    fn a_failure_schedules_the_next_attempt() {
+        let mut model = retrying(3);
+        model.finish_job(0, Some(7));
+        let output = &model.outputs[0];
+        let next = output.retry.as_ref().unwrap().next.unwrap();
+        assert!(next > Instant::now() + Duration::from_secs(1));
+        assert!(
+            output
+                .stdout
+                .ends_with("--- attempt 1/3 (exit 7), retrying in 2s ---\n")
+        );
+        assert_eq!(
+            output.retry_note().unwrap(),
+            "attempt 1/3 failed, retrying in 2s"
+        );
+        assert_eq!(
+            model.retry_summary().unwrap(),
+            "retrying curl example.com in 2s"
+        );
+    }
+
+    #[test]
+    fn success_or_the_last_attempt_stops_retrying() {
+        let mut model = retrying(3);
+        model.finish_job(0, Some(0));
+        let output = &model.outputs[0];
+        assert_eq!(output.retry.as_ref().unwrap().next, None);
+        assert!(
+            output
+                .retry_note()
+                .unwrap()
+                .starts_with("succeeded on attempt 1/3")
+        );
+
+        let mut model = retrying(2);
+        model.outputs[0].retry.as_mut().unwrap().attempt = 2;
+        model.finish_job(0, Some(1));
+        let output = &model.outputs[0];
+        assert_eq!(output.retry.as_ref().unwrap().next, None);
+        assert!(
+            output
+                .retry_note()
+                .unwrap()
+                .starts_with("failed after 2 attempts")
+        );
+        assert_eq!(model.retry_summary(), None);
+    }
+
+    #[test]
+    fn ctrl_c_gives_up_on_the_next_attempt() {
+        let mut model = retrying(3);
+        model.finish_job(0, None);
+        assert!(model.outputs[0].stdout.contains("(killed), retrying in 2s"));
+        update(&mut model, Message::Interrupt);
+        let output = &model.outputs[0];
+        assert!(output.stdout.ends_with("--- retry cancelled ---\n"));
+        assert_eq!(output.retry.as_ref().unwrap().next, None);
+        assert!(
+            output
+                .retry_note()
+                .unwrap()
+                .starts_with("failed after 1 attempts")
+        );
+        assert_eq!(model.retry_summary(), None);
+        // nothing left to cancel
+        assert!(!model.cancel_retry());
+    }
+}
@@ -0,0 +1,112 @@
+//! Guards for what shim writes to the terminal itself, past what ratatui
+//! draws: text copied to the system clipboard with OSC 52, and images
+//! from a command's output sent back to the terminal to draw. Nothing a
+//! command printed may end such a sequence early, or the terminal would
+//! act on whatever follows as if shim had sent it.
+
+/// Ends an escape sequence's string.
+const ST: &str = "\x1b\\";
+
+/// Most bytes of text copied to the clipboard, about 100 kB once encoded;
+/// terminals drop or cut short a longer OSC 52.
+pub const CLIPBOARD_MAX: usize = 75_000;
+
+/// The OSC 52 sequence putting `text` on the system clipboard. The text is
+/// sent as base64, so its own escapes and bells can't end the sequence.
+pub fn clipboard(text: &str) -> Result<String, String> {
+    if text.len() > CLIPBOARD_MAX {
+        return Err(format!(
+            "{} bytes is too much to copy, the most is {CLIPBOARD_MAX}",
+            text.len()
+        ));
+    }
+    Ok(format!("\x1b]52;c;{}\x07", base64(text.as_bytes())))
+}
+
+/// Whether `sequence`, an image escape taken from a command's output, can
+/// be sent to the terminal as it is: its body, between the introducer and
+/// the terminator, is printable text and line breaks, with no escape,
+/// bell, CAN, SUB or C1 control to end or cancel it before its end.
+pub fn sealed(sequence: &str) -> bool {
+    // ESC and `P`, `_` or `]`
+    let Some(body) = sequence.get(2..) else {
+        return false;
+    };
+    let Some(body) = body.strip_suffix(ST).or_else(|| body.strip_suffix('\x07')) else {
+        return false;
+    };
+    body.chars()
+        .all(|c| !c.is_control() || matches!(c, '\n' | '\r'))
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, b)| n | (*b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn copied_text_cant_end_the_sequence() {
+        let hostile = "ls\n\x1b]0;pwned\x07\x1b[2J\u{9c}\x1b\\done";
+        let sequence = clipboard(hostile).unwrap();
+        assert!(sequence.starts_with("\x1b]52;c;"));
+        let payload = &sequence["\x1b]52;c;".len()..sequence.len() - 1];
+        assert!(
+            payload
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "+/=".contains(c))
+        );
+        assert!(sequence.ends_with('\x07'));
+        assert_eq!(clipboard("hi\n").unwrap(), "\x1b]52;c;aGkK\x07");
+
+        let long = "x".repeat(CLIPBOARD_MAX + 1);
+        assert_eq!(
+            clipboard(&long),
+            Err("75001 bytes is too much to copy, the most is 75000".into())
+        );
+        assert!(clipboard(&long[1..]).is_ok());
+    }
+
+    #[test]
+    fn images_with_controls_inside_arent_sealed() {
+        assert!(sealed("\x1bPq\"1;1;2;2#0~~\x1b\\"));
+        assert!(sealed("\x1bPq#0~~\n-~~\x1b\\"));
+        assert!(sealed("\x1b_Ga=T,s=1,v=1;AAAA\x1b\\"));
+        assert!(sealed("\x1b]1337;File=inline=1:AAAA\x07"));
+        let hostile = [
+            // a CSI inside: the escape ends the DCS and clears the screen
+            "\x1bPq#0~~\x1b[2J\x1b\\",
+            // an OSC setting the title inside the body
+            "\x1b_Ga=T;AA\x1b]0;pwned\x07AA\x1b\\",
+            // CAN and SUB cancel the sequence, C1 ST ends it
+            "\x1bPq#0\x18\x1b[31mred\x1b\\",
+            "\x1bPq#0\x1a~~\x1b\\",
+            "\x1bPq#0\u{9c}\x1b[2J\x1b\\",
+            // not ended at all
+            "\x1bPq#0~~",
+            "\x1b",
+        ];
+        for sequence in hostile {
+            assert!(!sealed(sequence), "{sequence:?}");
+        }
+        let long = format!("\x1bPq{}\x1b\\", "~".repeat(1 << 20));
+        assert!(sealed(&long));
+    }
+}
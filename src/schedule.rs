@@ -0,0 +1,261 @@
+//! When the main loop wakes with no event to wake it: for the next frame
+//! of a smooth scroll or of streaming output, a retry coming due, typing
+//! having paused, a count of seconds on screen moving on, sampling
+//! running jobs, a toast's time being up, sweeping up after jobs, the
+//! clock on the top line turning over, or saving the buffers. Each need
+//! is a deadline; the loop sleeps until the soonest, or a long while if
+//! there is none.
+
+use std::{
+    cmp::Reverse,
+    collections::BinaryHeap,
+    time::{Duration, Instant},
+};
+
+use crate::{Model, PickerKind, smooth};
+
+/// How long to sleep with nothing due.
+pub const IDLE: Duration = Duration::from_secs(10);
+/// Time between redraws while output streams in, so a flood of output
+/// isn't drawn chunk by chunk.
+pub const STREAM_FRAME: Duration = Duration::from_millis(33);
+const SECOND: Duration = Duration::from_secs(1);
+
+/// What the loop wakes for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Wake {
+    /// The next frame of a smooth scroll.
+    Scroll,
+    /// Drawing output that came in since the last frame.
+    Stream,
+    /// A failed command's next attempt.
+    Retry,
+    /// Checking the command, typing having paused.
+    Lint,
+    /// Listing where `cd` is going, typing having paused.
+    Peek,
+    /// A count of seconds on screen going up or down.
+    Second,
+    /// Sampling running jobs' CPU and memory.
+    Sample,
+    /// A toast's time being up.
+    Toast,
+    /// Letting go of what ended jobs held.
+    Sweep,
+    /// The clock on the top line turning over.
+    Clock,
+    /// Saving the buffers; see `autosave`.
+    Autosave,
+    /// Previewing pipeline stages, typing having paused.
+    Preview,
+}
+
+#[derive(Debug, Default)]
+pub struct Schedule {
+    deadlines: BinaryHeap<Reverse<(Instant, Wake)>>,
+    /// When the screen was last drawn.
+    drawn: Option<Instant>,
+    /// Output has come in since then.
+    streamed: bool,
+}
+
+impl Schedule {
+    pub fn push(&mut self, at: Instant, wake: Wake) {
+        self.deadlines.push(Reverse((at, wake)));
+    }
+
+    /// How long to wait from `now` for the soonest deadline.
+    pub fn timeout(&self, now: Instant) -> Duration {
+        self.deadlines
+            .peek()
+            .map_or(IDLE, |Reverse((at, _))| at.saturating_duration_since(now))
+            .min(IDLE)
+    }
+
+    /// Take what's due by `now`.
+    pub fn due(&mut self, now: Instant) -> Vec<Wake> {
+        let mut due = Vec::new();
+        while let Some(Reverse((at, wake))) = self.deadlines.peek().copied()
+            && at <= now
+        {
+            self.deadlines.pop();
+            due.push(wake);
+        }
+        due
+    }
+
+    pub fn drawn(&mut self, now: Instant) {
+        self.drawn = Some(now);
+        self.streamed = false;
+    }
+
+    pub fn streamed(&mut self) {
+        self.streamed = true;
+    }
+
+    /// Whether output that came in now should wait for the next frame.
+    pub fn holding(&self, now: Instant) -> bool {
+        self.drawn.is_some_and(|drawn| now < drawn + STREAM_FRAME)
+    }
+}
+
+/// The next whole second after `now` counting from `start`.
+fn next_second(start: Instant, now: Instant) -> Instant {
+    let elapsed = now.saturating_duration_since(start).as_secs();
+    start + SECOND * (elapsed as u32 + 1)
+}
+
+/// When a countdown of whole seconds, rounded up, to `end` next changes.
+fn next_countdown(end: Instant, now: Instant) -> Instant {
+    let left = end.saturating_duration_since(now).as_secs();
+    end - SECOND * left as u32
+}
+
+impl Model {
+    /// Fill the schedule afresh with what's needed as of `now`.
+    pub fn plan(&mut self, now: Instant) {
+        let mut schedule = std::mem::take(&mut self.schedule);
+        schedule.deadlines.clear();
+        let drawn = schedule.drawn.unwrap_or(now);
+        if self.scrolling() {
+            schedule.push(drawn + smooth::FRAME, Wake::Scroll);
+        }
+        if schedule.streamed {
+            schedule.push(drawn + STREAM_FRAME, Wake::Stream);
+        }
+        if let Some(at) = self.check_due() {
+            schedule.push(at, Wake::Lint);
+        }
+        if let Some(at) = self.cd_due() {
+            schedule.push(at, Wake::Peek);
+        }
+        if let Some(at) = self.preview_due() {
+            schedule.push(at, Wake::Preview);
+        }
+        let jobs_open = self.picker.as_ref().map(|p| p.kind) == Some(PickerKind::Jobs);
+        for (i, output) in self.outputs.iter().enumerate() {
+            if let Some(next) = output.retry.as_ref().and_then(|r| r.next) {
+                schedule.push(next, Wake::Retry);
+                schedule.push(next_countdown(next, now), Wake::Second);
+            }
+            // running times are in the title, and all of them in `jobs`
+            let viewed = i == self.viewing_output || jobs_open;
+            let counting = output.running || output.rerun_note().is_some();
+            if let Some(started) = output.started.filter(|_| viewed && counting) {
+                schedule.push(next_second(started, now), Wake::Second);
+            }
+        }
+        if let Some(at) = self.next_sample(now) {
+            schedule.push(at, Wake::Sample);
+        }
+        if let Some(at) = self.toast_due() {
+            schedule.push(at, Wake::Toast);
+        }
+        if let Some(at) = self.reaper.due(now) {
+            schedule.push(at, Wake::Sweep);
+        }
+        if let Some(at) = self.clock_due(now) {
+            schedule.push(at, Wake::Clock);
+        }
+        if let Some(at) = self.autosave_due() {
+            schedule.push(at, Wake::Autosave);
+        }
+        if jobs_open {
+            schedule.push(now + SECOND, Wake::Second);
+        }
+        self.schedule = schedule;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::retry::Retry;
+
+    fn timeout(model: &mut Model, now: Instant) -> Duration {
+        model.plan(now);
+        model.schedule.timeout(now)
+    }
+
+    /// A model with no clock on the top line to wake for.
+    fn without_clock() -> Model {
+        let mut model = Model::default();
+        model.config.clock = None;
+        model
+    }
+
+    #[test]
+    fn idle_sleeps_long() {
+        let mut model = without_clock();
+        let now = Instant::now();
+        assert_eq!(timeout(&mut model, now), IDLE);
+        // a finished command has nothing counting
+        model.push_output("ls".into(), "a\n".into());
+        model.outputs[0].started = Some(now - SECOND * 5);
+        assert_eq!(timeout(&mut model, now), IDLE);
+    }
+
+    #[test]
+    fn running_jobs_wake_on_the_second() {
+        let mut model = without_clock();
+        let now = Instant::now();
+        model.push_output("sleep 9".into(), String::new());
+        model.outputs[0].running = true;
+        model.outputs[0].started = Some(now - Duration::from_millis(2250));
+        assert_eq!(timeout(&mut model, now), Duration::from_millis(750));
+        // not while another buffer is viewed
+        model.push_output("ls".into(), String::new());
+        assert_eq!(timeout(&mut model, now), IDLE);
+    }
+
+    #[test]
+    fn the_clock_wakes_only_when_it_changes() {
+        let mut model = Model::default();
+        let now = Instant::now();
+        model.config.clock = Some("%H:%M:%S".into());
+        assert!(timeout(&mut model, now) <= SECOND);
+        model.config.clock = Some("%H:%M".into());
+        model.plan(now);
+        let due = model.schedule.deadlines.peek().map(|Reverse((at, _))| *at);
+        assert!(due.is_some_and(|at| at > now && at <= now + SECOND * 60));
+    }
+
+    #[test]
+    fn a_retry_counts_down_then_runs() {
+        let mut model = without_clock();
+        let now = Instant::now();
+        model.push_output("make".into(), String::new());
+        model.outputs[0].retry = Some(Retry {
+            attempt: 1,
+            attempts: 3,
+            next: Some(now + Duration::from_millis(1400)),
+        });
+        // the countdown shows 2s, then 1s
+        assert_eq!(timeout(&mut model, now), Duration::from_millis(400));
+        let later = now + Duration::from_millis(1000);
+        assert_eq!(timeout(&mut model, later), Duration::from_millis(400));
+        let due = now + Duration::from_millis(1400);
+        model.plan(due);
+        assert_eq!(model.schedule.due(due), [Wake::Retry, Wake::Second]);
+    }
+
+    #[test]
+    fn typing_scrolling_and_streaming_wake_soon() {
+        let mut model = without_clock();
+        let now = Instant::now();
+        model.current_command = "ls".into();
+        model.recheck_command();
+        let check = timeout(&mut model, now);
+        assert!(check >= Duration::from_millis(300) && check < Duration::from_millis(400));
+
+        model.schedule.drawn(now);
+        model.schedule.streamed();
+        assert!(model.schedule.holding(now + Duration::from_millis(10)));
+        assert_eq!(timeout(&mut model, now), STREAM_FRAME);
+
+        model.config.smooth_scroll = true;
+        model.push_output("seq 100".into(), "1\n".repeat(100));
+        model.scroll_to(0, 0);
+        assert_eq!(timeout(&mut model, now), smooth::FRAME);
+    }
+}
@@ -0,0 +1,210 @@
+//! `:scrollback`: every buffer in one view, like a plain terminal's
+//! scrollback, each under a prompt line with its command. The view has one
+//! scroll position for all of them; the buffer the cursor is in (or, from
+//! the command line, the one at the bottom of the view) is the viewed one,
+//! so commands acting on the viewed buffer act on that.
+
+use ratatui::{
+    style::{Style, Stylize},
+    text::{Line, Text},
+};
+
+use crate::{Cursor, Model, display, title};
+
+/// Where the scrollback view is.
+#[derive(Debug, Default)]
+pub struct View {
+    /// The line at the top of the view, counting prompt lines.
+    top: usize,
+    /// Keep the end in view as output comes in.
+    follow: bool,
+}
+
+/// A line of the scrollback: the prompt line of an output, or one of its
+/// lines.
+#[derive(Debug, PartialEq)]
+pub struct Place {
+    pub output: usize,
+    pub line: Option<usize>,
+}
+
+impl Model {
+    /// Where each output's prompt line is.
+    fn prompt_lines(&self) -> Vec<usize> {
+        let mut next = 0;
+        self.outputs
+            .iter()
+            .map(|o| {
+                let start = next;
+                next += 1 + o.line_count();
+                start
+            })
+            .collect()
+    }
+
+    pub fn scrollback_len(&self) -> usize {
+        self.outputs.iter().map(|o| 1 + o.line_count()).sum()
+    }
+
+    /// The top line when the end is in view.
+    fn scrollback_bottom(&self) -> usize {
+        self.scrollback_len().saturating_sub(self.height as usize)
+    }
+
+    /// The line at the top of the scrollback view, if it's shown.
+    pub fn scrollback_top(&self) -> Option<usize> {
+        let view = self.scrollback.as_ref()?;
+        let bottom = self.scrollback_bottom();
+        Some(match view.follow {
+            true => bottom,
+            false => view.top.min(bottom),
+        })
+    }
+
+    /// Which output line `line` of the scrollback belongs to.
+    pub fn locate(&self, line: usize) -> Option<Place> {
+        let starts = self.prompt_lines();
+        let output = starts.iter().rposition(|&start| start <= line)?;
+        let within = line - starts[output];
+        let count = self.outputs[output].line_count();
+        (within <= count).then(|| Place {
+            output,
+            line: within.checked_sub(1),
+        })
+    }
+
+    /// The output line on screen row `y` of the scrollback view; a prompt
+    /// line has none.
+    pub fn scrollback_line(&self, y: u16) -> Option<&str> {
+        let place = self.locate(self.scrollback_top()? + y as usize)?;
+        self.outputs[place.output].line(place.line?)
+    }
+
+    /// `:scrollback`: switch between one buffer at a time and all of them
+    /// in one view, keeping the line under the cursor (or at the top) where
+    /// it is on screen.
+    pub fn toggle_scrollback(&mut self) -> String {
+        let row = match self.cursor {
+            Cursor::OutputBuffer(_, y) => y,
+            Cursor::CommandLine(_, _) => 0,
+        };
+        match self.scrollback_top() {
+            Some(top) => {
+                let place = self.locate(top + row as usize);
+                let following = self.scrollback.take().is_some_and(|v| v.follow);
+                if let Some(place) = place.filter(|_| !following) {
+                    let line = place.line.unwrap_or(0);
+                    self.viewing_output = place.output;
+                    let scroll = line.saturating_sub(row as usize);
+                    self.outputs[place.output].set_scroll(scroll);
+                    if let Cursor::OutputBuffer(x, _) = self.cursor {
+                        self.cursor = Cursor::OutputBuffer(x, (line - scroll) as u16);
+                    }
+                } else if let Some(last) = self.outputs.len().checked_sub(1) {
+                    self.viewing_output = last;
+                    let bottom = self.bottom(last);
+                    self.outputs[last].set_scroll(bottom);
+                }
+                self.clamp_output_cursor();
+                "one buffer at a time".into()
+            }
+            None => {
+                let Some(output) = self.outputs.get(self.viewing_output) else {
+                    self.scrollback = Some(View {
+                        top: 0,
+                        follow: true,
+                    });
+                    return "all buffers in one view".into();
+                };
+                let scroll = output.scroll.0;
+                let start = self.prompt_lines()[self.viewing_output];
+                let last = self.viewing_output + 1 == self.outputs.len();
+                self.scrollback = Some(View {
+                    top: start + 1 + scroll,
+                    follow: last && scroll >= self.bottom(self.viewing_output),
+                });
+                self.clamp_output_cursor();
+                "all buffers in one view".into()
+            }
+        }
+    }
+
+    /// Move the scrollback view to `top`, following the end if it gets
+    /// there.
+    pub fn scroll_scrollback(&mut self, top: usize) {
+        let bottom = self.scrollback_bottom();
+        if let Some(view) = &mut self.scrollback {
+            view.top = top.min(bottom);
+            view.follow = top >= bottom;
+        }
+        self.clamp_output_cursor();
+    }
+
+    /// Ctrl-d and Ctrl-u in the scrollback.
+    pub fn scroll_scrollback_by(&mut self, lines: isize) {
+        if let Some(top) = self.scrollback_top() {
+            self.scroll_scrollback(top.saturating_add_signed(lines));
+        }
+    }
+
+    /// Ctrl-n and Ctrl-p in the scrollback: bring the next or previous
+    /// prompt line to the top.
+    pub fn scrollback_to_output(&mut self, forward: bool) {
+        let Some(top) = self.scrollback_top() else {
+            return;
+        };
+        let starts = self.prompt_lines();
+        let start = match forward {
+            true => starts.iter().find(|&&s| s > top),
+            false => starts.iter().rev().find(|&&s| s < top),
+        };
+        if let Some(&start) = start {
+            self.scroll_scrollback(start);
+        }
+    }
+
+    /// Make the output the cursor is in the viewed one, or from the command
+    /// line the one at the bottom of the view.
+    pub fn follow_scrollback_cursor(&mut self) {
+        let Some(top) = self.scrollback_top() else {
+            return;
+        };
+        let row = match self.cursor {
+            Cursor::OutputBuffer(_, y) => y as usize,
+            Cursor::CommandLine(_, _) => (self.height as usize).saturating_sub(1),
+        };
+        let line = (top + row).min(self.scrollback_len().saturating_sub(1));
+        if let Some(place) = self.locate(line) {
+            self.viewing_output = place.output;
+        }
+    }
+
+    /// What's on screen of the scrollback, with a prompt line before each
+    /// output.
+    pub fn scrollback_text(&self) -> Text<'static> {
+        let Some(top) = self.scrollback_top() else {
+            return Text::default();
+        };
+        (top..top + self.height as usize)
+            .map_while(|line| self.locate(line))
+            .map(|place| {
+                let output = &self.outputs[place.output];
+                match place.line {
+                    Some(line) => {
+                        let settings = output.display_settings(&self.config);
+                        let text = output.line(line).unwrap_or("");
+                        Line::raw(display::render_line(text, settings))
+                    }
+                    None => {
+                        let prompt = format!("❯ {}", title(output, &self.config.redact));
+                        let style = match output.failed() {
+                            true => Style::new().red(),
+                            false => Style::new(),
+                        };
+                        Line::styled(prompt, style.bold())
+                    }
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,133 @@
+//! Looking back at how a buffer's output stood earlier on. While a job
+//! writes, the length of its output is noted every `checkpoint_interval`;
+//! `[[`, `]]` and `:at HH:MM` freeze the view at one of those points.
+
+use std::time::SystemTime;
+
+use crate::{Model, Output, clock};
+
+/// How much output there was at a moment.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint {
+    time: SystemTime,
+    /// Length of `stdout`, which only grows while the job runs.
+    len: usize,
+}
+
+impl Output {
+    /// Note how much output there is now, if it's been long enough since
+    /// the last checkpoint. The oldest are dropped beyond `limit`.
+    pub fn checkpoint(&mut self, interval: u64, limit: usize) {
+        let now = SystemTime::now();
+        let due = self.checkpoints.last().is_none_or(|last| {
+            now.duration_since(last.time)
+                .is_ok_and(|d| d.as_secs() >= interval)
+        });
+        if interval == 0 || limit == 0 || !due {
+            return;
+        }
+        if self.checkpoints.len() >= limit {
+            self.checkpoints.remove(0);
+        }
+        self.checkpoints.push(Checkpoint {
+            time: now,
+            len: self.stdout.len(),
+        });
+    }
+
+    /// Forget what the first `cut` bytes of the output were, now they're
+    /// gone.
+    pub fn drop_checkpoints(&mut self, cut: usize) {
+        self.checkpoints.retain(|point| point.len > cut);
+        for point in self.checkpoints.iter_mut().chain(&mut self.frozen) {
+            point.len = point.len.saturating_sub(cut);
+        }
+    }
+
+    /// The output as of the checkpoint the view is frozen at.
+    pub fn frozen_text(&self) -> Option<&str> {
+        let point = self.frozen?;
+        Some(&self.stdout[..point.len.min(self.stdout.len())])
+    }
+
+    /// When the view is frozen at, for the title.
+    pub fn frozen_note(&self) -> Option<String> {
+        let point = self.frozen?;
+        Some(format!("as of {}, frozen", clock::format(point.time)))
+    }
+
+    /// Forget the checkpoints, when the output they measure is gone.
+    pub fn clear_checkpoints(&mut self) {
+        self.checkpoints.clear();
+        self.frozen = None;
+    }
+}
+
+impl Model {
+    /// Freeze the viewed buffer at `point`, or go back to the live view.
+    fn freeze(&mut self, point: Option<Checkpoint>) -> String {
+        let index = self.viewing_output;
+        let Some(output) = self.outputs.get_mut(index) else {
+            return "no buffer".into();
+        };
+        output.frozen = point;
+        output.line_index.changed();
+        let status = match point {
+            Some(point) => format!("viewing as of {} (frozen)", clock::format(point.time)),
+            None => "viewing live output".into(),
+        };
+        // like scrolling to the end of new output, following it again
+        let bottom = self.bottom(index);
+        self.scroll_to(index, bottom);
+        status
+    }
+
+    /// `[[`: back a checkpoint in the viewed buffer.
+    pub fn scrub_back(&mut self) {
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return;
+        };
+        let before = |p: &&Checkpoint| output.frozen.is_none_or(|f| p.time < f.time);
+        let status = match output.checkpoints.iter().rev().find(before) {
+            Some(point) => self.freeze(Some(*point)),
+            None if output.checkpoints.is_empty() => "no checkpoints in this buffer".into(),
+            None => "at the oldest checkpoint".into(),
+        };
+        self.status = Some(status);
+    }
+
+    /// `]]`: on a checkpoint, and past the last one back to the live view.
+    pub fn scrub_forward(&mut self) {
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return;
+        };
+        let Some(frozen) = output.frozen else {
+            self.status = Some("already viewing live output".into());
+            return;
+        };
+        let after = output.checkpoints.iter().find(|p| p.time > frozen.time);
+        self.status = Some(self.freeze(after.copied()));
+    }
+
+    /// `:at HH:MM[:SS]` freezes the viewed buffer as it stood then, and
+    /// `:at` alone goes back to the live view.
+    pub fn view_at(&mut self, time: &str) -> String {
+        if time.is_empty() || time == "live" {
+            return self.freeze(None);
+        }
+        let Some(time) = clock::parse(time) else {
+            return "usage: :at HH:MM[:SS], or :at to go back to live output".into();
+        };
+        let Some(output) = self.outputs.get(self.viewing_output) else {
+            return "no buffer".into();
+        };
+        let point = output.checkpoints.iter().rev().find(|p| p.time <= time);
+        match (point, output.checkpoints.first()) {
+            (Some(point), _) => self.freeze(Some(*point)),
+            (None, Some(first)) => {
+                format!("the first checkpoint is at {}", clock::format(first.time))
+            }
+            (None, None) => "no checkpoints in this buffer".into(),
+        }
+    }
+}
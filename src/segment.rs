@@ -0,0 +1,271 @@
+//! Prompt segments: small commands whose output is shown before the
+//! prompt or on the top line, like the git branch or the kubernetes
+//! context. Each is set in the config as `segment_NAME = WHERE TTL
+//! COMMAND`, WHERE being `prompt` or `top` and TTL how long what it gave
+//! holds, as in `segment_k8s = top 30s kubectl config current-context`.
+//! They're run on threads of their own after a `cd` or a command ending,
+//! unless what they gave here last is younger than their TTL, and what's
+//! shown is whatever they gave last: dimmed if it was in another
+//! directory. A command that fails, takes too long or prints nothing
+//! shows nothing. `git`, the branch, is set by default;
+//! `segment_git = off` takes it away.
+
+use std::{
+    collections::{HashMap, HashSet},
+    env,
+    io::Read,
+    path::{Path, PathBuf},
+    process::Stdio,
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, Instant},
+};
+
+use ratatui::{style::Stylize, text::Span};
+
+use crate::{Model, config, event::AppEvent, shell};
+
+/// How long a segment's command may take before it's given up on.
+const TIMEOUT: Duration = Duration::from_secs(2);
+/// Most characters a segment shows.
+const WIDTH: usize = 30;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Place {
+    /// Before the prompt on the command line.
+    Prompt,
+    /// On the top line, by the clock.
+    Top,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    pub name: String,
+    pub place: Place,
+    pub ttl: Duration,
+    pub command: String,
+}
+
+/// The segments set by default.
+pub fn defaults() -> Vec<Segment> {
+    vec![Segment {
+        name: "git".into(),
+        place: Place::Prompt,
+        ttl: Duration::ZERO,
+        command: "git branch --show-current".into(),
+    }]
+}
+
+/// The segment `segment_NAME = value` sets, or `None` for `off`.
+pub fn parse(name: &str, value: &str) -> Result<Option<Segment>, String> {
+    if value == "off" {
+        return Ok(None);
+    }
+    let usage = || format!("segment_{name} must be prompt or top, a TTL like 30s, and a command");
+    let mut words = value.splitn(3, char::is_whitespace);
+    let (Some(place), Some(ttl), Some(command)) = (words.next(), words.next(), words.next()) else {
+        return Err(usage());
+    };
+    let place = match place {
+        "prompt" => Place::Prompt,
+        "top" => Place::Top,
+        _ => return Err(usage()),
+    };
+    let seconds = config::parse_seconds(ttl);
+    let (Some(seconds), false) = (seconds, name.is_empty() || command.trim().is_empty()) else {
+        return Err(usage());
+    };
+    Ok(Some(Segment {
+        name: name.into(),
+        place,
+        ttl: Duration::from_secs(seconds),
+        command: command.trim().into(),
+    }))
+}
+
+/// What a segment last gave, where and when.
+#[derive(Debug)]
+struct Cached {
+    text: String,
+    dir: PathBuf,
+    at: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Segments {
+    cache: HashMap<String, Cached>,
+    /// Being run, by name.
+    pending: HashSet<String>,
+}
+
+impl Segments {
+    /// Whether `segment` is to be run again in `dir`.
+    fn due(&self, segment: &Segment, dir: &Path, now: Instant) -> bool {
+        if self.pending.contains(&segment.name) {
+            return false;
+        }
+        self.cache
+            .get(&segment.name)
+            .is_none_or(|c| c.dir != *dir || now.duration_since(c.at) >= segment.ttl)
+    }
+}
+
+/// What to show of a command's output: its first line with something on
+/// it, without controls, cut short.
+fn shown(output: &str) -> String {
+    let line = output.lines().map(str::trim).find(|l| !l.is_empty());
+    let line: String = line
+        .unwrap_or("")
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect();
+    match line.char_indices().nth(WIDTH) {
+        Some((i, _)) => format!("{}…", &line[..i]),
+        None => line,
+    }
+}
+
+/// Run `command` in `dir`, for what it printed if it worked in time.
+fn run(command: &str, dir: &Path) -> Option<String> {
+    let parsed = shell::parse(command, |name| env::var(name).ok(), dir)?;
+    let mut child = shell::command(parsed)
+        .current_dir(dir)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let reader = thread::spawn(move || {
+        let mut text = String::new();
+        stdout.read_to_string(&mut text).ok().map(|_| text)
+    });
+    let deadline = Instant::now() + TIMEOUT;
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break status,
+            Ok(None) if Instant::now() < deadline => thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    };
+    let text = reader.join().ok()??;
+    status.success().then(|| shown(&text))
+}
+
+/// Run `segment` in `dir` in the background; what it gives comes back as
+/// `AppEvent::Segment`.
+fn spawn(segment: &Segment, dir: PathBuf, events: Sender<AppEvent>) {
+    let (name, command) = (segment.name.clone(), segment.command.clone());
+    thread::spawn(move || {
+        let text = run(&command, &dir).unwrap_or_default();
+        let _ = events.send(AppEvent::Segment { name, dir, text });
+    });
+}
+
+impl Model {
+    /// Run the segments whose text is out of date here, after a `cd` or a
+    /// command ending.
+    pub fn refresh_segments(&mut self) {
+        let (Some(events), Ok(dir)) = (&self.events, env::current_dir()) else {
+            return;
+        };
+        let now = Instant::now();
+        for segment in &self.config.segments {
+            if self.segments.due(segment, &dir, now) {
+                self.segments.pending.insert(segment.name.clone());
+                spawn(segment, dir.clone(), events.clone());
+            }
+        }
+    }
+
+    /// What segment `name` gave, run in `dir`.
+    pub fn segment_done(&mut self, name: String, dir: PathBuf, text: String) {
+        self.segments.pending.remove(&name);
+        let moved = env::current_dir().is_ok_and(|cwd| cwd != dir);
+        let at = Instant::now();
+        self.segments.cache.insert(name, Cached { text, dir, at });
+        // it was run before a cd
+        if moved {
+            self.refresh_segments();
+        }
+    }
+
+    /// The segments shown at `place`, each with a blank after it.
+    pub fn segment_spans(&self, place: Place) -> Vec<Span<'static>> {
+        let cwd = env::current_dir().ok();
+        self.config
+            .segments
+            .iter()
+            .filter(|s| s.place == place)
+            .filter_map(|s| self.segments.cache.get(&s.name))
+            .filter(|c| !c.text.is_empty())
+            .map(|c| {
+                let span = Span::raw(format!("{} ", c.text));
+                match Some(&c.dir) == cwd.as_ref() {
+                    true => span,
+                    false => span.dim(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn segments_from_the_config() {
+        assert_eq!(
+            parse("k8s", "top 30s kubectl config current-context"),
+            Ok(Some(Segment {
+                name: "k8s".into(),
+                place: Place::Top,
+                ttl: Duration::from_secs(30),
+                command: "kubectl config current-context".into(),
+            }))
+        );
+        let ttl = |value| parse("x", value).map(|s| s.unwrap().ttl);
+        assert_eq!(ttl("prompt 2m node -v"), Ok(Duration::from_secs(120)));
+        assert_eq!(ttl("prompt 5 node -v"), Ok(Duration::from_secs(5)));
+        assert_eq!(parse("git", "off"), Ok(None));
+        for bad in ["left 5s ls", "top soon ls", "top 5s", "top 5s  "] {
+            assert!(parse("x", bad).is_err(), "{bad}");
+        }
+    }
+
+    #[test]
+    fn what_a_segment_shows() {
+        assert_eq!(shown("\n  main  \nother\n"), "main");
+        assert_eq!(shown("a\x1b]0;title\x07b"), "a]0;titleb");
+        assert_eq!(shown(&"x".repeat(40)), format!("{}…", "x".repeat(30)));
+        assert_eq!(shown(""), "");
+        // a command that can't be started shows nothing
+        assert_eq!(run("no-such-program-for-shim", &env::temp_dir()), None);
+    }
+
+    #[test]
+    fn a_segment_runs_again_when_out_of_date() {
+        let segment = parse("k8s", "top 30s kubectl").unwrap().unwrap();
+        let (here, there) = (PathBuf::from("/here"), PathBuf::from("/there"));
+        let now = Instant::now();
+        let mut segments = Segments::default();
+        assert!(segments.due(&segment, &here, now));
+        segments.pending.insert("k8s".into());
+        assert!(!segments.due(&segment, &here, now));
+        segments.pending.clear();
+        let cached = Cached {
+            text: "prod".into(),
+            dir: here.clone(),
+            at: now,
+        };
+        segments.cache.insert("k8s".into(), cached);
+        assert!(!segments.due(&segment, &here, now + Duration::from_secs(29)));
+        assert!(segments.due(&segment, &here, now + Duration::from_secs(30)));
+        assert!(segments.due(&segment, &there, now));
+    }
+}
@@ -0,0 +1,283 @@
+//! Selecting output with `v` and yanking it with `y`. With `wrap` a line
+//! can take several screen rows, so what looks like three lines may be
+//! one: the selection is kept as positions in lines, turned into screen
+//! rows and columns only to be drawn, and yanked text has a newline only
+//! where a line really ends. Screen positions map back with `to_logical`.
+
+use std::ops::Range;
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    style::{Style, Stylize},
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{Cursor, Model, display};
+
+/// A cursor position in a line of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Point {
+    pub line: usize,
+    pub index: usize,
+}
+
+/// Where screen row `row`, column `col` falls in `lines`, counting rows
+/// from the first of them. Past the end of a row is its last position, so
+/// blanks the row ends in are taken even where nothing is drawn.
+pub fn to_logical<'a>(
+    lines: impl IntoIterator<Item = &'a str>,
+    width: usize,
+    settings: display::Settings,
+    row: usize,
+    col: usize,
+) -> Option<Point> {
+    let mut row = row;
+    for (i, line) in lines.into_iter().enumerate() {
+        let rows = display::rows(line, width, settings);
+        if row < rows.len() {
+            let index = display::index_at(line, &rows, row, col, settings);
+            return Some(Point { line: i, index });
+        }
+        row -= rows.len();
+    }
+    None
+}
+
+/// The screen rows of `line` that positions `from` to `to` take, with
+/// the columns they cover on each. A `to` past the last position takes
+/// the line's end, drawn as one more column.
+pub fn to_display(
+    line: &str,
+    from: usize,
+    to: usize,
+    width: usize,
+    settings: display::Settings,
+) -> Vec<(usize, Range<usize>)> {
+    let len = display::len(line);
+    let rows = display::rows(line, width, settings);
+    let column = |index| display::column(line, index, settings);
+    let mut spans = Vec::new();
+    for (r, row) in rows.iter().enumerate() {
+        let end = rows.get(r + 1).map_or(len, |next| next.start);
+        let last = r + 1 == rows.len();
+        let (first, upto) = (from.max(row.start), to.min(end.saturating_sub(1)));
+        let origin = column(row.start);
+        let mut cols = match first <= upto && first < end {
+            true => column(first) - origin..column(upto + 1) - origin,
+            false => {
+                let at = column(end) - origin;
+                at..at
+            }
+        };
+        if last && to >= len && from <= len {
+            if cols.is_empty() {
+                cols.start = column(from.max(row.start).min(len)) - origin;
+            }
+            cols.end = (column(len) - origin + 1).min(width.max(cols.start + 1));
+        }
+        if !cols.is_empty() {
+            spans.push((r, cols));
+        }
+    }
+    spans
+}
+
+/// The text from `start` to `end`, both taken, where `line(n)` is line
+/// `n`. Lines are joined with newlines, and wrapping adds none.
+pub fn text<'a>(start: Point, end: Point, line: impl Fn(usize) -> Option<&'a str>) -> String {
+    let mut text = String::new();
+    for n in start.line..=end.line {
+        let graphemes = line(n).unwrap_or("").graphemes(true);
+        let from = if n == start.line { start.index } else { 0 };
+        let taken = match n == end.line {
+            true => (end.index + 1).saturating_sub(from),
+            false => usize::MAX,
+        };
+        text.extend(graphemes.skip(from).take(taken));
+        if n < end.line {
+            text.push('\n');
+        }
+    }
+    text
+}
+
+impl Model {
+    /// The cursor in the viewed buffer, as a line of the buffer.
+    fn cursor_point(&self) -> Option<Point> {
+        let Cursor::OutputBuffer(x, y) = self.cursor else {
+            return None;
+        };
+        let output = self.outputs.get(self.viewing_output)?;
+        (self.scrollback.is_none()).then_some(Point {
+            line: output.scroll.0 + y as usize,
+            index: x as usize,
+        })
+    }
+
+    /// `v`: start selecting at the cursor, or stop.
+    pub fn toggle_visual(&mut self) {
+        self.visual = match self.selection() {
+            Some(_) => None,
+            None => self.cursor_point().map(|at| (self.viewing_output, at)),
+        };
+    }
+
+    /// What's selected, first position first, while the cursor is in the
+    /// buffer it was started in.
+    pub fn selection(&self) -> Option<(Point, Point)> {
+        let (output, anchor) = self.visual?;
+        let cursor = self
+            .cursor_point()
+            .filter(|_| output == self.viewing_output)?;
+        Some((anchor.min(cursor), anchor.max(cursor)))
+    }
+
+    /// `y` while selecting: yank what's selected.
+    pub fn yank_selection(&mut self) {
+        let Some((start, end)) = self.selection() else {
+            return;
+        };
+        let output = &self.outputs[self.viewing_output];
+        let mut end = end;
+        // to the end of a line that's cut off takes the rest of it
+        let long_line = self.config.long_line;
+        if end.index + 1 >= long_line
+            && output
+                .line(end.line)
+                .is_some_and(|line| display::cap(line, long_line).len() < line.len())
+        {
+            end.index = usize::MAX - 1;
+        }
+        let text = text(start, end, |n| output.line(n));
+        self.visual = None;
+        self.yank_text(text);
+    }
+}
+
+/// Shade what's selected in the output pane `pane`.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    let Some((start, end)) = model.selection() else {
+        return;
+    };
+    let Some(output) = model.outputs.get(model.viewing_output) else {
+        return;
+    };
+    let (width, settings) = (model.wrap_width(), model.output_settings());
+    let across = match model.wrapping() {
+        true => 0,
+        false => output.scroll.1 as usize,
+    };
+    let top = output.scroll.0;
+    let inner = pane.inner(Margin::new(1, 1));
+    let mut screen_row = 0;
+    for (i, line) in model.shown_lines().enumerate() {
+        if screen_row >= inner.height as usize {
+            break;
+        }
+        let n = top + i;
+        let rows = match model.wrapping() {
+            true => display::rows(line, width, settings).len(),
+            false => 1,
+        };
+        if (start.line..=end.line).contains(&n) {
+            let from = if n == start.line { start.index } else { 0 };
+            let to = if n == end.line { end.index } else { usize::MAX };
+            for (row, cols) in to_display(line, from, to, width, settings) {
+                let y = screen_row + row;
+                let cols = cols.start.saturating_sub(across)..cols.end.saturating_sub(across);
+                if y >= inner.height as usize || cols.is_empty() {
+                    continue;
+                }
+                let area = Rect {
+                    x: inner.x + cols.start as u16,
+                    y: inner.y + y as u16,
+                    width: cols.len() as u16,
+                    height: 1,
+                }
+                .intersection(inner);
+                frame.buffer_mut().set_style(area, Style::new().reversed());
+            }
+        }
+        screen_row += rows;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLAIN: display::Settings = display::Settings {
+        tab_stop: 8,
+        invisibles: false,
+        long_line: usize::MAX,
+    };
+
+    fn point(line: usize, index: usize) -> Point {
+        Point { line, index }
+    }
+
+    #[test]
+    fn screen_positions_land_in_wrapped_lines() {
+        // "abcd" "efgh" "ij" then "xy"
+        let lines = ["abcdefghij", "xy"];
+        let at = |row, col| to_logical(lines, 4, PLAIN, row, col);
+        assert_eq!(at(0, 0), Some(point(0, 0)));
+        assert_eq!(at(1, 2), Some(point(0, 6)));
+        assert_eq!(at(2, 1), Some(point(0, 9)));
+        // past the end of a row is its last position
+        assert_eq!(at(2, 3), Some(point(0, 9)));
+        assert_eq!(at(3, 1), Some(point(1, 1)));
+        assert_eq!(at(4, 0), None);
+        // without wrapping a row is a line
+        assert_eq!(
+            to_logical(lines, usize::MAX, PLAIN, 1, 7),
+            Some(point(1, 1))
+        );
+    }
+
+    #[test]
+    fn blanks_a_row_ends_in_are_taken() {
+        // "ab" "  " and the blanks aren't drawn as anything
+        let lines = ["ab  ", "c"];
+        let end = to_logical(lines, 2, PLAIN, 1, 5).unwrap();
+        assert_eq!(end, point(0, 3));
+        assert_eq!(text(point(0, 1), end, |n| lines.get(n).copied()), "b  ");
+        assert_eq!(to_display(lines[0], 1, 3, 2, PLAIN), [(0, 1..2), (1, 0..2)]);
+    }
+
+    #[test]
+    fn a_wrapped_line_yanks_without_newlines() {
+        let lines = ["abcdefghij", "xy", "", "z"];
+        let line = |n: usize| lines.get(n).copied();
+        // starting mid-row and across the wrap
+        assert_eq!(text(point(0, 2), point(0, 5), line), "cdef");
+        assert_eq!(text(point(0, 0), point(0, 9), line), "abcdefghij");
+        // lines end with newlines, the last one without
+        assert_eq!(text(point(0, 8), point(1, 0), line), "ij\nx");
+        assert_eq!(text(point(1, 1), point(3, 0), line), "y\n\nz");
+        assert_eq!(text(point(2, 0), point(2, 0), line), "");
+        assert_eq!(text(point(0, 9), point(0, 9), line), "j");
+    }
+
+    #[test]
+    fn selections_are_drawn_on_each_row_they_cross() {
+        let line = "abcdefghij";
+        // from mid-row across the wrap
+        assert_eq!(to_display(line, 2, 5, 4, PLAIN), [(0, 2..4), (1, 0..2)]);
+        assert_eq!(to_display(line, 4, 7, 4, PLAIN), [(1, 0..4)]);
+        assert_eq!(
+            to_display(line, 0, 9, 4, PLAIN),
+            [(0, 0..4), (1, 0..4), (2, 0..2)]
+        );
+        // on into the next line takes this one's end
+        assert_eq!(to_display(line, 9, usize::MAX, 4, PLAIN), [(2, 1..3)]);
+        assert_eq!(to_display("", 0, usize::MAX, 4, PLAIN), [(0, 0..1)]);
+        // the end of a full row has no room for it
+        assert_eq!(to_display("abcd", 3, usize::MAX, 4, PLAIN), [(0, 3..4)]);
+        // wide characters and tabs take their columns
+        assert_eq!(to_display("日本語", 1, 2, 4, PLAIN), [(0, 2..4), (1, 0..2)]);
+        assert_eq!(to_display("a\tb", 1, 1, usize::MAX, PLAIN), [(0, 1..8)]);
+        assert_eq!(to_display("a\tb", 2, 2, usize::MAX, PLAIN), [(0, 8..9)]);
+    }
+}
@@ -0,0 +1,429 @@
+//! The commands run this session, which can be saved with `:session FILE`
+//! and stepped through again with `:replay FILE` or `shim --replay FILE`.
+//! The file also keeps the `pushd` stack and the letter marks.
+
+use std::{fs, path::PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Cursor, Mode, Model, builtin, marks::Mark, registers::Registers};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Step {
+    pub command: String,
+    #[serde(default)]
+    pub cwd: Option<PathBuf>,
+    /// Exit status, once known; builtins have none.
+    #[serde(default)]
+    pub status: Option<i32>,
+    /// Tags its buffer had, given back to it when replayed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Its buffer's `:title` and `:note`, also given back when replayed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub note: Option<String>,
+    /// Lines bookmarked in its buffer.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub bookmarks: Vec<usize>,
+    /// The buffer the command is running in.
+    #[serde(skip)]
+    output: Option<usize>,
+    /// The buffer the command made, running or not.
+    #[serde(skip)]
+    buffer: Option<usize>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Session {
+    steps: Vec<Step>,
+    /// Only saved with `session_registers = true`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    registers: Option<Registers>,
+    /// The `pushd` stack, top last.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    dirs: Vec<PathBuf>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    marks: Vec<SavedMark>,
+}
+
+/// A letter mark in the buffer of one of the steps.
+#[derive(Debug, Serialize, Deserialize)]
+struct SavedMark {
+    name: char,
+    step: usize,
+    #[serde(flatten)]
+    mark: Mark,
+}
+
+/// Stepping through a saved session.
+#[derive(Debug)]
+pub struct Replay {
+    steps: Vec<Step>,
+    /// The step on the command line.
+    next: usize,
+    /// Buffers of replayed commands still running, with the status they
+    /// had when recorded.
+    running: Vec<(usize, Option<i32>)>,
+    mismatches: usize,
+    /// Marks set once their step's buffer is made again.
+    marks: Vec<SavedMark>,
+}
+
+impl Model {
+    /// Note that the command which started buffer `id`, if any, was run.
+    /// Saving or replaying sessions isn't itself part of one.
+    pub fn record_step(&mut self, command: String, cwd: Option<PathBuf>, id: usize) {
+        if command.trim().is_empty()
+            || matches!(builtin::lookup(&command), Some("session" | "replay"))
+        {
+            return;
+        }
+        let output = self.outputs.iter().find(|o| o.id == id);
+        self.session.push(Step {
+            command,
+            cwd,
+            status: output.and_then(|o| o.status),
+            tags: Vec::new(),
+            title: None,
+            note: None,
+            bookmarks: Vec::new(),
+            output: output.filter(|o| o.running).map(|o| o.id),
+            buffer: output.map(|o| o.id),
+        });
+    }
+
+    /// The step on the command line was run, starting buffer `id` if it
+    /// made one; move on to the next.
+    pub fn replay_ran(&mut self, id: usize) {
+        let Some(replay) = self.replay.as_mut() else {
+            return;
+        };
+        let Some(step) = replay.steps.get_mut(replay.next) else {
+            return;
+        };
+        let (step_index, recorded) = (replay.next, step.status);
+        let tags = std::mem::take(&mut step.tags);
+        let (title, note) = (step.title.take(), step.note.take());
+        let bookmarks = std::mem::take(&mut step.bookmarks);
+        replay.next += 1;
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.id == id) {
+            if !tags.is_empty() {
+                output.tags = tags;
+            }
+            if title.is_some() {
+                output.title = title;
+            }
+            if note.is_some() {
+                output.note = note;
+            }
+            output.bookmarks.extend(bookmarks);
+        }
+        if self.outputs.iter().any(|o| o.id == id) {
+            for saved in replay.marks.iter().filter(|m| m.step == step_index) {
+                let mut mark = saved.mark;
+                mark.output_id = id;
+                self.marks.insert(saved.name, mark);
+            }
+        }
+        match self.outputs.iter().find(|o| o.id == id) {
+            Some(o) if o.running => replay.running.push((id, recorded)),
+            Some(o) => {
+                let status = o.status;
+                self.compare_replayed(id, status, recorded);
+            }
+            None => {}
+        }
+        self.prefill_replay();
+    }
+
+    /// Fill in the status of a finished command and check it against the
+    /// replayed session.
+    pub fn finish_step(&mut self, id: usize, status: Option<i32>) {
+        if let Some(step) = self.session.iter_mut().find(|s| s.output == Some(id)) {
+            step.status = status;
+            step.output = None;
+        }
+        let Some(replay) = self.replay.as_mut() else {
+            return;
+        };
+        let Some(i) = replay.running.iter().position(|(o, _)| *o == id) else {
+            return;
+        };
+        let (_, recorded) = replay.running.remove(i);
+        self.compare_replayed(id, status, recorded);
+        self.prefill_replay();
+    }
+
+    fn compare_replayed(&mut self, id: usize, status: Option<i32>, recorded: Option<i32>) {
+        if recorded.is_none() || status == recorded {
+            return;
+        }
+        let Some(replay) = self.replay.as_mut() else {
+            return;
+        };
+        replay.mismatches += 1;
+        let command = self
+            .outputs
+            .iter()
+            .find(|o| o.id == id)
+            .map_or("", |o| o.command.as_str());
+        let show = |s: Option<i32>| s.map_or("none".into(), |s| s.to_string());
+        self.status = Some(format!(
+            "replay mismatch: {command} exited {}, recorded {}",
+            show(status),
+            show(recorded)
+        ));
+    }
+
+    pub fn save_session(&self, path: &str) -> String {
+        let mut marks: Vec<_> = self
+            .marks
+            .iter()
+            .filter_map(|(&name, &mark)| {
+                let step = self
+                    .session
+                    .iter()
+                    .position(|s| s.buffer == Some(mark.output_id))?;
+                Some(SavedMark { name, step, mark })
+            })
+            .collect();
+        marks.sort_by_key(|m| m.name);
+        let session = Session {
+            steps: self
+                .session
+                .iter()
+                .map(|s| {
+                    let buffer = self.outputs.iter().find(|o| Some(o.id) == s.buffer);
+                    Step {
+                        command: s.command.clone(),
+                        cwd: s.cwd.clone(),
+                        status: s.status,
+                        tags: buffer.map(|o| o.tags.clone()).unwrap_or_default(),
+                        title: buffer.and_then(|o| o.title.clone()),
+                        note: buffer.and_then(|o| o.note.clone()),
+                        bookmarks: buffer
+                            .map(|o| o.bookmarks.iter().copied().collect())
+                            .unwrap_or_default(),
+                        output: None,
+                        buffer: None,
+                    }
+                })
+                .collect(),
+            registers: self
+                .config
+                .session_registers
+                .then(|| self.registers.clone()),
+            dirs: self.dir_stack.clone(),
+            marks,
+        };
+        let json = match serde_json::to_string_pretty(&session) {
+            Ok(json) => json,
+            Err(e) => return format!("session: {e}"),
+        };
+        match fs::write(path, json) {
+            Ok(()) => format!("saved {} commands to {path}", session.steps.len()),
+            Err(e) => format!("session: {path}: {e}"),
+        }
+    }
+
+    pub fn start_replay(&mut self, path: &str) -> Result<(), String> {
+        let json = fs::read_to_string(path).map_err(|e| format!("replay: {path}: {e}"))?;
+        let session: Session =
+            serde_json::from_str(&json).map_err(|e| format!("replay: {path}: {e}"))?;
+        if let Some(registers) = session.registers.filter(|_| self.config.session_registers) {
+            self.registers = registers;
+        }
+        if !session.dirs.is_empty() {
+            self.dir_stack = session.dirs;
+        }
+        self.replay = Some(Replay {
+            steps: session.steps,
+            next: 0,
+            running: Vec::new(),
+            mismatches: 0,
+            marks: session.marks,
+        });
+        self.prefill_replay();
+        Ok(())
+    }
+
+    /// Put the next step on the command line, or finish once every step
+    /// has run.
+    fn prefill_replay(&mut self) {
+        let Some(replay) = self.replay.as_ref() else {
+            return;
+        };
+        match replay.steps.get(replay.next) {
+            Some(step) => {
+                self.current_command = step.command.clone();
+                self.viewing_command = None;
+                self.mode = Mode::Normal;
+                self.cursor = self.command_end();
+                self.clamp_command_cursor();
+            }
+            None if replay.running.is_empty() => {
+                let mismatches = replay.mismatches;
+                self.replay = None;
+                let done = format!("replay done, {mismatches} mismatched exit codes");
+                // keep a mismatch reported by the last command in view
+                self.status = Some(match self.status.take() {
+                    Some(status) => format!("{status}; {done}"),
+                    None => done,
+                });
+            }
+            None => {}
+        }
+    }
+
+    pub fn skip_replayed(&mut self) {
+        if let Some(replay) = self.replay.as_mut() {
+            replay.next += 1;
+        }
+        self.current_command.clear();
+        self.cursor = Cursor::CommandLine(0, 0);
+        self.prefill_replay();
+    }
+
+    pub fn stop_replay(&mut self) {
+        self.replay = None;
+        self.current_command.clear();
+        self.cursor = Cursor::CommandLine(0, 0);
+        self.status = Some("replay stopped".into());
+    }
+
+    /// Whether the command line holds a step waiting to be run.
+    pub fn replay_waiting(&self) -> bool {
+        self.replay.as_ref().is_some_and(|r| r.next < r.steps.len())
+    }
+
+    /// Shown at the top of the screen while replaying.
+    pub fn replay_summary(&self) -> Option<String> {
+        let replay = self.replay.as_ref()?;
+        Some(match replay.steps.get(replay.next) {
+            Some(_) => format!(
+                "replay {}/{} (Enter runs, s skips, q stops)",
+                replay.next + 1,
+                replay.steps.len()
+            ),
+            None => "replay: waiting for the last commands".into(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, process};
+
+    use super::*;
+
+    /// A model that ran `commands`, each making a buffer that exited 0.
+    fn ran(commands: &[&str]) -> Model {
+        let mut model = Model::default();
+        for command in commands {
+            let id = model.next_output_id;
+            model.push_output(command.to_string(), "out\n".into());
+            model.outputs[model.viewing_output].status = Some(0);
+            model.record_step(command.to_string(), None, id);
+        }
+        model
+    }
+
+    fn saved(model: &Model, name: &str) -> String {
+        let path = env::temp_dir().join(format!("shim-session-{name}-{}", process::id()));
+        let path = path.to_string_lossy().into_owned();
+        assert!(model.save_session(&path).starts_with("saved"));
+        path
+    }
+
+    #[test]
+    fn saving_and_replaying_keeps_the_setup() {
+        let mut model = ran(&["make", "  ", ":session s.json", "make test"]);
+        assert_eq!(model.session.len(), 2);
+        model.outputs[0].title = Some("build".into());
+        model.dir_stack = vec![PathBuf::from("/tmp")];
+        model.viewing_output = 0;
+        model.set_mark('a');
+        let path = saved(&model, "setup");
+
+        let mut next = Model::default();
+        next.start_replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(next.current_command, "make");
+        assert_eq!(next.dir_stack, [PathBuf::from("/tmp")]);
+        assert_eq!(
+            next.replay_summary().unwrap(),
+            "replay 1/2 (Enter runs, s skips, q stops)"
+        );
+        // the mark waits for its buffer to be made again
+        next.jump_to_mark('a');
+        assert_eq!(next.status.as_deref(), Some("mark not set: a"));
+        next.push_output("make".into(), String::new());
+        next.outputs[0].status = Some(0);
+        next.replay_ran(0);
+        assert_eq!(next.outputs[0].title.as_deref(), Some("build"));
+        assert_eq!(next.current_command, "make test");
+        next.push_output("make test".into(), String::new());
+        next.status = None;
+        next.jump_to_mark('a');
+        assert_eq!(next.status, None);
+        assert_eq!(next.viewing_output, 0);
+    }
+
+    #[test]
+    fn steps_can_be_skipped_or_the_replay_stopped() {
+        let path = saved(&ran(&["one", "two", "three"]), "skip");
+        let mut model = Model::default();
+        model.start_replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        model.skip_replayed();
+        assert_eq!(model.current_command, "two");
+        model.stop_replay();
+        assert!(model.replay.is_none());
+        assert_eq!(model.current_command, "");
+        assert_eq!(model.status.as_deref(), Some("replay stopped"));
+
+        // a file that's gone can't be replayed
+        model.start_replay(&path).unwrap_err();
+        // skipping the last step finishes
+        let path = saved(&ran(&["one"]), "last");
+        model.start_replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        model.status = None;
+        model.skip_replayed();
+        assert!(model.replay.is_none());
+        assert_eq!(
+            model.status.as_deref(),
+            Some("replay done, 0 mismatched exit codes")
+        );
+    }
+
+    #[test]
+    fn a_different_exit_code_is_flagged() {
+        let path = saved(&ran(&["make", "make test"]), "mismatch");
+        let mut model = Model::default();
+        model.start_replay(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        // one still running when its step is run
+        model.push_output("make".into(), String::new());
+        model.outputs[0].running = true;
+        model.replay_ran(0);
+        model.push_output("make test".into(), String::new());
+        model.outputs[1].status = Some(0);
+        model.replay_ran(1);
+        assert!(model.replay.is_some());
+        assert_eq!(
+            model.replay_summary().unwrap(),
+            "replay: waiting for the last commands"
+        );
+        model.finish_step(0, Some(2));
+        assert!(model.replay.is_none());
+        assert_eq!(
+            model.status.as_deref(),
+            Some(
+                "replay mismatch: make exited 2, recorded 0; replay done, 1 mismatched exit codes"
+            )
+        );
+    }
+}
@@ -1,8 +1,504 @@
-use portable_pty::{CommandBuilder, PtySize, PtySystem, native_pty_system};
-use std::process::{Command, Output};
+use std::{env, ops::Range, path::Path, process::Command};
 
-pub fn run(command: String) -> Option<Output> {
-    let mut split_command = command.split_whitespace();
-    let program = split_command.next()?;
-    Command::new(program).args(split_command).output().ok()
+use crate::glob;
+
+/// A command line after quoting and expansion, ready to spawn.
+#[derive(Debug, Default)]
+pub struct ParsedCommand {
+    pub program: String,
+    pub args: Vec<String>,
+    /// Every substitution made along the way, in order.
+    pub expansions: Vec<Expansion>,
+}
+
+#[derive(Debug)]
+pub enum Expansion {
+    Variable {
+        name: String,
+        value: Option<String>,
+    },
+    Tilde {
+        home: String,
+    },
+    Glob {
+        pattern: String,
+        matches: Vec<String>,
+    },
+}
+
+/// Tokenize `command` and expand variables, `~` and globs, without running
+/// anything. Backslash-newlines are joined; if the command still spans
+/// several lines it is a script for `$SHELL -c` (`cmd /C` on Windows),
+/// passed through unchanged.
+pub fn parse(
+    command: &str,
+    env: impl Fn(&str) -> Option<String>,
+    cwd: &Path,
+) -> Option<ParsedCommand> {
+    let command = command.replace("\\\n", " ");
+    if command.contains('\n') {
+        let (program, flag) = script_shell(&env);
+        return Some(ParsedCommand {
+            program,
+            args: vec![flag.into(), command],
+            expansions: Vec::new(),
+        });
+    }
+    let mut expansions = Vec::new();
+    let mut words = Vec::new();
+    for word in split(&command, &env, &mut expansions) {
+        if glob::is_pattern(&word) {
+            let matches = glob::expand(&word, cwd);
+            expansions.push(Expansion::Glob {
+                pattern: glob::unescape(&word),
+                matches: matches.clone(),
+            });
+            // like sh, a pattern that matches nothing is passed on as is
+            if matches.is_empty() {
+                words.push(glob::unescape(&word));
+            } else {
+                words.extend(matches);
+            }
+        } else {
+            words.push(glob::unescape(&word));
+        }
+    }
+    let mut words = words.into_iter();
+    Some(ParsedCommand {
+        program: words.next()?,
+        args: words.collect(),
+        expansions,
+    })
+}
+
+/// The stages of a pipeline, split at each `|` outside quotes. A command
+/// that isn't one, or that `$SHELL` runs, or has `||` or an empty stage,
+/// comes back whole.
+pub fn pipeline(command: &str) -> Vec<&str> {
+    if command.replace("\\\n", " ").contains('\n') {
+        return vec![command];
+    }
+    let mut stages = Vec::new();
+    let mut start = 0;
+    let mut quote = None;
+    let mut chars = command.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None | Some('"'), '\\') if !cfg!(windows) => {
+                chars.next();
+            }
+            (None, '#') if command[..i].ends_with(|c: char| c.is_whitespace()) || i == 0 => {
+                break;
+            }
+            (None, '|') if chars.peek().is_some_and(|(_, c)| *c == '|') => return vec![command],
+            (None, '|') => {
+                stages.push(command[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    stages.push(command[start..].trim());
+    if stages.len() == 1 || stages.iter().any(|s| s.is_empty()) {
+        return vec![command];
+    }
+    stages
+}
+
+/// Where each word of one line of a command starts and ends, quotes and
+/// backslashes included, as `split` would divide them. Comments are split
+/// into words like the rest, for editing.
+pub fn token_spans(line: &str) -> Vec<Range<usize>> {
+    let mut spans = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut chars = line.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            spans.extend(start.take().map(|s| s..i));
+            continue;
+        }
+        start.get_or_insert(i);
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            (None, '\\') if cfg!(windows) => {
+                chars.next_if(|(_, c)| c.is_whitespace() || matches!(c, '"' | '\''));
+            }
+            (None | Some('"'), '\\') => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    spans.extend(start.map(|s| s..line.len()));
+    spans
+}
+
+/// The words of a one-line `command` with quotes taken off and variables
+/// and `~` expanded, as `parse` would give them but with globs left
+/// unmatched, so nothing is read from disk. A glob comes back as `None`.
+pub fn words(command: &str, env: impl Fn(&str) -> Option<String>) -> Vec<Option<String>> {
+    split(command, &env, &mut Vec::new())
+        .into_iter()
+        .map(|word| (!glob::is_pattern(&word)).then(|| glob::unescape(&word)))
+        .collect()
+}
+
+/// The words of `command` as `words` gives them, but with globs left as
+/// they're written rather than matched or left out.
+pub fn unglobbed(command: &str, env: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    let command = command.replace("\\\n", " ");
+    split(&command, &env, &mut Vec::new())
+        .iter()
+        .map(|word| glob::unescape(word))
+        .collect()
+}
+
+/// Split into words, applying quotes and substituting variables and `~`.
+/// Wildcards that came from quotes are escaped with `\` so that only bare
+/// ones are treated as globs.
+fn split(
+    command: &str,
+    env: &impl Fn(&str) -> Option<String>,
+    expansions: &mut Vec<Expansion>,
+) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars().peekable();
+    let mut quote = None;
+    while let Some(c) = chars.next() {
+        if quote.is_none() && c.is_whitespace() {
+            words.extend(word.take());
+            continue;
+        }
+        // a comment, to the end of the line
+        if quote.is_none() && c == '#' && word.is_none() {
+            while chars.next_if(|c| *c != '\n').is_some() {}
+            continue;
+        }
+        let at_start = word.is_none();
+        let current = word.get_or_insert_with(String::new);
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(q), c) if q == c => quote = None,
+            // Windows paths are full of backslashes, so there they only
+            // escape what would otherwise end or split the word
+            (None, '\\') if cfg!(windows) => match chars.peek() {
+                Some(c) if c.is_whitespace() || matches!(c, '"' | '\'') => {
+                    push_escaped(current, chars.next())
+                }
+                _ => escape_into(current, "\\"),
+            },
+            (None, '\\') => push_escaped(current, chars.next()),
+            (Some('"'), '\\') => match chars.peek() {
+                Some('"' | '\\' | '$' | '`') => push_escaped(current, chars.next()),
+                _ => escape_into(current, "\\"),
+            },
+            (None | Some('"'), '$') => {
+                let name = variable_name(&mut chars);
+                if name.is_empty() {
+                    current.push('$');
+                } else {
+                    let value = env(&name);
+                    let expanded = value.clone().unwrap_or_default();
+                    expansions.push(Expansion::Variable { name, value });
+                    match quote {
+                        Some(_) => escape_into(current, &expanded),
+                        None => current.push_str(&expanded),
+                    }
+                }
+            }
+            (None, '~') if at_start && matches!(chars.peek(), None | Some('/' | '\\')) => {
+                match home(env) {
+                    Some(home) => {
+                        escape_into(current, &home);
+                        expansions.push(Expansion::Tilde { home });
+                    }
+                    None => current.push('~'),
+                }
+            }
+            (Some(_), c) => escape_into(current, &c.to_string()),
+            (None, c) => current.push(c),
+        }
+    }
+    words.extend(word);
+    words
+}
+
+fn push_escaped(word: &mut String, c: Option<char>) {
+    if let Some(c) = c {
+        escape_into(word, &c.to_string());
+    }
+}
+
+/// Append `text` with glob characters protected.
+fn escape_into(word: &mut String, text: &str) {
+    for c in text.chars() {
+        if matches!(c, '*' | '?' | '[' | '\\') {
+            word.push('\\');
+        }
+        word.push(c);
+    }
+}
+
+fn variable_name(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut name = String::new();
+    // `$?`, the last exit code
+    if chars.next_if_eq(&'?').is_some() {
+        return "?".into();
+    }
+    if chars.peek() == Some(&'{') {
+        chars.next();
+        for c in chars.by_ref() {
+            if c == '}' {
+                break;
+            }
+            name.push(c);
+        }
+        return name;
+    }
+    while let Some(c) = chars.peek().filter(|c| c.is_alphanumeric() || **c == '_') {
+        name.push(*c);
+        chars.next();
+    }
+    name
+}
+
+/// `text` quoted if need be so that `parse` gives it back as a single
+/// word, as is.
+pub fn quote(text: &str) -> String {
+    let plain = |c: char| c.is_alphanumeric() || "_-./=,:@+%".contains(c);
+    if !text.is_empty() && !text.starts_with('~') && text.chars().all(plain) {
+        return text.to_string();
+    }
+    format!("'{}'", text.replace('\'', r"'\''"))
+}
+
+/// The user's home directory, `%USERPROFILE%` on Windows.
+pub fn home(env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    env("HOME").or_else(|| {
+        if cfg!(windows) {
+            env("USERPROFILE")
+        } else {
+            None
+        }
+    })
+}
+
+/// The shell multi-line scripts are run with, and its flag for a command
+/// string.
+fn script_shell(env: impl Fn(&str) -> Option<String>) -> (String, &'static str) {
+    if cfg!(windows) {
+        (env("COMSPEC").unwrap_or("cmd.exe".into()), "/C")
+    } else {
+        (env("SHELL").unwrap_or("/bin/sh".into()), "-c")
+    }
+}
+
+/// File extensions that make a file executable on Windows, e.g. `.EXE`.
+pub fn executable_extensions(env: impl Fn(&str) -> Option<String>) -> Vec<String> {
+    if !cfg!(windows) {
+        return vec![String::new()];
+    }
+    let pathext = env("PATHEXT").unwrap_or(".COM;.EXE;.BAT;.CMD".into());
+    Some(String::new())
+        .into_iter()
+        .chain(pathext.split(';').map(|ext| ext.to_lowercase()))
+        .collect()
+}
+
+/// Where `program` would be found on `$PATH`.
+pub fn resolve(program: &str, env: impl Fn(&str) -> Option<String>) -> Option<String> {
+    if program.contains(std::path::is_separator) {
+        return Path::new(program).exists().then(|| program.to_string());
+    }
+    let extensions = executable_extensions(&env);
+    env::split_paths(&env("PATH")?)
+        .flat_map(|dir| {
+            extensions
+                .iter()
+                .map(move |ext| dir.join(format!("{program}{ext}")))
+        })
+        .find(|path| path.is_file())
+        .and_then(|path| path.to_str().map(String::from))
+}
+
+/// The process to start for a parsed command.
+pub fn command(parsed: ParsedCommand) -> Command {
+    let mut command = Command::new(parsed.program);
+    command.args(parsed.args);
+    command
+}
+
+/// How a command's quotes, brackets and block keywords stand at its end.
+#[derive(Default)]
+struct Balance {
+    quote: Option<char>,
+    escaped: bool,
+    /// Brackets open, and the fewest there were, below zero if one was
+    /// closed that wasn't open.
+    depth: i32,
+    lowest: i32,
+    blocks: i32,
+}
+
+/// `do`, `if` and `case` open a block, `done`, `fi` and `esac` close one.
+fn block(word: &str) -> i32 {
+    match word {
+        "do" | "if" | "case" => 1,
+        "done" | "fi" | "esac" => -1,
+        _ => 0,
+    }
+}
+
+fn balance(command: &str) -> Balance {
+    let mut b = Balance::default();
+    let mut word = String::new();
+    let mut comment = false;
+    for c in command.chars() {
+        if b.escaped {
+            b.escaped = false;
+            continue;
+        }
+        if comment {
+            comment = c != '\n';
+            continue;
+        }
+        match (b.quote, c) {
+            (Some('\''), '\'') | (Some('"'), '"') => b.quote = None,
+            (Some('"'), '\\') => b.escaped = true,
+            (Some(_), _) => {}
+            (None, '\\') => b.escaped = true,
+            (None, '\'' | '"') => b.quote = Some(c),
+            (None, '#') if word.is_empty() => comment = true,
+            (None, '(' | '{' | '[') => b.depth += 1,
+            (None, ')' | '}' | ']') => {
+                b.depth -= 1;
+                b.lowest = b.lowest.min(b.depth);
+            }
+            (None, c) if c.is_whitespace() || c == ';' => {
+                b.blocks += block(&word);
+                word.clear();
+            }
+            (None, c) => word.push(c),
+        }
+    }
+    b.blocks += block(&word);
+    b
+}
+
+/// Whether Enter should start a new line rather than submit: the command
+/// ends in a `\`, or leaves a quote, bracket or block keyword open.
+pub fn needs_continuation(command: &str) -> bool {
+    let balance = balance(command);
+    balance.escaped || balance.quote.is_some() || balance.depth > 0 || balance.blocks > 0
+}
+
+/// What's left open or closed too often in `command`, if anything.
+pub fn unbalanced(command: &str) -> Option<&'static str> {
+    let balance = balance(command);
+    match balance {
+        Balance { quote: Some(_), .. } => Some("unclosed quote"),
+        Balance { lowest: ..0, .. } => Some("unmatched closing bracket"),
+        Balance { depth: 1.., .. } => Some("unclosed bracket"),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Output;
+
+    fn run(line: &str) -> Output {
+        let cwd = env::current_dir().unwrap();
+        let parsed = parse(line, |name| env::var(name).ok(), &cwd).unwrap();
+        command(parsed).output().unwrap()
+    }
+
+    fn stdout(output: Output) -> String {
+        assert!(output.status.success());
+        String::from_utf8_lossy(&output.stdout).replace("\r\n", "\n")
+    }
+
+    #[test]
+    fn unbalanced_quotes_and_brackets() {
+        assert_eq!(unbalanced("echo 'hi"), Some("unclosed quote"));
+        assert_eq!(unbalanced("echo \"it's\""), None);
+        assert_eq!(unbalanced("echo (a"), Some("unclosed bracket"));
+        assert_eq!(unbalanced("echo a)"), Some("unmatched closing bracket"));
+        // closed too soon, even if it evens out
+        assert_eq!(unbalanced("echo a) (b"), Some("unmatched closing bracket"));
+        assert_eq!(unbalanced("echo \\( '('"), None);
+        assert_eq!(unbalanced("echo ${HOME}"), None);
+        assert_eq!(unbalanced("echo hi # it's"), None);
+        assert_eq!(unbalanced("echo a#'b"), Some("unclosed quote"));
+        assert!(needs_continuation("for f in *; do # it's\n"));
+    }
+
+    #[test]
+    fn comments_run_to_the_end_of_the_line() {
+        let cwd = env::current_dir().unwrap();
+        let parsed = parse("echo a#b '#c' # d 'e", |_| None, &cwd).unwrap();
+        assert_eq!(parsed.args, ["a#b", "#c"]);
+        assert!(parse("# just a note", |_| None, &cwd).is_none());
+    }
+
+    #[test]
+    fn pipelines_split_at_bars_outside_quotes() {
+        assert_eq!(
+            pipeline("ps aux | grep 'a|b'|wc -l"),
+            ["ps aux", "grep 'a|b'", "wc -l"]
+        );
+        assert_eq!(pipeline("echo \"|\" # a | b"), ["echo \"|\" # a | b"]);
+        assert_eq!(pipeline("true || false"), ["true || false"]);
+        assert_eq!(pipeline("ls |"), ["ls |"]);
+        assert_eq!(pipeline("ls | wc\n-l"), ["ls | wc\n-l"]);
+    }
+
+    #[test]
+    fn runs_a_command() {
+        let command = if cfg!(windows) {
+            "cmd /C echo hi"
+        } else {
+            "echo hi"
+        };
+        let output = run(command);
+        assert_eq!(stdout(output).trim(), "hi");
+    }
+
+    #[test]
+    fn quoted_words_come_back_unchanged() {
+        let cwd = env::current_dir().unwrap();
+        for word in [
+            "plain",
+            "with space",
+            "it's",
+            "$HOME ~ *.rs",
+            r"back\slash",
+            "",
+            "~",
+        ] {
+            let line = format!("echo {}", quote(word));
+            let parsed = parse(&line, |_| Some("expanded".into()), &cwd).unwrap();
+            assert_eq!(parsed.args, [word]);
+        }
+    }
+
+    #[test]
+    fn the_last_exit_code() {
+        let cwd = env::current_dir().unwrap();
+        let env = |name: &str| (name == "?").then(|| "101".to_string());
+        let parsed = parse("echo $? \"$?\" '$?' $?x ${?}", env, &cwd).unwrap();
+        assert_eq!(parsed.args, ["101", "101", "$?", "101x", "101"]);
+    }
+
+    #[test]
+    fn runs_a_script_through_the_shell() {
+        let output = run("echo a\necho b");
+        assert_eq!(stdout(output).trim(), "a\nb");
+    }
 }
@@ -0,0 +1,170 @@
+//! Smooth scrolling (`smooth_scroll`): big jumps of the view slide there
+//! over a few frames instead of happening at once, so it's easier to keep
+//! track of where you are.
+
+use std::time::{Duration, Instant};
+
+use crate::{Model, Output};
+
+/// How long a scroll takes.
+const DURATION: Duration = Duration::from_millis(100);
+/// How often the view moves while scrolling.
+pub const FRAME: Duration = Duration::from_millis(16);
+/// Jumps this short happen at once.
+const MIN_DISTANCE: usize = 3;
+
+/// A scroll in progress.
+#[derive(Debug, Clone, Copy)]
+pub struct ScrollTarget {
+    from: usize,
+    to: usize,
+    started: Instant,
+}
+
+impl ScrollTarget {
+    /// The first `lines` lines are gone; see `anchor`.
+    pub fn lines_dropped(&mut self, lines: usize) {
+        self.from = self.from.saturating_sub(lines);
+        self.to = self.to.saturating_sub(lines);
+    }
+
+    /// Where the view should be now, easing out towards the end.
+    fn now(&self) -> Option<usize> {
+        let done = self.started.elapsed().as_secs_f64() / DURATION.as_secs_f64();
+        if done >= 1.0 {
+            return None;
+        }
+        let eased = 1.0 - (1.0 - done).powi(3);
+        let (from, to) = (self.from as f64, self.to as f64);
+        Some((from + (to - from) * eased).round() as usize)
+    }
+}
+
+impl Output {
+    /// Where the view is headed, which is where it is unless it's scrolling.
+    pub fn scroll_goal(&self) -> usize {
+        self.scroll_target.map_or(self.scroll.0, |t| t.to)
+    }
+
+    /// Move the view to `line` at once, stopping any scroll under way.
+    pub fn set_scroll(&mut self, line: usize) {
+        self.scroll.0 = line;
+        self.scroll_target = None;
+    }
+}
+
+impl Model {
+    /// Scroll output `index` to `line`, smoothly if that's on and it's far.
+    /// A scroll already under way carries on from wherever it got to.
+    pub fn scroll_to(&mut self, index: usize, line: usize) {
+        let smooth = self.config.smooth_scroll;
+        let Some(output) = self.outputs.get_mut(index) else {
+            return;
+        };
+        let from = output.scroll.0;
+        if !smooth || from.abs_diff(line) < MIN_DISTANCE {
+            output.set_scroll(line);
+            return;
+        }
+        output.scroll_target = Some(ScrollTarget {
+            from,
+            to: line,
+            started: Instant::now(),
+        });
+    }
+
+    /// Whether a scroll is under way, so the screen needs redrawing.
+    pub fn scrolling(&self) -> bool {
+        self.outputs.iter().any(|o| o.scroll_target.is_some())
+    }
+
+    /// Move scrolling views on a frame.
+    pub fn step_scroll(&mut self) {
+        for output in &mut self.outputs {
+            let Some(target) = output.scroll_target else {
+                continue;
+            };
+            match target.now() {
+                Some(line) => output.scroll.0 = line,
+                None => output.set_scroll(target.to),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Message, update};
+
+    /// A model with smooth scrolling on, viewing 100 lines from the top.
+    fn smooth() -> Model {
+        let mut model = Model::default();
+        model.config.smooth_scroll = true;
+        model.push_output("seq 100".into(), "1\n".repeat(100));
+        model.outputs[0].set_scroll(0);
+        model
+    }
+
+    /// Pretend the scroll under way started `ago`.
+    fn started(model: &mut Model, ago: Duration) {
+        let target = model.outputs[0].scroll_target.as_mut().unwrap();
+        target.started = Instant::now() - ago;
+    }
+
+    #[test]
+    fn a_scroll_steps_towards_the_target_and_stops_there() {
+        let mut model = smooth();
+        model.scroll_to(0, 50);
+        assert!(model.scrolling());
+        assert_eq!(model.outputs[0].scroll.0, 0);
+        assert_eq!(model.outputs[0].scroll_goal(), 50);
+        started(&mut model, DURATION / 2);
+        update(&mut model, Message::ScrollFrame);
+        let halfway = model.outputs[0].scroll.0;
+        assert!(halfway > 0 && halfway < 50, "{halfway}");
+        started(&mut model, DURATION);
+        update(&mut model, Message::ScrollFrame);
+        assert_eq!(model.outputs[0].scroll.0, 50);
+        assert!(!model.scrolling());
+    }
+
+    #[test]
+    fn new_input_takes_over_a_scroll_under_way() {
+        let mut model = smooth();
+        model.scroll_to(0, 60);
+        started(&mut model, DURATION / 2);
+        model.step_scroll();
+        let reached = model.outputs[0].scroll.0;
+        // another scroll carries on from wherever this one got to
+        update(&mut model, Message::ScrollUp);
+        let target = model.outputs[0].scroll_target.unwrap();
+        assert_eq!((target.from, target.to), (reached, 50));
+        // and moving the view outright stops it
+        model.outputs[0].set_scroll(7);
+        assert!(!model.scrolling());
+        model.step_scroll();
+        assert_eq!(model.outputs[0].scroll.0, 7);
+    }
+
+    #[test]
+    fn short_jumps_happen_at_once() {
+        let mut model = smooth();
+        model.scroll_to(0, MIN_DISTANCE - 1);
+        assert!(!model.scrolling());
+        assert_eq!(model.outputs[0].scroll.0, MIN_DISTANCE - 1);
+        model.scroll_to(0, MIN_DISTANCE - 1 + MIN_DISTANCE);
+        assert!(model.scrolling());
+    }
+
+    #[test]
+    fn turned_off_every_jump_happens_at_once() {
+        let mut model = smooth();
+        model.config.smooth_scroll = false;
+        model.scroll_to(0, 50);
+        assert!(!model.scrolling());
+        assert_eq!(model.outputs[0].scroll.0, 50);
+        update(&mut model, Message::ScrollUp);
+        assert_eq!(model.outputs[0].scroll.0, 40);
+    }
+}
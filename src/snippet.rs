@@ -0,0 +1,325 @@
+use std::{collections::BTreeMap, fmt::Write, fs};
+
+use crate::{Mode, Model, config};
+
+/// Snippets live next to the config, one `name = template` per line.
+fn path() -> Option<std::path::PathBuf> {
+    Some(config::dir()?.join("snippets"))
+}
+
+pub fn load() -> BTreeMap<String, String> {
+    let Some(contents) = path().and_then(|p| fs::read_to_string(p).ok()) else {
+        return BTreeMap::new();
+    };
+    contents
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .map(|(name, template)| (name.trim().to_string(), template.trim().to_string()))
+        .filter(|(name, _)| !name.is_empty() && !name.starts_with('#'))
+        .collect()
+}
+
+fn save(snippets: &BTreeMap<String, String>) -> Result<(), String> {
+    let path = path().ok_or("snippet: no config directory")?;
+    let mut contents = String::new();
+    for (name, template) in snippets {
+        let _ = writeln!(contents, "{name} = {template}");
+    }
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("snippet: {e}"))?;
+    }
+    fs::write(&path, contents).map_err(|e| format!("snippet: {}: {e}", path.display()))
+}
+
+/// A `{name}` or `{name:default}` in a template.
+#[derive(Debug)]
+struct Placeholder {
+    name: String,
+    default: Option<String>,
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Slot(&'a str, Option<&'a str>),
+}
+
+/// Split a template into text and placeholders. Braces around anything
+/// but a name, like awk's `{print $1}`, are left as they are, as is
+/// `${VAR}` for the shell; `{{` and `}}` are a brace each.
+fn pieces(template: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    // where the text not yet taken starts
+    let mut text = 0;
+    let mut i = 0;
+    while let Some(c) = template[i..].chars().next() {
+        let rest = &template[i..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            pieces.push(Piece::Text(&template[text..=i]));
+            i += 2;
+            text = i;
+            continue;
+        }
+        if c == '{'
+            && !template[..i].ends_with('$')
+            && let Some((slot, len)) = slot(rest)
+        {
+            pieces.push(Piece::Text(&template[text..i]));
+            pieces.push(slot);
+            i += len;
+            text = i;
+            continue;
+        }
+        i += c.len_utf8();
+    }
+    pieces.push(Piece::Text(&template[text..]));
+    pieces
+}
+
+/// The placeholder `text` starts with, and how long it is.
+fn slot(text: &str) -> Option<(Piece<'_>, usize)> {
+    let len = text.find('}')?;
+    let inner = &text[1..len];
+    let (name, default) = match inner.split_once(':') {
+        Some((name, default)) => (name, Some(default)),
+        None => (inner, None),
+    };
+    let mut chars = name.chars();
+    let first = chars.next()?;
+    let identifier =
+        (first.is_alphabetic() || first == '_') && chars.all(|c| c.is_alphanumeric() || c == '_');
+    identifier.then_some((Piece::Slot(name, default), len + 1))
+}
+
+fn placeholders(template: &str) -> Vec<Placeholder> {
+    let mut found: Vec<Placeholder> = Vec::new();
+    for piece in pieces(template) {
+        // the same placeholder twice is asked for once
+        if let Piece::Slot(name, default) = piece
+            && !found.iter().any(|p| p.name == name)
+        {
+            found.push(Placeholder {
+                name: name.to_string(),
+                default: default.map(String::from),
+            });
+        }
+    }
+    found
+}
+
+fn fill(template: &str, values: &[(String, String)]) -> String {
+    pieces(template)
+        .into_iter()
+        .map(|piece| match piece {
+            Piece::Text(text) => text,
+            Piece::Slot(name, _) => values
+                .iter()
+                .find(|(n, _)| n == name)
+                .map_or("", |(_, value)| value.as_str()),
+        })
+        .collect()
+}
+
+/// Asking for a snippet's placeholders one at a time on the command line.
+#[derive(Debug)]
+pub struct Prompt {
+    pub snippet: String,
+    template: String,
+    placeholders: Vec<Placeholder>,
+    values: Vec<(String, String)>,
+    pub input: String,
+}
+
+impl Prompt {
+    /// What is being asked for, e.g. `version [latest]`.
+    pub fn label(&self) -> String {
+        match &self.placeholders[self.values.len()] {
+            Placeholder {
+                name,
+                default: Some(default),
+            } => format!("{name} [{default}]"),
+            Placeholder { name, .. } => name.clone(),
+        }
+    }
+}
+
+impl Model {
+    /// Start filling in snippet `name`, or put it straight on the command
+    /// line if it has no placeholders.
+    pub fn start_snippet(&mut self, name: &str) {
+        let Some(template) = self.snippets.get(name).cloned() else {
+            self.status = Some(format!("no snippet named {name}"));
+            return;
+        };
+        let prompt = Prompt {
+            snippet: name.to_string(),
+            placeholders: placeholders(&template),
+            template,
+            values: Vec::new(),
+            input: String::new(),
+        };
+        self.snippet_prompt = Some(prompt);
+        self.advance_snippet();
+    }
+
+    /// Take the typed value for the current placeholder. Empty input takes
+    /// the default, or asks again if there is none.
+    pub fn submit_snippet_value(&mut self) {
+        let Some(prompt) = self.snippet_prompt.as_mut() else {
+            return;
+        };
+        let placeholder = &prompt.placeholders[prompt.values.len()];
+        let value = match (prompt.input.is_empty(), &placeholder.default) {
+            (false, _) => std::mem::take(&mut prompt.input),
+            (true, Some(default)) => default.clone(),
+            (true, None) => {
+                self.status = Some(format!("{} needs a value", placeholder.name));
+                return;
+            }
+        };
+        prompt.values.push((placeholder.name.clone(), value));
+        self.advance_snippet();
+    }
+
+    /// Once every placeholder has a value, put the command on the command
+    /// line for the user to check and submit.
+    fn advance_snippet(&mut self) {
+        let Some(prompt) = &self.snippet_prompt else {
+            return;
+        };
+        if prompt.values.len() < prompt.placeholders.len() {
+            return;
+        }
+        self.current_command = fill(&prompt.template, &prompt.values);
+        self.snippet_prompt = None;
+        self.viewing_command = None;
+        self.mode = Mode::Insert;
+        self.cursor = self.command_end();
+    }
+
+    /// Tab after a snippet's name on an otherwise empty command line.
+    pub fn is_snippet_name(&self) -> bool {
+        self.snippets.contains_key(self.command().trim())
+    }
+}
+
+/// `:snippet add NAME TEMPLATE`, `:snippet rm NAME`, or `:snippet` to list.
+pub fn command(model: &mut Model, line: &str) -> String {
+    let line = line.trim_start().trim_start_matches(':').trim_start();
+    let rest = line.strip_prefix("snippet").unwrap_or(line).trim();
+    let (action, rest) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let (name, template) = rest
+        .trim()
+        .split_once(char::is_whitespace)
+        .unwrap_or((rest.trim(), ""));
+    let result = match action {
+        "" | "list" => return list(model),
+        "add" if !name.is_empty() && !template.trim().is_empty() => {
+            model
+                .snippets
+                .insert(name.to_string(), unquote(template.trim()).to_string());
+            save(&model.snippets).map(|()| format!("added snippet {name}"))
+        }
+        "rm" if model.snippets.remove(name).is_some() => {
+            save(&model.snippets).map(|()| format!("removed snippet {name}"))
+        }
+        "rm" => Err(format!("no snippet named {name}")),
+        _ => Err("usage: :snippet [add NAME TEMPLATE | rm NAME]".into()),
+    };
+    result.unwrap_or_else(|e| e)
+}
+
+fn list(model: &Model) -> String {
+    let mut listing = String::new();
+    for (name, template) in &model.snippets {
+        let _ = writeln!(listing, "{name}  {template}");
+    }
+    listing
+}
+
+/// Strip one pair of matching outer quotes.
+fn unquote(text: &str) -> &str {
+    for quote in ['"', '\''] {
+        if let Some(inner) = text.strip_prefix(quote).and_then(|t| t.strip_suffix(quote)) {
+            return inner;
+        }
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn names(template: &str) -> Vec<(String, Option<String>)> {
+        placeholders(template)
+            .into_iter()
+            .map(|p| (p.name, p.default))
+            .collect()
+    }
+
+    #[test]
+    fn only_names_are_placeholders() {
+        assert_eq!(
+            names("git checkout {branch:main} && cd {dir} {branch}"),
+            [("branch".into(), Some("main".into())), ("dir".into(), None)]
+        );
+        assert_eq!(names("awk '{print $1}' ${HOME} { x } {1st}"), []);
+        assert_eq!(
+            fill("awk '{print $1}' {file}", &[("file".into(), "log".into())]),
+            "awk '{print $1}' log"
+        );
+    }
+
+    #[test]
+    fn doubled_braces_are_literal() {
+        assert_eq!(names("echo {{name}}"), []);
+        assert_eq!(
+            fill("echo {{name}} {{{x}}}", &[("x".into(), "1".into())]),
+            "echo {name} {1}"
+        );
+    }
+
+    /// A model with `template` as snippet `s`, being filled in.
+    fn filling(template: &str) -> Model {
+        let mut model = Model::default();
+        model.snippets.insert("s".into(), template.into());
+        model.start_snippet("s");
+        model
+    }
+
+    fn answer(model: &mut Model, value: &str) {
+        model.snippet_prompt.as_mut().unwrap().input = value.into();
+        model.submit_snippet_value();
+    }
+
+    #[test]
+    fn empty_input_takes_the_default() {
+        let mut model = filling("cargo {cmd:build} --{profile:release}");
+        assert_eq!(
+            model.snippet_prompt.as_ref().unwrap().label(),
+            "cmd [build]"
+        );
+        answer(&mut model, "test");
+        answer(&mut model, "");
+        assert!(model.snippet_prompt.is_none());
+        assert_eq!(model.current_command, "cargo test --release");
+        assert_eq!(model.mode, Mode::Insert);
+    }
+
+    #[test]
+    fn empty_input_without_a_default_asks_again() {
+        let mut model = filling("ssh {host}");
+        answer(&mut model, "");
+        assert_eq!(model.status.as_deref(), Some("host needs a value"));
+        assert_eq!(model.snippet_prompt.as_ref().unwrap().label(), "host");
+        answer(&mut model, "box");
+        assert_eq!(model.current_command, "ssh box");
+    }
+
+    #[test]
+    fn a_template_without_placeholders_goes_straight_in() {
+        let model = filling("awk '{print $1}' | sort");
+        assert!(model.snippet_prompt.is_none());
+        assert_eq!(model.current_command, "awk '{print $1}' | sort");
+    }
+}
@@ -0,0 +1,170 @@
+//! Where startup time goes. Each step from the start of `main` to the
+//! first frame is timed as a phase, and `shim --profile-startup` shows
+//! them in a buffer, with how far over `BUDGET` shim was in getting to
+//! its first frame. Steps that only start work on a thread of their own,
+//! like the PATH index, are timed to when they were started and marked
+//! as going on in the background; `defer_history` makes loading history
+//! one of them. A phase that panics still has the phases before it
+//! written to stderr, with the one that failed.
+
+use std::{
+    fmt::Write,
+    panic::{self, AssertUnwindSafe},
+    time::{Duration, Instant},
+};
+
+use crate::Model;
+
+/// How long shim should take to be ready for keys.
+pub const BUDGET: Duration = Duration::from_millis(50);
+
+#[derive(Debug, Clone, PartialEq)]
+struct Phase {
+    name: &'static str,
+    took: Duration,
+    /// The work was only started, and goes on in the background.
+    background: bool,
+}
+
+#[derive(Debug)]
+pub struct Profile {
+    started: Instant,
+    phases: Vec<Phase>,
+    /// Shown once startup is done.
+    pub report: bool,
+    reported: bool,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Profile::new(false)
+    }
+}
+
+impl Profile {
+    pub fn new(report: bool) -> Self {
+        Profile {
+            started: Instant::now(),
+            phases: Vec::new(),
+            report,
+            reported: false,
+        }
+    }
+
+    /// Run `step` as the phase `name`.
+    pub fn time<T>(&mut self, name: &'static str, step: impl FnOnce() -> T) -> T {
+        self.run(name, false, step)
+    }
+
+    /// Run `step`, which starts work in the background, as the phase `name`.
+    pub fn start<T>(&mut self, name: &'static str, step: impl FnOnce() -> T) -> T {
+        self.run(name, true, step)
+    }
+
+    fn run<T>(&mut self, name: &'static str, background: bool, step: impl FnOnce() -> T) -> T {
+        let at = Instant::now();
+        let result = panic::catch_unwind(AssertUnwindSafe(step));
+        self.record(name, at.elapsed(), background);
+        match result {
+            Ok(value) => value,
+            Err(panic) => {
+                if self.report {
+                    eprintln!("{}\n{name} panicked", self.summary());
+                }
+                panic::resume_unwind(panic)
+            }
+        }
+    }
+
+    fn record(&mut self, name: &'static str, took: Duration, background: bool) {
+        self.phases.push(Phase {
+            name,
+            took,
+            background,
+        });
+    }
+
+    /// The phases a line each, and what they came to.
+    pub fn summary(&self) -> String {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let mut text = String::new();
+        for phase in &self.phases {
+            let background = if phase.background {
+                "  (in the background)"
+            } else {
+                ""
+            };
+            let _ = writeln!(
+                text,
+                "{:>8.1}ms  {}{background}",
+                ms(phase.took),
+                phase.name
+            );
+        }
+        let total: Duration = self.phases.iter().map(|p| p.took).sum();
+        let _ = write!(text, "{:>8.1}ms  in all", ms(total));
+        if total > BUDGET {
+            let _ = write!(
+                text,
+                ", {:.1}ms over {}ms",
+                ms(total - BUDGET),
+                BUDGET.as_millis()
+            );
+        }
+        text
+    }
+}
+
+impl Model {
+    /// With `--profile-startup`, show where startup went in a buffer once
+    /// the first frame is drawn, `drawing` being when that started, and
+    /// say whether it was shown, to be drawn again.
+    pub fn report_startup(&mut self, drawing: Instant) -> bool {
+        let profile = &mut self.startup;
+        if !profile.report || profile.reported {
+            return false;
+        }
+        profile.reported = true;
+        profile.record("first frame", drawing.elapsed(), false);
+        let ready = profile.started.elapsed().as_secs_f64() * 1000.0;
+        let text = format!(
+            "{}\nready for keys {ready:.1}ms after shim started\n",
+            profile.summary()
+        );
+        self.push_output("shim --profile-startup".into(), text);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn phases_are_reported_and_summed() {
+        let mut profile = Profile::new(false);
+        profile.record("config", Duration::from_micros(1500), false);
+        profile.record("path index", Duration::from_micros(200), true);
+        profile.record("history", Duration::from_millis(60), false);
+        assert_eq!(
+            profile.summary(),
+            "     1.5ms  config\n     0.2ms  path index  (in the background)\n    \
+             60.0ms  history\n    61.7ms  in all, 11.7ms over 50ms"
+        );
+
+        let mut profile = Profile::new(false);
+        assert_eq!(profile.time("config", || 7), 7);
+        profile.start("path index", || ());
+        let summary = profile.summary();
+        assert!(summary.contains("ms  config\n"));
+        assert!(summary.ends_with("ms  in all"));
+        assert!(profile.phases[1].background);
+
+        // a phase that panics is kept, and the panic goes on
+        let panicked = panic::catch_unwind(AssertUnwindSafe(|| {
+            profile.time("history", || panic!("no history"))
+        }));
+        assert!(panicked.is_err());
+        assert_eq!(profile.phases.last().map(|p| p.name), Some("history"));
+    }
+}
@@ -0,0 +1,295 @@
+//! How long commands take and how often they fail, over time. Each
+//! finished command is logged as a line of JSON in `runs.jsonl` next to
+//! the history, and `:stats [--since 7d] PREFIX` sums up the runs of
+//! commands starting with PREFIX: how many, how many failed, the median
+//! and 90th percentile durations, and a histogram of them. The log is
+//! read a line at a time, and lines that don't parse are counted and
+//! skipped.
+
+use std::{
+    fmt::Write as _,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Model, config, lock};
+
+/// Rows of the histogram.
+const BUCKETS: usize = 10;
+/// Width of its longest bar.
+const BAR: usize = 40;
+
+/// A finished command, as logged.
+#[derive(Debug, Serialize, Deserialize)]
+struct Run {
+    command: String,
+    /// Seconds since the epoch when it finished.
+    time: u64,
+    /// How long it took, in milliseconds.
+    took: u64,
+    /// None if it was killed by a signal.
+    status: Option<i32>,
+}
+
+fn path() -> Option<PathBuf> {
+    Some(config::dir()?.join("runs.jsonl"))
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Add a run of `command` to the log.
+pub fn log(command: &str, status: Option<i32>, took: Duration) {
+    let Some(path) = path() else {
+        return;
+    };
+    let run = Run {
+        command: command.to_string(),
+        time: now(),
+        took: took.as_millis() as u64,
+        status,
+    };
+    let Ok(line) = serde_json::to_string(&run) else {
+        return;
+    };
+    // like the history, a lost line isn't worth interrupting anyone for
+    let _ = lock::append(&path, None, &format!("{line}\n"));
+}
+
+/// `7d`, `12h`, `30m`, `90s` or `2w` as seconds.
+fn parse_age(text: &str) -> Option<u64> {
+    let split = text.find(|c: char| !c.is_ascii_digit())?;
+    let (number, unit) = text.split_at(split);
+    let unit = match unit {
+        "s" => 1,
+        "m" => 60,
+        "h" => 60 * 60,
+        "d" => 24 * 60 * 60,
+        "w" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+    number.parse::<u64>().ok()?.checked_mul(unit)
+}
+
+/// What the matching runs add up to.
+#[derive(Debug, Default, PartialEq)]
+struct Stats {
+    /// Durations in milliseconds, sorted once all are read.
+    took: Vec<u64>,
+    failures: usize,
+    /// Lines that weren't runs.
+    malformed: usize,
+}
+
+/// The runs in `log` of commands starting with `prefix` that finished at
+/// or after `since`.
+fn aggregate(log: impl BufRead, prefix: &str, since: u64) -> Stats {
+    let mut stats = Stats::default();
+    for line in log.lines() {
+        let Ok(line) = line else {
+            stats.malformed += 1;
+            continue;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(run) = serde_json::from_str::<Run>(&line) else {
+            stats.malformed += 1;
+            continue;
+        };
+        if run.time < since || !run.command.starts_with(prefix) {
+            continue;
+        }
+        stats.took.push(run.took);
+        if run.status != Some(0) {
+            stats.failures += 1;
+        }
+    }
+    stats.took.sort_unstable();
+    stats
+}
+
+impl Stats {
+    /// The duration `p` percent of runs took at most, by nearest rank.
+    fn percentile(&self, p: usize) -> Option<u64> {
+        let rank = (p * self.took.len()).div_ceil(100).max(1);
+        self.took.get(rank - 1).copied()
+    }
+
+    /// How many runs fell in each of `BUCKETS` equal spans from the
+    /// quickest to the slowest, with where each starts.
+    fn histogram(&self) -> Vec<(u64, usize)> {
+        let (Some(&low), Some(&high)) = (self.took.first(), self.took.last()) else {
+            return Vec::new();
+        };
+        let span = (high - low).div_ceil(BUCKETS as u64).max(1);
+        let mut counts = vec![0; BUCKETS];
+        for took in &self.took {
+            let bucket = ((took - low) / span) as usize;
+            counts[bucket.min(BUCKETS - 1)] += 1;
+        }
+        // no empty rows past the slowest
+        let rows = ((high - low) / span) as usize + 1;
+        counts
+            .into_iter()
+            .take(rows)
+            .enumerate()
+            .map(|(i, count)| (low + span * i as u64, count))
+            .collect()
+    }
+
+    fn report(&self, prefix: &str) -> String {
+        let runs = self.took.len();
+        if runs == 0 {
+            return format!("no runs of {prefix}");
+        }
+        let mut report = format!(
+            "{runs} runs, {} failed ({}%)\nmedian {}, p90 {}\n\n",
+            self.failures,
+            self.failures * 100 / runs,
+            format_ms(self.percentile(50).unwrap_or(0)),
+            format_ms(self.percentile(90).unwrap_or(0)),
+        );
+        let histogram = self.histogram();
+        let most = histogram.iter().map(|(_, n)| *n).max().unwrap_or(1);
+        for (from, count) in histogram {
+            let bar = "#".repeat((count * BAR).div_ceil(most));
+            let _ = writeln!(report, "{:>8} {bar} {count}", format_ms(from));
+        }
+        if self.malformed > 0 {
+            let _ = write!(report, "\n{} lines of the log skipped", self.malformed);
+        }
+        report.trim_end().to_string()
+    }
+}
+
+/// A duration in the largest unit it runs to.
+pub fn format_ms(ms: u64) -> String {
+    match ms {
+        0..1_000 => format!("{ms}ms"),
+        1_000..60_000 => format!("{:.1}s", ms as f64 / 1000.0),
+        60_000..3_600_000 => format!("{}m{:02}s", ms / 60_000, ms / 1000 % 60),
+        _ => format!("{}h{:02}m", ms / 3_600_000, ms / 60_000 % 60),
+    }
+}
+
+impl Model {
+    /// `:stats [--since AGE] PREFIX`
+    pub fn stats(&self, args: &str) -> String {
+        let usage = "usage: :stats [--since 7d] PREFIX";
+        let mut rest = args.trim();
+        let mut since = 0;
+        if let Some(after) = rest.strip_prefix("--since") {
+            let after = after.trim_start();
+            let (age, prefix) = after.split_once(char::is_whitespace).unwrap_or((after, ""));
+            let Some(age) = parse_age(age) else {
+                return usage.into();
+            };
+            since = now().saturating_sub(age);
+            rest = prefix.trim_start();
+        }
+        if rest.is_empty() {
+            return usage.into();
+        }
+        let Some(file) = path().and_then(|p| File::open(p).ok()) else {
+            return "no runs logged yet".into();
+        };
+        aggregate(BufReader::new(file), rest, since).report(rest)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn line(command: &str, time: u64, took: u64, status: Option<i32>) -> String {
+        let run = Run {
+            command: command.into(),
+            time,
+            took,
+            status,
+        };
+        serde_json::to_string(&run).unwrap()
+    }
+
+    fn synthetic() -> String {
+        let mut log = Vec::new();
+        // an old, quick week of builds
+        for i in 0..10 {
+            log.push(line("cargo build", 1_000 + i, 1_000 + i * 100, Some(0)));
+        }
+        log.push("{\"command\": \"cargo build\", \"ti".into());
+        log.push("not json at all".into());
+        log.push(String::new());
+        // a newer, slower one with failures
+        for i in 0..10 {
+            let status = if i % 5 == 0 { Some(101) } else { Some(0) };
+            log.push(line(
+                "cargo build --release",
+                9_000 + i,
+                10_000 + i * 1_000,
+                status,
+            ));
+        }
+        log.push(line("cargo test", 9_500, 500, None));
+        log.push(line("make", 9_600, 50, Some(0)));
+        log.join("\n")
+    }
+
+    #[test]
+    fn runs_are_counted_by_prefix_and_time() {
+        let log = synthetic();
+        let all = aggregate(log.as_bytes(), "cargo", 0);
+        assert_eq!(all.took.len(), 21);
+        assert_eq!(all.failures, 3);
+        assert_eq!(all.malformed, 2);
+
+        let recent = aggregate(log.as_bytes(), "cargo build", 5_000);
+        assert_eq!(recent.took.len(), 10);
+        assert_eq!(recent.failures, 2);
+        assert_eq!(recent.percentile(50), Some(14_000));
+        assert_eq!(recent.percentile(90), Some(18_000));
+        assert_eq!(recent.percentile(100), Some(19_000));
+
+        let old = aggregate(log.as_bytes(), "cargo build", 0);
+        assert_eq!(old.percentile(50), Some(1_900));
+        assert_eq!(old.percentile(90), Some(17_000));
+        assert_eq!(aggregate(log.as_bytes(), "ninja", 0).percentile(50), None);
+    }
+
+    #[test]
+    fn the_histogram_covers_every_run() {
+        let log = synthetic();
+        let recent = aggregate(log.as_bytes(), "cargo build", 5_000);
+        let histogram = recent.histogram();
+        // 10s to 19s in ten spans of 900ms
+        assert_eq!(histogram.len(), BUCKETS);
+        assert_eq!(histogram[0], (10_000, 1));
+        assert_eq!(histogram.iter().map(|(_, n)| n).sum::<usize>(), 10);
+        let one = aggregate(line("make", 0, 50, Some(0)).as_bytes(), "", 0);
+        assert_eq!(one.histogram(), [(50, 1)]);
+        let report = recent.report("cargo build");
+        assert!(report.starts_with("10 runs, 2 failed (20%)\nmedian 14.0s, p90 18.0s"));
+    }
+
+    #[test]
+    fn ages_and_durations_read_and_print() {
+        assert_eq!(parse_age("7d"), Some(7 * 24 * 60 * 60));
+        assert_eq!(parse_age("90s"), Some(90));
+        assert_eq!(parse_age("2w"), Some(14 * 24 * 60 * 60));
+        assert_eq!(parse_age("7"), None);
+        assert_eq!(parse_age("d"), None);
+        assert_eq!(parse_age("3y"), None);
+        assert_eq!(format_ms(850), "850ms");
+        assert_eq!(format_ms(12_340), "12.3s");
+        assert_eq!(format_ms(245_000), "4m05s");
+        assert_eq!(format_ms(3_720_000), "1h02m");
+    }
+}
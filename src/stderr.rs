@@ -0,0 +1,493 @@
+//! A command's stderr. Every stage of a job writes it to one pipe of its
+//! own, and it goes into the buffer along with stdout, interleaved as the
+//! two came. Once anything comes on stderr the buffer also keeps each
+//! stream by itself: what's taken from a buffer, by `NAME=! cmd`,
+//! `:capture`, `:pipe`, `:sort`, `:uniq`, `:each` or the preview, is its
+//! stdout only, as in a shell. `S` splits the output pane in two: stdout above
+//! and stderr below, each scrolled on its own, so a build's progress and
+//! its warnings can be watched at once. In the split `j`, `k`, Ctrl-d,
+//! Ctrl-u, `gg`, `G` and the wheel scroll the half with focus, Tab gives
+//! focus to the other half and the top line says which has it; `S` again
+//! goes back to the merged view. A running job's buffer can be split
+//! before anything has come on stderr.
+
+use std::{borrow::Cow, mem};
+
+use ratatui::{
+    Frame,
+    layout::{Constraint, Layout, Rect},
+    style::Stylize,
+    text::Line,
+    widgets::{Block, Paragraph},
+};
+use regex::Regex;
+
+use crate::{Cursor, Focus, Message, Model, Output, anchor, display, encoding, redact, title};
+
+/// One stream by itself, redacted line by line like the buffer.
+#[derive(Debug, Default)]
+pub struct Stream {
+    pub text: String,
+    /// The text unredacted, kept once there's a secret in it.
+    raw: Option<String>,
+    /// The last line, held back until it ends.
+    partial: String,
+    /// Turns it into UTF-8, as the buffer's does.
+    decoder: encoding::Decoder,
+    /// The top line of its half.
+    pub scroll: usize,
+}
+
+impl Stream {
+    fn push(&mut self, text: &str, patterns: &[Regex]) {
+        self.partial.push_str(text);
+        let Some(end) = self.partial.rfind('\n') else {
+            return;
+        };
+        let rest = self.partial.split_off(end + 1);
+        let lines = mem::replace(&mut self.partial, rest);
+        for line in lines.split_inclusive('\n') {
+            self.push_line(line, patterns);
+        }
+    }
+
+    fn flush(&mut self, patterns: &[Regex]) {
+        let rest = mem::take(&mut self.partial);
+        self.push_line(&rest, patterns);
+    }
+
+    fn push_line(&mut self, line: &str, patterns: &[Regex]) {
+        let masked = redact::line(patterns, line);
+        if matches!(masked, Cow::Owned(_)) && self.raw.is_none() {
+            self.raw = Some(self.text.clone());
+        }
+        self.text.push_str(&masked);
+        if let Some(raw) = self.raw.as_mut() {
+            raw.push_str(line);
+        }
+    }
+
+    /// Drop all but the last `kept` lines, keeping its half on the same
+    /// text.
+    fn keep_last(&mut self, kept: usize) {
+        let excess = self.text.lines().count().saturating_sub(kept);
+        if excess == 0 {
+            return;
+        }
+        self.text.drain(..anchor::after_lines(&self.text, excess));
+        if let Some(raw) = self.raw.as_mut() {
+            raw.drain(..anchor::after_lines(raw, excess));
+        }
+        self.scroll = self.scroll.saturating_sub(excess);
+    }
+
+    /// The top line when scrolled to the end, in a half `height` lines high.
+    fn bottom(&self, height: u16) -> usize {
+        self.text.lines().count().saturating_sub(height as usize)
+    }
+
+    /// The text from the top of its half down.
+    fn shown(&self) -> &str {
+        match self.scroll {
+            0 => &self.text,
+            n => self
+                .text
+                .match_indices('\n')
+                .nth(n - 1)
+                .map_or("", |(i, _)| &self.text[i + 1..]),
+        }
+    }
+}
+
+/// A buffer's stdout and stderr apart.
+#[derive(Debug, Default)]
+pub struct Streams {
+    pub stdout: Stream,
+    pub stderr: Stream,
+    /// Shown split in two rather than merged.
+    split: bool,
+    /// The stderr half has focus.
+    lower: bool,
+}
+
+impl Streams {
+    pub fn size(&self) -> usize {
+        self.stdout.text.len() + self.stderr.text.len()
+    }
+
+    /// Keep no more of either stream than the `kept` lines left of the
+    /// buffer, once its oldest have been dropped.
+    pub fn keep_last(&mut self, kept: usize) {
+        self.stdout.keep_last(kept);
+        self.stderr.keep_last(kept);
+    }
+
+    fn focused(&mut self) -> &mut Stream {
+        match self.lower {
+            true => &mut self.stderr,
+            false => &mut self.stdout,
+        }
+    }
+}
+
+/// The stdout and stderr halves of the output pane `pane`.
+pub fn halves(pane: Rect) -> [Rect; 2] {
+    Layout::vertical([Constraint::Fill(1), Constraint::Fill(1)]).areas(pane)
+}
+
+impl Output {
+    /// The streams apart, from now on. Until now there was only stdout.
+    fn streams(&mut self) -> &mut Streams {
+        self.streams.get_or_insert_with(|| Streams {
+            stdout: Stream {
+                text: self.stdout.clone(),
+                raw: self.raw.clone(),
+                partial: self.partial.clone(),
+                ..Default::default()
+            },
+            stderr: Stream {
+                decoder: encoding::Decoder::new(self.decoder.forced()),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    }
+
+    /// Keep a copy of text from stdout, once stderr is kept apart.
+    pub fn copy_stdout(&mut self, text: &str, redact: &[Regex]) {
+        if let Some(streams) = self.streams.as_mut() {
+            streams.stdout.push(text, redact);
+        }
+    }
+
+    /// What came on stdout, redacted, without the stderr shown in with it.
+    pub fn stdout_text(&self) -> &str {
+        match &self.streams {
+            Some(streams) => &streams.stdout.text,
+            None => &self.stdout,
+        }
+    }
+
+    /// What came on stdout as the command wrote it, secrets and all.
+    pub fn raw_stdout(&self) -> &str {
+        match &self.streams {
+            Some(streams) => streams
+                .stdout
+                .raw
+                .as_deref()
+                .unwrap_or(&streams.stdout.text),
+            None => self.raw_text(),
+        }
+    }
+
+    /// What's shown of stdout: as `shown_text`, but with the stderr shown
+    /// in with it left out.
+    pub fn shown_stdout(&self) -> &str {
+        if let Some(text) = self.frozen_text().or_else(|| self.piped_text()) {
+            return text;
+        }
+        match self.show_raw {
+            true => self.raw_stdout(),
+            false => self.stdout_text(),
+        }
+    }
+
+    /// Add a chunk of stderr, to the buffer and by itself.
+    pub fn append_error(&mut self, data: &[u8], redact: &[Regex]) {
+        let decoder = &mut self.streams().stderr.decoder;
+        let (text, invalid) = decoder.decode(data);
+        if invalid {
+            let warning = decoder.warning();
+            self.warn(warning);
+        }
+        self.streams().stderr.push(&text, redact);
+        self.ingest(&text, redact);
+    }
+
+    /// The job has ended: add what's left of stderr and show both streams'
+    /// last lines.
+    pub fn finish_error(&mut self, redact: &[Regex]) {
+        let Some(streams) = self.streams.as_mut() else {
+            return;
+        };
+        let (rest, invalid) = streams.stderr.decoder.finish();
+        let warning = streams.stderr.decoder.warning();
+        streams.stderr.push(&rest, redact);
+        streams.stderr.flush(redact);
+        streams.stdout.flush(redact);
+        if invalid {
+            self.warn(warning);
+        }
+        if !rest.is_empty() {
+            self.ingest(&rest, redact);
+        }
+    }
+}
+
+impl Model {
+    /// Whether the viewed buffer is shown split.
+    pub fn splitting(&self) -> bool {
+        self.scrollback.is_none()
+            && self
+                .outputs
+                .get(self.viewing_output)
+                .and_then(|o| o.streams.as_ref())
+                .is_some_and(|s| s.split)
+    }
+
+    /// Lines of text the stdout and stderr halves show.
+    fn half_heights(&self) -> (u16, u16) {
+        let [upper, lower] = halves(self.pane);
+        (
+            upper.height.saturating_sub(2),
+            lower.height.saturating_sub(2),
+        )
+    }
+
+    /// Whether each half of output `index` is scrolled to its end, to
+    /// stay there as more comes in.
+    pub fn halves_following(&self, index: usize) -> (bool, bool) {
+        let (upper, lower) = self.half_heights();
+        match self.outputs[index].streams.as_ref() {
+            Some(s) if !self.outputs[index].held => (
+                s.stdout.scroll >= s.stdout.bottom(upper),
+                s.stderr.scroll >= s.stderr.bottom(lower),
+            ),
+            _ => (false, false),
+        }
+    }
+
+    /// Keep the halves of output `index` that were `following` at the end.
+    pub fn keep_halves_following(&mut self, index: usize, following: (bool, bool)) {
+        let (upper, lower) = self.half_heights();
+        if let Some(s) = self.outputs[index].streams.as_mut() {
+            if following.0 {
+                s.stdout.scroll = s.stdout.bottom(upper);
+            }
+            if following.1 {
+                s.stderr.scroll = s.stderr.bottom(lower);
+            }
+        }
+    }
+
+    pub fn append_error(&mut self, job_id: usize, data: &[u8]) {
+        let Some(index) = self.outputs.iter().position(|o| o.id == job_id) else {
+            return;
+        };
+        let following = self.following(index);
+        let halves = self.halves_following(index);
+        let output = &mut self.outputs[index];
+        let before = output.stdout.len();
+        output.append_error(data, &self.config.redact);
+        output.checkpoint(
+            self.config.checkpoint_interval,
+            self.config.checkpoint_limit,
+        );
+        if let Some(recorder) = &self.recorder {
+            recorder.output(&output.stdout[before..]);
+        }
+        self.cap_lines(index, following);
+        if following {
+            let bottom = self.bottom(index);
+            self.outputs[index].set_scroll(bottom);
+        }
+        self.keep_halves_following(index, halves);
+    }
+
+    /// `S`: split the viewed buffer into stdout and stderr, or merge it
+    /// back.
+    pub fn toggle_split(&mut self) {
+        let index = self.viewing_output;
+        let Some(output) = self.outputs.get_mut(index) else {
+            return;
+        };
+        if output.streams.is_none() && !output.running {
+            self.status = Some("nothing came on stderr to split off".into());
+            return;
+        }
+        let streams = output.streams();
+        streams.split = !streams.split;
+        if streams.split {
+            self.keep_halves_following(index, (true, true));
+        }
+    }
+
+    /// Tab in the split: give focus to the other half.
+    pub fn switch_half(&mut self) {
+        if let Some(streams) = self.outputs[self.viewing_output].streams.as_mut() {
+            streams.lower = !streams.lower;
+        }
+    }
+
+    /// Scroll the half with focus for `msg`, if the view is split and it's
+    /// a scroll, returning whether it was.
+    pub fn scroll_half(&mut self, msg: &Message) -> bool {
+        if !self.splitting() {
+            return false;
+        }
+        let in_output = matches!(self.cursor, Cursor::OutputBuffer(..));
+        let (upper, lower) = self.half_heights();
+        let streams = self.outputs[self.viewing_output]
+            .streams
+            .as_mut()
+            .expect("a split buffer has its streams");
+        let height = match streams.lower {
+            true => lower,
+            false => upper,
+        };
+        let half = (height as usize / 2).max(1);
+        let stream = streams.focused();
+        let bottom = stream.bottom(height);
+        let line = match msg {
+            Message::ScrollDown => stream.scroll + half,
+            Message::ScrollUp => stream.scroll.saturating_sub(half),
+            Message::Down if in_output => stream.scroll + 1,
+            Message::Up if in_output => stream.scroll.saturating_sub(1),
+            Message::Top if in_output => 0,
+            Message::Bottom if in_output => bottom,
+            _ => return false,
+        };
+        stream.scroll = line.min(bottom);
+        true
+    }
+
+    /// The wheel in the split, by `lines`.
+    pub fn wheel_half(&mut self, lines: i16) {
+        let (upper, lower) = self.half_heights();
+        let Some(streams) = self.outputs[self.viewing_output].streams.as_mut() else {
+            return;
+        };
+        let height = match streams.lower {
+            true => lower,
+            false => upper,
+        };
+        let stream = streams.focused();
+        let bottom = stream.bottom(height);
+        stream.scroll = stream
+            .scroll
+            .saturating_add_signed(lines as isize)
+            .min(bottom);
+    }
+
+    /// Which half has focus, for the top line.
+    pub fn split_note(&self) -> Option<String> {
+        let streams = self.outputs.get(self.viewing_output)?.streams.as_ref();
+        match streams.filter(|_| self.splitting())?.lower {
+            true => Some("scrolling stderr".into()),
+            false => Some("scrolling stdout".into()),
+        }
+    }
+}
+
+/// The viewed buffer split into its stdout and stderr halves, in the output
+/// pane `pane`.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    let Some(output) = model.outputs.get(model.viewing_output) else {
+        return;
+    };
+    let Some(streams) = output.streams.as_ref() else {
+        return;
+    };
+    let theme = &model.config.theme;
+    let settings = output.display_settings(&model.config);
+    let focused = model.focus() == Focus::Output;
+    let title = title(output, &model.config.redact);
+    let halves = [
+        (&streams.stdout, "stdout", !streams.lower),
+        (&streams.stderr, "stderr", streams.lower),
+    ];
+    for ((stream, name, has_focus), area) in halves.into_iter().zip(self::halves(pane)) {
+        let height = area.height.saturating_sub(2) as usize;
+        let text = display::render(stream.shown(), height, settings);
+        frame.render_widget(
+            Paragraph::new(text).block(
+                Block::bordered()
+                    .title(format!("{title} {name}"))
+                    .title_bottom(Line::raw(format!("{} lines", stream.text.lines().count())).dim())
+                    .border_style(theme.border(focused && has_focus)),
+            ),
+            area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_streams_are_kept_apart_once_stderr_comes() {
+        let redact = redact::defaults();
+        let mut output = Output::default();
+        output.append(b"building\n", &redact, Default::default());
+        assert!(output.streams.is_none());
+        output.append_error(b"warning: unused\n", &redact);
+        output.append(b"done\n", &redact, Default::default());
+        // a secret split across chunks is still caught
+        output.append_error(b"tok", &redact);
+        output.append_error(b"en=hunter2\n", &redact);
+        output.finish_error(&redact);
+        output.flush(&redact);
+        let streams = output.streams.as_ref().unwrap();
+        assert_eq!(streams.stdout.text, "building\ndone\n");
+        assert_eq!(
+            streams.stderr.text,
+            format!("warning: unused\ntoken={}\n", redact::MASK)
+        );
+        assert_eq!(
+            output.stdout,
+            format!("building\nwarning: unused\ndone\ntoken={}\n", redact::MASK)
+        );
+    }
+
+    #[test]
+    fn only_stdout_is_taken_from_a_buffer() {
+        let redact = redact::defaults();
+        let mut output = Output::default();
+        output.append(b"token=hunter2\n", &redact, Default::default());
+        output.append_error(b"warning: unused\n", &redact);
+        output.append(b"done\n", &redact, Default::default());
+        output.finish_error(&redact);
+        output.flush(&redact);
+        assert_eq!(
+            output.stdout_text(),
+            format!("token={}\ndone\n", redact::MASK)
+        );
+        assert_eq!(output.raw_stdout(), "token=hunter2\ndone\n");
+        assert_eq!(output.shown_stdout(), output.stdout_text());
+        assert!(output.raw_text().contains("warning"));
+    }
+
+    #[test]
+    fn stderr_is_kept_to_max_lines_too() {
+        let mut model = Model::default();
+        model.config.max_lines = 100;
+        model.push_output("make".into(), String::new());
+        model.outputs[0].running = true;
+        let lines = |from: usize, to: usize| -> Vec<u8> {
+            let text: String = (from..to).map(|n| format!("line {n}\n")).collect();
+            text.into_bytes()
+        };
+        model.append_error(0, &lines(0, 100));
+        model.outputs[0].streams().stderr.scroll = 80;
+        model.append_error(0, &lines(100, 150));
+        let output = &model.outputs[0];
+        assert_eq!(output.dropped, 50);
+        let stderr = &output.streams.as_ref().unwrap().stderr;
+        assert_eq!(stderr.text.lines().count(), 100);
+        assert_eq!(stderr.scroll, 30);
+        assert_eq!(stderr.shown().lines().next(), Some("line 80"));
+    }
+
+    #[test]
+    fn a_half_scrolls_by_lines() {
+        let stream = Stream {
+            text: "a\nb\nc\n".into(),
+            scroll: 2,
+            ..Default::default()
+        };
+        assert_eq!(stream.shown(), "c\n");
+        assert_eq!(stream.bottom(2), 1);
+        assert_eq!(stream.bottom(5), 0);
+    }
+}
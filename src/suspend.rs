@@ -0,0 +1,38 @@
+//! Ctrl-Z and `:suspend`: stopping shim to go back to the shell it was
+//! started from, which gets the terminal back as it had it until `fg`.
+//! Neither the key, the command nor a SIGTSTP sent from elsewhere touches
+//! the terminal where it's seen; the main loop puts it back between
+//! draws, so a draw is never cut short, and then stops. On SIGCONT, after
+//! `fg` or being stopped some other way, shim takes the terminal again and
+//! draws the whole screen afresh.
+
+use crate::Model;
+
+/// What the main loop is to do before it next draws.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Pending {
+    #[default]
+    Nothing,
+    /// Give the terminal back and stop.
+    Stop,
+    /// Take the terminal again and draw it all.
+    Redraw,
+}
+
+impl Model {
+    /// Ctrl-Z or `:suspend`, saying why not if it can't be.
+    pub fn suspend(&mut self) -> String {
+        if !cfg!(unix) {
+            return "there is no job control here to suspend shim to".into();
+        }
+        self.suspending = Pending::Stop;
+        String::new()
+    }
+
+    /// SIGCONT: shim was stopped, maybe not by itself.
+    pub fn continued(&mut self) {
+        if self.suspending == Pending::Nothing {
+            self.suspending = Pending::Redraw;
+        }
+    }
+}
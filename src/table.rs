@@ -0,0 +1,375 @@
+//! Output laid out in columns, like `ps`, `docker ps` or `kubectl get`.
+//! Columns are found from the header and the runs of blanks that line up
+//! under it. In a buffer's table mode `w` and `b` move by column, `yy`
+//! yanks a row and `yc` the column under the cursor, and that column is
+//! shaded. Rows that don't line up are left as plain text.
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    style::Style,
+};
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{Cursor, Model, display};
+
+/// Lines looked at to find the columns.
+const SAMPLE: usize = 500;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Table {
+    /// The line the header is on.
+    header: usize,
+    /// Where each column starts and where the blanks after it end, in
+    /// cursor positions. The last column runs to the end of the line.
+    columns: Vec<usize>,
+    /// The blanks between columns, which a row has to keep to.
+    gutters: Vec<(usize, usize)>,
+}
+
+fn is_blank(grapheme: Option<&&str>) -> bool {
+    grapheme.is_none_or(|g| g.trim().is_empty())
+}
+
+/// Columns in `text`, if its first lines look like a header over rows
+/// that line up under it. Unless `loose`, there must be at least two rows
+/// and a gap of two or more blanks somewhere, so a couple of lines of
+/// prose aren't taken for a table.
+pub fn detect(text: &str, loose: bool) -> Option<Table> {
+    let lines: Vec<Vec<&str>> = text
+        .lines()
+        .take(SAMPLE)
+        .map(|l| l.graphemes(true).collect())
+        .collect();
+    let header = lines.iter().position(|l| !is_blank_line(l))?;
+    let rows: Vec<_> = lines[header + 1..]
+        .iter()
+        .filter(|l| !is_blank_line(l))
+        .collect();
+    if rows.is_empty() || (!loose && rows.len() < 2) {
+        return None;
+    }
+    let head = &lines[header];
+    // a few rows may stray into a gap
+    let allowed = rows.len() / 10;
+    let gutter: Vec<bool> = (0..head.len())
+        .map(|x| {
+            is_blank(head.get(x)) && rows.iter().filter(|r| !is_blank(r.get(x))).count() <= allowed
+        })
+        .collect();
+    let mut columns = Vec::new();
+    let mut gutters = Vec::new();
+    for x in 0..gutter.len() {
+        match (x.checked_sub(1).map(|p| gutter[p]), gutter[x]) {
+            (None | Some(true), false) => columns.push(x),
+            (Some(false), true) if !columns.is_empty() => gutters.push((x, x)),
+            _ => {}
+        }
+        if gutter[x]
+            && let Some(last) = gutters.last_mut()
+        {
+            last.1 = x + 1;
+        }
+    }
+    // blanks at the end of the header aren't a gap
+    gutters.truncate(columns.len().saturating_sub(1));
+    // and those before right-aligned numbers are part of the first column
+    if let Some(first) = columns.first_mut() {
+        *first = 0;
+    }
+    let wide = gutters.iter().any(|(from, to)| to - from >= 2);
+    if columns.len() < 2 || (!loose && !wide) {
+        return None;
+    }
+    let table = Table {
+        header,
+        columns,
+        gutters,
+    };
+    let ragged = rows.iter().filter(|r| table.is_ragged(r)).count();
+    (ragged <= allowed).then_some(table)
+}
+
+fn is_blank_line(line: &[&str]) -> bool {
+    line.iter().all(|g| g.trim().is_empty())
+}
+
+impl Table {
+    fn is_ragged(&self, line: &[&str]) -> bool {
+        self.gutters
+            .iter()
+            .any(|(from, to)| (*from..*to).any(|x| !is_blank(line.get(x))))
+    }
+
+    /// Where the columns start on line `index`, or `None` if it isn't one
+    /// of the table's rows.
+    fn starts(&self, index: usize, line: &str) -> Option<&[usize]> {
+        let graphemes: Vec<_> = line.graphemes(true).collect();
+        (index >= self.header && !is_blank_line(&graphemes) && !self.is_ragged(&graphemes))
+            .then_some(&self.columns)
+    }
+
+    /// The column cursor position `x` is in, on a row of the table.
+    pub fn column_at(&self, index: usize, line: &str, x: usize) -> Option<usize> {
+        let starts = self.starts(index, line)?;
+        Some(starts.iter().rposition(|s| *s <= x).unwrap_or(0))
+    }
+
+    /// Cursor positions `column` covers on line `index`, up to the blanks
+    /// after it.
+    pub fn span(&self, index: usize, line: &str, column: usize) -> Option<(usize, usize)> {
+        let starts = self.starts(index, line)?;
+        let start = *starts.get(column)?;
+        let end = match self.gutters.get(column) {
+            Some((from, _)) => *from,
+            None => line.graphemes(true).count(),
+        };
+        Some((start, end.max(start)))
+    }
+
+    /// The text of `column` in every row below the header, skipping empty
+    /// cells and rows that don't line up.
+    pub fn column_text(&self, text: &str, column: usize) -> Vec<String> {
+        text.lines()
+            .enumerate()
+            .skip(self.header + 1)
+            .filter_map(|(i, line)| {
+                let (start, end) = self.span(i, line, column)?;
+                let cell: String = line.graphemes(true).skip(start).take(end - start).collect();
+                let cell = cell.trim();
+                (!cell.is_empty()).then(|| cell.to_string())
+            })
+            .collect()
+    }
+}
+
+/// Where `w` goes from `x` outside a table: the start of the next run of
+/// non-blanks.
+fn next_word(line: &str, x: usize) -> usize {
+    let graphemes: Vec<_> = line.graphemes(true).collect();
+    let mut i = x;
+    while i < graphemes.len() && !is_blank(graphemes.get(i)) {
+        i += 1;
+    }
+    while i < graphemes.len() && is_blank(graphemes.get(i)) {
+        i += 1;
+    }
+    if i >= graphemes.len() { x } else { i }
+}
+
+/// Where `b` goes: the start of the run of non-blanks before `x`.
+fn previous_word(line: &str, x: usize) -> usize {
+    let graphemes: Vec<_> = line.graphemes(true).collect();
+    let mut i = x.min(graphemes.len());
+    while i > 0 && is_blank(graphemes.get(i - 1)) {
+        i -= 1;
+    }
+    while i > 0 && !is_blank(graphemes.get(i - 1)) {
+        i -= 1;
+    }
+    i
+}
+
+impl Model {
+    /// Once buffer `index` is done or filtered anew, with `detect_tables`
+    /// set: look for columns in it, turning table mode on if they're clear
+    /// enough.
+    pub fn detect_table(&mut self, index: usize) {
+        if !self.config.detect_tables {
+            return;
+        }
+        if let Some(output) = self.outputs.get_mut(index) {
+            output.table = detect(output.shown_text(), false);
+        }
+    }
+
+    /// The viewed buffer's table, and the line and its index under the
+    /// cursor, if the cursor is in the output.
+    fn table_line(&self) -> Option<(&Table, usize, &str, usize)> {
+        let Cursor::OutputBuffer(x, y) = self.cursor else {
+            return None;
+        };
+        let output = self.outputs.get(self.viewing_output)?;
+        let table = output.table.as_ref()?;
+        let index = match self.scrollback_top() {
+            Some(top) => self.locate(top + y as usize)?.line?,
+            None => y as usize + output.scroll.0,
+        };
+        Some((table, index, self.output_line(y)?, x as usize))
+    }
+
+    /// Whether `y` starts `yy` and `yc` rather than taking a suggestion.
+    pub fn in_table(&self) -> bool {
+        self.table_line().is_some()
+    }
+
+    /// `w` and `b` in the output: to the next or previous column on a row
+    /// of a table, or else word.
+    pub fn column_motion(&mut self, forward: bool) {
+        let Cursor::OutputBuffer(x, y) = self.cursor else {
+            return;
+        };
+        let Some(line) = self.output_line(y) else {
+            return;
+        };
+        let x = x as usize;
+        let cell = self
+            .table_line()
+            .and_then(|(table, index, line, x)| Some((table, table.column_at(index, line, x)?)));
+        let to = match (cell, forward) {
+            (Some((table, column)), true) => table.columns.get(column + 1).copied(),
+            (Some((table, column)), false) => {
+                // back to the start of this column first, like `b`
+                let start = table.columns[column];
+                match start < x {
+                    true => Some(start),
+                    false => column.checked_sub(1).map(|c| table.columns[c]),
+                }
+            }
+            (None, true) => Some(next_word(line, x)),
+            (None, false) => Some(previous_word(line, x)),
+        };
+        let last = crate::display::len(line).saturating_sub(1);
+        if let Some(to) = to.filter(|to| *to <= last) {
+            self.cursor = Cursor::OutputBuffer(to as u16, y);
+        }
+    }
+
+    /// `yc`: yank the column under the cursor, a line for each cell.
+    pub fn yank_column(&mut self) {
+        let Some((table, index, line, x)) = self.table_line() else {
+            self.status = Some("not in a table".into());
+            return;
+        };
+        let Some(column) = table.column_at(index, line, x) else {
+            self.status = Some("this line isn't a row of the table".into());
+            return;
+        };
+        let text = self.outputs[self.viewing_output].shown_text();
+        let cells = table.column_text(text, column);
+        self.yank_text(cells.join("\n"));
+    }
+
+    /// `:table`: toggle table mode for the viewed buffer.
+    pub fn toggle_table(&mut self) -> String {
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return "no buffer".into();
+        };
+        if output.table.take().is_some() {
+            return format!("table mode off in {}", output.command);
+        }
+        match detect(output.shown_text(), true) {
+            Some(table) => {
+                let count = table.columns.len();
+                output.table = Some(table);
+                format!("table mode on: {count} columns")
+            }
+            None => "no columns found".into(),
+        }
+    }
+}
+
+/// Shade the column under the cursor in the output pane `area`, when it's
+/// on a row of a table. Wrapped output and the scrollback view aren't
+/// shaded.
+pub fn render(model: &Model, frame: &mut Frame, area: Rect) {
+    if model.config.wrap || model.scrollback.is_some() {
+        return;
+    }
+    let Some((table, at, current, x)) = model.table_line() else {
+        return;
+    };
+    let Some(column) = table.column_at(at, current, x) else {
+        return;
+    };
+    let output = &model.outputs[model.viewing_output];
+    let settings = output.display_settings(&model.config);
+    let inner = area.inner(Margin::new(1, 1));
+    let (top, left) = (output.scroll.0, output.scroll.1 as usize);
+    let style = Style::new().bg(model.config.theme.column);
+    let lines = output.text_from(top).lines().take(inner.height as usize);
+    for (row, line) in lines.enumerate() {
+        let index = top + row;
+        let Some((start, end)) = table.span(index, line, column) else {
+            continue;
+        };
+        let from = display::column(line, start, settings).saturating_sub(left);
+        let to = display::column(line, end, settings).saturating_sub(left);
+        let to = to.min(inner.width as usize);
+        if from >= to {
+            continue;
+        }
+        let cells = Rect::new(
+            inner.x + from as u16,
+            inner.y + row as u16,
+            (to - from) as u16,
+            1,
+        );
+        frame.buffer_mut().set_style(cells, style);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PS: &str = "    PID TTY          TIME CMD
+      1 ?        00:00:03 init
+    512 pts/0    00:00:00 bash
+  10233 pts/0    00:00:00 ps
+";
+
+    const DOCKER: &str = "CONTAINER ID   IMAGE     COMMAND       STATUS
+a1b2c3d4e5f6   nginx     \"nginx -g\"    Up 2 hours
+0f9e8d7c6b5a   redis     \"redis\"       Up 5 minutes
+";
+
+    #[test]
+    fn finds_columns_under_a_header() {
+        let table = detect(PS, false).unwrap();
+        assert_eq!(table.columns, [0, 8, 17, 26]);
+        let table = detect(DOCKER, false).unwrap();
+        assert_eq!(table.columns, [0, 15, 25, 39]);
+        assert_eq!(
+            table.column_text(DOCKER, 0),
+            ["a1b2c3d4e5f6", "0f9e8d7c6b5a"]
+        );
+        assert_eq!(table.column_text(DOCKER, 3), ["Up 2 hours", "Up 5 minutes"]);
+    }
+
+    #[test]
+    fn prose_is_not_a_table() {
+        assert_eq!(detect("a b\nc d\ne f\n", false), None);
+        assert_eq!(detect("hello there\nhow are you\n", false), None);
+        assert_eq!(detect("just one line\n", false), None);
+    }
+
+    #[test]
+    fn rows_that_dont_line_up_are_plain() {
+        let rows: String = (1..=10)
+            .map(|pid| format!("{pid:>7} pts/0    00:00:00 bash\n"))
+            .collect();
+        let text = format!("{PS}{rows}a note that runs across the columns\n");
+        let table = detect(&text, false).unwrap();
+        let note = text.lines().count() - 1;
+        let line = text.lines().last().unwrap();
+        assert_eq!(table.column_at(note, line, 3), None);
+        assert_eq!(
+            table.column_at(1, "      1 ?        00:00:03 init", 12),
+            Some(1)
+        );
+        // but too many of them and it isn't a table at all
+        let notes = "a note that runs across the columns\n".repeat(3);
+        assert_eq!(detect(&format!("{PS}{rows}{notes}"), false), None);
+    }
+
+    #[test]
+    fn words_outside_tables() {
+        assert_eq!(next_word("one two  three", 0), 4);
+        assert_eq!(next_word("one two  three", 5), 9);
+        assert_eq!(next_word("one two", 5), 5);
+        assert_eq!(previous_word("one two  three", 9), 4);
+        assert_eq!(previous_word("one two  three", 6), 4);
+        assert_eq!(previous_word("one", 0), 0);
+    }
+}
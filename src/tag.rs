@@ -0,0 +1,256 @@
+//! Tagging buffers (`:tag NAME`) so a group of them, say a test watcher and
+//! a server, can be re-run together with `:retag-run NAME`.
+
+use std::collections::BTreeMap;
+
+use crate::{Model, Output, vars};
+
+/// A `:retag-run` waiting for its buffers to finish. Another started
+/// meanwhile joins it, so there's one summary once they're all done.
+#[derive(Debug, Default)]
+pub struct Rerun {
+    /// Buffers still running.
+    pending: Vec<usize>,
+    /// Buffers done, and whether they succeeded.
+    done: BTreeMap<usize, bool>,
+}
+
+impl Output {
+    /// Tags for the title and `:buffers`.
+    pub fn tag_note(&self) -> Option<String> {
+        (!self.tags.is_empty()).then(|| format!("[{}]", self.tags.join(", ")))
+    }
+
+    /// When a re-run buffer last started, for the title.
+    pub fn rerun_note(&self) -> Option<String> {
+        let started = self.started.filter(|_| self.reruns > 0 && !self.running)?;
+        Some(format!("re-run {}s ago", started.elapsed().as_secs()))
+    }
+}
+
+impl Model {
+    /// `:tag [NAME]`: add NAME to the viewed buffer's tags, or take it off
+    /// if it's there already. Returns what to show on the status line.
+    pub fn tag(&mut self, name: &str) -> String {
+        let Some(output) = self.outputs.get_mut(self.viewing_output) else {
+            return "no buffer to tag".into();
+        };
+        if name.is_empty() {
+            return match output.tag_note() {
+                Some(tags) => format!("{} {tags}", output.command),
+                None => "usage: :tag NAME".into(),
+            };
+        }
+        // only buffers of commands shim started can be run again
+        if output.started.is_none() || output.each.is_some() {
+            return "only a command's buffer can be tagged".into();
+        }
+        match output.tags.iter().position(|t| t == name) {
+            Some(i) => {
+                output.tags.remove(i);
+                format!("untagged {} {name}", output.command)
+            }
+            None => {
+                output.tags.push(name.to_string());
+                format!("tagged {} {name}", output.command)
+            }
+        }
+    }
+
+    /// `:retag-run NAME`: run every buffer tagged NAME again in place, all
+    /// at once. Ones still running are killed and started over; if that's
+    /// from an earlier `:retag-run`, they're still counted once.
+    pub fn retag_run(&mut self, name: &str) -> String {
+        if name.is_empty() {
+            return "usage: :retag-run NAME".into();
+        }
+        let mut pending = Vec::new();
+        for output in &mut self.outputs {
+            if !output.tags.iter().any(|t| t == name) {
+                continue;
+            }
+            pending.push(output.id);
+            output.retry = None;
+            if output.running {
+                // started again once it's gone; see `finish_job`
+                output.restart = true;
+                output.kill();
+            }
+        }
+        if pending.is_empty() {
+            return format!("no buffers tagged {name}");
+        }
+        let count = pending.len();
+        let going = self.rerun.is_some();
+        let rerun = self.rerun.get_or_insert_with(Rerun::default);
+        for id in &pending {
+            rerun.done.remove(id);
+            if !rerun.pending.contains(id) {
+                rerun.pending.push(*id);
+            }
+        }
+        for id in pending {
+            if self.outputs.iter().any(|o| o.id == id && !o.running) {
+                self.run_again(id, false);
+            }
+        }
+        match going {
+            true => format!("re-running {count} buffers tagged {name}, with the earlier re-run"),
+            false => format!("re-running {count} buffers tagged {name}"),
+        }
+    }
+
+    /// Start buffer `id`'s command over, into the same buffer: in place of
+    /// what's there, or after it with `append`.
+    pub fn run_again(&mut self, id: usize, append: bool) {
+        let Some(command) = self
+            .outputs
+            .iter()
+            .find(|o| o.id == id)
+            .map(|o| o.command.clone())
+        else {
+            return;
+        };
+        let line = vars::split_capture(&command).map_or(command.as_str(), |(_, line)| line);
+        let spawned = self
+            .parse_pipeline(line)
+            .map(|stages| self.spawn_stages(stages, id));
+        let Some(index) = self.outputs.iter().position(|o| o.id == id) else {
+            return;
+        };
+        if append {
+            self.append_section(index);
+        }
+        let output = &mut self.outputs[index];
+        if !append {
+            output.stdout.clear();
+            output.line_index.changed();
+            output.clear_checkpoints();
+            output.bookmarks.clear();
+            output.evicted = None;
+            output.raw = None;
+            output.streams = None;
+            output.section = None;
+            output.partial.clear();
+            output.images.clear();
+            output.warnings.clear();
+            output.usage = Default::default();
+            output.set_scroll(0);
+        }
+        // a filter of what was there isn't wanted
+        output.piping = None;
+        output.decoder.reset();
+        output.retry = None;
+        output.status = None;
+        output.stages.clear();
+        output.duration = None;
+        output.restart = false;
+        output.mark_started();
+        output.reruns += 1;
+        match spawned {
+            Some(Ok(process)) => {
+                output.running = true;
+                output.process = Some(process);
+            }
+            Some(Err(e)) => {
+                output.warn(format!("couldn't start: {e}"));
+                self.finish_job(id, Some(126));
+            }
+            None => self.finish_job(id, Some(1)),
+        }
+    }
+
+    /// Count buffer `id` as done if it's part of a `:retag-run`, and sum up
+    /// once they all are.
+    pub fn rerun_finished(&mut self, id: usize, status: Option<i32>) {
+        let Some(rerun) = self.rerun.as_mut() else {
+            return;
+        };
+        let Some(i) = rerun.pending.iter().position(|p| *p == id) else {
+            return;
+        };
+        rerun.pending.remove(i);
+        rerun.done.insert(id, status == Some(0));
+        if rerun.pending.is_empty() {
+            let ok = rerun.done.values().filter(|ok| **ok).count();
+            let failed = rerun.done.len() - ok;
+            self.status = Some(format!("{} re-run: {ok} ok, {failed} failed", ok + failed));
+            self.rerun = None;
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::{
+        sync::mpsc::{self, Receiver},
+        time::Instant,
+    };
+
+    use super::*;
+    use crate::event::AppEvent;
+
+    /// Buffers of `commands` that ran, all tagged `t`. Their jobs' ends
+    /// are given by hand, so what they send is left unread.
+    fn tagged(commands: &[&str]) -> (Model, Receiver<AppEvent>) {
+        let (events, received) = mpsc::channel();
+        let mut model = Model {
+            events: Some(events),
+            ..Default::default()
+        };
+        for command in commands {
+            model.push_output(command.to_string(), String::new());
+            let output = model.outputs.last_mut().unwrap();
+            output.started = Some(Instant::now());
+            output.tags.push("t".into());
+        }
+        (model, received)
+    }
+
+    #[test]
+    fn idle_buffers_are_all_run_again() {
+        let (mut model, _events) = tagged(&["true", "false"]);
+        assert_eq!(model.retag_run("t"), "re-running 2 buffers tagged t");
+        assert!(model.outputs.iter().all(|o| o.running && o.reruns == 1));
+        model.finish_job(0, Some(0));
+        assert_eq!(model.status, None);
+        model.finish_job(1, Some(1));
+        assert_eq!(model.status.as_deref(), Some("2 re-run: 1 ok, 1 failed"));
+        assert!(model.rerun.is_none());
+        assert_eq!(model.retag_run("x"), "no buffers tagged x");
+    }
+
+    #[test]
+    fn a_running_buffer_is_killed_and_started_once_more() {
+        let (mut model, _events) = tagged(&["true", "true"]);
+        model.outputs[0].running = true;
+        model.retag_run("t");
+        assert!(model.outputs[0].restart);
+        assert_eq!(model.outputs[0].reruns, 0);
+        // its end from being killed isn't counted
+        model.finish_job(0, None);
+        assert_eq!(model.outputs[0].reruns, 1);
+        assert!(model.outputs[0].running);
+        model.finish_job(1, Some(0));
+        assert_eq!(model.status, None);
+        model.finish_job(0, Some(0));
+        assert_eq!(model.status.as_deref(), Some("2 re-run: 2 ok, 0 failed"));
+    }
+
+    #[test]
+    fn another_re_run_meanwhile_joins_the_first() {
+        let (mut model, _events) = tagged(&["false", "true"]);
+        model.retag_run("t");
+        model.finish_job(0, Some(1));
+        assert_eq!(
+            model.retag_run("t"),
+            "re-running 2 buffers tagged t, with the earlier re-run"
+        );
+        model.finish_job(1, None);
+        model.finish_job(0, Some(0));
+        model.finish_job(1, Some(0));
+        // each counted once, as it last ended
+        assert_eq!(model.status.as_deref(), Some("2 re-run: 2 ok, 0 failed"));
+        assert_eq!(model.outputs[1].reruns, 2);
+    }
+}
@@ -0,0 +1,186 @@
+//! Text objects for `c`, `d` and `y` on the command line: `iw` and `aw`
+//! for a word, `ia` and `aa` for a shell argument, so a quoted string is
+//! taken whole. `dd` deletes a line of it.
+
+use std::ops::Range;
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+use crate::{Cursor, Mode, Model, edit::class, shell};
+
+/// The word or run of blanks at byte `at` of `line`. Around a word takes
+/// the blanks after it too, or if there are none those before; around
+/// blanks takes the word after them.
+pub fn word(line: &str, at: usize, around: bool) -> Option<Range<usize>> {
+    let (at, c) = line.char_indices().take_while(|(i, _)| *i <= at).last()?;
+    let run_end = |from: usize| {
+        let first = line[from..].chars().next().map_or(0, class);
+        line[from..]
+            .find(|c| class(c) != first)
+            .map_or(line.len(), |i| from + i)
+    };
+    let start = line[..at].rfind(|b| class(b) != class(c)).map_or(0, |i| {
+        i + line[i..].chars().next().map_or(1, char::len_utf8)
+    });
+    let end = run_end(at);
+    if !around {
+        return Some(start..end);
+    }
+    if class(c) == 0 {
+        return Some(start..run_end(end));
+    }
+    let blanks_after = run_end(end);
+    if line[end..].starts_with(char::is_whitespace) {
+        return Some(start..blanks_after);
+    }
+    let blanks_before = line[..start].trim_end().len();
+    Some(blanks_before..end)
+}
+
+/// The argument at byte `at` of `line` as the shell would split it, quotes
+/// and all, or the next one if `at` is between two. Around an argument
+/// takes the blanks after it too, or if there are none those before.
+pub fn argument(line: &str, at: usize, around: bool) -> Option<Range<usize>> {
+    let spans = shell::token_spans(line);
+    let i = spans
+        .iter()
+        .position(|s| at < s.end)
+        .or(spans.len().checked_sub(1))?;
+    let span = spans[i].clone();
+    if !around {
+        return Some(span);
+    }
+    let blanks_after = spans.get(i + 1).map_or(line.len(), |s| s.start);
+    if blanks_after > span.end {
+        return Some(span.start..blanks_after);
+    }
+    let blanks_before = i.checked_sub(1).map_or(0, |j| spans[j].end);
+    Some(blanks_before..span.end)
+}
+
+impl Model {
+    /// The operator `key` starts in normal mode, waiting for a text object:
+    /// `c` or `d` with the cursor on the command line, or `y` there when
+    /// there's no suggestion for it to take and no register chosen for it
+    /// to yank the command into.
+    pub fn operator(&self, key: KeyEvent) -> Option<char> {
+        let Cursor::CommandLine(_, _) = self.cursor else {
+            return None;
+        };
+        let suggested = self
+            .outputs
+            .get(self.viewing_output)
+            .is_some_and(|o| o.suggestion.is_some());
+        match key.code {
+            _ if !key.modifiers.is_empty() => None,
+            KeyCode::Char(c @ ('c' | 'd')) => Some(c),
+            KeyCode::Char('y') if !suggested && self.register.is_none() => Some('y'),
+            _ => None,
+        }
+    }
+
+    /// `ciw`, `daa`, `yia` and the like: change, delete or yank a word or
+    /// argument of the command line under the cursor. Deleted text goes
+    /// into the registers as a yank would.
+    pub fn text_object(&mut self, operator: char, around: bool, argument: bool) {
+        let Some((offset, start, end)) = self.edit_position() else {
+            return;
+        };
+        let line = &self.current_command[start..end];
+        let span = match argument {
+            true => self::argument(line, offset - start, around),
+            false => word(line, offset - start, around),
+        };
+        let Some(span) = span else {
+            return;
+        };
+        let text = line[span.clone()].to_string();
+        let span = start + span.start..start + span.end;
+        self.edit_cursor(span.start, start);
+        if operator == 'y' {
+            self.yank_text(text);
+            return;
+        }
+        self.registers.store(self.register.take(), text);
+        self.current_command.replace_range(span, "");
+        match operator {
+            'c' => self.mode = Mode::Insert,
+            _ => self.clamp_command_cursor(),
+        }
+    }
+
+    /// `dd`: delete the command line's line under the cursor, newline and
+    /// all, into the registers.
+    pub fn delete_line(&mut self) {
+        let Some((_, start, end)) = self.edit_position() else {
+            return;
+        };
+        let text = self.current_command[start..end].to_string();
+        // the newline after it, or else the one before
+        let span = match self.current_command[end..].starts_with('\n') {
+            true => start..end + 1,
+            false => start.saturating_sub(1)..end,
+        };
+        self.registers.store(self.register.take(), text);
+        self.current_command.replace_range(span, "");
+        if let Cursor::CommandLine(_, y) = self.cursor {
+            let last = self.get_command_rows() - 1;
+            self.cursor = Cursor::CommandLine(0, y.min(last));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(line: &str, at: usize, around: bool) -> &str {
+        &line[word(line, at, around).unwrap()]
+    }
+
+    fn args(line: &str, at: usize, around: bool) -> &str {
+        &line[argument(line, at, around).unwrap()]
+    }
+
+    #[test]
+    fn words_break_at_blanks_and_punctuation() {
+        let line = "git log --oneline";
+        assert_eq!(words(line, 0, false), "git");
+        assert_eq!(words(line, 2, false), "git");
+        assert_eq!(words(line, 2, true), "git ");
+        assert_eq!(words(line, 8, false), "--");
+        assert_eq!(words(line, 10, false), "oneline");
+        // at the end there are no blanks after, so those before go
+        assert_eq!(words(line, 16, true), "oneline");
+        assert_eq!(words("ls  -l", 7, true), "l");
+        assert_eq!(words("ls  x", 4, true), "  x");
+        // on blanks, inner is the blanks and around takes the next word
+        assert_eq!(words("ls  -l", 2, false), "  ");
+        assert_eq!(words("ls  -l", 3, true), "  -");
+        assert_eq!(word("", 0, false), None);
+    }
+
+    #[test]
+    fn arguments_keep_quotes_together() {
+        let line = r#"git commit -m "some long message" --amend"#;
+        for at in 14..=32 {
+            assert_eq!(args(line, at, false), "\"some long message\"");
+        }
+        assert_eq!(args(line, 14, true), "\"some long message\" ");
+        assert_eq!(args(line, 0, false), "git");
+        assert_eq!(args(line, 2, false), "git");
+        assert_eq!(args(line, 35, true), " --amend");
+        assert_eq!(args("echo 'a b'c\\ d", 7, false), "'a b'c\\ d");
+        assert_eq!(args("echo 'it's", 6, false), "'it's");
+    }
+
+    #[test]
+    fn between_arguments_is_the_next_one() {
+        let line = "ls   -la  /tmp";
+        assert_eq!(args(line, 2, false), "-la");
+        assert_eq!(args(line, 4, true), "-la  ");
+        assert_eq!(args("ls -l   ", 6, false), "-l");
+        assert_eq!(args("ls -l   ", 6, true), "-l   ");
+        assert_eq!(argument("   ", 1, false), None);
+    }
+}
@@ -0,0 +1,93 @@
+//! Colours shim draws with, set in the config as `theme_NAME = COLOUR`,
+//! where COLOUR is a name like `green` or `dark gray`, `rrggbb` in hex, or
+//! a 256-colour index.
+
+use ratatui::style::{Color, Style, Stylize};
+
+use crate::{Mode, toast::Level};
+
+#[derive(Debug, Clone)]
+pub struct Theme {
+    /// Border of the pane with the cursor.
+    pub focused: Color,
+    /// Border of the other one.
+    pub unfocused: Color,
+    /// Behind the mode on the status line.
+    pub insert: Color,
+    pub normal: Color,
+    pub replace: Color,
+    /// Behind the column under the cursor in a table.
+    pub column: Color,
+    /// Behind toasts, by level.
+    pub info: Color,
+    pub warn: Color,
+    pub error: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            focused: Color::White,
+            unfocused: Color::DarkGray,
+            insert: Color::Green,
+            normal: Color::Blue,
+            replace: Color::Magenta,
+            column: Color::Indexed(236),
+            info: Color::Cyan,
+            warn: Color::Yellow,
+            error: Color::Red,
+        }
+    }
+}
+
+impl Theme {
+    pub fn set(&mut self, name: &str, value: &str) -> Result<(), String> {
+        // `#` starts a comment in the config, so hex colours go without it
+        let hex = value.len() == 6 && value.chars().all(|c| c.is_ascii_hexdigit());
+        let color = match hex {
+            true => format!("#{value}").parse(),
+            false => value.parse(),
+        }
+        .map_err(|_| format!("not a colour: {value}"))?;
+        match name {
+            "focused" => self.focused = color,
+            "unfocused" => self.unfocused = color,
+            "insert" => self.insert = color,
+            "normal" => self.normal = color,
+            "replace" => self.replace = color,
+            "column" => self.column = color,
+            "info" => self.info = color,
+            "warn" => self.warn = color,
+            "error" => self.error = color,
+            _ => return Err(format!("unknown theme colour: {name}")),
+        }
+        Ok(())
+    }
+
+    pub fn border(&self, focused: bool) -> Style {
+        match focused {
+            true => Style::new().fg(self.focused),
+            false => Style::new().fg(self.unfocused),
+        }
+    }
+
+    /// The mode on the status line.
+    pub fn mode(&self, mode: &Mode) -> Style {
+        let background = match mode {
+            Mode::Insert => self.insert,
+            Mode::Normal => self.normal,
+            Mode::Replace => self.replace,
+        };
+        Style::new().black().bg(background)
+    }
+
+    /// A toast.
+    pub fn toast(&self, level: Level) -> Style {
+        let background = match level {
+            Level::Info => self.info,
+            Level::Warn => self.warn,
+            Level::Error => self.error,
+        };
+        Style::new().black().bg(background)
+    }
+}
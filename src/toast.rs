@@ -0,0 +1,188 @@
+//! Toasts: short notes that show in the corner of the output pane for a
+//! few seconds and go by themselves, for things worth knowing that don't
+//! need an answer, like a job finishing in a buffer that isn't viewed.
+//! `:messages` lists the recent ones for anything missed.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use ratatui::{
+    Frame,
+    layout::{Margin, Rect},
+    text::Line,
+    widgets::{Clear, Paragraph},
+};
+use unicode_width::UnicodeWidthStr;
+
+use crate::{Model, redact};
+
+/// How long a toast shows unless it says otherwise.
+pub const TTL: Duration = Duration::from_secs(4);
+/// Toasts shown at once; an older one goes to make room.
+const SHOWN: usize = 3;
+/// Toasts kept for `:messages`.
+const KEPT: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn name(self) -> &'static str {
+        match self {
+            Level::Info => "info",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Toast {
+    level: Level,
+    text: String,
+    until: Instant,
+}
+
+#[derive(Debug, Default)]
+pub struct Toasts {
+    /// Showing, oldest first.
+    shown: VecDeque<Toast>,
+    /// All of them lately, oldest first.
+    past: VecDeque<(Level, String)>,
+}
+
+impl Toasts {
+    fn push(&mut self, level: Level, text: String, until: Instant) {
+        if self.past.len() == KEPT {
+            self.past.pop_front();
+        }
+        self.past.push_back((level, text.clone()));
+        if self.shown.len() == SHOWN {
+            self.shown.pop_front();
+        }
+        self.shown.push_back(Toast { level, text, until });
+    }
+
+    /// Drop the toasts whose time is up by `now`.
+    fn expire(&mut self, now: Instant) {
+        self.shown.retain(|t| t.until > now);
+    }
+
+    /// When the next toast goes.
+    fn due(&self) -> Option<Instant> {
+        self.shown.iter().map(|t| t.until).min()
+    }
+
+    /// The recent toasts, a line each, for `:messages`.
+    fn history(&self) -> String {
+        match self.past.is_empty() {
+            true => "no messages yet".into(),
+            false => self
+                .past
+                .iter()
+                .map(|(level, text)| format!("{:<5} {text}", level.name()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        }
+    }
+}
+
+impl Model {
+    /// Show `text` for `ttl`.
+    pub fn push_toast(&mut self, level: Level, text: impl Into<String>, ttl: Duration) {
+        let text = text.into().replace('\n', " ");
+        self.toasts.push(level, text, Instant::now() + ttl);
+    }
+
+    pub fn expire_toasts(&mut self, now: Instant) {
+        self.toasts.expire(now);
+    }
+
+    /// When a toast is to go.
+    pub fn toast_due(&self) -> Option<Instant> {
+        self.toasts.due()
+    }
+
+    /// Say how job `job_id` ended if its buffer isn't the one in view,
+    /// or is in a workspace that isn't.
+    pub fn toast_unseen_exit(&mut self, job_id: usize, code: i32) {
+        let Some(index) = self.outputs.iter().position(|o| o.id == job_id) else {
+            return;
+        };
+        let in_view = index == self.viewing_output || self.scrollback.is_some();
+        if in_view && !self.workspaces.visiting() {
+            return;
+        }
+        let command = self.outputs[index].command.replace('\n', " ");
+        let command = redact::line(&self.config.redact, &command).into_owned();
+        match code {
+            0 => self.push_toast(Level::Info, format!("done: {command}"), TTL),
+            code => self.push_toast(Level::Error, format!("exit {code}: {command}"), TTL),
+        }
+    }
+
+    /// `:messages`
+    pub fn messages(&self) -> String {
+        self.toasts.history()
+    }
+}
+
+/// The toasts showing, newest lowest, right-aligned at the bottom of the
+/// output pane `pane`.
+pub fn render(model: &Model, frame: &mut Frame, pane: Rect) {
+    let inner = pane.inner(Margin::new(1, 1));
+    let theme = &model.config.theme;
+    for (i, toast) in model.toasts.shown.iter().rev().enumerate() {
+        let Some(y) = (inner.y + inner.height).checked_sub(i as u16 + 1) else {
+            break;
+        };
+        if y < inner.y {
+            break;
+        }
+        let text = format!(" {} ", toast.text);
+        let width = (text.width() as u16).min(inner.width);
+        let area = Rect {
+            x: inner.x + inner.width - width,
+            y,
+            width,
+            height: 1,
+        };
+        frame.render_widget(Clear, area);
+        frame.render_widget(
+            Paragraph::new(Line::raw(text)).style(theme.toast(toast.level)),
+            area,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn toasts_go_in_time_and_make_room() {
+        let mut toasts = Toasts::default();
+        let now = Instant::now();
+        let second = Duration::from_secs(1);
+        for i in 0..4 {
+            toasts.push(Level::Info, format!("t{i}"), now + second * (4 - i));
+        }
+        // the oldest went to make room
+        let shown = |t: &Toasts| t.shown.iter().map(|t| t.text.clone()).collect::<Vec<_>>();
+        assert_eq!(shown(&toasts), ["t1", "t2", "t3"]);
+        assert_eq!(toasts.due(), Some(now + second));
+        toasts.expire(now + second * 2);
+        assert_eq!(shown(&toasts), ["t1"]);
+        toasts.expire(now + second * 3);
+        assert_eq!(toasts.due(), None);
+        // but all of them are kept
+        assert_eq!(toasts.history(), "info  t0\ninfo  t1\ninfo  t2\ninfo  t3");
+        assert_eq!(Toasts::default().history(), "no messages yet");
+    }
+}
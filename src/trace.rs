@@ -0,0 +1,187 @@
+//! A trace of what shim did, for working out how a bug came about. With
+//! `--debug` or `debug = true`, every message `update` handles is kept
+//! along with the mode, cursor and viewed buffer it left behind and how
+//! long it took, the last `LEN` of them. `:trace` shows them in a buffer,
+//! and with `trace_file = PATH` they're written there if shim panics.
+//! Nothing is put into words until it's shown, so keeping them costs
+//! little. Characters typed into the command line or a snippet are kept
+//! as `Typed` unless `trace_keys` is set, as commands may hold secrets,
+//! and output and other bulky messages only by their size.
+
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    fs,
+    path::PathBuf,
+    sync::{Arc, Mutex, OnceLock, PoisonError, TryLockError},
+    time::{Duration, Instant},
+};
+
+use crate::{Cursor, Message, Mode, Model};
+
+/// Messages kept.
+pub const LEN: usize = 2000;
+
+/// The trace shim's panic hook writes out, and where.
+static ON_PANIC: OnceLock<(Trace, PathBuf)> = OnceLock::new();
+
+/// A message as it's kept.
+#[derive(Debug)]
+enum Traced {
+    Message(Message),
+    /// A character typed into a command.
+    Typed,
+    /// A message with a lot in it, by name and size.
+    Bulk(&'static str, usize),
+}
+
+impl Traced {
+    fn of(msg: &Message, keys: bool) -> Self {
+        match msg {
+            Message::WriteCommandChar(_) | Message::ReplaceChar(_) | Message::SnippetChar(_)
+                if !keys =>
+            {
+                Traced::Typed
+            }
+            Message::OutputChunk(_, data) => Traced::Bulk("OutputChunk", data.len()),
+            Message::ErrorChunk(_, data) => Traced::Bulk("ErrorChunk", data.len()),
+            Message::Files(_, paths, _) => Traced::Bulk("Files", paths.len()),
+            Message::Derived(_, text) => Traced::Bulk("Derived", text.len()),
+            Message::Previewed(_, result) => {
+                Traced::Bulk("Previewed", result.as_ref().map_or(0, String::len))
+            }
+            Message::Piped(_, _, _, result) => {
+                Traced::Bulk("Piped", result.as_ref().map_or(0, String::len))
+            }
+            Message::HistoryImported(_) => Traced::Bulk("HistoryImported", 0),
+            Message::HistoryLoaded(loaded) => Traced::Bulk("HistoryLoaded", loaded.len()),
+            Message::Completions(..) => Traced::Bulk("Completions", 0),
+            msg => Traced::Message(msg.clone()),
+        }
+    }
+}
+
+/// A message handled, and what it left.
+#[derive(Debug)]
+struct Entry {
+    /// Since the trace started.
+    at: Duration,
+    took: Duration,
+    msg: Traced,
+    mode: Mode,
+    cursor: Cursor,
+    viewing: usize,
+}
+
+#[derive(Debug)]
+pub struct Ring {
+    start: Instant,
+    entries: VecDeque<Entry>,
+}
+
+impl Default for Ring {
+    fn default() -> Self {
+        Ring {
+            start: Instant::now(),
+            entries: VecDeque::with_capacity(LEN),
+        }
+    }
+}
+
+impl Ring {
+    fn push(&mut self, entry: Entry) {
+        if self.entries.len() == LEN {
+            self.entries.pop_front();
+        }
+        self.entries.push_back(entry);
+    }
+
+    /// The messages kept, oldest first, a line each.
+    fn dump(&self) -> String {
+        let mut text = String::new();
+        for e in &self.entries {
+            let msg = match &e.msg {
+                Traced::Message(msg) => format!("{msg:?}"),
+                Traced::Typed => "Typed".into(),
+                Traced::Bulk(name, size) => format!("{name} ({size})"),
+            };
+            let _ = writeln!(
+                text,
+                "{:>10.3}s {:>7}µs  {:<7} {:<22} buf {:<3} {msg}",
+                e.at.as_secs_f64(),
+                e.took.as_micros(),
+                format!("{:?}", e.mode),
+                format!("{:?}", e.cursor),
+                e.viewing + 1,
+            );
+        }
+        text
+    }
+}
+
+pub type Trace = Arc<Mutex<Ring>>;
+
+/// A message about to be handled, to be kept once it has been.
+pub struct Pending {
+    msg: Traced,
+    started: Instant,
+}
+
+impl Model {
+    /// Start keeping a trace, if it isn't already, and have it written to
+    /// `trace_file` on a panic.
+    pub fn start_trace(&mut self) {
+        let trace = self.trace.get_or_insert_with(Trace::default);
+        if let Some(path) = &self.config.trace_file {
+            let _ = ON_PANIC.set((trace.clone(), path.clone()));
+        }
+    }
+
+    /// Note `msg` as it's about to be handled, if there's a trace.
+    pub fn trace_start(&self, msg: &Message) -> Option<Pending> {
+        self.trace.as_ref()?;
+        Some(Pending {
+            msg: Traced::of(msg, self.config.trace_keys),
+            started: Instant::now(),
+        })
+    }
+
+    /// Keep the message `pending` was for, now it's been handled.
+    pub fn trace_end(&self, pending: Pending) {
+        let Some(trace) = &self.trace else {
+            return;
+        };
+        let mut ring = trace.lock().unwrap_or_else(PoisonError::into_inner);
+        let entry = Entry {
+            at: pending.started.duration_since(ring.start),
+            took: pending.started.elapsed(),
+            msg: pending.msg,
+            mode: self.mode,
+            cursor: self.cursor,
+            viewing: self.viewing_output,
+        };
+        ring.push(entry);
+    }
+
+    /// `:trace`
+    pub fn dump_trace(&self) -> String {
+        match &self.trace {
+            Some(trace) => trace.lock().unwrap_or_else(PoisonError::into_inner).dump(),
+            None => "no trace is kept; start shim with --debug or set debug = true".into(),
+        }
+    }
+}
+
+/// Write the trace to `trace_file`, from the panic hook.
+pub fn write_on_panic() {
+    let Some((trace, path)) = ON_PANIC.get() else {
+        return;
+    };
+    // the panic may have come while it was held
+    let ring = match trace.try_lock() {
+        Ok(ring) => ring,
+        Err(TryLockError::Poisoned(e)) => e.into_inner(),
+        Err(TryLockError::WouldBlock) => return,
+    };
+    let _ = fs::write(path, ring.dump());
+}
@@ -0,0 +1,173 @@
+//! Keys typed while shim was busy. When a frame takes long to draw or a
+//! key long to handle, keys pile up unseen and would then land on
+//! whatever is on screen afterwards, which needn't be what they were
+//! typed for: a half-typed command gets submitted, or a question answered.
+//! Keys are stamped as they're read, so those read while shim was busy
+//! are told apart, and `stale_keys` says what becomes of them: `last`
+//! keeps only the last one, `none` drops them all and `all` keeps them.
+//! An Enter read just after a key that switched mode is dropped too, as
+//! it was more likely held over than meant for the new mode. Keys read
+//! while shim was keeping up are never dropped, however fast they came.
+
+use std::time::{Duration, Instant};
+
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+
+use crate::{Model, event::AppEvent, toast};
+
+/// Busy for this long, the keys read meanwhile are stale.
+pub const BUSY: Duration = Duration::from_millis(400);
+/// An Enter this soon after a mode switch is dropped.
+pub const BOUNCE: Duration = Duration::from_millis(20);
+
+/// Which keys read while shim was busy are kept.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Keep {
+    All,
+    Last,
+    None,
+}
+
+#[derive(Debug, Default)]
+pub struct Typeahead {
+    /// When shim was last busy for long, from and to.
+    busy: Option<(Instant, Instant)>,
+    /// When the last key that switched mode was read.
+    switched: Option<Instant>,
+}
+
+impl Typeahead {
+    /// Note that shim was busy from `start` to `end`, returning whether
+    /// it was for long enough that keys read meanwhile are stale.
+    pub fn busy(&mut self, start: Instant, end: Instant) -> bool {
+        let long = end.duration_since(start) >= BUSY;
+        if long {
+            self.busy = Some((start, end));
+        }
+        long
+    }
+
+    /// Note that the key read at `at` switched mode.
+    pub fn switched_mode(&mut self, at: Instant) {
+        self.switched = Some(at);
+    }
+
+    /// Whether `key`, read at `at`, is an Enter hard on a mode switch.
+    pub fn bounced(&self, key: &KeyEvent, at: Instant) -> bool {
+        key.code == KeyCode::Enter
+            && self
+                .switched
+                .is_some_and(|s| at >= s && at.duration_since(s) < BOUNCE)
+    }
+
+    /// `events` without the keys read while shim was last busy that
+    /// `keep` doesn't keep, and how many were dropped.
+    fn drop_stale(&self, events: Vec<AppEvent>, keep: Keep) -> (Vec<AppEvent>, usize) {
+        let Some((start, end)) = self.busy.filter(|_| keep != Keep::All) else {
+            return (events, 0);
+        };
+        let stale = |e: &AppEvent| matches!(e, AppEvent::Key(_, at) if (start..=end).contains(at));
+        let last = match keep {
+            Keep::Last => events.iter().rposition(stale),
+            _ => None,
+        };
+        let before = events.len();
+        let kept: Vec<_> = events
+            .into_iter()
+            .enumerate()
+            .filter(|(i, e)| !stale(e) || Some(*i) == last)
+            .map(|(_, e)| e)
+            .collect();
+        let dropped = before - kept.len();
+        (kept, dropped)
+    }
+}
+
+impl Model {
+    /// `events` without the keys typed while shim was busy, saying so if
+    /// any were dropped.
+    pub fn drop_stale_keys(&mut self, events: Vec<AppEvent>) -> Vec<AppEvent> {
+        let (events, dropped) = self.typeahead.drop_stale(events, self.config.stale_keys);
+        if dropped > 0 {
+            let note = format!("dropped {dropped} key(s) typed while shim was busy");
+            self.push_toast(toast::Level::Warn, note, toast::TTL);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(c: char, at: Instant) -> AppEvent {
+        AppEvent::Key(KeyEvent::from(KeyCode::Char(c)), at)
+    }
+
+    fn typed(events: &[AppEvent]) -> String {
+        events
+            .iter()
+            .filter_map(|e| match e {
+                AppEvent::Key(
+                    KeyEvent {
+                        code: KeyCode::Char(c),
+                        ..
+                    },
+                    _,
+                ) => Some(*c),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keys_read_while_busy_are_dropped() {
+        let start = Instant::now();
+        let ms = |n| start + Duration::from_millis(n);
+        let events = || {
+            vec![
+                key('a', ms(0)),
+                key('b', ms(100)),
+                AppEvent::Resize {
+                    width: 80,
+                    height: 24,
+                },
+                key('c', ms(300)),
+                key('d', ms(600)),
+            ]
+        };
+        let mut typeahead = Typeahead::default();
+        // a short pause isn't being busy
+        assert!(!typeahead.busy(ms(50), ms(300)));
+        let (kept, dropped) = typeahead.drop_stale(events(), Keep::Last);
+        assert_eq!((typed(&kept), dropped), ("abcd".into(), 0));
+
+        assert!(typeahead.busy(ms(50), ms(500)));
+        let (kept, dropped) = typeahead.drop_stale(events(), Keep::Last);
+        assert_eq!((typed(&kept), dropped), ("acd".into(), 1));
+        assert_eq!(kept.len(), 4);
+        let (kept, dropped) = typeahead.drop_stale(events(), Keep::None);
+        assert_eq!((typed(&kept), dropped), ("ad".into(), 2));
+        let (kept, _) = typeahead.drop_stale(events(), Keep::All);
+        assert_eq!(typed(&kept), "abcd");
+    }
+
+    #[test]
+    fn fast_typing_isnt_eaten() {
+        let start = Instant::now();
+        let mut typeahead = Typeahead::default();
+        // a burst, all read in the same instant, as a paste is
+        let burst: Vec<_> = "ls -la".chars().map(|c| key(c, start)).collect();
+        let (kept, dropped) = typeahead.drop_stale(burst, Keep::None);
+        assert_eq!((typed(&kept), dropped), ("ls -la".into(), 0));
+
+        let enter = KeyEvent::from(KeyCode::Enter);
+        assert!(!typeahead.bounced(&enter, start));
+        typeahead.switched_mode(start);
+        assert!(typeahead.bounced(&enter, start + Duration::from_millis(5)));
+        assert!(!typeahead.bounced(&enter, start + BOUNCE));
+        // only Enter is held back
+        let i = KeyEvent::from(KeyCode::Char('i'));
+        assert!(!typeahead.bounced(&i, start + Duration::from_millis(5)));
+    }
+}
@@ -0,0 +1,223 @@
+//! How much CPU and memory running jobs use, sampled every couple of
+//! seconds from /proc for everything in each job's process group. Shown on
+//! the status line for the viewed job and as a sparkline in `jobs`; a
+//! job's peak memory is kept once it ends. Elsewhere than Linux nothing is
+//! sampled.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::{Model, Output, memory::format_size};
+
+/// How often to sample.
+pub const EVERY: Duration = Duration::from_secs(2);
+/// Samples kept for the sparkline.
+const KEPT: usize = 10;
+const BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+pub const SUPPORTED: bool = cfg!(target_os = "linux");
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Sample {
+    /// Percent of one CPU.
+    cpu: f64,
+    rss: u64,
+}
+
+/// A job's samples.
+#[derive(Debug, Default)]
+pub struct Usage {
+    samples: VecDeque<Sample>,
+    /// CPU time used as of the last sample, in clock ticks.
+    last: Option<(Instant, u64)>,
+    /// The most memory the job held at once, as sampled.
+    pub peak_rss: u64,
+}
+
+impl Usage {
+    fn add(&mut self, now: Instant, ticks: u64, rss: u64, ticks_per_second: u64) {
+        if let Some((then, before)) = self.last {
+            let seconds = now.saturating_duration_since(then).as_secs_f64();
+            let used = ticks.saturating_sub(before) as f64 / ticks_per_second as f64;
+            let cpu = match seconds > 0.0 {
+                true => used / seconds * 100.0,
+                false => 0.0,
+            };
+            if self.samples.len() == KEPT {
+                self.samples.pop_front();
+            }
+            self.samples.push_back(Sample { cpu, rss });
+        }
+        self.last = Some((now, ticks));
+        self.peak_rss = self.peak_rss.max(rss);
+    }
+}
+
+/// The CPU samples as bars, the busiest as tall as a whole CPU or the
+/// highest sample, whichever is more.
+fn sparkline(cpu: impl Iterator<Item = f64> + Clone) -> String {
+    let top = cpu.clone().fold(100.0, f64::max);
+    cpu.map(|c| {
+        let bar = (c / top * (BARS.len() - 1) as f64).round() as usize;
+        BARS[bar.min(BARS.len() - 1)]
+    })
+    .collect()
+}
+
+/// The process group, CPU time (user and system, in clock ticks) and
+/// resident pages in a `/proc/PID/stat` line.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn parse_stat(stat: &str) -> Option<(u32, u64, u64)> {
+    // the command name in brackets may hold blanks and brackets itself
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    let field = |n: usize| fields.get(n - 3)?.parse::<u64>().ok();
+    let group = field(5)? as u32;
+    Some((group, field(14)? + field(15)?, field(24)?))
+}
+
+/// CPU ticks and resident bytes summed over each process group in
+/// `groups`, with how many ticks make a second.
+#[cfg(target_os = "linux")]
+fn measure(groups: &[u32]) -> (HashMap<u32, (u64, u64)>, u64) {
+    let mut totals: HashMap<u32, (u64, u64)> = HashMap::new();
+    // SAFETY: sysconf only reads configuration
+    let (ticks, page) = unsafe {
+        (
+            libc::sysconf(libc::_SC_CLK_TCK),
+            libc::sysconf(libc::_SC_PAGESIZE),
+        )
+    };
+    let Ok(entries) = std::fs::read_dir("/proc") else {
+        return (totals, 100);
+    };
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        if !name
+            .to_string_lossy()
+            .starts_with(|c: char| c.is_ascii_digit())
+        {
+            continue;
+        }
+        // a process may be gone by the time it's read
+        let Ok(stat) = std::fs::read_to_string(entry.path().join("stat")) else {
+            continue;
+        };
+        let Some((group, cpu, pages)) = parse_stat(&stat) else {
+            continue;
+        };
+        if groups.contains(&group) {
+            let total = totals.entry(group).or_default();
+            total.0 += cpu;
+            total.1 += pages * page.max(1) as u64;
+        }
+    }
+    (totals, ticks.max(1) as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn measure(_groups: &[u32]) -> (HashMap<u32, (u64, u64)>, u64) {
+    (HashMap::new(), 100)
+}
+
+impl Output {
+    /// The latest sample, for the status line while the job runs.
+    pub fn usage_note(&self) -> Option<String> {
+        let sample = self.usage.samples.back().filter(|_| self.running)?;
+        Some(format!(
+            "cpu {:.0}% mem {}",
+            sample.cpu,
+            format_size(sample.rss as usize)
+        ))
+    }
+
+    /// Usage columns for the `jobs` table: the latest sample and a
+    /// sparkline while it runs, the peak once it's done.
+    pub fn usage_columns(&self) -> String {
+        let usage = &self.usage;
+        match usage.samples.back() {
+            Some(sample) if self.running => format!(
+                "{:>4.0}% {:>9} {:<10}",
+                sample.cpu,
+                format_size(sample.rss as usize),
+                sparkline(usage.samples.iter().map(|s| s.cpu))
+            ),
+            _ => self.peak_note().unwrap_or_default(),
+        }
+    }
+
+    /// The most memory the job held, once it's done.
+    pub fn peak_note(&self) -> Option<String> {
+        let peak = self.usage.peak_rss;
+        (peak > 0 && !self.running).then(|| format!("peak {}", format_size(peak as usize)))
+    }
+}
+
+impl Model {
+    /// Sample running jobs if it's time.
+    pub fn sample_usage(&mut self, now: Instant) {
+        if !SUPPORTED || self.sampled.is_some_and(|at| now < at + EVERY) {
+            return;
+        }
+        let leaders: Vec<(usize, u32)> = self
+            .outputs
+            .iter()
+            .enumerate()
+            .filter(|(_, o)| o.running)
+            .filter_map(|(i, o)| Some((i, o.process.as_ref()?.lock().ok()?.id())))
+            .collect();
+        if leaders.is_empty() {
+            self.sampled = None;
+            return;
+        }
+        let groups: Vec<u32> = leaders.iter().map(|(_, pid)| *pid).collect();
+        let (totals, ticks_per_second) = measure(&groups);
+        for (i, pid) in leaders {
+            if let Some(&(ticks, rss)) = totals.get(&pid) {
+                self.outputs[i].usage.add(now, ticks, rss, ticks_per_second);
+            }
+        }
+        self.sampled = Some(now);
+    }
+
+    /// When running jobs are next to be sampled.
+    pub fn next_sample(&self, now: Instant) -> Option<Instant> {
+        let running = self
+            .outputs
+            .iter()
+            .any(|o| o.running && o.process.is_some());
+        (SUPPORTED && running).then(|| self.sampled.map_or(now, |at| at + EVERY))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stat_lines() {
+        let stat = "4242 (make (j4)) S 4200 4242 4200 34816 4242 4194304 \
+                    1200 0 0 0 150 25 0 0 20 0 1 0 12345 104857600 2560 \
+                    18446744073709551615";
+        assert_eq!(parse_stat(stat), Some((4242, 175, 2560)));
+        assert_eq!(parse_stat("4242 (sh) S 1"), None);
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    #[test]
+    fn samples_give_cpu_from_the_ticks_between_them() {
+        let mut usage = Usage::default();
+        let now = Instant::now();
+        usage.add(now, 1000, 50 << 20, 100);
+        // the first is only a starting point
+        assert!(usage.samples.is_empty());
+        usage.add(now + EVERY, 1100, 80 << 20, 100);
+        usage.add(now + EVERY * 2, 1500, 60 << 20, 100);
+        let cpu: Vec<f64> = usage.samples.iter().map(|s| s.cpu).collect();
+        assert_eq!(cpu, [50.0, 200.0]);
+        assert_eq!(usage.peak_rss, 80 << 20);
+        assert_eq!(sparkline(cpu.into_iter()), "▃█");
+        assert_eq!(sparkline([0.0, 50.0, 100.0].into_iter()), "▁▅█");
+    }
+}
@@ -0,0 +1,152 @@
+use std::{env, fmt::Write};
+
+use crate::{
+    Model,
+    memory::format_size,
+    shell::{self, Expansion, ParsedCommand},
+};
+
+/// A session variable, set by `:capture` or `name=! command`.
+#[derive(Debug)]
+pub struct Variable {
+    pub value: String,
+    /// Passed on to the environment of commands, not just expanded.
+    pub exported: bool,
+}
+
+impl Model {
+    /// The value `$name` expands to. Session variables, then those from
+    /// an env file, shadow the environment without changing it.
+    pub fn var(&self, name: &str) -> Option<String> {
+        if name == "?" {
+            return Some(self.last_exit.unwrap_or(0).to_string());
+        }
+        self.vars
+            .get(name)
+            .map(|v| v.value.clone())
+            .or_else(|| self.env_overlay.as_ref()?.vars.get(name).cloned())
+            .or_else(|| env::var(name).ok())
+    }
+
+    /// `shell::parse` with session variables in scope.
+    pub fn parse(&self, command: &str) -> Option<ParsedCommand> {
+        let cwd = env::current_dir().unwrap_or_default();
+        shell::parse(command, |name| self.var(name), &cwd)
+    }
+
+    pub fn exported_vars(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars
+            .iter()
+            .filter(|(_, v)| v.exported)
+            .map(|(name, v)| (name.as_str(), v.value.as_str()))
+    }
+
+    /// Store `value` under `name`, cut down to `var_cap` if need be.
+    /// Returns what happened, for the user.
+    pub fn set_var(&mut self, name: &str, mut value: String) -> String {
+        // like $(...), drop trailing newlines
+        value.truncate(value.trim_end_matches('\n').len());
+        let size = value.len();
+        let mut note = format!("{name} = {}", format_size(size));
+        if size > self.config.var_cap {
+            let mut end = self.config.var_cap;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+            note = format!(
+                "{name} truncated from {} to {}",
+                format_size(size),
+                format_size(end)
+            );
+        }
+        let exported = self.vars.get(name).is_some_and(|v| v.exported);
+        self.vars
+            .insert(name.to_string(), Variable { value, exported });
+        note
+    }
+}
+
+pub fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Split `name=! command` into the variable and the command to capture.
+pub fn split_capture(line: &str) -> Option<(&str, &str)> {
+    let (name, command) = line.trim_start().split_once("=!")?;
+    is_name(name).then_some((name, command.trim()))
+}
+
+/// The first variable `parsed` expanded that has no value.
+pub fn unbound(parsed: &ParsedCommand) -> Option<&str> {
+    parsed.expansions.iter().find_map(|e| match e {
+        Expansion::Variable { name, value: None } => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// `:capture NAME` stores the viewed buffer's output under `NAME`.
+pub fn capture(model: &mut Model, args: &[&str]) -> String {
+    let [name] = args else {
+        return "usage: :capture NAME".into();
+    };
+    if !is_name(name) {
+        return format!("capture: not a valid name: {name}");
+    }
+    match model.outputs.get(model.viewing_output) {
+        Some(output) if output.evicted.is_some() => "capture: buffer was evicted".into(),
+        Some(output) => {
+            let value = output.raw_stdout().to_string();
+            model.set_var(name, value)
+        }
+        None => "capture: no buffer to capture".into(),
+    }
+}
+
+pub fn vars(model: &Model) -> String {
+    let mut names: Vec<&String> = model.vars.keys().collect();
+    names.sort();
+    let mut listing = String::new();
+    for name in names {
+        let var = &model.vars[name];
+        let export = if var.exported { "export " } else { "" };
+        let first_line = var.value.lines().next().unwrap_or("");
+        let more = if var.value.lines().nth(1).is_some() {
+            " …"
+        } else {
+            ""
+        };
+        let _ = writeln!(
+            listing,
+            "{export}{name} ({}) = {first_line}{more}",
+            format_size(var.value.len())
+        );
+    }
+    listing
+}
+
+/// `export NAME` or `export NAME=VALUE` passes a session variable on to
+/// commands' environments.
+pub fn export(model: &mut Model, args: &[String]) -> String {
+    let mut notes = String::new();
+    for arg in args {
+        let name = match arg.split_once('=') {
+            Some((name, value)) if is_name(name) => {
+                let _ = writeln!(notes, "{}", model.set_var(name, value.to_string()));
+                name
+            }
+            _ => arg.as_str(),
+        };
+        match model.vars.get_mut(name) {
+            Some(var) => var.exported = true,
+            None => {
+                let _ = writeln!(notes, "export: no session variable {name}");
+            }
+        }
+    }
+    notes
+}
@@ -0,0 +1,30 @@
+//! What went wrong getting a buffer's output, as opposed to what the
+//! command wrote: a command that couldn't be started, bytes that weren't
+//! UTF-8, a read that failed part way, contents evicted to save memory.
+//! These are kept apart from the output and shown under it, so they can't
+//! be mistaken for it.
+
+use crate::{Model, Output};
+
+impl Output {
+    /// Note that something went wrong, once however often it happens.
+    pub fn warn(&mut self, warning: String) {
+        if !self.warnings.contains(&warning) {
+            self.warnings.push(warning);
+        }
+    }
+
+    /// The warnings, for the foot of the buffer.
+    pub fn warning_footer(&self) -> Option<String> {
+        (!self.warnings.is_empty()).then(|| format!(" ⚠ {} ", self.warnings.join("; ")))
+    }
+}
+
+impl Model {
+    /// Reading job `job_id`'s output failed; it ends there.
+    pub fn read_failed(&mut self, job_id: usize, error: String) {
+        if let Some(output) = self.outputs.iter_mut().find(|o| o.id == job_id) {
+            output.warn(format!("reading output failed: {error}"));
+        }
+    }
+}
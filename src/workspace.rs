@@ -0,0 +1,156 @@
+//! Workspaces, shown as numbered tabs on the top row once there's more
+//! than one: each has its own buffers, working directory and history
+//! filter. `:tabnew [DIR]` opens one, `gt` and `gT` go to the next and
+//! previous, `3gt` to the third. The one in front keeps its state in the
+//! model as ever, and the others are parked here until switched to.
+//! Jobs keep writing to the buffers of the workspace they started in.
+
+use std::{env, mem, path::PathBuf};
+
+use crate::{Model, Output, scrollback};
+
+/// A workspace's share of the model.
+#[derive(Debug, Default)]
+pub struct Workspace {
+    outputs: Vec<Output>,
+    viewing_output: usize,
+    scrollback: Option<scrollback::View>,
+    history_here: bool,
+    /// Where commands run in it, kept while it's parked.
+    cwd: Option<PathBuf>,
+}
+
+#[derive(Debug)]
+pub struct Workspaces {
+    /// A slot for each; the one in front has an empty one.
+    list: Vec<Workspace>,
+    active: usize,
+    /// Set while a parked workspace is brought in for its job's output.
+    visiting: bool,
+}
+
+impl Default for Workspaces {
+    fn default() -> Self {
+        Workspaces {
+            list: vec![Workspace::default()],
+            active: 0,
+            visiting: false,
+        }
+    }
+}
+
+impl Workspaces {
+    /// Whether what's in the model now is out of view.
+    pub fn visiting(&self) -> bool {
+        self.visiting
+    }
+}
+
+impl Model {
+    /// Swap the model's share with slot `i`.
+    fn exchange(&mut self, i: usize) {
+        let slot = &mut self.workspaces.list[i];
+        mem::swap(&mut self.outputs, &mut slot.outputs);
+        mem::swap(&mut self.viewing_output, &mut slot.viewing_output);
+        mem::swap(&mut self.scrollback, &mut slot.scrollback);
+        mem::swap(&mut self.history_here, &mut slot.history_here);
+    }
+
+    /// Bring workspace `to` to the front.
+    pub fn switch_workspace(&mut self, to: usize) {
+        let from = self.workspaces.active;
+        if to == from || to >= self.workspaces.list.len() {
+            return;
+        }
+        self.save_cursor();
+        self.visual = None;
+        self.line_popup = None;
+        self.picker = None;
+        self.exchange(from);
+        self.workspaces.list[from].cwd = env::current_dir().ok();
+        self.exchange(to);
+        self.workspaces.active = to;
+        if let Some(dir) = self.workspaces.list[to].cwd.take()
+            && let Err(e) = env::set_current_dir(&dir)
+        {
+            self.status = Some(format!("{}: {e}", dir.display()));
+        }
+        self.refresh_segments();
+        self.restore_cursor();
+    }
+
+    /// `gt` and `gT`: the next workspace or the one before, round the end.
+    pub fn cycle_workspace(&mut self, forward: bool) {
+        let count = self.workspaces.list.len();
+        let step = if forward { 1 } else { count - 1 };
+        self.switch_workspace((self.workspaces.active + step) % count);
+    }
+
+    /// `:tabnew [DIR]`
+    pub fn new_workspace(&mut self, dir: Option<&str>) -> String {
+        self.workspaces.list.push(Workspace::default());
+        self.switch_workspace(self.workspaces.list.len() - 1);
+        if let Some(dir) = dir
+            && let Err(e) = self.change_dir(dir.as_ref())
+        {
+            return e;
+        }
+        format!("workspace {}", self.workspaces.active + 1)
+    }
+
+    /// Run `f` with job `job_id`'s workspace in front, if it's parked, so
+    /// the job's output goes to its own buffer.
+    pub fn in_home_of<R>(&mut self, job_id: usize, f: impl FnOnce(&mut Model) -> R) -> R {
+        let here = self.outputs.iter().any(|o| o.id == job_id);
+        let home = self
+            .workspaces
+            .list
+            .iter()
+            .position(|w| w.outputs.iter().any(|o| o.id == job_id));
+        let Some(home) = home.filter(|_| !here) else {
+            return f(self);
+        };
+        let (active, cursor) = (self.workspaces.active, self.cursor);
+        self.exchange(active);
+        self.exchange(home);
+        self.workspaces.visiting = true;
+        let result = f(self);
+        self.workspaces.visiting = false;
+        self.exchange(home);
+        self.exchange(active);
+        self.cursor = cursor;
+        result
+    }
+
+    /// Jobs running in every workspace.
+    pub fn running_everywhere(&self) -> usize {
+        let parked = self.workspaces.list.iter().flat_map(|w| &w.outputs);
+        self.outputs
+            .iter()
+            .chain(parked)
+            .filter(|o| o.running)
+            .count()
+    }
+
+    /// The tabs to show, with whether each is in front, once there's more
+    /// than one.
+    pub fn tabs(&self) -> Vec<(String, bool)> {
+        if self.workspaces.list.len() < 2 {
+            return Vec::new();
+        }
+        let cwd = env::current_dir().ok();
+        self.workspaces
+            .list
+            .iter()
+            .enumerate()
+            .map(|(i, w)| {
+                let active = i == self.workspaces.active;
+                let dir = if active { cwd.as_ref() } else { w.cwd.as_ref() };
+                let name = dir
+                    .and_then(|d| d.file_name())
+                    .map_or("/".into(), |n| n.to_string_lossy());
+                (format!("{} {name}", i + 1), active)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,168 @@
+//! Wrapping output lines wider than the pane (the `wrap` setting) and
+//! moving by screen row with `gj`/`gk`. The cursor still counts lines from
+//! the top of the view; with wrapping a line may take several screen rows.
+
+use std::iter;
+
+use crate::{Cursor, Message, Model, display, select};
+
+impl Model {
+    /// Whether output lines wrap; the scrollback view doesn't.
+    pub fn wrapping(&self) -> bool {
+        self.config.wrap && self.scrollback.is_none()
+    }
+
+    /// Columns output lines wrap at.
+    pub fn wrap_width(&self) -> usize {
+        match self.wrapping() {
+            true => self.width.max(1) as usize,
+            false => usize::MAX,
+        }
+    }
+
+    pub fn output_settings(&self) -> display::Settings {
+        match self.outputs.get(self.viewing_output) {
+            Some(output) => output.display_settings(&self.config),
+            None => display::Settings {
+                tab_stop: self.config.tab_stop,
+                invisibles: false,
+                long_line: self.config.long_line,
+            },
+        }
+    }
+
+    /// Lines of the viewed output from the top of the view down.
+    pub fn shown_lines(&self) -> impl Iterator<Item = &str> {
+        self.outputs
+            .get(self.viewing_output)
+            .into_iter()
+            .flat_map(|o| o.text_from(o.scroll.0).lines())
+            .map(|line| display::cap(line, self.config.long_line))
+    }
+
+    /// What's on screen of the viewed output, broken into rows the way the
+    /// cursor expects.
+    pub fn wrapped_view(&self) -> String {
+        let (width, settings) = (self.wrap_width(), self.output_settings());
+        let rows: Vec<_> = self
+            .shown_lines()
+            .flat_map(|line| display::rows(line, width, settings))
+            .take(self.height as usize)
+            .map(|row| row.text)
+            .collect();
+        rows.join("\n")
+    }
+
+    /// The screen rows of the line `y` lines from the top of the view.
+    pub fn output_rows(&self, y: u16) -> Vec<display::Row> {
+        let line = self.output_line(y).unwrap_or("");
+        display::rows(line, self.wrap_width(), self.output_settings())
+    }
+
+    /// The screen row the line `y` lines from the top of the view starts on.
+    pub fn screen_row(&self, y: u16) -> usize {
+        if !self.wrapping() {
+            return y as usize;
+        }
+        let (width, settings) = (self.wrap_width(), self.output_settings());
+        self.shown_lines()
+            .chain(iter::repeat(""))
+            .take(y as usize)
+            .map(|line| display::rows(line, width, settings).len())
+            .sum()
+    }
+
+    /// The last line that starts on screen, where Up from the command line
+    /// lands.
+    pub fn last_shown_line(&self) -> u16 {
+        let height = self.height as usize;
+        if !self.wrapping() {
+            return height.saturating_sub(1) as u16;
+        }
+        let (width, settings) = (self.wrap_width(), self.output_settings());
+        let mut next = 0;
+        self.shown_lines()
+            .chain(iter::repeat(""))
+            .position(|line| {
+                next += display::rows(line, width, settings).len();
+                next >= height
+            })
+            .unwrap_or(0) as u16
+    }
+
+    /// The top line of the view when output `index` is scrolled to its end.
+    pub fn bottom(&self, index: usize) -> usize {
+        let Some(output) = self.outputs.get(index) else {
+            return 0;
+        };
+        if !self.config.wrap {
+            return output.line_count().saturating_sub(self.height as usize);
+        }
+        let settings = output.display_settings(&self.config);
+        let lines = output.shown_text().lines();
+        let mut rows = 0;
+        let fitting = lines
+            .clone()
+            .rev()
+            .take_while(|line| {
+                rows += display::rows(line, self.wrap_width(), settings).len();
+                rows <= self.height as usize
+            })
+            .count();
+        lines.count() - fitting
+    }
+
+    /// `gj`: down a screen row, within a wrapped line or onto the next one.
+    pub fn row_down(&mut self) -> Option<Message> {
+        let Cursor::OutputBuffer(x, y) = self.cursor else {
+            // the command line doesn't wrap
+            return Some(Message::Down);
+        };
+        let rows = self.output_rows(y);
+        let row = display::row_of(&rows, x as usize);
+        let (y_to, row_to) = match row + 1 < rows.len() {
+            true => (y, row + 1),
+            false => (y + 1, 0),
+        };
+        if self.screen_row(y_to) + row_to >= self.height as usize {
+            // off the bottom, so on to the command line like j
+            return Some(Message::Down);
+        }
+        self.move_to_row(x, y, y_to, row_to);
+        None
+    }
+
+    /// `gk`: up a screen row, within a wrapped line or onto the one above.
+    pub fn row_up(&mut self) -> Option<Message> {
+        let Cursor::OutputBuffer(x, y) = self.cursor else {
+            return Some(Message::Up);
+        };
+        let rows = self.output_rows(y);
+        match display::row_of(&rows, x as usize) {
+            0 if y == 0 => {}
+            0 => {
+                let last = self.output_rows(y - 1).len() - 1;
+                self.move_to_row(x, y, y - 1, last);
+            }
+            row => self.move_to_row(x, y, y, row - 1),
+        }
+        None
+    }
+
+    /// Put the cursor on row `row_to` of line `y_to`, as near as it gets to
+    /// the goal column: where gj/gk started from, like vim.
+    fn move_to_row(&mut self, x: u16, y: u16, y_to: u16, row_to: usize) {
+        let settings = self.output_settings();
+        let goal = self.goal_column.unwrap_or_else(|| {
+            let line = self.output_line(y).unwrap_or("");
+            let rows = display::rows(line, self.wrap_width(), settings);
+            let start = rows[display::row_of(&rows, x as usize)].start;
+            display::column(line, x as usize, settings) - display::column(line, start, settings)
+        });
+        self.goal_column = Some(goal);
+        let line = self.output_line(y_to).unwrap_or("");
+        let x = select::to_logical([line], self.wrap_width(), settings, row_to, goal)
+            .map_or(0, |at| at.index);
+        self.cursor = Cursor::OutputBuffer(x as u16, y_to);
+    }
+}